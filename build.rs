@@ -0,0 +1,72 @@
+//! Embed build metadata (commit, date, target) for `rnr version`
+
+use std::path::Path;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn main() {
+    println!("cargo:rustc-env=RNR_GIT_SHA={}", git_sha());
+    println!("cargo:rustc-env=RNR_BUILD_DATE={}", build_date());
+    println!(
+        "cargo:rustc-env=RNR_TARGET={}",
+        std::env::var("TARGET").unwrap_or_default()
+    );
+
+    // Rebuild the metadata when the checked-out commit changes
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+    let head = Path::new(".git").join("HEAD");
+    if head.is_file() {
+        println!("cargo:rerun-if-changed={}", head.display());
+        if let Ok(contents) = std::fs::read_to_string(&head) {
+            if let Some(reference) = contents.trim().strip_prefix("ref: ") {
+                let path = Path::new(".git").join(reference);
+                if path.is_file() {
+                    println!("cargo:rerun-if-changed={}", path.display());
+                }
+            }
+        }
+    }
+}
+
+/// Short SHA of the commit being built, or `unknown` outside a git checkout
+fn git_sha() -> String {
+    Command::new("git")
+        .args(["rev-parse", "--short=12", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|sha| sha.trim().to_string())
+        .filter(|sha| !sha.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// UTC build date as `YYYY-MM-DD`, honouring `SOURCE_DATE_EPOCH` for
+/// reproducible builds
+fn build_date() -> String {
+    let secs = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.parse::<u64>().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default()
+        });
+    let (year, month, day) = civil_date((secs / 86_400) as i64);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Calendar date of a day count since 1970-01-01
+fn civil_date(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
@@ -0,0 +1,274 @@
+//! Running tasks from other programs
+//!
+//! [`Runner`] is the embedding entry point: point it at a project, optionally
+//! override environment variables or redirect rnr's own output, and run tasks
+//! by name. Each run returns a [`RunReport`] with the outcome of every step.
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::config::{Config, CONFIG_FILE};
+use crate::error::{Error, Result};
+use crate::history::{StepRecord, TaskRecord};
+use crate::runner::{self, RunOptions};
+use crate::ui::Ui;
+
+pub use crate::history::Status;
+
+/// Runs tasks from a project's rnr.yaml
+///
+/// ```
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let dir = tempfile::tempdir()?;
+/// std::fs::write(dir.path().join("rnr.yaml"), "greet: echo hello $NAME\n")?;
+///
+/// let report = rnr::Runner::new(dir.path())
+///     .env("NAME", "portal")
+///     .output(std::io::sink())
+///     .run("greet")?;
+/// assert!(report.success());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Runner {
+    root: PathBuf,
+    env: HashMap<String, String>,
+    ui: Ui,
+    dry_run: bool,
+}
+
+impl Runner {
+    /// Run tasks in the project at `project_root` (the directory holding rnr.yaml)
+    pub fn new(project_root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: project_root.into(),
+            env: HashMap::new(),
+            ui: Ui::default(),
+            dry_run: false,
+        }
+    }
+
+    /// Set an environment variable for every command, overriding the task's `env`
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env.insert(key.into(), value.into());
+        self
+    }
+
+    /// Set several environment variables (see [`Runner::env`])
+    pub fn envs<K, V>(mut self, vars: impl IntoIterator<Item = (K, V)>) -> Self
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.env
+            .extend(vars.into_iter().map(|(k, v)| (k.into(), v.into())));
+        self
+    }
+
+    /// Write rnr's own output (command echoes, progress, warnings) to `sink`
+    /// instead of stdout/stderr. Commands still inherit the process's stdio.
+    pub fn output(mut self, sink: impl Write + Send + 'static) -> Self {
+        self.ui = Ui::sink(sink);
+        self
+    }
+
+    /// Print what would be done without executing anything
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// The project's task definitions
+    pub fn config(&self) -> Result<Config> {
+        Config::load_from(&self.root.join(CONFIG_FILE))
+    }
+
+    /// Run a task that takes no arguments
+    pub fn run(&self, task: &str) -> Result<RunReport> {
+        self.run_with_args(task, &[] as &[&str])
+    }
+
+    /// Run a task with command-line style arguments, e.g. `["staging", "--force"]`
+    ///
+    /// A task that runs and fails is still `Ok`; check [`RunReport::success`].
+    /// `Err` means the task couldn't be started at all.
+    pub fn run_with_args(&self, task: &str, args: &[impl AsRef<str>]) -> Result<RunReport> {
+        let opts = RunOptions {
+            dry_run: self.dry_run,
+            ui: self.ui.clone(),
+            project_root: Some(self.root.clone()),
+            env: self.env.clone(),
+            ..Default::default()
+        };
+        let path = self.root.join(CONFIG_FILE);
+        let config = opts.configs.load(&path)?;
+        if config.strict {
+            config.check_unknown_fields(&path)?;
+        }
+        let task_def = config
+            .get_task(task)
+            .ok_or_else(|| Error::TaskNotFound(task.to_string()))?;
+
+        let args: Vec<String> = args.iter().map(|a| a.as_ref().to_string()).collect();
+        let bound = crate::args::bind(task, task_def, &args, &self.ui)
+            .map_err(|e| Error::flatten(e, Error::InvalidArgs))?;
+
+        let (record, _) = runner::run_bound_task(&config, &self.root, task, &args, &bound, &opts)
+            .map_err(|e| Error::flatten(e, Error::Plan))?;
+        Ok(RunReport::from(record))
+    }
+}
+
+/// Outcome of running a task
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunReport {
+    /// Task name
+    pub task: String,
+    /// Overall status
+    pub status: Status,
+    /// Wall-clock duration
+    pub duration: Duration,
+    /// Error message when the task failed
+    pub error: Option<String>,
+    /// Top-level steps, with parallel branches listed individually
+    /// (empty for tasks without steps)
+    pub steps: Vec<StepResult>,
+}
+
+impl RunReport {
+    /// Whether the task passed
+    pub fn success(&self) -> bool {
+        self.status == Status::Passed
+    }
+}
+
+/// Outcome of one top-level step or parallel branch
+#[derive(Debug, Clone, PartialEq)]
+pub struct StepResult {
+    /// 1-based index in the task's `steps`
+    pub index: usize,
+    /// 1-based branch within a parallel block
+    pub branch: Option<usize>,
+    /// Short description, e.g. `$ cargo test`
+    pub label: String,
+    /// Outcome
+    pub status: Status,
+    /// Wall-clock duration (zero if it didn't run)
+    pub duration: Duration,
+}
+
+impl From<TaskRecord> for RunReport {
+    fn from(record: TaskRecord) -> Self {
+        Self {
+            task: record.name,
+            status: record.status,
+            duration: Duration::from_millis(record.duration_ms),
+            error: record.error,
+            steps: record.steps.into_iter().map(StepResult::from).collect(),
+        }
+    }
+}
+
+impl From<StepRecord> for StepResult {
+    fn from(record: StepRecord) -> Self {
+        Self {
+            index: record.index,
+            branch: record.branch,
+            label: record.label,
+            status: record.status,
+            duration: Duration::from_millis(record.duration_ms),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn project(yaml: &str) -> tempfile::TempDir {
+        let temp = tempfile::tempdir().unwrap();
+        fs::write(temp.path().join(CONFIG_FILE), yaml).unwrap();
+        temp
+    }
+
+    #[test]
+    fn test_run_reports_steps() {
+        let temp = project(
+            r#"
+ci:
+  steps:
+    - cmd: echo one
+    - parallel:
+        - cmd: echo a
+        - cmd: exit 3
+    - cmd: echo never
+"#,
+        );
+        let report = Runner::new(temp.path())
+            .output(std::io::sink())
+            .run("ci")
+            .unwrap();
+
+        assert!(!report.success());
+        let statuses: Vec<_> = report.steps.iter().map(|s| s.status).collect();
+        assert_eq!(
+            statuses,
+            [
+                Status::Passed,
+                Status::Passed,
+                Status::Failed,
+                Status::NotRun
+            ]
+        );
+        assert_eq!(report.steps[2].branch, Some(2));
+        assert_eq!(report.steps[3].duration, Duration::ZERO);
+        assert!(report.error.unwrap().contains("exit code 3"));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_env_overrides_task_env() {
+        let temp = project(
+            r#"
+check:
+  env:
+    MODE: debug
+  cmd: test "$MODE" = release
+"#,
+        );
+        let runner = Runner::new(temp.path()).output(std::io::sink());
+        assert!(!runner.run("check").unwrap().success());
+        let runner = runner.env("MODE", "release");
+        assert!(runner.run("check").unwrap().success());
+    }
+
+    #[test]
+    fn test_errors_are_typed() {
+        let temp = project("build: echo build\ndeploy:\n  args:\n    - name: env\n      required: true\n  cmd: echo\n");
+        let runner = Runner::new(temp.path()).output(std::io::sink());
+        assert!(matches!(runner.run("test"), Err(Error::TaskNotFound(name)) if name == "test"));
+        assert!(matches!(runner.run("deploy"), Err(Error::InvalidArgs(_))));
+
+        let missing = Runner::new(temp.path().join("nope"));
+        assert!(matches!(
+            missing.run("build"),
+            Err(Error::ReadConfig { .. })
+        ));
+    }
+
+    #[test]
+    fn test_dry_run_echoes_to_sink() {
+        let temp = project("build: echo hi\n");
+        let (ui, capture) = Ui::capture(false);
+        let runner = Runner {
+            ui,
+            ..Runner::new(temp.path()).dry_run(true)
+        };
+        assert!(runner.run("build").unwrap().success());
+        assert!(capture.stdout().contains("$ echo hi"));
+    }
+}
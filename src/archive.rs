@@ -0,0 +1,213 @@
+//! Release assets packed in archives
+//!
+//! A release asset is either the rnr binary itself or a `.tar.gz` or `.zip`
+//! archive holding it. [`unpack`] returns the binary either way, so the
+//! download code doesn't care which one a release publishes. Only what
+//! release archives use is read: gzip over a plain tar, and zip entries that
+//! are stored or deflated.
+
+use anyhow::{bail, Context, Result};
+use flate2::read::GzDecoder;
+use std::io::{self, Cursor, Read};
+
+/// How a release asset is packaged, judging by its name
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Packaging {
+    /// The binary itself
+    Binary,
+    /// A gzipped tar archive
+    TarGz,
+    /// A zip archive
+    Zip,
+}
+
+impl Packaging {
+    /// Packaging of the asset named `name`
+    pub fn of(name: &str) -> Self {
+        if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Packaging::TarGz
+        } else if name.ends_with(".zip") {
+            Packaging::Zip
+        } else {
+            Packaging::Binary
+        }
+    }
+
+    /// What an asset packaged this way ends with
+    pub fn extension(self) -> &'static str {
+        match self {
+            Packaging::Binary => "",
+            Packaging::TarGz => ".tar.gz",
+            Packaging::Zip => ".zip",
+        }
+    }
+}
+
+/// The binary in the asset `name`, downloaded as `bytes`: the bytes
+/// themselves for a bare binary, otherwise the one file in the archive whose
+/// name starts with `rnr`. Archives unpacking to more than `limit` bytes are
+/// refused.
+pub fn unpack(name: &str, bytes: Vec<u8>, limit: u64) -> Result<Vec<u8>> {
+    let files = match Packaging::of(name) {
+        Packaging::Binary => return Ok(bytes),
+        Packaging::TarGz => tar_files(&bytes, limit),
+        Packaging::Zip => zip_files(&bytes, limit),
+    }
+    .with_context(|| format!("Failed to unpack {}", name))?;
+
+    let base_name = |path: &str| path.rsplit('/').next().unwrap_or_default().to_string();
+    let mut binaries: Vec<(String, Vec<u8>)> = files
+        .into_iter()
+        .filter(|(path, _)| base_name(path).starts_with("rnr"))
+        .collect();
+    match binaries.len() {
+        1 => Ok(binaries.remove(0).1),
+        0 => bail!("{} doesn't contain an rnr binary", name),
+        _ => bail!(
+            "{} contains several files that could be the rnr binary: {}",
+            name,
+            binaries
+                .iter()
+                .map(|(path, _)| path.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
+
+/// Regular files in a gzipped tar archive, as path and contents,
+/// unpacking at most `limit` bytes in all
+fn tar_files(data: &[u8], limit: u64) -> Result<Vec<(String, Vec<u8>)>> {
+    let mut archive = tar::Archive::new(GzDecoder::new(data));
+    let mut files = Vec::new();
+    let mut budget = limit;
+    for entry in archive.entries().context("not a gzipped tar archive")? {
+        let mut entry = entry.context("corrupt tar archive")?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let path = entry.path()?.to_string_lossy().replace('\\', "/");
+        let contents = read_within(&mut entry, &mut budget, limit)?;
+        files.push((path, contents));
+    }
+    // The gzip checksum is only checked once the stream is read to its end
+    io::copy(&mut archive.into_inner(), &mut io::sink()).context("corrupt gzip stream")?;
+    Ok(files)
+}
+
+/// Files in a zip archive, as path and contents, unpacking at most `limit`
+/// bytes in all
+fn zip_files(data: &[u8], limit: u64) -> Result<Vec<(String, Vec<u8>)>> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(data)).context("not a zip file")?;
+    let mut files = Vec::new();
+    let mut budget = limit;
+    for index in 0..archive.len() {
+        let mut file = archive.by_index(index).context("corrupt zip archive")?;
+        if file.is_dir() {
+            continue;
+        }
+        let path = file.name().to_string();
+        let contents = read_within(&mut file, &mut budget, limit)
+            .with_context(|| format!("zip entry {} is corrupt", path))?;
+        files.push((path, contents));
+    }
+    Ok(files)
+}
+
+/// All of `entry`, taking its size from `budget`; refused once the
+/// archive's files add up to more than `limit`
+fn read_within(entry: &mut impl Read, budget: &mut u64, limit: u64) -> Result<Vec<u8>> {
+    let mut contents = Vec::new();
+    entry.take(*budget + 1).read_to_end(&mut contents)?;
+    let size = contents.len() as u64;
+    if size > *budget {
+        bail!("unpacks to more than {} bytes", limit);
+    }
+    *budget -= size;
+    Ok(contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMIT: u64 = 1024 * 1024;
+    const TAR_GZ: &[u8] = include_bytes!("../tests/fixtures/archives/rnr-linux-amd64.tar.gz");
+    const ZIP: &[u8] = include_bytes!("../tests/fixtures/archives/rnr-windows-amd64.zip");
+
+    fn binary() -> Vec<u8> {
+        "#!/bin/sh\necho 'rnr from an archive'\n"
+            .repeat(40)
+            .into_bytes()
+    }
+
+    #[test]
+    fn test_packaging_by_name() {
+        assert_eq!(Packaging::of("rnr-linux-amd64"), Packaging::Binary);
+        assert_eq!(Packaging::of("rnr-windows-amd64.exe"), Packaging::Binary);
+        assert_eq!(Packaging::of("rnr-linux-amd64.tar.gz"), Packaging::TarGz);
+        assert_eq!(Packaging::of("rnr-linux-amd64.tgz"), Packaging::TarGz);
+        assert_eq!(Packaging::of("rnr-windows-amd64.zip"), Packaging::Zip);
+    }
+
+    #[test]
+    fn test_unpack_binary_is_unchanged() {
+        assert_eq!(
+            unpack("rnr-linux-amd64", b"abc".to_vec(), 1).unwrap(),
+            b"abc"
+        );
+    }
+
+    #[test]
+    fn test_unpack_tar_gz() {
+        let unpacked = unpack("rnr-linux-amd64.tar.gz", TAR_GZ.to_vec(), LIMIT).unwrap();
+        assert_eq!(unpacked, binary());
+    }
+
+    #[test]
+    fn test_unpack_zip() {
+        // Also holds a directory and a stored README.txt
+        let unpacked = unpack("rnr-windows-amd64.zip", ZIP.to_vec(), LIMIT).unwrap();
+        assert_eq!(unpacked, binary());
+        let files = zip_files(ZIP, LIMIT).unwrap();
+        assert!(files.iter().any(|(path, _)| path == "README.txt"));
+    }
+
+    #[test]
+    fn test_unpack_rejects_corrupt_and_oversized_archives() {
+        let mut corrupt = TAR_GZ.to_vec();
+        let middle = corrupt.len() / 2;
+        corrupt[middle] ^= 0xff;
+        assert!(unpack("rnr.tar.gz", corrupt, LIMIT).is_err());
+
+        let error = unpack("rnr.zip", ZIP.to_vec(), 100).unwrap_err();
+        assert!(
+            format!("{:#}", error).contains("more than 100 bytes"),
+            "{:#}",
+            error
+        );
+
+        let error = unpack("rnr.zip", b"not a zip".to_vec(), LIMIT).unwrap_err();
+        assert!(
+            format!("{:#}", error).starts_with("Failed to unpack rnr.zip: not a zip file"),
+            "{:#}",
+            error
+        );
+        let error = unpack("rnr.tar.gz", b"not a tar".to_vec(), LIMIT).unwrap_err();
+        assert!(
+            format!("{:#}", error).starts_with("Failed to unpack rnr.tar.gz:"),
+            "{:#}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_tar_gz_over_the_limit() {
+        let error = unpack("rnr.tar.gz", TAR_GZ.to_vec(), 100).unwrap_err();
+        assert!(
+            format!("{:#}", error).contains("more than 100 bytes"),
+            "{:#}",
+            error
+        );
+    }
+}
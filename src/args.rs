@@ -0,0 +1,305 @@
+//! Binding and validation of task arguments
+
+use anyhow::{bail, Result};
+use std::collections::HashMap;
+
+use crate::commands::help;
+use crate::config::TaskDef;
+use crate::ui::Ui;
+
+/// Arguments bound to a task invocation
+#[derive(Debug, Default, Clone)]
+pub struct TaskArgs {
+    /// Values of declared arguments, exposed as `${args.<name>}`
+    pub values: HashMap<String, String>,
+    /// Remaining arguments, appended to the task's command
+    pub extra: Vec<String>,
+    /// Value of the `foreach` iteration running, exposed as `${foreach.<as>}`
+    pub foreach: HashMap<String, String>,
+}
+
+impl TaskArgs {
+    /// Environment variables exposing the argument values (`RNR_ARG_<NAME>`)
+    pub fn env_vars(&self) -> HashMap<String, String> {
+        self.values
+            .iter()
+            .map(|(name, value)| (env_var_name(name), value.clone()))
+            .collect()
+    }
+}
+
+/// Environment variable name for an argument, e.g. `dry-run` -> `RNR_ARG_DRY_RUN`
+pub fn env_var_name(arg_name: &str) -> String {
+    let name: String = arg_name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("RNR_ARG_{}", name)
+}
+
+/// Bind command-line arguments to a task's declared args.
+///
+/// Declared args are matched by `--name value` / `--name=value` first, then
+/// positionally. Everything is validated before the task runs; a missing
+/// required argument prints the task's help.
+pub fn bind(task_name: &str, task: &TaskDef, args: &[String], ui: &Ui) -> Result<TaskArgs> {
+    let defs = task.arg_defs();
+    let usage = || help::usage_line(task_name, task);
+
+    // Pull out named arguments, leaving the rest positional
+    let mut named: HashMap<&str, String> = HashMap::new();
+    let mut positional: Vec<&String> = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let declared = arg.strip_prefix("--").and_then(|flag| {
+            let (name, inline) = match flag.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (flag, None),
+            };
+            defs.iter()
+                .find(|def| def.name == name)
+                .map(|def| (def.name.as_str(), inline))
+        });
+
+        match declared {
+            Some((name, Some(value))) => {
+                named.insert(name, value.to_string());
+            }
+            Some((name, None)) => match iter.next() {
+                Some(value) => {
+                    named.insert(name, value.clone());
+                }
+                None => bail!(
+                    "Argument '--{}' of task '{}' requires a value\nUsage: {}",
+                    name,
+                    task_name,
+                    usage()
+                ),
+            },
+            None => positional.push(arg),
+        }
+    }
+
+    let mut positional = positional.into_iter();
+    let mut values = HashMap::new();
+    let mut missing = Vec::new();
+    for def in defs {
+        let value = match named.remove(def.name.as_str()) {
+            Some(value) => Some(value),
+            None => positional.next().cloned(),
+        };
+        let value = match value.or_else(|| def.default.clone()) {
+            Some(value) => value,
+            None if def.required => {
+                missing.push(def.name.as_str());
+                continue;
+            }
+            None => String::new(),
+        };
+
+        if let Some(choices) = &def.choices {
+            if !value.is_empty() && !choices.contains(&value) {
+                bail!(
+                    "Invalid value '{}' for argument '{}' of task '{}' (expected one of: {})\nUsage: {}",
+                    value,
+                    def.name,
+                    task_name,
+                    choices.join(", "),
+                    usage()
+                );
+            }
+        }
+
+        values.insert(def.name.clone(), value);
+    }
+
+    if !missing.is_empty() {
+        ui.print_err(format!("{}\n", help::render(task_name, task)));
+        bail!(
+            "Missing required argument(s) for task '{}': {}",
+            task_name,
+            missing.join(", ")
+        );
+    }
+
+    let extra: Vec<String> = positional.cloned().collect();
+    if !extra.is_empty() {
+        let strict = matches!(task, TaskDef::Full(full) if full.strict_args);
+        if strict {
+            bail!(
+                "Unexpected argument(s) for task '{}': {}\nUsage: {}",
+                task_name,
+                extra.join(" "),
+                usage()
+            );
+        }
+
+        let runs_single_command = match task {
+            TaskDef::Shorthand(_) => true,
+            TaskDef::Full(full) => full.steps.is_none() && full.task.is_none(),
+        };
+        if !runs_single_command {
+            bail!(
+                "Task '{}' does not accept extra arguments: {}\nUsage: {}",
+                task_name,
+                extra.join(" "),
+                usage()
+            );
+        }
+    }
+
+    Ok(TaskArgs {
+        values,
+        extra,
+        foreach: HashMap::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ui() -> Ui {
+        Ui::capture(false).0
+    }
+
+    fn task(yaml: &str) -> TaskDef {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn deploy() -> TaskDef {
+        task(
+            r#"
+args:
+  - name: environment
+    required: true
+    choices: [staging, production]
+  - name: version
+    default: latest
+cmd: ./deploy.sh ${args.environment} ${args.version}
+"#,
+        )
+    }
+
+    #[test]
+    fn test_bind_positional_with_default() {
+        let bound = bind("deploy", &deploy(), &strings(&["staging"]), &ui()).unwrap();
+        assert_eq!(bound.values["environment"], "staging");
+        assert_eq!(bound.values["version"], "latest");
+        assert!(bound.extra.is_empty());
+    }
+
+    #[test]
+    fn test_bind_named_args() {
+        let bound = bind(
+            "deploy",
+            &deploy(),
+            &strings(&["--version", "1.2.3", "--environment=production"]),
+            &ui(),
+        )
+        .unwrap();
+        assert_eq!(bound.values["environment"], "production");
+        assert_eq!(bound.values["version"], "1.2.3");
+    }
+
+    #[test]
+    fn test_bind_named_and_positional_mix() {
+        let bound = bind(
+            "deploy",
+            &deploy(),
+            &strings(&["--version", "2.0.0", "staging"]),
+            &ui(),
+        )
+        .unwrap();
+        assert_eq!(bound.values["environment"], "staging");
+        assert_eq!(bound.values["version"], "2.0.0");
+    }
+
+    #[test]
+    fn test_bind_missing_required_arg() {
+        let (ui, capture) = Ui::capture(false);
+        let err = bind("deploy", &deploy(), &[], &ui).unwrap_err();
+        assert!(err.to_string().contains("environment"));
+        // The task's help is shown before the error
+        assert!(capture
+            .stderr()
+            .starts_with("deploy\n\nUsage: rnr deploy <environment> [version]"));
+    }
+
+    #[test]
+    fn test_bind_named_arg_without_value() {
+        let err = bind("deploy", &deploy(), &strings(&["--environment"]), &ui()).unwrap_err();
+        assert!(err.to_string().contains("requires a value"));
+    }
+
+    #[test]
+    fn test_bind_invalid_choice() {
+        let err = bind("deploy", &deploy(), &strings(&["qa"]), &ui()).unwrap_err();
+        let message = err.to_string();
+        assert!(message.contains("expected one of: staging, production"));
+        assert!(message.contains("Usage: rnr deploy <environment> [version]"));
+    }
+
+    #[test]
+    fn test_bind_extra_args_passthrough() {
+        let test = task("cargo test");
+        let bound = bind("test", &test, &strings(&["--", "--nocapture"]), &ui()).unwrap();
+        assert_eq!(bound.extra, strings(&["--", "--nocapture"]));
+    }
+
+    #[test]
+    fn test_bind_unknown_flags_pass_through() {
+        let bound = bind(
+            "deploy",
+            &deploy(),
+            &strings(&["staging", "1.0", "--verbose"]),
+            &ui(),
+        )
+        .unwrap();
+        assert_eq!(bound.extra, strings(&["--verbose"]));
+    }
+
+    #[test]
+    fn test_bind_strict_args_rejects_extra() {
+        let strict = task(
+            r#"
+args:
+  - name: name
+strict_args: true
+cmd: echo ${args.name}
+"#,
+        );
+        let err = bind("greet", &strict, &strings(&["a", "b"]), &ui()).unwrap_err();
+        assert!(err.to_string().contains("Unexpected argument(s)"));
+    }
+
+    #[test]
+    fn test_bind_extra_args_rejected_for_steps() {
+        let ci = task(
+            r#"
+steps:
+  - cmd: cargo test
+"#,
+        );
+        assert!(bind("ci", &ci, &strings(&["extra"]), &ui()).is_err());
+    }
+
+    #[test]
+    fn test_env_vars() {
+        let bound = bind("deploy", &deploy(), &strings(&["staging"]), &ui()).unwrap();
+        let env = bound.env_vars();
+        assert_eq!(env["RNR_ARG_ENVIRONMENT"], "staging");
+        assert_eq!(env["RNR_ARG_VERSION"], "latest");
+        assert_eq!(env_var_name("dry-run"), "RNR_ARG_DRY_RUN");
+    }
+}
@@ -0,0 +1,361 @@
+//! Tasks that make the files other tasks read: `outputs:` feeding `sources:`
+//!
+//! A task whose `sources` overlap another task's `outputs` needs that task
+//! first. Before a task runs, each task it needs (and what those need) runs
+//! if its outputs are stale, and is skipped if they're up to date. Whether
+//! they are comes from a fingerprint of each task's sources, recorded in
+//! `.rnr/cache/fingerprints.json` when it succeeds.
+
+use anyhow::{Context, Result};
+use glob::{MatchOptions, Pattern};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::checksum::Sha256Hasher;
+use crate::config::{self, Config, TaskDef};
+
+/// File (inside the cache) holding the fingerprint of each task's sources
+const FINGERPRINTS_FILE: &str = "fingerprints.json";
+
+/// One task needing another: `producer`'s `output` overlaps `task`'s `source`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edge {
+    /// The task reading the files
+    pub task: String,
+    /// The task making them
+    pub producer: String,
+    /// The glob of `producer`'s `outputs`
+    pub output: String,
+    /// The glob of `task`'s `sources`
+    pub source: String,
+}
+
+/// Every edge reachable from `task`, and the tasks it needs in the order
+/// they run, those needed by others first. Fails if the edges form a cycle.
+pub fn resolve(config: &Config, task: &str) -> Result<(Vec<Edge>, Vec<String>)> {
+    let mut resolver = Resolver {
+        config,
+        edges: Vec::new(),
+        order: Vec::new(),
+        path: Vec::new(),
+    };
+    resolver.visit(task)?;
+    // The task itself runs anyway
+    resolver.order.retain(|name| name != task);
+    Ok((resolver.edges, resolver.order))
+}
+
+/// Depth-first walk of the edges
+struct Resolver<'a> {
+    config: &'a Config,
+    edges: Vec<Edge>,
+    order: Vec<String>,
+    path: Vec<String>,
+}
+
+impl Resolver<'_> {
+    fn visit(&mut self, task: &str) -> Result<()> {
+        if let Some(start) = self.path.iter().position(|name| name == task) {
+            let mut cycle = self.path[start..].to_vec();
+            cycle.push(task.to_string());
+            anyhow::bail!(
+                "Tasks' outputs and sources form a cycle: {}; pass --no-auto-deps to run without them",
+                cycle.join(" -> ")
+            );
+        }
+        if self.order.iter().any(|name| name == task) {
+            return Ok(());
+        }
+        self.path.push(task.to_string());
+        for edge in needs(self.config, task)? {
+            let producer = edge.producer.clone();
+            if !self.edges.contains(&edge) {
+                self.edges.push(edge);
+            }
+            self.visit(&producer)?;
+        }
+        self.path.pop();
+        self.order.push(task.to_string());
+        Ok(())
+    }
+}
+
+/// The tasks `task` reads the outputs of, one edge per task: the first of
+/// its sources that overlaps one of their outputs
+fn needs(config: &Config, task: &str) -> Result<Vec<Edge>> {
+    let Some(sources) = config.get_task(task).and_then(TaskDef::sources) else {
+        return Ok(Vec::new());
+    };
+    let mut edges = Vec::new();
+    for producer in config.task_names() {
+        if producer == task {
+            continue;
+        }
+        let Some(outputs) = config.get_task(producer).and_then(TaskDef::outputs) else {
+            continue;
+        };
+        let overlap = sources.iter().find_map(|source| {
+            let output = outputs.iter().find(|output| overlaps(output, source))?;
+            Some((output.clone(), source.clone()))
+        });
+        if let Some((output, source)) = overlap {
+            check_glob(producer, &output)?;
+            check_glob(task, &source)?;
+            edges.push(Edge {
+                task: task.to_string(),
+                producer: producer.to_string(),
+                output,
+                source,
+            });
+        }
+    }
+    Ok(edges)
+}
+
+/// Whether two globs can match the same file: they're the same, or one
+/// matches the other as written (`dist/**` and `dist/app.js`, or `dist/*.js`).
+/// A glob naming a directory covers everything in it.
+fn overlaps(a: &str, b: &str) -> bool {
+    let (a, b) = (normalize(a), normalize(b));
+    let matches = |pattern: &str, path: &str| {
+        [pattern.to_string(), format!("{}/**", pattern)]
+            .iter()
+            .filter_map(|pattern| Pattern::new(pattern).ok())
+            .any(|pattern| pattern.matches_with(path, match_options()))
+    };
+    a == b || matches(&a, &b) || matches(&b, &a)
+}
+
+/// A glob without a leading `./` or trailing `/`
+fn normalize(glob: &str) -> String {
+    let glob = glob.strip_prefix("./").unwrap_or(glob);
+    glob.trim_end_matches('/').to_string()
+}
+
+/// `*` stays within a directory; `**` crosses them
+fn match_options() -> MatchOptions {
+    MatchOptions {
+        require_literal_separator: true,
+        ..MatchOptions::new()
+    }
+}
+
+/// Fail on a glob that can't be read
+fn check_glob(task: &str, glob: &str) -> Result<()> {
+    Pattern::new(glob)
+        .map(drop)
+        .with_context(|| format!("Task '{}' has an invalid glob '{}'", task, glob))
+}
+
+/// Why `task`'s outputs are stale, e.g. "its sources changed", or `None`
+/// when they're up to date
+pub fn stale(root: &Path, name: &str, task: &TaskDef) -> Result<Option<String>> {
+    let recorded = load(root)?;
+    let Some(recorded) = recorded.get(name) else {
+        return Ok(Some("it hasn't run yet".to_string()));
+    };
+    for output in task.outputs().unwrap_or_default() {
+        if files(root, output)?.is_empty() {
+            return Ok(Some(format!("nothing matches its output '{}'", output)));
+        }
+    }
+    if &fingerprint(root, task.sources().unwrap_or_default())? != recorded {
+        return Ok(Some("its sources changed".to_string()));
+    }
+    Ok(None)
+}
+
+/// Remember the sources `task` just succeeded with, if it has `outputs`
+/// another task might need
+pub fn record(root: &Path, name: &str, task: &TaskDef) -> Result<()> {
+    if task.outputs().is_none() {
+        return Ok(());
+    }
+    let mut recorded = load(root)?;
+    recorded.insert(
+        name.to_string(),
+        fingerprint(root, task.sources().unwrap_or_default())?,
+    );
+    let path = fingerprints_path(root);
+    config::create_cache_dir(root)?;
+    let content = serde_json::to_string_pretty(&recorded)?;
+    fs::write(&path, content).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Hash of the names and contents of the files `sources` match
+fn fingerprint(root: &Path, sources: &[String]) -> Result<String> {
+    let mut paths = Vec::new();
+    for source in sources {
+        paths.extend(files(root, source)?);
+    }
+    paths.sort();
+    paths.dedup();
+    let mut hasher = Sha256Hasher::default();
+    for path in paths {
+        let content =
+            fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let relative = path.strip_prefix(root).unwrap_or(&path);
+        hasher.update(relative.to_string_lossy().as_bytes());
+        hasher.update(b"\0");
+        hasher.update(&content);
+        hasher.update(b"\0");
+    }
+    Ok(hasher.finish())
+}
+
+/// The files `glob` matches under `root`; a directory stands for the
+/// files in it
+fn files(root: &Path, glob: &str) -> Result<Vec<PathBuf>> {
+    let root_pattern = Pattern::escape(&root.to_string_lossy());
+    let pattern = format!("{}/{}", root_pattern, normalize(glob));
+    let mut files = Vec::new();
+    let matches = glob::glob_with(&pattern, match_options())
+        .with_context(|| format!("Invalid glob '{}'", glob))?;
+    for path in matches.flatten() {
+        if path.is_dir() {
+            let inside = format!("{}/**/*", Pattern::escape(&path.to_string_lossy()));
+            let nested = glob::glob_with(&inside, match_options())?;
+            files.extend(nested.flatten().filter(|path| path.is_file()));
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+/// Path of the fingerprints file
+fn fingerprints_path(root: &Path) -> PathBuf {
+    root.join(config::CACHE_DIR).join(FINGERPRINTS_FILE)
+}
+
+/// The recorded fingerprints, by task
+fn load(root: &Path) -> Result<BTreeMap<String, String>> {
+    let path = fingerprints_path(root);
+    if !path.exists() {
+        return Ok(BTreeMap::new());
+    }
+    let content =
+        fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+    serde_json::from_str(&content).with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(yaml: &str) -> Config {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    const CHAIN: &str = "\
+gen:\n  sources: [schema.json]\n  outputs: ['src/generated/*.rs']\n  cmd: ./gen.sh\n\
+build:\n  sources: ['src/**/*.rs']\n  outputs: ['dist/*.bin']\n  cmd: make\n\
+package:\n  sources: [dist/app.bin, README.md]\n  outputs: [pkg.tar]\n  cmd: tar cf pkg.tar dist\n\
+lint: cargo clippy\n";
+
+    #[test]
+    fn test_overlaps() {
+        assert!(overlaps("dist/**", "dist/app.js"));
+        assert!(overlaps("dist/*.js", "dist/**"));
+        assert!(overlaps("./dist/", "dist"));
+        assert!(overlaps("dist/", "dist/app.js"));
+        assert!(overlaps("dist", "dist/*/app.js"));
+        // `dist/*` may be the directory `dist/css`
+        assert!(overlaps("dist/*", "dist/css/app.css"));
+        assert!(overlaps("src/**/*.rs", "src/generated/api.rs"));
+        assert!(!overlaps("dist/*.js", "dist/css/app.css"));
+        assert!(!overlaps("build/**", "dist/**"));
+        assert!(!overlaps("dist", "distribution/app.js"));
+    }
+
+    #[test]
+    fn test_resolve_chain() {
+        let config = config(CHAIN);
+        let (edges, order) = resolve(&config, "package").unwrap();
+        assert_eq!(order, ["gen", "build"]);
+        assert_eq!(
+            edges,
+            [
+                Edge {
+                    task: "package".into(),
+                    producer: "build".into(),
+                    output: "dist/*.bin".into(),
+                    source: "dist/app.bin".into(),
+                },
+                Edge {
+                    task: "build".into(),
+                    producer: "gen".into(),
+                    output: "src/generated/*.rs".into(),
+                    source: "src/**/*.rs".into(),
+                }
+            ]
+        );
+        assert_eq!(resolve(&config, "build").unwrap().1, ["gen"]);
+        assert_eq!(resolve(&config, "lint").unwrap(), (Vec::new(), Vec::new()));
+    }
+
+    #[test]
+    fn test_cycles() {
+        let config = config(
+            "a:\n  sources: [b.out]\n  outputs: [a.out]\n  cmd: x\n\
+             b:\n  sources: [a.out]\n  outputs: [b.out]\n  cmd: x\n\
+             c:\n  sources: [a.out]\n  cmd: x\n",
+        );
+        let err = resolve(&config, "c").unwrap_err().to_string();
+        assert_eq!(
+            err,
+            "Tasks' outputs and sources form a cycle: a -> b -> a; pass --no-auto-deps to run without them"
+        );
+        // A task reading its own outputs doesn't need itself
+        let config = self::config("fmt:\n  sources: ['*.rs']\n  outputs: ['*.rs']\n  cmd: x\n");
+        assert!(resolve(&config, "fmt").unwrap().1.is_empty());
+    }
+
+    #[test]
+    fn test_stale_until_recorded() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let config = config(CHAIN);
+        let build = config.get_task("build").unwrap();
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(root.join("src/main.rs"), "fn main() {}").unwrap();
+
+        assert_eq!(
+            stale(root, "build", build).unwrap().as_deref(),
+            Some("it hasn't run yet")
+        );
+        record(root, "build", build).unwrap();
+        assert_eq!(
+            stale(root, "build", build).unwrap().as_deref(),
+            Some("nothing matches its output 'dist/*.bin'")
+        );
+        fs::create_dir_all(root.join("dist")).unwrap();
+        fs::write(root.join("dist/app.bin"), "bin").unwrap();
+        assert_eq!(stale(root, "build", build).unwrap(), None);
+
+        fs::write(root.join("src/lib.rs"), "").unwrap();
+        assert_eq!(
+            stale(root, "build", build).unwrap().as_deref(),
+            Some("its sources changed")
+        );
+        record(root, "build", build).unwrap();
+        assert_eq!(stale(root, "build", build).unwrap(), None);
+
+        // Tasks without outputs aren't recorded
+        record(root, "lint", config.get_task("lint").unwrap()).unwrap();
+        assert!(!load(root).unwrap().contains_key("lint"));
+    }
+
+    #[test]
+    fn test_directories_stand_for_their_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("out/nested")).unwrap();
+        fs::write(root.join("out/nested/a.txt"), "a").unwrap();
+        let before = fingerprint(root, &["out/".to_string()]).unwrap();
+        fs::write(root.join("out/nested/a.txt"), "b").unwrap();
+        assert_ne!(fingerprint(root, &["out".to_string()]).unwrap(), before);
+        assert_eq!(files(root, "out").unwrap().len(), 1);
+    }
+}
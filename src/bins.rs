@@ -0,0 +1,190 @@
+//! Project-local programs named in rnr.yaml's `bins`
+//!
+//! `bins: { dprint: .tools/dprint }` lets commands run `${bins.dprint}`
+//! instead of spelling out the path. Paths are relative to the directory of
+//! the rnr.yaml declaring them; on Windows, `.exe` is added when only that
+//! file exists.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::config::{self, Cmd, Config, TaskDef};
+use crate::interpolate;
+
+/// Where a bin declared as `path` in an rnr.yaml in `dir` is on this platform
+pub fn resolve(dir: &Path, path: &str) -> PathBuf {
+    resolve_for(dir, path, cfg!(windows))
+}
+
+/// [`resolve`], adding `.exe` to a missing extension-less file if `windows`
+fn resolve_for(dir: &Path, path: &str, windows: bool) -> PathBuf {
+    let path = config::join_dir(dir, path);
+    if windows && path.extension().is_none() && !path.exists() {
+        let exe = path.with_extension("exe");
+        if exe.exists() {
+            return exe;
+        }
+    }
+    path
+}
+
+/// Every bin `config` declares, by name, at its absolute path
+pub fn paths(config: &Config) -> HashMap<String, String> {
+    let dir = dir(config);
+    config
+        .bins
+        .iter()
+        .map(|(name, path)| (name.clone(), resolve(&dir, path).display().to_string()))
+        .collect()
+}
+
+/// The directory `config`'s bins are relative to
+fn dir(config: &Config) -> PathBuf {
+    let dir = config
+        .path()
+        .and_then(Path::parent)
+        .unwrap_or(Path::new(""));
+    std::path::absolute(dir).unwrap_or_else(|_| dir.to_path_buf())
+}
+
+/// Names of the bins `text` refers to as `${bins.<name>}`, in order
+pub fn referenced(text: &str) -> Vec<String> {
+    let names = RefCell::new(Vec::new());
+    let _ = interpolate::render(text, |key| {
+        if let Some(name) = key.strip_prefix("bins.") {
+            let mut names = names.borrow_mut();
+            if !names.iter().any(|seen| seen == name) {
+                names.push(name.to_string());
+            }
+        }
+        Some(String::new())
+    });
+    names.into_inner()
+}
+
+/// Names of the bins `cmd` refers to, in order
+pub fn used_by_cmd(cmd: &Cmd) -> Vec<String> {
+    let text = match cmd {
+        Cmd::Shell(line) => line.clone(),
+        Cmd::Argv(argv) => argv.join(" "),
+    };
+    referenced(&text)
+}
+
+/// The bins a task's own commands use, leaving out the tasks it runs
+fn used_by(task_def: &TaskDef) -> Vec<String> {
+    let task = match task_def {
+        TaskDef::Shorthand(cmd) => return referenced(cmd),
+        TaskDef::Full(task) => task,
+    };
+    let steps = task
+        .steps
+        .iter()
+        .flatten()
+        .flat_map(|step| step.step_defs());
+    let cmds = task
+        .cmd
+        .iter()
+        .chain(steps.filter_map(|step| step.cmd.as_ref()));
+    let mut names: Vec<String> = Vec::new();
+    for name in cmds.flat_map(used_by_cmd) {
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+}
+
+/// What's wrong with the bins task `name` uses: ones `config` doesn't
+/// declare, and ones that aren't there
+pub fn problems(name: &str, task_def: &TaskDef, config: &Config) -> Vec<String> {
+    let dir = dir(config);
+    used_by(task_def)
+        .into_iter()
+        .filter_map(|bin| match config.bins.get(&bin) {
+            None => Some(format!(
+                "task '{}' uses ${{bins.{}}}, but '{}' isn't listed under 'bins' in {}",
+                name,
+                bin,
+                bin,
+                config::CONFIG_FILE
+            )),
+            Some(path) if !resolve(&dir, path).is_file() => Some(format!(
+                "task '{}' needs bin '{}', but {} doesn't exist",
+                name, bin, path
+            )),
+            Some(_) => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_resolve_adds_exe_on_windows() {
+        let temp = tempfile::tempdir().unwrap();
+        let tools = temp.path().join(".tools");
+        fs::create_dir(&tools).unwrap();
+        fs::write(tools.join("dprint.exe"), "").unwrap();
+        fs::write(tools.join("cog"), "").unwrap();
+
+        let root = temp.path();
+        assert_eq!(
+            resolve_for(root, ".tools/dprint", true),
+            tools.join("dprint.exe")
+        );
+        assert_eq!(
+            resolve_for(root, ".tools/dprint", false),
+            tools.join("dprint")
+        );
+        // The extension-less file wins when it's there
+        assert_eq!(resolve_for(root, ".tools/cog", true), tools.join("cog"));
+        assert_eq!(resolve_for(root, ".tools/fmt", true), tools.join("fmt"));
+    }
+
+    #[test]
+    fn test_referenced() {
+        assert_eq!(
+            referenced("${bins.dprint} fmt && ${bins.cog} check ${bins.dprint}"),
+            ["dprint", "cog"]
+        );
+        assert!(referenced("$${bins.dprint} ${env.HOME} ${HOME}").is_empty());
+    }
+
+    #[test]
+    fn test_problems() {
+        let temp = tempfile::tempdir().unwrap();
+        fs::create_dir(temp.path().join(".tools")).unwrap();
+        fs::write(temp.path().join(".tools").join("dprint"), "").unwrap();
+        let path = temp.path().join(config::CONFIG_FILE);
+        fs::write(
+            &path,
+            "bins:\n  dprint: .tools/dprint\n  cog: .tools/cog\n\
+             fmt: ${bins.dprint} fmt\n\
+             release:\n  steps:\n    - cmd: [\"${bins.cog}\", bump]\n    - parallel:\n        - cmd: ${bins.git-cliff}\n    - task: fmt\n",
+        )
+        .unwrap();
+        let config = Config::load_from(&path).unwrap();
+
+        let check = |name| problems(name, config.get_task(name).unwrap(), &config);
+        assert!(check("fmt").is_empty());
+        assert_eq!(
+            check("release"),
+            [
+                "task 'release' needs bin 'cog', but .tools/cog doesn't exist",
+                "task 'release' uses ${bins.git-cliff}, but 'git-cliff' isn't listed under 'bins' in rnr.yaml",
+            ]
+        );
+
+        let paths = paths(&config);
+        assert_eq!(
+            Path::new(&paths["dprint"]),
+            temp.path().join(".tools").join("dprint")
+        );
+        assert!(Path::new(&paths["cog"]).is_absolute());
+    }
+}
@@ -0,0 +1,114 @@
+//! What this rnr binary is: version, commit, target, and features, for
+//! `rnr --version --verbose`, `rnr version --json`, the top of
+//! `rnr verify`, and support tickets
+
+use serde::Serialize;
+use std::fmt::Write;
+
+/// Version of the running binary
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Commit rnr was built from (`unknown` outside a git checkout)
+pub const GIT_SHA: &str = env!("RNR_GIT_SHA");
+
+/// UTC date of the build, `YYYY-MM-DD`
+pub const BUILD_DATE: &str = env!("RNR_BUILD_DATE");
+
+/// Target triple the binary was built for
+pub const TARGET: &str = env!("RNR_TARGET");
+
+/// Cargo features compiled in
+pub fn features() -> Vec<&'static str> {
+    let mut features = Vec::new();
+    if cfg!(feature = "network") {
+        features.push("network");
+    }
+    if cfg!(feature = "tokio") {
+        features.push("tokio");
+    }
+    if cfg!(feature = "otel") {
+        features.push("otel");
+    }
+    features
+}
+
+/// Everything `rnr version --json` reports
+#[derive(Debug, Serialize)]
+pub struct VersionReport {
+    pub binary_version: &'static str,
+    pub git_sha: &'static str,
+    pub build_date: &'static str,
+    pub target: &'static str,
+    pub features: Vec<&'static str>,
+    /// The project's rnr version, if run inside an initialized project
+    pub config_version: Option<String>,
+    /// The project's configured platforms
+    pub platforms: Vec<String>,
+}
+
+impl VersionReport {
+    /// This binary's details, with the project's version and platforms
+    pub fn new(config_version: Option<String>, platforms: Vec<String>) -> Self {
+        Self {
+            binary_version: VERSION,
+            git_sha: GIT_SHA,
+            build_date: BUILD_DATE,
+            target: TARGET,
+            features: features(),
+            config_version,
+            platforms,
+        }
+    }
+}
+
+/// The `rnr --version --verbose` block
+pub fn verbose_version() -> String {
+    let features = features();
+    let mut out = format!("rnr {}\n", VERSION);
+    let _ = writeln!(out, "  commit:   {}", GIT_SHA);
+    let _ = writeln!(out, "  built:    {}", BUILD_DATE);
+    let _ = writeln!(out, "  target:   {}", TARGET);
+    let _ = writeln!(
+        out,
+        "  features: {}",
+        if features.is_empty() {
+            "none".to_string()
+        } else {
+            features.join(", ")
+        }
+    );
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_fields() {
+        let report = VersionReport::new(Some("0.1.0".to_string()), vec!["linux-amd64".into()]);
+        let json = serde_json::to_value(&report).unwrap();
+        for field in [
+            "binary_version",
+            "git_sha",
+            "build_date",
+            "target",
+            "features",
+            "config_version",
+            "platforms",
+        ] {
+            assert!(json.get(field).is_some(), "missing {}", field);
+        }
+        assert_eq!(json["binary_version"], VERSION);
+        assert_eq!(json["platforms"][0], "linux-amd64");
+        assert!(!TARGET.is_empty());
+        assert_eq!(BUILD_DATE.len(), 10);
+    }
+
+    #[test]
+    fn test_verbose_version() {
+        let text = verbose_version();
+        assert!(text.starts_with(&format!("rnr {}\n", VERSION)));
+        assert!(text.contains(&format!("  target:   {}\n", TARGET)));
+    }
+}
@@ -0,0 +1,971 @@
+//! Builtin file-operation steps (copy, remove, mkdir, download, render),
+//! and reading the files of `env_file` steps
+//!
+//! These are implemented in Rust so tasks behave the same on every platform
+//! instead of relying on `cp -r` vs `xcopy` or `rm -rf` vs `rmdir /s /q`.
+//! Deep trees beyond Windows' MAX_PATH work because std transparently switches
+//! to verbatim (`\\?\`) paths for long absolute paths.
+
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::checksum;
+use crate::config::{CopySpec, DownloadSpec, FileMode, RemoveSpec, RenderSpec};
+use crate::interpolate::Scope;
+use crate::permissions;
+
+/// Outcome of a download step
+#[derive(Debug, PartialEq, Eq)]
+pub enum DownloadOutcome {
+    /// The file was fetched, with its size in bytes
+    #[cfg_attr(not(feature = "network"), allow(dead_code))]
+    Downloaded(u64),
+    /// The destination already exists with the expected checksum
+    UpToDate,
+    /// Dry-run: the file would be fetched
+    WouldDownload,
+}
+
+/// Copy files or directories, returning the number of files copied
+/// (or that would be copied in dry-run mode)
+pub fn copy(spec: &CopySpec, work_dir: &Path, dry_run: bool) -> Result<usize> {
+    let sources = resolve(&spec.from, work_dir)?;
+    if sources.is_empty() {
+        bail!("copy: nothing matches '{}'", spec.from);
+    }
+
+    let dest = work_dir.join(&spec.to);
+    let glob = is_glob(&spec.from);
+    let into_dir = glob || sources.len() > 1 || ends_with_separator(&spec.to) || dest.is_dir();
+
+    let mut count = 0;
+    for source in &sources {
+        let name = source
+            .file_name()
+            .with_context(|| format!("copy: invalid source path {}", source.display()))?;
+
+        if source.is_dir() {
+            // A directory named explicitly has its contents copied into the
+            // destination; directories matched by a glob keep their own name.
+            let target = if glob { dest.join(name) } else { dest.clone() };
+            count += copy_dir(source, &target, dry_run)?;
+        } else {
+            let target = if into_dir {
+                dest.join(name)
+            } else {
+                dest.clone()
+            };
+            if !dry_run {
+                copy_file(source, &target)?;
+            }
+            count += 1;
+        }
+    }
+
+    Ok(count)
+}
+
+/// Remove files or directories, returning the number of files removed
+/// (or that would be removed in dry-run mode)
+pub fn remove(
+    spec: &RemoveSpec,
+    work_dir: &Path,
+    project_root: &Path,
+    dry_run: bool,
+) -> Result<usize> {
+    let targets = resolve(spec.path(), work_dir)?;
+
+    // Check every target before touching anything
+    if !spec.allow_outside_root() {
+        let root = fs::canonicalize(project_root).with_context(|| {
+            format!("Failed to resolve project root: {}", project_root.display())
+        })?;
+        for target in &targets {
+            if !is_within(target, &root)? {
+                bail!(
+                    "Refusing to remove {} because it is outside the project root ({}).\n\
+                     Use `remove: {{ path: ..., allow_outside_root: true }}` to allow this.",
+                    target.display(),
+                    root.display()
+                );
+            }
+        }
+    }
+
+    let mut count = 0;
+    for target in &targets {
+        let metadata = fs::symlink_metadata(target)
+            .with_context(|| format!("Failed to read {}", target.display()))?;
+
+        if metadata.is_dir() {
+            count += count_files(target)?;
+            if !dry_run {
+                make_tree_writable(target)?;
+                fs::remove_dir_all(target)
+                    .with_context(|| format!("Failed to remove {}", target.display()))?;
+            }
+        } else {
+            count += 1;
+            if !dry_run {
+                make_writable(target)?;
+                fs::remove_file(target)
+                    .with_context(|| format!("Failed to remove {}", target.display()))?;
+            }
+        }
+    }
+
+    Ok(count)
+}
+
+/// Create a directory and any missing parents, returning whether it was created
+pub fn mkdir(path: &str, work_dir: &Path, dry_run: bool) -> Result<bool> {
+    let dir = work_dir.join(path);
+    if dir.is_dir() {
+        return Ok(false);
+    }
+    if !dry_run {
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create directory: {}", dir.display()))?;
+    }
+    Ok(true)
+}
+
+/// Download a file, verifying its checksum when one is given
+pub fn download(spec: &DownloadSpec, work_dir: &Path, dry_run: bool) -> Result<DownloadOutcome> {
+    let dest = work_dir.join(&spec.to);
+
+    if let Some(expected) = &spec.sha256 {
+        if dest.is_file() && checksum::matches(expected, &checksum::sha256_file(&dest)?) {
+            return Ok(DownloadOutcome::UpToDate);
+        }
+    }
+
+    if dry_run {
+        return Ok(DownloadOutcome::WouldDownload);
+    }
+
+    #[cfg(feature = "network")]
+    {
+        fetch(spec, &dest).map(DownloadOutcome::Downloaded)
+    }
+
+    #[cfg(not(feature = "network"))]
+    {
+        bail!(
+            "Cannot download {}: this rnr build does not include the network feature",
+            spec.url
+        )
+    }
+}
+
+/// Stream a URL into `dest` via a temporary file, returning the number of bytes written
+#[cfg(feature = "network")]
+fn fetch(spec: &DownloadSpec, dest: &Path) -> Result<u64> {
+    use std::io::{Read, Write};
+
+    let client = crate::http::client()?;
+    let mut request = client.get(&spec.url);
+    if let Some(var) = &spec.token_env {
+        let token = std::env::var(var).with_context(|| {
+            format!(
+                "Environment variable {} (token_env for {}) is not set",
+                var, spec.url
+            )
+        })?;
+        request = request.bearer_auth(token);
+    }
+
+    let mut response = crate::http::send(request, &spec.url)
+        .with_context(|| format!("Failed to download to {}", dest.display()))?;
+
+    if !response.status().is_success() {
+        bail!(
+            "Failed to download {}: HTTP {} (destination: {})",
+            spec.url,
+            response.status().as_u16(),
+            dest.display()
+        );
+    }
+
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+    }
+
+    let mut partial = dest.as_os_str().to_owned();
+    partial.push(".download");
+    let partial = PathBuf::from(partial);
+
+    let mut file = fs::File::create(&partial)
+        .with_context(|| format!("Failed to create {}", partial.display()))?;
+    let mut hasher = checksum::Sha256Hasher::default();
+    let mut buf = [0u8; 64 * 1024];
+    let mut total = 0u64;
+    loop {
+        let n = response
+            .read(&mut buf)
+            .with_context(|| format!("Failed to read response from {}", spec.url))?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        file.write_all(&buf[..n])
+            .with_context(|| format!("Failed to write {}", partial.display()))?;
+        total += n as u64;
+    }
+    drop(file);
+
+    if let Some(expected) = &spec.sha256 {
+        let actual = hasher.finish();
+        if !checksum::matches(expected, &actual) {
+            let _ = fs::remove_file(&partial);
+            bail!(
+                "Checksum mismatch for {} (destination: {})\n  expected: {}\n  actual:   {}",
+                spec.url,
+                dest.display(),
+                expected.trim(),
+                actual
+            );
+        }
+    }
+
+    if dest.exists() {
+        make_writable(dest)?;
+    }
+    fs::rename(&partial, dest)
+        .with_context(|| format!("Failed to move download into {}", dest.display()))?;
+
+    Ok(total)
+}
+
+/// Render a template through `${...}` interpolation into the destination file.
+/// Returns the rendered content (which is not written in dry-run mode).
+pub fn render(spec: &RenderSpec, work_dir: &Path, scope: &Scope, dry_run: bool) -> Result<String> {
+    let (source, template) = match (&spec.template, &spec.content) {
+        (Some(path), None) => {
+            let path = work_dir.join(path);
+            let template = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read template: {}", path.display()))?;
+            (path.display().to_string(), template)
+        }
+        (None, Some(content)) => ("inline content".to_string(), content.clone()),
+        _ => bail!("render: specify exactly one of `template` or `content`"),
+    };
+
+    let rendered = scope
+        .render(&template)
+        .with_context(|| format!("Failed to render {}", source))?;
+
+    if !dry_run {
+        let dest = work_dir.join(&spec.to);
+        write_file(&dest, rendered.as_bytes(), spec.mode)?;
+    }
+
+    Ok(rendered)
+}
+
+/// Read a dotenv-style file of `KEY=value` lines, in the order written.
+/// Blank lines and `#` comments are skipped, a leading `export ` is allowed,
+/// and a value wrapped in matching quotes has them removed. Values are taken
+/// as written, without interpolation.
+pub fn read_env_file(path: &Path) -> Result<Vec<(String, String)>> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read env file: {}", path.display()))?;
+    let mut vars = Vec::new();
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let Some((name, value)) = line.split_once('=') else {
+            bail!(
+                "{}:{}: expected KEY=value, found '{}'",
+                path.display(),
+                number + 1,
+                line
+            );
+        };
+        let name = name.trim();
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            bail!(
+                "{}:{}: '{}' is not a variable name",
+                path.display(),
+                number + 1,
+                name
+            );
+        }
+        let value = value.trim();
+        let unquoted = ['"', '\''].iter().find_map(|&quote| {
+            value
+                .strip_prefix(quote)
+                .and_then(|rest| rest.strip_suffix(quote))
+        });
+        vars.push((name.to_string(), unquoted.unwrap_or(value).to_string()));
+    }
+    Ok(vars)
+}
+
+/// Write a file, creating parent directories and applying an optional mode.
+/// On Unix the mode is applied at creation so secrets are never briefly
+/// world-readable; elsewhere it is ignored.
+pub fn write_file(dest: &Path, content: &[u8], mode: Option<FileMode>) -> Result<()> {
+    use std::io::Write;
+
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+    }
+    if dest.exists() {
+        make_writable(dest)?;
+    }
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+
+    #[cfg(unix)]
+    if let Some(FileMode(mode)) = mode {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(mode);
+    }
+
+    let mut file = options
+        .open(dest)
+        .with_context(|| format!("Failed to create {}", dest.display()))?;
+    file.write_all(content)
+        .with_context(|| format!("Failed to write {}", dest.display()))?;
+
+    match mode {
+        Some(mode) => permissions::set(dest, mode),
+        None => Ok(()),
+    }
+}
+
+/// Check whether a path contains glob metacharacters
+fn is_glob(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '['])
+}
+
+/// Check whether a path ends with a directory separator
+fn ends_with_separator(path: &str) -> bool {
+    path.ends_with('/') || path.ends_with('\\')
+}
+
+/// Resolve a path or glob relative to the working directory into existing paths
+fn resolve(pattern: &str, work_dir: &Path) -> Result<Vec<PathBuf>> {
+    if !is_glob(pattern) {
+        let path = work_dir.join(pattern);
+        return Ok(if fs::symlink_metadata(&path).is_ok() {
+            vec![path]
+        } else {
+            Vec::new()
+        });
+    }
+
+    let base = glob::Pattern::escape(&work_dir.to_string_lossy());
+    let full = format!("{}/{}", base, pattern);
+    let mut paths = Vec::new();
+    for entry in glob::glob(&full).with_context(|| format!("Invalid glob pattern: {}", pattern))? {
+        paths.push(entry.with_context(|| format!("Failed to expand glob: {}", pattern))?);
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// Check whether a path lies inside `root` (which must already be canonical)
+/// without being `root` itself. Only a final link is left unresolved, so
+/// removing a link inside the project is allowed even when it points
+/// elsewhere; a path ending in `.`, `..` or a separator is resolved in full,
+/// as the filesystem would.
+fn is_within(path: &Path, root: &Path) -> Result<bool> {
+    // `Path` drops a trailing `.` or separator, so look at what's written
+    let last = path.file_name().filter(|name| {
+        path.as_os_str()
+            .as_encoded_bytes()
+            .ends_with(name.as_encoded_bytes())
+    });
+    let resolved = match (path.parent(), last) {
+        (Some(parent), Some(name)) if !parent.as_os_str().is_empty() => fs::canonicalize(parent)
+            .with_context(|| format!("Failed to resolve {}", parent.display()))?
+            .join(name),
+        _ => fs::canonicalize(path)
+            .with_context(|| format!("Failed to resolve {}", path.display()))?,
+    };
+    Ok(resolved.starts_with(root) && resolved != root)
+}
+
+/// Recursively copy a directory's contents, returning the number of files
+fn copy_dir(source: &Path, dest: &Path, dry_run: bool) -> Result<usize> {
+    if !dry_run {
+        fs::create_dir_all(dest)
+            .with_context(|| format!("Failed to create directory: {}", dest.display()))?;
+    }
+
+    let mut count = 0;
+    for entry in
+        fs::read_dir(source).with_context(|| format!("Failed to read {}", source.display()))?
+    {
+        let entry = entry?;
+        let path = entry.path();
+        let target = dest.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            count += copy_dir(&path, &target, dry_run)?;
+        } else {
+            if !dry_run {
+                copy_file(&path, &target)?;
+            }
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+/// Copy a single file, creating parent directories and replacing a readonly
+/// destination
+fn copy_file(source: &Path, dest: &Path) -> Result<()> {
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+    }
+    if dest.exists() {
+        make_writable(dest)?;
+    }
+    fs::copy(source, dest)
+        .with_context(|| format!("Failed to copy {} to {}", source.display(), dest.display()))?;
+    Ok(())
+}
+
+/// Count the files below a directory
+fn count_files(dir: &Path) -> Result<usize> {
+    let mut count = 0;
+    for entry in fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            count += count_files(&entry.path())?;
+        } else {
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+/// Clear readonly attributes throughout a tree so it can be deleted
+fn make_tree_writable(dir: &Path) -> Result<()> {
+    make_writable(dir)?;
+    for entry in fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            make_tree_writable(&entry.path())?;
+        } else {
+            make_writable(&entry.path())?;
+        }
+    }
+    Ok(())
+}
+
+/// Clear the readonly attribute on a path (owner write bit on Unix)
+fn make_writable(path: &Path) -> Result<()> {
+    let metadata =
+        fs::symlink_metadata(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    if metadata.file_type().is_symlink() || !metadata.permissions().readonly() {
+        return Ok(());
+    }
+
+    let mut perms = metadata.permissions();
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        perms.set_mode(perms.mode() | 0o200);
+    }
+
+    #[cfg(not(unix))]
+    {
+        #[allow(clippy::permissions_set_readonly_false)]
+        perms.set_readonly(false);
+    }
+
+    fs::set_permissions(path, perms)
+        .with_context(|| format!("Failed to clear readonly flag on {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(path: &Path, content: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    fn set_readonly(path: &Path) {
+        let mut perms = fs::metadata(path).unwrap().permissions();
+        perms.set_readonly(true);
+        fs::set_permissions(path, perms).unwrap();
+    }
+
+    fn copy_spec(from: &str, to: &str) -> CopySpec {
+        CopySpec {
+            from: from.to_string(),
+            to: to.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_copy_directory_contents() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        write(&root.join("dist/app.js"), "app");
+        write(&root.join("dist/css/site.css"), "css");
+
+        let count = copy(&copy_spec("dist/", "release/assets/"), root, false).unwrap();
+
+        assert_eq!(count, 2);
+        assert!(root.join("release/assets/app.js").exists());
+        assert!(root.join("release/assets/css/site.css").exists());
+    }
+
+    #[test]
+    fn test_copy_single_file_to_path() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        write(&root.join("schema.json"), "{}");
+
+        let count = copy(
+            &copy_spec("schema.json", "out/schema.copy.json"),
+            root,
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(count, 1);
+        assert!(root.join("out/schema.copy.json").exists());
+    }
+
+    #[test]
+    fn test_copy_glob_into_directory() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        write(&root.join("a.txt"), "a");
+        write(&root.join("b.txt"), "b");
+        write(&root.join("c.md"), "c");
+
+        let count = copy(&copy_spec("*.txt", "texts"), root, false).unwrap();
+
+        assert_eq!(count, 2);
+        assert!(root.join("texts/a.txt").exists());
+        assert!(root.join("texts/b.txt").exists());
+        assert!(!root.join("texts/c.md").exists());
+    }
+
+    #[test]
+    fn test_copy_over_readonly_destination() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        write(&root.join("new.txt"), "new");
+        write(&root.join("out/new.txt"), "old");
+        set_readonly(&root.join("out/new.txt"));
+
+        copy(&copy_spec("new.txt", "out/"), root, false).unwrap();
+
+        assert_eq!(fs::read_to_string(root.join("out/new.txt")).unwrap(), "new");
+    }
+
+    #[test]
+    fn test_copy_missing_source_errors() {
+        let temp = tempfile::tempdir().unwrap();
+        let result = copy(&copy_spec("missing/", "out/"), temp.path(), false);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_copy_dry_run_changes_nothing() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        write(&root.join("dist/app.js"), "app");
+
+        let count = copy(&copy_spec("dist/", "release/"), root, true).unwrap();
+
+        assert_eq!(count, 1);
+        assert!(!root.join("release").exists());
+    }
+
+    #[test]
+    fn test_remove_glob() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        write(&root.join("tmp/a.log"), "a");
+        write(&root.join("tmp/b.log"), "b");
+        write(&root.join("tmp/keep.txt"), "keep");
+
+        let spec = RemoveSpec::Path("tmp/*.log".to_string());
+        let count = remove(&spec, root, root, false).unwrap();
+
+        assert_eq!(count, 2);
+        assert!(!root.join("tmp/a.log").exists());
+        assert!(root.join("tmp/keep.txt").exists());
+    }
+
+    #[test]
+    fn test_remove_directory_with_readonly_files() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        write(&root.join("target/tmp/locked.txt"), "locked");
+        set_readonly(&root.join("target/tmp/locked.txt"));
+
+        let spec = RemoveSpec::Path("target/tmp".to_string());
+        let count = remove(&spec, root, root, false).unwrap();
+
+        assert_eq!(count, 1);
+        assert!(!root.join("target/tmp").exists());
+    }
+
+    #[test]
+    fn test_remove_missing_path_is_noop() {
+        let temp = tempfile::tempdir().unwrap();
+        let spec = RemoveSpec::Path("nothing-here".to_string());
+        assert_eq!(remove(&spec, temp.path(), temp.path(), false).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_remove_refuses_outside_root() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().join("project");
+        write(&temp.path().join("outside.txt"), "outside");
+        fs::create_dir_all(&root).unwrap();
+
+        let spec = RemoveSpec::Path("../outside.txt".to_string());
+        let err = remove(&spec, &root, &root, false).unwrap_err();
+
+        assert!(err.to_string().contains("outside the project root"));
+        assert!(temp.path().join("outside.txt").exists());
+    }
+
+    #[test]
+    fn test_remove_outside_root_when_allowed() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().join("project");
+        write(&temp.path().join("outside.txt"), "outside");
+        fs::create_dir_all(&root).unwrap();
+
+        let spec = RemoveSpec::Full {
+            path: "../outside.txt".to_string(),
+            allow_outside_root: true,
+        };
+        assert_eq!(remove(&spec, &root, &root, false).unwrap(), 1);
+        assert!(!temp.path().join("outside.txt").exists());
+    }
+
+    #[test]
+    fn test_remove_refuses_project_root_itself() {
+        let temp = tempfile::tempdir().unwrap();
+        let spec = RemoveSpec::Path(".".to_string());
+        assert!(remove(&spec, temp.path(), temp.path(), false).is_err());
+    }
+
+    #[test]
+    fn test_remove_refuses_paths_resolving_to_the_root() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        write(&root.join("sub/a.txt"), "a");
+        write(&root.join("keep.txt"), "keep");
+
+        for path in ["sub/..", ".", "sub/../.", "./sub/..", "sub/../"] {
+            let spec = RemoveSpec::Path(path.to_string());
+            let err = remove(&spec, root, root, false).unwrap_err();
+            assert!(
+                err.to_string().contains("outside the project root"),
+                "{}",
+                path
+            );
+        }
+        let spec = RemoveSpec::Path("sub/../..".to_string());
+        assert!(remove(&spec, root, root, false).is_err());
+        assert!(root.join("keep.txt").exists());
+        assert!(root.join("sub/a.txt").exists());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_remove_refuses_through_a_link_outside_the_root() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().join("project");
+        write(&temp.path().join("elsewhere/victim.txt"), "victim");
+        fs::create_dir_all(&root).unwrap();
+        std::os::unix::fs::symlink(temp.path().join("elsewhere"), root.join("link")).unwrap();
+
+        for path in ["link/victim.txt", "link/.", "link/..", "link/"] {
+            let spec = RemoveSpec::Path(path.to_string());
+            assert!(remove(&spec, &root, &root, false).is_err(), "{}", path);
+        }
+        assert!(temp.path().join("elsewhere/victim.txt").exists());
+
+        // The link itself is in the project, and only it goes
+        let spec = RemoveSpec::Path("link".to_string());
+        assert_eq!(remove(&spec, &root, &root, false).unwrap(), 1);
+        assert!(!root.join("link").exists());
+        assert!(temp.path().join("elsewhere/victim.txt").exists());
+    }
+
+    #[test]
+    fn test_remove_dry_run_changes_nothing() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        write(&root.join("tmp/a.log"), "a");
+
+        let spec = RemoveSpec::Path("tmp".to_string());
+        assert_eq!(remove(&spec, root, root, true).unwrap(), 1);
+        assert!(root.join("tmp/a.log").exists());
+    }
+
+    #[test]
+    fn test_mkdir() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+
+        assert!(mkdir("build/output", root, false).unwrap());
+        assert!(root.join("build/output").is_dir());
+        assert!(!mkdir("build/output", root, false).unwrap());
+        assert!(mkdir("dry/run", root, true).unwrap());
+        assert!(!root.join("dry").exists());
+    }
+
+    fn download_spec(url: &str, to: &str, sha256: Option<&str>) -> DownloadSpec {
+        DownloadSpec {
+            url: url.to_string(),
+            to: to.to_string(),
+            sha256: sha256.map(|s| s.to_string()),
+            token_env: None,
+        }
+    }
+
+    /// Serve a single HTTP response on a local port, returning its URL
+    #[cfg(feature = "network")]
+    fn serve_once(status: &str, body: &'static [u8]) -> String {
+        use std::io::{Read, Write};
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let status = status.to_string();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0u8; 4096];
+            let _ = stream.read(&mut buf);
+            let header = format!(
+                "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                status,
+                body.len()
+            );
+            stream.write_all(header.as_bytes()).unwrap();
+            stream.write_all(body).unwrap();
+        });
+        format!("http://{}/file", addr)
+    }
+
+    const ABC_SHA256: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+
+    #[test]
+    fn test_download_skips_when_checksum_matches() {
+        let temp = tempfile::tempdir().unwrap();
+        write(&temp.path().join("assets/abc.txt"), "abc");
+
+        // The URL is never contacted because the existing file already matches
+        let spec = download_spec("http://127.0.0.1:9/abc", "assets/abc.txt", Some(ABC_SHA256));
+        let outcome = download(&spec, temp.path(), false).unwrap();
+        assert_eq!(outcome, DownloadOutcome::UpToDate);
+    }
+
+    #[test]
+    fn test_download_dry_run() {
+        let temp = tempfile::tempdir().unwrap();
+        let spec = download_spec("http://127.0.0.1:9/abc", "abc.txt", None);
+        let outcome = download(&spec, temp.path(), true).unwrap();
+        assert_eq!(outcome, DownloadOutcome::WouldDownload);
+        assert!(!temp.path().join("abc.txt").exists());
+    }
+
+    #[test]
+    #[cfg(feature = "network")]
+    fn test_download_verifies_checksum() {
+        let temp = tempfile::tempdir().unwrap();
+        let url = serve_once("200 OK", b"abc");
+        let spec = download_spec(&url, "assets/abc.txt", Some(ABC_SHA256));
+
+        let outcome = download(&spec, temp.path(), false).unwrap();
+
+        assert_eq!(outcome, DownloadOutcome::Downloaded(3));
+        assert_eq!(
+            fs::read_to_string(temp.path().join("assets/abc.txt")).unwrap(),
+            "abc"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "network")]
+    fn test_download_checksum_mismatch() {
+        let temp = tempfile::tempdir().unwrap();
+        let url = serve_once("200 OK", b"not abc");
+        let spec = download_spec(&url, "abc.txt", Some(ABC_SHA256));
+
+        let err = download(&spec, temp.path(), false).unwrap_err();
+
+        assert!(err.to_string().contains("Checksum mismatch"));
+        assert!(!temp.path().join("abc.txt").exists());
+        assert!(!temp.path().join("abc.txt.download").exists());
+    }
+
+    #[test]
+    #[cfg(feature = "network")]
+    fn test_download_http_error_names_url_and_destination() {
+        let temp = tempfile::tempdir().unwrap();
+        let url = serve_once("404 Not Found", b"");
+        let spec = download_spec(&url, "abc.txt", None);
+
+        let err = download(&spec, temp.path(), false).unwrap_err().to_string();
+
+        assert!(err.contains(&url));
+        assert!(err.contains("HTTP 404"));
+        assert!(err.contains("abc.txt"));
+    }
+
+    fn render_spec(template: Option<&str>, content: Option<&str>, to: &str) -> RenderSpec {
+        RenderSpec {
+            template: template.map(|s| s.to_string()),
+            content: content.map(|s| s.to_string()),
+            to: to.to_string(),
+            mode: None,
+        }
+    }
+
+    fn scope(root: &Path) -> Scope {
+        let mut env = std::collections::HashMap::new();
+        env.insert("APP_PORT".to_string(), "8080".to_string());
+        Scope::new(root, &env)
+    }
+
+    #[test]
+    fn test_render_template_file() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        write(&root.join("conf/app.toml.tmpl"), "port = ${env.APP_PORT}\n");
+
+        let spec = render_spec(Some("conf/app.toml.tmpl"), None, ".local/app.toml");
+        render(&spec, root, &scope(root), false).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(root.join(".local/app.toml")).unwrap(),
+            "port = 8080\n"
+        );
+    }
+
+    #[test]
+    fn test_render_inline_content_dry_run() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+
+        let spec = render_spec(None, Some("port=${env.APP_PORT}"), "out.txt");
+        let rendered = render(&spec, root, &scope(root), true).unwrap();
+
+        assert_eq!(rendered, "port=8080");
+        assert!(!root.join("out.txt").exists());
+    }
+
+    #[test]
+    fn test_render_lists_unresolved_placeholders() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+
+        let spec = render_spec(
+            None,
+            Some("${env.RNR_TEST_MISSING_A} ${env.RNR_TEST_MISSING_B}"),
+            "out.txt",
+        );
+        let err = render(&spec, root, &scope(root), false).unwrap_err();
+        let message = format!("{:#}", err);
+
+        assert!(message.contains("${env.RNR_TEST_MISSING_A}"));
+        assert!(message.contains("${env.RNR_TEST_MISSING_B}"));
+        assert!(!root.join("out.txt").exists());
+    }
+
+    #[test]
+    fn test_render_requires_exactly_one_source() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let both = render_spec(Some("a"), Some("b"), "out.txt");
+        assert!(render(&both, root, &scope(root), false).is_err());
+        let neither = render_spec(None, None, "out.txt");
+        assert!(render(&neither, root, &scope(root), false).is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_render_mode() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let mut spec = render_spec(None, Some("secret"), "secret.txt");
+        spec.mode = Some(FileMode(0o600));
+
+        render(&spec, root, &scope(root), false).unwrap();
+
+        let mode = fs::metadata(root.join("secret.txt"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[test]
+    fn test_read_env_file() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join(".env.local");
+        write(
+            &path,
+            "# generated\n\nAPI_URL=http://localhost:8080/v1?a=b\nexport NAME = \"my app\"\nEMPTY=\nQUOTE='it''\n",
+        );
+        let vars = read_env_file(&path).unwrap();
+        let pairs: Vec<(&str, &str)> = vars
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect();
+        assert_eq!(
+            pairs,
+            [
+                ("API_URL", "http://localhost:8080/v1?a=b"),
+                ("NAME", "my app"),
+                ("EMPTY", ""),
+                ("QUOTE", "it'"),
+            ]
+        );
+
+        write(&path, "A=1\nnot a variable\n");
+        let error = read_env_file(&path).unwrap_err().to_string();
+        assert!(error.ends_with(":2: expected KEY=value, found 'not a variable'"));
+        write(&path, "MY VAR=1\n");
+        let error = read_env_file(&path).unwrap_err().to_string();
+        assert!(error.ends_with(":1: 'MY VAR' is not a variable name"));
+        assert!(read_env_file(&temp.path().join("missing.env")).is_err());
+    }
+
+    #[test]
+    fn test_long_paths() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let deep: PathBuf = (0..12)
+            .map(|i| format!("segment-{:02}-abcdefghij", i))
+            .collect();
+        write(&root.join("src").join(&deep).join("file.txt"), "deep");
+        assert!(root.join("src").join(&deep).as_os_str().len() > 260);
+
+        assert_eq!(copy(&copy_spec("src/", "dst/"), root, false).unwrap(), 1);
+        assert!(root.join("dst").join(&deep).join("file.txt").exists());
+
+        let spec = RemoveSpec::Path("dst".to_string());
+        assert_eq!(remove(&spec, root, root, false).unwrap(), 1);
+        assert!(!root.join("dst").exists());
+    }
+}
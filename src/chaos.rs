@@ -0,0 +1,269 @@
+//! Injected failures for rehearsing how a run goes wrong (`--chaos`)
+//!
+//! Each spec names a step or task: `fail-step=3` fails step 3 instead of
+//! running it, `fail-task=build-web` fails that task before its first step,
+//! and `timeout-step=2` leaves step 2 hanging until rnr is stopped. A step is
+//! its number in the task running it (`2.1` for the first branch of a
+//! parallel block), optionally after the task, like `ci:2.1`.
+
+use anyhow::Result;
+use std::fmt;
+use std::time::Duration;
+
+use crate::ui::Ui;
+
+/// The spec forms `--chaos` accepts
+const FORMS: &str = "fail-step=STEP, fail-task=TASK or timeout-step=STEP";
+
+/// What an injected fault does, and where
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Fault {
+    /// Fail the step instead of running it
+    FailStep(StepRef),
+    /// Fail the task before it runs anything
+    FailTask(String),
+    /// Hang in the step instead of running it
+    HangStep(StepRef),
+}
+
+/// A step, in any task or only in one
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct StepRef {
+    /// The task the step is in, or none for any task's
+    task: Option<String>,
+    /// Position of the step: `2`, or `2.1` for a branch
+    step: String,
+}
+
+impl StepRef {
+    /// Parse `2.1` or `ci:2.1`
+    fn parse(value: &str) -> Option<Self> {
+        let (task, step) = match value.rsplit_once(':') {
+            Some((task, step)) => (Some(task.to_string()), step),
+            None => (None, value),
+        };
+        let numbered = step
+            .split('.')
+            .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit()));
+        let named = task.as_deref().is_none_or(|task| !task.is_empty());
+        (numbered && named).then(|| Self {
+            task,
+            step: step.to_string(),
+        })
+    }
+
+    /// Whether this is step `step` of `task`
+    fn is(&self, task: &str, step: &str) -> bool {
+        self.step == step && self.task.as_deref().is_none_or(|name| name == task)
+    }
+}
+
+impl fmt::Display for StepRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.task {
+            Some(task) => write!(f, "{}:{}", task, self.step),
+            None => write!(f, "{}", self.step),
+        }
+    }
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Fault::FailStep(step) => write!(f, "fail-step={}", step),
+            Fault::FailTask(task) => write!(f, "fail-task={}", task),
+            Fault::HangStep(step) => write!(f, "timeout-step={}", step),
+        }
+    }
+}
+
+/// The error an injected failure ends a step or task with
+#[derive(Debug)]
+pub struct Injected(Fault);
+
+impl fmt::Display for Injected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Injected failure from --chaos {} (not a real failure)",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for Injected {}
+
+/// The faults to inject into a run; none unless `--chaos` was given
+#[derive(Debug, Clone, Default)]
+pub struct Chaos {
+    faults: Vec<Fault>,
+}
+
+impl Chaos {
+    /// Faults from `--chaos` specs
+    pub fn parse(specs: &[String]) -> Result<Self> {
+        let faults = specs
+            .iter()
+            .map(|spec| parse_fault(spec))
+            .collect::<Result<_>>()?;
+        Ok(Self { faults })
+    }
+
+    /// Whether any faults are injected
+    pub fn is_active(&self) -> bool {
+        !self.faults.is_empty()
+    }
+
+    /// Refuse to inject faults in CI (`CI=true`) unless `allow_ci`
+    pub fn check_ci(&self, allow_ci: bool) -> Result<()> {
+        let ci = std::env::var("CI").ok();
+        self.check_ci_for(ci.as_deref(), allow_ci)
+    }
+
+    /// [`Chaos::check_ci`] with `CI` set to `ci`
+    fn check_ci_for(&self, ci: Option<&str>, allow_ci: bool) -> Result<()> {
+        let in_ci = ci.is_some_and(|ci| ci.eq_ignore_ascii_case("true") || ci == "1");
+        if self.is_active() && in_ci && !allow_ci {
+            anyhow::bail!(
+                "--chaos injects failures, so it won't run with CI=true; \
+                 add --chaos-allow-ci to rehearse failures in CI"
+            );
+        }
+        Ok(())
+    }
+
+    /// Warn that the run has faults injected
+    pub fn announce(&self, ui: &Ui) {
+        if self.is_active() {
+            let specs: Vec<String> = self.faults.iter().map(Fault::to_string).collect();
+            ui.warn(format!(
+                "chaos mode: injecting {} (failures it causes aren't real)",
+                specs.join(", ")
+            ));
+        }
+    }
+
+    /// Fail, or hang, instead of running step `step` of `task` if a fault
+    /// names it
+    pub fn at_step(&self, task: &str, step: &str, ui: &Ui) -> Result<()> {
+        for fault in &self.faults {
+            match fault {
+                Fault::FailStep(target) if target.is(task, step) => {
+                    return Err(Injected(fault.clone()).into());
+                }
+                Fault::HangStep(target) if target.is(task, step) => {
+                    ui.warn(format!(
+                        "chaos: hanging in step {} of '{}' instead of running it \
+                         (--chaos {}); stop rnr to end it",
+                        step, task, fault
+                    ));
+                    loop {
+                        std::thread::sleep(Duration::from_secs(3600));
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Fail instead of running `task` if a fault names it
+    pub fn at_task(&self, task: &str) -> Result<()> {
+        match self
+            .faults
+            .iter()
+            .find(|fault| matches!(fault, Fault::FailTask(name) if name == task))
+        {
+            Some(fault) => Err(Injected(fault.clone()).into()),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Parse one `--chaos` spec
+fn parse_fault(spec: &str) -> Result<Fault> {
+    let invalid = || anyhow::anyhow!("Invalid --chaos '{}': expected {}", spec, FORMS);
+    let (kind, target) = spec.split_once('=').ok_or_else(invalid)?;
+    let target = target.trim();
+    match kind.trim() {
+        "fail-step" => StepRef::parse(target).map(Fault::FailStep),
+        "timeout-step" => StepRef::parse(target).map(Fault::HangStep),
+        "fail-task" if !target.is_empty() => Some(Fault::FailTask(target.to_string())),
+        _ => None,
+    }
+    .ok_or_else(invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chaos(specs: &[&str]) -> Chaos {
+        let specs: Vec<String> = specs.iter().map(|spec| spec.to_string()).collect();
+        Chaos::parse(&specs).unwrap()
+    }
+
+    #[test]
+    fn test_parse_specs() {
+        let faults = chaos(&["fail-step=3", "fail-task=build-web", "timeout-step=ci:2.1"]).faults;
+        assert_eq!(
+            faults,
+            [
+                Fault::FailStep(StepRef {
+                    task: None,
+                    step: "3".to_string()
+                }),
+                Fault::FailTask("build-web".to_string()),
+                Fault::HangStep(StepRef {
+                    task: Some("ci".to_string()),
+                    step: "2.1".to_string()
+                }),
+            ]
+        );
+        assert_eq!(faults[2].to_string(), "timeout-step=ci:2.1");
+
+        for spec in [
+            "fail-step",
+            "fail-step=x",
+            "fail-step=2.",
+            "fail-task=",
+            "explode=1",
+        ] {
+            let error = Chaos::parse(&[spec.to_string()]).unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                format!("Invalid --chaos '{}': expected {}", spec, FORMS)
+            );
+        }
+    }
+
+    #[test]
+    fn test_faults_match_their_step_or_task() {
+        let chaos = chaos(&["fail-step=2", "fail-step=deploy:1.2", "fail-task=lint"]);
+        let ui = Ui::default();
+
+        let error = chaos.at_step("ci", "2", &ui).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Injected failure from --chaos fail-step=2 (not a real failure)"
+        );
+        assert!(error.downcast_ref::<Injected>().is_some());
+        chaos.at_step("ci", "2.1", &ui).unwrap();
+        chaos.at_step("ci", "1.2", &ui).unwrap();
+        chaos.at_step("deploy", "1.2", &ui).unwrap_err();
+
+        chaos.at_task("lint").unwrap_err();
+        chaos.at_task("lint-docs").unwrap();
+    }
+
+    #[test]
+    fn test_refused_in_ci() {
+        let active = chaos(&["fail-step=1"]);
+        let error = active.check_ci_for(Some("true"), false).unwrap_err();
+        assert!(error.to_string().contains("--chaos-allow-ci"), "{}", error);
+        active.check_ci_for(Some("true"), true).unwrap();
+        active.check_ci_for(Some("false"), false).unwrap();
+        active.check_ci_for(None, false).unwrap();
+        Chaos::default().check_ci_for(Some("true"), false).unwrap();
+    }
+}
@@ -0,0 +1,277 @@
+//! Temporary clean checkouts for tasks with `checkout:`, so release tasks
+//! run against a commit rather than the working tree
+
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::rnr_config;
+
+/// Where clean checkouts are created, relative to the project root
+pub const TMP_DIR: &str = ".rnr/tmp";
+
+/// A clean copy of the repository, removed when dropped
+#[derive(Debug)]
+pub struct CleanCheckout {
+    /// Top of the checked-out tree
+    dir: PathBuf,
+    /// The project root inside the checkout
+    root: PathBuf,
+    /// Repository the worktree was added to, if it's a worktree rather than
+    /// an extracted archive
+    worktree_of: Option<PathBuf>,
+}
+
+impl CleanCheckout {
+    /// Check out `commit` at `dir`, as a git worktree or, when worktrees
+    /// aren't available, an extracted `git archive`
+    pub fn create(project_root: &Path, commit: &str, dir: PathBuf) -> Result<Self> {
+        let toplevel = PathBuf::from(git(project_root, &["rev-parse", "--show-toplevel"])?);
+        let prefix = git(project_root, &["rev-parse", "--show-prefix"])?;
+        if let Some(parent) = dir.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+            if let Some(rnr_dir) = parent.parent() {
+                rnr_config::ensure_ignored(rnr_dir).with_context(|| {
+                    format!(
+                        "Failed to write {}",
+                        rnr_dir.join(rnr_config::IGNORE_FILE).display()
+                    )
+                })?;
+            }
+        }
+
+        let added = Command::new("git")
+            .arg("-C")
+            .arg(&toplevel)
+            .args(["worktree", "add", "--detach", "--quiet"])
+            .arg(&dir)
+            .arg(commit)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success());
+        let worktree_of = if added {
+            Some(toplevel)
+        } else {
+            let _ = fs::remove_dir_all(&dir);
+            extract_archive(&toplevel, commit, &dir)?;
+            None
+        };
+
+        Ok(Self {
+            root: dir.join(prefix),
+            dir,
+            worktree_of,
+        })
+    }
+
+    /// The project root inside the checkout
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+}
+
+impl Drop for CleanCheckout {
+    fn drop(&mut self) {
+        if let Some(repo) = &self.worktree_of {
+            let _ = Command::new("git")
+                .arg("-C")
+                .arg(repo)
+                .args(["worktree", "remove", "--force"])
+                .arg(&self.dir)
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status();
+        }
+        let _ = fs::remove_dir_all(&self.dir);
+        if let Some(repo) = &self.worktree_of {
+            let _ = Command::new("git")
+                .arg("-C")
+                .arg(repo)
+                .args(["worktree", "prune"])
+                .stderr(Stdio::null())
+                .status();
+        }
+    }
+}
+
+/// Where `task`'s checkout goes for this process: `.rnr/tmp/<task>-<pid>`
+pub fn checkout_dir(project_root: &Path, task: &str) -> PathBuf {
+    let name: String = task
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    project_root
+        .join(TMP_DIR)
+        .join(format!("{}-{}", name, std::process::id()))
+}
+
+/// The commit `reference` names (the current commit if `None`)
+pub fn resolve_commit(project_root: &Path, reference: Option<&str>) -> Result<String> {
+    git(project_root, &["rev-parse", "--show-toplevel"])
+        .context("'checkout' needs the project to be in a git repository")?;
+    let reference = reference.unwrap_or("HEAD");
+    git(
+        project_root,
+        &[
+            "rev-parse",
+            "--verify",
+            "--quiet",
+            &format!("{}^{{commit}}", reference),
+        ],
+    )
+    .with_context(|| format!("Unknown git ref '{}' for checkout", reference))
+}
+
+/// Tracked files with uncommitted changes
+pub fn dirty_files(project_root: &Path) -> Result<Vec<String>> {
+    let status = git_output(
+        project_root,
+        &["status", "--porcelain", "--untracked-files=no"],
+    )?;
+    Ok(status
+        .lines()
+        .filter_map(|line| line.get(3..))
+        .map(str::to_string)
+        .collect())
+}
+
+/// Extract `commit` into `dir` with `git archive | tar -x`
+fn extract_archive(toplevel: &Path, commit: &str, dir: &Path) -> Result<()> {
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let mut archive = Command::new("git")
+        .arg("-C")
+        .arg(toplevel)
+        .args(["archive", "--format=tar", commit])
+        .stdout(Stdio::piped())
+        .spawn()
+        .context("Failed to run git archive")?;
+    let output = archive.stdout.take().expect("stdout is piped");
+    let extracted = Command::new("tar")
+        .arg("-x")
+        .arg("-C")
+        .arg(dir)
+        .stdin(output)
+        .status()
+        .context("Failed to run tar")?;
+    let archived = archive.wait().context("Failed to run git archive")?;
+    if !archived.success() || !extracted.success() {
+        let _ = fs::remove_dir_all(dir);
+        bail!("Failed to check out {} into {}", commit, dir.display());
+    }
+    Ok(())
+}
+
+/// Run git in `dir`, returning its trimmed output
+pub fn git(dir: &Path, args: &[&str]) -> Result<String> {
+    Ok(git_output(dir, args)?.trim().to_string())
+}
+
+/// Run git in `dir`, returning its output
+fn git_output(dir: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .context("Failed to run git")?;
+    if !output.status.success() {
+        bail!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn repo() -> tempfile::TempDir {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        for args in [
+            &["init", "--quiet"][..],
+            &["config", "user.email", "dev@example.com"],
+            &["config", "user.name", "dev"],
+        ] {
+            git(root, args).unwrap();
+        }
+        fs::write(root.join("version.txt"), "1\n").unwrap();
+        git(root, &["add", "."]).unwrap();
+        git(root, &["commit", "--quiet", "-m", "first"]).unwrap();
+        temp
+    }
+
+    #[test]
+    fn test_checkout_dir() {
+        let dir = checkout_dir(Path::new("/repo"), "sub:release/all");
+        assert_eq!(
+            dir,
+            Path::new("/repo/.rnr/tmp").join(format!("sub_release_all-{}", std::process::id()))
+        );
+    }
+
+    #[test]
+    fn test_dirty_files_and_resolve_commit() {
+        let temp = repo();
+        let root = temp.path();
+        assert!(dirty_files(root).unwrap().is_empty());
+        fs::write(root.join("untracked.txt"), "").unwrap();
+        assert!(dirty_files(root).unwrap().is_empty());
+        fs::write(root.join("version.txt"), "2\n").unwrap();
+        assert_eq!(dirty_files(root).unwrap(), vec!["version.txt"]);
+
+        let head = resolve_commit(root, None).unwrap();
+        assert_eq!(head.len(), 40);
+        let error = resolve_commit(root, Some("nope")).unwrap_err();
+        assert_eq!(error.to_string(), "Unknown git ref 'nope' for checkout");
+
+        let outside = tempfile::tempdir().unwrap();
+        let error = resolve_commit(outside.path(), None).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "'checkout' needs the project to be in a git repository"
+        );
+    }
+
+    #[test]
+    fn test_clean_checkout_is_removed_on_drop() {
+        let temp = repo();
+        let root = temp.path();
+        fs::write(root.join("version.txt"), "dirty\n").unwrap();
+
+        let commit = resolve_commit(root, None).unwrap();
+        let dir = checkout_dir(root, "release");
+        let checkout = CleanCheckout::create(root, &commit, dir.clone()).unwrap();
+        assert_eq!(
+            fs::read_to_string(checkout.root().join("version.txt")).unwrap(),
+            "1\n"
+        );
+        drop(checkout);
+        assert!(!dir.exists());
+        assert!(!git(root, &["worktree", "list"])
+            .unwrap()
+            .contains("release"));
+    }
+
+    #[test]
+    fn test_archive_fallback() {
+        let temp = repo();
+        let root = temp.path();
+        let commit = resolve_commit(root, None).unwrap();
+        let dir = root.join("extracted");
+        extract_archive(root, &commit, &dir).unwrap();
+        assert_eq!(fs::read_to_string(dir.join("version.txt")).unwrap(), "1\n");
+    }
+}
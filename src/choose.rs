@@ -0,0 +1,218 @@
+//! Menu tasks: `choose` offers tasks by name and runs the one picked
+//!
+//! The choice is the task's first argument or `--choice <option>`, so
+//! scripts and CI never wait on a prompt. Run without one in a terminal,
+//! the task shows its menu; anywhere else, it fails naming the options.
+
+use anyhow::{bail, Context, Result};
+use dialoguer::Select;
+use std::io::IsTerminal;
+
+use crate::config::{Choose, ChooseOption};
+use crate::suggest;
+
+/// Question asked when `choose` has no `prompt`
+const DEFAULT_PROMPT: &str = "Which task should run?";
+
+/// The option named by a `choose` task's arguments, from `--choice` or the
+/// first of them, and the arguments left over for the task it runs
+pub fn given(args: &[String]) -> Result<(Option<String>, Vec<String>)> {
+    let mut choice = None;
+    let mut rest = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let value = match arg.strip_prefix("--choice") {
+            Some("") => Some(
+                args.next()
+                    .context("'--choice' needs the name of an option")?
+                    .clone(),
+            ),
+            Some(inline) => inline.strip_prefix('=').map(str::to_string),
+            None => None,
+        };
+        match value {
+            Some(_) if choice.is_some() => bail!("'--choice' is given more than once"),
+            Some(value) => choice = Some(value),
+            None => rest.push(arg.clone()),
+        }
+    }
+    if choice.is_none() && !rest.is_empty() {
+        choice = Some(rest.remove(0));
+    }
+    Ok((choice, rest))
+}
+
+/// The option of `task`'s menu called `choice`
+pub fn named<'c>(task: &str, choose: &'c Choose, choice: &str) -> Result<&'c ChooseOption> {
+    if let Some(option) = choose.option(choice) {
+        return Ok(option);
+    }
+    let names = choose.names();
+    let hint = match suggest::nearest(choice, &names) {
+        Some(near) => format!(" (did you mean '{}'?)", near),
+        None => String::new(),
+    };
+    bail!(
+        "'{}' isn't an option of '{}'{}; choose one of: {}",
+        choice,
+        task,
+        hint,
+        names.join(", ")
+    )
+}
+
+/// The option of `task`'s menu picked in the terminal. Fails rather than
+/// waiting when there's no terminal to ask in.
+pub fn ask<'c>(task: &str, choose: &'c Choose) -> Result<&'c ChooseOption> {
+    if !(std::io::stdin().is_terminal() && std::io::stderr().is_terminal()) {
+        bail!("{}", no_terminal(task, choose));
+    }
+    let labels: Vec<String> = choose
+        .options
+        .iter()
+        .map(|option| format!("{} ({})", option.name, option.task))
+        .collect();
+    let picked = Select::new()
+        .with_prompt(prompt(choose))
+        .items(&labels)
+        .default(0)
+        .interact_opt()?;
+    match picked {
+        Some(index) => Ok(&choose.options[index]),
+        None => bail!("Nothing was chosen for '{}'", task),
+    }
+}
+
+/// Why `task` can't ask which option to run
+fn no_terminal(task: &str, choose: &Choose) -> String {
+    let first = &choose.options[0].name;
+    format!(
+        "'{}' asks which task to run, but there's no terminal to ask in; \
+         pass one of: {} (e.g. 'rnr {} {}' or '--choice {}')",
+        task,
+        choose.names().join(", "),
+        task,
+        first,
+        first
+    )
+}
+
+/// The question a menu asks
+fn prompt(choose: &Choose) -> &str {
+    choose.prompt.as_deref().unwrap_or(DEFAULT_PROMPT)
+}
+
+/// The menu as lines: its prompt, then each option and the task it runs
+pub fn menu(choose: &Choose) -> Vec<String> {
+    let width = choose
+        .options
+        .iter()
+        .map(|option| option.name.len())
+        .max()
+        .unwrap_or(0);
+    let mut lines = vec![prompt(choose).to_string()];
+    lines.extend(
+        choose
+            .options
+            .iter()
+            .map(|option| format!("  {:<width$}  -> {}", option.name, option.task)),
+    );
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn choose(yaml: &str) -> Choose {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    fn setup() -> Choose {
+        choose(
+            "prompt: What do you want to set up?\noptions:\n  frontend: setup-frontend\n  backend: setup-backend\n  everything: setup-all\n",
+        )
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_options_keep_their_order() {
+        assert_eq!(setup().names(), ["frontend", "backend", "everything"]);
+        assert_eq!(setup().option("everything").unwrap().task, "setup-all");
+
+        let err = serde_yaml::from_str::<Choose>("options: {}").unwrap_err();
+        assert!(err.to_string().contains("needs at least one option"));
+        let err = serde_yaml::from_str::<Choose>("options:\n  a: x\n  a: y\n").unwrap_err();
+        assert!(err.to_string().contains("duplicate"), "{}", err);
+    }
+
+    #[test]
+    fn test_given() {
+        let given = |list: &[&str]| given(&args(list)).unwrap();
+        assert_eq!(given(&[]), (None, vec![]));
+        assert_eq!(
+            given(&["frontend", "--fast"]),
+            (Some("frontend".into()), args(&["--fast"]))
+        );
+        assert_eq!(
+            given(&["--fast", "--choice", "backend"]),
+            (Some("backend".into()), args(&["--fast"]))
+        );
+        assert_eq!(
+            given(&["--choice=backend", "x"]),
+            (Some("backend".into()), args(&["x"]))
+        );
+        assert_eq!(given(&["--choices"]), (Some("--choices".into()), vec![]));
+
+        assert!(super::given(&args(&["--choice"])).is_err());
+        assert!(super::given(&args(&["--choice=a", "--choice", "b"])).is_err());
+    }
+
+    #[test]
+    fn test_named() {
+        let setup = setup();
+        assert_eq!(
+            named("setup", &setup, "backend").unwrap().task,
+            "setup-backend"
+        );
+        assert_eq!(
+            named("setup", &setup, "fronted").unwrap_err().to_string(),
+            "'fronted' isn't an option of 'setup' (did you mean 'frontend'?); \
+             choose one of: frontend, backend, everything"
+        );
+        assert_eq!(
+            named("setup", &setup, "docs").unwrap_err().to_string(),
+            "'docs' isn't an option of 'setup'; choose one of: frontend, backend, everything"
+        );
+    }
+
+    #[test]
+    fn test_no_terminal() {
+        assert_eq!(
+            no_terminal("setup", &setup()),
+            "'setup' asks which task to run, but there's no terminal to ask in; \
+             pass one of: frontend, backend, everything \
+             (e.g. 'rnr setup frontend' or '--choice frontend')"
+        );
+    }
+
+    #[test]
+    fn test_menu() {
+        assert_eq!(
+            menu(&setup()),
+            [
+                "What do you want to set up?",
+                "  frontend    -> setup-frontend",
+                "  backend     -> setup-backend",
+                "  everything  -> setup-all",
+            ]
+        );
+        assert_eq!(
+            menu(&choose("options:\n  a: b\n")),
+            ["Which task should run?", "  a  -> b"]
+        );
+    }
+}
@@ -1,18 +1,160 @@
-use clap::{Args, Parser, Subcommand};
+use anyhow::Result;
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
+
+use crate::rnr_config::Channel;
+use crate::settings::ColorChoice;
+use crate::ui::OutputMode;
 
 /// A cross-platform task runner with zero setup
 #[derive(Parser, Debug)]
 #[command(name = "rnr")]
 #[command(author, version, about, long_about = None)]
+#[command(disable_help_subcommand = true, disable_version_flag = true)]
 pub struct Cli {
     /// Task to run
     #[arg(value_name = "TASK")]
     pub task: Option<String>,
 
+    /// Arguments passed to the task
+    #[arg(
+        value_name = "ARGS",
+        trailing_var_arg = true,
+        allow_hyphen_values = true
+    )]
+    pub args: Vec<String>,
+
     /// List all available tasks
     #[arg(short, long)]
     pub list: bool,
 
+    /// With --list, also list the tasks of rnr.yaml files in subdirectories
+    #[arg(long, requires = "list")]
+    pub recursive: bool,
+
+    /// With --list, show descriptions in full instead of fitting the terminal
+    #[arg(long, requires = "list")]
+    pub wide: bool,
+
+    /// With --list, show the file and line where each task is defined
+    #[arg(long, requires = "list")]
+    pub sources: bool,
+
+    /// Print what would be done without executing anything
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// With --dry-run, show what changed since the last recorded plan
+    #[arg(long, requires = "dry_run")]
+    pub diff: bool,
+
+    /// Run a task with `checkout:` or `require_clean_git` even if the working tree has uncommitted changes
+    #[arg(long)]
+    pub allow_dirty: bool,
+
+    /// Run despite `not_between` windows, past the `budget`, and off `require_branch` or `require_upstream_synced`, for emergencies
+    #[arg(long)]
+    pub ignore_guards: bool,
+
+    /// Don't show how long each step took last time, or the time left
+    #[arg(long)]
+    pub no_eta: bool,
+
+    /// Don't run the tasks whose `outputs` the task's `sources` read first
+    #[arg(long)]
+    pub no_auto_deps: bool,
+
+    /// Inject a failure to rehearse how the run goes wrong: `fail-step=STEP`,
+    /// `fail-task=TASK` or `timeout-step=STEP` (repeatable)
+    #[arg(long, hide = true, value_name = "SPEC")]
+    pub chaos: Vec<String>,
+
+    /// Allow --chaos when CI=true
+    #[arg(long, hide = true, requires = "chaos")]
+    pub chaos_allow_ci: bool,
+
+    /// Run only the task's steps tagged TAG (comma-separated or repeated)
+    #[arg(long, value_name = "TAG", value_delimiter = ',')]
+    pub only: Vec<String>,
+
+    /// Leave out the task's steps tagged TAG (comma-separated or repeated)
+    #[arg(long, value_name = "TAG", value_delimiter = ',')]
+    pub skip: Vec<String>,
+
+    /// Run only the task's steps numbered in RANGES, e.g. `2-4`, `1,3` or `5-`
+    #[arg(long, value_name = "RANGES")]
+    pub steps: Option<String>,
+
+    /// Run the task in DIR, relative to the project root, instead of its own dir
+    #[arg(long, value_name = "DIR")]
+    pub dir: Option<PathBuf>,
+
+    /// Write a Chrome trace of the run (one span per task and step) to FILE
+    #[arg(long, value_name = "FILE")]
+    pub trace: Option<PathBuf>,
+
+    /// Use the project in DIR instead of looking next to the wrapper and up
+    /// from the current directory
+    #[arg(long, value_name = "DIR", global = true)]
+    pub project_dir: Option<PathBuf>,
+
+    /// Give up on network requests after SECS seconds (default: 120 for downloads)
+    #[arg(long, value_name = "SECS", global = true)]
+    pub timeout: Option<u64>,
+
+    /// Run at most N branches of a parallel block at once
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    pub jobs: Option<u32>,
+
+    /// On SIGTERM, give running commands SECS seconds to exit before killing
+    /// them (default 10)
+    #[arg(long, value_name = "SECS")]
+    pub term_grace: Option<u64>,
+
+    /// Emit newline-delimited JSON progress events on stderr
+    #[arg(long)]
+    pub events_json: bool,
+
+    /// Emit the --events-json events on file descriptor FD instead (Unix)
+    #[arg(long, value_name = "FD", conflicts_with = "events_json")]
+    pub events_fd: Option<u32>,
+
+    /// Run the task whose name uniquely starts with TASK
+    #[arg(long)]
+    pub fuzzy: bool,
+
+    /// Show the environment variables rnr sets for each task before running it
+    /// (with --version, show build details)
+    #[arg(short, long)]
+    pub verbose: bool,
+
+    /// Hide progress and command echoes; command output and problems still show,
+    /// except the output of tasks with a `log`, which goes only to the log
+    #[arg(short, long, global = true)]
+    pub quiet: bool,
+
+    /// How to lay out output: `plain` drops colors and passes commands'
+    /// output on a whole line at a time, for log collectors (default auto:
+    /// plain when CI=true and stdout isn't a terminal)
+    #[arg(long, value_enum, value_name = "MODE", global = true)]
+    pub output: Option<OutputMode>,
+
+    /// When to color output (default auto: when the terminal supports it)
+    #[arg(long, value_enum, value_name = "WHEN", global = true)]
+    pub color: Option<ColorChoice>,
+
+    /// Start every line of output with an RFC 3339 timestamp
+    #[arg(long, global = true)]
+    pub timestamps: bool,
+
+    /// Print version
+    #[arg(short = 'V', long)]
+    pub version: bool,
+
+    /// Reject task definitions with unknown fields (same as `strict: true` in rnr.yaml)
+    #[arg(long, global = true)]
+    pub strict: bool,
+
     #[command(subcommand)]
     pub command: Option<Command>,
 }
@@ -23,7 +165,337 @@ pub enum Command {
     Init(InitArgs),
 
     /// Upgrade rnr binaries to the latest version
-    Upgrade,
+    Upgrade(UpgradeArgs),
+
+    /// Check the binaries in .rnr/bin against their recorded checksums
+    Verify,
+
+    /// Show the version of rnr and of each configured binary
+    Version(VersionArgs),
+
+    /// Show rnr's settings in effect and where each one comes from
+    Settings,
+
+    /// Show help for rnr or for a task
+    Help(HelpArgs),
+
+    /// Show the results of the last run
+    Last,
+
+    /// Re-run the tasks and steps that failed in the last run
+    RerunFailed,
+
+    /// Check rnr.yaml (and nested task files) for mistakes
+    Validate,
+
+    /// Rewrite rnr.yaml in the canonical layout
+    Fmt(FmtArgs),
+
+    /// Add a task to rnr.yaml
+    Add(AddArgs),
+
+    /// Add tasks to rnr.yaml from a package.json's scripts or a Makefile's targets
+    Import(ImportArgs),
+
+    /// Write a Makefile or package.json scripts that run rnr's tasks, for tools that call make or npm
+    Export(ExportArgs),
+
+    /// Run a task, even one whose name matches a subcommand
+    Run(RunArgs),
+
+    /// Show the environment variables rnr would set for a task, without running it
+    Env(EnvArgs),
+
+    /// Show why each step of a task would or wouldn't run, without running it
+    Explain(ExplainArgs),
+
+    /// Run the tasks whose `paths` match files changed since a git ref
+    Affected(AffectedArgs),
+
+    /// Write rnr's man pages or a markdown reference of its commands and task file
+    Docs(DocsArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct RunArgs {
+    /// Task to run
+    #[arg(value_name = "TASK")]
+    pub task: String,
+
+    /// Arguments passed to the task
+    #[arg(
+        value_name = "ARGS",
+        trailing_var_arg = true,
+        allow_hyphen_values = true
+    )]
+    pub args: Vec<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct VersionArgs {
+    /// Print build details and the project's version and platforms as JSON
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct EnvArgs {
+    /// Task to inspect
+    #[arg(value_name = "TASK")]
+    pub task: String,
+
+    /// Arguments the task would be run with
+    #[arg(
+        value_name = "ARGS",
+        trailing_var_arg = true,
+        allow_hyphen_values = true
+    )]
+    pub args: Vec<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct ExplainArgs {
+    /// Task to explain
+    #[arg(value_name = "TASK")]
+    pub task: String,
+
+    /// Arguments the task would be run with
+    #[arg(
+        value_name = "ARGS",
+        trailing_var_arg = true,
+        allow_hyphen_values = true
+    )]
+    pub args: Vec<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct AddArgs {
+    /// Name of the new task
+    #[arg(value_name = "NAME")]
+    pub name: String,
+
+    /// Command the task runs
+    #[arg(long)]
+    pub cmd: String,
+
+    /// Description shown by --list
+    #[arg(long)]
+    pub description: Option<String>,
+
+    /// Directory to run the command in, relative to the project root
+    #[arg(long)]
+    pub dir: Option<String>,
+
+    /// Put the task after this one instead of at the end of the file
+    #[arg(long, value_name = "TASK")]
+    pub after: Option<String>,
+
+    /// Replace a task of the same name, where it is
+    #[arg(long)]
+    pub force: bool,
+
+    /// Write the one-line `name: cmd` form (only with just --cmd)
+    #[arg(long)]
+    pub shorthand: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct ImportArgs {
+    /// package.json or Makefile (`*.mk` too) to import from
+    #[arg(value_name = "FILE")]
+    pub file: PathBuf,
+
+    /// Copy the commands themselves instead of running them with `npm run` or `make`
+    #[arg(long)]
+    pub inline: bool,
+
+    /// Replace tasks of the same name, where they are
+    #[arg(long)]
+    pub force: bool,
+
+    /// Show what would change in rnr.yaml without writing it
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct ExportArgs {
+    /// What to write: a Makefile, or scripts in package.json
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    pub format: ExportFormat,
+
+    /// Don't write anything; fail if the file isn't in sync with rnr.yaml (for CI)
+    #[arg(long)]
+    pub check: bool,
+
+    /// Overwrite a Makefile that rnr export didn't write
+    #[arg(long)]
+    pub force: bool,
+}
+
+/// The shims `rnr export` writes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    /// A Makefile with a target per task
+    Makefile,
+    /// A script per task in package.json
+    Npm,
+}
+
+#[derive(Args, Debug)]
+pub struct FmtArgs {
+    /// Don't write anything; fail if rnr.yaml isn't formatted (for CI)
+    #[arg(long)]
+    pub check: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct AffectedArgs {
+    /// Compare against where this ref and HEAD diverged (default: uncommitted changes)
+    #[arg(long, value_name = "REF", default_value = "HEAD")]
+    pub since: String,
+
+    /// Show the affected tasks and what they would do without running them
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct DocsArgs {
+    /// What to write: `man` for rnr.1 and a page per command, `markdown` for rnr.md
+    #[arg(value_enum, value_name = "FORMAT")]
+    pub format: DocsFormat,
+
+    /// Directory to write it to (created if missing)
+    #[arg(long, value_name = "DIR")]
+    pub out: PathBuf,
+}
+
+/// The documents `rnr docs` writes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DocsFormat {
+    /// Roff man pages: rnr.1, and rnr-<command>.1 for each command
+    Man,
+    /// A markdown reference, rnr.md
+    Markdown,
+}
+
+/// Names of rnr's subcommands, which take precedence over tasks of the same name
+pub fn subcommand_names() -> Vec<String> {
+    Cli::command()
+        .get_subcommands()
+        .flat_map(|command| std::iter::once(command.get_name()).chain(command.get_all_aliases()))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Tasks in `names` that `rnr <name>` can't reach because a subcommand wins
+pub fn shadowed_tasks<'a>(names: &[&'a str]) -> Vec<&'a str> {
+    let subcommands = subcommand_names();
+    names
+        .iter()
+        .copied()
+        .filter(|name| subcommands.iter().any(|command| command == name))
+        .collect()
+}
+
+/// Task a bare `rnr` runs, for CI steps that are always just `./rnr`
+pub const TASK_ENV: &str = "RNR_TASK";
+
+/// Arguments for the `RNR_TASK` task, split the way a shell would
+pub const ARGS_ENV: &str = "RNR_ARGS";
+
+/// The task and arguments that `RNR_TASK` and `RNR_ARGS` ask for, if any
+pub fn env_task() -> Result<Option<(String, Vec<String>)>> {
+    task_from(
+        std::env::var(TASK_ENV).ok(),
+        std::env::var(ARGS_ENV).ok().as_deref(),
+    )
+}
+
+/// [`env_task`] for the given values; an empty `RNR_TASK` counts as unset
+fn task_from(task: Option<String>, args: Option<&str>) -> Result<Option<(String, Vec<String>)>> {
+    let Some(task) = task.filter(|task| !task.trim().is_empty()) else {
+        return Ok(None);
+    };
+    let args = match args {
+        Some(args) => split_words(args).map_err(|problem| {
+            anyhow::anyhow!("Couldn't parse {} ({}): {}", ARGS_ENV, args, problem)
+        })?,
+        None => Vec::new(),
+    };
+    Ok(Some((task, args)))
+}
+
+/// Split `text` into words like a POSIX shell: on whitespace, with single
+/// quotes taken literally, and backslashes escaping outside them
+fn split_words(text: &str) -> Result<Vec<String>, &'static str> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\\' => {
+                let escaped = chars.next().ok_or("it ends with a lone backslash")?;
+                word.get_or_insert_with(String::new).push(escaped);
+            }
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next().ok_or("a single quote is never closed")? {
+                        '\'' => break,
+                        c => word.push(c),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next().ok_or("a double quote is never closed")? {
+                        '"' => break,
+                        // Only these are escaped inside double quotes
+                        '\\' => match chars.next().ok_or("a double quote is never closed")? {
+                            c @ ('"' | '\\' | '$' | '`') => word.push(c),
+                            c => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                        },
+                        c => word.push(c),
+                    }
+                }
+            }
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+#[derive(Args, Debug)]
+pub struct UpgradeArgs {
+    /// Only report which binaries are out of date; don't download anything
+    #[arg(long)]
+    pub check: bool,
+
+    /// Switch the project to a release channel before upgrading
+    #[arg(long, value_enum)]
+    pub channel: Option<Channel>,
+
+    /// Don't ask for confirmation before downgrading
+    #[arg(long, short = 'y')]
+    pub yes: bool,
+
+    /// Emit newline-delimited JSON progress events on stderr
+    #[arg(long)]
+    pub progress_json: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct HelpArgs {
+    /// Task to show help for
+    pub task: Option<String>,
 }
 
 #[derive(Args, Debug)]
@@ -41,18 +513,334 @@ pub struct InitArgs {
     pub current_platform_only: bool,
 
     /// Add a platform to existing setup
-    #[arg(long, conflicts_with_all = ["platforms", "all_platforms", "current_platform_only", "remove_platform"])]
+    #[arg(long, conflicts_with_all = ["platforms", "all_platforms", "current_platform_only", "remove_platform", "force", "nested", "yes"])]
     pub add_platform: Option<String>,
 
     /// Remove a platform from existing setup
-    #[arg(long, conflicts_with_all = ["platforms", "all_platforms", "current_platform_only", "add_platform"])]
+    #[arg(long, conflicts_with_all = ["platforms", "all_platforms", "current_platform_only", "add_platform", "placeholders", "force", "nested", "yes"])]
     pub remove_platform: Option<String>,
 
-    /// Show currently configured platforms
-    #[arg(long)]
+    /// Show currently configured platforms and their on-disk sizes
+    #[arg(long, conflicts_with_all = ["platforms", "all_platforms", "current_platform_only", "add_platform", "remove_platform", "prune", "placeholders", "force", "nested", "yes"])]
     pub show_platforms: bool,
 
+    /// Remove files in .rnr/bin that config.yaml doesn't manage, such as
+    /// binaries for platforms that are no longer configured
+    #[arg(long, conflicts_with_all = ["platforms", "all_platforms", "current_platform_only", "add_platform", "remove_platform", "placeholders", "force", "nested"])]
+    pub prune: bool,
+
+    /// Don't ask for confirmation before deleting files (with --prune)
+    #[arg(long, short = 'y', requires = "prune", conflicts_with_all = ["platforms", "all_platforms", "current_platform_only", "placeholders", "force", "nested"])]
+    pub yes: bool,
+
+    /// Download binaries for a project set up to run the rnr on PATH
+    /// (mode: system), and switch its wrappers to them
+    #[arg(long, conflicts_with_all = ["add_platform", "remove_platform", "show_platforms", "prune", "placeholders", "nested", "starter"])]
+    pub download: bool,
+
+    /// Write labeled placeholder files instead of downloading binaries
+    /// (for projects set up before rnr has a release)
+    #[arg(long)]
+    pub placeholders: bool,
+
     /// Skip git repository root check
     #[arg(long)]
     pub force: bool,
+
+    /// Initialize a separate project inside an existing rnr project
+    #[arg(long)]
+    pub nested: bool,
+
+    /// Starter rnr.yaml to copy into the new project (overrides RNR_STARTER
+    /// and ~/.config/rnr/starter.yaml)
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["add_platform", "remove_platform", "show_platforms", "prune"])]
+    pub starter: Option<PathBuf>,
+
+    /// Emit newline-delimited JSON progress events on stderr
+    #[arg(long, conflicts_with_all = ["show_platforms", "prune"])]
+    pub progress_json: bool,
+
+    /// Check the platforms and show what init would create and download,
+    /// without writing anything
+    #[arg(long, conflicts_with_all = ["add_platform", "remove_platform", "show_platforms", "prune", "download", "progress_json"])]
+    pub dry_run: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_task_with_args() {
+        let cli = Cli::try_parse_from(["rnr", "deploy", "staging", "--force", "-x"]).unwrap();
+        assert_eq!(cli.task, Some("deploy".to_string()));
+        assert_eq!(cli.args, vec!["staging", "--force", "-x"]);
+        assert!(cli.command.is_none());
+    }
+
+    #[test]
+    fn test_rnr_flags_before_task_args() {
+        let cli = Cli::try_parse_from(["rnr", "build", "--dry-run", "release"]).unwrap();
+        assert!(cli.dry_run);
+        assert_eq!(cli.args, vec!["release"]);
+    }
+
+    #[test]
+    fn test_diff_requires_dry_run() {
+        assert!(Cli::try_parse_from(["rnr", "build", "--diff"]).is_err());
+        let cli = Cli::try_parse_from(["rnr", "build", "--dry-run", "--diff"]).unwrap();
+        assert!(cli.dry_run && cli.diff);
+    }
+
+    #[test]
+    fn test_help_subcommand() {
+        let cli = Cli::try_parse_from(["rnr", "help", "deploy"]).unwrap();
+        match cli.command {
+            Some(Command::Help(args)) => assert_eq!(args.task, Some("deploy".to_string())),
+            other => panic!("Expected help subcommand, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_history_subcommands() {
+        let cli = Cli::try_parse_from(["rnr", "--dry-run", "rerun-failed"]).unwrap();
+        assert!(cli.dry_run);
+        assert!(matches!(cli.command, Some(Command::RerunFailed)));
+        let cli = Cli::try_parse_from(["rnr", "last"]).unwrap();
+        assert!(matches!(cli.command, Some(Command::Last)));
+    }
+
+    #[test]
+    fn test_upgrade_channel() {
+        let cli = Cli::try_parse_from(["rnr", "upgrade", "--channel", "prerelease"]).unwrap();
+        match cli.command {
+            Some(Command::Upgrade(args)) => assert_eq!(args.channel, Some(Channel::Prerelease)),
+            other => panic!("Expected upgrade subcommand, got {:?}", other),
+        }
+        assert!(Cli::try_parse_from(["rnr", "upgrade", "--channel", "nightly"]).is_err());
+    }
+
+    #[test]
+    fn test_output_mode() {
+        let cli = Cli::try_parse_from(["rnr", "build"]).unwrap();
+        assert_eq!(cli.output, None);
+        assert!(!cli.timestamps);
+        let cli = Cli::try_parse_from(["rnr", "build", "--output=plain", "--timestamps"]).unwrap();
+        assert_eq!(cli.output, Some(OutputMode::Plain));
+        assert!(cli.timestamps);
+        let cli = Cli::try_parse_from(["rnr", "validate", "--output", "normal"]).unwrap();
+        assert_eq!(cli.output, Some(OutputMode::Normal));
+        assert!(Cli::try_parse_from(["rnr", "--output=fancy"]).is_err());
+    }
+
+    #[test]
+    fn test_strict_is_global() {
+        let cli = Cli::try_parse_from(["rnr", "validate", "--strict"]).unwrap();
+        assert!(cli.strict);
+        assert!(matches!(cli.command, Some(Command::Validate)));
+        let cli = Cli::try_parse_from(["rnr", "--strict", "build"]).unwrap();
+        assert!(cli.strict);
+    }
+
+    #[test]
+    fn test_split_words() {
+        assert_eq!(
+            split_words(r#"  --suite 'slow tests' a\ b "say \"hi\" \n" '' x"#).unwrap(),
+            ["--suite", "slow tests", "a b", "say \"hi\" \\n", "", "x"]
+        );
+        assert!(split_words("   ").unwrap().is_empty());
+        assert_eq!(
+            split_words("--tag 'v1").unwrap_err(),
+            "a single quote is never closed"
+        );
+        assert_eq!(
+            split_words("\"v1").unwrap_err(),
+            "a double quote is never closed"
+        );
+        assert_eq!(
+            split_words("v1\\").unwrap_err(),
+            "it ends with a lone backslash"
+        );
+    }
+
+    #[test]
+    fn test_task_from_env() {
+        let task = |task: Option<&str>, args| task_from(task.map(str::to_string), args);
+        assert_eq!(task(None, Some("--fast")).unwrap(), None);
+        assert_eq!(task(Some(" "), None).unwrap(), None);
+        assert_eq!(
+            task(Some("test-integration"), None).unwrap(),
+            Some(("test-integration".to_string(), vec![]))
+        );
+        assert_eq!(
+            task(Some("deploy"), Some("staging 'two words'")).unwrap(),
+            Some((
+                "deploy".to_string(),
+                vec!["staging".to_string(), "two words".to_string()]
+            ))
+        );
+        let err = task(Some("deploy"), Some("'staging")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Couldn't parse RNR_ARGS ('staging): a single quote is never closed"
+        );
+    }
+
+    #[test]
+    fn test_run_subcommand() {
+        let cli = Cli::try_parse_from(["rnr", "run", "init", "--force", "x"]).unwrap();
+        match cli.command {
+            Some(Command::Run(args)) => {
+                assert_eq!(args.task, "init");
+                assert_eq!(args.args, vec!["--force", "x"]);
+            }
+            other => panic!("Expected run subcommand, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_every_subcommand_shadows_tasks() {
+        let names = subcommand_names();
+        for expected in ["init", "upgrade", "validate", "run", "rerun-failed"] {
+            assert!(names.iter().any(|n| n == expected), "{:?}", names);
+        }
+
+        // Whatever subcommands exist now or later, a task of that name is shadowed
+        let mut tasks: Vec<&str> = names.iter().map(String::as_str).collect();
+        tasks.push("build");
+        let shadowed = shadowed_tasks(&tasks);
+        assert_eq!(shadowed.len(), names.len());
+        assert!(!shadowed.contains(&"build"));
+        for name in &names {
+            let cli = Cli::try_parse_from(["rnr", name.as_str()]);
+            assert!(
+                cli.map_or(true, |cli| cli.task.is_none()),
+                "'rnr {}' ran a task",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_init_flag_conflicts() {
+        let invalid: &[&[&str]] = &[
+            &["--show-platforms", "--add-platform", "linux-amd64"],
+            &["--show-platforms", "--remove-platform", "linux-amd64"],
+            &["--show-platforms", "--prune"],
+            &["--show-platforms", "--platforms", "linux-amd64"],
+            &["--show-platforms", "--all-platforms"],
+            &["--show-platforms", "--current-platform-only"],
+            &["--show-platforms", "--placeholders"],
+            &["--show-platforms", "--force"],
+            &["--show-platforms", "--yes"],
+            &["--prune", "--add-platform", "linux-amd64"],
+            &["--prune", "--remove-platform", "linux-amd64"],
+            &["--prune", "--platforms", "linux-amd64"],
+            &["--prune", "--all-platforms"],
+            &["--prune", "--current-platform-only"],
+            &["--prune", "--placeholders"],
+            &["--prune", "--force"],
+            &[
+                "--add-platform",
+                "linux-amd64",
+                "--remove-platform",
+                "macos-arm64",
+            ],
+            &[
+                "--add-platform",
+                "linux-amd64",
+                "--platforms",
+                "macos-arm64",
+            ],
+            &["--add-platform", "linux-amd64", "--all-platforms"],
+            &["--add-platform", "linux-amd64", "--current-platform-only"],
+            &["--add-platform", "linux-amd64", "--force"],
+            &["--add-platform", "linux-amd64", "--yes"],
+            &[
+                "--remove-platform",
+                "linux-amd64",
+                "--platforms",
+                "macos-arm64",
+            ],
+            &["--remove-platform", "linux-amd64", "--all-platforms"],
+            &[
+                "--remove-platform",
+                "linux-amd64",
+                "--current-platform-only",
+            ],
+            &["--remove-platform", "linux-amd64", "--placeholders"],
+            &["--remove-platform", "linux-amd64", "--force"],
+            &["--remove-platform", "linux-amd64", "--yes"],
+            &["--all-platforms", "--platforms", "linux-amd64"],
+            &["--all-platforms", "--current-platform-only"],
+            &["--current-platform-only", "--platforms", "linux-amd64"],
+            &["--yes"],
+            &["--force", "--yes"],
+            &["--nested", "--show-platforms"],
+            &["--nested", "--prune"],
+            &["--nested", "--add-platform", "linux-amd64"],
+            &["--nested", "--remove-platform", "linux-amd64"],
+            &["--nested", "--yes"],
+        ];
+        for flags in invalid {
+            let err = Cli::try_parse_from(["rnr", "init"].iter().chain(flags.iter()))
+                .expect_err(&format!("{:?} should be rejected", flags));
+            assert!(
+                matches!(
+                    err.kind(),
+                    clap::error::ErrorKind::ArgumentConflict
+                        | clap::error::ErrorKind::MissingRequiredArgument
+                ),
+                "{:?}: {}",
+                flags,
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn test_recursive_requires_list() {
+        let cli = Cli::try_parse_from(["rnr", "--list", "--recursive"]).unwrap();
+        assert!(cli.list && cli.recursive);
+        assert!(Cli::try_parse_from(["rnr", "--recursive"]).is_err());
+    }
+
+    #[test]
+    fn test_events_destinations_conflict() {
+        let cli = Cli::try_parse_from(["rnr", "--events-fd", "3", "build"]).unwrap();
+        assert_eq!(cli.events_fd, Some(3));
+        assert!(
+            Cli::try_parse_from(["rnr", "--events-json", "--events-fd", "3", "build"]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_jobs_must_be_positive() {
+        let cli = Cli::try_parse_from(["rnr", "--jobs", "4", "ci"]).unwrap();
+        assert_eq!(cli.jobs, Some(4));
+        assert!(Cli::try_parse_from(["rnr", "--jobs", "0", "ci"]).is_err());
+    }
+
+    #[test]
+    fn test_timeout_is_global() {
+        let cli = Cli::try_parse_from(["rnr", "init", "--timeout", "300"]).unwrap();
+        assert_eq!(cli.timeout, Some(300));
+        assert!(Cli::try_parse_from(["rnr", "upgrade", "--timeout", "soon"]).is_err());
+    }
+
+    #[test]
+    fn test_subcommands_still_parse() {
+        let cli = Cli::try_parse_from(["rnr", "upgrade"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::Upgrade(UpgradeArgs { check: false, .. }))
+        ));
+        let cli = Cli::try_parse_from(["rnr", "upgrade", "--check"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::Upgrade(UpgradeArgs { check: true, .. }))
+        ));
+        let cli = Cli::try_parse_from(["rnr", "verify"]).unwrap();
+        assert!(matches!(cli.command, Some(Command::Verify)));
+    }
 }
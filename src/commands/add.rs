@@ -0,0 +1,354 @@
+//! Add a task to rnr.yaml from the command line
+//!
+//! The rest of the file is kept byte for byte: the new task's lines are
+//! inserted at the end, after another task, or in place of the task they
+//! replace.
+
+use anyhow::{Context, Result};
+use serde_yaml::{Mapping, Value};
+use std::fs;
+
+use crate::cli::{self, AddArgs};
+use crate::commands::list;
+use crate::config::{Cmd, Config, TaskDef, CONFIG_FILE};
+use crate::formatter;
+use crate::project::Project;
+use crate::source::{quoted_line, split_mapping_key};
+use crate::ui::Ui;
+
+/// Run the add command
+pub fn run(args: &AddArgs, project: &Project, ui: &Ui) -> Result<()> {
+    let path = project.config_path()?;
+    // Only a file rnr can load is edited, so its errors come first
+    project.config()?;
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+
+    let updated = add_task(&content, args)?;
+    fs::write(path, updated).with_context(|| format!("Failed to write {}", path.display()))?;
+
+    let verb = if content_has_task(&content, &args.name) {
+        "Replaced"
+    } else {
+        "Added"
+    };
+    ui.line(format!("{} task '{}' in {}", verb, args.name, CONFIG_FILE));
+    if !cli::shadowed_tasks(&[args.name.as_str()]).is_empty() {
+        ui.warn(list::shadowed_warning(&args.name));
+    }
+    Ok(())
+}
+
+/// `content` with the task from `args` added, or replacing the task of that
+/// name with `--force`
+fn add_task(content: &str, args: &AddArgs) -> Result<String> {
+    let task = render(args)?;
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    let existing = task_lines(&lines, &args.name);
+
+    let updated = match (existing, &args.after) {
+        (Some(_), _) if !args.force => anyhow::bail!(
+            "Task '{}' already exists in {}; pass --force to replace it",
+            args.name,
+            CONFIG_FILE
+        ),
+        (Some(_), Some(_)) => anyhow::bail!(
+            "--after only places a new task; '{}' already exists, and --force replaces it where it is",
+            args.name
+        ),
+        (None, Some(after)) => {
+            let newline = newline_of(content);
+            let range = task_lines(&lines, after)
+                .with_context(|| format!("Task '{}' not found (for --after)", after))?;
+            let mut updated = lines[..range.end].concat();
+            ensure_newline(&mut updated, newline);
+            updated.push_str(newline);
+            updated.push_str(&task.replace('\n', newline));
+            updated.push_str(&lines[range.end..].concat());
+            updated
+        }
+        (_, None) => put_task(content, &args.name, &task),
+    };
+    check_added(&updated, args)?;
+    Ok(updated)
+}
+
+/// `content` with `task`, one task's rnr.yaml text, in place of the lines of
+/// the task called `name`, or at the end if there's no such task
+pub(crate) fn put_task(content: &str, name: &str, task: &str) -> String {
+    let newline = newline_of(content);
+    let task = task.replace('\n', newline);
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    if let Some(range) = task_lines(&lines, name) {
+        let mut updated = lines[..range.start].concat();
+        updated.push_str(&task);
+        updated.push_str(&lines[range.end..].concat());
+        return updated;
+    }
+    let mut updated = content.to_string();
+    ensure_newline(&mut updated, newline);
+    // Set the task off from what comes before it
+    if !updated.trim().is_empty() && !updated.ends_with(&format!("{0}{0}", newline)) {
+        updated.push_str(newline);
+    }
+    updated.push_str(&task);
+    updated
+}
+
+/// The line break `content` uses
+fn newline_of(content: &str) -> &'static str {
+    if content.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    }
+}
+
+/// The new task as canonical rnr.yaml text, ending in a newline
+fn render(args: &AddArgs) -> Result<String> {
+    let name = Value::String(args.name.clone());
+    let cmd = Value::String(args.cmd.clone());
+    let value = if args.shorthand {
+        if args.description.is_some() || args.dir.is_some() {
+            anyhow::bail!("--shorthand only writes a task with just a --cmd");
+        }
+        cmd
+    } else {
+        let mut task = Mapping::new();
+        let fields = [("description", &args.description), ("dir", &args.dir)];
+        for (field, value) in fields {
+            if let Some(value) = value {
+                task.insert(field.into(), Value::String(value.clone()));
+            }
+        }
+        task.insert("cmd".into(), cmd);
+        Value::Mapping(task)
+    };
+    let mut document = Mapping::new();
+    document.insert(name, value);
+    let key = if args.shorthand { &args.name } else { "cmd" };
+    Ok(quote_plain(
+        &formatter::format(&serde_yaml::to_string(&document)?)?,
+        key,
+    ))
+}
+
+/// `yaml` with the value of `key` single-quoted if it's written plain, so
+/// nothing in a command (`: `, a leading `*` or `&`) is ever read as YAML.
+/// Keys opening a list item (`- cmd: ...`) count too.
+pub(crate) fn quote_plain(yaml: &str, key: &str) -> String {
+    yaml.split_inclusive('\n')
+        .map(|line| {
+            let text = line.trim_end();
+            let field = text.trim_start();
+            let field = field.strip_prefix("- ").unwrap_or(field);
+            let rest = match split_mapping_key(field) {
+                Some((found, rest)) if found == key => rest,
+                _ => return line.to_string(),
+            };
+            let value = rest.trim();
+            if value.is_empty() || value.starts_with(['\'', '"', '|', '>']) {
+                return line.to_string();
+            }
+            let indent = &text[..text.len() - field.len()];
+            let end = &line[text.len()..];
+            format!("{}{}{}", indent, quoted_line(field, rest, value), end)
+        })
+        .collect()
+}
+
+/// Make sure the edited file loads and has the task as it was given
+fn check_added(content: &str, args: &AddArgs) -> Result<()> {
+    let config: Config = serde_yaml::from_str(content).with_context(|| {
+        format!(
+            "Adding '{}' would leave {} unreadable",
+            args.name, CONFIG_FILE
+        )
+    })?;
+    let added = match config.get_task(&args.name) {
+        Some(TaskDef::Shorthand(cmd)) => cmd == &args.cmd,
+        Some(TaskDef::Full(task)) => {
+            matches!(&task.cmd, Some(Cmd::Shell(cmd)) if cmd == &args.cmd)
+                && task.description == args.description
+                && task.dir == args.dir
+        }
+        None => false,
+    };
+    if !added {
+        anyhow::bail!(
+            "Adding '{}' to {} didn't give the task that was asked for; the file is unchanged",
+            args.name,
+            CONFIG_FILE
+        );
+    }
+    Ok(())
+}
+
+/// Whether `content` defines a task called `name`
+pub(crate) fn content_has_task(content: &str, name: &str) -> bool {
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    task_lines(&lines, name).is_some()
+}
+
+/// The lines of top-level entry `name`: its key and everything indented
+/// under it, leaving out blank lines and comments before the next entry
+fn task_lines(lines: &[&str], name: &str) -> Option<std::ops::Range<usize>> {
+    let top_level = |line: &str| !line.starts_with([' ', '\t', '\r', '\n']);
+    let start = lines.iter().position(|line| {
+        top_level(line) && split_mapping_key(line.trim_end()).is_some_and(|(key, _)| key == name)
+    })?;
+    let next = lines[start + 1..]
+        .iter()
+        .position(|line| top_level(line))
+        .map_or(lines.len(), |offset| start + 1 + offset);
+    let end = (start + 1..next)
+        .rev()
+        .find(|&i| !lines[i].trim().is_empty())
+        .map_or(start + 1, |last| last + 1);
+    Some(start..end)
+}
+
+/// End `text` with a line break, unless it's empty
+fn ensure_newline(text: &mut String, newline: &str) {
+    if !text.is_empty() && !text.ends_with('\n') {
+        text.push_str(newline);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(name: &str, cmd: &str) -> AddArgs {
+        AddArgs {
+            name: name.to_string(),
+            cmd: cmd.to_string(),
+            description: None,
+            dir: None,
+            after: None,
+            force: false,
+            shorthand: false,
+        }
+    }
+
+    #[test]
+    fn test_appends_and_keeps_the_rest() {
+        let deploy = AddArgs {
+            description: Some("Deploy to prod".into()),
+            dir: Some("infra".into()),
+            ..args("deploy", "./scripts/deploy.sh")
+        };
+        let content = "# Tasks\nbuild:   cargo build   # odd spacing kept\n";
+        assert_eq!(
+            add_task(content, &deploy).unwrap(),
+            "# Tasks\nbuild:   cargo build   # odd spacing kept\n\n\
+             deploy:\n  description: Deploy to prod\n  dir: infra\n  cmd: './scripts/deploy.sh'\n"
+        );
+
+        // Without a final newline, with one blank line, and empty
+        let lint = AddArgs {
+            shorthand: true,
+            ..args("lint", "cargo clippy")
+        };
+        assert_eq!(
+            add_task("build: make", &lint).unwrap(),
+            "build: make\n\nlint: 'cargo clippy'\n"
+        );
+        assert_eq!(
+            add_task("build: make\n\n", &lint).unwrap(),
+            "build: make\n\nlint: 'cargo clippy'\n"
+        );
+        assert_eq!(add_task("", &lint).unwrap(), "lint: 'cargo clippy'\n");
+        assert_eq!(
+            add_task("build: make\r\n", &args("lint", "cargo clippy")).unwrap(),
+            "build: make\r\n\r\nlint:\r\n  cmd: 'cargo clippy'\r\n"
+        );
+    }
+
+    #[test]
+    fn test_after_a_task() {
+        let content = "build:\n  cmd: make\n  # last line of build\n\n# Lint\nlint: cargo clippy\n";
+        let test = AddArgs {
+            after: Some("build".into()),
+            shorthand: true,
+            ..args("test", "make test")
+        };
+        assert_eq!(
+            add_task(content, &test).unwrap(),
+            "build:\n  cmd: make\n  # last line of build\n\ntest: 'make test'\n\n# Lint\nlint: cargo clippy\n"
+        );
+
+        let missing = AddArgs {
+            after: Some("nope".into()),
+            ..test
+        };
+        let err = add_task(content, &missing).unwrap_err().to_string();
+        assert_eq!(err, "Task 'nope' not found (for --after)");
+    }
+
+    #[test]
+    fn test_existing_tasks_need_force() {
+        let content = "build:\n  cmd: make\n\nlint: cargo clippy # fast\n";
+        let err = add_task(content, &args("build", "cargo build"))
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            err,
+            "Task 'build' already exists in rnr.yaml; pass --force to replace it"
+        );
+
+        let force = AddArgs {
+            force: true,
+            ..args("build", "cargo build")
+        };
+        assert_eq!(
+            add_task(content, &force).unwrap(),
+            "build:\n  cmd: 'cargo build'\n\nlint: cargo clippy # fast\n"
+        );
+    }
+
+    #[test]
+    fn test_quotes_what_needs_it() {
+        let odd = AddArgs {
+            description: Some("Build: everything".into()),
+            ..args("build all", "echo 'a: b' # not a comment")
+        };
+        let updated = add_task("", &odd).unwrap();
+        assert_eq!(
+            updated,
+            "build all:\n  description: 'Build: everything'\n  cmd: 'echo ''a: b'' # not a comment'\n"
+        );
+
+        // Commands are quoted even where YAML wouldn't need it
+        for cmd in ["*.o", "&x echo", "./deploy.sh --env=prod: true", "it's"] {
+            let shorthand = AddArgs {
+                shorthand: true,
+                ..args("clean", cmd)
+            };
+            let updated = add_task("", &shorthand).unwrap();
+            let config: Config = serde_yaml::from_str(&updated).unwrap();
+            assert!(
+                matches!(config.get_task("clean"), Some(TaskDef::Shorthand(found)) if found == cmd),
+                "{}",
+                updated
+            );
+            assert!(updated.starts_with("clean: '"), "{}", updated);
+        }
+        let multiline = add_task("", &args("build", "make\nmake install")).unwrap();
+        assert_eq!(multiline, "build:\n  cmd: |-\n    make\n    make install\n");
+
+        let err = add_task(
+            "",
+            &AddArgs {
+                shorthand: true,
+                dir: Some("app".into()),
+                ..args("x", "make")
+            },
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "--shorthand only writes a task with just a --cmd"
+        );
+    }
+}
@@ -0,0 +1,237 @@
+//! Run the tasks whose `paths` cover files changed since a git ref
+
+use anyhow::{Context, Result};
+use glob::{MatchOptions, Pattern};
+use std::fmt::Write;
+use std::path::Path;
+
+use crate::checkout;
+use crate::cli::AffectedArgs;
+use crate::config::{Config, WithoutPaths};
+use crate::project::Project;
+use crate::runner::{self, RunOptions};
+
+/// Why a task was picked
+#[derive(Debug, PartialEq, Eq)]
+enum Reason {
+    /// A changed file matches one of its `paths`
+    Matched { file: String, glob: String },
+    /// It has no `paths` and `affected_without_paths` is `run`
+    NoPaths,
+}
+
+/// Which of a config's tasks a set of changes affects
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Selection {
+    /// Tasks to run, in name order, with the reason for each
+    affected: Vec<(String, Reason)>,
+    /// Every other task
+    unaffected: Vec<String>,
+}
+
+/// Run the affected command
+pub fn run(args: &AffectedArgs, project: &Project, opts: &RunOptions) -> Result<()> {
+    let config = project.validated_config()?;
+    let root = project.root()?;
+    let (base, changed) = changed_files(root, &args.since)?;
+    let selection = select(&config, &changed)?;
+    opts.ui.print(render(
+        &args.since,
+        &base,
+        changed.len(),
+        &selection,
+        config.affected_without_paths,
+    ));
+    for (task, _) in &selection.affected {
+        runner::run_task(task, &[], opts)?;
+    }
+    Ok(())
+}
+
+/// Files changed between the merge base of `since` and HEAD and the working
+/// tree, relative to the project root, with the merge base
+fn changed_files(root: &Path, since: &str) -> Result<(String, Vec<String>)> {
+    checkout::git(root, &["rev-parse", "--show-toplevel"])
+        .context("'rnr affected' needs the project to be in a git repository")?;
+    let base = checkout::git(root, &["merge-base", since, "HEAD"])
+        .with_context(|| format!("Can't find where '{}' and HEAD diverged", since))?;
+    let files = checkout::git(root, &["diff", "--name-only", "--relative", &base])?;
+    Ok((base, files.lines().map(str::to_string).collect()))
+}
+
+/// Pick the tasks with a `paths` entry matching one of `changed`, plus those
+/// without `paths` if the config says to run them
+fn select(config: &Config, changed: &[String]) -> Result<Selection> {
+    let mut selection = Selection::default();
+    for name in config.task_names() {
+        let task = &config.tasks[name];
+        let reason = match task.paths() {
+            Some(paths) => first_match(name, paths, changed)?,
+            None => (config.affected_without_paths == WithoutPaths::Run).then_some(Reason::NoPaths),
+        };
+        match reason {
+            Some(reason) => selection.affected.push((name.to_string(), reason)),
+            None => selection.unaffected.push(name.to_string()),
+        }
+    }
+    Ok(selection)
+}
+
+/// The first changed file one of `paths` matches. A glob matches files as
+/// usual (`*` stays within a directory, `**` crosses them); a plain path
+/// also matches everything under it.
+fn first_match(task: &str, paths: &[String], changed: &[String]) -> Result<Option<Reason>> {
+    let options = MatchOptions {
+        require_literal_separator: true,
+        ..MatchOptions::new()
+    };
+    for glob in paths {
+        let pattern = Pattern::new(glob)
+            .with_context(|| format!("Task '{}' has an invalid path '{}'", task, glob))?;
+        let dir = glob.trim_end_matches('/');
+        let found = changed.iter().find(|file| {
+            pattern.matches_with(file, options)
+                || file
+                    .strip_prefix(dir)
+                    .is_some_and(|rest| rest.starts_with('/'))
+        });
+        if let Some(file) = found {
+            return Ok(Some(Reason::Matched {
+                file: file.clone(),
+                glob: glob.clone(),
+            }));
+        }
+    }
+    Ok(None)
+}
+
+/// What changed and which tasks that selects, and why
+fn render(
+    since: &str,
+    base: &str,
+    changed: usize,
+    selection: &Selection,
+    without_paths: WithoutPaths,
+) -> String {
+    let mut out = format!(
+        "{} file{} changed since {} (merge base {})\n",
+        changed,
+        if changed == 1 { "" } else { "s" },
+        since,
+        &base[..base.len().min(12)]
+    );
+    if selection.affected.is_empty() {
+        out.push_str("Nothing affected.\n");
+        return out;
+    }
+    out.push_str("Affected tasks:\n");
+    let width = selection
+        .affected
+        .iter()
+        .map(|(task, _)| task.len())
+        .max()
+        .unwrap_or(0);
+    for (task, reason) in &selection.affected {
+        let why = match reason {
+            Reason::Matched { file, glob } => format!("{} matches '{}'", file, glob),
+            Reason::NoPaths => format!("no paths (affected_without_paths: {})", without_paths),
+        };
+        let _ = writeln!(out, "  {:<width$}  {}", task, why, width = width);
+    }
+    if !selection.unaffected.is_empty() {
+        let _ = writeln!(out, "Not affected: {}", selection.unaffected.join(", "));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = "build:\n  paths: ['src/**/*.rs', Cargo.toml]\n  cmd: cargo build\ndocs:\n  paths: [docs/]\n  cmd: mkdocs build\nweb:\n  paths: ['web/*.ts']\n  cmd: npm run build\nlint: cargo clippy\n";
+
+    fn selection(yaml: &str, changed: &[&str]) -> Selection {
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        let changed: Vec<String> = changed.iter().map(|f| f.to_string()).collect();
+        select(&config, &changed).unwrap()
+    }
+
+    fn matched(file: &str, glob: &str) -> Reason {
+        Reason::Matched {
+            file: file.to_string(),
+            glob: glob.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_select_by_paths() {
+        let selection = selection(
+            CONFIG,
+            &[
+                "README.md",
+                "docs/guide/intro.md",
+                "src/cli/mod.rs",
+                "web/app/main.ts",
+            ],
+        );
+        assert_eq!(
+            selection.affected,
+            [
+                (
+                    "build".to_string(),
+                    matched("src/cli/mod.rs", "src/**/*.rs")
+                ),
+                ("docs".to_string(), matched("docs/guide/intro.md", "docs/")),
+            ]
+        );
+        // `*` doesn't cross directories, and tasks without paths are skipped
+        assert_eq!(selection.unaffected, ["lint", "web"]);
+    }
+
+    #[test]
+    fn test_select_without_paths_policy() {
+        let yaml = format!("affected_without_paths: run\n{}", CONFIG);
+        let selection = selection(&yaml, &["Cargo.toml"]);
+        assert_eq!(
+            selection.affected,
+            [
+                ("build".to_string(), matched("Cargo.toml", "Cargo.toml")),
+                ("lint".to_string(), Reason::NoPaths),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_select_invalid_glob() {
+        let config: Config =
+            serde_yaml::from_str("build:\n  paths: ['src/[']\n  cmd: make\n").unwrap();
+        let error = select(&config, &["src/a".to_string()]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Task 'build' has an invalid path 'src/['"
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let selection = selection(CONFIG, &["src/main.rs", "docs/index.md"]);
+        assert_eq!(
+            render(
+                "origin/main",
+                "0123456789abcdef",
+                2,
+                &selection,
+                WithoutPaths::Skip
+            ),
+            "2 files changed since origin/main (merge base 0123456789ab)\n\
+             Affected tasks:\n  \
+             build  src/main.rs matches 'src/**/*.rs'\n  \
+             docs   docs/index.md matches 'docs/'\n\
+             Not affected: lint, web\n"
+        );
+        assert_eq!(
+            render("HEAD", "abc", 1, &Selection::default(), WithoutPaths::Skip),
+            "1 file changed since HEAD (merge base abc)\nNothing affected.\n"
+        );
+    }
+}
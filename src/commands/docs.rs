@@ -0,0 +1,527 @@
+//! Write rnr's man pages or a markdown reference, generated from the
+//! command-line definitions so they can't drift from them. The man pages
+//! are rendered by clap_mangen: rnr.1, with a section on the task file,
+//! and an `rnr-<command>.1` for each command.
+//!
+//! Nothing depends on the time or the machine, so the output can be
+//! committed and diffed in CI: the same rnr always writes the same files.
+
+use anyhow::{Context, Result};
+use clap::{Arg, Command, CommandFactory};
+use clap_mangen::roff::{bold, roman, Roff};
+use clap_mangen::Man;
+use std::fs;
+
+use crate::build_info::VERSION;
+use crate::cli::{Cli, DocsArgs, DocsFormat};
+use crate::config::CONFIG_FILE;
+use crate::ui::Ui;
+
+/// Top-level settings of rnr.yaml, in the order of [`crate::config::SETTINGS`]
+const SETTINGS: &[(&str, &str)] = &[
+    (
+        "min_version",
+        "Oldest rnr that understands this file, checked before the rest of it is read",
+    ),
+    (
+        "allow_abbreviations",
+        "Run a task from an unambiguous abbreviation of its name (CLI only)",
+    ),
+    (
+        "strict",
+        "Refuse to run while any task has fields rnr doesn't know",
+    ),
+    (
+        "secrets",
+        "Environment variables whose values are never shown (--verbose, rnr env)",
+    ),
+    (
+        "secret_patterns",
+        "Extra name patterns (*_CERT*) marking variables as secret",
+    ),
+    (
+        "affected_without_paths",
+        "Whether rnr affected runs the tasks that have no paths",
+    ),
+    (
+        "echo_format",
+        "How commands are echoed before they run, e.g. \"[{dir}] $ {cmd}\"",
+    ),
+    (
+        "bins",
+        "Project-local programs, by name, relative to this file's directory (${bins.<name>})",
+    ),
+    (
+        "umask",
+        "Bits masked out of the files a run creates (Unix only)",
+    ),
+    ("hooks", "Hooks run around every task"),
+    (
+        "renamed",
+        "Old task names and the tasks they now run, with a deprecation notice",
+    ),
+    (
+        "contexts",
+        "Contexts besides ci and local that tasks' contexts may name",
+    ),
+    (
+        "tz",
+        "Time zone of not_between windows: local (the default), UTC, or an offset like +02:00",
+    ),
+    (
+        "budget",
+        "How long a run may go (e.g. 30m) before it stops starting steps",
+    ),
+    (
+        "output_buffer",
+        "Output a step may hold in memory (e.g. 512K), such as what quiet-success holds back (default 1MB)",
+    ),
+    (
+        "settings",
+        "Project defaults for rnr's flags: color, jobs, output, timestamps, eta (see rnr settings)",
+    ),
+];
+
+/// Fields of a full task definition, in the order of [`crate::config::TASK_FIELDS`]
+const TASK_FIELDS: &[(&str, &str)] = &[
+    ("description", "Human-readable description"),
+    (
+        "long_description",
+        "Extended description shown by rnr help <task>",
+    ),
+    (
+        "usage",
+        "Usage line shown by rnr help <task> (generated from args if omitted)",
+    ),
+    (
+        "args",
+        "Declared arguments, bound positionally or as --name value and exposed as ${args.<name>} and RNR_ARG_<NAME>",
+    ),
+    (
+        "strict_args",
+        "Reject arguments beyond the declared ones instead of passing them through",
+    ),
+    ("examples", "Example invocations shown by rnr help <task>"),
+    ("dir", "Working directory (relative to project root)"),
+    (
+        "env",
+        "Environment variables, literal or computed with from_cmd",
+    ),
+    ("cmd", "Command to execute"),
+    ("task", "Another task to run"),
+    ("task_args", "Arguments passed to the task named by task"),
+    (
+        "choose",
+        "A menu (prompt, options: {name: task}) of tasks, one of which runs: rnr <task> <option>, or picked in a terminal",
+    ),
+    ("steps", "Sequential steps"),
+    (
+        "checkout",
+        "Run in a temporary clean checkout instead of the working tree",
+    ),
+    ("foreach", "Run the task's cmd, task or steps once per value"),
+    (
+        "once",
+        "Run at most once per invocation, however many tasks delegate to it",
+    ),
+    ("output_filter", "Built-in filter for the stdout of cmd"),
+    ("echo", "Whether cmd is echoed before it runs (default true)"),
+    (
+        "expect_exit",
+        "Exit codes of cmd that count as success (default just 0)",
+    ),
+    (
+        "paths",
+        "Files the task depends on, as globs relative to the project root; rnr affected runs the task when one of them changed",
+    ),
+    (
+        "sources",
+        "Files the task reads, as globs; a task whose outputs they overlap runs first when those are stale",
+    ),
+    ("outputs", "Files the task makes, as globs"),
+    (
+        "log",
+        "File that gets a copy of everything the task's commands print",
+    ),
+    (
+        "log_mode",
+        "Whether each run adds to log (the default) or starts it over",
+    ),
+    (
+        "log_file_mode",
+        "Permissions for log and its rotated copies (Unix only)",
+    ),
+    ("log_max_size", "Rotate log before it grows past this size"),
+    ("log_keep", "Rotated copies of log to keep (default 5)"),
+    (
+        "priority",
+        "Scheduling priority of the task's commands, and of the steps and tasks it runs unless they set their own",
+    ),
+    (
+        "on_failure",
+        "What runs when this task, run from the command line, fails (instead of the top-level hooks.on_failure)",
+    ),
+    (
+        "deprecated",
+        "Marks the task as on its way out, optionally naming its replacement",
+    ),
+    (
+        "contexts",
+        "Contexts (ci, local, or ones declared at the top level) the task may or may not run in",
+    ),
+    (
+        "not_between",
+        "Times of day the task doesn't start, e.g. [\"22:00\", \"06:00\"]",
+    ),
+    (
+        "require_clean_git",
+        "Refuse to run with uncommitted changes to tracked files (--allow-dirty overrides)",
+    ),
+    (
+        "require_branch",
+        "Refuse to run unless this branch is checked out",
+    ),
+    (
+        "require_upstream_synced",
+        "Refuse to run while the branch is ahead of or behind its upstream, as of the last fetch",
+    ),
+];
+
+/// Run the docs command
+pub fn run(args: &DocsArgs, ui: &Ui) -> Result<()> {
+    let files = match args.format {
+        DocsFormat::Man => man_pages()?,
+        DocsFormat::Markdown => vec![("rnr.md".to_string(), markdown())],
+    };
+    fs::create_dir_all(&args.out)
+        .with_context(|| format!("Failed to create {}", args.out.display()))?;
+    for (file, content) in files {
+        let path = args.out.join(file);
+        fs::write(&path, content).with_context(|| format!("Failed to write {}", path.display()))?;
+        ui.line(format!("Wrote {}", path.display()));
+    }
+    Ok(())
+}
+
+/// A command as the references describe it
+struct Page {
+    /// `rnr`, or `rnr <subcommand>`
+    name: String,
+    /// What it does
+    about: String,
+    /// Usage line, without the `Usage:` clap puts before it
+    usage: String,
+    /// Its visible options and positionals
+    args: Vec<Entry>,
+}
+
+/// An option or positional: how it's written, and what it does
+struct Entry {
+    /// e.g. `-l, --list`, `--jobs <N>` or `[TASK]`
+    syntax: String,
+    /// Help text, with the possible and default values
+    help: String,
+}
+
+/// `rnr` itself, then each visible subcommand in declaration order.
+/// Global options are described once, on `rnr`.
+fn pages() -> Vec<Page> {
+    let mut cli = Cli::command();
+    cli.build();
+    let mut pages = vec![page(&mut cli, "rnr".to_string(), false)];
+    for sub in cli.get_subcommands_mut().filter(|sub| !sub.is_hide_set()) {
+        let name = format!("rnr {}", sub.get_name());
+        pages.push(page(sub, name, true));
+    }
+    pages
+}
+
+/// The page for `command`, leaving out global options if `sub`
+fn page(command: &mut Command, name: String, sub: bool) -> Page {
+    let usage = command.render_usage().to_string();
+    let usage = usage.trim_start_matches("Usage:").trim().to_string();
+    let about = command
+        .get_about()
+        .map(ToString::to_string)
+        .unwrap_or_default();
+    let args = command
+        .get_arguments()
+        .filter(|arg| !arg.is_hide_set() && arg.get_id() != "help")
+        .filter(|arg| !(sub && arg.is_global_set()))
+        .map(entry)
+        .collect();
+    Page {
+        name,
+        about,
+        usage,
+        args,
+    }
+}
+
+/// How `arg` is written and what it does
+fn entry(arg: &Arg) -> Entry {
+    let value_name = || match arg.get_value_names() {
+        Some(names) => names[0].to_string(),
+        None => arg.get_id().as_str().to_uppercase(),
+    };
+    let syntax = if arg.is_positional() {
+        let many = arg.get_num_args().is_some_and(|n| n.max_values() > 1);
+        let name = match arg.is_required_set() {
+            true => format!("<{}>", value_name()),
+            false => format!("[{}]", value_name()),
+        };
+        if many {
+            format!("{}...", name)
+        } else {
+            name
+        }
+    } else {
+        let mut names = Vec::new();
+        if let Some(short) = arg.get_short() {
+            names.push(format!("-{}", short));
+        }
+        if let Some(long) = arg.get_long() {
+            names.push(format!("--{}", long));
+        }
+        let mut syntax = names.join(", ");
+        if arg.get_action().takes_values() {
+            syntax.push_str(&format!(" <{}>", value_name()));
+        }
+        syntax
+    };
+
+    let mut help = arg
+        .get_long_help()
+        .or(arg.get_help())
+        .map(ToString::to_string)
+        .unwrap_or_default();
+    if arg.get_action().takes_values() {
+        let values: Vec<String> = arg
+            .get_possible_values()
+            .iter()
+            .filter(|value| !value.is_hide_set())
+            .map(|value| value.get_name().to_string())
+            .collect();
+        if !values.is_empty() {
+            help.push_str(&format!(" [possible values: {}]", values.join(", ")));
+        }
+        let defaults: Vec<String> = arg
+            .get_default_values()
+            .iter()
+            .map(|value| value.to_string_lossy().into_owned())
+            .collect();
+        if !defaults.is_empty() {
+            help.push_str(&format!(" [default: {}]", defaults.join(", ")));
+        }
+    }
+    Entry {
+        syntax,
+        help: help.trim().to_string(),
+    }
+}
+
+/// The man pages by file name: rnr.1, then one per visible command
+fn man_pages() -> Result<Vec<(String, String)>> {
+    let mut cli = Cli::command();
+    cli.build();
+    let mut pages = Vec::new();
+
+    let rnr = man(cli.clone());
+    let mut out = Vec::new();
+    rnr.render_title(&mut out)?;
+    rnr.render_name_section(&mut out)?;
+    rnr.render_synopsis_section(&mut out)?;
+    description().to_writer(&mut out)?;
+    rnr.render_options_section(&mut out)?;
+    rnr.render_subcommands_section(&mut out)?;
+    task_file().to_writer(&mut out)?;
+    pages.push((rnr.get_filename(), String::from_utf8(out)?));
+
+    for sub in cli.get_subcommands().filter(|sub| !sub.is_hide_set()) {
+        let page = man(without_globals(sub.clone()));
+        let mut out = Vec::new();
+        page.render(&mut out)?;
+        pages.push((page.get_filename(), String::from_utf8(out)?));
+    }
+    Ok(pages)
+}
+
+/// clap_mangen's page for `command`, with no date so the same rnr always
+/// writes the same file
+fn man(command: Command) -> Man {
+    let title = command
+        .get_display_name()
+        .unwrap_or_else(|| command.get_name())
+        .to_uppercase();
+    Man::new(command)
+        .title(title)
+        .source(format!("rnr {}", VERSION))
+        .manual("User Commands")
+}
+
+/// `command` with the global options hidden, since rnr.1 describes them
+fn without_globals(mut command: Command) -> Command {
+    let globals: Vec<String> = command
+        .get_arguments()
+        .filter(|arg| arg.is_global_set())
+        .map(|arg| arg.get_id().to_string())
+        .collect();
+    for id in globals {
+        command = command.mut_arg(id, |arg| arg.hide(true));
+    }
+    command
+}
+
+/// rnr.1's DESCRIPTION section
+fn description() -> Roff {
+    let mut roff = Roff::new();
+    roff.control("SH", ["DESCRIPTION"]);
+    roff.text([
+        roman(format!(
+            "rnr runs the tasks defined in {} at the project root. Run a task with ",
+            CONFIG_FILE
+        )),
+        bold("rnr TASK [ARGS]..."),
+        roman(", or one of the commands below."),
+    ]);
+    roff
+}
+
+/// rnr.1's section on the settings and task fields of the task file
+fn task_file() -> Roff {
+    let mut roff = Roff::new();
+    roff.control("SH", ["TASK FILE"]);
+    roff.text([roman(format!("Top-level settings of {}:", CONFIG_FILE))]);
+    man_fields(&mut roff, SETTINGS);
+    roff.control("PP", []);
+    roff.text([roman(
+        "Every other top-level key is a task: a command line, or a mapping of these fields:",
+    )]);
+    man_fields(&mut roff, TASK_FIELDS);
+    roff
+}
+
+/// Task file fields as a man page's tagged paragraphs
+fn man_fields(roff: &mut Roff, fields: &[(&str, &str)]) {
+    for (name, about) in fields {
+        roff.control("TP", []);
+        roff.text([bold(*name)]);
+        roff.text([roman(*about)]);
+    }
+}
+
+/// The markdown reference, rnr.md
+fn markdown() -> String {
+    let pages = pages();
+    let rnr = &pages[0];
+    let mut out = String::new();
+    out.push_str(&format!("# rnr {} command-line reference\n\n", VERSION));
+    out.push_str(&format!("{}\n\n", rnr.about));
+    out.push_str(&format!("```\n{}\n```\n\n", rnr.usage));
+    out.push_str("## Options\n\n");
+    markdown_entries(&mut out, &rnr.args);
+
+    out.push_str("## Commands\n\n");
+    for page in &pages[1..] {
+        out.push_str(&format!("### `{}`\n\n{}\n\n", page.name, page.about));
+        out.push_str(&format!("```\n{}\n```\n\n", page.usage));
+        markdown_entries(&mut out, &page.args);
+    }
+
+    out.push_str(&format!(
+        "## Task file\n\nTop-level settings of `{}`:\n\n",
+        CONFIG_FILE
+    ));
+    markdown_fields(&mut out, SETTINGS);
+    out.push_str(
+        "Every other top-level key is a task: a command line, or a mapping of these fields:\n\n",
+    );
+    markdown_fields(&mut out, TASK_FIELDS);
+    out.truncate(out.trim_end().len());
+    out.push('\n');
+    out
+}
+
+/// Options or positionals as a markdown list
+fn markdown_entries(out: &mut String, entries: &[Entry]) {
+    if entries.is_empty() {
+        return;
+    }
+    for entry in entries {
+        out.push_str(&format!("- `{}`: {}\n", entry.syntax, entry.help));
+    }
+    out.push('\n');
+}
+
+/// Task file fields as a markdown list
+fn markdown_fields(out: &mut String, fields: &[(&str, &str)]) {
+    for (name, about) in fields {
+        out.push_str(&format!("- `{}`: {}\n", name, about));
+    }
+    out.push('\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config;
+
+    #[test]
+    fn test_fields_match_config() {
+        let names = |fields: &[(&'static str, &str)]| -> Vec<&'static str> {
+            fields.iter().map(|(name, _)| *name).collect()
+        };
+        assert_eq!(names(SETTINGS), config::SETTINGS);
+        assert_eq!(names(TASK_FIELDS), config::TASK_FIELDS);
+    }
+
+    #[test]
+    fn test_writes_man_page_and_markdown() {
+        let temp = tempfile::tempdir().unwrap();
+        let out = temp.path().join("docs");
+        let (ui, capture) = Ui::capture(false);
+        for format in [DocsFormat::Man, DocsFormat::Markdown] {
+            let args = DocsArgs {
+                format,
+                out: out.clone(),
+            };
+            run(&args, &ui).unwrap();
+        }
+        assert!(capture.stdout().contains("rnr.1"));
+
+        let man = fs::read_to_string(out.join("rnr.1")).unwrap();
+        assert!(man.contains(".TH RNR 1 "));
+        assert!(man.contains(".SH SYNOPSIS\n\\fBrnr\\fR [\\fB\\-l\\fR|\\fB\\-\\-list\\fR]"));
+        assert!(man.contains(".TP\n\\fB\\-l\\fR, \\fB\\-\\-list\\fR\nList all available tasks\n"));
+        assert!(man.contains("rnr\\-init(1)"));
+        assert!(man.contains(".TP\n\\fBforeach\\fR\n"));
+        assert!(!man.contains("chaos"), "hidden flags stay hidden");
+
+        // Each command has its own page, without the global options
+        let init = fs::read_to_string(out.join("rnr-init.1")).unwrap();
+        assert!(init.contains(".TH RNR-INIT 1 "));
+        assert!(init.contains("\\fB\\-\\-platforms\\fR"));
+        assert!(!init.contains("project\\-dir"), "globals are listed once");
+        let upgrade = fs::read_to_string(out.join("rnr-upgrade.1")).unwrap();
+        assert!(upgrade.contains("\\fB\\-\\-channel\\fR \\fI<CHANNEL>\\fR"));
+
+        let markdown = fs::read_to_string(out.join("rnr.md")).unwrap();
+        assert!(markdown.contains("```\nrnr [OPTIONS] [TASK] [ARGS]... [COMMAND]\n```"));
+        assert!(markdown.contains("- `[TASK]`: Task to run\n"));
+        assert!(markdown.contains("### `rnr init`\n\nInitialize rnr in the current directory\n"));
+        assert!(markdown.contains("### `rnr upgrade`\n"));
+        assert!(markdown.contains("- `--output <MODE>`: "));
+        assert!(markdown.contains("[default: HEAD]"));
+        assert!(markdown.contains("## Task file\n"));
+        assert!(markdown.contains("- `cmd`: Command to execute\n"));
+        let init = &markdown[markdown.find("### `rnr init`").unwrap()..];
+        let init = &init[..init.find("### `rnr upgrade`").unwrap()];
+        assert!(!init.contains("--project-dir"), "globals are listed once");
+
+        // The same rnr writes the same files
+        assert_eq!(markdown, super::markdown());
+        let pages = man_pages().unwrap();
+        assert_eq!(pages[0], ("rnr.1".to_string(), man));
+        assert_eq!(pages, man_pages().unwrap());
+    }
+}
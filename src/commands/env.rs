@@ -0,0 +1,50 @@
+//! Show the environment rnr would set for a task
+
+use anyhow::{Context, Result};
+
+use crate::cli::EnvArgs;
+use crate::config::Config;
+use crate::env_report::EnvReport;
+use crate::project::Project;
+use crate::secrets::HiddenVars;
+use crate::ui::Ui;
+
+/// Run the env command
+pub fn run(args: &EnvArgs, project: &Project, ui: &Ui) -> Result<()> {
+    let config = project.validated_config()?;
+    ui.print(render(&config, &args.task, &args.args, ui)?);
+    Ok(())
+}
+
+/// The variables `task` would run with, given its command-line `args`
+fn render(config: &Config, task: &str, args: &[String], ui: &Ui) -> Result<String> {
+    let task_def = config
+        .get_task(task)
+        .with_context(|| format!("Task '{}' not found", task))?;
+    let bound = crate::args::bind(task, task_def, args, ui)?;
+    let report = EnvReport::new(task_def.env().as_ref(), None, &Default::default(), &bound);
+    Ok(format!(
+        "Environment rnr sets for '{}':\n\n{}",
+        task,
+        report.render(&HiddenVars::for_config(config))
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_hides_secrets() {
+        let config: Config = serde_yaml::from_str(
+            "secrets: [DB_URL]\ndeploy:\n  args:\n    - name: target\n  env:\n    DB_URL: postgres://u:p@db\n    DEPLOY_TOKEN: abc\n    REGION: eu\n  cmd: ./deploy.sh\n",
+        )
+        .unwrap();
+        let (ui, _) = Ui::capture(false);
+        assert_eq!(
+            render(&config, "deploy", &["staging".to_string()], &ui).unwrap(),
+            "Environment rnr sets for 'deploy':\n\n  task env:\n    DB_URL=***\n    DEPLOY_TOKEN=***\n    REGION=eu\n  arguments:\n    RNR_ARG_TARGET=staging\n"
+        );
+        assert!(render(&config, "nope", &[], &ui).is_err());
+    }
+}
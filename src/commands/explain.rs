@@ -0,0 +1,269 @@
+//! Explain what each step of a task would do, and why, without running it
+
+use anyhow::{Context, Result};
+use std::fmt::Write;
+use std::path::Path;
+
+use crate::auto_deps;
+use crate::checkout;
+use crate::cli::ExplainArgs;
+use crate::config::{Config, ConfigCache, TaskDef};
+use crate::guards::{self, GitState};
+use crate::plan::{self, GitGuarded, Plan};
+use crate::project::Project;
+use crate::step_filter::StepFilter;
+use crate::ui::Ui;
+
+/// Run the explain command
+pub fn run(args: &ExplainArgs, project: &Project, ui: &Ui) -> Result<()> {
+    let config = project.validated_config()?;
+    let root = project.root()?;
+    let task = config
+        .get_task(&args.task)
+        .with_context(|| format!("Task '{}' not found", args.task))?;
+    let bound = crate::args::bind(&args.task, task, &args.args, ui)?;
+    let plan = plan::build(
+        &args.task,
+        task,
+        root,
+        None,
+        &config,
+        &ConfigCache::default(),
+        &bound,
+        &StepFilter::default(),
+    )?;
+    let location = config.location(&args.task, None, root);
+    let mut gates = task_gates(task, &config, root);
+    gates.extend(producer_gates(&config, &args.task, root));
+    gates.extend(git_gates(&plan.git, root));
+    ui.print(render(&plan, location.as_deref(), &gates));
+    Ok(())
+}
+
+/// What decides how the task as a whole runs: its `checkout:`, and what
+/// runs if it fails
+fn task_gates(task: &TaskDef, config: &Config, root: &Path) -> Vec<String> {
+    let mut gates = checkout_gates(task, root);
+    if let Some(hook) = config.failure_hook(task) {
+        gates.push(format!("on_failure: runs {} if the task fails", hook));
+    }
+    gates
+}
+
+/// Where the task runs, if it has a `checkout:`
+fn checkout_gates(task: &TaskDef, root: &Path) -> Vec<String> {
+    let Some(spec) = task.checkout() else {
+        return Vec::new();
+    };
+    let reference = spec.reference.as_deref();
+    let commit = match checkout::resolve_commit(root, reference) {
+        Ok(commit) => commit,
+        Err(e) => return vec![format!("fail: {:#}", e)],
+    };
+    let mut gates = vec![format!(
+        "checkout: runs in a clean checkout of {} ({})",
+        &commit[..commit.len().min(12)],
+        reference.unwrap_or("HEAD")
+    )];
+    match checkout::dirty_files(root) {
+        Ok(dirty) if dirty.is_empty() => {}
+        Ok(dirty) => gates.push(format!(
+            "fail: uncommitted changes to {} won't be in the checkout (pass --allow-dirty to run anyway)",
+            dirty.join(", ")
+        )),
+        Err(e) => gates.push(format!("fail: {:#}", e)),
+    }
+    gates
+}
+
+/// The tasks that run first because the task's `sources` (or theirs) read
+/// their `outputs`, and whether each would run or is up to date
+fn producer_gates(config: &Config, task: &str, root: &Path) -> Vec<String> {
+    let (edges, order) = match auto_deps::resolve(config, task) {
+        Ok(resolved) => resolved,
+        Err(e) => return vec![format!("fail: {:#}", e)],
+    };
+    let mut gates: Vec<String> = edges
+        .iter()
+        .map(|edge| {
+            format!(
+                "needs: '{}' first, as its outputs '{}' overlap the sources '{}' of '{}'",
+                edge.producer, edge.output, edge.source, edge.task
+            )
+        })
+        .collect();
+    for name in order {
+        let Some(producer) = config.get_task(&name) else {
+            continue;
+        };
+        gates.push(match auto_deps::stale(root, &name, producer) {
+            Ok(Some(reason)) => format!("run: '{}' first: {}", name, reason),
+            Ok(None) => format!("skip: '{}' is up to date", name),
+            Err(e) => format!("fail: {:#}", e),
+        });
+    }
+    gates
+}
+
+/// The git state each task the run reaches needs, and whatever isn't so
+fn git_gates(guarded: &[GitGuarded], root: &Path) -> Vec<String> {
+    let mut state = GitState::default();
+    let mut gates = Vec::new();
+    for guarded in guarded {
+        gates.push(format!(
+            "requires: '{}' runs with {}",
+            guarded.task,
+            guards::describe_git(&guarded.requirements)
+        ));
+        if let Err(e) = state.read(root, &guarded.requirements) {
+            gates.push(format!("fail: {:#}", e));
+            continue;
+        }
+        for problem in state.problems(&guarded.task, &guarded.requirements) {
+            gates.push(format!(
+                "fail: {} (pass {} to run anyway)",
+                problem.message, problem.flag
+            ));
+        }
+    }
+    gates
+}
+
+/// Each planned action with the decision about it and the reasons, under a
+/// heading naming where the task is defined
+fn render(plan: &Plan, location: Option<&str>, gates: &[String]) -> String {
+    let mut out = format!("Why each step of '{}' ", plan.task);
+    if let Some(location) = location {
+        let _ = write!(out, "({}) ", location);
+    }
+    out.push_str("would run (nothing is run):\n\n");
+    if !gates.is_empty() {
+        let _ = writeln!(out, "{}", plan.task);
+        for gate in gates {
+            let _ = writeln!(out, "    {}", gate);
+        }
+    }
+    for entry in &plan.entries {
+        let _ = writeln!(out, "[{}] {}", entry.step, entry.action);
+        if entry.dir != "." {
+            let _ = writeln!(out, "    dir: {}", entry.dir);
+        }
+        for reason in &entry.why {
+            let _ = writeln!(out, "    {}", reason);
+        }
+    }
+    if plan.entries.is_empty() {
+        out.push_str("(no steps)\n");
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::args::TaskArgs;
+
+    fn explain(yaml: &str, task: &str) -> String {
+        explain_at(yaml, task, None)
+    }
+
+    fn explain_at(yaml: &str, task: &str, location: Option<&str>) -> String {
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        let task_def = config.get_task(task).unwrap();
+        let plan = plan::build(
+            task,
+            task_def,
+            Path::new("/project"),
+            None,
+            &config,
+            &ConfigCache::default(),
+            &TaskArgs::default(),
+            &StepFilter::default(),
+        )
+        .unwrap();
+        render(&plan, location, &[])
+    }
+
+    #[test]
+    fn test_explain_steps() {
+        let yaml = "ci:\n  steps:\n    - task: setup\n    - cmd: [cargo, test]\n      output_filter: cargo-json\n    - parallel:\n        - task: setup\n        - mkdir: dist\n    - task: nope\n    - cmd: echo ${args.missing}\n      dir: app\nsetup:\n  once: true\n  cmd: ./setup.sh\n";
+        assert_eq!(
+            explain(yaml, "ci"),
+            "Why each step of 'ci' would run (nothing is run):\n\n\
+             [ci/1/setup] $ ./setup.sh\n    \
+             run: command, run by the shell\n    \
+             once: true, first use of 'setup' in this run\n\
+             [ci/2] $ cargo test\n    \
+             run: command, run directly without a shell\n    \
+             output_filter: stdout passes through cargo-json (off with --verbose)\n\
+             [ci/3.1/setup] task setup (already ran)\n    \
+             skip: once: true, already ran at ci/1/setup\n\
+             [ci/3.2] mkdir dist\n    \
+             run: builtin step, handled by rnr itself\n\
+             [ci/4/nope] task nope (not found)\n    \
+             fail: no task named 'nope'\n\
+             [ci/5] $ echo ${args.missing}\n    \
+             dir: app\n    \
+             fail: Failed to interpolate command: echo ${args.missing}: \
+             Unresolved placeholder(s): ${args.missing}\n"
+        );
+    }
+
+    #[test]
+    fn test_explain_names_where_the_task_is() {
+        assert_eq!(
+            explain_at("lint: cargo clippy\n", "lint", Some("rnr.yaml:1")),
+            "Why each step of 'lint' (rnr.yaml:1) would run (nothing is run):\n\n\
+             [lint] $ cargo clippy\n    \
+             run: command, run by the shell\n"
+        );
+    }
+
+    #[test]
+    fn test_explain_task_gates() {
+        let config: Config =
+            serde_yaml::from_str("release:\n  checkout: clean\n  cmd: ./release.sh\n").unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        let gates = task_gates(config.get_task("release").unwrap(), &config, outside.path());
+        assert_eq!(gates.len(), 1);
+        assert!(
+            gates[0].starts_with("fail: 'checkout' needs the project to be in a git repository")
+        );
+    }
+
+    #[test]
+    fn test_explain_producers() {
+        let yaml = "build:\n  sources: ['src/*.c']\n  outputs: [app]\n  cmd: make\npackage:\n  sources: [app]\n  cmd: tar cf app.tar app\n";
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(
+            producer_gates(&config, "package", dir.path()),
+            [
+                "needs: 'build' first, as its outputs 'app' overlap the sources 'app' of 'package'",
+                "run: 'build' first: it hasn't run yet"
+            ]
+        );
+        std::fs::write(dir.path().join("app"), "").unwrap();
+        auto_deps::record(dir.path(), "build", config.get_task("build").unwrap()).unwrap();
+        assert_eq!(
+            producer_gates(&config, "package", dir.path())[1],
+            "skip: 'build' is up to date"
+        );
+        assert!(producer_gates(&config, "build", dir.path()).is_empty());
+    }
+
+    #[test]
+    fn test_explain_failure_hooks() {
+        let yaml = "hooks:\n  on_failure: { cmd: ./notify.sh }\ndeploy:\n  on_failure: page-oncall\n  cmd: ./deploy.sh\nbuild: make\npage-oncall: ./page.sh\n";
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        let gates = |task| task_gates(config.get_task(task).unwrap(), &config, Path::new("."));
+        assert_eq!(
+            gates("deploy"),
+            ["on_failure: runs task page-oncall if the task fails"]
+        );
+        assert_eq!(
+            gates("build"),
+            ["on_failure: runs $ ./notify.sh if the task fails"]
+        );
+    }
+}
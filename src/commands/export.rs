@@ -0,0 +1,545 @@
+//! Write shims that run rnr's tasks for tools that call make or npm
+//!
+//! While a repo moves to rnr, people and CI still run `make test` or
+//! `npm test`. `--format makefile` writes a Makefile with a `.PHONY` target
+//! per task that runs `./rnr <task>`. `--format npm` points the package.json
+//! script of each task at `./rnr <task>`, editing only those scripts and
+//! leaving the rest of the file as it was. `--check` fails when the shims
+//! are out of date. A task that only runs its own script or target, as
+//! `rnr import` writes them, keeps it: a shim would call the task back.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+
+use crate::cli::{ExportArgs, ExportFormat};
+use crate::config::{Config, TaskDef, CONFIG_FILE};
+use crate::plan;
+use crate::project::Project;
+use crate::runner::quote_arg;
+use crate::ui::Ui;
+
+/// First line of a Makefile `rnr export` writes, which it checks for before
+/// overwriting one
+const MAKEFILE_HEADER: &str =
+    "# Generated by `rnr export --format makefile` from rnr.yaml. Edit rnr.yaml";
+
+/// Characters a make target can't have, even escaped
+const MAKE_UNSAFE: &[char] = &[' ', '\t', '#', '%', '$', '=', ';', '\\', '"', '\''];
+
+/// Run the export command
+pub fn run(args: &ExportArgs, project: &Project, ui: &Ui) -> Result<()> {
+    let config = project.config()?;
+    let root = project.root()?;
+    let (tasks, looping): (Vec<&str>, Vec<&str>) = config
+        .task_names()
+        .into_iter()
+        .partition(|name| !calls_itself(&config, name, args.format));
+    let (file, current, updated, mut notes) = match args.format {
+        ExportFormat::Makefile => {
+            let path = root.join("Makefile");
+            let current = read_if_exists(&path)?;
+            if let Some(current) = &current {
+                if !args.force && !current.starts_with(MAKEFILE_HEADER) {
+                    anyhow::bail!(
+                        "Makefile wasn't written by rnr export; pass --force to replace it"
+                    );
+                }
+            }
+            let (makefile, skipped) = makefile(&config, &tasks);
+            let notes: Vec<String> = (!skipped.is_empty())
+                .then(|| {
+                    format!(
+                        "Left out tasks make can't name: {}; run them with ./rnr",
+                        skipped.join(", ")
+                    )
+                })
+                .into_iter()
+                .collect();
+            (path, current.unwrap_or_default(), makefile, notes)
+        }
+        ExportFormat::Npm => {
+            let path = root.join("package.json");
+            let current = read_if_exists(&path)?.with_context(|| {
+                format!(
+                    "No package.json in {}; create one with 'npm init' first",
+                    root.display()
+                )
+            })?;
+            let updated = npm_scripts(&current, &tasks)
+                .context("Failed to update the scripts in package.json")?;
+            (path, current, updated, Vec::new())
+        }
+    };
+    if !looping.is_empty() {
+        let what = match args.format {
+            ExportFormat::Makefile => "make target",
+            ExportFormat::Npm => "npm script",
+        };
+        notes.push(format!(
+            "Left out tasks that only run their own {}: {}; a shim would call them back",
+            what,
+            looping.join(", ")
+        ));
+    }
+    for note in &notes {
+        ui.warn(note);
+    }
+
+    let name = file_name(&file);
+    let command = match args.format {
+        ExportFormat::Makefile => "rnr export --format makefile",
+        ExportFormat::Npm => "rnr export --format npm",
+    };
+    if updated == current {
+        ui.line(format!("{} is in sync with {}.", name, CONFIG_FILE));
+        return Ok(());
+    }
+    if args.check {
+        let lines = |text: &str| text.lines().map(str::to_string).collect::<Vec<_>>();
+        plan::print_line_diff(
+            ui,
+            &name,
+            &format!("{} (from {})", name, CONFIG_FILE),
+            &plan::diff_lines(&lines(&current), &lines(&updated)),
+        );
+        anyhow::bail!(
+            "{} isn't in sync with {}; run '{}' to update it",
+            name,
+            CONFIG_FILE,
+            command
+        );
+    }
+    fs::write(&file, &updated).with_context(|| format!("Failed to write {}", file.display()))?;
+    ui.line(format!("Wrote {} from {}.", name, CONFIG_FILE));
+    Ok(())
+}
+
+/// The contents of `path`, or nothing if there's no such file
+fn read_if_exists(path: &Path) -> Result<Option<String>> {
+    match fs::read_to_string(path) {
+        Ok(content) => Ok(Some(content)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// The command a shim runs for `task`
+fn shim(task: &str) -> String {
+    format!("./rnr {}", quote_arg(task))
+}
+
+/// Whether task `name` only runs the npm script or make target of the same
+/// name, as `rnr import` writes tasks, so the shim `format` would put there
+/// would run the task again
+fn calls_itself(config: &Config, name: &str, format: ExportFormat) -> bool {
+    let command = match config.get_task(name) {
+        Some(TaskDef::Shorthand(cmd)) => cmd.clone(),
+        Some(TaskDef::Full(task)) if task.steps.is_none() => match &task.cmd {
+            Some(cmd) => cmd.to_string(),
+            None => return false,
+        },
+        _ => return false,
+    };
+    let words: Vec<&str> = command.split_whitespace().collect();
+    let named = |word: &str| word.trim_matches(['\'', '"']) == name;
+    match (format, words.as_slice()) {
+        (ExportFormat::Npm, ["npm", "run" | "run-script", script]) => named(script),
+        // npm's own shortcuts for these scripts
+        (ExportFormat::Npm, ["npm", script @ ("test" | "start" | "stop" | "restart")]) => {
+            named(script)
+        }
+        (ExportFormat::Makefile, ["make", target]) => named(target),
+        _ => false,
+    }
+}
+
+/// A Makefile with a target per one of `tasks` of `config`, and the tasks
+/// make can't name
+fn makefile<'t>(config: &Config, tasks: &[&'t str]) -> (String, Vec<&'t str>) {
+    let (tasks, skipped): (Vec<&str>, Vec<&str>) =
+        tasks.iter().partition(|name| !name.contains(MAKE_UNSAFE));
+    // Colons are escaped, so `docker:build` is one target
+    let targets: Vec<String> = tasks.iter().map(|name| name.replace(':', "\\:")).collect();
+
+    let mut out = format!(
+        "{}\n# and run it again instead of changing this file.\n",
+        MAKEFILE_HEADER
+    );
+    if !targets.is_empty() {
+        out.push_str(&format!("\n.PHONY: {}\n", targets.join(" ")));
+    }
+    for (name, target) in tasks.iter().zip(&targets) {
+        out.push('\n');
+        let description = config.get_task(name).and_then(|task| task.description());
+        if let Some(description) = description {
+            for line in description.lines() {
+                out.push_str(format!("# {}", line).trim_end());
+                out.push('\n');
+            }
+        }
+        out.push_str(&format!("{}:\n\t{}\n", target, shim(name)));
+    }
+    (out, skipped)
+}
+
+/// `package` with the script of each of `tasks` running it with rnr. Other
+/// scripts are kept, except shims for tasks that are gone, and nothing
+/// outside `scripts` changes.
+fn npm_scripts(package: &str, tasks: &[&str]) -> Result<String> {
+    let parsed: serde_json::Value =
+        serde_json::from_str(package).context("package.json isn't valid JSON")?;
+    if !parsed.is_object() {
+        anyhow::bail!("package.json isn't a JSON object");
+    }
+    let root = Object::scan(package, package.find('{').unwrap_or_default())?;
+    let compact = !package.trim().contains('\n');
+
+    let Some(scripts) = root.members.iter().find(|member| member.key == "scripts") else {
+        // No scripts yet, so they go at the end of the package
+        let indent = root.indent(package, "  ");
+        let inner = format!("{0}{0}", indent);
+        let entries: Vec<String> = tasks.iter().map(|task| entry(task, compact)).collect();
+        let colon = if compact { ":" } else { ": " };
+        let member = format!(
+            "\"scripts\"{}{}",
+            colon,
+            layout(&entries, &inner, &indent, compact)
+        );
+        let mut updated = package.to_string();
+        match root.members.last() {
+            Some(last) => updated.insert_str(
+                last.value.end,
+                &format!("{}{}", root.separator(package, compact), member),
+            ),
+            None => {
+                updated.replace_range(root.span.clone(), &layout(&[member], &indent, "", compact))
+            }
+        }
+        return Ok(updated);
+    };
+    if !package[scripts.value.clone()].starts_with('{') {
+        anyhow::bail!("its scripts aren't an object");
+    }
+    let object = Object::scan(package, scripts.value.start)?;
+    let mut entries = Vec::new();
+    for member in &object.members {
+        let value = &package[member.value.clone()];
+        let is_task = tasks.contains(&member.key.as_str());
+        let stale_shim =
+            serde_json::from_str::<String>(value).is_ok_and(|value| value == shim(&member.key));
+        if is_task {
+            entries.push(format!(
+                "{}{}",
+                &package[member.key_span.start..member.value.start],
+                json_string(&shim(&member.key))
+            ));
+        } else if !stale_shim {
+            entries.push(package[member.key_span.start..member.value.end].to_string());
+        }
+    }
+    for task in tasks {
+        if !object.members.iter().any(|member| member.key == *task) {
+            entries.push(entry(task, compact));
+        }
+    }
+
+    let rendered = match (object.members.first(), object.members.last()) {
+        // Kept as laid out: up to the first member, between them, and after
+        (Some(first), Some(last)) if !entries.is_empty() => format!(
+            "{{{}{}{}}}",
+            &package[object.span.start + 1..first.key_span.start],
+            entries.join(&object.separator(package, compact)),
+            &package[last.value.end..object.span.end - 1]
+        ),
+        _ => {
+            let outer = scripts.indent(package);
+            let indent = format!("{}{}", outer, root.indent(package, "  "));
+            layout(&entries, &indent, &outer, compact)
+        }
+    };
+    let mut updated = package.to_string();
+    updated.replace_range(object.span.clone(), &rendered);
+    serde_json::from_str::<serde_json::Value>(&updated)
+        .context("Updating the scripts would leave package.json invalid")?;
+    Ok(updated)
+}
+
+/// A new `"task": "./rnr task"` script
+fn entry(task: &str, compact: bool) -> String {
+    let colon = if compact { ":" } else { ": " };
+    format!("{}{}{}", json_string(task), colon, json_string(&shim(task)))
+}
+
+fn json_string(text: &str) -> String {
+    serde_json::Value::String(text.to_string()).to_string()
+}
+
+/// A JSON object of `entries`, a line each indented by `indent` and closed
+/// at `outer`, or all on one line if `compact`
+fn layout(entries: &[String], indent: &str, outer: &str, compact: bool) -> String {
+    if entries.is_empty() {
+        return "{}".to_string();
+    }
+    if compact {
+        return format!("{{{}}}", entries.join(","));
+    }
+    format!(
+        "{{\n{}{}\n{}}}",
+        indent,
+        entries.join(&format!(",\n{}", indent)),
+        outer
+    )
+}
+
+/// A member of a JSON object, by where its parts are in the text
+#[derive(Debug)]
+struct Member {
+    key: String,
+    /// The quoted key
+    key_span: Range<usize>,
+    value: Range<usize>,
+}
+
+impl Member {
+    /// The whitespace before the member on its line, if it starts the line
+    fn indent(&self, text: &str) -> String {
+        let before = &text[line_start(text, self.key_span.start)..self.key_span.start];
+        if before.trim().is_empty() {
+            before.to_string()
+        } else {
+            String::new()
+        }
+    }
+}
+
+/// A JSON object's members, found without changing a byte of it
+#[derive(Debug)]
+struct Object {
+    /// From `{` to just past `}`
+    span: Range<usize>,
+    members: Vec<Member>,
+}
+
+impl Object {
+    /// The object starting at the `{` at `start` of `text`, which is valid JSON
+    fn scan(text: &str, start: usize) -> Result<Object> {
+        let bytes = text.as_bytes();
+        let mut members = Vec::new();
+        let mut i = skip_space(bytes, start + 1);
+        while bytes.get(i) == Some(&b'"') {
+            let key_end = value_end(bytes, i)?;
+            let key: String = serde_json::from_str(&text[i..key_end])?;
+            let colon = skip_space(bytes, key_end);
+            let value_start = skip_space(bytes, colon + 1);
+            let end = value_end(bytes, value_start)?;
+            members.push(Member {
+                key,
+                key_span: i..key_end,
+                value: value_start..end,
+            });
+            i = skip_space(bytes, end);
+            if bytes.get(i) == Some(&b',') {
+                i = skip_space(bytes, i + 1);
+            }
+        }
+        if bytes.get(i) != Some(&b'}') {
+            anyhow::bail!("expected '}}' at byte {}", i);
+        }
+        Ok(Object {
+            span: start..i + 1,
+            members,
+        })
+    }
+
+    /// The indent of the object's members, `fallback` if they share a line
+    fn indent(&self, text: &str, fallback: &str) -> String {
+        self.members
+            .first()
+            .map(|first| first.indent(text))
+            .filter(|indent| !indent.is_empty())
+            .unwrap_or_else(|| fallback.to_string())
+    }
+
+    /// What goes between two members: what's between the first two, or a
+    /// comma and a new line
+    fn separator(&self, text: &str, compact: bool) -> String {
+        match self.members.as_slice() {
+            [first, second, ..] => text[first.value.end..second.key_span.start].to_string(),
+            [only, ..] if !compact => format!(",\n{}", only.indent(text)),
+            _ => ",".to_string(),
+        }
+    }
+}
+
+/// Where the line holding byte `at` of `text` starts
+fn line_start(text: &str, at: usize) -> usize {
+    text[..at].rfind('\n').map_or(0, |newline| newline + 1)
+}
+
+fn skip_space(bytes: &[u8], mut i: usize) -> usize {
+    while bytes.get(i).is_some_and(u8::is_ascii_whitespace) {
+        i += 1;
+    }
+    i
+}
+
+/// Just past the JSON value starting at `start`
+fn value_end(bytes: &[u8], start: usize) -> Result<usize> {
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut i = start;
+    while let Some(&byte) = bytes.get(i) {
+        i += 1;
+        if in_string {
+            match byte {
+                b'\\' => i += 1,
+                b'"' => {
+                    in_string = false;
+                    if depth == 0 {
+                        return Ok(i);
+                    }
+                }
+                _ => {}
+            }
+            continue;
+        }
+        match byte {
+            b'"' => in_string = true,
+            b'{' | b'[' => depth += 1,
+            b'}' | b']' if depth > 0 => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(i);
+                }
+            }
+            b',' | b'}' | b']' | b' ' | b'\t' | b'\r' | b'\n' if depth == 0 => return Ok(i - 1),
+            _ => {}
+        }
+    }
+    if depth == 0 && !in_string {
+        return Ok(i);
+    }
+    anyhow::bail!("unterminated JSON value at byte {}", start)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(yaml: &str) -> Config {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn test_makefile_targets_run_rnr() {
+        let config = config(
+            "test:\n  description: Run the tests\n  cmd: cargo test\nbuild: cargo build\n\
+             docker:build: docker build .\nbuild all: make\n",
+        );
+        let (makefile, skipped) = makefile(&config, &config.task_names());
+        assert_eq!(
+            makefile,
+            "# Generated by `rnr export --format makefile` from rnr.yaml. Edit rnr.yaml\n\
+             # and run it again instead of changing this file.\n\
+             \n\
+             .PHONY: build docker\\:build test\n\
+             \n\
+             build:\n\t./rnr build\n\
+             \n\
+             docker\\:build:\n\t./rnr docker:build\n\
+             \n\
+             # Run the tests\n\
+             test:\n\t./rnr test\n"
+        );
+        assert_eq!(skipped, ["build all"]);
+    }
+
+    const PACKAGE: &str = r#"{
+  "name": "web",
+  "version": "1.0.0",
+  "scripts": {
+    "test":   "vitest run",
+    "lint": "./rnr lint",
+    "dev": "vite"
+  },
+  "devDependencies": { "vite": "^5.0.0" }
+}
+"#;
+
+    #[test]
+    fn test_npm_scripts_only_touch_task_keys() {
+        let updated = npm_scripts(PACKAGE, &["build", "test"]).unwrap();
+        assert_eq!(
+            updated,
+            r#"{
+  "name": "web",
+  "version": "1.0.0",
+  "scripts": {
+    "test":   "./rnr test",
+    "dev": "vite",
+    "build": "./rnr build"
+  },
+  "devDependencies": { "vite": "^5.0.0" }
+}
+"#
+        );
+        // Stable once written
+        assert_eq!(npm_scripts(&updated, &["build", "test"]).unwrap(), updated);
+    }
+
+    #[test]
+    fn test_npm_scripts_are_added_where_missing() {
+        assert_eq!(
+            npm_scripts("{\n    \"name\": \"web\"\n}\n", &["build", "test"]).unwrap(),
+            "{\n    \"name\": \"web\",\n    \"scripts\": {\n        \"build\": \"./rnr build\",\n        \"test\": \"./rnr test\"\n    }\n}\n"
+        );
+        assert_eq!(
+            npm_scripts(r#"{"name":"web","scripts":{}}"#, &["test"]).unwrap(),
+            r#"{"name":"web","scripts":{"test":"./rnr test"}}"#
+        );
+        assert_eq!(
+            npm_scripts(r#"{"scripts": {"lint": "./rnr lint"}}"#, &[]).unwrap(),
+            r#"{"scripts": {}}"#
+        );
+        assert!(npm_scripts("{\"name\": ", &["test"]).is_err());
+        assert!(npm_scripts(r#"{"scripts": "x"}"#, &["test"]).is_err());
+    }
+
+    #[test]
+    fn test_tasks_running_their_own_script_aren_t_shimmed() {
+        let config = config(
+            "test: npm run test\nstart: npm start\nlint:\n  cmd: npm run-script 'lint'\n\
+             all: make all\nbuild: npm run compile\ndocs:\n  cmd: npm run docs\n  steps:\n    - cmd: x\n",
+        );
+        let looping = |format| {
+            config
+                .task_names()
+                .into_iter()
+                .filter(|name| calls_itself(&config, name, format))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(looping(ExportFormat::Npm), ["lint", "start", "test"]);
+        assert_eq!(looping(ExportFormat::Makefile), ["all"]);
+    }
+
+    #[test]
+    fn test_scanning_keeps_strings_and_nesting_whole() {
+        let text = r#"{"a": "x}\"y", "b": [1, {"c": 2}], "d": true}"#;
+        let object = Object::scan(text, 0).unwrap();
+        let values: Vec<&str> = object
+            .members
+            .iter()
+            .map(|member| &text[member.value.clone()])
+            .collect();
+        assert_eq!(values, [r#""x}\"y""#, r#"[1, {"c": 2}]"#, "true"]);
+        assert_eq!(object.span, 0..text.len());
+    }
+}
@@ -0,0 +1,65 @@
+//! Rewrite rnr.yaml in the canonical layout, or check that it is
+
+use anyhow::{Context, Result};
+use std::fs;
+
+use crate::cli::FmtArgs;
+use crate::config::CONFIG_FILE;
+use crate::formatter;
+use crate::project::Project;
+use crate::ui::Ui;
+
+/// Run the fmt command
+pub fn run(args: &FmtArgs, project: &Project, ui: &Ui) -> Result<()> {
+    let path = project.config_path()?;
+    // Only a file rnr can load is formatted, so its errors come first
+    project.config()?;
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let mut formatted =
+        formatter::format(&content).with_context(|| format!("Can't format {}", CONFIG_FILE))?;
+    if content.contains("\r\n") {
+        formatted = formatted.replace('\n', "\r\n");
+    }
+
+    if formatted == content {
+        ui.line(format!("{} is already formatted.", CONFIG_FILE));
+        return Ok(());
+    }
+    if args.check {
+        anyhow::bail!(
+            "{} isn't formatted (first difference on line {}); run 'rnr fmt' to fix it",
+            CONFIG_FILE,
+            first_difference(&content, &formatted)
+        );
+    }
+    fs::write(path, formatted).with_context(|| format!("Failed to write {}", path.display()))?;
+    ui.line(format!("Formatted {}.", CONFIG_FILE));
+    Ok(())
+}
+
+/// The 1-based line where `a` and `b` first differ
+fn first_difference(a: &str, b: &str) -> usize {
+    let mut a_lines = a.lines();
+    let mut b_lines = b.lines();
+    let mut line = 1;
+    while let (Some(x), Some(y)) = (a_lines.next(), b_lines.next()) {
+        if x != y {
+            return line;
+        }
+        line += 1;
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_first_difference() {
+        assert_eq!(first_difference("a\nb\nc\n", "a\nx\nc\n"), 2);
+        assert_eq!(first_difference("a\n", "a\nb\n"), 2);
+        assert_eq!(first_difference("a\n", "b\n"), 1);
+    }
+}
@@ -0,0 +1,240 @@
+//! Show help for a task
+
+use anyhow::{Context, Result};
+use clap::CommandFactory;
+use std::fmt::Write;
+
+use crate::choose;
+use crate::cli::{Cli, HelpArgs};
+use crate::config::TaskDef;
+use crate::project::Project;
+use crate::ui::Ui;
+
+/// Run the help command
+pub fn run(args: &HelpArgs, project: &Project, ui: &Ui) -> Result<()> {
+    let Some(task_name) = &args.task else {
+        let help = Cli::command().render_help();
+        if ui.color() {
+            ui.print(help.ansi());
+        } else {
+            ui.print(help);
+        }
+        return Ok(());
+    };
+
+    let config = project.config()?;
+    let task = config
+        .get_task(task_name)
+        .with_context(|| format!("Task '{}' not found", task_name))?;
+
+    ui.print(render(task_name, task));
+    Ok(())
+}
+
+/// The usage line for a task, generated from its declared args unless set explicitly
+pub fn usage_line(name: &str, task: &TaskDef) -> String {
+    if let TaskDef::Full(full) = task {
+        if let Some(usage) = &full.usage {
+            return usage.clone();
+        }
+    }
+
+    let mut usage = format!("rnr {}", name);
+    for arg in task.arg_defs() {
+        if arg.required {
+            let _ = write!(usage, " <{}>", arg.name);
+        } else {
+            let _ = write!(usage, " [{}]", arg.name);
+        }
+    }
+    if matches!(task, TaskDef::Full(full) if full.choose.is_some()) {
+        usage.push_str(" [option]");
+    }
+    usage
+}
+
+/// Render the full help text for a task
+pub fn render(name: &str, task: &TaskDef) -> String {
+    let mut out = String::new();
+
+    let full = match task {
+        TaskDef::Shorthand(cmd) => {
+            let _ = writeln!(out, "{}\n", name);
+            let _ = writeln!(out, "Usage: {}\n", usage_line(name, task));
+            let _ = writeln!(out, "Runs: {}", cmd);
+            return out;
+        }
+        TaskDef::Full(full) => full,
+    };
+
+    match &full.description {
+        Some(desc) => {
+            let _ = writeln!(out, "{} - {}\n", name, desc);
+        }
+        None => {
+            let _ = writeln!(out, "{}\n", name);
+        }
+    }
+
+    let _ = writeln!(out, "Usage: {}", usage_line(name, task));
+
+    if let Some(long) = &full.long_description {
+        let _ = writeln!(out, "\n{}", long.trim_end());
+    }
+
+    let args = task.arg_defs();
+    if !args.is_empty() {
+        let width = args
+            .iter()
+            .map(|a| a.name.chars().count())
+            .max()
+            .unwrap_or(0);
+        let _ = writeln!(out, "\nArguments:");
+        for arg in args {
+            // Whatever follows the name starts in the same column, with or
+            // without a description
+            let mut details: Vec<String> = arg.description.iter().cloned().collect();
+            if arg.required {
+                details.push("(required)".to_string());
+            }
+            if let Some(choices) = &arg.choices {
+                details.push(format!("[choices: {}]", choices.join(", ")));
+            }
+            if let Some(default) = &arg.default {
+                details.push(format!("[default: {}]", default));
+            }
+            let line = format!("  {:<width$}  {}", arg.name, details.join(" "));
+            let _ = writeln!(out, "{}", line.trim_end());
+        }
+    }
+
+    if let Some(choose) = &full.choose {
+        let mut menu = choose::menu(choose).into_iter();
+        let prompt = menu.next().unwrap_or_default();
+        let _ = writeln!(out, "\nChoices: {}", prompt);
+        for line in menu {
+            let _ = writeln!(out, "{}", line);
+        }
+    }
+
+    if let Some(env) = &full.env {
+        if !env.is_empty() {
+            let mut keys: Vec<_> = env.keys().collect();
+            keys.sort();
+            let _ = writeln!(out, "\nEnvironment:");
+            for key in keys {
+                let _ = writeln!(out, "  {}={}", key, env[key]);
+            }
+        }
+    }
+
+    if let Some(examples) = &full.examples {
+        if !examples.is_empty() {
+            let _ = writeln!(out, "\nExamples:");
+            for example in examples {
+                let _ = writeln!(out, "  {}", example);
+            }
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(yaml: &str) -> TaskDef {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn test_generated_usage_line() {
+        let deploy = task(
+            r#"
+args:
+  - name: environment
+    required: true
+  - name: version
+cmd: ./deploy.sh
+"#,
+        );
+        assert_eq!(
+            usage_line("deploy", &deploy),
+            "rnr deploy <environment> [version]"
+        );
+    }
+
+    #[test]
+    fn test_explicit_usage_line() {
+        let deploy = task("usage: rnr deploy ENV\ncmd: ./deploy.sh");
+        assert_eq!(usage_line("deploy", &deploy), "rnr deploy ENV");
+    }
+
+    #[test]
+    fn test_render_full_help() {
+        let deploy = task(
+            r#"
+description: Deploy a version
+long_description: Deploys to the chosen environment.
+args:
+  - name: environment
+    required: true
+    description: Target environment
+  - name: version
+    description: Version to deploy
+    default: latest
+env:
+  REGION: us-east-1
+examples:
+  - rnr deploy staging
+cmd: ./deploy.sh
+"#,
+        );
+        let help = render("deploy", &deploy);
+        assert_eq!(
+            help,
+            "deploy - Deploy a version\n\
+             \n\
+             Usage: rnr deploy <environment> [version]\n\
+             \n\
+             Deploys to the chosen environment.\n\
+             \n\
+             Arguments:\n  \
+             environment  Target environment (required)\n  \
+             version      Version to deploy [default: latest]\n\
+             \n\
+             Environment:\n  \
+             REGION=us-east-1\n\
+             \n\
+             Examples:\n  \
+             rnr deploy staging\n"
+        );
+    }
+
+    #[test]
+    fn test_render_shorthand_help() {
+        let build = task("cargo build");
+        assert_eq!(
+            render("build", &build),
+            "build\n\nUsage: rnr build\n\nRuns: cargo build\n"
+        );
+    }
+
+    #[test]
+    fn test_render_choose_help() {
+        let setup = task(
+            "description: Set up a checkout\nchoose:\n  prompt: What do you want to set up?\n  options:\n    frontend: setup-frontend\n    all: setup-all\n",
+        );
+        assert_eq!(
+            render("setup", &setup),
+            "setup - Set up a checkout\n\
+             \n\
+             Usage: rnr setup [option]\n\
+             \n\
+             Choices: What do you want to set up?\n  \
+             frontend  -> setup-frontend\n  \
+             all       -> setup-all\n"
+        );
+    }
+}
@@ -0,0 +1,768 @@
+//! Add tasks to rnr.yaml from a package.json's scripts or a Makefile's targets
+//!
+//! Each script or target becomes a task that runs it the way it's run now,
+//! `npm run <name>` or `make <target>`, or with `--inline` a task with the
+//! commands themselves. The tasks are merged into rnr.yaml the way `rnr add`
+//! adds one, leaving the rest of the file as it was, and the change is shown
+//! as a diff before it's written.
+
+use anyhow::{Context, Result};
+use serde::de::{self, Deserializer, MapAccess, Visitor};
+use serde::Deserialize;
+use serde_yaml::{Mapping, Value};
+use std::fmt;
+use std::fs;
+use std::path::{Component, Path};
+
+use crate::cli::{self, ImportArgs};
+use crate::commands::add::{content_has_task, put_task, quote_plain};
+use crate::commands::list;
+use crate::config::{Config, CONFIG_FILE, SETTINGS};
+use crate::formatter;
+use crate::plan;
+use crate::project::Project;
+use crate::runner::quote_arg;
+use crate::ui::Ui;
+
+/// Makefile names `make` finds without `-f`
+const MAKEFILES: &[&str] = &["GNUmakefile", "makefile", "Makefile"];
+
+/// What an imported task runs
+#[derive(Debug, Clone, PartialEq)]
+enum Run {
+    /// One command, written as a shorthand task
+    Cmd(String),
+    /// Steps, in order
+    Steps(Vec<Step>),
+}
+
+/// A step of an imported task
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+    Cmd(String),
+    Task(String),
+}
+
+/// Tasks read from a package.json or Makefile, and what to tell about them
+#[derive(Debug, Default)]
+struct Import {
+    tasks: Vec<(String, Run)>,
+    notes: Vec<String>,
+}
+
+/// Run the import command
+pub fn run(args: &ImportArgs, project: &Project, ui: &Ui) -> Result<()> {
+    let path = project.config_path()?;
+    // Only a file rnr can load is edited, so its errors come first
+    project.config()?;
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+
+    let source = &args.file;
+    let text = fs::read_to_string(source)
+        .with_context(|| format!("Failed to read {}", source.display()))?;
+    let file_name = source
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+    let mut import = if file_name.ends_with(".json") {
+        from_package_json(&text, args.inline)
+            .with_context(|| format!("Failed to read the scripts in {}", source.display()))?
+    } else if MAKEFILES.contains(&file_name) || file_name.ends_with(".mk") {
+        let make = if MAKEFILES.contains(&file_name) {
+            "make".to_string()
+        } else {
+            format!("make -f {}", quote_arg(file_name))
+        };
+        from_makefile(&text, &make, args.inline)
+    } else {
+        anyhow::bail!(
+            "Can't tell what {} is; rnr imports a package.json or a Makefile",
+            source.display()
+        );
+    };
+    let dir = source_dir(source, project.root()?)?;
+
+    let merged = merge(&content, &import.tasks, dir.as_deref(), args.force)?;
+    import.notes.extend(merged.notes);
+    for note in &import.notes {
+        ui.warn(note);
+    }
+    if merged.names.is_empty() || merged.content == content {
+        ui.line(format!("Nothing to import into {}", CONFIG_FILE));
+        return Ok(());
+    }
+
+    let lines = |text: &str| text.lines().map(str::to_string).collect::<Vec<_>>();
+    plan::print_line_diff(
+        ui,
+        CONFIG_FILE,
+        &format!("{} (imported from {})", CONFIG_FILE, file_name),
+        &plan::diff_lines(&lines(&content), &lines(&merged.content)),
+    );
+    if args.dry_run {
+        ui.line(format!(
+            "Would import {} task(s) from {}; nothing was written (--dry-run)",
+            merged.names.len(),
+            file_name
+        ));
+        return Ok(());
+    }
+    fs::write(path, &merged.content)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    ui.line(format!(
+        "Imported {} task(s) from {} into {}",
+        merged.names.len(),
+        file_name,
+        CONFIG_FILE
+    ));
+    let names: Vec<&str> = merged.names.iter().map(String::as_str).collect();
+    for name in cli::shadowed_tasks(&names) {
+        ui.warn(list::shadowed_warning(name));
+    }
+    Ok(())
+}
+
+/// The directory of `source` relative to `root`, if it isn't `root` itself,
+/// for the imported tasks to run in
+fn source_dir(source: &Path, root: &Path) -> Result<Option<String>> {
+    let canonical = |path: &Path| {
+        path.canonicalize()
+            .with_context(|| format!("Failed to resolve {}", path.display()))
+    };
+    let file = canonical(source)?;
+    let dir = file.parent().unwrap_or(&file);
+    let relative = dir.strip_prefix(canonical(root)?).map_err(|_| {
+        anyhow::anyhow!(
+            "{} is outside the project at {}",
+            source.display(),
+            root.display()
+        )
+    })?;
+    let parts: Vec<String> = relative
+        .components()
+        .filter_map(|part| match part {
+            Component::Normal(part) => Some(part.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect();
+    Ok((!parts.is_empty()).then(|| parts.join("/")))
+}
+
+/// The tasks for a package.json's `scripts`. npm runs `prebuild` and
+/// `postbuild` around `build`, so hooks go with the script they belong to.
+fn from_package_json(text: &str, inline: bool) -> Result<Import> {
+    let package: Package = serde_json::from_str(text)?;
+    let scripts = package.scripts.map(|scripts| scripts.0).unwrap_or_default();
+    if scripts.is_empty() {
+        anyhow::bail!("it has no scripts");
+    }
+    let script = |name: &str| {
+        scripts
+            .iter()
+            .find(|(script, _)| script == name)
+            .map(|(_, command)| command.clone())
+    };
+    let is_hook = |name: &str| {
+        ["pre", "post"].into_iter().any(|prefix| {
+            name.strip_prefix(prefix)
+                .is_some_and(|base| !base.is_empty() && script(base).is_some())
+        })
+    };
+
+    let mut import = Import::default();
+    let mut hooks = Vec::new();
+    for (name, command) in &scripts {
+        if is_hook(name) {
+            hooks.push(name.as_str());
+            continue;
+        }
+        let run = if inline {
+            let pre = script(&format!("pre{}", name));
+            let post = script(&format!("post{}", name));
+            if pre.is_none() && post.is_none() {
+                Run::Cmd(command.clone())
+            } else {
+                let commands = pre.into_iter().chain([command.clone()]).chain(post);
+                Run::Steps(commands.map(Step::Cmd).collect())
+            }
+        } else {
+            Run::Cmd(format!("npm run {}", quote_arg(name)))
+        };
+        import.tasks.push((name.clone(), run));
+    }
+    if !hooks.is_empty() {
+        import.notes.push(if inline {
+            format!(
+                "npm hooks {} became steps of the scripts they run around, not tasks",
+                hooks.join(", ")
+            )
+        } else {
+            format!(
+                "npm hooks {} aren't tasks; npm run still runs them around their scripts",
+                hooks.join(", ")
+            )
+        });
+    }
+    if inline {
+        import.notes.push(
+            "Inlined scripts run without node_modules/.bin on PATH, which npm run adds; \
+             use npx for tools installed there"
+                .to_string(),
+        );
+    }
+    Ok(import)
+}
+
+/// The parts of a package.json rnr reads
+#[derive(Deserialize)]
+struct Package {
+    scripts: Option<Scripts>,
+}
+
+/// A package.json's scripts, in the order they're written
+struct Scripts(Vec<(String, String)>);
+
+impl<'de> Deserialize<'de> for Scripts {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ScriptsVisitor;
+
+        impl<'de> Visitor<'de> for ScriptsVisitor {
+            type Value = Scripts;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a map of script names to commands")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Scripts, A::Error> {
+                let mut scripts = Vec::new();
+                while let Some((name, command)) = map.next_entry::<String, String>()? {
+                    scripts.push((name, command));
+                }
+                Ok(Scripts(scripts))
+            }
+        }
+
+        deserializer
+            .deserialize_map(ScriptsVisitor)
+            .map_err(de::Error::custom)
+    }
+}
+
+/// A rule of a Makefile
+#[derive(Debug, Default, PartialEq)]
+struct Rule {
+    target: String,
+    prerequisites: Vec<String>,
+    recipe: Vec<String>,
+}
+
+/// The tasks for a Makefile's targets, run with `make` (the command that
+/// runs the Makefile). Pattern rules, special targets like `.PHONY`, and
+/// targets named by variables aren't tasks, and variables aren't imported.
+fn from_makefile(text: &str, make: &str, inline: bool) -> Import {
+    let (rules, variables) = parse_makefile(text);
+    let mut import = Import::default();
+    let mut paths = Vec::new();
+    let targets: Vec<&Rule> = rules
+        .iter()
+        .filter(|rule| {
+            let target = &rule.target;
+            if target.contains('/') {
+                paths.push(target.as_str());
+            }
+            !target.starts_with('.') && !target.contains(['%', '$', '/'])
+        })
+        .collect();
+    let imported = |name: &str| targets.iter().any(|rule| rule.target == name);
+
+    for rule in &targets {
+        let by_make = Run::Cmd(format!("{} {}", make, quote_arg(&rule.target)));
+        let run = if inline {
+            inline_rule(rule, &imported).unwrap_or_else(|reason| {
+                import
+                    .notes
+                    .push(format!("'{}' runs {}, since {}", rule.target, make, reason));
+                by_make
+            })
+        } else {
+            by_make
+        };
+        import.tasks.push((rule.target.clone(), run));
+    }
+    if !paths.is_empty() {
+        import.notes.push(format!(
+            "Skipped targets that are paths, which rnr reads as task files: {}",
+            paths.join(", ")
+        ));
+    }
+    if !variables.is_empty() {
+        import.notes.push(format!(
+            "Skipped Makefile variables {}; make still sets them for the tasks that run it",
+            variables.join(", ")
+        ));
+    }
+    import
+}
+
+/// `rule` as steps: its prerequisites that are `imported` targets, then its
+/// recipe. The reason it can't be, if it can't.
+fn inline_rule(rule: &Rule, imported: &dyn Fn(&str) -> bool) -> Result<Run, &'static str> {
+    let mut steps: Vec<Step> = rule
+        .prerequisites
+        .iter()
+        .filter(|name| **name != rule.target && imported(name))
+        .map(|name| Step::Task(name.clone()))
+        .collect();
+    for line in &rule.recipe {
+        let command = line.trim_start_matches(['@', '+', ' ', '\t']);
+        if command.starts_with('-') {
+            return Err("its recipe ignores errors with '-'");
+        }
+        let unescaped = command.replace("$$", "");
+        if unescaped.contains('$') {
+            return Err("its recipe uses make variables");
+        }
+        steps.push(Step::Cmd(command.replace("$$", "$")));
+    }
+    match steps.as_slice() {
+        [] => Err("it has no recipe"),
+        [Step::Cmd(command)] => Ok(Run::Cmd(command.clone())),
+        _ => Ok(Run::Steps(steps)),
+    }
+}
+
+/// The rules of a Makefile, a target at a time in the order each target
+/// first appears, and the names of its variables
+fn parse_makefile(text: &str) -> (Vec<Rule>, Vec<String>) {
+    let mut rules: Vec<Rule> = Vec::new();
+    let mut variables = Vec::new();
+    // Rules the recipe lines being read belong to
+    let mut current: Vec<usize> = Vec::new();
+    let mut in_define = false;
+
+    for line in logical_lines(text) {
+        if in_define {
+            in_define = line.trim() != "endef";
+            continue;
+        }
+        if let Some(recipe) = line.strip_prefix('\t') {
+            for &index in &current {
+                rules[index].recipe.push(recipe.trim().to_string());
+            }
+            continue;
+        }
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+        current.clear();
+        let mut words = line.split_whitespace();
+        let first = words.next().unwrap_or_default();
+        let statement = match first {
+            "include" | "-include" | "sinclude" | "ifeq" | "ifneq" | "ifdef" | "ifndef"
+            | "else" | "endif" | "vpath" | "unexport" => continue,
+            "define" => {
+                in_define = true;
+                variables.extend(words.next().map(str::to_string));
+                continue;
+            }
+            "export" | "override" => line[first.len()..].trim_start(),
+            _ => line,
+        };
+        if let Some(name) = assigned_variable(statement) {
+            variables.push(name);
+            continue;
+        }
+        let Some((targets, rest)) = statement.split_once(':') else {
+            continue;
+        };
+        let rest = rest.strip_prefix(':').unwrap_or(rest);
+        let (prerequisites, recipe) = match rest.split_once(';') {
+            Some((prerequisites, recipe)) => (prerequisites, Some(recipe.trim())),
+            None => (rest, None),
+        };
+        if prerequisites.contains('=') {
+            // A target-specific variable, like `test: RUST_LOG = debug`
+            variables.extend(prerequisites.split('=').next().map(|name| {
+                name.trim()
+                    .trim_end_matches(['?', '+', '!', ':'])
+                    .trim()
+                    .to_string()
+            }));
+            continue;
+        }
+        for target in targets.split_whitespace() {
+            let index = match rules.iter().position(|rule| rule.target == target) {
+                Some(index) => index,
+                None => {
+                    rules.push(Rule {
+                        target: target.to_string(),
+                        ..Rule::default()
+                    });
+                    rules.len() - 1
+                }
+            };
+            let rule = &mut rules[index];
+            for prerequisite in prerequisites.split_whitespace() {
+                if !rule.prerequisites.iter().any(|p| p == prerequisite) {
+                    rule.prerequisites.push(prerequisite.to_string());
+                }
+            }
+            rule.recipe.extend(recipe.map(str::to_string));
+            current.push(index);
+        }
+    }
+    variables.dedup();
+    (rules, variables)
+}
+
+/// The name `statement` assigns to, if it's a variable assignment like
+/// `CC := gcc`, `VERSION ?= 1.0`, or `FLAGS += -O2`
+fn assigned_variable(statement: &str) -> Option<String> {
+    let end = statement.find([':', '='])?;
+    let rest = &statement[end..];
+    let assigns = rest.starts_with('=')
+        || rest.starts_with(":=")
+        || rest.starts_with("::=")
+        || rest.starts_with(":::=");
+    assigns.then(|| {
+        statement[..end]
+            .trim_end_matches(['?', '+', '!'])
+            .trim()
+            .to_string()
+    })
+}
+
+/// The lines of `text` with backslash-continued ones joined
+fn logical_lines(text: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut pending: Option<String> = None;
+    for line in text.lines() {
+        let line = line.trim_end_matches('\r');
+        let joined = match pending.take() {
+            Some(mut start) => {
+                start.push(' ');
+                start.push_str(line.trim_start());
+                start
+            }
+            None => line.to_string(),
+        };
+        match joined.strip_suffix('\\') {
+            Some(start) => pending = Some(start.trim_end().to_string()),
+            None => lines.push(joined),
+        }
+    }
+    lines.extend(pending);
+    lines
+}
+
+/// rnr.yaml with imported tasks merged in
+#[derive(Debug)]
+struct Merged {
+    content: String,
+    /// Tasks added or replaced
+    names: Vec<String>,
+    notes: Vec<String>,
+}
+
+/// `content` with `tasks` added at the end, running in `dir`. Tasks it
+/// already has are kept unless `force` replaces them where they are.
+fn merge(content: &str, tasks: &[(String, Run)], dir: Option<&str>, force: bool) -> Result<Merged> {
+    let mut merged = Merged {
+        content: content.to_string(),
+        names: Vec::new(),
+        notes: Vec::new(),
+    };
+    let mut kept = Vec::new();
+    for (name, run) in tasks {
+        if SETTINGS.contains(&name.as_str()) {
+            merged.notes.push(format!(
+                "Skipped '{}', which is the name of an {} setting",
+                name, CONFIG_FILE
+            ));
+            continue;
+        }
+        if !force && content_has_task(content, name) {
+            kept.push(name.as_str());
+            continue;
+        }
+        merged.content = put_task(&merged.content, name, &render(name, run, dir)?);
+        merged.names.push(name.clone());
+    }
+    if !kept.is_empty() {
+        merged.notes.push(format!(
+            "Kept the tasks already in {}: {}; pass --force to replace them",
+            CONFIG_FILE,
+            kept.join(", ")
+        ));
+    }
+
+    let config: Config = serde_yaml::from_str(&merged.content)
+        .with_context(|| format!("Importing would leave {} unreadable", CONFIG_FILE))?;
+    if let Some(missing) = merged
+        .names
+        .iter()
+        .find(|name| config.get_task(name).is_none())
+    {
+        anyhow::bail!(
+            "Importing '{}' into {} didn't give a task of that name; the file is unchanged",
+            missing,
+            CONFIG_FILE
+        );
+    }
+    Ok(merged)
+}
+
+/// A task as canonical rnr.yaml text, ending in a newline
+fn render(name: &str, run: &Run, dir: Option<&str>) -> Result<String> {
+    let value = match (run, dir) {
+        (Run::Cmd(command), None) => Value::String(command.clone()),
+        _ => {
+            let mut task = Mapping::new();
+            if let Some(dir) = dir {
+                task.insert("dir".into(), dir.into());
+            }
+            match run {
+                Run::Cmd(command) => {
+                    task.insert("cmd".into(), command.as_str().into());
+                }
+                Run::Steps(steps) => {
+                    let steps = steps
+                        .iter()
+                        .map(|step| {
+                            let (field, value) = match step {
+                                Step::Cmd(command) => ("cmd", command),
+                                Step::Task(task) => ("task", task),
+                            };
+                            let mut step = Mapping::new();
+                            step.insert(field.into(), value.as_str().into());
+                            Value::Mapping(step)
+                        })
+                        .collect();
+                    task.insert("steps".into(), Value::Sequence(steps));
+                }
+            }
+            Value::Mapping(task)
+        }
+    };
+    let key = if matches!(value, Value::String(_)) {
+        name
+    } else {
+        "cmd"
+    };
+    let mut document = Mapping::new();
+    document.insert(name.into(), value);
+    Ok(quote_plain(
+        &formatter::format(&serde_yaml::to_string(&document)?)?,
+        key,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PACKAGE_JSON: &str = r#"{
+  "name": "web",
+  "scripts": {
+    "prebuild": "rimraf dist",
+    "build": "tsc -p .",
+    "postbuild": "cp README.md dist/",
+    "dev": "vite",
+    "test": "vitest run",
+    "lint": "eslint . --ext .ts",
+    "format": "prettier --write 'src/**/*.ts'",
+    "start": "node dist/index.js",
+    "build:docs": "typedoc src",
+    "env": "node -e \"console.log(process.env)\""
+  }
+}"#;
+
+    const MAKEFILE: &str = "\
+CC := gcc
+VERSION ?= 1.0
+export GOFLAGS = -mod=vendor
+
+.PHONY: all build test clean
+
+all: build test
+
+build:
+\t@echo building $(VERSION)
+\t$(CC) -o app main.c
+
+test: build
+\t./app --self-test
+\tcargo test \\
+\t  --workspace
+
+clean:
+\t-rm -rf out
+
+%.o: %.c
+\t$(CC) -c $<
+
+out/app: build
+\tcp app out/app
+
+hello: ; echo \"hi $$USER\"
+";
+
+    fn names(import: &Import) -> Vec<&str> {
+        import.tasks.iter().map(|(name, _)| name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_scripts_run_with_npm_and_hooks_go_with_them() {
+        let import = from_package_json(PACKAGE_JSON, false).unwrap();
+        assert_eq!(
+            names(&import),
+            [
+                "build",
+                "dev",
+                "test",
+                "lint",
+                "format",
+                "start",
+                "build:docs",
+                "env"
+            ]
+        );
+        assert_eq!(import.tasks[0].1, Run::Cmd("npm run build".into()));
+        assert_eq!(
+            import.notes,
+            ["npm hooks prebuild, postbuild aren't tasks; npm run still runs them around their scripts"]
+        );
+
+        let inline = from_package_json(PACKAGE_JSON, true).unwrap();
+        assert_eq!(
+            inline.tasks[0].1,
+            Run::Steps(vec![
+                Step::Cmd("rimraf dist".into()),
+                Step::Cmd("tsc -p .".into()),
+                Step::Cmd("cp README.md dist/".into()),
+            ])
+        );
+        assert_eq!(
+            inline.tasks[4].1,
+            Run::Cmd("prettier --write 'src/**/*.ts'".into())
+        );
+        assert!(inline.notes[0].contains("became steps"));
+
+        let err = from_package_json(r#"{"name": "x"}"#, false).unwrap_err();
+        assert_eq!(err.to_string(), "it has no scripts");
+        assert!(from_package_json(r#"{"scripts": {"a": 1}}"#, false).is_err());
+    }
+
+    #[test]
+    fn test_makefile_targets_skip_variables_patterns_and_specials() {
+        let (rules, variables) = parse_makefile(MAKEFILE);
+        let targets: Vec<&str> = rules.iter().map(|rule| rule.target.as_str()).collect();
+        assert_eq!(
+            targets,
+            [".PHONY", "all", "build", "test", "clean", "%.o", "out/app", "hello"]
+        );
+        assert_eq!(variables, ["CC", "VERSION", "GOFLAGS"]);
+        assert_eq!(
+            rules[3].recipe,
+            ["./app --self-test", "cargo test --workspace"]
+        );
+
+        let import = from_makefile(MAKEFILE, "make", false);
+        assert_eq!(names(&import), ["all", "build", "test", "clean", "hello"]);
+        assert_eq!(import.tasks[2].1, Run::Cmd("make test".into()));
+        assert_eq!(
+            import.notes,
+            [
+                "Skipped targets that are paths, which rnr reads as task files: out/app",
+                "Skipped Makefile variables CC, VERSION, GOFLAGS; make still sets them for the tasks that run it",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_inlined_targets_fall_back_to_make() {
+        let import = from_makefile(MAKEFILE, "make -f ci.mk", true);
+        let run = |name: &str| {
+            import
+                .tasks
+                .iter()
+                .find(|(task, _)| task == name)
+                .map(|(_, run)| run.clone())
+                .unwrap()
+        };
+        assert_eq!(
+            run("all"),
+            Run::Steps(vec![Step::Task("build".into()), Step::Task("test".into())])
+        );
+        assert_eq!(run("build"), Run::Cmd("make -f ci.mk build".into()));
+        assert_eq!(
+            run("test"),
+            Run::Steps(vec![
+                Step::Task("build".into()),
+                Step::Cmd("./app --self-test".into()),
+                Step::Cmd("cargo test --workspace".into()),
+            ])
+        );
+        assert_eq!(run("clean"), Run::Cmd("make -f ci.mk clean".into()));
+        assert_eq!(run("hello"), Run::Cmd("echo \"hi $USER\"".into()));
+        assert_eq!(
+            &import.notes[..2],
+            [
+                "'build' runs make -f ci.mk, since its recipe uses make variables",
+                "'clean' runs make -f ci.mk, since its recipe ignores errors with '-'",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge_keeps_existing_tasks_unless_forced() {
+        let tasks = vec![
+            ("build".to_string(), Run::Cmd("npm run build".into())),
+            ("lint".to_string(), Run::Cmd("npm run lint".into())),
+            ("env".to_string(), Run::Cmd("npm run env".into())),
+        ];
+        let content = "# Tasks\nbuild: cargo build # ours\n";
+        let merged = merge(content, &tasks, None, false).unwrap();
+        assert_eq!(
+            merged.content,
+            "# Tasks\nbuild: cargo build # ours\n\nlint: 'npm run lint'\n\nenv: 'npm run env'\n"
+        );
+        assert_eq!(merged.names, ["lint", "env"]);
+        assert_eq!(
+            merged.notes,
+            ["Kept the tasks already in rnr.yaml: build; pass --force to replace them"]
+        );
+
+        let forced = merge(content, &tasks[..1], None, true).unwrap();
+        assert_eq!(forced.content, "# Tasks\nbuild: 'npm run build'\n");
+
+        let hooks = vec![("hooks".to_string(), Run::Cmd("make hooks".into()))];
+        let merged = merge("", &hooks, None, false).unwrap();
+        assert!(merged.names.is_empty());
+        assert_eq!(
+            merged.notes,
+            ["Skipped 'hooks', which is the name of an rnr.yaml setting"]
+        );
+    }
+
+    #[test]
+    fn test_renders_steps_and_dirs() {
+        let steps = Run::Steps(vec![
+            Step::Task("build".into()),
+            Step::Cmd("./app: --self-test".into()),
+        ]);
+        assert_eq!(
+            render("test", &steps, Some("web")).unwrap(),
+            "test:\n  dir: web\n  steps:\n    - task: build\n    - cmd: './app: --self-test'\n"
+        );
+        assert_eq!(
+            render("dev", &Run::Cmd("vite".into()), Some("web")).unwrap(),
+            "dev:\n  dir: web\n  cmd: 'vite'\n"
+        );
+        let merged = merge("", &[("test".into(), steps)], None, false).unwrap();
+        let config: Config = serde_yaml::from_str(&merged.content).unwrap();
+        assert!(config.get_task("test").is_some());
+    }
+}
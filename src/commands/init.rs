@@ -1,72 +1,358 @@
 //! Initialize rnr in the current directory
 
 use anyhow::{bail, Context, Result};
-use dialoguer::MultiSelect;
+use dialoguer::{Confirm, MultiSelect};
+use std::ffi::OsString;
+use std::fmt;
 use std::fs;
-use std::path::Path;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
 
 use crate::cli::InitArgs;
-use crate::config::CONFIG_FILE;
+use crate::config::{Config, CONFIG_FILE};
+use crate::events::{self, EventSink};
+#[cfg(feature = "network")]
+use crate::net::ReleaseSource;
+use crate::permissions;
 use crate::platform::{format_size, total_size, Platform, ALL_PLATFORMS};
-use crate::rnr_config::{bin_dir, is_initialized, RnrConfig};
+use crate::rnr_config::{
+    self, bin_dir, is_initialized, is_initialized_at, placeholder_path, Mode, ProjectLock,
+    RnrConfig, PLACEHOLDER_SUFFIX,
+};
+use crate::ui::Ui;
 
 /// Current rnr version
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
-/// Run the init command
-pub fn run(args: &InitArgs) -> Result<()> {
-    // Handle --show-platforms
-    if args.show_platforms {
-        return show_platforms();
+/// Environment variable naming the starter rnr.yaml for new projects
+pub const STARTER_ENV: &str = "RNR_STARTER";
+
+/// Placeholder in a starter rnr.yaml replaced by the project's directory name
+const PROJECT_NAME_PLACEHOLDER: &str = "${project_name}";
+
+/// The rnr.yaml a fresh init writes when none is configured
+const BUILT_IN_STARTER: &str = r#"# rnr task definitions
+# See https://github.com/CodingWithCalvin/rnr.cli for documentation
+
+# Simple command (shorthand)
+hello: echo "Hello from rnr!"
+
+# Full task definition
+build:
+  description: Build the project
+  cmd: echo "Add your build command here"
+
+# Task with steps
+ci:
+  description: Run CI pipeline
+  steps:
+    - cmd: 'echo "Step 1: Lint"'
+    - cmd: 'echo "Step 2: Test"'
+    - cmd: 'echo "Step 3: Build"'
+"#;
+
+/// What an `rnr init` invocation does, derived from its flags. Clap rejects
+/// flag combinations that mix modes, so each flag belongs to one mode.
+#[derive(Debug, PartialEq)]
+enum InitMode<'a> {
+    /// `--show-platforms`
+    ShowPlatforms,
+    /// `--prune [--yes]`
+    Prune { yes: bool },
+    /// `--add-platform <id> [--placeholders]`
+    AddPlatform {
+        platform: &'a str,
+        placeholders: bool,
+    },
+    /// `--remove-platform <id>`
+    RemovePlatform { platform: &'a str },
+    /// `--download`, switching a `mode: system` project to committed binaries
+    Download,
+    /// `--dry-run`, showing what a fresh init (or a change of platforms) would do
+    DryRun,
+    /// A fresh init, with platforms from the selection flags or a prompt
+    Fresh {
+        placeholders: bool,
+        force: bool,
+        nested: bool,
+        starter: Option<&'a Path>,
+    },
+}
+
+impl<'a> From<&'a InitArgs> for InitMode<'a> {
+    fn from(args: &'a InitArgs) -> Self {
+        if args.dry_run {
+            InitMode::DryRun
+        } else if args.show_platforms {
+            InitMode::ShowPlatforms
+        } else if args.prune {
+            InitMode::Prune { yes: args.yes }
+        } else if let Some(platform) = &args.add_platform {
+            InitMode::AddPlatform {
+                platform,
+                placeholders: args.placeholders,
+            }
+        } else if let Some(platform) = &args.remove_platform {
+            InitMode::RemovePlatform { platform }
+        } else if args.download {
+            InitMode::Download
+        } else {
+            InitMode::Fresh {
+                placeholders: args.placeholders,
+                force: args.force,
+                nested: args.nested,
+                starter: args.starter.as_deref(),
+            }
+        }
     }
+}
 
-    // Handle --add-platform
-    if let Some(platform_id) = &args.add_platform {
-        return add_platform(platform_id);
+/// Run the init command
+pub fn run(args: &InitArgs, ui: &Ui) -> Result<()> {
+    let mode = InitMode::from(args);
+    let events = args.progress_json.then(EventSink::stderr);
+    let events = events.as_ref();
+
+    // Only reads, so even slim builds preview without a full rnr
+    if mode == InitMode::DryRun {
+        return dry_run(args, ui);
     }
 
-    // Handle --remove-platform
-    if let Some(platform_id) = &args.remove_platform {
-        return remove_platform(platform_id);
+    // Slim builds can't download binaries, so a full rnr does the rest. A
+    // fresh init without one sets the project up to run the rnr on PATH.
+    #[cfg(not(feature = "network"))]
+    if matches!(
+        mode,
+        InitMode::AddPlatform {
+            placeholders: false,
+            ..
+        } | InitMode::Download
+    ) || (matches!(
+        mode,
+        InitMode::Fresh {
+            placeholders: false,
+            ..
+        }
+    ) && crate::delegate::full_build_available())
+    {
+        return crate::delegate::to_full_build("rnr init", ui);
     }
 
+    let (placeholders, force, nested, starter) = match mode {
+        InitMode::ShowPlatforms => return show_platforms(ui),
+        InitMode::Prune { yes } => return prune(yes, ui),
+        InitMode::AddPlatform {
+            platform,
+            placeholders,
+        } => return add_platform(platform, placeholders, ui, events),
+        InitMode::RemovePlatform { platform } => return remove_platform(platform, ui),
+        InitMode::Download => return download(args, ui, events),
+        InitMode::DryRun => unreachable!("handled above"),
+        InitMode::Fresh {
+            placeholders,
+            force,
+            nested,
+            starter,
+        } => (placeholders, force, nested, starter),
+    };
+
     // Check if already initialized (for fresh init)
     if is_initialized()? {
-        println!("rnr is already initialized in this directory.");
-        println!("Use --add-platform or --remove-platform to modify platforms.");
-        println!("Use --show-platforms to see configured platforms.");
+        ui.status("rnr is already initialized in this directory.");
+        ui.status("Use --add-platform or --remove-platform to modify platforms.");
+        ui.status("Use --show-platforms to see configured platforms.");
         return Ok(());
     }
 
-    // Error if not at git repo root (unless --force is used)
-    if !args.force && !is_git_repo_root()? {
+    // Refuse to set up a second project inside another (unless --nested is used)
+    let current_dir = rnr_config::project_dir()?;
+    if let Some(enclosing) = enclosing_project(&current_dir).filter(|_| !nested) {
         bail!(
-            "This directory does not appear to be a git repository root.\n\
-             rnr is typically initialized at the root of a git repository.\n\
-             Use --force to initialize anyway, or run from the directory containing your .git folder."
+            "This directory is inside the rnr project at {}.\n\
+             Run its tasks from here with that project's wrapper, or add a task with\n\
+             'dir:' and 'task:' to its rnr.yaml to run tasks from an rnr.yaml in this directory.\n\
+             Use --nested to set up a separate rnr project here anyway.",
+            enclosing.display()
         );
     }
 
+    // Error if not at git repo root (unless --force is used)
+    if !force {
+        match repo_position(&current_dir) {
+            RepoPosition::Root => {}
+            RepoPosition::NotARepo => bail!(
+                "This directory is not inside a git repository.\n\
+                 rnr is typically initialized at the root of a git repository.\n\
+                 Use --force to initialize anyway."
+            ),
+            RepoPosition::Subdirectory(root) => bail!(
+                "This directory is inside the git repository at {}, not at its root.\n\
+                 Run rnr init from {}, or use --force to initialize here anyway.",
+                root.display(),
+                root.display()
+            ),
+        }
+    }
+
+    // Check the starter rnr.yaml before downloading anything
+    let starter = if current_dir.join(CONFIG_FILE).exists() {
+        None
+    } else {
+        let source = Starter::resolve(starter, std::env::var_os(STARTER_ENV), dirs::home_dir());
+        Some(source.content(&project_name(&current_dir))?)
+    };
+
+    if cfg!(not(feature = "network")) && !placeholders {
+        return initialize_system(starter.as_deref(), ui, events);
+    }
+
     // Determine platforms to install
-    let platforms = select_platforms(args)?;
+    let platforms = select_platforms(args, ui)?;
 
     if platforms.is_empty() {
         bail!("No platforms selected. At least one platform is required.");
     }
 
     // Perform initialization
-    initialize(&platforms)
+    initialize(&platforms, placeholders, starter.as_deref(), ui, events)
+}
+
+/// Where a fresh init's rnr.yaml comes from
+#[derive(Debug, PartialEq)]
+enum Starter {
+    /// `--starter <path>`
+    Flag(PathBuf),
+    /// The file named by `RNR_STARTER`
+    Env(PathBuf),
+    /// `~/.config/rnr/starter.yaml`
+    User(PathBuf),
+    /// The hello-world template built into rnr
+    BuiltIn,
+}
+
+impl Starter {
+    /// The first of: the `--starter` flag, `RNR_STARTER`, and the user's
+    /// starter file under `home` if it exists
+    fn resolve(flag: Option<&Path>, env: Option<OsString>, home: Option<PathBuf>) -> Self {
+        if let Some(path) = flag {
+            return Starter::Flag(path.to_path_buf());
+        }
+        if let Some(path) = env.filter(|path| !path.is_empty()) {
+            return Starter::Env(PathBuf::from(path));
+        }
+        home.map(|home| home.join(".config").join("rnr").join("starter.yaml"))
+            .filter(|path| path.is_file())
+            .map_or(Starter::BuiltIn, Starter::User)
+    }
+
+    /// The starter's rnr.yaml for `project_name`, checked to be a valid task file
+    fn content(&self, project_name: &str) -> Result<String> {
+        let template = match self {
+            Starter::Flag(path) | Starter::Env(path) | Starter::User(path) => {
+                fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read starter rnr.yaml from {}", self))?
+            }
+            Starter::BuiltIn => BUILT_IN_STARTER.to_string(),
+        };
+        let content = template.replace(PROJECT_NAME_PLACEHOLDER, project_name);
+        serde_yaml::from_str::<Config>(&content)
+            .with_context(|| format!("Starter rnr.yaml from {} is not a valid task file", self))?;
+        Ok(content)
+    }
+}
+
+impl fmt::Display for Starter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Starter::Flag(path) => write!(f, "--starter {}", path.display()),
+            Starter::Env(path) => write!(f, "{} ({})", STARTER_ENV, path.display()),
+            Starter::User(path) => write!(f, "{}", path.display()),
+            Starter::BuiltIn => write!(f, "the built-in template"),
+        }
+    }
+}
+
+/// Name of the project in `dir`, for `${project_name}` in starter files
+fn project_name(dir: &Path) -> String {
+    dir.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
 }
 
-/// Check if the current directory is a git repository root
-fn is_git_repo_root() -> Result<bool> {
-    let current_dir = std::env::current_dir().context("Failed to get current directory")?;
-    let git_dir = current_dir.join(".git");
-    Ok(git_dir.exists())
+/// The nearest rnr project above `dir`: one that's initialized, or just has an rnr.yaml
+fn enclosing_project(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .skip(1)
+        .find(|ancestor| is_initialized_at(ancestor) || ancestor.join(CONFIG_FILE).is_file())
+        .map(Path::to_path_buf)
+}
+
+/// Where a directory sits relative to a git repository
+#[derive(Debug, PartialEq, Eq)]
+enum RepoPosition {
+    /// At the top of a repository, worktree, or submodule
+    Root,
+    /// Below the top of the repository rooted at this path
+    Subdirectory(PathBuf),
+    /// Not in a repository
+    NotARepo,
+}
+
+/// Where `dir` sits, asking git when it's installed and looking for `.git`
+/// entries otherwise
+fn repo_position(dir: &Path) -> RepoPosition {
+    let output = std::process::Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["rev-parse", "--show-toplevel"])
+        .stderr(std::process::Stdio::null())
+        .output();
+    let Ok(output) = output else {
+        return repo_position_from_files(dir);
+    };
+    if !output.status.success() {
+        return RepoPosition::NotARepo;
+    }
+    let toplevel = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    if same_dir(&toplevel, dir) {
+        RepoPosition::Root
+    } else {
+        RepoPosition::Subdirectory(toplevel)
+    }
+}
+
+/// Where `dir` sits, judged by the nearest `.git` directory or `gitdir:`
+/// file (as in worktrees and submodules) at or above it
+fn repo_position_from_files(dir: &Path) -> RepoPosition {
+    let is_repo_top = |dir: &Path| {
+        let git = dir.join(".git");
+        if git.is_dir() {
+            return true;
+        }
+        fs::read_to_string(&git).is_ok_and(|contents| {
+            contents
+                .lines()
+                .find_map(|line| line.strip_prefix("gitdir:"))
+                .is_some_and(|target| dir.join(target.trim()).is_dir())
+        })
+    };
+    match dir.ancestors().find(|ancestor| is_repo_top(ancestor)) {
+        Some(top) if top == dir => RepoPosition::Root,
+        Some(top) => RepoPosition::Subdirectory(top.to_path_buf()),
+        None => RepoPosition::NotARepo,
+    }
+}
+
+/// Whether two paths name the same directory
+fn same_dir(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
 }
 
 /// Select platforms based on args or interactively
-fn select_platforms(args: &InitArgs) -> Result<Vec<Platform>> {
+fn select_platforms(args: &InitArgs, ui: &Ui) -> Result<Vec<Platform>> {
     // --all-platforms
     if args.all_platforms {
         return Ok(ALL_PLATFORMS.to_vec());
@@ -74,7 +360,7 @@ fn select_platforms(args: &InitArgs) -> Result<Vec<Platform>> {
 
     // --current-platform-only
     if args.current_platform_only {
-        let current = Platform::current()
+        let current = Platform::native()
             .context("Unable to detect current platform. Use --platforms to specify manually.")?;
         return Ok(vec![current]);
     }
@@ -83,20 +369,30 @@ fn select_platforms(args: &InitArgs) -> Result<Vec<Platform>> {
     if let Some(platform_ids) = &args.platforms {
         let mut platforms = Vec::new();
         for id in platform_ids {
-            let platform = Platform::from_id(id)
-                .with_context(|| format!("Unknown platform: {}. Valid platforms: linux-amd64, macos-amd64, macos-arm64, windows-amd64, windows-arm64", id))?;
-            platforms.push(platform);
+            platforms.push(parse_platform(id)?);
         }
         return Ok(platforms);
     }
 
     // Interactive selection
-    interactive_platform_select()
+    interactive_platform_select(ui)
+}
+
+/// The platform with id `id`, or an error listing the valid ids
+fn parse_platform(id: &str) -> Result<Platform> {
+    Platform::from_id(id).with_context(|| {
+        let valid: Vec<&str> = ALL_PLATFORMS.iter().map(|p| p.id()).collect();
+        format!(
+            "Unknown platform: {}. Valid platforms: {}",
+            id,
+            valid.join(", ")
+        )
+    })
 }
 
 /// Interactive platform selection
-fn interactive_platform_select() -> Result<Vec<Platform>> {
-    let current = Platform::current();
+fn interactive_platform_select(ui: &Ui) -> Result<Vec<Platform>> {
+    let current = Platform::native();
 
     // Build items with size info
     let items: Vec<String> = ALL_PLATFORMS
@@ -114,7 +410,7 @@ fn interactive_platform_select() -> Result<Vec<Platform>> {
     // Determine default selections (current platform pre-selected)
     let defaults: Vec<bool> = ALL_PLATFORMS.iter().map(|p| Some(*p) == current).collect();
 
-    println!("\nWhich platforms should this project support?\n");
+    ui.status("\nWhich platforms should this project support?\n");
 
     let selections = MultiSelect::new()
         .items(&items)
@@ -126,140 +422,558 @@ fn interactive_platform_select() -> Result<Vec<Platform>> {
 
     // Show total size
     let total = total_size(&selected);
-    println!("\nSelected: {} total\n", format_size(total));
+    ui.status(format!("\nSelected: {} total\n", format_size(total)));
 
     Ok(selected)
 }
 
 /// Perform the actual initialization
-fn initialize(platforms: &[Platform]) -> Result<()> {
-    let current_dir = std::env::current_dir().context("Failed to get current directory")?;
+fn initialize(
+    platforms: &[Platform],
+    placeholders: bool,
+    starter: Option<&str>,
+    ui: &Ui,
+    events: Option<&EventSink>,
+) -> Result<()> {
+    let current_dir = rnr_config::project_dir()?;
 
-    println!("Initializing rnr...\n");
+    ui.status("Initializing rnr...\n");
 
     // Create .rnr/bin directory
     let bin_directory = bin_dir()?;
     fs::create_dir_all(&bin_directory).context("Failed to create .rnr/bin directory")?;
-    println!("  Created .rnr/bin/");
-
-    // Download binaries
-    download_binaries(platforms, &bin_directory)?;
-
-    // Save config
-    let config = RnrConfig::new(VERSION, platforms);
+    ui.status("  Created .rnr/bin/");
+
+    // Download binaries (or write placeholders), recording each in the config
+    let mut config = RnrConfig::new(VERSION, platforms);
+    install_binaries(
+        platforms,
+        &bin_directory,
+        placeholders,
+        &mut config,
+        ui,
+        events,
+    )?;
     config.save()?;
-    println!("  Created .rnr/config.yaml");
+    ui.status("  Created .rnr/config.yaml");
+    config_written(events);
+    create_ignore_file(ui)?;
 
     // Create wrapper scripts
-    create_wrapper_scripts(&current_dir)?;
+    create_wrapper_scripts(&current_dir, Mode::Binaries, ui, events)?;
 
     // Create starter rnr.yaml if it doesn't exist
-    let task_config_path = current_dir.join(CONFIG_FILE);
-    if !task_config_path.exists() {
-        create_starter_config(&task_config_path)?;
-    } else {
-        println!("  {} already exists, skipping", CONFIG_FILE);
+    match starter {
+        Some(starter) => {
+            create_starter_config(&current_dir.join(CONFIG_FILE), starter, ui, events)?
+        }
+        None => ui.status(format!("  {} already exists, skipping", CONFIG_FILE)),
     }
 
-    println!("\nrnr initialized successfully!");
-    println!("\nConfigured platforms:");
+    ui.status("\nrnr initialized successfully!");
+    ui.status("\nConfigured platforms:");
     for p in platforms {
-        println!("  - {}", p.id());
+        ui.status(format!("  - {}", p.id()));
     }
-    println!("\nNext steps:");
-    println!("  1. Edit {} to define your tasks", CONFIG_FILE);
-    println!("  2. Run ./rnr --list to see available tasks");
-    println!("  3. Run ./rnr <task> to execute a task");
-    println!("  4. Commit the .rnr directory and wrapper scripts to your repo");
+    ui.status("\nNext steps:");
+    ui.status(format!("  1. Edit {} to define your tasks", CONFIG_FILE));
+    ui.status("  2. Run ./rnr --list to see available tasks");
+    ui.status("  3. Run ./rnr <task> to execute a task");
+    ui.status("  4. Commit the .rnr directory and wrapper scripts to your repo");
+    completed(events, VERSION);
 
     Ok(())
 }
 
-/// Download binaries for selected platforms
-fn download_binaries(platforms: &[Platform], bin_directory: &Path) -> Result<()> {
-    println!("  Downloading binaries...");
+/// Set up a project whose wrappers run the rnr on PATH, for slim builds with
+/// no full rnr to download binaries
+fn initialize_system(starter: Option<&str>, ui: &Ui, events: Option<&EventSink>) -> Result<()> {
+    let current_dir = rnr_config::project_dir()?;
 
-    for platform in platforms {
-        let binary_path = bin_directory.join(platform.binary_name());
+    ui.status("Initializing rnr...\n");
 
-        #[cfg(feature = "network")]
-        {
-            download_binary(*platform, &binary_path)?;
-        }
+    RnrConfig::system(VERSION).save()?;
+    ui.status("  Created .rnr/config.yaml (mode: system)");
+    config_written(events);
+    create_ignore_file(ui)?;
 
-        #[cfg(not(feature = "network"))]
-        {
-            // Create placeholder for testing without network
-            fs::write(
-                &binary_path,
-                format!("# placeholder for {}\n", platform.id()),
-            )
-            .with_context(|| format!("Failed to create {}", binary_path.display()))?;
+    create_wrapper_scripts(&current_dir, Mode::System, ui, events)?;
+
+    match starter {
+        Some(starter) => {
+            create_starter_config(&current_dir.join(CONFIG_FILE), starter, ui, events)?
         }
+        None => ui.status(format!("  {} already exists, skipping", CONFIG_FILE)),
+    }
 
-        println!(
-            "    {} ({})",
-            platform.binary_name(),
-            platform.size_display()
+    ui.status("\nrnr initialized successfully!");
+    ui.warn(
+        "this rnr build has no network support, so no binaries were downloaded.\n\
+         The wrappers run the rnr installed on PATH, so everyone using this project needs one.\n\
+         To commit binaries instead, run 'rnr init --download' with a full rnr from\n\
+         https://github.com/CodingWithCalvin/rnr.cli/releases/latest",
+    );
+    ui.status("\nNext steps:");
+    ui.status(format!("  1. Edit {} to define your tasks", CONFIG_FILE));
+    ui.status("  2. Run ./rnr --list to see available tasks");
+    ui.status("  3. Run ./rnr <task> to execute a task");
+    ui.status("  4. Commit the .rnr directory and wrapper scripts to your repo");
+    completed(events, VERSION);
+
+    Ok(())
+}
+
+/// Download binaries into a `mode: system` project and switch its wrappers
+/// to run them
+fn download(args: &InitArgs, ui: &Ui, events: Option<&EventSink>) -> Result<()> {
+    let project = InitializedProject::locate()?;
+    let _lock = project.lock()?;
+    let current = project.load()?;
+    if current.mode != Mode::System {
+        bail!(
+            "This project already has binaries in .rnr/bin.\n\
+             Use --add-platform to add one, or 'rnr upgrade' to update them."
         );
     }
 
+    let platforms = select_platforms(args, ui)?;
+    if platforms.is_empty() {
+        bail!("No platforms selected. At least one platform is required.");
+    }
+
+    let bin_directory = project.bin_dir();
+    fs::create_dir_all(&bin_directory).context("Failed to create .rnr/bin directory")?;
+    let mut config = RnrConfig::new(VERSION, &platforms);
+    config.channel = current.channel;
+    install_binaries(&platforms, &bin_directory, false, &mut config, ui, events)?;
+    project.save(&config)?;
+    ui.status("  Updated .rnr/config.yaml");
+    config_written(events);
+
+    create_wrapper_scripts(&project.root, Mode::Binaries, ui, events)?;
+
+    ui.status("\nThe wrappers now run the binaries in .rnr/bin.");
+    ui.status("Commit the .rnr directory and wrapper scripts to your repo.");
+    completed(events, VERSION);
     Ok(())
 }
 
-/// GitHub repository for releases
-const GITHUB_REPO: &str = "CodingWithCalvin/rnr.cli";
+/// Check the requested platforms and show what init would create and
+/// download, or how it would change an initialized project's platforms,
+/// without writing anything
+fn dry_run(args: &InitArgs, ui: &Ui) -> Result<()> {
+    let platforms = select_platforms(args, ui)?;
+    if platforms.is_empty() {
+        bail!("No platforms selected. At least one platform is required.");
+    }
 
-/// Download a single binary from GitHub releases
-#[cfg(feature = "network")]
-fn download_binary(platform: Platform, dest: &Path) -> Result<()> {
-    let url = format!(
-        "https://github.com/{}/releases/latest/download/{}",
-        GITHUB_REPO,
-        platform.binary_name()
-    );
+    ui.line("Dry run: nothing will be created or downloaded.");
+    let sizes = if args.placeholders {
+        BinarySizes::estimates()
+    } else {
+        BinarySizes::lookup(ui)
+    };
 
-    let client = reqwest::blocking::Client::builder()
-        .user_agent("rnr-cli")
-        .build()
-        .context("Failed to create HTTP client")?;
+    if is_initialized()? {
+        let config = RnrConfig::load_from(&rnr_config::config_path()?)?;
+        preview_platform_changes(&config, &bin_dir()?, &platforms, &sizes, ui);
+        return Ok(());
+    }
+    preview_fresh(args, &platforms, &sizes, ui)
+}
 
-    let response = client
-        .get(&url)
-        .send()
-        .with_context(|| format!("Failed to download {}", platform.binary_name()))?;
+/// Sizes of the binaries init would download: the latest release's, when
+/// they can be looked up, otherwise rnr's estimates
+struct BinarySizes {
+    /// Version of the release the sizes came from
+    release: Option<String>,
+    sizes: Vec<(Platform, u64)>,
+}
 
-    if !response.status().is_success() {
-        anyhow::bail!(
-            "Failed to download {}: HTTP {}",
-            platform.binary_name(),
-            response.status().as_u16()
+impl BinarySizes {
+    /// rnr's built-in estimates
+    fn estimates() -> Self {
+        Self {
+            release: None,
+            sizes: Vec::new(),
+        }
+    }
+
+    /// The latest stable release's asset sizes, which init downloads
+    #[cfg(feature = "network")]
+    fn lookup(ui: &Ui) -> Self {
+        match ReleaseSource::from_env().latest(rnr_config::Channel::Stable) {
+            Ok(release) => Self {
+                sizes: ALL_PLATFORMS
+                    .iter()
+                    .filter_map(|p| release.binary_size(*p).map(|bytes| (*p, bytes)))
+                    .collect(),
+                release: Some(release.version().to_string()),
+            },
+            Err(e) => {
+                ui.warn(format!(
+                    "couldn't look up the latest release, showing estimated sizes: {:#}",
+                    e
+                ));
+                Self::estimates()
+            }
+        }
+    }
+
+    /// Slim builds can't look anything up
+    #[cfg(not(feature = "network"))]
+    fn lookup(_ui: &Ui) -> Self {
+        Self::estimates()
+    }
+
+    /// Size of `platform`'s binary, and whether it's only an estimate
+    fn of(&self, platform: Platform) -> (u64, bool) {
+        match self.sizes.iter().find(|(p, _)| *p == platform) {
+            Some((_, bytes)) => (*bytes, false),
+            None => (platform.size_bytes(), true),
+        }
+    }
+
+    /// Where the sizes came from
+    fn source(&self) -> String {
+        match &self.release {
+            Some(version) => format!("sizes from the v{} release", version),
+            None => "sizes are estimates (~)".to_string(),
+        }
+    }
+}
+
+/// A file a dry run would write
+struct PlannedFile {
+    /// Path relative to the project root
+    path: String,
+    size: u64,
+    /// Size of the file it would replace
+    existing: Option<u64>,
+    estimated: bool,
+}
+
+impl PlannedFile {
+    /// `path` under `root`, to be written with `size` bytes
+    fn new(root: &Path, path: &str, size: u64) -> Self {
+        Self {
+            path: path.to_string(),
+            size,
+            existing: file_size(&root.join(path)),
+            estimated: false,
+        }
+    }
+
+    /// How much it would grow the repository
+    fn change(&self) -> i64 {
+        self.size as i64 - self.existing.unwrap_or(0) as i64
+    }
+}
+
+/// Show the files a fresh init would write, and the checks it would stop at
+fn preview_fresh(
+    args: &InitArgs,
+    platforms: &[Platform],
+    sizes: &BinarySizes,
+    ui: &Ui,
+) -> Result<()> {
+    let current_dir = rnr_config::project_dir()?;
+
+    if let Some(enclosing) = enclosing_project(&current_dir).filter(|_| !args.nested) {
+        ui.warn(format!(
+            "this directory is inside the rnr project at {}; init would stop here without --nested",
+            enclosing.display()
+        ));
+    }
+    if !args.force {
+        match repo_position(&current_dir) {
+            RepoPosition::Root => {}
+            RepoPosition::NotARepo => ui.warn(
+                "this directory is not inside a git repository; init would stop here without --force",
+            ),
+            RepoPosition::Subdirectory(root) => ui.warn(format!(
+                "this directory is below the root of the git repository at {}; \
+                 init would stop here without --force",
+                root.display()
+            )),
+        }
+    }
+    if cfg!(not(feature = "network")) && !args.placeholders {
+        ui.warn(
+            "this rnr build has no network support, so init would hand the downloads to a \
+             full rnr, or set the project up to run the rnr on PATH if there is none",
         );
     }
 
-    let bytes = response
-        .bytes()
-        .with_context(|| format!("Failed to read response for {}", platform.binary_name()))?;
+    let mut files = Vec::new();
+    let config = serde_yaml::to_string(&RnrConfig::new(VERSION, platforms))
+        .context("Failed to serialize config")?;
+    files.push(PlannedFile::new(
+        &current_dir,
+        ".rnr/config.yaml",
+        config.len() as u64,
+    ));
+    let ignore = format!("{}/{}", rnr_config::RNR_DIR, rnr_config::IGNORE_FILE);
+    if !current_dir.join(&ignore).exists() {
+        files.push(PlannedFile::new(
+            &current_dir,
+            &ignore,
+            rnr_config::IGNORED.len() as u64,
+        ));
+    }
+    for platform in platforms {
+        let path = format!(".rnr/bin/{}", platform.binary_name());
+        if args.placeholders {
+            let path = format!("{}{}", path, PLACEHOLDER_SUFFIX);
+            let size = placeholder_content(*platform).len() as u64;
+            files.push(PlannedFile::new(&current_dir, &path, size));
+        } else {
+            let (size, estimated) = sizes.of(*platform);
+            files.push(PlannedFile {
+                estimated,
+                ..PlannedFile::new(&current_dir, &path, size)
+            });
+        }
+    }
+    files.push(PlannedFile::new(
+        &current_dir,
+        "rnr",
+        unix_wrapper(Mode::Binaries).len() as u64,
+    ));
+    files.push(PlannedFile::new(
+        &current_dir,
+        "rnr.cmd",
+        WINDOWS_WRAPPER.len() as u64,
+    ));
+    let gitattributes = fs::read_to_string(current_dir.join(".gitattributes")).ok();
+    let updated = with_managed_block(gitattributes.as_deref().unwrap_or_default());
+    if gitattributes.as_deref() != Some(updated.as_str()) {
+        files.push(PlannedFile::new(
+            &current_dir,
+            ".gitattributes",
+            updated.len() as u64,
+        ));
+    }
+    let kept_config = current_dir.join(CONFIG_FILE).exists();
+    if !kept_config {
+        let source = Starter::resolve(
+            args.starter.as_deref(),
+            std::env::var_os(STARTER_ENV),
+            dirs::home_dir(),
+        );
+        let starter = source.content(&project_name(&current_dir))?;
+        files.push(PlannedFile::new(
+            &current_dir,
+            CONFIG_FILE,
+            starter.len() as u64,
+        ));
+    }
 
-    // Write to file
-    fs::write(dest, &bytes).with_context(|| format!("Failed to write {}", dest.display()))?;
+    let ids: Vec<&str> = platforms.iter().map(|p| p.id()).collect();
+    ui.line(format!("\nPlatforms: {}", ids.join(", ")));
+    if !args.placeholders {
+        ui.line(format!("({})", sizes.source()));
+    }
+    ui.line("\nWould write:");
+    for file in &files {
+        let verb = if file.existing.is_some() {
+            "replace"
+        } else {
+            "create "
+        };
+        let size = format!(
+            "{}{}",
+            if file.estimated { "~" } else { "" },
+            format_size(file.size)
+        );
+        ui.line(format!("  {} {:<32} {:>12}", verb, file.path, size));
+    }
+    if kept_config {
+        ui.line(format!("  keep    {} (already exists)", CONFIG_FILE));
+    }
+    let total: i64 = files.iter().map(PlannedFile::change).sum();
+    ui.line(format!(
+        "\nRepository size change: {}",
+        format_change(total)
+    ));
+    Ok(())
+}
 
-    // Make executable on Unix
-    #[cfg(unix)]
-    {
-        use std::os::unix::fs::PermissionsExt;
-        let mut perms = fs::metadata(dest)?.permissions();
-        perms.set_mode(0o755);
-        fs::set_permissions(dest, perms)?;
+/// Show how init would change an initialized project's platforms to `platforms`
+fn preview_platform_changes(
+    config: &RnrConfig,
+    bin_directory: &Path,
+    platforms: &[Platform],
+    sizes: &BinarySizes,
+    ui: &Ui,
+) {
+    let configured = config.get_platforms();
+    let added: Vec<Platform> = platforms
+        .iter()
+        .filter(|p| !configured.contains(p))
+        .copied()
+        .collect();
+    let removed: Vec<Platform> = configured
+        .iter()
+        .filter(|p| !platforms.contains(p))
+        .copied()
+        .collect();
+
+    ui.line("\nrnr is already initialized here; compared with the configured platforms:\n");
+    let mut total: i64 = 0;
+    for platform in ALL_PLATFORMS {
+        if added.contains(platform) {
+            let (size, estimated) = sizes.of(*platform);
+            total += size as i64;
+            let tilde = if estimated { "~" } else { "" };
+            ui.line(format!(
+                "  + {:<16} +{}{}",
+                platform.id(),
+                tilde,
+                format_size(size)
+            ));
+        } else if removed.contains(platform) {
+            let binary = bin_directory.join(platform.binary_name());
+            let size = file_size(&binary)
+                .or_else(|| file_size(&placeholder_path(&binary)))
+                .unwrap_or(0);
+            total -= size as i64;
+            ui.line(format!("  - {:<16} -{}", platform.id(), format_size(size)));
+        } else if configured.contains(platform) {
+            ui.line(format!("    {:<16} unchanged", platform.id()));
+        }
+    }
+
+    if added.is_empty() && removed.is_empty() {
+        ui.line("\nThe configured platforms already match; nothing would change.");
+        return;
+    }
+    ui.line(format!(
+        "\nRepository size change: {}",
+        format_change(total)
+    ));
+    ui.line(format!("({})", sizes.source()));
+
+    let mut commands: Vec<String> = Vec::new();
+    if config.mode == Mode::System {
+        let ids: Vec<&str> = platforms.iter().map(|p| p.id()).collect();
+        commands.push(format!("rnr init --download --platforms {}", ids.join(",")));
+    } else {
+        commands.extend(
+            added
+                .iter()
+                .map(|p| format!("rnr init --add-platform {}", p.id())),
+        );
+        commands.extend(
+            removed
+                .iter()
+                .map(|p| format!("rnr init --remove-platform {}", p.id())),
+        );
+    }
+    ui.line("\nApply with:");
+    for command in commands {
+        ui.line(format!("  {}", command));
+    }
+}
+
+/// A signed size, like `+4.50 MB` or `-120 KB`
+fn format_change(bytes: i64) -> String {
+    let sign = if bytes < 0 { "-" } else { "+" };
+    format!("{}{}", sign, format_size(bytes.unsigned_abs()))
+}
+
+/// Download binaries for selected platforms, or write placeholders for them
+fn install_binaries(
+    platforms: &[Platform],
+    bin_directory: &Path,
+    placeholders: bool,
+    config: &mut RnrConfig,
+    ui: &Ui,
+    events: Option<&EventSink>,
+) -> Result<()> {
+    if placeholders {
+        for platform in platforms {
+            write_placeholder(*platform, bin_directory, ui, events)?;
+            config.record_placeholder(*platform, VERSION);
+        }
+        return Ok(());
+    }
+
+    ui.status("  Downloading binaries...");
+    for platform in platforms {
+        let binary_path = bin_directory.join(platform.binary_name());
+
+        download_binary(*platform, &binary_path, events)?;
+        config.record_binary(*platform, VERSION, &binary_path)?;
+        ui.status(format!(
+            "    {} ({})",
+            platform.binary_name(),
+            platform.size_display()
+        ));
     }
 
     Ok(())
 }
 
-/// Create the wrapper scripts at the project root
-fn create_wrapper_scripts(project_root: &Path) -> Result<()> {
-    // Unix wrapper script (smart detection)
-    let unix_script = r#"#!/bin/sh
+/// Write a clearly labeled stand-in for a binary that can't be downloaded yet
+fn write_placeholder(
+    platform: Platform,
+    bin_directory: &Path,
+    ui: &Ui,
+    events: Option<&EventSink>,
+) -> Result<()> {
+    let path = placeholder_path(&bin_directory.join(platform.binary_name()));
+    let content = placeholder_content(platform);
+    // Reported like a download, so tools following along work offline too
+    let size = content.len() as u64;
+    let name = format!(".rnr/bin/{}{}", platform.binary_name(), PLACEHOLDER_SUFFIX);
+    events::track_download(events, platform.id(), &name, size, true, |progress| {
+        fs::write(&path, &content)
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+        permissions::set(&path, permissions::READABLE)?;
+        progress(size, Some(size));
+        Ok(())
+    })?;
+    ui.warn(format!(
+        "wrote placeholder .rnr/bin/{}{} instead of a binary; \
+         run 'rnr upgrade' once a release is published",
+        platform.binary_name(),
+        PLACEHOLDER_SUFFIX
+    ));
+    Ok(())
+}
+
+/// What a placeholder for `platform`'s binary says
+fn placeholder_content(platform: Platform) -> String {
+    format!(
+        "This is a placeholder for the rnr {} binary, not a program.\n\
+         It was created by 'rnr init --placeholders' before rnr had a release to download.\n\
+         Run 'rnr upgrade' to replace it with the real binary.\n",
+        platform.id()
+    )
+}
+
+/// Download a single binary from the latest release
+#[cfg(feature = "network")]
+fn download_binary(platform: Platform, dest: &Path, events: Option<&EventSink>) -> Result<()> {
+    let name = format!(".rnr/bin/{}", platform.binary_name());
+    let expected = platform.size_bytes();
+    events::track_download(events, platform.id(), &name, expected, false, |progress| {
+        ReleaseSource::from_env().download_asset(platform, None, dest, progress)
+    })
+}
+
+/// Slim builds hand downloads to a full rnr before getting this far
+#[cfg(not(feature = "network"))]
+fn download_binary(platform: Platform, _dest: &Path, _events: Option<&EventSink>) -> Result<()> {
+    bail!(
+        "Cannot download {}: this rnr build does not include the network feature",
+        platform.binary_name()
+    )
+}
+
+/// Unix wrapper script (smart detection)
+const UNIX_WRAPPER: &str = r#"#!/bin/sh
 set -e
 
 # Detect OS
@@ -280,7 +994,35 @@ case "$ARCH" in
   *) echo "Error: Unsupported architecture: $ARCH" >&2; exit 1 ;;
 esac
 
-BINARY="$(dirname "$0")/.rnr/bin/rnr-${OS}-${ARCH}${EXT}"
+# A shell emulating x64 on ARM64 Windows reports x86_64; the machine's own
+# architecture is still in PROCESSOR_ARCHITEW6432 or the CPU's name
+if [ "$OS" = "windows" ]; then
+  case "${PROCESSOR_ARCHITECTURE:-}:${PROCESSOR_ARCHITEW6432:-}:${PROCESSOR_IDENTIFIER:-}" in
+    ARM64:*|*:ARM64:*|*:ARM*) ARCH="arm64" ;;
+  esac
+fi
+
+# Let rnr find this project's rnr.yaml wherever it's run from
+RNR_WRAPPER_DIR="$(cd "$(dirname "$0")" && pwd)"
+export RNR_WRAPPER_DIR
+
+BIN_DIR="${RNR_WRAPPER_DIR}/.rnr/bin"
+BINARY="${BIN_DIR}/rnr-${OS}-${ARCH}${EXT}"
+
+# Fall back to the amd64 binary under emulation (Rosetta on macOS, x64 emulation on Windows)
+if [ ! -f "$BINARY" ] && [ "$ARCH" = "arm64" ] && [ "$OS" != "linux" ] && [ "${RNR_NO_ARCH_FALLBACK:-}" != "1" ]; then
+  FALLBACK="${BIN_DIR}/rnr-${OS}-amd64${EXT}"
+  if [ -f "$FALLBACK" ]; then
+    echo "rnr: no ${OS}-arm64 binary, running ${OS}-amd64 under emulation" >&2
+    BINARY="$FALLBACK"
+  fi
+fi
+
+if [ ! -f "$BINARY" ] && [ -f "${BINARY}.placeholder" ]; then
+  echo "Error: .rnr/bin/$(basename "$BINARY").placeholder is a placeholder, not the rnr binary." >&2
+  echo "It was created before rnr had a release. Run 'rnr upgrade' with an installed rnr to download the real binary." >&2
+  exit 1
+fi
 
 if [ ! -f "$BINARY" ]; then
   echo "Error: rnr is not configured for ${OS}-${ARCH}." >&2
@@ -288,35 +1030,70 @@ if [ ! -f "$BINARY" ]; then
   exit 1
 fi
 
+# Check the file before running it; exec errors for the wrong file are cryptic
+NAME=".rnr/bin/$(basename "$BINARY")"
+if [ ! -s "$BINARY" ]; then
+  echo "Error: ${NAME} is empty, not the rnr binary." >&2
+  echo "Run 'rnr upgrade' with an installed rnr to download it again." >&2
+  exit 1
+fi
+
+FORMAT=""
+case "$(od -An -tx1 -N4 "$BINARY" | tr -d ' \n')" in
+  2321*) ;;
+  23*)
+    echo "Error: ${NAME} looks like a placeholder, not the rnr binary." >&2
+    echo "Run 'rnr init' with network access, or 'rnr upgrade' with an installed rnr, to download it." >&2
+    exit 1 ;;
+  7f454c46) FORMAT="linux" ;;
+  cffaedfe|cafebabe) FORMAT="macos" ;;
+  4d5a*) FORMAT="windows" ;;
+esac
+if [ -n "$FORMAT" ] && [ "$FORMAT" != "$OS" ]; then
+  echo "Error: ${NAME} is a ${FORMAT} binary, not one for ${OS}." >&2
+  echo "Run 'rnr upgrade' with an installed rnr to download the right one." >&2
+  exit 1
+fi
+
+if [ ! -x "$BINARY" ]; then
+  echo "Error: ${NAME} is not executable." >&2
+  echo "Run 'chmod +x ${NAME}' and commit it with 'git update-index --chmod=+x ${NAME}'." >&2
+  exit 1
+fi
+
 exec "$BINARY" "$@"
 "#;
 
-    let unix_path = project_root.join("rnr");
-    fs::write(&unix_path, unix_script).context("Failed to create rnr wrapper script")?;
+/// Windows wrapper script (smart detection)
+const WINDOWS_WRAPPER: &str = r##"@echo off
+setlocal
 
-    // Make executable on Unix
-    #[cfg(unix)]
-    {
-        use std::os::unix::fs::PermissionsExt;
-        let mut perms = fs::metadata(&unix_path)?.permissions();
-        perms.set_mode(0o755);
-        fs::set_permissions(&unix_path, perms)?;
-    }
+:: Detect the machine's architecture rather than the one this shell runs as:
+:: an x64 or x86 shell on ARM64 Windows sees PROCESSOR_ARCHITECTURE=AMD64
+:: (or x86), while PROCESSOR_ARCHITEW6432 or the CPU's name still says ARM
+set "ARCH=amd64"
+if /i "%PROCESSOR_ARCHITECTURE%"=="ARM64" set "ARCH=arm64"
+if /i "%PROCESSOR_ARCHITEW6432%"=="ARM64" set "ARCH=arm64"
+if defined PROCESSOR_IDENTIFIER if /i "%PROCESSOR_IDENTIFIER:~0,3%"=="ARM" set "ARCH=arm64"
 
-    println!("  Created rnr (Unix wrapper)");
+:: Let rnr find this project's rnr.yaml wherever it's run from
+set "RNR_WRAPPER_DIR=%~dp0"
 
-    // Windows wrapper script (smart detection)
-    let windows_script = r#"@echo off
-setlocal
+set "BINARY=%~dp0.rnr\bin\rnr-windows-%ARCH%.exe"
 
-:: Detect architecture
-if "%PROCESSOR_ARCHITECTURE%"=="ARM64" (
-  set "ARCH=arm64"
-) else (
-  set "ARCH=amd64"
+:: Prefer the native binary; fall back to the amd64 one under x64 emulation
+if not exist "%BINARY%" if "%ARCH%"=="arm64" if not "%RNR_NO_ARCH_FALLBACK%"=="1" (
+  if exist "%~dp0.rnr\bin\rnr-windows-amd64.exe" (
+    echo rnr: no windows-arm64 binary, running windows-amd64 under emulation >&2
+    set "BINARY=%~dp0.rnr\bin\rnr-windows-amd64.exe"
+  )
 )
 
-set "BINARY=%~dp0.rnr\bin\rnr-windows-%ARCH%.exe"
+if not exist "%BINARY%" if exist "%BINARY%.placeholder" (
+  echo Error: .rnr\bin\rnr-windows-%ARCH%.exe.placeholder is a placeholder, not the rnr binary. >&2
+  echo It was created before rnr had a release. Run 'rnr upgrade' with an installed rnr to download the real binary. >&2
+  exit /b 1
+)
 
 if not exist "%BINARY%" (
   echo Error: rnr is not configured for windows-%ARCH%. >&2
@@ -324,138 +1101,454 @@ if not exist "%BINARY%" (
   exit /b 1
 )
 
+:: Check the file before running it; errors for the wrong file are cryptic
+for %%F in ("%BINARY%") do set "NAME=.rnr\bin\%%~nxF" & set "SIZE=%%~zF"
+if "%SIZE%"=="0" (
+  echo Error: %NAME% is empty, not the rnr binary. >&2
+  echo Run 'rnr upgrade' with an installed rnr to download it again. >&2
+  exit /b 1
+)
+set "HEADER="
+set /p HEADER=<"%BINARY%"
+setlocal EnableDelayedExpansion
+if not "!HEADER:~0,2!"=="MZ" (
+  if "!HEADER:~0,1!"=="#" (
+    echo Error: %NAME% looks like a placeholder, not the rnr binary. >&2
+    echo Run 'rnr init' with network access, or 'rnr upgrade' with an installed rnr, to download it. >&2
+  ) else (
+    echo Error: %NAME% is not a Windows program. >&2
+    echo Run 'rnr upgrade' with an installed rnr to download the right one. >&2
+  )
+  exit /b 1
+)
+endlocal
+
 "%BINARY%" %*
+"##;
+
+/// Unix wrapper for `mode: system` projects, which run the rnr on PATH
+const SYSTEM_UNIX_WRAPPER: &str = r#"#!/bin/sh
+set -e
+
+# Let rnr find this project's rnr.yaml wherever it's run from
+RNR_WRAPPER_DIR="$(cd "$(dirname "$0")" && pwd)"
+export RNR_WRAPPER_DIR
+
+# This project has no committed binaries (mode: system in .rnr/config.yaml)
+if ! command -v rnr >/dev/null 2>&1; then
+  echo "Error: this project runs the rnr installed on PATH, and none was found." >&2
+  echo "Install rnr from https://github.com/CodingWithCalvin/rnr.cli/releases/latest" >&2
+  echo "or run 'rnr init --download' with it to commit binaries to the project." >&2
+  exit 1
+fi
+
+exec rnr "$@"
 "#;
 
+/// Windows wrapper for `mode: system` projects, which run the rnr on PATH
+const SYSTEM_WINDOWS_WRAPPER: &str = r##"@echo off
+setlocal
+
+:: Let rnr find this project's rnr.yaml wherever it's run from
+set "RNR_WRAPPER_DIR=%~dp0"
+
+:: This project has no committed binaries (mode: system in .rnr\config.yaml).
+:: Name rnr.exe so cmd doesn't find this wrapper in the current directory.
+where rnr.exe >nul 2>nul
+if errorlevel 1 (
+  echo Error: this project runs the rnr installed on PATH, and none was found. >&2
+  echo Install rnr from https://github.com/CodingWithCalvin/rnr.cli/releases/latest >&2
+  echo or run 'rnr init --download' with it to commit binaries to the project. >&2
+  exit /b 1
+)
+rnr.exe %*
+"##;
+
+/// Create the wrapper scripts at the project root
+fn create_wrapper_scripts(
+    project_root: &Path,
+    mode: Mode,
+    ui: &Ui,
+    events: Option<&EventSink>,
+) -> Result<()> {
+    let unix_path = project_root.join("rnr");
+    fs::write(&unix_path, unix_wrapper(mode)).context("Failed to create rnr wrapper script")?;
+    permissions::set(&unix_path, permissions::EXECUTABLE)?;
+
+    ui.status("  Created rnr (Unix wrapper)");
+    if let Some(events) = events {
+        events.wrapper_written("rnr");
+    }
+
     let windows_path = project_root.join("rnr.cmd");
-    fs::write(&windows_path, windows_script).context("Failed to create rnr.cmd wrapper script")?;
-    println!("  Created rnr.cmd (Windows wrapper)");
+    let windows_wrapper = match mode {
+        Mode::Binaries => WINDOWS_WRAPPER,
+        Mode::System => SYSTEM_WINDOWS_WRAPPER,
+    };
+    fs::write(&windows_path, windows_wrapper).context("Failed to create rnr.cmd wrapper script")?;
+    permissions::set(&windows_path, permissions::READABLE)?;
+    ui.status("  Created rnr.cmd (Windows wrapper)");
+    if let Some(events) = events {
+        events.wrapper_written("rnr.cmd");
+    }
+
+    update_gitattributes(project_root, ui)?;
 
     Ok(())
 }
 
-/// Create a starter rnr.yaml configuration
-fn create_starter_config(path: &Path) -> Result<()> {
-    let starter = r#"# rnr task definitions
-# See https://github.com/CodingWithCalvin/rnr.cli for documentation
+/// Write .rnr/.gitignore, so the cache, checkouts and lock file rnr keeps
+/// next to the committed binaries stay out of the repository
+fn create_ignore_file(ui: &Ui) -> Result<()> {
+    let rnr_dir = rnr_config::rnr_dir()?;
+    if rnr_config::ensure_ignored(&rnr_dir).context("Failed to create .rnr/.gitignore")? {
+        ui.status("  Created .rnr/.gitignore (local state)");
+    }
+    Ok(())
+}
 
-# Simple command (shorthand)
-hello: echo "Hello from rnr!"
+/// The Unix wrapper with LF line endings, even if this source was checked
+/// out with CRLF (`/bin/sh^M: bad interpreter` otherwise)
+fn unix_wrapper(mode: Mode) -> String {
+    match mode {
+        Mode::Binaries => UNIX_WRAPPER,
+        Mode::System => SYSTEM_UNIX_WRAPPER,
+    }
+    .replace("\r\n", "\n")
+}
 
-# Full task definition
-build:
-  description: Build the project
-  cmd: echo "Add your build command here"
+/// Start of the block init manages in .gitattributes
+const GITATTRIBUTES_BEGIN: &str = "# >>> rnr (managed by 'rnr init') >>>";
+
+/// End of the block init manages in .gitattributes
+const GITATTRIBUTES_END: &str = "# <<< rnr <<<";
+
+/// Rules that keep `core.autocrlf` from rewriting the Unix wrapper on checkout
+const GITATTRIBUTES_RULES: &[&str] = &["rnr text eol=lf"];
+
+/// Add (or refresh) rnr's managed block in the project's .gitattributes
+fn update_gitattributes(project_root: &Path, ui: &Ui) -> Result<()> {
+    let path = project_root.join(".gitattributes");
+    let existing = match fs::read_to_string(&path) {
+        Ok(text) => Some(text),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e).context("Failed to read .gitattributes"),
+    };
+    let updated = with_managed_block(existing.as_deref().unwrap_or_default());
+    if existing.as_deref() == Some(updated.as_str()) {
+        return Ok(());
+    }
+    fs::write(&path, updated).context("Failed to write .gitattributes")?;
+    ui.status(if existing.is_some() {
+        "  Updated .gitattributes (wrapper line endings)"
+    } else {
+        "  Created .gitattributes (wrapper line endings)"
+    });
+    Ok(())
+}
 
-# Task with steps
-ci:
-  description: Run CI pipeline
-  steps:
-    - cmd: 'echo "Step 1: Lint"'
-    - cmd: 'echo "Step 2: Test"'
-    - cmd: 'echo "Step 3: Build"'
-"#;
+/// `text` with rnr's managed block replaced, or appended if it has none
+fn with_managed_block(text: &str) -> String {
+    let block = format!(
+        "{}\n{}\n{}\n",
+        GITATTRIBUTES_BEGIN,
+        GITATTRIBUTES_RULES.join("\n"),
+        GITATTRIBUTES_END
+    );
+    if let Some(start) = text.find(GITATTRIBUTES_BEGIN) {
+        if let Some(len) = text[start..].find(GITATTRIBUTES_END) {
+            let mut end = start + len + GITATTRIBUTES_END.len();
+            if text[end..].starts_with("\r\n") {
+                end += 2;
+            } else if text[end..].starts_with('\n') {
+                end += 1;
+            }
+            return format!("{}{}{}", &text[..start], block, &text[end..]);
+        }
+    }
+    let mut out = text.to_string();
+    if !out.is_empty() && !out.ends_with('\n') {
+        out.push('\n');
+    }
+    out.push_str(&block);
+    out
+}
 
+/// Create a starter rnr.yaml configuration
+fn create_starter_config(
+    path: &Path,
+    starter: &str,
+    ui: &Ui,
+    events: Option<&EventSink>,
+) -> Result<()> {
     fs::write(path, starter).context("Failed to create rnr.yaml")?;
-    println!("  Created {}", CONFIG_FILE);
+    permissions::set(path, permissions::READABLE)?;
+    ui.status(format!("  Created {}", CONFIG_FILE));
+    if let Some(events) = events {
+        events.config_written(CONFIG_FILE);
+    }
 
     Ok(())
 }
 
-/// Show currently configured platforms
-fn show_platforms() -> Result<()> {
-    if !is_initialized()? {
-        println!("rnr is not initialized in this directory.");
-        println!("Run 'rnr init' to initialize.");
-        return Ok(());
+/// Report that .rnr/config.yaml was written, for `--progress-json`
+fn config_written(events: Option<&EventSink>) {
+    if let Some(events) = events {
+        events.config_written(".rnr/config.yaml");
+    }
+}
+
+/// Report that init finished, for `--progress-json`
+fn completed(events: Option<&EventSink>, version: &str) {
+    if let Some(events) = events {
+        events.completed(version);
     }
+}
+
+/// Show currently configured platforms with their actual on-disk sizes
+fn show_platforms(ui: &Ui) -> Result<()> {
+    let Ok(project) = InitializedProject::locate() else {
+        ui.line("rnr is not initialized in this directory or any parent directory.");
+        ui.line("Run 'rnr init' to initialize.");
+        return Ok(());
+    };
+
+    report_platforms(&project.load()?, &project.bin_dir(), ui);
+    Ok(())
+}
 
-    let config = RnrConfig::load()?;
+/// Print configured platforms, their sizes, and stray binaries in `bin_directory`
+fn report_platforms(config: &RnrConfig, bin_directory: &Path, ui: &Ui) {
     let platforms = config.get_platforms();
 
-    println!("\nConfigured platforms:\n");
+    ui.line("\nConfigured platforms:\n");
     let mut total: u64 = 0;
     for p in &platforms {
-        println!("  {} ({})", p.id(), p.size_display());
-        total += p.size_bytes();
+        let actual = file_size(&bin_directory.join(p.binary_name()));
+        let on_disk = match actual {
+            Some(bytes) => {
+                total += bytes;
+                format_size(bytes)
+            }
+            None => "missing".to_string(),
+        };
+        let line = format!(
+            "  {:<16} {:>10}  (expected ~{})",
+            p.id(),
+            on_disk,
+            p.size_display()
+        );
+        if config.binary(*p).is_some_and(|entry| entry.placeholder) {
+            ui.line(format!("{}  ! placeholder, run 'rnr upgrade'", line));
+            continue;
+        }
+        match size_discrepancy(p.size_bytes(), actual) {
+            Some(warning) => ui.line(format!("{}  ! {}", line, warning)),
+            None => ui.line(line),
+        }
+    }
+    ui.line(format!("\nTotal on disk: {}", format_size(total)));
+
+    if let Some(current) = Platform::native() {
+        if !config.has_platform(current) {
+            match current.emulation_fallback() {
+                Some(fallback) if config.has_platform(fallback) => ui.line(format!(
+                    "\nNote: {} is not configured; the wrapper runs {} under emulation here.",
+                    current, fallback
+                )),
+                _ => ui.line(format!(
+                    "\nNote: this machine ({}) is not configured. Run 'rnr init --add-platform {}'.",
+                    current, current
+                )),
+            }
+        }
+    }
+
+    let unmanaged = config.unmanaged_files(bin_directory);
+    if !unmanaged.is_empty() {
+        let size: u64 = unmanaged.iter().map(|(_, bytes)| bytes).sum();
+        ui.line(format!(
+            "\n{} file(s) in .rnr/bin that config.yaml doesn't manage ({}). Run 'rnr init --prune' to remove them.",
+            unmanaged.len(),
+            format_size(size)
+        ));
+    }
+}
+
+/// Remove files in .rnr/bin that config.yaml doesn't manage
+fn prune(yes: bool, ui: &Ui) -> Result<()> {
+    let project = InitializedProject::locate()?;
+    prune_binaries(&project.load()?, &project.bin_dir(), yes, ui)
+}
+
+/// Delete files in `bin_directory` the config doesn't manage: binaries for
+/// platforms missing from it, and files an older version left behind
+fn prune_binaries(config: &RnrConfig, bin_directory: &Path, yes: bool, ui: &Ui) -> Result<()> {
+    let unmanaged = config.unmanaged_files(bin_directory);
+
+    if unmanaged.is_empty() {
+        ui.status("Nothing to prune: .rnr/bin only contains files config.yaml manages.");
+        return Ok(());
+    }
+
+    ui.status("\nFiles in .rnr/bin that config.yaml doesn't manage:\n");
+    for (name, bytes) in &unmanaged {
+        ui.status(format!("  {:<24} {}", name, format_size(*bytes)));
+    }
+    let savings: u64 = unmanaged.iter().map(|(_, bytes)| bytes).sum();
+    ui.status(format!("\nPotential savings: {}\n", format_size(savings)));
+
+    if !yes {
+        if !std::io::stdin().is_terminal() {
+            bail!("Refusing to delete files without confirmation. Pass --yes to prune.");
+        }
+        let confirmed = Confirm::new()
+            .with_prompt("Delete these files?")
+            .default(false)
+            .interact()?;
+        if !confirmed {
+            ui.status("Nothing deleted.");
+            return Ok(());
+        }
+    }
+
+    for (name, _) in &unmanaged {
+        let path = bin_directory.join(name);
+        fs::remove_file(&path).with_context(|| format!("Failed to remove {}", path.display()))?;
+        ui.status(format!("  Removed {}", name));
     }
-    println!("\nTotal: {}", format_size(total));
+    ui.status(format!("\nFreed {}.", format_size(savings)));
 
     Ok(())
 }
 
-/// Add a platform to existing setup
-fn add_platform(platform_id: &str) -> Result<()> {
-    if !is_initialized()? {
-        bail!("rnr is not initialized. Run 'rnr init' first.");
+/// Size of a file, if it exists
+fn file_size(path: &Path) -> Option<u64> {
+    fs::metadata(path)
+        .ok()
+        .filter(|meta| meta.is_file())
+        .map(|meta| meta.len())
+}
+
+/// Describe a suspicious difference between the expected and actual binary size
+fn size_discrepancy(expected: u64, actual: Option<u64>) -> Option<&'static str> {
+    let Some(actual) = actual else {
+        return Some("binary not found in .rnr/bin");
+    };
+    if actual < expected / 4 {
+        Some("much smaller than expected (placeholder file?)")
+    } else if actual > expected * 4 {
+        Some("much larger than expected (unstripped build?)")
+    } else {
+        None
     }
+}
 
-    let platform = Platform::from_id(platform_id).with_context(|| {
-        format!(
-            "Unknown platform: {}. Valid platforms: linux-amd64, macos-amd64, macos-arm64, windows-amd64, windows-arm64",
-            platform_id
-        )
-    })?;
+/// The initialized project a platform subcommand works on, found the way
+/// every command finds it (see [`rnr_config::locate_project`])
+struct InitializedProject {
+    root: PathBuf,
+}
+
+impl InitializedProject {
+    /// Find the project, failing if rnr isn't initialized
+    fn locate() -> Result<Self> {
+        Ok(Self {
+            root: rnr_config::locate_project()?,
+        })
+    }
+
+    /// Its .rnr/config.yaml
+    fn config_path(&self) -> PathBuf {
+        self.root
+            .join(rnr_config::RNR_DIR)
+            .join(rnr_config::CONFIG_FILE)
+    }
+
+    /// Its .rnr/bin
+    fn bin_dir(&self) -> PathBuf {
+        self.root
+            .join(rnr_config::RNR_DIR)
+            .join(rnr_config::BIN_DIR)
+    }
+
+    /// Lock its .rnr directory for a read-modify-write of the config
+    fn lock(&self) -> Result<ProjectLock> {
+        ProjectLock::acquire(&self.root.join(rnr_config::RNR_DIR))
+    }
+
+    /// Read its .rnr/config.yaml
+    fn load(&self) -> Result<RnrConfig> {
+        RnrConfig::load_from(&self.config_path())
+    }
+
+    /// Write its .rnr/config.yaml
+    fn save(&self, config: &RnrConfig) -> Result<()> {
+        config.save_to(&self.config_path())
+    }
+}
 
-    let mut config = RnrConfig::load()?;
+/// Add a platform to existing setup
+fn add_platform(
+    platform_id: &str,
+    placeholders: bool,
+    ui: &Ui,
+    events: Option<&EventSink>,
+) -> Result<()> {
+    let project = InitializedProject::locate()?;
+
+    let platform = parse_platform(platform_id)?;
+
+    let _lock = project.lock()?;
+    let mut config = project.load()?;
 
     if config.has_platform(platform) {
-        println!("Platform {} is already configured.", platform_id);
+        ui.status(format!("Platform {} is already configured.", platform_id));
         return Ok(());
     }
 
     // Download the binary
-    let bin_directory = bin_dir()?;
+    let bin_directory = project.bin_dir();
     let binary_path = bin_directory.join(platform.binary_name());
 
-    println!("Adding platform {}...", platform_id);
-
-    #[cfg(feature = "network")]
-    {
-        download_binary(platform, &binary_path)?;
-    }
+    ui.status(format!("Adding platform {}...", platform_id));
 
-    #[cfg(not(feature = "network"))]
-    {
-        fs::write(
-            &binary_path,
-            format!("# placeholder for {}\n", platform.id()),
-        )?;
+    let version = config.version.clone();
+    if placeholders {
+        write_placeholder(platform, &bin_directory, ui, events)?;
+        config.record_placeholder(platform, &version);
+    } else {
+        download_binary(platform, &binary_path, events)?;
+        ui.status(format!(
+            "  Downloaded {} ({})",
+            platform.binary_name(),
+            platform.size_display()
+        ));
+        config.record_binary(platform, &version, &binary_path)?;
     }
 
-    println!(
-        "  Downloaded {} ({})",
-        platform.binary_name(),
-        platform.size_display()
-    );
-
     // Update config
-    config.add_platform(platform);
-    config.save()?;
-    println!("  Updated .rnr/config.yaml");
+    project.save(&config)?;
+    ui.status("  Updated .rnr/config.yaml");
+    config_written(events);
 
-    println!("\nPlatform {} added successfully!", platform_id);
+    ui.status(format!("\nPlatform {} added successfully!", platform_id));
+    completed(events, &version);
 
     Ok(())
 }
 
 /// Remove a platform from existing setup
-fn remove_platform(platform_id: &str) -> Result<()> {
-    if !is_initialized()? {
-        bail!("rnr is not initialized. Run 'rnr init' first.");
-    }
+fn remove_platform(platform_id: &str, ui: &Ui) -> Result<()> {
+    let project = InitializedProject::locate()?;
 
-    let platform = Platform::from_id(platform_id).with_context(|| {
-        format!(
-            "Unknown platform: {}. Valid platforms: linux-amd64, macos-amd64, macos-arm64, windows-amd64, windows-arm64",
-            platform_id
-        )
-    })?;
+    let platform = parse_platform(platform_id)?;
 
-    let mut config = RnrConfig::load()?;
+    let _lock = project.lock()?;
+    let mut config = project.load()?;
 
     if !config.has_platform(platform) {
-        println!("Platform {} is not configured.", platform_id);
+        ui.status(format!("Platform {} is not configured.", platform_id));
         return Ok(());
     }
 
@@ -464,23 +1557,862 @@ fn remove_platform(platform_id: &str) -> Result<()> {
         bail!("Cannot remove the last platform. At least one platform must be configured.");
     }
 
-    println!("Removing platform {}...", platform_id);
+    ui.status(format!("Removing platform {}...", platform_id));
 
     // Remove the binary
-    let bin_directory = bin_dir()?;
+    let bin_directory = project.bin_dir();
     let binary_path = bin_directory.join(platform.binary_name());
-    if binary_path.exists() {
-        fs::remove_file(&binary_path)
-            .with_context(|| format!("Failed to remove {}", binary_path.display()))?;
-        println!("  Removed {}", platform.binary_name());
+    for path in [placeholder_path(&binary_path), binary_path] {
+        if path.exists() {
+            fs::remove_file(&path)
+                .with_context(|| format!("Failed to remove {}", path.display()))?;
+            ui.status(format!(
+                "  Removed {}",
+                path.file_name().unwrap().to_string_lossy()
+            ));
+        }
     }
 
     // Update config
     config.remove_platform(platform);
-    config.save()?;
-    println!("  Updated .rnr/config.yaml");
+    project.save(&config)?;
+    ui.status("  Updated .rnr/config.yaml");
 
-    println!("\nPlatform {} removed successfully!", platform_id);
+    ui.status(format!("\nPlatform {} removed successfully!", platform_id));
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::{Cli, Command};
+    use clap::Parser;
+
+    fn init_args(flags: &[&str]) -> InitArgs {
+        let cli = Cli::try_parse_from(["rnr", "init"].iter().chain(flags)).unwrap();
+        match cli.command {
+            Some(Command::Init(args)) => args,
+            other => panic!("expected init, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_enclosing_project() {
+        let temp = tempfile::tempdir().unwrap();
+        let api = temp.path().join("services").join("api");
+        fs::create_dir_all(&api).unwrap();
+        assert_eq!(enclosing_project(&api), None);
+
+        // An rnr.yaml in the directory being initialized doesn't count
+        fs::write(api.join(CONFIG_FILE), "build: echo api").unwrap();
+        assert_eq!(enclosing_project(&api), None);
+
+        fs::write(temp.path().join(CONFIG_FILE), "build: echo root").unwrap();
+        assert_eq!(enclosing_project(&api).as_deref(), Some(temp.path()));
+
+        RnrConfig::new(VERSION, &[])
+            .save_to(&temp.path().join("services/.rnr/config.yaml"))
+            .unwrap();
+        assert_eq!(enclosing_project(&api), Some(temp.path().join("services")));
+    }
+
+    /// Run git in `dir`, asserting it succeeds
+    fn git(dir: &Path, args: &[&str]) {
+        let status = std::process::Command::new("git")
+            .current_dir(dir)
+            .args(["-c", "user.name=dev", "-c", "user.email=dev@example.com"])
+            .args(args)
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .status()
+            .unwrap();
+        assert!(status.success(), "git {:?}", args);
+    }
+
+    #[test]
+    fn test_repo_position() {
+        let temp = tempfile::tempdir().unwrap();
+        let repo = temp.path().join("repo");
+        let sub = repo.join("sub");
+        fs::create_dir_all(&sub).unwrap();
+        assert_eq!(repo_position(&repo), RepoPosition::NotARepo);
+
+        git(&repo, &["init", "--quiet"]);
+        git(
+            &repo,
+            &["commit", "--quiet", "--allow-empty", "-m", "first"],
+        );
+        assert_eq!(repo_position(&repo), RepoPosition::Root);
+        match repo_position(&sub) {
+            RepoPosition::Subdirectory(root) => assert!(same_dir(&root, &repo)),
+            other => panic!("expected a subdirectory, got {:?}", other),
+        }
+
+        // A worktree's .git is a file pointing back into the main repository
+        let worktree = temp.path().join("worktree");
+        git(
+            &repo,
+            &["worktree", "add", "--quiet", worktree.to_str().unwrap()],
+        );
+        assert!(worktree.join(".git").is_file());
+        assert_eq!(repo_position(&worktree), RepoPosition::Root);
+        assert_eq!(repo_position_from_files(&worktree), RepoPosition::Root);
+    }
+
+    #[test]
+    fn test_repo_position_from_files() {
+        let temp = tempfile::tempdir().unwrap();
+        let module = temp.path().join("module");
+        let nested = module.join("src");
+        fs::create_dir_all(&nested).unwrap();
+        assert_eq!(repo_position_from_files(&module), RepoPosition::NotARepo);
+
+        // A submodule's .git file points into the superproject's .git/modules
+        fs::write(module.join(".git"), "gitdir: ../.git/modules/module\n").unwrap();
+        assert_eq!(repo_position_from_files(&module), RepoPosition::NotARepo);
+        fs::create_dir_all(temp.path().join(".git/modules/module")).unwrap();
+        assert_eq!(repo_position_from_files(&module), RepoPosition::Root);
+        assert_eq!(
+            repo_position_from_files(&nested),
+            RepoPosition::Subdirectory(module.clone())
+        );
+        assert_eq!(repo_position_from_files(temp.path()), RepoPosition::Root);
+    }
+
+    #[test]
+    fn test_init_modes() {
+        let cases: &[(&[&str], InitMode)] = &[
+            (&["--show-platforms"], InitMode::ShowPlatforms),
+            (&["--prune"], InitMode::Prune { yes: false }),
+            (&["--prune", "-y"], InitMode::Prune { yes: true }),
+            (&["--download"], InitMode::Download),
+            (
+                &["--platforms", "linux-amd64,macos-arm64", "--dry-run"],
+                InitMode::DryRun,
+            ),
+            (
+                &["--download", "--current-platform-only"],
+                InitMode::Download,
+            ),
+            (
+                &["--add-platform", "linux-amd64"],
+                InitMode::AddPlatform {
+                    platform: "linux-amd64",
+                    placeholders: false,
+                },
+            ),
+            (
+                &["--add-platform", "linux-amd64", "--placeholders"],
+                InitMode::AddPlatform {
+                    platform: "linux-amd64",
+                    placeholders: true,
+                },
+            ),
+            (
+                &["--remove-platform", "macos-arm64"],
+                InitMode::RemovePlatform {
+                    platform: "macos-arm64",
+                },
+            ),
+            (
+                &[],
+                InitMode::Fresh {
+                    placeholders: false,
+                    force: false,
+                    nested: false,
+                    starter: None,
+                },
+            ),
+            (
+                &["--all-platforms", "--force"],
+                InitMode::Fresh {
+                    placeholders: false,
+                    force: true,
+                    nested: false,
+                    starter: None,
+                },
+            ),
+            (
+                &[
+                    "--platforms",
+                    "linux-amd64,macos-arm64",
+                    "--placeholders",
+                    "--nested",
+                ],
+                InitMode::Fresh {
+                    placeholders: true,
+                    force: false,
+                    nested: true,
+                    starter: None,
+                },
+            ),
+            (
+                &["--force", "--starter", "team.yaml"],
+                InitMode::Fresh {
+                    placeholders: false,
+                    force: true,
+                    nested: false,
+                    starter: Some(Path::new("team.yaml")),
+                },
+            ),
+            (
+                &["--current-platform-only", "--placeholders", "--force"],
+                InitMode::Fresh {
+                    placeholders: true,
+                    force: true,
+                    nested: false,
+                    starter: None,
+                },
+            ),
+        ];
+        for (flags, expected) in cases {
+            let args = init_args(flags);
+            assert_eq!(&InitMode::from(&args), expected, "{:?}", flags);
+        }
+    }
+
+    #[test]
+    fn test_starter_resolution_order() {
+        let temp = tempfile::tempdir().unwrap();
+        let user = temp.path().join(".config").join("rnr").join("starter.yaml");
+        let home = || Some(temp.path().to_path_buf());
+        let flag = Path::new("flag.yaml");
+        let env = || Some(OsString::from("env.yaml"));
+
+        // Without a user file, the built-in template is the fallback
+        assert_eq!(Starter::resolve(None, None, home()), Starter::BuiltIn);
+        assert_eq!(Starter::resolve(None, None, None), Starter::BuiltIn);
+
+        fs::create_dir_all(user.parent().unwrap()).unwrap();
+        fs::write(&user, "lint: echo lint\n").unwrap();
+        assert_eq!(Starter::resolve(None, None, home()), Starter::User(user));
+        assert_eq!(
+            Starter::resolve(None, env(), home()),
+            Starter::Env(PathBuf::from("env.yaml"))
+        );
+        assert_eq!(
+            Starter::resolve(Some(flag), env(), home()),
+            Starter::Flag(flag.to_path_buf())
+        );
+        // An empty variable counts as unset
+        assert!(matches!(
+            Starter::resolve(None, Some(OsString::new()), home()),
+            Starter::User(_)
+        ));
+    }
+
+    #[test]
+    fn test_starter_content() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("starter.yaml");
+        fs::write(
+            &path,
+            "ci:\n  description: CI for ${project_name}\n  cmd: echo ${env.CI}\n",
+        )
+        .unwrap();
+        assert_eq!(
+            Starter::Env(path.clone()).content("widgets").unwrap(),
+            "ci:\n  description: CI for widgets\n  cmd: echo ${env.CI}\n"
+        );
+        assert!(Starter::BuiltIn
+            .content("widgets")
+            .unwrap()
+            .contains("hello:"));
+
+        fs::write(&path, "ci:\n  steps: not-a-list\n").unwrap();
+        let err = Starter::Flag(path.clone()).content("widgets").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "Starter rnr.yaml from --starter {} is not a valid task file",
+                path.display()
+            )
+        );
+
+        let missing = temp.path().join("missing.yaml");
+        let err = Starter::Env(missing.clone())
+            .content("widgets")
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "Failed to read starter rnr.yaml from RNR_STARTER ({})",
+                missing.display()
+            )
+        );
+    }
+
+    /// Run the Unix wrapper against a fake `.rnr/bin` layout, faking `uname`
+    #[cfg(unix)]
+    fn run_unix_wrapper(
+        os: &str,
+        arch: &str,
+        binaries: &[&str],
+        env: &[(&str, &str)],
+    ) -> std::process::Output {
+        let scripts: Vec<(&str, String)> = binaries
+            .iter()
+            .map(|name| {
+                let script = format!(
+                    "#!/bin/sh\necho \"{} $@\"\n[ -z \"$SHOW_WRAPPER_DIR\" ] || {{ echo \"$RNR_WRAPPER_DIR\"; ls \"$RNR_WRAPPER_DIR\"; }}\n",
+                    name
+                );
+                (*name, script)
+            })
+            .collect();
+        let files: Vec<(&str, &[u8], u32)> = scripts
+            .iter()
+            .map(|(name, script)| (*name, script.as_bytes(), 0o755))
+            .collect();
+        run_unix_wrapper_with(os, arch, &files, env)
+    }
+
+    /// Run the Unix wrapper with `.rnr/bin` holding `(name, contents, mode)` files
+    #[cfg(unix)]
+    fn run_unix_wrapper_with(
+        os: &str,
+        arch: &str,
+        files: &[(&str, &[u8], u32)],
+        env: &[(&str, &str)],
+    ) -> std::process::Output {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = tempfile::tempdir().unwrap();
+        let write_script = |path: &Path, content: &str| {
+            fs::write(path, content).unwrap();
+            fs::set_permissions(path, fs::Permissions::from_mode(0o755)).unwrap();
+        };
+
+        let bin = temp.path().join(".rnr/bin");
+        fs::create_dir_all(&bin).unwrap();
+        for (name, contents, mode) in files {
+            fs::write(bin.join(name), contents).unwrap();
+            fs::set_permissions(bin.join(name), fs::Permissions::from_mode(*mode)).unwrap();
+        }
+        write_script(&temp.path().join("rnr"), UNIX_WRAPPER);
+
+        let fake_path = temp.path().join("fake-path");
+        fs::create_dir_all(&fake_path).unwrap();
+        write_script(
+            &fake_path.join("uname"),
+            &format!(
+                "#!/bin/sh\ncase \"$1\" in -s) echo {} ;; -m) echo {} ;; esac\n",
+                os, arch
+            ),
+        );
+        let path = format!(
+            "{}:{}",
+            fake_path.display(),
+            std::env::var("PATH").unwrap_or_default()
+        );
+
+        std::process::Command::new("sh")
+            .arg(temp.path().join("rnr"))
+            .arg("build")
+            .env("PATH", path)
+            .env_remove("RNR_NO_ARCH_FALLBACK")
+            .envs(env.iter().copied())
+            .output()
+            .unwrap()
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_unix_wrapper_uses_native_binary() {
+        let output = run_unix_wrapper(
+            "Darwin",
+            "arm64",
+            &["rnr-macos-arm64", "rnr-macos-amd64"],
+            &[],
+        );
+        assert!(output.status.success());
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "rnr-macos-arm64 build\n"
+        );
+        assert!(output.stderr.is_empty());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_unix_wrapper_exports_its_directory() {
+        let output = run_unix_wrapper(
+            "Linux",
+            "x86_64",
+            &["rnr-linux-amd64"],
+            &[("SHOW_WRAPPER_DIR", "1")],
+        );
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let mut lines = stdout.lines();
+        assert_eq!(lines.next(), Some("rnr-linux-amd64 build"));
+        assert!(Path::new(lines.next().unwrap()).is_absolute(), "{}", stdout);
+        // The wrapper's own directory, not the test's working directory
+        assert_eq!(lines.collect::<Vec<_>>(), vec!["fake-path", "rnr"]);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_unix_wrapper_falls_back_to_amd64() {
+        let output = run_unix_wrapper("Darwin", "arm64", &["rnr-macos-amd64"], &[]);
+        assert!(output.status.success());
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "rnr-macos-amd64 build\n"
+        );
+        assert!(String::from_utf8_lossy(&output.stderr).contains("under emulation"));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_unix_wrapper_prefers_native_windows_binary_under_emulation() {
+        let binaries = ["rnr-windows-arm64.exe", "rnr-windows-amd64.exe"];
+        let arm = "ARMv8 (64-bit) Family 8 Model 1 Revision 201, Qualcomm Technologies Inc";
+        for env in [
+            vec![
+                ("PROCESSOR_ARCHITECTURE", "AMD64"),
+                ("PROCESSOR_IDENTIFIER", arm),
+            ],
+            vec![
+                ("PROCESSOR_ARCHITECTURE", "x86"),
+                ("PROCESSOR_ARCHITEW6432", "ARM64"),
+            ],
+        ] {
+            let output = run_unix_wrapper("MINGW64_NT-10.0-22631", "x86_64", &binaries, &env);
+            assert!(output.status.success());
+            assert_eq!(
+                String::from_utf8_lossy(&output.stdout),
+                "rnr-windows-arm64.exe build\n",
+                "{:?}",
+                env
+            );
+        }
+
+        // Without the native binary, the emulated one runs with a notice
+        let env = [
+            ("PROCESSOR_ARCHITECTURE", "AMD64"),
+            ("PROCESSOR_IDENTIFIER", arm),
+        ];
+        let output = run_unix_wrapper(
+            "MINGW64_NT-10.0-22631",
+            "x86_64",
+            &["rnr-windows-amd64.exe"],
+            &env,
+        );
+        assert!(output.status.success());
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "rnr-windows-amd64.exe build\n"
+        );
+        assert!(String::from_utf8_lossy(&output.stderr).contains("under emulation"));
+
+        // An Intel machine runs the amd64 binary
+        let intel = "Intel64 Family 6 Model 154 Stepping 3, GenuineIntel";
+        let env = [
+            ("PROCESSOR_ARCHITECTURE", "AMD64"),
+            ("PROCESSOR_IDENTIFIER", intel),
+        ];
+        let output = run_unix_wrapper("MINGW64_NT-10.0-22631", "x86_64", &binaries, &env);
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "rnr-windows-amd64.exe build\n"
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_unix_wrapper_fallback_can_be_disabled() {
+        let output = run_unix_wrapper(
+            "Darwin",
+            "arm64",
+            &["rnr-macos-amd64"],
+            &[("RNR_NO_ARCH_FALLBACK", "1")],
+        );
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr).contains("not configured for macos-arm64"));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_unix_wrapper_no_fallback_on_linux() {
+        let output = run_unix_wrapper("Linux", "aarch64", &["rnr-linux-amd64"], &[]);
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr).contains("not configured for linux-arm64"));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_unix_wrapper_explains_placeholders() {
+        let output = run_unix_wrapper("Linux", "x86_64", &["rnr-linux-amd64.placeholder"], &[]);
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains("rnr-linux-amd64.placeholder is a placeholder"),
+            "{}",
+            stderr
+        );
+        assert!(stderr.contains("rnr upgrade"), "{}", stderr);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_unix_wrapper_rejects_placeholder_text() {
+        let placeholder: &[u8] = b"# rnr placeholder: no release was available\n";
+        let output = run_unix_wrapper_with(
+            "Linux",
+            "x86_64",
+            &[("rnr-linux-amd64", placeholder, 0o755)],
+            &[],
+        );
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains(".rnr/bin/rnr-linux-amd64 looks like a placeholder"),
+            "{}",
+            stderr
+        );
+        assert!(stderr.contains("with network access"), "{}", stderr);
+        assert!(output.stdout.is_empty());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_unix_wrapper_rejects_empty_binary() {
+        let output =
+            run_unix_wrapper_with("Linux", "x86_64", &[("rnr-linux-amd64", b"", 0o755)], &[]);
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("rnr-linux-amd64 is empty"), "{}", stderr);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_unix_wrapper_rejects_foreign_and_unexecutable_binaries() {
+        let macho: &[u8] = &[0xcf, 0xfa, 0xed, 0xfe, 0x0c, 0x00, 0x00, 0x01];
+        let output =
+            run_unix_wrapper_with("Linux", "x86_64", &[("rnr-linux-amd64", macho, 0o755)], &[]);
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains("is a macos binary, not one for linux"),
+            "{}",
+            stderr
+        );
+
+        let script: &[u8] = b"#!/bin/sh\necho ran\n";
+        let output = run_unix_wrapper_with(
+            "Linux",
+            "x86_64",
+            &[("rnr-linux-amd64", script, 0o644)],
+            &[],
+        );
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("is not executable"), "{}", stderr);
+        assert!(
+            stderr.contains("chmod +x .rnr/bin/rnr-linux-amd64"),
+            "{}",
+            stderr
+        );
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn test_windows_wrapper_prefers_native_binary() {
+        let temp = tempfile::tempdir().unwrap();
+        let bin = temp.path().join(".rnr").join("bin");
+        fs::create_dir_all(&bin).unwrap();
+        // Two console programs that say which one ran
+        let cmd = std::env::var("ComSpec").unwrap();
+        fs::copy(&cmd, bin.join("rnr-windows-amd64.exe")).unwrap();
+        fs::copy(&cmd, bin.join("rnr-windows-arm64.exe")).unwrap();
+        fs::write(temp.path().join("rnr.cmd"), WINDOWS_WRAPPER).unwrap();
+
+        let arm = "ARMv8 (64-bit) Family 8 Model 1 Revision 201, Qualcomm Technologies Inc";
+        let intel = "Intel64 Family 6 Model 154 Stepping 3, GenuineIntel";
+        let run = |vars: &[(&str, &str)]| {
+            let mut command = std::process::Command::new("cmd");
+            command
+                .args(["/C", "rnr.cmd", "/C", "echo", "%BINARY%"])
+                .current_dir(temp.path())
+                .env_remove("PROCESSOR_ARCHITEW6432")
+                .env("PROCESSOR_IDENTIFIER", intel)
+                .envs(vars.iter().copied());
+            let output = command.output().unwrap();
+            assert!(output.status.success(), "{:?}", vars);
+            String::from_utf8_lossy(&output.stdout).into_owned()
+        };
+
+        for vars in [
+            &[("PROCESSOR_ARCHITECTURE", "ARM64")][..],
+            &[
+                ("PROCESSOR_ARCHITECTURE", "x86"),
+                ("PROCESSOR_ARCHITEW6432", "ARM64"),
+            ],
+            &[
+                ("PROCESSOR_ARCHITECTURE", "AMD64"),
+                ("PROCESSOR_IDENTIFIER", arm),
+            ],
+        ] {
+            assert!(run(vars).contains("rnr-windows-arm64.exe"), "{:?}", vars);
+        }
+        let stdout = run(&[("PROCESSOR_ARCHITECTURE", "AMD64")]);
+        assert!(stdout.contains("rnr-windows-amd64.exe"), "{}", stdout);
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn test_windows_wrapper_falls_back_to_amd64() {
+        let temp = tempfile::tempdir().unwrap();
+        let bin = temp.path().join(".rnr").join("bin");
+        fs::create_dir_all(&bin).unwrap();
+        // Any console program works as a stand-in binary
+        let cmd = std::env::var("ComSpec").unwrap();
+        fs::copy(&cmd, bin.join("rnr-windows-amd64.exe")).unwrap();
+        fs::write(temp.path().join("rnr.cmd"), WINDOWS_WRAPPER).unwrap();
+
+        let run = |disable: bool| {
+            let mut command = std::process::Command::new("cmd");
+            command
+                .args(["/C", "rnr.cmd", "/C", "echo", "ran"])
+                .current_dir(temp.path())
+                .env("PROCESSOR_ARCHITECTURE", "ARM64")
+                .env_remove("RNR_NO_ARCH_FALLBACK");
+            if disable {
+                command.env("RNR_NO_ARCH_FALLBACK", "1");
+            }
+            command.output().unwrap()
+        };
+
+        let output = run(false);
+        assert!(output.status.success());
+        assert!(String::from_utf8_lossy(&output.stdout).contains("ran"));
+        assert!(String::from_utf8_lossy(&output.stderr).contains("under emulation"));
+
+        let output = run(true);
+        assert!(!output.status.success());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_system_wrapper_runs_rnr_from_path() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = tempfile::tempdir().unwrap();
+        let (ui, _capture) = Ui::capture(true);
+        create_wrapper_scripts(temp.path(), Mode::System, &ui, None).unwrap();
+        let installed = temp.path().join("installed");
+        fs::create_dir_all(&installed).unwrap();
+        let run = |path: &std::ffi::OsStr| {
+            std::process::Command::new("sh")
+                .arg(temp.path().join("rnr"))
+                .arg("build")
+                .env("PATH", path)
+                .output()
+                .unwrap()
+        };
+
+        let path =
+            std::env::join_paths([installed.clone(), "/usr/bin".into(), "/bin".into()]).unwrap();
+
+        let output = run(&path);
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr).contains("none was found"));
+
+        let rnr = installed.join("rnr");
+        fs::write(&rnr, "#!/bin/sh\necho \"system $@ $RNR_WRAPPER_DIR\"\n").unwrap();
+        fs::set_permissions(&rnr, fs::Permissions::from_mode(0o755)).unwrap();
+        let output = run(&path);
+        assert!(output.status.success());
+        let dir = temp.path().canonicalize().unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout).trim(),
+            format!("system build {}", dir.display())
+        );
+    }
+
+    #[test]
+    fn test_wrappers_written_with_lf_on_windows_paths() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().join("C:\\Users\\dev\\project");
+        fs::create_dir_all(&root).unwrap();
+
+        let (ui, _capture) = Ui::capture(true);
+        create_wrapper_scripts(&root, Mode::Binaries, &ui, None).unwrap();
+
+        let wrapper = fs::read(root.join("rnr")).unwrap();
+        assert!(wrapper.starts_with(b"#!/bin/sh\n"));
+        assert!(!wrapper.contains(&b'\r'));
+        let attributes = fs::read_to_string(root.join(".gitattributes")).unwrap();
+        assert!(attributes.lines().any(|line| line == "rnr text eol=lf"));
+    }
+
+    #[test]
+    fn test_gitattributes_managed_block() {
+        let block = with_managed_block("");
+        assert_eq!(
+            block,
+            format!(
+                "{}\nrnr text eol=lf\n{}\n",
+                GITATTRIBUTES_BEGIN, GITATTRIBUTES_END
+            )
+        );
+
+        // Appended after the user's rules, and idempotent
+        let user = "*.png binary";
+        let updated = with_managed_block(user);
+        assert_eq!(updated, format!("*.png binary\n{}", block));
+        assert_eq!(with_managed_block(&updated), updated);
+
+        // A stale block is replaced in place
+        let stale = format!(
+            "*.png binary\n{}\nrnr -text\n{}\n*.sh text eol=lf\n",
+            GITATTRIBUTES_BEGIN, GITATTRIBUTES_END
+        );
+        assert_eq!(
+            with_managed_block(&stale),
+            format!("*.png binary\n{}*.sh text eol=lf\n", block)
+        );
+    }
+
+    #[test]
+    fn test_size_discrepancy() {
+        let expected = Platform::LinuxAmd64.size_bytes();
+        assert_eq!(size_discrepancy(expected, Some(expected - 10 * 1024)), None);
+        assert!(size_discrepancy(expected, Some(500))
+            .unwrap()
+            .contains("placeholder"));
+        assert!(size_discrepancy(expected, Some(expected * 10))
+            .unwrap()
+            .contains("larger"));
+        assert!(size_discrepancy(expected, None)
+            .unwrap()
+            .contains("not found"));
+    }
+
+    #[test]
+    fn test_unmanaged_files() {
+        let temp = tempfile::tempdir().unwrap();
+        let bin = temp.path();
+        fs::write(bin.join(Platform::LinuxAmd64.binary_name()), "linux").unwrap();
+        fs::write(bin.join(Platform::MacosAmd64.binary_name()), "mac").unwrap();
+        fs::write(bin.join("rnr-linux-amd64.tar.gz"), "archive").unwrap();
+        fs::write(bin.join("rnr-linux-amd64.old"), "replaced").unwrap();
+        fs::write(bin.join(".DS_Store"), "").unwrap();
+
+        let config = RnrConfig::new("0.1.0", &[Platform::LinuxAmd64, Platform::WindowsAmd64]);
+        assert_eq!(
+            config.unmanaged_files(bin),
+            vec![
+                ("rnr-linux-amd64.tar.gz".to_string(), 7),
+                ("rnr-macos-amd64".to_string(), 3)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_report_platforms() {
+        let temp = tempfile::tempdir().unwrap();
+        let bin = temp.path();
+        fs::write(bin.join(Platform::MacosAmd64.binary_name()), "mac").unwrap();
+
+        let (ui, capture) = Ui::capture(false);
+        let config = RnrConfig::new("0.1.0", &[Platform::LinuxAmd64]);
+        report_platforms(&config, bin, &ui);
+
+        let output = capture.stdout();
+        assert!(output.starts_with("\nConfigured platforms:\n\n  linux-amd64"));
+        assert!(output.contains("missing  (expected ~"));
+        assert!(output.contains("! binary not found in .rnr/bin"));
+        assert!(output.contains("1 file(s) in .rnr/bin that config.yaml doesn't manage (3 bytes)"));
+        assert_eq!(capture.stderr(), "");
+    }
+
+    #[test]
+    fn test_parse_platform_lists_valid_ids() {
+        assert_eq!(parse_platform("macos-arm64").unwrap(), Platform::MacosArm64);
+        assert_eq!(
+            parse_platform("linux-arm64").unwrap_err().to_string(),
+            "Unknown platform: linux-arm64. Valid platforms: linux-amd64, macos-amd64, \
+             macos-arm64, windows-amd64, windows-arm64"
+        );
+    }
+
+    #[test]
+    fn test_preview_platform_changes() {
+        let temp = tempfile::tempdir().unwrap();
+        let bin = temp.path();
+        fs::write(bin.join(Platform::LinuxAmd64.binary_name()), "linux").unwrap();
+        let sizes = BinarySizes {
+            release: Some("0.4.1".to_string()),
+            sizes: vec![(Platform::MacosArm64, 2048)],
+        };
+
+        let (ui, capture) = Ui::capture(false);
+        let config = RnrConfig::new("0.1.0", &[Platform::LinuxAmd64, Platform::WindowsAmd64]);
+        preview_platform_changes(
+            &config,
+            bin,
+            &[Platform::MacosArm64, Platform::WindowsAmd64],
+            &sizes,
+            &ui,
+        );
+        let output = capture.stdout();
+        assert!(
+            output.contains("  - linux-amd64      -5 bytes\n"),
+            "{}",
+            output
+        );
+        assert!(
+            output.contains("  + macos-arm64      +2 KB\n"),
+            "{}",
+            output
+        );
+        assert!(
+            output.contains("    windows-amd64    unchanged\n"),
+            "{}",
+            output
+        );
+        assert!(output.contains("Repository size change: +1 KB\n(sizes from the v0.4.1 release)"));
+        assert!(output.contains(
+            "  rnr init --add-platform macos-arm64\n  rnr init --remove-platform linux-amd64\n"
+        ));
+
+        let (ui, capture) = Ui::capture(false);
+        preview_platform_changes(&config, bin, &config.get_platforms(), &sizes, &ui);
+        assert!(capture.stdout().contains("nothing would change"));
+    }
+
+    #[test]
+    fn test_format_change() {
+        assert_eq!(format_change(0), "+0 bytes");
+        assert_eq!(format_change(2048), "+2 KB");
+        assert_eq!(format_change(-300), "-300 bytes");
+    }
+
+    #[test]
+    fn test_prune_binaries() {
+        let temp = tempfile::tempdir().unwrap();
+        let bin = temp.path();
+        fs::write(bin.join(Platform::LinuxAmd64.binary_name()), "linux").unwrap();
+        fs::write(bin.join(Platform::MacosAmd64.binary_name()), "mac").unwrap();
+
+        let (ui, capture) = Ui::capture(false);
+        let config = RnrConfig::new("0.1.0", &[Platform::LinuxAmd64]);
+        prune_binaries(&config, bin, true, &ui).unwrap();
+
+        assert!(!bin.join(Platform::MacosAmd64.binary_name()).exists());
+        assert!(bin.join(Platform::LinuxAmd64.binary_name()).exists());
+        assert!(capture
+            .stdout()
+            .contains("  Removed rnr-macos-amd64\n\nFreed 3 bytes.\n"));
+
+        let (ui, capture) = Ui::capture(false);
+        prune_binaries(&config, bin, true, &ui).unwrap();
+        assert_eq!(
+            capture.stdout(),
+            "Nothing to prune: .rnr/bin only contains files config.yaml manages.\n"
+        );
+    }
+}
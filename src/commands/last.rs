@@ -0,0 +1,173 @@
+use anyhow::Result;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::commands::list;
+use crate::config;
+use crate::history::{self, RunRecord, Status};
+use crate::project::Project;
+use crate::ui::Ui;
+
+/// Run the last command
+pub fn run(project: &Project, ui: &Ui) -> Result<()> {
+    let root = project.root()?;
+    let Some(record) = history::load(root)? else {
+        ui.line("No previous run recorded.");
+        return Ok(());
+    };
+
+    let stale = history::config_hash(root)? != record.config_hash;
+    ui.print(render(&record, now(), Some(list::terminal_width())));
+    if stale {
+        ui.line(format!(
+            "\nNote: {} has changed since this run; step numbers may no longer match.",
+            config::CONFIG_FILE
+        ));
+    }
+    if record.tasks.iter().any(|task| task.status.needs_rerun()) {
+        ui.line("\nRun 'rnr rerun-failed' to retry what failed.");
+    }
+    Ok(())
+}
+
+/// Current time in seconds since the Unix epoch
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Render the summary of a recorded run, cutting step labels to fit
+/// `width` columns (`None` keeps them whole)
+fn render(record: &RunRecord, now: u64, width: Option<usize>) -> String {
+    let mut out = format!(
+        "\nLast run ({}):\n",
+        format_ago(now.saturating_sub(record.finished_at))
+    );
+
+    for task in &record.tasks {
+        let mut invocation = task.name.clone();
+        for arg in &task.args {
+            invocation.push(' ');
+            invocation.push_str(arg);
+        }
+        let duration = if task.status == Status::NotRun {
+            String::new()
+        } else {
+            format!(" in {:.1}s", task.duration_ms as f64 / 1000.0)
+        };
+        out.push_str(&format!(
+            "\n  {}  {}{}\n",
+            invocation,
+            task.status.label(),
+            duration
+        ));
+
+        let position_width = task
+            .steps
+            .iter()
+            .map(|step| step.position().len())
+            .max()
+            .unwrap_or(0);
+        for step in &task.steps {
+            let label = match width {
+                Some(columns) => list::truncate(&step.label, label_width(columns, position_width)),
+                None => step.label.clone(),
+            };
+            out.push_str(&format!(
+                "    {:<width$}  {:<7}  {}\n",
+                step.position(),
+                step.status.label(),
+                label,
+                width = position_width
+            ));
+        }
+        if let Some(error) = &task.error {
+            let error = error.replace('\n', "\n    ");
+            out.push_str(&format!("    Error: {}\n", error));
+        }
+    }
+    out
+}
+
+/// Columns left for a step's label in `columns`, after its position and
+/// status (never fewer than [`list::MIN_LABEL_WIDTH`])
+fn label_width(columns: usize, position_width: usize) -> usize {
+    columns
+        .saturating_sub(4 + position_width + 2 + 7 + 2)
+        .max(list::MIN_LABEL_WIDTH)
+}
+
+/// Human-readable age, e.g. `5 minutes ago`
+fn format_ago(seconds: u64) -> String {
+    let (value, unit) = match seconds {
+        0..=59 => return "just now".to_string(),
+        60..=3599 => (seconds / 60, "minute"),
+        3600..=86399 => (seconds / 3600, "hour"),
+        _ => (seconds / 86400, "day"),
+    };
+    let plural = if value == 1 { "" } else { "s" };
+    format!("{} {}{} ago", value, unit, plural)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::{StepRecord, TaskRecord};
+
+    #[test]
+    fn test_render() {
+        let record = RunRecord {
+            config_hash: "abc".to_string(),
+            finished_at: 1000,
+            tasks: vec![TaskRecord {
+                name: "ci".to_string(),
+                args: vec!["--fast".to_string()],
+                status: Status::Failed,
+                duration_ms: 1500,
+                error: Some("Command failed with exit code 1".to_string()),
+                steps: vec![
+                    StepRecord {
+                        index: 1,
+                        branch: Some(1),
+                        label: "$ cargo test".to_string(),
+                        status: Status::Failed,
+                        duration_ms: 1200,
+                        failure: None,
+                    },
+                    StepRecord {
+                        index: 2,
+                        branch: None,
+                        label: "task build".to_string(),
+                        status: Status::NotRun,
+                        duration_ms: 0,
+                        failure: None,
+                    },
+                ],
+            }],
+        };
+
+        let output = render(&record, 1000 + 120, None);
+        assert!(output.contains("Last run (2 minutes ago)"));
+        assert!(output.contains("ci --fast  FAILED in 1.5s"));
+        assert!(output.contains("1.1  FAILED   $ cargo test"));
+        assert!(output.contains("2    not run  task build"));
+        assert!(output.contains("Error: Command failed with exit code 1"));
+
+        // Long commands are cut to the terminal's width
+        let mut record = record;
+        record.tasks[0].steps[0].label = format!("$ docker run {}", "--flag ".repeat(40));
+        let output = render(&record, 1000, Some(60));
+        let line = output.lines().find(|line| line.contains("docker")).unwrap();
+        assert!(line.chars().count() <= 60, "{}", line);
+        assert!(line.ends_with('…'));
+    }
+
+    #[test]
+    fn test_format_ago() {
+        assert_eq!(format_ago(5), "just now");
+        assert_eq!(format_ago(60), "1 minute ago");
+        assert_eq!(format_ago(7200), "2 hours ago");
+        assert_eq!(format_ago(3 * 86400), "3 days ago");
+    }
+}
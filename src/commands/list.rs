@@ -1,39 +1,396 @@
 use anyhow::Result;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 
-use crate::config::{Config, TaskDef};
+use crate::config::{Config, TaskDef, CONFIG_FILE};
+use crate::project::Project;
+use crate::ui::Ui;
 
-/// Run the list command
-pub fn run() -> Result<()> {
-    let config = Config::load()?;
+/// How many directories below the project root `--recursive` looks for rnr.yaml files
+const MAX_DEPTH: usize = 6;
 
-    println!("\nAvailable tasks:\n");
+/// Directories `--recursive` never looks inside
+const SKIPPED_DIRS: &[&str] = &[".git", ".rnr", "node_modules", "target"];
 
+/// Terminal width assumed when output isn't a terminal and `COLUMNS` is unset
+const DEFAULT_WIDTH: usize = 80;
+
+/// Longest task name kept on the same line as its description
+const NAME_COLUMN_MAX: usize = 24;
+
+/// Fewest description characters shown before truncating
+const MIN_DESCRIPTION: usize = 12;
+
+/// Run the list command, fitting descriptions to the terminal unless `wide`,
+/// and showing where each task is defined with `sources`
+pub fn run(project: &Project, recursive: bool, wide: bool, sources: bool, ui: &Ui) -> Result<()> {
+    let config = project.config()?;
+    let width = (!wide).then(terminal_width);
+    let root = project.root()?;
+    let sources = sources.then_some(root);
+    show(&config, width, sources, ui)?;
+    for name in crate::cli::shadowed_tasks(&config.task_names()) {
+        ui.warn(shadowed_warning(name));
+    }
+    if recursive {
+        show_nested(root, width, sources.is_some(), ui);
+    }
+    Ok(())
+}
+
+/// Fewest columns a cut-down step label is given, however narrow the terminal
+pub const MIN_LABEL_WIDTH: usize = 20;
+
+/// Width of the terminal on stdout, else `COLUMNS`, else [`DEFAULT_WIDTH`]
+pub fn terminal_width() -> usize {
+    console::Term::stdout()
+        .size_checked()
+        .map(|(_, columns)| columns as usize)
+        .or_else(|| std::env::var("COLUMNS").ok()?.parse().ok())
+        .filter(|&columns| columns > 0)
+        .unwrap_or(DEFAULT_WIDTH)
+}
+
+/// Print the tasks of each rnr.yaml below `root`, under its relative path
+fn show_nested(root: &Path, width: Option<usize>, sources: bool, ui: &Ui) {
+    let mut out = Vec::new();
+    for path in nested_configs(root) {
+        let config = match Config::load_from(&path) {
+            Ok(config) => config,
+            Err(e) => {
+                ui.warn(format!("{:#}", anyhow::Error::from(e)));
+                continue;
+            }
+        };
+        if config.tasks.is_empty() {
+            continue;
+        }
+        let dir = path.parent().unwrap_or(root);
+        let relative = dir.strip_prefix(root).unwrap_or(dir);
+        // Writing to a Vec can't fail
+        let _ = writeln!(out, "Tasks in {}:\n", relative.display());
+        let _ = render_tasks(&config, width, sources.then_some(root), &mut out);
+    }
+    ui.print(String::from_utf8_lossy(&out));
+}
+
+/// rnr.yaml files in directories below `root`, found in one walk that skips
+/// dependency and build directories and stops at [`MAX_DEPTH`]
+fn nested_configs(root: &Path) -> Vec<PathBuf> {
+    let mut found = Vec::new();
+    let mut pending = vec![(root.to_path_buf(), 0)];
+    while let Some((dir, depth)) = pending.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        let mut subdirs: Vec<PathBuf> = entries
+            .flatten()
+            .filter(|entry| entry.file_type().is_ok_and(|t| t.is_dir()))
+            .filter(|entry| !SKIPPED_DIRS.contains(&&*entry.file_name().to_string_lossy()))
+            .map(|entry| entry.path())
+            .collect();
+        if depth > 0 && dir.join(CONFIG_FILE).is_file() {
+            found.push(dir.join(CONFIG_FILE));
+        }
+        if depth < MAX_DEPTH {
+            subdirs.sort();
+            pending.extend(subdirs.into_iter().rev().map(|subdir| (subdir, depth + 1)));
+        }
+    }
+    found
+}
+
+/// Warning for a task that `rnr <name>` can't reach
+pub fn shadowed_warning(name: &str) -> String {
+    format!(
+        "task '{}' is shadowed by the '{}' subcommand; run it with 'rnr run {}'",
+        name, name, name
+    )
+}
+
+/// Print the task list for `config`
+fn show(config: &Config, width: Option<usize>, sources: Option<&Path>, ui: &Ui) -> Result<()> {
+    let mut out = Vec::new();
+    render(config, width, sources, &mut out)?;
+    ui.print(String::from_utf8_lossy(&out));
+    Ok(())
+}
+
+/// Write the task list for `config`, truncating descriptions to fit `width`
+/// columns (`None` keeps them whole). With `sources`, each task's file and
+/// line are shown, relative to that directory.
+pub fn render(
+    config: &Config,
+    width: Option<usize>,
+    sources: Option<&Path>,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    writeln!(out, "\nAvailable tasks:\n")?;
+    render_tasks(config, width, sources, out)
+}
+
+/// Write `config`'s tasks, aligned, with their descriptions. Names longer
+/// than [`NAME_COLUMN_MAX`] get a line to themselves.
+fn render_tasks(
+    config: &Config,
+    width: Option<usize>,
+    sources: Option<&Path>,
+    out: &mut impl Write,
+) -> io::Result<()> {
     let task_names = config.task_names();
 
     if task_names.is_empty() {
-        println!("  No tasks defined in rnr.yaml");
+        writeln!(out, "  No tasks defined in rnr.yaml")?;
         return Ok(());
     }
 
-    // Find the longest task name for alignment
-    let max_len = task_names.iter().map(|n| n.len()).max().unwrap_or(0);
+    // Tasks that take arguments are marked with `*`
+    let labels: Vec<String> = task_names
+        .iter()
+        .map(|name| {
+            let takes_args = config
+                .get_task(name)
+                .is_some_and(|task| !task.arg_defs().is_empty());
+            if takes_args {
+                format!("{}*", name)
+            } else {
+                name.to_string()
+            }
+        })
+        .collect();
 
-    for name in task_names {
-        let description = get_task_description(&config, name);
-        match description {
-            Some(desc) => println!("  {:<width$}  {}", name, desc, width = max_len),
-            None => println!("  {}", name),
+    // Align descriptions after the longest name that fits the column
+    let column = labels
+        .iter()
+        .map(|label| label.chars().count())
+        .filter(|&len| len <= NAME_COLUMN_MAX)
+        .max()
+        .unwrap_or(0);
+    // With --sources, `rnr.yaml:12` goes in a column of its own
+    let locations: Vec<Option<String>> = task_names
+        .iter()
+        .map(|name| sources.and_then(|root| config.location(name, None, root)))
+        .collect();
+    let source_column = sources.map(|_| {
+        locations
+            .iter()
+            .flatten()
+            .map(|location| location.chars().count() + 2)
+            .max()
+            .unwrap_or(0)
+    });
+    let room = width.map(|width| {
+        width
+            .saturating_sub(column + 4 + source_column.unwrap_or(0))
+            .max(MIN_DESCRIPTION)
+    });
+
+    for ((name, label), location) in task_names.iter().zip(&labels).zip(&locations) {
+        let source = source_column
+            .map(|width| format!("{:<width$}", location.as_deref().unwrap_or_default()));
+        let Some(description) = get_task_description(config, name) else {
+            match source {
+                Some(source) => writeln!(out, "  {:<column$}  {}", label, source.trim_end())?,
+                None => writeln!(out, "  {}", label)?,
+            }
+            continue;
+        };
+        let description = match room {
+            Some(room) => truncate(&description, room),
+            None => description,
+        };
+        let description = format!("{}{}", source.unwrap_or_default(), description);
+        if label.chars().count() > column {
+            writeln!(out, "  {}", label)?;
+            writeln!(out, "  {:<column$}  {}", "", description)?;
+        } else {
+            writeln!(out, "  {:<column$}  {}", label, description)?;
         }
     }
 
-    println!();
-    Ok(())
+    if labels.iter().any(|label| label.ends_with('*')) {
+        writeln!(out, "\n  * takes arguments (see 'rnr help <task>')")?;
+    }
+
+    writeln!(out)
+}
+
+/// `text` cut to `max` characters, ending in an ellipsis when shortened
+pub fn truncate(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        return text.to_string();
+    }
+    let kept: String = text.chars().take(max.saturating_sub(1)).collect();
+    format!("{}…", kept.trim_end())
 }
 
 /// Get the description for a task, if any
 fn get_task_description(config: &Config, name: &str) -> Option<String> {
-    match config.get_task(name)? {
+    let task = config.get_task(name)?;
+    let description = match task {
         TaskDef::Shorthand(_) => None,
         TaskDef::Full(task) => task.description.clone(),
+    };
+    // Deprecated and restricted tasks say so first, so it survives truncation
+    let mut markers = Vec::new();
+    if let Some(deprecated) = task.deprecated() {
+        markers.push(match deprecated.replaced_by() {
+            Some(replacement) => format!("(deprecated, use '{}')", replacement),
+            None => "(deprecated)".to_string(),
+        });
+    }
+    if let Some(contexts) = task.contexts() {
+        markers.push(format!("[{}]", contexts));
+    }
+    if let Some(window) = task.not_between() {
+        markers.push(format!("[not {}]", window));
+    }
+    if markers.is_empty() {
+        return description;
+    }
+    markers.extend(description);
+    Some(markers.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(yaml: &str) -> Config {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn test_show_lists_tasks() {
+        let (ui, capture) = Ui::capture(false);
+        let config = config(
+            r#"
+build: cargo build
+test:
+  description: Run the tests
+  cmd: cargo test
+"#,
+        );
+        show(&config, Some(80), None, &ui).unwrap();
+        assert_eq!(
+            capture.stdout(),
+            "\nAvailable tasks:\n\n  build\n  test   Run the tests\n\n"
+        );
+        assert_eq!(capture.stderr(), "");
+    }
+
+    #[test]
+    fn test_render_fits_width() {
+        let config = config(
+            "a-very-long-task-name-indeed:\n  description: Long name\n  cmd: x\nci:\n  description: Run every check we have\n  cmd: x\n",
+        );
+        let listing = |width| {
+            let mut out = Vec::new();
+            render_tasks(&config, width, None, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(
+            listing(Some(24)),
+            "  a-very-long-task-name-indeed\n      Long name\n  ci  Run every check w…\n\n"
+        );
+        assert_eq!(
+            listing(None),
+            "  a-very-long-task-name-indeed\n      Long name\n  ci  Run every check we have\n\n"
+        );
+    }
+
+    #[test]
+    fn test_render_marks_deprecated_tasks() {
+        let config = config(
+            "build: cargo build\nold-build:\n  description: Build it\n  cmd: x\n  deprecated:\n    replaced_by: build\nlint:\n  cmd: x\n  deprecated: true\nfmt:\n  cmd: x\n  deprecated: false\n",
+        );
+        let mut out = Vec::new();
+        render_tasks(&config, None, None, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "  build\n  fmt\n  lint       (deprecated)\n  old-build  (deprecated, use 'build') Build it\n\n"
+        );
+    }
+
+    #[test]
+    fn test_render_marks_restricted_tasks() {
+        let config = config(
+            "contexts: [prod]\ndeploy:\n  description: Ship it\n  cmd: x\n  contexts:\n    allow: [ci, prod]\n  not_between: ['22:00', '06:00']\nclean:\n  cmd: x\n  contexts:\n    deny: [ci]\n",
+        );
+        let mut out = Vec::new();
+        render_tasks(&config, None, None, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "  clean   [not in ci]\n  deploy  [ci, prod only] [not 22:00-06:00] Ship it\n\n"
+        );
+    }
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("short", 10), "short");
+        assert_eq!(truncate("exactly 10", 10), "exactly 10");
+        assert_eq!(truncate("one two three", 9), "one two…");
+        assert_eq!(truncate("héllo wörld", 6), "héllo…");
+    }
+
+    #[test]
+    fn test_show_empty_config_ignores_quiet() {
+        let (ui, capture) = Ui::capture(true);
+        show(&config("{}"), Some(80), None, &ui).unwrap();
+        assert!(capture.stdout().contains("No tasks defined in rnr.yaml"));
+    }
+
+    #[test]
+    fn test_nested_configs_skips_dependency_dirs() {
+        let temp = tempfile::tempdir().unwrap();
+        for dir in [
+            "",
+            "services/api",
+            "services/web",
+            "node_modules/pkg",
+            "target/debug",
+            ".git/hooks",
+            "a/b/c/d/e/f/g",
+        ] {
+            fs::create_dir_all(temp.path().join(dir)).unwrap();
+            fs::write(temp.path().join(dir).join(CONFIG_FILE), "build: echo").unwrap();
+        }
+        let found: Vec<PathBuf> = nested_configs(temp.path())
+            .iter()
+            .map(|path| path.strip_prefix(temp.path()).unwrap().to_path_buf())
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                Path::new("services/api/rnr.yaml"),
+                Path::new("services/web/rnr.yaml")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_show_nested_sections() {
+        let temp = tempfile::tempdir().unwrap();
+        let write = |dir: &str, yaml: &str| {
+            fs::create_dir_all(temp.path().join(dir)).unwrap();
+            fs::write(temp.path().join(dir).join(CONFIG_FILE), yaml).unwrap();
+        };
+        write("", "build: echo root\n");
+        write(
+            "services/api",
+            "build:\n  description: Build the API\n  cmd: cargo build\nlint: cargo clippy\n",
+        );
+        write("services/empty", "{}\n");
+
+        let (ui, capture) = Ui::capture(false);
+        show_nested(temp.path(), Some(80), false, &ui);
+        assert_eq!(
+            capture.stdout(),
+            format!(
+                "Tasks in {}:\n\n  build  Build the API\n  lint\n\n",
+                Path::new("services/api").display()
+            )
+        );
     }
 }
@@ -1,3 +1,17 @@
+pub mod add;
+pub mod affected;
+pub mod docs;
+pub mod env;
+pub mod explain;
+pub mod export;
+pub mod fmt;
+pub mod help;
+pub mod import;
 pub mod init;
+pub mod last;
 pub mod list;
+pub mod settings;
 pub mod upgrade;
+pub mod validate;
+pub mod verify;
+pub mod version;
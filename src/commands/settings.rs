@@ -0,0 +1,49 @@
+use crate::settings::Settings;
+use crate::ui::Ui;
+
+/// Run the settings command
+pub fn run(settings: &Settings, ui: &Ui) {
+    ui.print(render(settings));
+}
+
+/// Each setting's value and where it came from, in columns
+fn render(settings: &Settings) -> String {
+    let rows = settings.rows();
+    let name_width = rows.iter().map(|(name, ..)| name.len()).max().unwrap_or(0);
+    let value_width = rows
+        .iter()
+        .map(|(_, value, _)| value.len())
+        .max()
+        .unwrap_or(0);
+    rows.iter()
+        .map(|(name, value, source)| {
+            format!(
+                "{:<name_width$}  {:<value_width$}  ({})\n",
+                name, value, source
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::Cli;
+    use crate::settings::Layers;
+    use clap::Parser;
+
+    #[test]
+    fn test_render() {
+        let cli =
+            Cli::try_parse_from(["rnr", "--jobs", "4", "--output=plain", "settings"]).unwrap();
+        let settings = Layers::default().resolve(&cli).unwrap();
+        assert_eq!(
+            render(&settings),
+            "color       auto   (default)\n\
+             jobs        4      (--jobs)\n\
+             output      plain  (--output)\n\
+             timestamps  false  (default)\n\
+             eta         true   (default)\n"
+        );
+    }
+}
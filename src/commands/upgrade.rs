@@ -1,17 +1,31 @@
 //! Upgrade rnr binaries to the latest version
 
-use anyhow::{Context, Result};
-use std::fs;
-use std::path::PathBuf;
+use anyhow::Result;
+#[cfg(feature = "network")]
+use dialoguer::Confirm;
+#[cfg(feature = "network")]
+use std::cmp::Ordering;
+#[cfg(feature = "network")]
+use std::io::IsTerminal;
 
+use crate::cli::UpgradeArgs;
+#[cfg(feature = "network")]
+use crate::events::{self, EventSink};
+#[cfg(feature = "network")]
+use crate::net::{is_newer_version, ReleaseSource};
+#[cfg(feature = "network")]
 use crate::platform::Platform;
-use crate::rnr_config::RnrConfig;
-
-/// GitHub repository for releases
-const GITHUB_REPO: &str = "CodingWithCalvin/rnr.cli";
+#[cfg(feature = "network")]
+use crate::rnr_config::Channel;
+#[cfg(feature = "network")]
+use crate::rnr_config::{find_rnr_dir, ProjectLock, RnrConfig};
+use crate::ui::Ui;
+#[cfg(feature = "network")]
+use crate::version::{compare_versions, is_prerelease};
 
 /// Run the upgrade command
-pub fn run() -> Result<()> {
+#[cfg(feature = "network")]
+pub fn run(args: &UpgradeArgs, ui: &Ui) -> Result<()> {
     let rnr_dir = find_rnr_dir()?;
     let bin_dir = rnr_dir.join("bin");
 
@@ -19,215 +33,238 @@ pub fn run() -> Result<()> {
         anyhow::bail!("rnr is not initialized. Run 'rnr init' first.");
     }
 
+    // Held until the upgrade is recorded, so no other rnr's change is lost
+    let _lock = ProjectLock::acquire(&rnr_dir)?;
+
     // Load current config
     let config_path = rnr_dir.join("config.yaml");
     let mut config = RnrConfig::load_from(&config_path)?;
-    let platforms = config.get_platforms();
-
-    if platforms.is_empty() {
+    if config.get_platforms().is_empty() {
         anyhow::bail!("No platforms configured. Run 'rnr init' to set up platforms.");
     }
 
-    println!("Checking for updates...\n");
-    println!("  Current version: v{}", config.version);
-
-    #[cfg(feature = "network")]
-    {
-        upgrade_binaries(&bin_dir, &mut config, &config_path, &platforms)?;
+    let events = args.progress_json.then(EventSink::stderr);
+    let events = events.as_ref();
+
+    if let Some(channel) = args.channel {
+        if channel != config.channel {
+            config.channel = channel;
+            config.save_to(&config_path)?;
+            ui.status(format!("Switched to the {} channel.\n", channel));
+            if let Some(events) = events {
+                events.config_written(".rnr/config.yaml");
+            }
+        }
     }
 
-    #[cfg(not(feature = "network"))]
-    {
-        println!("\nNetwork feature is disabled. Cannot check for updates.");
-        println!("Please manually update binaries in .rnr/bin/");
+    ui.status("Checking for updates...\n");
+    ui.status(format!("  Current version: v{}", config.version));
+    ui.status(format!("  Channel:         {}", config.channel));
+
+    let source = ReleaseSource::from_env();
+    let latest_version = source.latest_version(config.channel)?;
+    ui.status(format!("  Latest version:  v{}", latest_version));
+
+    if args.check {
+        check_binaries(ui, &config, &latest_version);
+    } else {
+        upgrade_binaries(
+            ui,
+            &source,
+            &bin_dir,
+            &mut config,
+            &config_path,
+            &latest_version,
+            args.yes,
+            events,
+        )?;
     }
 
+    if let Some(events) = events {
+        events.completed(&config.version);
+    }
     Ok(())
 }
 
-/// Find the .rnr directory by walking up from current directory
-fn find_rnr_dir() -> Result<PathBuf> {
-    let current_dir = std::env::current_dir().context("Failed to get current directory")?;
+/// Run the upgrade command with a full rnr, since slim builds can't download
+#[cfg(not(feature = "network"))]
+pub fn run(_args: &UpgradeArgs, ui: &Ui) -> Result<()> {
+    crate::delegate::to_full_build("rnr upgrade", ui)
+}
 
-    let mut dir = current_dir.as_path();
-    loop {
-        let rnr_path = dir.join(".rnr");
-        if rnr_path.exists() && rnr_path.is_dir() {
-            return Ok(rnr_path);
-        }
+/// Report which binaries are behind the latest release without downloading
+#[cfg(feature = "network")]
+fn check_binaries(ui: &Ui, config: &RnrConfig, latest_version: &str) {
+    ui.line("");
+    let outdated = outdated_binaries(config, latest_version);
+    for platform in config.get_platforms() {
+        let version = config
+            .binary(platform)
+            .map(|entry| entry.version.as_str())
+            .unwrap_or("unknown");
+        let marker = if outdated.contains(&platform) {
+            format!("{}", ui.style("update available").yellow())
+        } else {
+            format!("{}", ui.style("up to date").green())
+        };
+        ui.line(format!(
+            "  {:<14} v{:<10} {}",
+            platform.id(),
+            version,
+            marker
+        ));
+    }
 
-        match dir.parent() {
-            Some(parent) => dir = parent,
-            None => break,
-        }
+    if outdated.is_empty() {
+        ui.line("\nAll binaries are on the latest version.");
+    } else {
+        ui.line(format!(
+            "\n{} of {} binaries can be upgraded. Run 'rnr upgrade' to update them.",
+            outdated.len(),
+            config.get_platforms().len()
+        ));
     }
+}
 
-    anyhow::bail!("No .rnr directory found. Run 'rnr init' first.")
+/// Platforms whose recorded binary is older than `latest` or a placeholder
+#[cfg(feature = "network")]
+fn outdated_binaries(config: &RnrConfig, latest: &str) -> Vec<Platform> {
+    config
+        .get_platforms()
+        .into_iter()
+        .filter(|platform| {
+            config
+                .binary(*platform)
+                .is_none_or(|entry| entry.placeholder || is_newer_version(&entry.version, latest))
+        })
+        .collect()
 }
 
-/// Upgrade binaries to the latest version
+/// Upgrade binaries to the latest version on the configured channel
 #[cfg(feature = "network")]
+#[allow(clippy::too_many_arguments)]
 fn upgrade_binaries(
+    ui: &Ui,
+    source: &ReleaseSource,
     bin_dir: &std::path::Path,
     config: &mut RnrConfig,
     config_path: &std::path::Path,
-    platforms: &[Platform],
+    latest_version: &str,
+    yes: bool,
+    events: Option<&EventSink>,
 ) -> Result<()> {
-    // Get latest release info from GitHub
-    let latest_version = get_latest_version()?;
-    println!("  Latest version:  v{}", latest_version);
-
-    // Compare versions
-    if !is_newer_version(&config.version, &latest_version) {
-        println!("\nYou're already on the latest version!");
+    let downgrade = config.channel == Channel::Stable
+        && is_prerelease(&config.version)
+        && compare_versions(latest_version, &config.version) == Ordering::Less;
+
+    if downgrade {
+        ui.status(format!(
+            "\nv{} is a prerelease, but this project is now on the stable channel.",
+            config.version
+        ));
+        if !confirm_downgrade(&config.version, latest_version, yes)? {
+            ui.status(format!("Keeping v{}.", config.version));
+            return Ok(());
+        }
+    } else if !is_newer_version(&config.version, latest_version)
+        // Binaries left behind by a partial upgrade still need updating
+        && outdated_binaries(config, latest_version).is_empty()
+    {
+        ui.status("\nYou're already on the latest version!");
         return Ok(());
     }
 
-    println!("\nUpgrading to v{}...\n", latest_version);
+    let verb = if downgrade {
+        "Downgrading"
+    } else {
+        "Upgrading"
+    };
+    ui.status(format!("\n{} to v{}...\n", verb, latest_version));
 
     // Download new binaries for all configured platforms
-    for platform in platforms {
-        print!("  Downloading {}...", platform.binary_name());
+    let previous = config.managed_files();
+    for platform in config.get_platforms() {
+        ui.status_inline(format!("  Downloading {}...", platform.binary_name()));
         let binary_path = bin_dir.join(platform.binary_name());
-        download_binary(*platform, &latest_version, &binary_path)?;
-        println!(" done");
+        let name = format!(".rnr/bin/{}", platform.binary_name());
+        let expected = platform.size_bytes();
+        events::track_download(events, platform.id(), &name, expected, false, |progress| {
+            source.download_asset(platform, Some(latest_version), &binary_path, progress)
+        })?;
+        config.record_binary(platform, latest_version, &binary_path)?;
+        ui.status(" done");
     }
 
     // Update config version
-    config.version = latest_version.clone();
+    config.version = latest_version.to_string();
     config.save_to(config_path)?;
+    if let Some(events) = events {
+        events.config_written(".rnr/config.yaml");
+    }
+    // Only once everything is swapped in, so a failed upgrade keeps them
+    for name in config.remove_stale_files(bin_dir, &previous)? {
+        ui.status(format!("  Removed {} (no longer used)", name));
+    }
 
-    println!("\nUpgrade complete! Now running v{}", latest_version);
+    ui.status(format!(
+        "\nUpgrade complete! Now running v{}",
+        latest_version
+    ));
 
     Ok(())
 }
 
-/// Get the latest release version from GitHub
+/// Ask before replacing a prerelease with an older stable release
 #[cfg(feature = "network")]
-fn get_latest_version() -> Result<String> {
-    let url = format!(
-        "https://api.github.com/repos/{}/releases/latest",
-        GITHUB_REPO
-    );
-
-    let client = reqwest::blocking::Client::builder()
-        .user_agent("rnr-cli")
-        .build()
-        .context("Failed to create HTTP client")?;
-
-    let response = client
-        .get(&url)
-        .send()
-        .context("Failed to fetch latest release info")?;
-
-    if !response.status().is_success() {
-        if response.status().as_u16() == 404 {
-            anyhow::bail!("No releases found. This may be the first version.");
-        }
-        anyhow::bail!(
-            "Failed to fetch release info: HTTP {}",
-            response.status().as_u16()
-        );
+fn confirm_downgrade(current: &str, stable: &str, yes: bool) -> Result<bool> {
+    if yes {
+        return Ok(true);
     }
-
-    let json: serde_json::Value = response
-        .json()
-        .context("Failed to parse release info as JSON")?;
-
-    let tag = json["tag_name"]
-        .as_str()
-        .context("Release missing tag_name")?;
-
-    // Strip 'v' prefix if present
-    let version = tag.strip_prefix('v').unwrap_or(tag);
-    Ok(version.to_string())
-}
-
-/// Download a binary for a specific platform and version
-#[cfg(feature = "network")]
-fn download_binary(platform: Platform, version: &str, dest: &std::path::Path) -> Result<()> {
-    let url = format!(
-        "https://github.com/{}/releases/download/v{}/{}",
-        GITHUB_REPO,
-        version,
-        platform.binary_name()
-    );
-
-    let client = reqwest::blocking::Client::builder()
-        .user_agent("rnr-cli")
-        .build()
-        .context("Failed to create HTTP client")?;
-
-    let response = client
-        .get(&url)
-        .send()
-        .with_context(|| format!("Failed to download {}", platform.binary_name()))?;
-
-    if !response.status().is_success() {
+    if !std::io::stdin().is_terminal() {
         anyhow::bail!(
-            "Failed to download {}: HTTP {}",
-            platform.binary_name(),
-            response.status().as_u16()
+            "Refusing to downgrade from v{} to v{} without confirmation. Pass --yes to downgrade.",
+            current,
+            stable
         );
     }
-
-    let bytes = response
-        .bytes()
-        .with_context(|| format!("Failed to read response for {}", platform.binary_name()))?;
-
-    // Write to file
-    fs::write(dest, &bytes).with_context(|| format!("Failed to write {}", dest.display()))?;
-
-    // Make executable on Unix
-    #[cfg(unix)]
-    {
-        use std::os::unix::fs::PermissionsExt;
-        let mut perms = fs::metadata(dest)?.permissions();
-        perms.set_mode(0o755);
-        fs::set_permissions(dest, perms)?;
-    }
-
-    Ok(())
+    Ok(Confirm::new()
+        .with_prompt(format!(
+            "Downgrade to the latest stable release, v{}?",
+            stable
+        ))
+        .default(false)
+        .interact()?)
 }
 
-/// Compare semantic versions, returns true if latest is newer than current
-#[cfg(feature = "network")]
-fn is_newer_version(current: &str, latest: &str) -> bool {
-    let parse_version = |v: &str| -> (u32, u32, u32) {
-        let parts: Vec<&str> = v.split('.').collect();
-        let major = parts.first().and_then(|s| s.parse().ok()).unwrap_or(0);
-        let minor = parts.get(1).and_then(|s| s.parse().ok()).unwrap_or(0);
-        let patch = parts.get(2).and_then(|s| s.parse().ok()).unwrap_or(0);
-        (major, minor, patch)
-    };
+#[cfg(all(test, feature = "network"))]
+mod tests {
+    use super::*;
 
-    let (cur_major, cur_minor, cur_patch) = parse_version(current);
-    let (lat_major, lat_minor, lat_patch) = parse_version(latest);
+    #[test]
+    fn test_outdated_binaries() {
+        let mut config = RnrConfig::new("0.2.0", &[Platform::LinuxAmd64, Platform::MacosArm64]);
+        config.binaries.get_mut("linux-amd64").unwrap().version = "0.1.0".to_string();
+        assert_eq!(
+            outdated_binaries(&config, "0.2.0"),
+            vec![Platform::LinuxAmd64]
+        );
+        assert_eq!(outdated_binaries(&config, "0.3.0").len(), 2);
 
-    if lat_major > cur_major {
-        return true;
+        config.record_placeholder(Platform::MacosArm64, "0.2.0");
+        assert_eq!(outdated_binaries(&config, "0.2.0").len(), 2);
     }
-    if lat_major == cur_major && lat_minor > cur_minor {
-        return true;
-    }
-    if lat_major == cur_major && lat_minor == cur_minor && lat_patch > cur_patch {
-        return true;
-    }
-    false
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
 
     #[test]
-    #[cfg(feature = "network")]
-    fn test_version_comparison() {
-        assert!(is_newer_version("0.1.0", "0.2.0"));
-        assert!(is_newer_version("0.1.0", "1.0.0"));
-        assert!(is_newer_version("0.1.0", "0.1.1"));
-        assert!(!is_newer_version("0.2.0", "0.1.0"));
-        assert!(!is_newer_version("1.0.0", "0.9.0"));
-        assert!(!is_newer_version("0.1.0", "0.1.0"));
+    fn test_check_binaries_output() {
+        let mut config = RnrConfig::new("0.2.0", &[Platform::LinuxAmd64, Platform::MacosArm64]);
+        config.binaries.get_mut("linux-amd64").unwrap().version = "0.1.0".to_string();
+        let (ui, capture) = Ui::capture(false);
+        check_binaries(&ui, &config, "0.2.0");
+        assert_eq!(
+            capture.stdout(),
+            "\n  linux-amd64    v0.1.0      update available\n  \
+             macos-arm64    v0.2.0      up to date\n\
+             \n1 of 2 binaries can be upgraded. Run 'rnr upgrade' to update them.\n"
+        );
     }
 }
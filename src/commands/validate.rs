@@ -0,0 +1,300 @@
+//! Check rnr.yaml and the nested task files it delegates to
+
+use anyhow::Result;
+use std::collections::HashSet;
+use std::path::Path;
+
+use crate::bins;
+use crate::cli;
+use crate::commands::list;
+use crate::config::{ConfigCache, CONFIG_FILE};
+use crate::project::Project;
+use crate::ui::Ui;
+
+/// Run the validate command
+pub fn run(project: &Project, strict: bool, ui: &Ui) -> Result<()> {
+    match check(project.root()?, strict, ui)? {
+        0 => ui.line(format!("{} is valid.", CONFIG_FILE)),
+        n => ui.line(format!(
+            "{} is valid with {} warning{}.",
+            CONFIG_FILE,
+            n,
+            if n == 1 { "" } else { "s" }
+        )),
+    }
+    Ok(())
+}
+
+/// Warn about problems in every task file reachable from the project's
+/// rnr.yaml, returning how many warnings were printed. Unknown task fields
+/// are errors in strict mode; replacements and contexts that don't exist
+/// always are.
+fn check(root: &Path, strict: bool, ui: &Ui) -> Result<usize> {
+    let configs = ConfigCache::default();
+    let mut seen = HashSet::new();
+    let root_config = root.join(CONFIG_FILE);
+    let mut pending = vec![root_config.clone()];
+    let mut warnings = 0;
+
+    while let Some(path) = pending.pop() {
+        if !seen.insert(path.canonicalize().unwrap_or_else(|_| path.clone())) {
+            continue;
+        }
+        let config = configs.load(&path)?;
+        if strict || config.strict {
+            config.check_unknown_fields(&path)?;
+        }
+
+        let name = path
+            .strip_prefix(root)
+            .unwrap_or(&path)
+            .display()
+            .to_string();
+        // Point at the task's line when it can be found
+        let at = |task: &str| config.location(task, None, root).unwrap_or(name.clone());
+        for field in config.unknown_fields() {
+            match field.line {
+                Some(line) => ui.warn(format!("{}:{}: {}", name, line, field)),
+                None => ui.warn(format!("{}: {}", name, field)),
+            }
+            warnings += 1;
+        }
+        for problem in config.altered_commands() {
+            ui.warn(format!("{}:{}: {}", name, problem.line, problem));
+            warnings += 1;
+        }
+        for (task, dir) in config.backslash_dirs() {
+            ui.warn(format!(
+                "{}: task '{}' has a backslash in dir '{}'; use '/' instead \
+                 (backslashes are escapes in double-quoted YAML)",
+                at(task),
+                task,
+                dir
+            ));
+            warnings += 1;
+        }
+        for task in config.task_names() {
+            let task_def = &config.tasks[task];
+            for problem in bins::problems(task, task_def, &config) {
+                ui.warn(format!("{}: {}", at(task), problem));
+                warnings += 1;
+            }
+        }
+        let mut problems = config.deprecation_problems();
+        problems.extend(config.context_problems());
+        problems.extend(config.choice_problems());
+        if !problems.is_empty() {
+            anyhow::bail!(problems
+                .iter()
+                .map(|problem| format!("{}: {}", name, problem))
+                .collect::<Vec<_>>()
+                .join("\n"));
+        }
+        // Only the project's own tasks can be run by name
+        if path == root_config {
+            if config.tasks.is_empty() {
+                ui.notice(format!("{} doesn't define any tasks yet", name));
+            }
+            for task in cli::shadowed_tasks(&config.task_names()) {
+                ui.warn(format!("{}: {}", at(task), list::shadowed_warning(task)));
+                warnings += 1;
+            }
+        }
+
+        let dir = path.parent().unwrap_or(root);
+        pending.extend(config.nested_configs(dir, root).into_iter().rev());
+    }
+    Ok(warnings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn project() -> tempfile::TempDir {
+        let temp = tempfile::tempdir().unwrap();
+        fs::create_dir_all(temp.path().join("api")).unwrap();
+        fs::write(
+            temp.path().join(CONFIG_FILE),
+            "build:\n  comand: cargo build\napi:\n  dir: api\n  task: build\nci:\n  steps:\n    - parallel:\n        - task: build\n          dir: api\n",
+        )
+        .unwrap();
+        fs::write(
+            temp.path().join("api").join(CONFIG_FILE),
+            "build:\n  descripton: Build the API\n  cmd: cargo build\n",
+        )
+        .unwrap();
+        temp
+    }
+
+    #[test]
+    fn test_warns_about_unknown_fields_in_nested_files() {
+        let temp = project();
+        let (ui, capture) = Ui::capture(false);
+        assert_eq!(check(temp.path(), false, &ui).unwrap(), 2);
+        let api = Path::new("api").join(CONFIG_FILE);
+        assert_eq!(
+            capture.stderr(),
+            format!(
+                "Warning: rnr.yaml:2: task 'build' has unknown field 'comand' (did you mean 'cmd'?)\n\
+                 Warning: {}:2: task 'build' has unknown field 'descripton' (did you mean 'description'?)\n",
+                api.display()
+            )
+        );
+    }
+
+    #[test]
+    fn test_warns_about_shadowed_tasks() {
+        let temp = tempfile::tempdir().unwrap();
+        fs::write(
+            temp.path().join(CONFIG_FILE),
+            "init: echo setup\nupgrade: echo deps\nbuild: echo build\n",
+        )
+        .unwrap();
+        let (ui, capture) = Ui::capture(false);
+        assert_eq!(check(temp.path(), false, &ui).unwrap(), 2);
+        assert_eq!(
+            capture.stderr(),
+            "Warning: rnr.yaml:1: task 'init' is shadowed by the 'init' subcommand; run it with 'rnr run init'\n\
+             Warning: rnr.yaml:2: task 'upgrade' is shadowed by the 'upgrade' subcommand; run it with 'rnr run upgrade'\n"
+        );
+    }
+
+    #[test]
+    fn test_warns_about_backslashes_in_dirs() {
+        let temp = tempfile::tempdir().unwrap();
+        fs::create_dir_all(temp.path().join("services").join("api")).unwrap();
+        fs::write(
+            temp.path().join(CONFIG_FILE),
+            "api:\n  dir: services\\api\n  cmd: make\nci:\n  steps:\n    - dir: services/api\n      cmd: make\n    - parallel:\n        - dir: 'services\\api'\n          cmd: make test\n",
+        )
+        .unwrap();
+        let (ui, capture) = Ui::capture(false);
+        assert_eq!(check(temp.path(), false, &ui).unwrap(), 2);
+        assert_eq!(
+            capture.stderr(),
+            "Warning: rnr.yaml:1: task 'api' has a backslash in dir 'services\\api'; use '/' instead (backslashes are escapes in double-quoted YAML)\n\
+             Warning: rnr.yaml:4: task 'ci' has a backslash in dir 'services\\api'; use '/' instead (backslashes are escapes in double-quoted YAML)\n"
+        );
+    }
+
+    #[test]
+    fn test_warns_about_commands_yaml_changed() {
+        let temp = tempfile::tempdir().unwrap();
+        fs::write(
+            temp.path().join(CONFIG_FILE),
+            "greet: &hello echo hi\nlong: echo a\n  b c\nnoted: echo hi # a comment\nquoted: '&x echo'\n",
+        )
+        .unwrap();
+        let (ui, capture) = Ui::capture(false);
+        assert_eq!(check(temp.path(), false, &ui).unwrap(), 1);
+        assert_eq!(
+            capture.stderr(),
+            "Warning: rnr.yaml:1: task 'greet' runs 'echo hi' rather than '&hello echo hi', \
+             because a leading '&' means something else to YAML; write the line as:\n    \
+             greet: '&hello echo hi'\n"
+        );
+    }
+
+    #[test]
+    fn test_warns_about_missing_bins() {
+        let temp = tempfile::tempdir().unwrap();
+        fs::create_dir_all(temp.path().join(".tools")).unwrap();
+        fs::write(temp.path().join(".tools").join("dprint"), "").unwrap();
+        fs::write(
+            temp.path().join(CONFIG_FILE),
+            "bins:\n  dprint: .tools/dprint\n  cog: .tools/cog\nformat: ${bins.dprint} fmt\nrelease:\n  steps:\n    - cmd: ${bins.cog} bump --auto\n    - cmd: ${bins.gh} release create\n",
+        )
+        .unwrap();
+        let (ui, capture) = Ui::capture(false);
+        assert_eq!(check(temp.path(), false, &ui).unwrap(), 2);
+        assert_eq!(
+            capture.stderr(),
+            "Warning: rnr.yaml:5: task 'release' needs bin 'cog', but .tools/cog doesn't exist\n\
+             Warning: rnr.yaml:5: task 'release' uses ${bins.gh}, but 'gh' isn't listed under 'bins' in rnr.yaml\n"
+        );
+    }
+
+    #[test]
+    fn test_missing_replacements_are_errors() {
+        let temp = tempfile::tempdir().unwrap();
+        fs::write(
+            temp.path().join(CONFIG_FILE),
+            "build: echo build\nold-build:\n  cmd: echo old\n  deprecated:\n    replaced_by: bild\nrenamed:\n  compile: complie\n  make: build\n  old-build: build\n",
+        )
+        .unwrap();
+        let (ui, _) = Ui::capture(false);
+        assert_eq!(
+            check(temp.path(), false, &ui).unwrap_err().to_string(),
+            "rnr.yaml: task 'old-build' is replaced_by 'bild', which isn't a task\n\
+             rnr.yaml: renamed 'compile' points at 'complie', which isn't a task\n\
+             rnr.yaml: renamed 'old-build' is still a task, so it never runs 'build'"
+        );
+
+        fs::write(
+            temp.path().join(CONFIG_FILE),
+            "build: echo build\nold-build:\n  cmd: echo old\n  deprecated:\n    replaced_by: build\nrenamed:\n  make: build\n",
+        )
+        .unwrap();
+        assert_eq!(check(temp.path(), false, &ui).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_unknown_contexts_are_errors() {
+        let temp = tempfile::tempdir().unwrap();
+        fs::write(
+            temp.path().join(CONFIG_FILE),
+            "contexts: [prod]\ndeploy:\n  cmd: ./deploy\n  contexts:\n    allow: [ci, prd]\n",
+        )
+        .unwrap();
+        let (ui, _) = Ui::capture(false);
+        assert_eq!(
+            check(temp.path(), false, &ui).unwrap_err().to_string(),
+            "rnr.yaml: task 'deploy' names context 'prd', which isn't one of: ci, local, prod"
+        );
+
+        fs::write(
+            temp.path().join(CONFIG_FILE),
+            "contexts: [prod]\ndeploy:\n  cmd: ./deploy\n  contexts:\n    allow: [ci, prod]\n",
+        )
+        .unwrap();
+        assert_eq!(check(temp.path(), false, &ui).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_strict_makes_unknown_fields_errors() {
+        let temp = project();
+        let (ui, _) = Ui::capture(false);
+        let err = check(temp.path(), true, &ui).unwrap_err();
+        assert!(err.to_string().contains("'comand'"), "{}", err);
+
+        fs::write(
+            temp.path().join(CONFIG_FILE),
+            "strict: true\nbuild:\n  step: []\n",
+        )
+        .unwrap();
+        assert!(check(temp.path(), false, &ui).is_err());
+    }
+
+    #[test]
+    fn test_choices_of_missing_tasks_are_errors() {
+        let temp = tempfile::tempdir().unwrap();
+        fs::write(
+            temp.path().join(CONFIG_FILE),
+            "web: echo web
+setup:
+  choose:
+    options:
+      web: web
+      api: setup-api
+",
+        )
+        .unwrap();
+        let (ui, _) = Ui::capture(false);
+        assert_eq!(
+            check(temp.path(), false, &ui).unwrap_err().to_string(),
+            "rnr.yaml: task 'setup' offers 'api', which runs 'setup-api', which isn't a task"
+        );
+    }
+}
@@ -0,0 +1,343 @@
+//! Check the binaries in .rnr/bin against config.yaml
+
+use anyhow::Result;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use crate::build_info;
+use crate::checksum;
+use crate::platform::{Platform, Target};
+use crate::rnr_config::{find_rnr_dir, placeholder_path, BinaryEntry, Mode, RnrConfig};
+use crate::ui::Ui;
+
+/// Run the verify command
+pub fn run(ui: &Ui) -> Result<()> {
+    let rnr_dir = find_rnr_dir()?;
+    let config = RnrConfig::load_from(&rnr_dir.join("config.yaml"))?;
+    let bin_dir = rnr_dir.join("bin");
+
+    // What's checking, for a pasted report to say which rnr it came from
+    ui.line(build_info::verbose_version());
+    ui.line(format!(
+        "Verifying binaries (project version v{})...\n",
+        config.version
+    ));
+
+    if config.mode == Mode::System {
+        ui.line("  No binaries: this project runs the rnr installed on PATH (mode: system)");
+    }
+
+    let mut failures = 0;
+    for platform in config.get_platforms() {
+        let Some(entry) = config.binary(platform) else {
+            continue;
+        };
+        let problems = check_binary(
+            platform,
+            entry,
+            &bin_dir.join(platform.binary_name()),
+            &config.version,
+        );
+        if problems.is_empty() {
+            let note = if entry.sha256.is_none() {
+                " (no checksum recorded)"
+            } else {
+                ""
+            };
+            ui.line(format!(
+                "  {} {}{}",
+                ui.style("✓").green(),
+                platform.id(),
+                note
+            ));
+        } else {
+            failures += 1;
+            ui.line(format!("  {} {}", ui.style("✗").red(), platform.id()));
+            for problem in problems {
+                ui.line(format!("      {}", problem));
+            }
+        }
+    }
+
+    if let Some(root) = rnr_dir.parent() {
+        if let Some(problem) = wrapper_problem(&root.join("rnr")) {
+            failures += 1;
+            ui.line(format!("  {} rnr (Unix wrapper)", ui.style("✗").red()));
+            ui.line(format!("      {}", problem));
+        }
+    }
+
+    // Left by another version or added by hand; never a failure
+    let unmanaged = config.unmanaged_files(&bin_dir);
+    for (name, _) in &unmanaged {
+        ui.line(format!(
+            "  {} {} (not managed by config.yaml)",
+            ui.style("!").yellow(),
+            name
+        ));
+    }
+    if !unmanaged.is_empty() {
+        ui.line("\nRun 'rnr init --prune' to delete the files config.yaml doesn't manage.");
+    }
+
+    if failures > 0 {
+        anyhow::bail!(
+            "{} binar{} failed verification. Run 'rnr upgrade' or 'rnr init --add-platform' to restore them.",
+            failures,
+            if failures == 1 { "y" } else { "ies" }
+        );
+    }
+    ui.line("\nAll binaries match config.yaml.");
+    Ok(())
+}
+
+/// Differences between a binary on disk and its recorded entry
+pub fn check_binary(
+    platform: Platform,
+    entry: &BinaryEntry,
+    path: &Path,
+    project_version: &str,
+) -> Vec<String> {
+    let mut problems = Vec::new();
+    if entry.version != project_version {
+        problems.push(format!(
+            "version v{} differs from project version v{}",
+            entry.version, project_version
+        ));
+    }
+
+    if entry.placeholder || placeholder_path(path).exists() {
+        problems.push("placeholder file, not a real binary (run 'rnr upgrade')".to_string());
+        return problems;
+    }
+
+    let Ok(metadata) = std::fs::metadata(path) else {
+        problems.push("binary not found in .rnr/bin".to_string());
+        return problems;
+    };
+    if let Some(size) = entry.size {
+        if metadata.len() != size {
+            problems.push(format!(
+                "size is {} bytes, expected {} bytes",
+                metadata.len(),
+                size
+            ));
+        }
+    }
+    if let Some(expected) = &entry.sha256 {
+        match checksum::sha256_file(path) {
+            Ok(actual) if actual.eq_ignore_ascii_case(expected) => {}
+            Ok(actual) => problems.push(format!(
+                "checksum mismatch: expected {}, found {}",
+                expected, actual
+            )),
+            Err(e) => problems.push(format!("{:#}", e)),
+        }
+    }
+    match classify(path) {
+        Ok(kind) => problems.extend(kind_problem(kind, platform, path)),
+        Err(e) => problems.push(format!("cannot read {}: {}", path.display(), e)),
+    }
+    problems
+}
+
+/// What a file in .rnr/bin is, judging by its first bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryKind {
+    /// Zero bytes long
+    Empty,
+    /// Text starting with `#` that isn't a `#!` script
+    Placeholder,
+    /// An executable built for `Target`
+    Executable(Target),
+    /// Anything else, such as a script
+    Unknown,
+}
+
+/// Look at the start of a file to see what kind of binary it is
+pub fn classify(path: &Path) -> io::Result<BinaryKind> {
+    let mut header = Vec::with_capacity(4096);
+    File::open(path)?.take(4096).read_to_end(&mut header)?;
+    Ok(match header.as_slice() {
+        [] => BinaryKind::Empty,
+        [b'#', b'!', ..] => BinaryKind::Unknown,
+        [b'#', ..] => BinaryKind::Placeholder,
+        _ => Target::from_header(&header).map_or(BinaryKind::Unknown, BinaryKind::Executable),
+    })
+}
+
+/// Why a file of this kind can't run as `platform`'s rnr, if it can't
+fn kind_problem(kind: BinaryKind, platform: Platform, path: &Path) -> Option<String> {
+    match kind {
+        BinaryKind::Empty => Some("empty file, not a real binary (run 'rnr upgrade')".to_string()),
+        BinaryKind::Placeholder => Some(
+            "placeholder text, not a real binary (run 'rnr init' with network access, or 'rnr upgrade')"
+                .to_string(),
+        ),
+        BinaryKind::Executable(target) if !target.matches(platform) => {
+            Some(format!("built for {}, not {}", target, platform))
+        }
+        BinaryKind::Executable(_) if platform.os() != "windows" && !is_executable(path) => {
+            Some(format!(
+                "not executable (run 'chmod +x .rnr/bin/{}')",
+                platform.binary_name()
+            ))
+        }
+        BinaryKind::Executable(_) | BinaryKind::Unknown => None,
+    }
+}
+
+/// Why the Unix wrapper at `path` won't run, if it has CRLF line endings
+/// (usually `core.autocrlf=true` on a Windows checkout)
+pub fn wrapper_problem(path: &Path) -> Option<String> {
+    let contents = std::fs::read(path).ok()?;
+    contents.contains(&b'\r').then(|| {
+        "has CRLF line endings, so macOS and Linux fail with '/bin/sh^M: bad interpreter'. \
+         Fix: add 'rnr text eol=lf' to .gitattributes, run \
+         'git add --renormalize rnr', and commit"
+            .to_string()
+    })
+}
+
+/// Whether the file has an executable bit set (always true off Unix)
+fn is_executable(path: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::metadata(path).is_ok_and(|m| m.permissions().mode() & 0o111 != 0)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ABC_SHA256: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+
+    fn entry(version: &str) -> BinaryEntry {
+        BinaryEntry {
+            version: version.to_string(),
+            sha256: Some(ABC_SHA256.to_string()),
+            size: Some(3),
+            placeholder: false,
+            files: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_wrapper_problem() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("rnr");
+        assert_eq!(wrapper_problem(&path), None);
+
+        std::fs::write(&path, "#!/bin/sh\nexec true\n").unwrap();
+        assert_eq!(wrapper_problem(&path), None);
+
+        std::fs::write(&path, "#!/bin/sh\r\nexec true\r\n").unwrap();
+        let problem = wrapper_problem(&path).unwrap();
+        assert!(problem.contains("bad interpreter"));
+        assert!(problem.contains("git add --renormalize rnr"));
+    }
+
+    #[test]
+    fn test_check_binary_ok() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("rnr-linux-amd64");
+        std::fs::write(&path, "abc").unwrap();
+        assert!(check_binary(Platform::LinuxAmd64, &entry("0.1.0"), &path, "0.1.0").is_empty());
+    }
+
+    #[test]
+    fn test_check_binary_reports_drift() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("rnr-linux-amd64");
+        std::fs::write(&path, "abcd").unwrap();
+
+        let problems = check_binary(Platform::LinuxAmd64, &entry("0.1.0"), &path, "0.2.0");
+        assert_eq!(problems.len(), 3);
+        assert!(problems[0].contains("v0.1.0 differs"));
+        assert!(problems[1].contains("size is 4 bytes"));
+        assert!(problems[2].contains("checksum mismatch"));
+
+        let missing = check_binary(
+            Platform::LinuxAmd64,
+            &entry("0.2.0"),
+            &temp.path().join("nope"),
+            "0.2.0",
+        );
+        assert_eq!(missing, vec!["binary not found in .rnr/bin"]);
+    }
+
+    #[test]
+    fn test_check_binary_flags_placeholders() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("rnr-linux-amd64");
+        std::fs::write(placeholder_path(&path), "placeholder").unwrap();
+
+        let problems = check_binary(Platform::LinuxAmd64, &entry("0.1.0"), &path, "0.1.0");
+        assert_eq!(
+            problems,
+            vec!["placeholder file, not a real binary (run 'rnr upgrade')"]
+        );
+
+        let recorded = BinaryEntry {
+            placeholder: true,
+            ..entry("0.1.0")
+        };
+        let other = temp.path().join("rnr-macos-arm64");
+        assert_eq!(
+            check_binary(Platform::LinuxAmd64, &recorded, &other, "0.1.0").len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_check_binary_classifies_contents() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("rnr-linux-amd64");
+        let unchecked = BinaryEntry {
+            sha256: None,
+            size: None,
+            ..entry("0.1.0")
+        };
+        let problems = |contents: &[u8]| {
+            std::fs::write(&path, contents).unwrap();
+            check_binary(Platform::LinuxAmd64, &unchecked, &path, "0.1.0")
+        };
+
+        assert_eq!(
+            problems(b""),
+            vec!["empty file, not a real binary (run 'rnr upgrade')"]
+        );
+        assert!(problems(b"# rnr placeholder\n")[0].starts_with("placeholder text"));
+        assert!(problems(b"#!/bin/sh\necho hi\n").is_empty());
+
+        let mut macho = vec![0xcf, 0xfa, 0xed, 0xfe];
+        macho.extend(0x0100_000cu32.to_le_bytes());
+        assert_eq!(
+            problems(&macho),
+            vec!["built for macos-arm64, not linux-amd64"]
+        );
+
+        let mut elf = vec![0x7f, b'E', b'L', b'F'];
+        elf.resize(20, 0);
+        elf[18] = 0x3e;
+        #[cfg(unix)]
+        assert_eq!(
+            problems(&elf),
+            vec!["not executable (run 'chmod +x .rnr/bin/rnr-linux-amd64')"]
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+            assert!(check_binary(Platform::LinuxAmd64, &unchecked, &path, "0.1.0").is_empty());
+        }
+    }
+}
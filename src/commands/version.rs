@@ -0,0 +1,68 @@
+//! Show the running rnr version and the versions recorded for each binary
+
+use anyhow::Result;
+
+use crate::build_info::{VersionReport, VERSION};
+use crate::cli::VersionArgs;
+use crate::rnr_config::{find_rnr_dir, RnrConfig};
+use crate::ui::Ui;
+
+/// Run the version command
+pub fn run(args: &VersionArgs, ui: &Ui) -> Result<()> {
+    let config = match find_rnr_dir() {
+        Ok(rnr_dir) => Some(RnrConfig::load_from(&rnr_dir.join("config.yaml"))?),
+        Err(_) => None,
+    };
+    if args.json {
+        ui.line(serde_json::to_string_pretty(&report(config.as_ref()))?);
+        return Ok(());
+    }
+
+    ui.line(format!("rnr v{}", VERSION));
+    let Some(config) = config else {
+        return Ok(());
+    };
+
+    ui.line(format!("Project version: v{}\n", config.version));
+    ui.line("Binaries:");
+    for platform in config.get_platforms() {
+        let Some(entry) = config.binary(platform) else {
+            continue;
+        };
+        let drift = if entry.version != config.version {
+            format!("  {}", ui.style("(differs from project version)").yellow())
+        } else {
+            String::new()
+        };
+        ui.line(format!(
+            "  {:<14} v{}{}",
+            platform.id(),
+            entry.version,
+            drift
+        ));
+    }
+
+    let drifted = config.version_drift().len();
+    if drifted > 0 {
+        ui.line(format!(
+            "\n{} binar{} out of step. Run 'rnr upgrade' to bring them in line.",
+            drifted,
+            if drifted == 1 { "y is" } else { "ies are" }
+        ));
+    }
+    Ok(())
+}
+
+/// The `--json` report for this binary and the project's config, if any
+fn report(config: Option<&RnrConfig>) -> VersionReport {
+    let platforms = config
+        .map(|config| {
+            config
+                .get_platforms()
+                .iter()
+                .map(|platform| platform.id().to_string())
+                .collect()
+        })
+        .unwrap_or_default();
+    VersionReport::new(config.map(|config| config.version.clone()), platforms)
+}
@@ -1,127 +1,2312 @@
+//! Task definitions from rnr.yaml
+
 use anyhow::{Context, Result};
-use serde::Deserialize;
-use std::collections::HashMap;
+use serde::{Deserialize, Deserializer};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
 use std::fs;
+use std::num::{NonZeroU32, NonZeroUsize};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use crate::build_info::VERSION;
+use crate::context;
+use crate::error::Error;
+use crate::limits;
+use crate::rnr_config;
+use crate::source::{self, Locations, RawConfig};
+use crate::suggest::{self, Abbreviation};
+use crate::version;
 
 /// The main configuration file name
 pub const CONFIG_FILE: &str = "rnr.yaml";
 
+/// Set by the wrapper scripts to the directory containing them
+pub const WRAPPER_DIR_ENV: &str = "RNR_WRAPPER_DIR";
+
+/// How many directories the search for rnr.yaml climbs, starting with the
+/// current one, before giving up
+pub const MAX_SEARCH_LEVELS: usize = 32;
+
+/// Cache directory for recorded plans and run results (relative to the project root)
+pub const CACHE_DIR: &str = ".rnr/cache";
+
 /// Represents a single task in the configuration
-#[derive(Debug, Deserialize)]
-#[serde(untagged)]
+#[derive(Debug)]
 pub enum TaskDef {
     /// Shorthand: just a command string
     Shorthand(String),
     /// Full task definition
-    Full(Task),
+    Full(Box<Task>),
+}
+
+impl<'de> Deserialize<'de> for TaskDef {
+    /// Told apart by shape rather than `untagged`, so a mistake inside a full
+    /// task is reported instead of "did not match any variant"
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match serde_yaml::Value::deserialize(deserializer)? {
+            serde_yaml::Value::String(cmd) => Ok(TaskDef::Shorthand(cmd)),
+            value => Task::deserialize(value)
+                .map(|task| TaskDef::Full(Box::new(task)))
+                .map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+/// Full task definition with all properties
+#[derive(Debug, Deserialize)]
+pub struct Task {
+    /// Human-readable description
+    pub description: Option<String>,
+
+    /// Extended description shown by `rnr help <task>`
+    pub long_description: Option<String>,
+
+    /// Usage line shown by `rnr help <task>` (generated from `args` if omitted)
+    pub usage: Option<String>,
+
+    /// Declared arguments, bound positionally or as `--name value` and
+    /// exposed as `${args.<name>}` and `RNR_ARG_<NAME>`
+    pub args: Option<Vec<ArgDef>>,
+
+    /// Reject arguments beyond the declared ones instead of passing them through
+    #[serde(default)]
+    pub strict_args: bool,
+
+    /// Example invocations shown by `rnr help <task>`
+    pub examples: Option<Vec<String>>,
+
+    /// Working directory (relative to project root)
+    pub dir: Option<String>,
+
+    /// Environment variables, literal or computed with `from_cmd`
+    pub env: Option<HashMap<String, EnvValue>>,
+
+    /// Command to execute
+    pub cmd: Option<Cmd>,
+
+    /// Another task to run
+    pub task: Option<String>,
+
+    /// Arguments passed to the task named by `task`
+    pub task_args: Option<Vec<String>>,
+
+    /// A menu of tasks, one of which runs: the one named on the command
+    /// line, or picked from the menu in a terminal
+    pub choose: Option<Choose>,
+
+    /// Sequential steps
+    pub steps: Option<Vec<Step>>,
+
+    /// Run in a temporary clean checkout instead of the working tree
+    pub checkout: Option<Checkout>,
+
+    /// Run the task's `cmd`, `task` or `steps` once per value
+    pub foreach: Option<Foreach>,
+
+    /// Run at most once per invocation, however many tasks delegate to it
+    #[serde(default)]
+    pub once: bool,
+
+    /// Built-in filter for the stdout of `cmd`
+    pub output_filter: Option<OutputFilter>,
+
+    /// Whether `cmd` is echoed before it runs (default true)
+    pub echo: Option<bool>,
+
+    /// Exit codes of `cmd` that count as success (default just 0)
+    pub expect_exit: Option<ExpectExit>,
+
+    /// Files the task depends on, as globs relative to the project root;
+    /// `rnr affected` runs the task when one of them changed
+    pub paths: Option<Vec<String>>,
+
+    /// Files the task reads, as globs relative to the project root; a task
+    /// whose `outputs` they overlap runs first when those are stale
+    pub sources: Option<Vec<String>>,
+
+    /// Files the task makes, as globs relative to the project root
+    pub outputs: Option<Vec<String>>,
+
+    /// File that gets a copy of everything the task's commands print
+    pub log: Option<String>,
+
+    /// Whether each run adds to `log` (the default) or starts it over
+    pub log_mode: Option<LogMode>,
+
+    /// Permissions for `log` and its rotated copies (Unix only)
+    pub log_file_mode: Option<FileMode>,
+
+    /// Rotate `log` before it grows past this size
+    pub log_max_size: Option<ByteSize>,
+
+    /// Rotated copies of `log` to keep (default 5)
+    pub log_keep: Option<usize>,
+
+    /// Scheduling priority of the task's commands, and of the steps and
+    /// tasks it runs unless they set their own
+    pub priority: Option<Priority>,
+
+    /// What runs when this task, run from the command line, fails (instead
+    /// of the top-level `hooks.on_failure`)
+    pub on_failure: Option<Hook>,
+
+    /// Marks the task as on its way out, optionally naming its replacement
+    pub deprecated: Option<Deprecated>,
+
+    /// Contexts (`ci`, `local`, or ones declared at the top level) the task
+    /// may or may not run in
+    pub contexts: Option<Contexts>,
+
+    /// Times of day the task doesn't start, such as a change freeze
+    pub not_between: Option<Window>,
+
+    /// Refuse to run with uncommitted changes to tracked files
+    pub require_clean_git: Option<bool>,
+
+    /// Refuse to run unless this branch is checked out
+    pub require_branch: Option<String>,
+
+    /// Refuse to run while the branch is ahead of or behind its upstream
+    pub require_upstream_synced: Option<bool>,
+}
+
+/// What a task needs of the git repository before it runs
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GitRequirements {
+    /// `require_clean_git`: no uncommitted changes to tracked files
+    pub clean: bool,
+    /// `require_branch`: the branch that must be checked out
+    pub branch: Option<String>,
+    /// `require_upstream_synced`: not ahead of or behind the upstream
+    pub upstream_synced: bool,
+}
+
+/// A task's `contexts`: `{ allow: [ci] }` or `{ deny: [local] }`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Contexts {
+    /// The only contexts the task runs in
+    pub allow: Option<Vec<String>>,
+    /// Contexts the task never runs in
+    pub deny: Option<Vec<String>>,
+}
+
+impl Contexts {
+    /// Whether the task may run in `context`
+    pub fn permits(&self, context: &str) -> bool {
+        let listed = |names: &Option<Vec<String>>| {
+            names.iter().flatten().any(|name| name.as_str() == context)
+        };
+        (self.allow.is_none() || listed(&self.allow)) && !listed(&self.deny)
+    }
+
+    /// Every context named in `allow` and `deny`
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.allow
+            .iter()
+            .chain(&self.deny)
+            .flatten()
+            .map(String::as_str)
+    }
+}
+
+impl fmt::Display for Contexts {
+    /// How `--list` sums it up: `ci only`, `not in local`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(allow) = &self.allow {
+            parts.push(format!("{} only", allow.join(", ")));
+        }
+        if let Some(deny) = self.deny.as_ref().filter(|deny| !deny.is_empty()) {
+            parts.push(format!("not in {}", deny.join(", ")));
+        }
+        f.write_str(&parts.join("; "))
+    }
+}
+
+/// A time of day to the minute, written `HH:MM` (24-hour)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TimeOfDay(u16);
+
+impl TimeOfDay {
+    /// The time `minutes` after midnight (wrapping at a day)
+    pub fn from_minutes(minutes: u32) -> Self {
+        TimeOfDay((minutes % (24 * 60)) as u16)
+    }
+}
+
+impl std::str::FromStr for TimeOfDay {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let parsed = text
+            .trim()
+            .split_once(':')
+            .filter(|(_, minutes)| minutes.len() == 2)
+            .and_then(|(hours, minutes)| {
+                let hours: u16 = hours.parse().ok().filter(|&h| h < 24)?;
+                let minutes: u16 = minutes.parse().ok().filter(|&m| m < 60)?;
+                Some(TimeOfDay(hours * 60 + minutes))
+            });
+        parsed.ok_or_else(|| format!("invalid time of day '{}' (expected HH:MM)", text))
+    }
+}
+
+impl fmt::Display for TimeOfDay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02}:{:02}", self.0 / 60, self.0 % 60)
+    }
+}
+
+/// A task's `not_between: ["22:00", "06:00"]`: the times of day it doesn't
+/// start, from `from` up to (not including) `to`, past midnight when `from`
+/// is the later of the two
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Window {
+    pub from: TimeOfDay,
+    pub to: TimeOfDay,
+}
+
+impl Window {
+    /// Whether `time` falls in the window
+    pub fn contains(&self, time: TimeOfDay) -> bool {
+        match self.from <= self.to {
+            true => self.from <= time && time < self.to,
+            false => time >= self.from || time < self.to,
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Window {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let [from, to] = <[String; 2]>::deserialize(deserializer)?;
+        let from = from.parse().map_err(serde::de::Error::custom)?;
+        let to = to.parse().map_err(serde::de::Error::custom)?;
+        if from == to {
+            return Err(serde::de::Error::custom(
+                "not_between needs two different times",
+            ));
+        }
+        Ok(Window { from, to })
+    }
+}
+
+impl fmt::Display for Window {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.from, self.to)
+    }
+}
+
+/// The time zone of `not_between` windows (`tz`): the machine's local time,
+/// `UTC`, or a fixed offset from it such as `+02:00`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TimeZone {
+    #[default]
+    Local,
+    /// Minutes ahead of UTC
+    Offset(i32),
+}
+
+impl<'de> Deserialize<'de> for TimeZone {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        let trimmed = text.trim();
+        if trimmed.eq_ignore_ascii_case("local") {
+            return Ok(TimeZone::Local);
+        }
+        if trimmed.eq_ignore_ascii_case("utc") || trimmed == "Z" {
+            return Ok(TimeZone::Offset(0));
+        }
+        let offset = trimmed.strip_prefix(['+', '-']).and_then(|rest| {
+            let (hours, minutes) = match rest.split_once(':') {
+                Some((hours, minutes)) => (hours, minutes),
+                None if rest.len() == 4 => rest.split_at(2),
+                None => (rest, "0"),
+            };
+            let hours: i32 = hours.parse().ok().filter(|&h| h <= 14)?;
+            let minutes: i32 = minutes.parse().ok().filter(|&m| m < 60)?;
+            let sign = if trimmed.starts_with('-') { -1 } else { 1 };
+            Some(TimeZone::Offset(sign * (hours * 60 + minutes)))
+        });
+        offset.ok_or_else(|| {
+            serde::de::Error::custom(format!(
+                "invalid tz '{}' (expected local, UTC, or an offset like +02:00)",
+                text
+            ))
+        })
+    }
+}
+
+impl fmt::Display for TimeZone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            TimeZone::Local => f.write_str("local time"),
+            TimeZone::Offset(0) => f.write_str("UTC"),
+            TimeZone::Offset(offset) => {
+                let sign = if offset < 0 { '-' } else { '+' };
+                let offset = offset.unsigned_abs();
+                write!(f, "UTC{}{:02}:{:02}", sign, offset / 60, offset % 60)
+            }
+        }
+    }
+}
+
+/// A length of time, written as seconds or with units (`90s`, `30m`,
+/// `1h30m`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeSpan(pub std::time::Duration);
+
+impl<'de> Deserialize<'de> for TimeSpan {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Number(u64),
+            Text(String),
+        }
+
+        let text = match Raw::deserialize(deserializer)? {
+            Raw::Number(n) => return Ok(TimeSpan(std::time::Duration::from_secs(n))),
+            Raw::Text(text) => text,
+        };
+        let mut seconds: u64 = 0;
+        let mut digits = String::new();
+        let mut valid = !text.trim().is_empty();
+        for c in text.trim().chars() {
+            let scale = match c {
+                '0'..='9' => {
+                    digits.push(c);
+                    continue;
+                }
+                'h' => 3600,
+                'm' => 60,
+                's' => 1,
+                _ => 0,
+            };
+            match digits.parse::<u64>() {
+                Ok(n) if scale > 0 => seconds = seconds.saturating_add(n.saturating_mul(scale)),
+                _ => valid = false,
+            }
+            digits.clear();
+        }
+        if !digits.is_empty() {
+            // A bare number is seconds
+            match digits.parse::<u64>() {
+                Ok(n) if seconds == 0 => seconds = n,
+                _ => valid = false,
+            }
+        }
+        match valid && seconds > 0 {
+            true => Ok(TimeSpan(std::time::Duration::from_secs(seconds))),
+            false => Err(serde::de::Error::custom(format!(
+                "invalid duration '{}' (expected e.g. 90s, 30m, or 1h30m)",
+                text
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for TimeSpan {
+    /// `1h30m`, `45m`, `90s` → `1m30s`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total = self.0.as_secs();
+        let (hours, minutes, seconds) = (total / 3600, total / 60 % 60, total % 60);
+        if hours > 0 {
+            write!(f, "{}h", hours)?;
+        }
+        if minutes > 0 {
+            write!(f, "{}m", minutes)?;
+        }
+        if seconds > 0 || total == 0 {
+            write!(f, "{}s", seconds)?;
+        }
+        Ok(())
+    }
+}
+
+/// A task's `deprecated`: `true`, or `{ replaced_by: build, message: ... }`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum Deprecated {
+    /// `deprecated: true` (`false` is the same as leaving it out)
+    Flag(bool),
+    /// What to use instead, and why
+    Details {
+        replaced_by: Option<String>,
+        message: Option<String>,
+    },
+}
+
+impl Deprecated {
+    /// Whether the task is actually deprecated
+    pub fn is_set(&self) -> bool {
+        !matches!(self, Deprecated::Flag(false))
+    }
+
+    /// The task to use instead, if one is named
+    pub fn replaced_by(&self) -> Option<&str> {
+        match self {
+            Deprecated::Flag(_) => None,
+            Deprecated::Details { replaced_by, .. } => replaced_by.as_deref(),
+        }
+    }
+
+    /// The extra explanation, if any
+    pub fn message(&self) -> Option<&str> {
+        match self {
+            Deprecated::Flag(_) => None,
+            Deprecated::Details { message, .. } => message.as_deref(),
+        }
+    }
+}
+
+/// A `cmd` value: a line for the platform shell, or a program and its
+/// arguments (`[cargo, run, --, "hello world"]`) run directly without one
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum Cmd {
+    /// Run by `sh -c` (or `cmd /C` on Windows)
+    Shell(String),
+    /// Run directly: the program, then its arguments
+    Argv(Vec<String>),
+}
+
+impl Cmd {
+    /// Whether the command runs without a shell
+    pub fn is_direct(&self) -> bool {
+        matches!(self, Cmd::Argv(_))
+    }
+}
+
+impl fmt::Display for Cmd {
+    /// The command as a shell line, quoting array elements where needed
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Cmd::Shell(line) => f.write_str(line),
+            Cmd::Argv(argv) => {
+                let quoted: Vec<String> = argv
+                    .iter()
+                    .map(|arg| crate::runner::quote_arg(arg))
+                    .collect();
+                f.write_str(&quoted.join(" "))
+            }
+        }
+    }
+}
+
+/// The exit codes a command may end with: `[0, 1]`, a single code, or `any`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExpectExit {
+    /// Whatever it exits with (though not being killed by a signal)
+    Any,
+    /// One of these codes
+    Codes(Vec<i32>),
+}
+
+impl ExpectExit {
+    /// Whether a command exiting with `code` succeeded
+    pub fn allows(&self, code: i32) -> bool {
+        match self {
+            ExpectExit::Any => true,
+            ExpectExit::Codes(codes) => codes.contains(&code),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ExpectExit {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let code = |value: &serde_yaml::Value| value.as_i64().and_then(|n| i32::try_from(n).ok());
+        let expect = match serde_yaml::Value::deserialize(deserializer)? {
+            serde_yaml::Value::String(word) if word == "any" => Some(ExpectExit::Any),
+            serde_yaml::Value::Sequence(values) if !values.is_empty() => values
+                .iter()
+                .map(code)
+                .collect::<Option<Vec<i32>>>()
+                .map(ExpectExit::Codes),
+            value => code(&value).map(|code| ExpectExit::Codes(vec![code])),
+        };
+        expect.ok_or_else(|| {
+            serde::de::Error::custom(
+                "expect_exit must be 'any', an exit code, or a list of exit codes",
+            )
+        })
+    }
+}
+
+impl fmt::Display for ExpectExit {
+    /// `any exit code`, `exit code 1`, `exit codes 0 or 1`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let codes = match self {
+            ExpectExit::Any => return f.write_str("any exit code"),
+            ExpectExit::Codes(codes) => codes,
+        };
+        let codes: Vec<String> = codes.iter().map(i32::to_string).collect();
+        match codes.split_last() {
+            Some((last, [])) => write!(f, "exit code {}", last),
+            Some((last, rest)) => write!(f, "exit codes {} or {}", rest.join(", "), last),
+            None => f.write_str("no exit code"),
+        }
+    }
+}
+
+/// A task `env` value: text, or `{ from_cmd: ... }` for the trimmed output
+/// of a command, run once per invocation
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum EnvValue {
+    /// The value itself
+    Text(String),
+    /// Computed by running a command
+    Command(FromCmd),
+}
+
+/// The `{ from_cmd: ... }` form of an `env` value
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FromCmd {
+    /// Command line run by the platform shell in the task's directory
+    pub from_cmd: String,
+}
+
+/// Start of how a computed value is shown when its command isn't run
+const COMPUTED_FROM: &str = "<computed from: ";
+
+impl fmt::Display for EnvValue {
+    /// The value, or where it would come from for one that's computed
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EnvValue::Text(text) => f.write_str(text),
+            EnvValue::Command(command) => write!(f, "{}{}>", COMPUTED_FROM, command.from_cmd),
+        }
+    }
+}
+
+/// Whether `value` is how a computed value is shown when its command isn't
+/// run. It holds nothing secret, so it's shown even for a secret's name.
+pub fn is_computed_marker(value: &str) -> bool {
+    value.starts_with(COMPUTED_FROM) && value.ends_with('>')
+}
+
+/// A declared task argument
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ArgDef {
+    /// Argument name, used as `${args.<name>}`
+    pub name: String,
+
+    /// Whether the argument must be provided
+    #[serde(default)]
+    pub required: bool,
+
+    /// Value used when the argument is not provided
+    pub default: Option<String>,
+
+    /// Allowed values
+    pub choices: Option<Vec<String>>,
+
+    /// Human-readable description
+    pub description: Option<String>,
+}
+
+/// A step in a task
+#[derive(Debug)]
+pub enum Step {
+    /// Simple step with cmd/task/dir
+    Simple(Box<StepDef>),
+    /// Parallel execution block
+    Parallel {
+        parallel: Vec<StepDef>,
+        /// Don't check for branches working in the same directory
+        allow_shared_dir: bool,
+    },
+    /// Parallel block of named lanes, each running its own steps in order
+    Lanes {
+        lanes: Vec<Lane>,
+        /// Don't check for lanes working in the same directory
+        allow_shared_dir: bool,
+    },
+}
+
+impl<'de> Deserialize<'de> for Step {
+    /// A mapping with `parallel` is a parallel block; anything else is a
+    /// simple step, whose errors are reported as they are
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Parallel {
+            Branches {
+                parallel: Vec<StepDef>,
+                #[serde(default)]
+                allow_shared_dir: bool,
+            },
+            Lanes {
+                #[serde(rename = "parallel", deserialize_with = "deserialize_lanes")]
+                lanes: Vec<Lane>,
+                #[serde(default)]
+                allow_shared_dir: bool,
+            },
+        }
+
+        let value = serde_yaml::Value::deserialize(deserializer)?;
+        let is_parallel =
+            matches!(&value, serde_yaml::Value::Mapping(m) if m.contains_key("parallel"));
+        let step = if is_parallel {
+            Parallel::deserialize(value).map(|block| match block {
+                Parallel::Branches {
+                    parallel,
+                    allow_shared_dir,
+                } => Step::Parallel {
+                    parallel,
+                    allow_shared_dir,
+                },
+                Parallel::Lanes {
+                    lanes,
+                    allow_shared_dir,
+                } => Step::Lanes {
+                    lanes,
+                    allow_shared_dir,
+                },
+            })
+        } else {
+            StepDef::deserialize(value).map(|step_def| Step::Simple(Box::new(step_def)))
+        };
+        let step = step.map_err(serde::de::Error::custom)?;
+        // Branches run side by side, so there are no "following steps" to set
+        let in_parallel = !matches!(step, Step::Simple(_));
+        if in_parallel && step.step_defs().iter().any(|step_def| step_def.sets_env()) {
+            return Err(serde::de::Error::custom(
+                "an env step ('env' or 'env_file' on its own) can't be in a parallel block; \
+                 put it before the block",
+            ));
+        }
+        if in_parallel
+            && step
+                .step_defs()
+                .iter()
+                .any(|step_def| step_def.export_exit.is_some())
+        {
+            return Err(serde::de::Error::custom(
+                "'export_exit' can't be used in a parallel block, whose steps have no \
+                 following steps to set it for",
+            ));
+        }
+        Ok(step)
+    }
+}
+
+/// A named lane of a parallel block
+#[derive(Debug)]
+pub struct Lane {
+    /// Name shown before the lane's output
+    pub name: String,
+    /// Steps run one after another
+    pub steps: Vec<StepDef>,
+}
+
+/// Read `{ name: [steps...] }` lanes, keeping the order they're written in
+fn deserialize_lanes<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Lane>, D::Error> {
+    struct LanesVisitor;
+
+    impl<'de> serde::de::Visitor<'de> for LanesVisitor {
+        type Value = Vec<Lane>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a map of lane names to lists of steps")
+        }
+
+        fn visit_map<A: serde::de::MapAccess<'de>>(
+            self,
+            mut map: A,
+        ) -> Result<Self::Value, A::Error> {
+            let mut lanes: Vec<Lane> = Vec::new();
+            while let Some((name, steps)) = map.next_entry::<String, Vec<StepDef>>()? {
+                if lanes.iter().any(|lane| lane.name == name) {
+                    return Err(serde::de::Error::custom(format!(
+                        "duplicate lane '{}'",
+                        name
+                    )));
+                }
+                lanes.push(Lane { name, steps });
+            }
+            Ok(lanes)
+        }
+    }
+
+    deserializer.deserialize_map(LanesVisitor)
+}
+
+/// A task's `choose` menu
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Choose {
+    /// Question asked above the options
+    pub prompt: Option<String>,
+
+    /// Each option and the task it runs, in the order they're written
+    #[serde(deserialize_with = "deserialize_options")]
+    pub options: Vec<ChooseOption>,
+}
+
+/// An option of a `choose` menu
+#[derive(Debug)]
+pub struct ChooseOption {
+    /// What's picked, on the command line or in the menu
+    pub name: String,
+    /// Reference to the task it runs, as in `task:`
+    pub task: String,
+}
+
+impl Choose {
+    /// The option called `name`
+    pub fn option(&self, name: &str) -> Option<&ChooseOption> {
+        self.options.iter().find(|option| option.name == name)
+    }
+
+    /// The options' names, in order
+    pub fn names(&self) -> Vec<&str> {
+        self.options
+            .iter()
+            .map(|option| option.name.as_str())
+            .collect()
+    }
+}
+
+/// Read `{ name: task }` options, keeping the order they're written in
+fn deserialize_options<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<ChooseOption>, D::Error> {
+    struct OptionsVisitor;
+
+    impl<'de> serde::de::Visitor<'de> for OptionsVisitor {
+        type Value = Vec<ChooseOption>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a map of option names to the tasks they run")
+        }
+
+        fn visit_map<A: serde::de::MapAccess<'de>>(
+            self,
+            mut map: A,
+        ) -> Result<Self::Value, A::Error> {
+            let mut options: Vec<ChooseOption> = Vec::new();
+            while let Some((name, task)) = map.next_entry::<String, String>()? {
+                if options.iter().any(|option| option.name == name) {
+                    return Err(serde::de::Error::custom(format!(
+                        "duplicate option '{}'",
+                        name
+                    )));
+                }
+                options.push(ChooseOption { name, task });
+            }
+            if options.is_empty() {
+                return Err(serde::de::Error::custom(
+                    "'choose' needs at least one option",
+                ));
+            }
+            Ok(options)
+        }
+    }
+
+    deserializer.deserialize_map(OptionsVisitor)
+}
+
+impl Step {
+    /// Every step definition in this step, including parallel branches and lanes
+    pub fn step_defs(&self) -> Vec<&StepDef> {
+        match self {
+            Step::Simple(step_def) => vec![step_def],
+            Step::Parallel { parallel, .. } => parallel.iter().collect(),
+            Step::Lanes { lanes, .. } => lanes.iter().flat_map(|lane| &lane.steps).collect(),
+        }
+    }
+}
+
+/// Definition of a single step
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StepDef {
+    /// What the step does, shown instead of its command in progress and
+    /// failure output
+    pub description: Option<String>,
+
+    /// Working directory
+    pub dir: Option<String>,
+
+    /// Command to execute
+    pub cmd: Option<Cmd>,
+
+    /// Task to run
+    pub task: Option<String>,
+
+    /// Environment variables, layered over the task's (or the step's `task`'s)
+    pub env: Option<HashMap<String, String>>,
+
+    /// Arguments passed to the step's `task`
+    pub args: Option<Vec<String>>,
+
+    /// Builtin: copy files or directories
+    pub copy: Option<CopySpec>,
+
+    /// Builtin: remove files or directories (glob-capable)
+    pub remove: Option<RemoveSpec>,
+
+    /// Builtin: create a directory (and any missing parents)
+    pub mkdir: Option<String>,
+
+    /// Builtin: download a file over HTTP(S)
+    pub download: Option<DownloadSpec>,
+
+    /// Builtin: render a template with `${...}` interpolation
+    pub render: Option<RenderSpec>,
+
+    /// Built-in filter for the stdout of `cmd`
+    pub output_filter: Option<OutputFilter>,
+
+    /// Whether `cmd` is echoed before it runs (default true)
+    pub echo: Option<bool>,
+
+    /// Exit codes of `cmd` that count as success (default just 0)
+    pub expect_exit: Option<ExpectExit>,
+
+    /// Variable that gets `cmd`'s exit code, for the steps after this one
+    pub export_exit: Option<String>,
+
+    /// Labels that `--only` and `--skip` select the step by
+    pub tags: Option<Vec<String>>,
+
+    /// Builtin: load `KEY=value` lines from a file into the environment of
+    /// the steps after this one
+    pub env_file: Option<String>,
+
+    /// Scheduling priority of the step's commands
+    pub priority: Option<Priority>,
+
+    /// How many `--jobs` slots the step takes as a parallel branch (default 1)
+    pub weight: Option<NonZeroU32>,
+}
+
+/// Arguments for the `copy` builtin step
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CopySpec {
+    /// Source path or glob, relative to the working directory
+    pub from: String,
+
+    /// Destination path, relative to the working directory
+    pub to: String,
+}
+
+/// Arguments for the `download` builtin step
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DownloadSpec {
+    /// URL to fetch
+    pub url: String,
+
+    /// Destination file, relative to the working directory
+    pub to: String,
+
+    /// Expected SHA-256 checksum (hex)
+    pub sha256: Option<String>,
+
+    /// Environment variable holding a bearer token for the request
+    pub token_env: Option<String>,
+}
+
+/// Arguments for the `render` builtin step
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RenderSpec {
+    /// Template file, relative to the working directory
+    pub template: Option<String>,
+
+    /// Inline template content (instead of a template file)
+    pub content: Option<String>,
+
+    /// Destination file, relative to the working directory
+    pub to: String,
+
+    /// Permissions for the rendered file (Unix only)
+    pub mode: Option<FileMode>,
+}
+
+/// What a failure hook runs: a task (`notify-slack`) or a command
+/// (`{ cmd: ./scripts/notify.sh }`)
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum Hook {
+    /// The task of this name, or a path reference to one
+    Task(String),
+    /// A command, run in the project root
+    Cmd { cmd: Cmd },
+}
+
+impl fmt::Display for Hook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Hook::Task(name) => write!(f, "task {}", name),
+            Hook::Cmd { cmd } => write!(f, "$ {}", cmd),
+        }
+    }
+}
+
+/// The top-level `hooks`
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Hooks {
+    /// Runs when a task run from the command line fails, unless the task has
+    /// an `on_failure` of its own
+    pub on_failure: Option<Hook>,
+}
+
+/// A task's `checkout`: `clean` for the current commit, or `{ ref: main }`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checkout {
+    /// Git ref to check out (default: the current commit)
+    pub reference: Option<String>,
+}
+
+impl<'de> Deserialize<'de> for Checkout {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Mode(String),
+            Ref {
+                #[serde(rename = "ref")]
+                reference: String,
+            },
+        }
+
+        match Raw::deserialize(deserializer)? {
+            Raw::Mode(mode) if mode == "clean" => Ok(Checkout { reference: None }),
+            Raw::Mode(mode) => Err(serde::de::Error::custom(format!(
+                "unknown checkout '{}' (expected 'clean' or {{ ref: <git ref> }})",
+                mode
+            ))),
+            Raw::Ref { reference } => Ok(Checkout {
+                reference: Some(reference),
+            }),
+        }
+    }
+}
+
+/// A task's `foreach`: `{ values: [users, orders], as: DB }`, or
+/// `{ from_cmd: ls migrations/, as: FILE }` for a value per output line
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Foreach {
+    /// The values, listed
+    pub values: Option<Vec<String>>,
+    /// Command line run by the platform shell in the task's directory,
+    /// each non-blank line of its output a value
+    pub from_cmd: Option<String>,
+    /// Name the value is exposed as: `${foreach.<as>}` and env var `<as>`
+    #[serde(rename = "as")]
+    pub name: String,
+    /// Iterations run at once (default 1, one after another)
+    pub jobs: Option<NonZeroUsize>,
+    /// Start the remaining iterations after one fails (the task still fails)
+    #[serde(default)]
+    pub continue_on_error: bool,
+}
+
+impl Foreach {
+    /// Fail unless exactly one of `values` and `from_cmd` is set and `as`
+    /// can name an environment variable
+    pub fn check(&self) -> Result<()> {
+        match (&self.values, &self.from_cmd) {
+            (Some(_), Some(_)) => anyhow::bail!("'foreach' takes 'values' or 'from_cmd', not both"),
+            (None, None) => anyhow::bail!("'foreach' needs 'values' or 'from_cmd'"),
+            _ => {}
+        }
+        let valid = !self.name.is_empty()
+            && !self.name.starts_with(|c: char| c.is_ascii_digit())
+            && self
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid {
+            anyhow::bail!(
+                "foreach 'as: {}' must be a variable name (letters, digits and '_')",
+                self.name
+            );
+        }
+        Ok(())
+    }
+}
+
+/// A built-in filter for a command's stdout, selected by `output_filter`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFilter {
+    /// Readable pass/fail lines and counts from `cargo --message-format json`
+    CargoJson,
+    /// Swallow stdout unless the command fails
+    QuietSuccess,
+}
+
+impl OutputFilter {
+    /// Every filter, by the name `output_filter` selects it with
+    pub const ALL: &'static [(&'static str, OutputFilter)] = &[
+        ("cargo-json", OutputFilter::CargoJson),
+        ("quiet-success", OutputFilter::QuietSuccess),
+    ];
+}
+
+impl fmt::Display for OutputFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (name, _) = OutputFilter::ALL
+            .iter()
+            .find(|(_, filter)| filter == self)
+            .expect("every filter has a name");
+        f.write_str(name)
+    }
+}
+
+impl<'de> Deserialize<'de> for OutputFilter {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        OutputFilter::ALL
+            .iter()
+            .find(|(known, _)| *known == name)
+            .map(|(_, filter)| *filter)
+            .ok_or_else(|| {
+                let names: Vec<&str> = OutputFilter::ALL.iter().map(|(n, _)| *n).collect();
+                serde::de::Error::custom(format!(
+                    "unknown output_filter '{}' (expected one of: {})",
+                    name,
+                    names.join(", ")
+                ))
+            })
+    }
+}
+
+/// Unix file permissions, written as an octal string (`"0600"`) or a YAML
+/// octal number (`0o600`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileMode(pub u32);
+
+impl<'de> Deserialize<'de> for FileMode {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Text(String),
+            Number(u32),
+        }
+
+        let mode = match Raw::deserialize(deserializer)? {
+            Raw::Text(text) => {
+                let digits = text.trim().trim_start_matches("0o");
+                u32::from_str_radix(digits, 8).map_err(|_| {
+                    serde::de::Error::custom(format!("invalid file mode '{}'", text))
+                })?
+            }
+            Raw::Number(n) => n,
+        };
+        if mode > 0o7777 {
+            return Err(serde::de::Error::custom(format!(
+                "invalid file mode {:o}",
+                mode
+            )));
+        }
+        Ok(FileMode(mode))
+    }
+}
+
+/// How a task's `log` is opened at the start of a run
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogMode {
+    /// Add to what's there
+    #[default]
+    Append,
+    /// Start the file over
+    Truncate,
+}
+
+/// Scheduling priority of a command: `low` is niced on Unix and runs below
+/// normal priority on Windows; `high` runs above normal priority on Windows
+/// only, since raising it on Unix needs root
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Priority::Low => "low",
+            Priority::Normal => "normal",
+            Priority::High => "high",
+        })
+    }
+}
+
+/// A size in bytes, written as a number or with a unit (`512K`, `10MB`, `1G`;
+/// units are powers of 1024)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteSize(pub u64);
+
+impl<'de> Deserialize<'de> for ByteSize {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Number(u64),
+            Text(String),
+        }
+
+        let text = match Raw::deserialize(deserializer)? {
+            Raw::Number(n) => return Ok(ByteSize(n)),
+            Raw::Text(text) => text,
+        };
+        let trimmed = text.trim();
+        let digits = trimmed.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+        let unit = trimmed[digits.len()..].to_ascii_uppercase();
+        let scale: u64 = match unit.trim_end_matches('B').trim_end_matches('I') {
+            "" => 1,
+            "K" => 1 << 10,
+            "M" => 1 << 20,
+            "G" => 1 << 30,
+            _ => 0,
+        };
+        digits
+            .trim()
+            .parse::<u64>()
+            .ok()
+            .filter(|_| scale > 0)
+            .and_then(|n| n.checked_mul(scale))
+            .map(ByteSize)
+            .ok_or_else(|| {
+                serde::de::Error::custom(format!(
+                    "invalid size '{}' (expected bytes, or a number with K, M, or G)",
+                    text
+                ))
+            })
+    }
+}
+
+/// Arguments for the `remove` builtin step
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum RemoveSpec {
+    /// Shorthand: a single path or glob
+    Path(String),
+    /// Full form with options
+    Full {
+        /// Path or glob, relative to the working directory
+        path: String,
+
+        /// Allow removing paths outside the project root
+        #[serde(default)]
+        allow_outside_root: bool,
+    },
+}
+
+impl RemoveSpec {
+    /// The path or glob to remove
+    pub fn path(&self) -> &str {
+        match self {
+            RemoveSpec::Path(path) => path,
+            RemoveSpec::Full { path, .. } => path,
+        }
+    }
+
+    /// Whether paths outside the project root may be removed
+    pub fn allow_outside_root(&self) -> bool {
+        match self {
+            RemoveSpec::Path(_) => false,
+            RemoveSpec::Full {
+                allow_outside_root, ..
+            } => *allow_outside_root,
+        }
+    }
+}
+
+/// The complete rnr.yaml configuration
+#[derive(Debug, Deserialize)]
+pub struct Config {
+    /// Oldest rnr that understands this file, checked before the rest of
+    /// it is read
+    #[serde(default, deserialize_with = "deserialize_min_version")]
+    pub min_version: Option<String>,
+
+    /// Run a task from an unambiguous abbreviation of its name (CLI only)
+    #[serde(default)]
+    pub allow_abbreviations: bool,
+
+    /// Refuse to run while any task has fields rnr doesn't know
+    #[serde(default)]
+    pub strict: bool,
+
+    /// Environment variables whose values are never shown (`--verbose`, `rnr env`)
+    #[serde(default)]
+    pub secrets: Vec<String>,
+
+    /// Extra name patterns (`*_CERT*`) marking variables as secret
+    #[serde(default)]
+    pub secret_patterns: Vec<String>,
+
+    /// Whether `rnr affected` runs the tasks that have no `paths`
+    #[serde(default)]
+    pub affected_without_paths: WithoutPaths,
+
+    /// How commands are echoed before they run, e.g. `"[{dir}] $ {cmd}"`
+    #[serde(default, deserialize_with = "deserialize_echo_format")]
+    pub echo_format: Option<String>,
+
+    /// Project-local programs, by name, relative to this file's directory
+    /// (`${bins.<name>}`)
+    #[serde(default)]
+    pub bins: HashMap<String, String>,
+
+    /// Bits masked out of the files a run creates, for rnr and the commands
+    /// it starts alike (Unix only)
+    pub umask: Option<FileMode>,
+
+    /// Hooks run around every task
+    #[serde(default)]
+    pub hooks: Hooks,
+
+    /// Old task names and the tasks they now run, with a deprecation notice
+    #[serde(default)]
+    pub renamed: HashMap<String, String>,
+
+    /// Contexts besides `ci` and `local` that tasks' `contexts` may name
+    #[serde(default)]
+    pub contexts: Vec<String>,
+
+    /// Time zone of tasks' `not_between` windows (default: local time)
+    #[serde(default)]
+    pub tz: TimeZone,
+
+    /// How long a run may go before it stops starting steps
+    pub budget: Option<TimeSpan>,
+
+    /// Output a step may hold in memory, such as what `quiet-success` holds
+    /// back (default 1MB)
+    pub output_buffer: Option<ByteSize>,
+
+    /// Project defaults for rnr's own flags; read before the rest of the
+    /// file, by [`crate::settings`]
+    #[serde(default)]
+    pub settings: serde_yaml::Mapping,
+
+    #[serde(flatten)]
+    pub tasks: HashMap<String, TaskDef>,
+
+    /// Task fields that were ignored while parsing
+    #[serde(skip)]
+    unknown_fields: Vec<UnknownField>,
+
+    /// Where each task and step is written
+    #[serde(skip)]
+    locations: Locations,
+
+    /// Shorthand commands YAML changed from what's written
+    #[serde(skip)]
+    altered_commands: Vec<QuotingProblem>,
+
+    /// The rnr.yaml this was loaded from
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+/// What `rnr affected` does with a task that has no `paths`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WithoutPaths {
+    /// Leave it out
+    #[default]
+    Skip,
+    /// Run it whatever changed
+    Run,
+}
+
+impl fmt::Display for WithoutPaths {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            WithoutPaths::Skip => "skip",
+            WithoutPaths::Run => "run",
+        })
+    }
+}
+
+/// The echo of a command when `echo_format` isn't set
+pub const DEFAULT_ECHO_FORMAT: &str = "$ {cmd}";
+
+/// Placeholders an `echo_format` may use
+pub const ECHO_PLACEHOLDERS: &[&str] = &["cmd", "dir", "task", "step"];
+
+/// Read an `echo_format`, rejecting placeholders it can't fill
+/// `min_version` as written, whether quoted (`"0.5"`) or a bare number
+fn deserialize_min_version<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<String>, D::Error> {
+    Ok(min_version_text(&serde_yaml::Value::deserialize(
+        deserializer,
+    )?))
+}
+
+/// The version a `min_version` value names, if it's a scalar
+fn min_version_text(value: &serde_yaml::Value) -> Option<String> {
+    match value {
+        serde_yaml::Value::String(text) => Some(text.trim().to_string()),
+        serde_yaml::Value::Number(number) => Some(number.to_string()),
+        _ => None,
+    }
+}
+
+/// Fail if the document's `min_version` is newer than `current`. It's read
+/// from the raw document, before anything else, so an older rnr says what's
+/// wrong instead of tripping over settings it doesn't know.
+fn check_min_version(
+    path: &Path,
+    document: &serde_yaml::Value,
+    current: &str,
+) -> crate::Result<()> {
+    let Some(value) = document.get("min_version") else {
+        return Ok(());
+    };
+    let required = min_version_text(value)
+        .filter(|text| version::is_version(text))
+        .ok_or_else(|| Error::InvalidMinVersion {
+            path: path.to_path_buf(),
+            value: serde_yaml::to_string(value)
+                .unwrap_or_default()
+                .trim()
+                .to_string(),
+        })?;
+    if version::compare_versions(current, &required) == std::cmp::Ordering::Less {
+        return Err(Error::RnrTooOld {
+            required,
+            current: current.to_string(),
+        });
+    }
+    Ok(())
+}
+
+fn deserialize_echo_format<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<String>, D::Error> {
+    let format = String::deserialize(deserializer)?;
+    let mut rest = format.as_str();
+    while let Some(start) = rest.find('{') {
+        let Some(end) = rest[start..].find('}') else {
+            break;
+        };
+        let name = &rest[start + 1..start + end];
+        if !ECHO_PLACEHOLDERS.contains(&name) {
+            return Err(serde::de::Error::custom(format!(
+                "unknown echo_format placeholder '{{{}}}' (expected one of: {})",
+                name,
+                ECHO_PLACEHOLDERS
+                    .iter()
+                    .map(|p| format!("{{{}}}", p))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )));
+        }
+        rest = &rest[start + end + 1..];
+    }
+    Ok(Some(format))
+}
+
+/// Fill an `echo_format`'s placeholders from `values`, name by name
+pub fn render_echo(format: &str, values: &[(&str, &str)]) -> String {
+    let mut echo = String::with_capacity(format.len());
+    let mut rest = format;
+    while let Some(start) = rest.find('{') {
+        echo.push_str(&rest[..start]);
+        let placeholder = rest[start..].find('}').and_then(|end| {
+            let name = &rest[start + 1..start + end];
+            values
+                .iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| (end, *value))
+        });
+        match placeholder {
+            Some((end, value)) => {
+                echo.push_str(value);
+                rest = &rest[start + end + 1..];
+            }
+            None => {
+                echo.push('{');
+                rest = &rest[start + 1..];
+            }
+        }
+    }
+    echo.push_str(rest);
+    echo
+}
+
+/// Top-level keys that are settings rather than tasks
+pub(crate) const SETTINGS: &[&str] = &[
+    "min_version",
+    "allow_abbreviations",
+    "strict",
+    "secrets",
+    "secret_patterns",
+    "affected_without_paths",
+    "echo_format",
+    "bins",
+    "umask",
+    "hooks",
+    "renamed",
+    "contexts",
+    "tz",
+    "budget",
+    "output_buffer",
+    "settings",
+];
+
+/// Whether `value` is something the top-level setting `name` accepts, so a
+/// key that could be a task is read as the setting
+pub(crate) fn fits_setting(name: &str, value: &serde_yaml::Value) -> bool {
+    fn is<T: serde::de::DeserializeOwned>(value: &serde_yaml::Value) -> bool {
+        serde_yaml::from_value::<T>(value.clone()).is_ok()
+    }
+    match name {
+        "min_version" => min_version_text(value).is_some_and(|text| version::is_version(&text)),
+        "allow_abbreviations" | "strict" => is::<bool>(value),
+        "secrets" | "secret_patterns" | "contexts" => is::<Vec<String>>(value),
+        "affected_without_paths" => is::<WithoutPaths>(value),
+        "echo_format" => is::<String>(value),
+        "bins" | "renamed" => is::<HashMap<String, String>>(value),
+        "umask" => is::<FileMode>(value),
+        "hooks" => is::<Hooks>(value),
+        "tz" => is::<TimeZone>(value),
+        "budget" => is::<TimeSpan>(value),
+        "output_buffer" => is::<ByteSize>(value),
+        "settings" => value.is_mapping(),
+        _ => false,
+    }
+}
+
+/// Fields of a full task definition (keep in sync with [`Task`])
+pub(crate) const TASK_FIELDS: &[&str] = &[
+    "description",
+    "long_description",
+    "usage",
+    "args",
+    "strict_args",
+    "examples",
+    "dir",
+    "env",
+    "cmd",
+    "task",
+    "task_args",
+    "choose",
+    "steps",
+    "checkout",
+    "foreach",
+    "once",
+    "output_filter",
+    "echo",
+    "expect_exit",
+    "paths",
+    "sources",
+    "outputs",
+    "log",
+    "log_mode",
+    "log_file_mode",
+    "log_max_size",
+    "log_keep",
+    "priority",
+    "on_failure",
+    "deprecated",
+    "contexts",
+    "not_between",
+    "require_clean_git",
+    "require_branch",
+    "require_upstream_synced",
+];
+
+/// A key in a task definition that rnr doesn't recognize
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownField {
+    /// Task the field appears in
+    pub task: String,
+    /// The unrecognized key
+    pub field: String,
+    /// The closest known field, if any is close
+    pub suggestion: Option<&'static str>,
+    /// The line it's on, if it could be found
+    pub line: Option<usize>,
 }
 
-/// Full task definition with all properties
-#[derive(Debug, Deserialize)]
-pub struct Task {
-    /// Human-readable description
-    pub description: Option<String>,
+impl fmt::Display for UnknownField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "task '{}' has unknown field '{}'", self.task, self.field)?;
+        if let Some(suggestion) = self.suggestion {
+            write!(f, " (did you mean '{}'?)", suggestion)?;
+        }
+        Ok(())
+    }
+}
 
-    /// Working directory (relative to project root)
-    pub dir: Option<String>,
+/// A shorthand command YAML reads differently from how it's written, for
+/// want of quotes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuotingProblem {
+    /// Task the command belongs to
+    pub task: String,
+    /// The line it's on
+    pub line: usize,
+    /// What YAML made of it, and the line quoted
+    pub message: String,
+}
 
-    /// Environment variables
-    pub env: Option<HashMap<String, String>>,
+impl fmt::Display for QuotingProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
 
-    /// Shell command to execute
-    pub cmd: Option<String>,
+/// How a `task:` reference runs its target: extra variables layered over
+/// the target's `env`, and arguments bound to its declared `args`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Invocation<'a> {
+    /// Variables that win over the target task's own
+    pub env: Option<&'a HashMap<String, String>>,
+    /// Arguments, as if given on the command line
+    pub args: &'a [String],
+}
 
-    /// Another task to run
-    pub task: Option<String>,
+impl StepDef {
+    /// Whether this is an env step, setting variables for the steps after it:
+    /// one with `env_file`, or with `env` and nothing to run
+    pub fn sets_env(&self) -> bool {
+        self.env_file.is_some() || (self.env.is_some() && !self.has_action())
+    }
 
-    /// Sequential steps
-    pub steps: Option<Vec<Step>>,
+    /// Whether the step runs a command, a task or a file builtin
+    pub fn has_action(&self) -> bool {
+        self.cmd.is_some()
+            || self.task.is_some()
+            || self.copy.is_some()
+            || self.remove.is_some()
+            || self.mkdir.is_some()
+            || self.download.is_some()
+            || self.render.is_some()
+    }
+
+    /// The invocation of this step's `task:` reference
+    pub fn invocation(&self) -> Invocation<'_> {
+        Invocation {
+            env: self.env.as_ref(),
+            args: self.args.as_deref().unwrap_or_default(),
+        }
+    }
 }
 
-/// A step in a task
-#[derive(Debug, Deserialize)]
-#[serde(untagged)]
-pub enum Step {
-    /// Simple step with cmd/task/dir
-    Simple(StepDef),
-    /// Parallel execution block
-    Parallel { parallel: Vec<StepDef> },
+impl Task {
+    /// The task's `env`, with `from_cmd` values described rather than run
+    pub fn described_env(&self) -> Option<HashMap<String, String>> {
+        self.env.as_ref().map(|env| {
+            env.iter()
+                .map(|(name, value)| (name.clone(), value.to_string()))
+                .collect()
+        })
+    }
 }
 
-/// Definition of a single step
-#[derive(Debug, Deserialize)]
-#[serde(deny_unknown_fields)]
-pub struct StepDef {
-    /// Working directory
-    pub dir: Option<String>,
+impl TaskDef {
+    /// The task's `description` (none for shorthand tasks)
+    pub fn description(&self) -> Option<&str> {
+        match self {
+            TaskDef::Shorthand(_) => None,
+            TaskDef::Full(task) => task.description.as_deref(),
+        }
+    }
 
-    /// Shell command
-    pub cmd: Option<String>,
+    /// Declared arguments for this task (empty for shorthand tasks)
+    pub fn arg_defs(&self) -> &[ArgDef] {
+        match self {
+            TaskDef::Shorthand(_) => &[],
+            TaskDef::Full(task) => task.args.as_deref().unwrap_or_default(),
+        }
+    }
 
-    /// Task to run
-    pub task: Option<String>,
-}
+    /// Scheduling priority the task sets for its commands, if any
+    pub fn priority(&self) -> Option<Priority> {
+        match self {
+            TaskDef::Shorthand(_) => None,
+            TaskDef::Full(task) => task.priority,
+        }
+    }
 
-/// The complete rnr.yaml configuration
-#[derive(Debug, Deserialize)]
-pub struct Config {
-    #[serde(flatten)]
-    pub tasks: HashMap<String, TaskDef>,
+    /// Whether the task runs at most once per invocation
+    pub fn is_once(&self) -> bool {
+        matches!(self, TaskDef::Full(task) if task.once)
+    }
+
+    /// The task's `checkout` (none for shorthand tasks)
+    pub fn checkout(&self) -> Option<&Checkout> {
+        match self {
+            TaskDef::Shorthand(_) => None,
+            TaskDef::Full(task) => task.checkout.as_ref(),
+        }
+    }
+
+    /// The task's own `on_failure` (none for shorthand tasks)
+    pub fn on_failure(&self) -> Option<&Hook> {
+        match self {
+            TaskDef::Shorthand(_) => None,
+            TaskDef::Full(task) => task.on_failure.as_ref(),
+        }
+    }
+
+    /// The task's `deprecated`, if it's actually deprecated
+    pub fn deprecated(&self) -> Option<&Deprecated> {
+        match self {
+            TaskDef::Shorthand(_) => None,
+            TaskDef::Full(task) => task.deprecated.as_ref().filter(|d| d.is_set()),
+        }
+    }
+
+    /// The task's `contexts` restriction (none for shorthand tasks)
+    pub fn contexts(&self) -> Option<&Contexts> {
+        match self {
+            TaskDef::Shorthand(_) => None,
+            TaskDef::Full(task) => task.contexts.as_ref(),
+        }
+    }
+
+    /// The task's `not_between` window (none for shorthand tasks)
+    pub fn not_between(&self) -> Option<&Window> {
+        match self {
+            TaskDef::Shorthand(_) => None,
+            TaskDef::Full(task) => task.not_between.as_ref(),
+        }
+    }
+
+    /// What the task needs of the git repository, if anything
+    pub fn git_requirements(&self) -> Option<GitRequirements> {
+        let TaskDef::Full(task) = self else {
+            return None;
+        };
+        let requirements = GitRequirements {
+            clean: task.require_clean_git == Some(true),
+            branch: task.require_branch.clone(),
+            upstream_synced: task.require_upstream_synced == Some(true),
+        };
+        (requirements != GitRequirements::default()).then_some(requirements)
+    }
+
+    /// The task's `paths` (none for shorthand tasks)
+    pub fn paths(&self) -> Option<&[String]> {
+        match self {
+            TaskDef::Shorthand(_) => None,
+            TaskDef::Full(task) => task.paths.as_deref(),
+        }
+    }
+
+    /// The task's `sources` (none for shorthand tasks)
+    pub fn sources(&self) -> Option<&[String]> {
+        match self {
+            TaskDef::Shorthand(_) => None,
+            TaskDef::Full(task) => task.sources.as_deref(),
+        }
+    }
+
+    /// The task's `outputs` (none for shorthand tasks)
+    pub fn outputs(&self) -> Option<&[String]> {
+        match self {
+            TaskDef::Shorthand(_) => None,
+            TaskDef::Full(task) => task.outputs.as_deref(),
+        }
+    }
+
+    /// The task's own `env` (none for shorthand tasks), with `from_cmd`
+    /// values described rather than run
+    pub fn env(&self) -> Option<HashMap<String, String>> {
+        match self {
+            TaskDef::Shorthand(_) => None,
+            TaskDef::Full(task) => task.described_env(),
+        }
+    }
 }
 
 impl Config {
-    /// Load configuration from the default file
-    pub fn load() -> Result<Self> {
+    /// Load configuration from the nearest rnr.yaml in this or a parent directory
+    pub fn load() -> crate::Result<Self> {
         let path = find_config_file()?;
         Self::load_from(&path)
     }
 
     /// Load configuration from a specific path
-    pub fn load_from(path: &Path) -> Result<Self> {
-        let content = fs::read_to_string(path)
-            .with_context(|| format!("Failed to read config file: {}", path.display()))?;
-
-        let config: Config = serde_yaml::from_str(&content)
-            .with_context(|| format!("Failed to parse config file: {}", path.display()))?;
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let dir = tempfile::tempdir()?;
+    /// let path = dir.path().join("rnr.yaml");
+    /// std::fs::write(&path, "build: cargo build\n")?;
+    ///
+    /// let config = rnr::Config::load_from(&path)?;
+    /// assert_eq!(config.task_names(), ["build"]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn load_from(path: &Path) -> crate::Result<Self> {
+        let read_error = |source| Error::ReadConfig {
+            path: path.to_path_buf(),
+            source,
+        };
+        let too_large = |reason| Error::ConfigTooLarge {
+            path: path.to_path_buf(),
+            reason,
+        };
+        limits::check_size(path)
+            .map_err(read_error)?
+            .map_err(too_large)?;
+        let content = fs::read_to_string(path).map_err(read_error)?;
+        limits::check_nodes(&content, limits::MAX_NODES).map_err(too_large)?;
 
+        // A value that needed quotes gets the line and the quoted form
+        let parse_error = |source: serde_yaml::Error| {
+            let hint = source
+                .location()
+                .and_then(|at| Some((at.line(), source::quoting_hint(&content, at.line())?)));
+            match hint {
+                Some((line, hint)) => Error::NeedsQuotes {
+                    path: path.to_path_buf(),
+                    line,
+                    hint,
+                },
+                None => Error::ParseConfig {
+                    path: path.to_path_buf(),
+                    source,
+                },
+            }
+        };
+        let value: serde_yaml::Value = serde_yaml::from_str(&content).map_err(parse_error)?;
+        if let Some((name, line)) = source::find_reserved(&content, &value) {
+            return Err(Error::ReservedName {
+                path: path.to_path_buf(),
+                line,
+                name,
+            });
+        }
+        check_min_version(path, &value, VERSION)?;
+        // An empty or comments-only file is a project with no tasks yet
+        let document = match value {
+            serde_yaml::Value::Mapping(_) => content.as_str(),
+            serde_yaml::Value::Null => "{}",
+            other => {
+                return Err(Error::ConfigNotMapping {
+                    path: path.to_path_buf(),
+                    found: yaml_kind(&other),
+                })
+            }
+        };
+        let raw = RawConfig::scan(&content, &value);
+        if let Some(problem) = raw.misread_tasks.into_iter().next() {
+            return Err(Error::NeedsQuotes {
+                path: path.to_path_buf(),
+                line: problem.line,
+                hint: problem.message,
+            });
+        }
+        let mut config: Config = serde_yaml::from_str(document).map_err(parse_error)?;
+        limits::check_counts(&config).map_err(too_large)?;
+        config.unknown_fields = raw.unknown_fields;
+        config.locations = raw.locations;
+        config.altered_commands = raw.altered_commands;
+        config.path = Some(path.to_path_buf());
         Ok(config)
     }
 
+    /// Task fields in the file that rnr ignored, e.g. a misspelled `comand:`
+    pub fn unknown_fields(&self) -> &[UnknownField] {
+        &self.unknown_fields
+    }
+
+    /// Shorthand commands that YAML changed from what's written, e.g. by
+    /// reading a leading `&name` as an anchor
+    pub fn altered_commands(&self) -> &[QuotingProblem] {
+        &self.altered_commands
+    }
+
+    /// Fail if any task has unknown fields (strict mode)
+    pub fn check_unknown_fields(&self, path: &Path) -> crate::Result<()> {
+        if self.unknown_fields.is_empty() {
+            return Ok(());
+        }
+        Err(Error::UnknownFields {
+            path: path.to_path_buf(),
+            fields: self.unknown_fields.clone(),
+        })
+    }
+
     /// Get a task by name
     pub fn get_task(&self, name: &str) -> Option<&TaskDef> {
         self.tasks.get(name)
     }
 
+    /// What runs when `task` fails: its own `on_failure`, or else the
+    /// top-level `hooks.on_failure`
+    pub fn failure_hook<'a>(&'a self, task: &'a TaskDef) -> Option<&'a Hook> {
+        task.on_failure().or(self.hooks.on_failure.as_ref())
+    }
+
+    /// The task an old name in `renamed` now runs, unless a task still has
+    /// that name
+    pub fn renamed_to(&self, name: &str) -> Option<&str> {
+        if self.tasks.contains_key(name) {
+            return None;
+        }
+        self.renamed.get(name).map(String::as_str)
+    }
+
+    /// The task `name` means: the one with exactly that name, or else the
+    /// one task named the same but for case. Several such tasks always fail,
+    /// and with `strict`, so does a name in the wrong case.
+    pub fn match_case(&self, name: &str, strict: bool) -> Result<Option<&str>> {
+        if let Some((task, _)) = self.tasks.get_key_value(name) {
+            return Ok(Some(task));
+        }
+        match suggest::case_insensitive(name, &self.task_names()) {
+            Abbreviation::Unique(task) if strict => anyhow::bail!(
+                "Task '{}' not found; task names are case-sensitive in strict mode, \
+                 so use '{}'",
+                name,
+                task
+            ),
+            Abbreviation::Unique(task) => Ok(Some(task)),
+            Abbreviation::Ambiguous(candidates) => anyhow::bail!(
+                "Task '{}' matches several tasks that differ only in case: {}; use the exact name",
+                name,
+                candidates.join(", ")
+            ),
+            Abbreviation::None => Ok(None),
+        }
+    }
+
+    /// `replaced_by` and `renamed` entries that name a task that doesn't
+    /// exist, or old names that are still tasks, sorted
+    pub fn deprecation_problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        for name in self.task_names() {
+            let replacement = self.tasks[name]
+                .deprecated()
+                .and_then(Deprecated::replaced_by);
+            if let Some(replacement) = replacement.filter(|r| !self.tasks.contains_key(*r)) {
+                problems.push(format!(
+                    "task '{}' is replaced_by '{}', which isn't a task",
+                    name, replacement
+                ));
+            }
+        }
+        let mut renamed: Vec<_> = self.renamed.iter().collect();
+        renamed.sort();
+        for (old, new) in renamed {
+            if self.tasks.contains_key(old) {
+                problems.push(format!(
+                    "renamed '{}' is still a task, so it never runs '{}'",
+                    old, new
+                ));
+            } else if !self.tasks.contains_key(new) {
+                problems.push(format!(
+                    "renamed '{}' points at '{}', which isn't a task",
+                    old, new
+                ));
+            }
+        }
+        problems
+    }
+
+    /// `choose` options that run a task that doesn't exist, sorted
+    pub fn choice_problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        for name in self.task_names() {
+            let TaskDef::Full(task) = &self.tasks[name] else {
+                continue;
+            };
+            let options = task.choose.iter().flat_map(|choose| &choose.options);
+            for option in options {
+                // Tasks in other files are checked when they run
+                let nested = task.dir.is_some() || split_task_ref(&option.task).is_some();
+                if !nested && !self.tasks.contains_key(&option.task) {
+                    problems.push(format!(
+                        "task '{}' offers '{}', which runs '{}', which isn't a task",
+                        name, option.name, option.task
+                    ));
+                }
+            }
+        }
+        problems
+    }
+
+    /// Contexts tasks may name: `ci`, `local`, and the declared ones
+    pub fn known_contexts(&self) -> Vec<&str> {
+        let mut known = context::BUILTIN.to_vec();
+        for name in &self.contexts {
+            if !known.contains(&name.as_str()) {
+                known.push(name);
+            }
+        }
+        known
+    }
+
+    /// Contexts that tasks' `contexts` name but aren't known, as messages
+    pub fn context_problems(&self) -> Vec<String> {
+        let known = self.known_contexts();
+        let mut problems = Vec::new();
+        for name in self.task_names() {
+            let Some(contexts) = self.tasks[name].contexts() else {
+                continue;
+            };
+            for context in contexts.names().filter(|c| !known.contains(c)) {
+                problems.push(format!(
+                    "task '{}' names context '{}', which isn't one of: {}",
+                    name,
+                    context,
+                    known.join(", ")
+                ));
+            }
+        }
+        problems
+    }
+
     /// List all task names
     pub fn task_names(&self) -> Vec<&str> {
         let mut names: Vec<_> = self.tasks.keys().map(|s| s.as_str()).collect();
         names.sort();
         names
     }
+
+    /// `(task, dir)` for each `dir:` containing a backslash, which only
+    /// works as a separator because rnr normalizes it and is an escape in
+    /// double-quoted YAML
+    pub fn backslash_dirs(&self) -> Vec<(&str, &str)> {
+        let mut found = Vec::new();
+        for name in self.task_names() {
+            let Some(TaskDef::Full(task)) = self.get_task(name) else {
+                continue;
+            };
+            let mut dirs = vec![&task.dir];
+            for step in task.steps.iter().flatten() {
+                dirs.extend(step.step_defs().into_iter().map(|s| &s.dir));
+            }
+            found.extend(
+                dirs.into_iter()
+                    .flatten()
+                    .filter(|dir| dir.contains('\\'))
+                    .map(|dir| (name, dir.as_str())),
+            );
+        }
+        found
+    }
+
+    /// The rnr.yaml this config was loaded from, if it came from a file
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Where a task, or one of its steps (1-based), is written, e.g.
+    /// `api/rnr.yaml:42` with the file relative to `root`. `None` for a
+    /// config that didn't come from a file.
+    pub fn location(&self, task: &str, step: Option<usize>, root: &Path) -> Option<String> {
+        let line = match step {
+            Some(index) => self.locations.step(task, index)?,
+            None => self.locations.task(task)?,
+        };
+        let path = self.path.as_deref()?;
+        let file = path.strip_prefix(root).unwrap_or(path);
+        Some(format!("{}:{}", file.display(), line))
+    }
+
+    /// Other rnr.yaml files this config's `task:` references run tasks from,
+    /// for a config in `dir` of the project at `root`
+    pub fn nested_configs(&self, dir: &Path, root: &Path) -> BTreeSet<PathBuf> {
+        let mut references: Vec<(&Option<String>, Option<&str>)> = Vec::new();
+        for task_def in self.tasks.values() {
+            let TaskDef::Full(task) = task_def else {
+                continue;
+            };
+            references.push((&task.dir, task.task.as_deref()));
+            for option in task.choose.iter().flat_map(|choose| &choose.options) {
+                references.push((&task.dir, Some(&option.task)));
+            }
+            for step in task.steps.iter().flatten() {
+                references.extend(
+                    step.step_defs()
+                        .into_iter()
+                        .map(|s| (&s.dir, s.task.as_deref())),
+                );
+            }
+        }
+
+        references
+            .into_iter()
+            .filter_map(|(nested_dir, reference)| {
+                let reference = reference?;
+                match split_task_ref(reference).filter(|_| self.get_task(reference).is_none()) {
+                    Some((path, _)) => Some(join_dir(root, path).join(CONFIG_FILE)),
+                    None => Some(join_dir(dir, nested_dir.as_ref()?).join(CONFIG_FILE)),
+                }
+            })
+            .filter(|path| path.is_file())
+            .collect()
+    }
+}
+
+/// A `dir:` value as a native path, accepting `/` and `\` as separators so
+/// task files work on every platform
+pub fn normalize_dir(dir: &str) -> PathBuf {
+    PathBuf::from(dir.replace(['/', '\\'], std::path::MAIN_SEPARATOR_STR))
+}
+
+/// Resolve a `dir:` value against `base`
+pub fn join_dir(base: &Path, dir: &str) -> PathBuf {
+    base.join(normalize_dir(dir))
+}
+
+/// Where a `task:` reference finds its task
+pub enum TaskTarget<'r> {
+    /// A task in the referencing rnr.yaml
+    Current(&'r str),
+    /// A task in another rnr.yaml, run from that file's directory
+    Nested {
+        name: &'r str,
+        config: Arc<Config>,
+        dir: PathBuf,
+    },
+}
+
+/// Split a `<path>:<task>` reference into its path (relative to the project
+/// root) and task name. The path must contain a `/`, so names like
+/// `docker:build` stay plain task names.
+pub fn split_task_ref(reference: &str) -> Option<(&str, &str)> {
+    let slash = reference.rfind('/')?;
+    let colon = slash + reference[slash..].find(':')?;
+    let (path, name) = (&reference[..colon], &reference[colon + 1..]);
+    (!name.is_empty()).then_some((path, name))
+}
+
+/// Resolve a `task:` reference made from `config`
+///
+/// `<path>:<task>` runs a task from `<root>/<path>/rnr.yaml`, unless `config`
+/// has a task with exactly that name. Other references run a task from the
+/// rnr.yaml in `dir`, if there is one, or from `config`.
+pub fn resolve_task_ref<'r>(
+    reference: &'r str,
+    dir: Option<&Path>,
+    root: &Path,
+    config: &Config,
+    load: impl FnOnce(&Path) -> Result<Arc<Config>>,
+) -> Result<TaskTarget<'r>> {
+    let (nested_dir, name) = match split_task_ref(reference) {
+        Some((path, name)) if config.get_task(reference).is_none() => {
+            let nested_dir = join_dir(root, path);
+            if !nested_dir.join(CONFIG_FILE).is_file() {
+                anyhow::bail!(
+                    "Task reference '{}' points at {}, which has no {}",
+                    reference,
+                    path,
+                    CONFIG_FILE
+                );
+            }
+            (nested_dir, name)
+        }
+        _ => match dir.filter(|dir| dir.join(CONFIG_FILE).is_file()) {
+            Some(dir) => (dir.to_path_buf(), reference),
+            None => return Ok(TaskTarget::Current(reference)),
+        },
+    };
+    let config = load(&nested_dir.join(CONFIG_FILE))?;
+    Ok(TaskTarget::Nested {
+        name,
+        config,
+        dir: nested_dir,
+    })
 }
 
-/// Find the config file by walking up from the current directory
-pub fn find_config_file() -> Result<PathBuf> {
-    let current_dir = std::env::current_dir().context("Failed to get current directory")?;
+/// Error for a `task:` reference to `name`, which `config` doesn't define,
+/// naming the enclosing and nested rnr.yaml files that do
+pub fn task_not_found(
+    name: &str,
+    config: &Config,
+    root: &Path,
+    configs: &ConfigCache,
+) -> anyhow::Error {
+    let searched = config
+        .path()
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| root.join(CONFIG_FILE));
+    let dir = searched.parent().unwrap_or(root);
+    let relative = |path: &Path| {
+        path.strip_prefix(root)
+            .unwrap_or(path)
+            .display()
+            .to_string()
+    };
+
+    let mut candidates: Vec<PathBuf> = dir
+        .ancestors()
+        .skip(1)
+        .take_while(|ancestor| ancestor.starts_with(root))
+        .map(|ancestor| ancestor.join(CONFIG_FILE))
+        .filter(|path| path.is_file())
+        .collect();
+    candidates.extend(config.nested_configs(dir, root));
 
-    let mut dir = current_dir.as_path();
-    loop {
-        let config_path = dir.join(CONFIG_FILE);
-        if config_path.exists() {
-            return Ok(config_path);
+    let mut message = format!("Task '{}' not found in {}", name, relative(&searched));
+    for path in candidates.into_iter().filter(|path| *path != searched) {
+        let defines = configs
+            .load(&path)
+            .is_ok_and(|other| other.get_task(name).is_some());
+        let Some(relative_dir) = path.parent().and_then(|d| d.strip_prefix(root).ok()) else {
+            continue;
+        };
+        if defines {
+            message.push_str(&format!(
+                "\n  {} defines it; reference it as '{}'",
+                relative(&path),
+                task_ref(relative_dir, name)
+            ));
         }
+    }
+    anyhow::anyhow!(message)
+}
 
-        match dir.parent() {
-            Some(parent) => dir = parent,
-            None => break,
+/// A `<path>:<task>` reference to `name` in the rnr.yaml in `dir`, relative
+/// to the project root
+fn task_ref(dir: &Path, name: &str) -> String {
+    let parts: Vec<_> = dir
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect();
+    // The path needs a `/` to read as a path
+    let path = match parts.len() {
+        0 => "./".to_string(),
+        1 => format!("./{}", parts[0]),
+        _ => parts.join("/"),
+    };
+    format!("{}:{}", path, name)
+}
+
+/// What a YAML value is, for messages about the wrong kind
+fn yaml_kind(value: &serde_yaml::Value) -> &'static str {
+    match value {
+        serde_yaml::Value::Null => "empty document",
+        serde_yaml::Value::Bool(_) => "boolean",
+        serde_yaml::Value::Number(_) => "number",
+        serde_yaml::Value::String(_) => "string",
+        serde_yaml::Value::Sequence(_) => "list",
+        serde_yaml::Value::Mapping(_) => "mapping",
+        serde_yaml::Value::Tagged(_) => "tagged value",
+    }
+}
+
+/// Configs loaded during one invocation, keyed by canonical path
+///
+/// Tasks that delegate into the same sub-project many times share one parsed
+/// config instead of re-reading its rnr.yaml for every step.
+#[derive(Debug, Default)]
+pub struct ConfigCache {
+    configs: Mutex<HashMap<PathBuf, Arc<Config>>>,
+    reads: AtomicUsize,
+}
+
+impl ConfigCache {
+    /// Load the config at `path`, reading it only the first time it's asked for
+    pub fn load(&self, path: &Path) -> crate::Result<Arc<Config>> {
+        let key = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        let mut configs = self.configs.lock().unwrap();
+        if let Some(config) = configs.get(&key) {
+            return Ok(Arc::clone(config));
         }
+        self.reads.fetch_add(1, Ordering::Relaxed);
+        let config = Arc::new(Config::load_from(path)?);
+        configs.insert(key, Arc::clone(&config));
+        Ok(config)
     }
 
-    anyhow::bail!(
-        "No {} found in current directory or any parent directory",
-        CONFIG_FILE
-    )
+    /// Remember a config that was already read from `path`
+    pub fn insert(&self, path: &Path, config: Arc<Config>) {
+        let key = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        self.configs.lock().unwrap().insert(key, config);
+    }
+
+    /// How many config files have been read from disk
+    pub fn reads(&self) -> usize {
+        self.reads.load(Ordering::Relaxed)
+    }
+}
+
+/// Find the config file in the `--project-dir`, or by walking up from the
+/// current directory
+pub fn find_config_file() -> crate::Result<PathBuf> {
+    if let Some(dir) = project_dir_override() {
+        let path = dir.join(CONFIG_FILE);
+        return match path.exists() {
+            true => Ok(path),
+            false => Err(Error::ConfigNotInProjectDir(dir)),
+        };
+    }
+    let current_dir = std::env::current_dir().map_err(Error::CurrentDir)?;
+    find_config_from(wrapper_dir().as_deref(), &current_dir).ok_or(Error::ConfigNotFound)
+}
+
+/// The rnr.yaml next to the wrapper, if there is one, or the nearest one
+/// at or above `start`
+fn find_config_from(wrapper_dir: Option<&Path>, start: &Path) -> Option<PathBuf> {
+    wrapper_dir
+        .map(|dir| dir.join(CONFIG_FILE))
+        .filter(|path| path.exists())
+        .or_else(|| {
+            start
+                .ancestors()
+                .take(MAX_SEARCH_LEVELS)
+                .map(|dir| dir.join(CONFIG_FILE))
+                .find(|path| path.exists())
+        })
+}
+
+/// Project directory pinned with `--project-dir`
+static PROJECT_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Pin the project every command in this process works on (`--project-dir`),
+/// skipping the wrapper's directory and the search up from the current one
+pub fn set_project_dir(dir: PathBuf) {
+    let _ = PROJECT_DIR.set(dir);
+}
+
+/// The directory pinned with `--project-dir`, if any
+pub fn project_dir_override() -> Option<PathBuf> {
+    PROJECT_DIR.get().cloned()
+}
+
+/// The directory of the wrapper script that started rnr, if any
+pub fn wrapper_dir() -> Option<PathBuf> {
+    std::env::var_os(WRAPPER_DIR_ENV)
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_dir())
+}
+
+/// Create the cache directory of the project at `project_root`, along with
+/// the ignore file that keeps it out of commits
+pub fn create_cache_dir(project_root: &Path) -> Result<PathBuf> {
+    let dir = project_root.join(CACHE_DIR);
+    fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let rnr_dir = project_root.join(rnr_config::RNR_DIR);
+    rnr_config::ensure_ignored(&rnr_dir).with_context(|| {
+        format!(
+            "Failed to write {}",
+            rnr_dir.join(rnr_config::IGNORE_FILE).display()
+        )
+    })?;
+    Ok(dir)
 }
 
 /// Get the project root (directory containing rnr.yaml)
@@ -190,68 +2375,270 @@ build:
     }
 
     #[test]
-    fn test_parse_full_task_with_description() {
-        let yaml = r#"
-build:
-  description: Build the project for production
-  cmd: cargo build --release
-"#;
+    fn test_parse_full_task_with_description() {
+        let yaml = r#"
+build:
+  description: Build the project for production
+  cmd: cargo build --release
+"#;
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        if let Some(TaskDef::Full(task)) = config.get_task("build") {
+            assert_eq!(
+                task.description,
+                Some("Build the project for production".to_string())
+            );
+        } else {
+            panic!("Expected full task");
+        }
+    }
+
+    #[test]
+    fn test_parse_full_task_with_dir() {
+        let yaml = r#"
+build:
+  dir: src/subproject
+  cmd: cargo build
+"#;
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        if let Some(TaskDef::Full(task)) = config.get_task("build") {
+            assert_eq!(task.dir, Some("src/subproject".to_string()));
+        } else {
+            panic!("Expected full task");
+        }
+    }
+
+    #[test]
+    fn test_parse_full_task_with_env() {
+        let yaml = r#"
+build:
+  env:
+    NODE_ENV: production
+    DEBUG: "false"
+  cmd: npm run build
+"#;
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        if let Some(TaskDef::Full(task)) = config.get_task("build") {
+            let env = task.env.as_ref().unwrap();
+            assert_eq!(
+                env.get("NODE_ENV"),
+                Some(&EnvValue::Text("production".to_string()))
+            );
+            assert_eq!(env.get("DEBUG"), Some(&EnvValue::Text("false".to_string())));
+        } else {
+            panic!("Expected full task");
+        }
+    }
+
+    #[test]
+    fn test_parse_env_from_cmd() {
+        let yaml = r#"
+image:
+  env:
+    REGISTRY: ghcr.io/acme
+    IMAGE_TAG: { from_cmd: git describe --tags }
+  cmd: docker build .
+"#;
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        let task = config.get_task("image").unwrap();
+        let env = task.env().unwrap();
+        assert_eq!(env["REGISTRY"], "ghcr.io/acme");
+        assert_eq!(env["IMAGE_TAG"], "<computed from: git describe --tags>");
+
+        let yaml = "image:\n  env:\n    TAG: { from_cmd: x, shell: bash }\n  cmd: y\n";
+        assert!(serde_yaml::from_str::<Config>(yaml).is_err());
+    }
+
+    #[test]
+    fn test_parse_env_steps() {
+        let yaml = "dev:\n  steps:\n    - env:\n        COMPOSE_PROJECT_NAME: demo\n    - env_file: .env.local\n    - cmd: docker compose up\n      env:\n        DEBUG: '1'\n";
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        let Some(TaskDef::Full(dev)) = config.get_task("dev") else {
+            panic!("Expected full task");
+        };
+        let sets_env: Vec<bool> = dev
+            .steps
+            .iter()
+            .flatten()
+            .map(|step| step.step_defs()[0].sets_env())
+            .collect();
+        // `env` next to a `cmd` is still that command's own environment
+        assert_eq!(sets_env, [true, true, false]);
+
+        for yaml in [
+            "dev:\n  steps:\n    - parallel:\n        - env:\n            A: '1'\n        - cmd: make\n",
+            "dev:\n  steps:\n    - parallel:\n        api:\n          - env_file: api.env\n          - cmd: make\n",
+        ] {
+            let err = serde_yaml::from_str::<Config>(yaml).unwrap_err();
+            assert!(
+                err.to_string()
+                    .contains("an env step ('env' or 'env_file' on its own) can't be in a parallel block"),
+                "{}",
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_echo() {
+        let yaml = "echo_format: '[{dir}] $ {cmd}'\nquiet:\n  cmd: ./gen.sh\n  echo: false\nci:\n  steps:\n    - cmd: make\n      echo: false\n";
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(config.echo_format.as_deref(), Some("[{dir}] $ {cmd}"));
+        assert!(config.get_task("echo_format").is_none());
+        let Some(TaskDef::Full(quiet)) = config.get_task("quiet") else {
+            panic!("Expected full task");
+        };
+        assert_eq!(quiet.echo, Some(false));
+        let Some(TaskDef::Full(ci)) = config.get_task("ci") else {
+            panic!("Expected full task");
+        };
+        assert_eq!(
+            ci.steps.as_ref().unwrap()[0].step_defs()[0].echo,
+            Some(false)
+        );
+
+        let err = serde_yaml::from_str::<Config>("echo_format: '{cwd} $ {cmd}'\n").unwrap_err();
+        assert!(
+            err.to_string().contains(
+                "unknown echo_format placeholder '{cwd}' (expected one of: {cmd}, {dir}, {task}, {step})"
+            ),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_parse_priority_and_weight() {
+        let yaml = "build:\n  priority: low\n  steps:\n    - parallel:\n        - cmd: cargo build\n          weight: 4\n          priority: high\n        - cmd: ./lint.sh\n";
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        let task = config.get_task("build").unwrap();
+        assert_eq!(task.priority(), Some(Priority::Low));
+        let TaskDef::Full(build) = task else {
+            panic!("Expected full task");
+        };
+        let branches = build.steps.as_ref().unwrap()[0].step_defs();
+        assert_eq!(branches[0].priority, Some(Priority::High));
+        assert_eq!(branches[0].weight, NonZeroU32::new(4));
+        assert_eq!(branches[1].priority, None);
+        assert_eq!(branches[1].weight, None);
+
+        for bad in [
+            "x:\n  priority: urgent\n",
+            "x:\n  steps:\n    - cmd: a\n      weight: 0\n",
+        ] {
+            assert!(serde_yaml::from_str::<Config>(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_render_echo() {
+        let values = [("cmd", "make {dir}"), ("dir", "app"), ("step", "")];
+        assert_eq!(render_echo(DEFAULT_ECHO_FORMAT, &values), "$ make {dir}");
+        assert_eq!(
+            render_echo("[{dir}] $ {cmd}", &values),
+            "[app] $ make {dir}"
+        );
+        assert_eq!(render_echo("{step}{x} {", &values), "{x} {");
+    }
+
+    #[test]
+    fn test_parse_output_filter() {
+        let yaml = "test:\n  cmd: cargo test\n  output_filter: cargo-json\nbuild:\n  steps:\n    - cmd: cargo build\n      output_filter: quiet-success\n";
         let config: Config = serde_yaml::from_str(yaml).unwrap();
-        if let Some(TaskDef::Full(task)) = config.get_task("build") {
-            assert_eq!(
-                task.description,
-                Some("Build the project for production".to_string())
-            );
-        } else {
+        let Some(TaskDef::Full(test)) = config.get_task("test") else {
             panic!("Expected full task");
+        };
+        assert_eq!(test.output_filter, Some(OutputFilter::CargoJson));
+        let Some(TaskDef::Full(build)) = config.get_task("build") else {
+            panic!("Expected full task");
+        };
+        let steps = build.steps.as_ref().unwrap();
+        assert_eq!(
+            steps[0].step_defs()[0].output_filter,
+            Some(OutputFilter::QuietSuccess)
+        );
+
+        // Unknown names are reported, in tasks and in steps
+        for yaml in [
+            "test:\n  cmd: cargo test\n  output_filter: junit\n",
+            "test:\n  steps:\n    - cmd: cargo test\n      output_filter: junit\n",
+        ] {
+            let err = serde_yaml::from_str::<Config>(yaml).unwrap_err();
+            assert!(
+                err.to_string().contains(
+                    "unknown output_filter 'junit' (expected one of: cargo-json, quiet-success)"
+                ),
+                "{}",
+                err
+            );
         }
     }
 
     #[test]
-    fn test_parse_full_task_with_dir() {
+    fn test_parse_full_task_with_task_delegation() {
         let yaml = r#"
 build:
-  dir: src/subproject
-  cmd: cargo build
+  dir: services/api
+  task: build
 "#;
         let config: Config = serde_yaml::from_str(yaml).unwrap();
         if let Some(TaskDef::Full(task)) = config.get_task("build") {
-            assert_eq!(task.dir, Some("src/subproject".to_string()));
+            assert_eq!(task.task, Some("build".to_string()));
+            assert_eq!(task.dir, Some("services/api".to_string()));
         } else {
             panic!("Expected full task");
         }
     }
 
     #[test]
-    fn test_parse_full_task_with_env() {
+    fn test_parse_allow_abbreviations() {
         let yaml = r#"
-build:
-  env:
-    NODE_ENV: production
-    DEBUG: "false"
-  cmd: npm run build
+allow_abbreviations: true
+build: cargo build
 "#;
         let config: Config = serde_yaml::from_str(yaml).unwrap();
-        if let Some(TaskDef::Full(task)) = config.get_task("build") {
-            let env = task.env.as_ref().unwrap();
-            assert_eq!(env.get("NODE_ENV"), Some(&"production".to_string()));
-            assert_eq!(env.get("DEBUG"), Some(&"false".to_string()));
-        } else {
-            panic!("Expected full task");
-        }
+        assert!(config.allow_abbreviations);
+        assert_eq!(config.task_names(), vec!["build"]);
+
+        let config: Config = serde_yaml::from_str("build: cargo build").unwrap();
+        assert!(!config.allow_abbreviations);
     }
 
     #[test]
-    fn test_parse_full_task_with_task_delegation() {
+    fn test_parse_task_help_fields() {
         let yaml = r#"
-build:
-  dir: services/api
-  task: build
+deploy:
+  description: Deploy a version
+  long_description: Deploys the given version to the selected environment.
+  usage: rnr deploy <environment> [version]
+  args:
+    - name: environment
+      required: true
+      description: Target environment
+    - name: version
+      default: latest
+      choices: [latest, stable]
+  strict_args: true
+  examples:
+    - rnr deploy staging
+  cmd: ./deploy.sh ${args.environment}
 "#;
         let config: Config = serde_yaml::from_str(yaml).unwrap();
-        if let Some(TaskDef::Full(task)) = config.get_task("build") {
-            assert_eq!(task.task, Some("build".to_string()));
-            assert_eq!(task.dir, Some("services/api".to_string()));
+        let task_def = config.get_task("deploy").unwrap();
+        let args = task_def.arg_defs();
+        assert_eq!(args.len(), 2);
+        assert_eq!(args[0].name, "environment");
+        assert!(args[0].required);
+        assert!(!args[1].required);
+        assert_eq!(args[1].default, Some("latest".to_string()));
+        assert_eq!(args[1].choices.as_ref().unwrap().len(), 2);
+        if let TaskDef::Full(task) = task_def {
+            assert!(task.long_description.is_some());
+            assert!(task.strict_args);
+            assert_eq!(
+                task.usage,
+                Some("rnr deploy <environment> [version]".to_string())
+            );
+            assert_eq!(task.examples.as_ref().unwrap().len(), 1);
         } else {
             panic!("Expected full task");
         }
@@ -300,6 +2687,26 @@ ci:
         }
     }
 
+    #[test]
+    fn test_parse_step_description() {
+        let yaml = "ci:\n  description: Every check\n  steps:\n    - description: Integration tests\n      cmd: make test\n    - cmd: make lint\n";
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        let task = config.get_task("ci").unwrap();
+        assert_eq!(task.description(), Some("Every check"));
+        let TaskDef::Full(task) = task else {
+            panic!("Expected full task");
+        };
+        let steps: Vec<&StepDef> = task
+            .steps
+            .iter()
+            .flatten()
+            .flat_map(Step::step_defs)
+            .collect();
+        assert_eq!(steps[0].description.as_deref(), Some("Integration tests"));
+        assert_eq!(steps[1].description, None);
+        assert!(config.unknown_fields().is_empty());
+    }
+
     #[test]
     fn test_parse_steps_with_dir() {
         let yaml = r#"
@@ -316,7 +2723,7 @@ build-all:
             assert_eq!(steps.len(), 2);
             if let Step::Simple(step) = &steps[0] {
                 assert_eq!(step.dir, Some("services/api".to_string()));
-                assert_eq!(step.cmd, Some("cargo build".to_string()));
+                assert_eq!(step.cmd, Some(Cmd::Shell("cargo build".to_string())));
             } else {
                 panic!("Expected simple step");
             }
@@ -325,6 +2732,51 @@ build-all:
         }
     }
 
+    #[test]
+    fn test_parse_cmd_arrays() {
+        let yaml = r#"
+greet:
+  cmd: [cargo, run, --, --name, "hello world"]
+ci:
+  steps:
+    - cmd: ["echo", '{"a": 1}']
+"#;
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        let Some(TaskDef::Full(task)) = config.get_task("greet") else {
+            panic!("Expected full task");
+        };
+        let cmd = task.cmd.as_ref().unwrap();
+        assert!(cmd.is_direct());
+        assert_eq!(
+            cmd,
+            &Cmd::Argv(vec![
+                "cargo".into(),
+                "run".into(),
+                "--".into(),
+                "--name".into(),
+                "hello world".into()
+            ])
+        );
+        let Some(TaskDef::Full(task)) = config.get_task("ci") else {
+            panic!("Expected full task");
+        };
+        let Step::Simple(step) = &task.steps.as_ref().unwrap()[0] else {
+            panic!("Expected simple step");
+        };
+        assert_eq!(
+            step.cmd,
+            Some(Cmd::Argv(vec!["echo".into(), r#"{"a": 1}"#.into()]))
+        );
+    }
+
+    #[test]
+    #[cfg(not(target_os = "windows"))]
+    fn test_cmd_display_quotes_arrays() {
+        let cmd = Cmd::Argv(vec!["cargo".into(), "run".into(), "hello world".into()]);
+        assert_eq!(cmd.to_string(), "cargo run 'hello world'");
+        assert_eq!(Cmd::Shell("echo $HOME".into()).to_string(), "echo $HOME");
+    }
+
     // ==================== Parallel Parsing ====================
 
     #[test]
@@ -340,7 +2792,7 @@ build-all:
         if let Some(TaskDef::Full(task)) = config.get_task("build-all") {
             let steps = task.steps.as_ref().unwrap();
             assert_eq!(steps.len(), 1);
-            if let Step::Parallel { parallel } = &steps[0] {
+            if let Step::Parallel { parallel, .. } = &steps[0] {
                 assert_eq!(parallel.len(), 2);
             } else {
                 panic!("Expected parallel step");
@@ -350,6 +2802,35 @@ build-all:
         }
     }
 
+    #[test]
+    fn test_parse_parallel_lanes() {
+        let yaml = r#"
+ci:
+  steps:
+    - parallel:
+        web:
+          - cmd: npm install
+          - cmd: npm run build
+        api:
+          - cmd: cargo build
+"#;
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        let Some(TaskDef::Full(task)) = config.get_task("ci") else {
+            panic!("Expected full task");
+        };
+        let Step::Lanes { lanes, .. } = &task.steps.as_ref().unwrap()[0] else {
+            panic!("Expected lanes");
+        };
+        let names: Vec<(&str, usize)> = lanes
+            .iter()
+            .map(|lane| (lane.name.as_str(), lane.steps.len()))
+            .collect();
+        assert_eq!(names, [("web", 2), ("api", 1)]);
+
+        let duplicate = "ci:\n  steps:\n    - parallel:\n        a: []\n        a: []\n";
+        assert!(serde_yaml::from_str::<Config>(duplicate).is_err());
+    }
+
     #[test]
     fn test_parse_mixed_sequential_and_parallel() {
         let yaml = r#"
@@ -373,6 +2854,281 @@ deploy:
         }
     }
 
+    // ==================== Builtin Steps ====================
+
+    #[test]
+    fn test_parse_builtin_steps() {
+        let yaml = r#"
+package:
+  steps:
+    - mkdir: release/assets
+    - copy: { from: dist/, to: release/assets/ }
+    - remove: target/tmp/*.log
+    - remove: { path: ../shared/cache, allow_outside_root: true }
+"#;
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        if let Some(TaskDef::Full(task)) = config.get_task("package") {
+            let steps = task.steps.as_ref().unwrap();
+            assert_eq!(steps.len(), 4);
+
+            let Step::Simple(mkdir) = &steps[0] else {
+                panic!("Expected simple step");
+            };
+            assert_eq!(mkdir.mkdir, Some("release/assets".to_string()));
+
+            let Step::Simple(copy) = &steps[1] else {
+                panic!("Expected simple step");
+            };
+            let spec = copy.copy.as_ref().unwrap();
+            assert_eq!(spec.from, "dist/");
+            assert_eq!(spec.to, "release/assets/");
+
+            let Step::Simple(remove) = &steps[2] else {
+                panic!("Expected simple step");
+            };
+            let spec = remove.remove.as_ref().unwrap();
+            assert_eq!(spec.path(), "target/tmp/*.log");
+            assert!(!spec.allow_outside_root());
+
+            let Step::Simple(remove) = &steps[3] else {
+                panic!("Expected simple step");
+            };
+            let spec = remove.remove.as_ref().unwrap();
+            assert_eq!(spec.path(), "../shared/cache");
+            assert!(spec.allow_outside_root());
+        } else {
+            panic!("Expected full task with steps");
+        }
+    }
+
+    #[test]
+    fn test_parse_download_step() {
+        let yaml = r#"
+fetch:
+  steps:
+    - download:
+        url: https://example.com/schema.json
+        to: assets/schema.json
+        sha256: ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad
+        token_env: GITHUB_TOKEN
+"#;
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        if let Some(TaskDef::Full(task)) = config.get_task("fetch") {
+            let steps = task.steps.as_ref().unwrap();
+            let Step::Simple(step) = &steps[0] else {
+                panic!("Expected simple step");
+            };
+            let spec = step.download.as_ref().unwrap();
+            assert_eq!(spec.url, "https://example.com/schema.json");
+            assert_eq!(spec.to, "assets/schema.json");
+            assert!(spec.sha256.is_some());
+            assert_eq!(spec.token_env, Some("GITHUB_TOKEN".to_string()));
+        } else {
+            panic!("Expected full task with steps");
+        }
+    }
+
+    #[test]
+    fn test_parse_umask_and_log_file_mode() {
+        let yaml =
+            "umask: \"077\"\nbuild:\n  log: build.log\n  log_file_mode: \"0600\"\n  cmd: make\n";
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(config.umask, Some(FileMode(0o77)));
+        let Some(TaskDef::Full(task)) = config.get_task("build") else {
+            panic!("Expected full task");
+        };
+        assert_eq!(task.log_file_mode, Some(FileMode(0o600)));
+        assert!(config.unknown_fields().is_empty());
+
+        let config: Config = serde_yaml::from_str("build: make\n").unwrap();
+        assert_eq!(config.umask, None);
+    }
+
+    #[test]
+    fn test_min_version() {
+        let path = Path::new("rnr.yaml");
+        let check = |yaml: &str, current: &str| {
+            let document: serde_yaml::Value = serde_yaml::from_str(yaml).unwrap();
+            check_min_version(path, &document, current)
+        };
+        assert!(check("build: make\n", "0.3.0").is_ok());
+        assert!(check("min_version: \"0.5\"\n", "0.5.0").is_ok());
+        assert!(check("min_version: 0.5\n", "0.6.1").is_ok());
+        assert!(check("min_version: v0.5.2\n", "0.5.2").is_ok());
+        assert_eq!(
+            check("min_version: \"0.5\"\n", "0.3.0")
+                .unwrap_err()
+                .to_string(),
+            "this project requires rnr >= 0.5, you have 0.3.0 — run ./rnr upgrade"
+        );
+        // A prerelease comes before its release
+        assert!(check("min_version: 0.5.0\n", "0.5.0-rc.1").is_err());
+        assert_eq!(
+            check("min_version: latest\n", "0.5.0")
+                .unwrap_err()
+                .to_string(),
+            "min_version in rnr.yaml must be a version like \"0.5\", not latest"
+        );
+
+        // A config rnr can't otherwise parse still reports the version first
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join(CONFIG_FILE);
+        fs::write(
+            &path,
+            "min_version: \"999.0\"\nstrict: [not, a, bool]\nbuild: make\n",
+        )
+        .unwrap();
+        let err = Config::load_from(&path).unwrap_err();
+        assert!(matches!(err, Error::RnrTooOld { .. }), "{}", err);
+
+        // And an rnr new enough reads it as a setting, not a task
+        fs::write(&path, "min_version: 0.1\nbuild: make\n").unwrap();
+        let config = Config::load_from(&path).unwrap();
+        assert_eq!(config.min_version.as_deref(), Some("0.1"));
+        assert_eq!(config.task_names(), ["build"]);
+    }
+
+    #[test]
+    fn test_parse_render_step() {
+        let yaml = r#"
+configure:
+  steps:
+    - render: { template: conf/app.toml.tmpl, to: .local/app.toml, mode: 0600 }
+    - render: { content: "name = ${env.USER}", to: .local/name.txt, mode: 0o644 }
+"#;
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        if let Some(TaskDef::Full(task)) = config.get_task("configure") {
+            let steps = task.steps.as_ref().unwrap();
+            let Step::Simple(step) = &steps[0] else {
+                panic!("Expected simple step");
+            };
+            let spec = step.render.as_ref().unwrap();
+            assert_eq!(spec.template, Some("conf/app.toml.tmpl".to_string()));
+            assert_eq!(spec.mode, Some(FileMode(0o600)));
+
+            let Step::Simple(step) = &steps[1] else {
+                panic!("Expected simple step");
+            };
+            let spec = step.render.as_ref().unwrap();
+            assert_eq!(spec.content, Some("name = ${env.USER}".to_string()));
+            assert_eq!(spec.mode, Some(FileMode(0o644)));
+        } else {
+            panic!("Expected full task with steps");
+        }
+    }
+
+    #[test]
+    fn test_parse_invalid_file_mode() {
+        let yaml = r#"
+configure:
+  steps:
+    - render: { content: "x", to: out.txt, mode: "0999" }
+"#;
+        let result: Result<Config, _> = serde_yaml::from_str(yaml);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_parse_expect_exit() {
+        let yaml = r#"
+check:
+  cmd: diff a b
+  expect_exit: any
+lint:
+  steps:
+    - cmd: grep -q TODO src
+      expect_exit: [0, 1]
+      export_exit: HAS_TODO
+    - cmd: exit 2
+      expect_exit: 2
+"#;
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        let TaskDef::Full(check) = config.get_task("check").unwrap() else {
+            panic!("expected a full task");
+        };
+        assert_eq!(check.expect_exit, Some(ExpectExit::Any));
+        let TaskDef::Full(lint) = config.get_task("lint").unwrap() else {
+            panic!("expected a full task");
+        };
+        let steps = lint.steps.as_ref().unwrap();
+        let grep = steps[0].step_defs()[0];
+        assert_eq!(grep.expect_exit, Some(ExpectExit::Codes(vec![0, 1])));
+        assert_eq!(grep.export_exit.as_deref(), Some("HAS_TODO"));
+        assert_eq!(
+            steps[1].step_defs()[0].expect_exit,
+            Some(ExpectExit::Codes(vec![2]))
+        );
+
+        for value in ["some", "[]", "1.5"] {
+            let yaml = format!("check:\n  cmd: diff a b\n  expect_exit: {}\n", value);
+            let err = serde_yaml::from_str::<Config>(&yaml).unwrap_err();
+            assert!(
+                err.to_string()
+                    .contains("expect_exit must be 'any', an exit code, or a list of exit codes"),
+                "{}",
+                err
+            );
+        }
+
+        let yaml = "ci:\n  steps:\n    - parallel:\n        - cmd: grep -q a b\n          export_exit: FOUND\n";
+        let err = serde_yaml::from_str::<Config>(yaml).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("'export_exit' can't be used in a parallel block"));
+    }
+
+    #[test]
+    fn test_expect_exit_display() {
+        assert_eq!(ExpectExit::Any.to_string(), "any exit code");
+        assert_eq!(ExpectExit::Codes(vec![1]).to_string(), "exit code 1");
+        assert_eq!(
+            ExpectExit::Codes(vec![0, 1]).to_string(),
+            "exit codes 0 or 1"
+        );
+        assert_eq!(
+            ExpectExit::Codes(vec![0, 1, 2]).to_string(),
+            "exit codes 0, 1 or 2"
+        );
+        assert!(ExpectExit::Codes(vec![0, 1]).allows(1));
+        assert!(!ExpectExit::Codes(vec![0, 1]).allows(2));
+        assert!(ExpectExit::Any.allows(255));
+    }
+
+    #[test]
+    fn test_parse_checkout() {
+        let yaml = r#"
+release:
+  checkout: clean
+  cmd: cargo publish
+docs:
+  checkout: { ref: main }
+  cmd: mdbook build
+"#;
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        let checkout = |name: &str| config.get_task(name).unwrap().checkout().cloned();
+        assert_eq!(checkout("release"), Some(Checkout { reference: None }));
+        assert_eq!(
+            checkout("docs"),
+            Some(Checkout {
+                reference: Some("main".to_string())
+            })
+        );
+
+        let result: Result<Config, _> = serde_yaml::from_str("release:\n  checkout: dirty\n");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_parse_copy_requires_from_and_to() {
+        let yaml = r#"
+package:
+  steps:
+    - copy: { from: dist/ }
+"#;
+        let result: Result<Config, _> = serde_yaml::from_str(yaml);
+        assert!(result.is_err());
+    }
+
     // ==================== Task Names ====================
 
     #[test]
@@ -525,4 +3281,403 @@ build:
             panic!("Expected full task");
         }
     }
+
+    // ==================== Unknown Fields ====================
+
+    #[test]
+    fn test_unknown_fields() {
+        let yaml = "strict: true\nlint: cargo clippy\nbuild:\n  comand: cargo build\nci:\n  step:\n    - cmd: make\n  timeout: 10\n";
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        assert!(config.strict);
+        assert!(config.get_task("strict").is_none());
+
+        let unknown = RawConfig::scan(yaml, &serde_yaml::from_str(yaml).unwrap()).unknown_fields;
+        let found: Vec<(&str, &str, Option<&str>, Option<usize>)> = unknown
+            .iter()
+            .map(|u| (u.task.as_str(), u.field.as_str(), u.suggestion, u.line))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("build", "comand", Some("cmd"), Some(4)),
+                ("ci", "step", Some("steps"), Some(6)),
+                ("ci", "timeout", None, Some(8))
+            ]
+        );
+        assert_eq!(
+            unknown[0].to_string(),
+            "task 'build' has unknown field 'comand' (did you mean 'cmd'?)"
+        );
+    }
+
+    #[test]
+    fn test_check_unknown_fields() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join(CONFIG_FILE);
+        fs::write(&path, "build:\n  descripton: Build\n  cmd: make\n").unwrap();
+        let config = Config::load_from(&path).unwrap();
+        assert_eq!(config.unknown_fields().len(), 1);
+
+        let err = config.check_unknown_fields(&path).unwrap_err();
+        assert!(matches!(err, Error::UnknownFields { .. }));
+        assert!(err
+            .to_string()
+            .ends_with("(strict mode):\n  line 2: task 'build' has unknown field 'descripton' (did you mean 'description'?)"));
+    }
+
+    #[test]
+    fn test_load_empty_config() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join(CONFIG_FILE);
+        for content in ["", "\n", "# tasks go here\n", "---\n", "null\n", "~\n"] {
+            fs::write(&path, content).unwrap();
+            let config = Config::load_from(&path).unwrap();
+            assert!(config.tasks.is_empty(), "{:?}", content);
+            assert_eq!(config.path(), Some(path.as_path()));
+        }
+    }
+
+    #[test]
+    fn test_load_config_that_isnt_a_mapping() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join(CONFIG_FILE);
+        for (content, found) in [
+            ("- build\n- test\n", "list"),
+            ("cargo build\n", "string"),
+            ("42\n", "number"),
+        ] {
+            fs::write(&path, content).unwrap();
+            let err = Config::load_from(&path).unwrap_err();
+            assert!(matches!(err, Error::ConfigNotMapping { .. }), "{:?}", err);
+            assert!(
+                err.to_string().ends_with(&format!(
+                    "rnr.yaml must be a mapping of task names to tasks (like 'build: cargo build'), but it's a {}",
+                    found
+                )),
+                "{}",
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn test_load_task_named_like_a_setting() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join(CONFIG_FILE);
+        let load = |content: &str| {
+            fs::write(&path, content).unwrap();
+            Config::load_from(&path)
+        };
+        for (content, name, line) in [
+            ("build: make\nhooks: ./install-hooks.sh\n", "hooks", 2),
+            ("tz: date -u\n", "tz", 1),
+            (
+                "budget:\n  description: Check the bundle size\n  cmd: size-limit\n",
+                "budget",
+                1,
+            ),
+            ("strict:\n  steps:\n    - cmd: cargo clippy\n", "strict", 1),
+            ("bins:\n  cmd: ls target/release\n", "bins", 1),
+            ("settings:\n  steps:\n    - cmd: code .\n", "settings", 1),
+        ] {
+            let err = load(content).unwrap_err();
+            assert!(matches!(err, Error::ReservedName { .. }), "{:?}", err);
+            assert!(
+                err.to_string().ends_with(&format!(
+                    "rnr.yaml:{}: `{}` is a reserved setting; rename the task",
+                    line, name
+                )),
+                "{}",
+                err
+            );
+        }
+
+        // The settings themselves still load
+        let config = load(
+            "hooks:\n  on_failure: echo failed\ntz: UTC\nbudget: 10m\nstrict: false\n\
+             bins:\n  lint: tools/lint\nbuild: make\n",
+        )
+        .unwrap();
+        assert!(config.hooks.on_failure.is_some());
+        assert_eq!(config.task_names(), ["build"]);
+    }
+
+    #[test]
+    fn test_load_commands_that_need_quotes() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join(CONFIG_FILE);
+        let load_error = |content: &str| {
+            fs::write(&path, content).unwrap();
+            let err = Config::load_from(&path).unwrap_err();
+            assert!(matches!(err, Error::NeedsQuotes { .. }), "{:?}", err);
+            let message = err.to_string();
+            message[message.find("rnr.yaml:").unwrap()..].to_string()
+        };
+
+        // The classic trap: a `: ` in a command
+        assert_eq!(
+            load_error("build: make\ndeploy: ./deploy.sh --env=prod: true # ship it\n"),
+            "rnr.yaml:2: the value of 'deploy' needs quotes, because a ': ' inside it starts \
+             a mapping in YAML; write the line as:\n    deploy: './deploy.sh --env=prod: true'"
+        );
+        assert_eq!(
+            load_error("ci:\n  steps:\n    - cmd: echo done:\n"),
+            "rnr.yaml:3: the value of 'cmd' needs quotes, because a ':' at its end starts a \
+             mapping in YAML; write the line as:\n    - cmd: 'echo done:'"
+        );
+        assert_eq!(
+            load_error("clean: *.o\n"),
+            "rnr.yaml:1: the value of 'clean' needs quotes, because a leading '*' means \
+             something else to YAML; write the line as:\n    clean: '*.o'"
+        );
+
+        // Values that parse, but as something other than a command
+        assert_eq!(
+            load_error("build: make\ndeploy:\n  echo deploying to: prod\n"),
+            "rnr.yaml:3: task 'deploy' is a mapping in YAML, because the ': ' in 'echo \
+             deploying to: prod' starts one; if it's a command, write it as:\n    \
+             deploy: 'echo deploying to: prod'"
+        );
+        assert_eq!(
+            load_error("check: true\n"),
+            "rnr.yaml:1: task 'check' is a boolean in YAML, not a command; if it's a \
+             command, write the line as:\n    check: 'true'"
+        );
+
+        // Quoted, a full task, or an ordinary failure elsewhere
+        fs::write(
+            &path,
+            "deploy: './deploy.sh --env=prod: true'\nci:\n  cmd: make\nurl: echo http://x\n",
+        )
+        .unwrap();
+        Config::load_from(&path).unwrap();
+        fs::write(&path, "build: [unclosed\n").unwrap();
+        let err = Config::load_from(&path).unwrap_err();
+        assert!(matches!(err, Error::ParseConfig { .. }), "{:?}", err);
+    }
+
+    #[test]
+    fn test_location() {
+        let temp = tempfile::tempdir().unwrap();
+        fs::create_dir(temp.path().join("api")).unwrap();
+        let path = temp.path().join("api").join(CONFIG_FILE);
+        fs::write(
+            &path,
+            "lint: cargo clippy
+deploy:
+  steps:
+    - cmd: make
+    - task: lint
+",
+        )
+        .unwrap();
+        let config = Config::load_from(&path).unwrap();
+        let file = Path::new("api").join(CONFIG_FILE).display().to_string();
+        assert_eq!(
+            config.location("deploy", None, temp.path()),
+            Some(format!("{}:2", file))
+        );
+        assert_eq!(
+            config.location("deploy", Some(2), temp.path()),
+            Some(format!("{}:5", file))
+        );
+        assert_eq!(config.location("deploy", Some(3), temp.path()), None);
+        assert_eq!(config.location("missing", None, temp.path()), None);
+
+        // Parsed from a string, there's no file to point at
+        let config: Config = serde_yaml::from_str("lint: cargo clippy\n").unwrap();
+        assert_eq!(config.location("lint", None, temp.path()), None);
+    }
+
+    // ==================== Config Cache ====================
+
+    #[test]
+    fn test_config_cache_reads_each_file_once() {
+        let temp = tempfile::tempdir().unwrap();
+        let sub = temp.path().join("sub");
+        fs::create_dir(&sub).unwrap();
+        fs::write(temp.path().join(CONFIG_FILE), "build: echo root\n").unwrap();
+        fs::write(sub.join(CONFIG_FILE), "build: echo sub\n").unwrap();
+
+        let cache = ConfigCache::default();
+        let first = cache.load(&sub.join(CONFIG_FILE)).unwrap();
+        let again = cache
+            .load(&temp.path().join("sub/../sub").join(CONFIG_FILE))
+            .unwrap();
+        assert!(Arc::ptr_eq(&first, &again));
+        cache.load(&temp.path().join(CONFIG_FILE)).unwrap();
+        assert_eq!(cache.reads(), 2);
+
+        assert!(cache.load(&temp.path().join("missing.yaml")).is_err());
+    }
+
+    // ==================== Config Discovery ====================
+
+    #[test]
+    fn test_find_config_prefers_wrapper_dir() {
+        let temp = tempfile::tempdir().unwrap();
+        let (repo, elsewhere) = (temp.path().join("repo"), temp.path().join("elsewhere"));
+        let sub = elsewhere.join("sub");
+        fs::create_dir_all(&repo).unwrap();
+        fs::create_dir_all(&sub).unwrap();
+        fs::write(repo.join(CONFIG_FILE), "build: echo repo\n").unwrap();
+        fs::write(elsewhere.join(CONFIG_FILE), "build: echo elsewhere\n").unwrap();
+
+        assert_eq!(
+            find_config_from(Some(&repo), &sub),
+            Some(repo.join(CONFIG_FILE))
+        );
+        assert_eq!(
+            find_config_from(None, &sub),
+            Some(elsewhere.join(CONFIG_FILE))
+        );
+        // A wrapper without an rnr.yaml next to it falls back to the search
+        assert_eq!(
+            find_config_from(Some(temp.path()), &sub),
+            Some(elsewhere.join(CONFIG_FILE))
+        );
+        assert_eq!(find_config_from(None, temp.path()), None);
+    }
+
+    #[test]
+    fn test_find_config_search_is_capped() {
+        let temp = tempfile::tempdir().unwrap();
+        fs::write(temp.path().join(CONFIG_FILE), "build: echo top\n").unwrap();
+        let near = (1..MAX_SEARCH_LEVELS)
+            .fold(temp.path().to_path_buf(), |dir, i| dir.join(i.to_string()));
+        let far = near.join("deeper");
+        fs::create_dir_all(&far).unwrap();
+
+        assert_eq!(
+            find_config_from(None, &near),
+            Some(temp.path().join(CONFIG_FILE))
+        );
+        assert_eq!(find_config_from(None, &far), None);
+    }
+
+    // ==================== Task References ====================
+
+    #[test]
+    fn test_split_task_ref() {
+        assert_eq!(
+            split_task_ref("services/api:build"),
+            Some(("services/api", "build"))
+        );
+        assert_eq!(split_task_ref("./api:build"), Some(("./api", "build")));
+        assert_eq!(split_task_ref("./:build"), Some(("./", "build")));
+        // Colons in the task name belong to the task
+        assert_eq!(
+            split_task_ref("services/api:docker:build"),
+            Some(("services/api", "docker:build"))
+        );
+        assert_eq!(split_task_ref("docker:build"), None);
+        assert_eq!(split_task_ref("services/api"), None);
+        assert_eq!(split_task_ref("services/api:"), None);
+    }
+
+    fn nested_project() -> tempfile::TempDir {
+        let temp = tempfile::tempdir().unwrap();
+        let api = temp.path().join("services").join("api");
+        fs::create_dir_all(&api).unwrap();
+        fs::write(
+            temp.path().join(CONFIG_FILE),
+            "build: echo root\n\"tools/gen:all\": echo literal\napi:\n  dir: services/api\n  task: lint\n",
+        )
+        .unwrap();
+        fs::write(
+            api.join(CONFIG_FILE),
+            "lint: echo lint\n\"docker:build\": echo image\n",
+        )
+        .unwrap();
+        temp
+    }
+
+    fn resolve<'r>(reference: &'r str, dir: Option<&Path>, root: &Path) -> Result<TaskTarget<'r>> {
+        let config = Config::load_from(&root.join(CONFIG_FILE)).unwrap();
+        resolve_task_ref(reference, dir, root, &config, |path| {
+            Ok(Arc::new(Config::load_from(path)?))
+        })
+    }
+
+    #[test]
+    fn test_resolve_task_ref() {
+        let temp = nested_project();
+        let root = temp.path();
+        let api = root.join("services").join("api");
+
+        let Ok(TaskTarget::Nested { name, config, dir }) =
+            resolve("services/api:docker:build", None, root)
+        else {
+            panic!("expected a nested task");
+        };
+        assert_eq!((name, dir.as_path()), ("docker:build", api.as_path()));
+        assert!(config.get_task(name).is_some());
+
+        assert!(matches!(
+            resolve("lint", Some(&api), root),
+            Ok(TaskTarget::Nested { name: "lint", .. })
+        ));
+        assert!(matches!(
+            resolve("build", Some(&root.join("services")), root),
+            Ok(TaskTarget::Current("build"))
+        ));
+        // A task literally named like a reference wins over the reference
+        assert!(matches!(
+            resolve("tools/gen:all", None, root),
+            Ok(TaskTarget::Current("tools/gen:all"))
+        ));
+
+        let err = resolve("services/web:build", None, root).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Task reference 'services/web:build' points at services/web, which has no rnr.yaml"
+        );
+    }
+
+    #[test]
+    fn test_task_not_found_names_other_configs() {
+        let temp = nested_project();
+        let root = temp.path();
+        let configs = ConfigCache::default();
+
+        let config = configs.load(&root.join(CONFIG_FILE)).unwrap();
+        let err = task_not_found("lint", &config, root, &configs);
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "Task 'lint' not found in rnr.yaml\n  {} defines it; reference it as 'services/api:lint'",
+                Path::new("services/api/rnr.yaml").display()
+            )
+        );
+
+        let nested = configs
+            .load(&root.join("services/api").join(CONFIG_FILE))
+            .unwrap();
+        let err = task_not_found("build", &nested, root, &configs);
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "Task 'build' not found in {}\n  rnr.yaml defines it; reference it as './:build'",
+                Path::new("services/api/rnr.yaml").display()
+            )
+        );
+
+        let err = task_not_found("deploy", &config, root, &configs);
+        assert_eq!(err.to_string(), "Task 'deploy' not found in rnr.yaml");
+    }
+
+    // ==================== Directories ====================
+
+    #[test]
+    fn test_join_dir_accepts_both_separators() {
+        let base = Path::new("project");
+        let expected: PathBuf = ["project", "services", "api"].iter().collect();
+        assert_eq!(join_dir(base, "services/api"), expected);
+        assert_eq!(join_dir(base, "services\\api"), expected);
+        assert_eq!(join_dir(base, "services\\api/"), expected);
+
+        let up: PathBuf = ["project", "..", "shared"].iter().collect();
+        assert_eq!(join_dir(base, "..\\shared"), up);
+        assert_eq!(join_dir(base, "../shared"), up);
+    }
 }
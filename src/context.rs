@@ -0,0 +1,106 @@
+//! The context rnr runs in, for tasks limited with `contexts:`
+//!
+//! The context is `RNR_CONTEXT` when it's set, otherwise `ci` when the `CI`
+//! variable says this is a CI build, otherwise `local`. Projects can declare
+//! more names (`staging`, `prod`) with a top-level `contexts:` list.
+
+use anyhow::Result;
+
+use crate::config::Contexts;
+
+/// Names the context outright, overriding detection
+pub const CONTEXT_ENV: &str = "RNR_CONTEXT";
+
+/// Contexts every project has
+pub const BUILTIN: &[&str] = &["ci", "local"];
+
+/// The context of this process
+pub fn current() -> String {
+    detect(
+        std::env::var(CONTEXT_ENV).ok().as_deref(),
+        std::env::var("CI").ok().as_deref(),
+    )
+}
+
+/// The context, given `RNR_CONTEXT` and `CI`
+fn detect(context: Option<&str>, ci: Option<&str>) -> String {
+    if let Some(context) = context.map(str::trim).filter(|c| !c.is_empty()) {
+        return context.to_string();
+    }
+    let ci = ci
+        .map(str::trim)
+        .is_some_and(|ci| !ci.is_empty() && !ci.eq_ignore_ascii_case("false") && ci != "0");
+    if ci { "ci" } else { "local" }.to_string()
+}
+
+/// Fail if `task`, limited by `contexts`, may not run in `context`; `known`
+/// are the contexts its rnr.yaml knows, for listing the allowed ones
+pub fn check(task: &str, contexts: &Contexts, context: &str, known: &[&str]) -> Result<()> {
+    if contexts.permits(context) {
+        return Ok(());
+    }
+    let allowed: Vec<&str> = known
+        .iter()
+        .copied()
+        .filter(|name| contexts.permits(name))
+        .collect();
+    let allowed = match allowed.is_empty() {
+        true => "none".to_string(),
+        false => allowed.join(", "),
+    };
+    anyhow::bail!(
+        "Task '{}' can't run in the '{}' context (allowed: {}). Set {} to run it in another context",
+        task,
+        context,
+        allowed,
+        CONTEXT_ENV
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::config::Config;
+
+    fn config(yaml: &str) -> Config {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(detect(None, None), "local");
+        assert_eq!(detect(None, Some("true")), "ci");
+        assert_eq!(detect(None, Some("1")), "ci");
+        assert_eq!(detect(None, Some("false")), "local");
+        assert_eq!(detect(None, Some("")), "local");
+        assert_eq!(detect(Some("staging"), Some("true")), "staging");
+        assert_eq!(detect(Some(" "), Some("true")), "ci");
+    }
+
+    #[test]
+    fn test_check() {
+        let config = config(
+            "contexts: [prod]\ndeploy:\n  cmd: ./deploy\n  contexts:\n    allow: [ci, prod]\nclean:\n  cmd: rm -rf out\n  contexts:\n    deny: [ci]\n",
+        );
+        let limits = |name| config.get_task(name).unwrap().contexts().unwrap();
+        let known = config.known_contexts();
+
+        check("deploy", limits("deploy"), "ci", &known).unwrap();
+        assert_eq!(
+            check("deploy", limits("deploy"), "local", &known)
+                .unwrap_err()
+                .to_string(),
+            "Task 'deploy' can't run in the 'local' context (allowed: ci, prod). \
+             Set RNR_CONTEXT to run it in another context"
+        );
+        check("clean", limits("clean"), "local", &known).unwrap();
+        assert_eq!(
+            check("clean", limits("clean"), "ci", &known)
+                .unwrap_err()
+                .to_string(),
+            "Task 'clean' can't run in the 'ci' context (allowed: local, prod). \
+             Set RNR_CONTEXT to run it in another context"
+        );
+    }
+}
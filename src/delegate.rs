@@ -0,0 +1,104 @@
+//! Hand download commands to a full rnr build
+//!
+//! Slim builds (`--no-default-features`) leave out the HTTP stack to keep the
+//! binaries committed to projects small. `init` and `upgrade` still need to
+//! download, so a slim build re-runs the same command line with a full rnr
+//! found via `RNR_FULL` or `PATH`, or explains how to install one.
+
+use anyhow::{bail, Context, Result};
+use std::env;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::ui::Ui;
+
+/// Path to a full rnr build, overriding the `PATH` search
+const FULL_ENV: &str = "RNR_FULL";
+
+/// Set on delegated runs so a slim rnr found on `PATH` doesn't delegate again
+const DELEGATED_ENV: &str = "RNR_DELEGATED";
+
+/// Run this process's command line with a full rnr build
+pub fn to_full_build(command: &str, ui: &Ui) -> Result<()> {
+    let Some(full) = find_full_build() else {
+        bail!(
+            "'{}' needs to download binaries, but this is a slim rnr build without network support.\n\
+             Install the full rnr from https://github.com/CodingWithCalvin/rnr.cli/releases/latest\n\
+             and run the command with it, or set {} to the path of a full rnr binary.",
+            command,
+            FULL_ENV
+        );
+    };
+
+    ui.notice(format!(
+        "This rnr build has no network support; running '{}' with {}",
+        command,
+        full.display()
+    ));
+    let status = Command::new(&full)
+        .args(env::args_os().skip(1))
+        .env(DELEGATED_ENV, "1")
+        .status()
+        .with_context(|| format!("Failed to run {}", full.display()))?;
+    if !status.success() {
+        bail!("{} failed ({})", full.display(), status);
+    }
+    Ok(())
+}
+
+/// Whether there's a full rnr to delegate to
+pub fn full_build_available() -> bool {
+    find_full_build().is_some()
+}
+
+/// The full rnr to delegate to, unless this run was itself delegated
+fn find_full_build() -> Option<PathBuf> {
+    if env::var_os(DELEGATED_ENV).is_some() {
+        return None;
+    }
+    if let Some(path) = env::var_os(FULL_ENV).filter(|p| !p.is_empty()) {
+        return Some(PathBuf::from(path));
+    }
+    let current = env::current_exe().ok();
+    search_path(&env::var_os("PATH")?, current.as_deref())
+}
+
+/// First `rnr` executable on `path_var` that isn't `exclude`
+fn search_path(path_var: &OsStr, exclude: Option<&Path>) -> Option<PathBuf> {
+    let exclude = exclude.and_then(|p| p.canonicalize().ok());
+    let name = format!("rnr{}", env::consts::EXE_SUFFIX);
+    env::split_paths(path_var)
+        .map(|dir| dir.join(&name))
+        .filter(|candidate| candidate.is_file())
+        .find(|candidate| candidate.canonicalize().ok() != exclude)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_search_path_skips_current_exe() {
+        let temp = tempfile::tempdir().unwrap();
+        let (slim, full) = (temp.path().join("slim"), temp.path().join("full"));
+        for dir in [&slim, &full] {
+            fs::create_dir(dir).unwrap();
+            fs::write(dir.join(format!("rnr{}", env::consts::EXE_SUFFIX)), "").unwrap();
+        }
+        let path_var =
+            env::join_paths([temp.path().join("empty"), slim.clone(), full.clone()]).unwrap();
+
+        let own = slim.join(format!("rnr{}", env::consts::EXE_SUFFIX));
+        assert_eq!(
+            search_path(&path_var, Some(&own)),
+            Some(full.join(format!("rnr{}", env::consts::EXE_SUFFIX)))
+        );
+        assert_eq!(search_path(&path_var, None), Some(own));
+        assert_eq!(
+            search_path(temp.path().join("empty").as_os_str(), None),
+            None
+        );
+    }
+}
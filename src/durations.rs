@@ -0,0 +1,428 @@
+//! How long each step took before, for the `[3/7] ... (last: 4m 12s)` lines
+//!
+//! Successful runs add the duration of each top-level step to
+//! `.rnr/cache/history.json`, keyed by `task/step`. Only the latest
+//! samples are kept, along with a hash of the step's resolved commands: once
+//! the commands change, the old samples no longer say anything and are
+//! dropped.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::checksum::Sha256Hasher;
+use crate::config;
+use crate::history::{Status, StepRecord};
+use crate::plan::Plan;
+use crate::ui::Ui;
+
+/// File (inside the cache) holding step durations
+const HISTORY_FILE: &str = "history.json";
+
+/// Durations kept per step
+const MAX_SAMPLES: usize = 10;
+
+/// Steps usually quicker than this aren't annotated or flagged as slow
+const MIN_SHOWN: Duration = Duration::from_secs(1);
+
+/// How many times its median a step may take before it's "slower than usual"
+const SLOW_FACTOR: u32 = 2;
+
+/// Recorded durations of every step
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct History {
+    /// Samples by `task/step`, e.g. `ci/3`
+    #[serde(default)]
+    pub steps: BTreeMap<String, Samples>,
+}
+
+/// Durations of one step while its commands stayed the same
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Samples {
+    /// Hash of the step's resolved commands and directories
+    pub hash: String,
+    /// Durations in milliseconds, oldest first
+    pub samples_ms: Vec<u64>,
+}
+
+impl Samples {
+    /// The latest duration
+    fn last(&self) -> Option<Duration> {
+        self.samples_ms.last().copied().map(Duration::from_millis)
+    }
+
+    /// The median duration
+    fn median(&self) -> Option<Duration> {
+        let mut sorted = self.samples_ms.clone();
+        sorted.sort_unstable();
+        let middle = sorted.len() / 2;
+        let median = match sorted.len() {
+            0 => return None,
+            n if n % 2 == 0 => (sorted[middle - 1] + sorted[middle]) / 2,
+            _ => sorted[middle],
+        };
+        Some(Duration::from_millis(median))
+    }
+}
+
+/// What the history says about the steps of the task being run
+#[derive(Debug)]
+pub struct Estimates {
+    /// The task's name
+    task: String,
+    /// Number of top-level steps
+    total: usize,
+    /// Hash of each top-level step's commands, by index
+    hashes: BTreeMap<usize, String>,
+    /// Samples still matching each step's commands, by index
+    known: BTreeMap<usize, Samples>,
+}
+
+impl Estimates {
+    /// Estimates for the `total` top-level steps of `plan`'s task
+    pub fn new(history: &History, plan: &Plan, total: usize) -> Self {
+        let hashes = step_hashes(plan);
+        let known = hashes
+            .iter()
+            .filter_map(|(&index, hash)| {
+                let samples = history.steps.get(&key(&plan.task, index))?;
+                (&samples.hash == hash).then(|| (index, samples.clone()))
+            })
+            .collect();
+        Self {
+            task: plan.task.clone(),
+            total,
+            hashes,
+            known,
+        }
+    }
+
+    /// The line shown before step `index` starts, e.g.
+    /// `[3/7] integration tests (last: 4m 12s, about 6m 40s left)`, if it
+    /// usually takes long enough to be worth it
+    pub fn header(&self, index: usize, label: &str) -> Option<String> {
+        let samples = self.known.get(&index)?;
+        if samples.median()? < MIN_SHOWN {
+            return None;
+        }
+        let mut notes = vec![format!("last: {}", human(samples.last()?))];
+        if let Some(left) = self.remaining(index) {
+            notes.push(format!("about {} left", human(left)));
+        }
+        Some(format!(
+            "[{}/{}] {} ({})",
+            index,
+            self.total,
+            label,
+            notes.join(", ")
+        ))
+    }
+
+    /// Usual time from the start of step `index` to the end of the task,
+    /// if every step from there on has run before
+    fn remaining(&self, index: usize) -> Option<Duration> {
+        (index..=self.total)
+            .map(|index| self.known.get(&index)?.median())
+            .sum()
+    }
+
+    /// Top-level steps that took more than twice their median, as
+    /// `step 3 (integration tests) took 9m 30s; usually 4m 12s`
+    pub fn slow_steps(&self, records: &[StepRecord]) -> Vec<String> {
+        step_durations(records)
+            .into_iter()
+            .filter_map(|(index, label, took)| {
+                let usual = self.known.get(&index)?.median()?;
+                let slow = took >= MIN_SHOWN && took > usual * SLOW_FACTOR;
+                slow.then(|| {
+                    format!(
+                        "step {} ({}) took {}; usually {}",
+                        index,
+                        label,
+                        human(took),
+                        human(usual)
+                    )
+                })
+            })
+            .collect()
+    }
+
+    /// Add the durations of a successful run's steps to `history`
+    pub fn record(&self, history: &mut History, records: &[StepRecord]) {
+        for (index, _, took) in step_durations(records) {
+            let Some(hash) = self.hashes.get(&index) else {
+                continue;
+            };
+            let samples = history
+                .steps
+                .entry(key(&self.task, index))
+                .or_insert_with(|| Samples {
+                    hash: hash.clone(),
+                    samples_ms: Vec::new(),
+                });
+            if &samples.hash != hash {
+                samples.hash = hash.clone();
+                samples.samples_ms.clear();
+            }
+            samples.samples_ms.push(took.as_millis() as u64);
+            let excess = samples.samples_ms.len().saturating_sub(MAX_SAMPLES);
+            samples.samples_ms.drain(..excess);
+        }
+    }
+}
+
+/// Say which steps were slower than usual, if any
+pub fn report_slow(estimates: &Estimates, records: &[StepRecord], ui: &Ui) {
+    let slow = estimates.slow_steps(records);
+    if slow.is_empty() {
+        return;
+    }
+    ui.status(format!("Slower than usual in '{}':", estimates.task));
+    for line in slow {
+        ui.status(format!("  {}", line));
+    }
+}
+
+/// How long each top-level step that passed took, with its label. A
+/// parallel block counts as one step lasting as long as its slowest branch,
+/// and only if every branch passed.
+fn step_durations(records: &[StepRecord]) -> Vec<(usize, String, Duration)> {
+    let mut steps: BTreeMap<usize, Vec<&StepRecord>> = BTreeMap::new();
+    for record in records {
+        steps.entry(record.index).or_default().push(record);
+    }
+    steps
+        .into_iter()
+        .filter(|(_, records)| records.iter().all(|r| r.status == Status::Passed))
+        .map(|(index, records)| {
+            let label = match records.as_slice() {
+                [record] if record.branch.is_none() => record.label.clone(),
+                branches => format!("{} parallel steps", branches.len()),
+            };
+            let took = records.iter().map(|r| r.duration_ms).max().unwrap_or(0);
+            (index, label, Duration::from_millis(took))
+        })
+        .collect()
+}
+
+/// Hash of what each top-level step of `plan` runs, by index
+fn step_hashes(plan: &Plan) -> BTreeMap<usize, String> {
+    let prefix = format!("{}/", plan.task);
+    let mut hashers: BTreeMap<usize, Sha256Hasher> = BTreeMap::new();
+    for entry in &plan.entries {
+        let Some(rest) = entry.step.strip_prefix(&prefix) else {
+            continue;
+        };
+        let index = rest.split(['/', '.']).next().unwrap_or_default();
+        let Ok(index) = index.parse() else {
+            continue;
+        };
+        let hasher = hashers.entry(index).or_default();
+        for part in [&entry.step, &entry.action, &entry.dir] {
+            hasher.update(part.as_bytes());
+            hasher.update(b"\0");
+        }
+    }
+    hashers
+        .into_iter()
+        .map(|(index, hasher)| (index, hasher.finish()))
+        .collect()
+}
+
+/// Key of a step in the history
+fn key(task: &str, index: usize) -> String {
+    format!("{}/{}", task, index)
+}
+
+/// A duration as people read it: `0.4s`, `42s`, `4m 12s` or `1h 5m`
+pub fn human(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match secs {
+        0..=9 => format!("{:.1}s", duration.as_secs_f64()),
+        10..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m {}s", secs / 60, secs % 60),
+        _ => format!("{}h {}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+/// Path of the history file
+pub fn history_path(project_root: &Path) -> PathBuf {
+    project_root.join(config::CACHE_DIR).join(HISTORY_FILE)
+}
+
+/// Load the recorded durations; none if nothing was recorded yet
+pub fn load(project_root: &Path) -> Result<History> {
+    let path = history_path(project_root);
+    if !path.exists() {
+        return Ok(History::default());
+    }
+    let content =
+        fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+    serde_json::from_str(&content).with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+/// Save the recorded durations
+pub fn save(project_root: &Path, history: &History) -> Result<()> {
+    let path = history_path(project_root);
+    config::create_cache_dir(project_root)?;
+    let content = serde_json::to_string_pretty(history)?;
+    fs::write(&path, content).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plan::PlanEntry;
+
+    fn plan(entries: &[(&str, &str)]) -> Plan {
+        Plan {
+            task: "ci".to_string(),
+            entries: entries
+                .iter()
+                .map(|(step, action)| PlanEntry {
+                    step: step.to_string(),
+                    action: action.to_string(),
+                    dir: ".".to_string(),
+                    env: BTreeMap::new(),
+                    why: Vec::new(),
+                })
+                .collect(),
+            restricted: Vec::new(),
+            windows: Vec::new(),
+            git: Vec::new(),
+        }
+    }
+
+    fn record(index: usize, branch: Option<usize>, label: &str, secs: u64) -> StepRecord {
+        StepRecord {
+            index,
+            branch,
+            label: label.to_string(),
+            status: Status::Passed,
+            duration_ms: secs * 1000,
+            failure: None,
+        }
+    }
+
+    fn ci() -> Plan {
+        plan(&[
+            ("ci/1", "$ make"),
+            ("ci/2.1", "$ lint"),
+            ("ci/2.2/fmt/1", "$ fmt"),
+            ("ci/3", "$ test"),
+        ])
+    }
+
+    /// History with the given samples (in seconds) for each step of `ci()`
+    fn seeded(samples: &[&[u64]]) -> History {
+        let mut history = History::default();
+        let estimates = Estimates::new(&history, &ci(), 3);
+        for (index, secs) in samples.iter().enumerate() {
+            for &secs in *secs {
+                estimates.record(&mut history, &[record(index + 1, None, "x", secs)]);
+            }
+        }
+        history
+    }
+
+    #[test]
+    fn test_header_and_eta() {
+        let history = seeded(&[&[60, 70, 65], &[5, 300], &[252]]);
+        let estimates = Estimates::new(&history, &ci(), 3);
+        assert_eq!(
+            estimates.header(1, "build").unwrap(),
+            "[1/3] build (last: 1m 5s, about 7m 49s left)"
+        );
+        assert_eq!(
+            estimates.header(3, "$ test").unwrap(),
+            "[3/3] $ test (last: 4m 12s, about 4m 12s left)"
+        );
+
+        // No ETA while a later step has never run
+        let history = seeded(&[&[60], &[], &[252]]);
+        let estimates = Estimates::new(&history, &ci(), 3);
+        assert_eq!(
+            estimates.header(1, "build").unwrap(),
+            "[1/3] build (last: 1m 0s)"
+        );
+        assert_eq!(estimates.header(2, "lint"), None);
+    }
+
+    #[test]
+    fn test_quick_steps_arent_annotated() {
+        let history = seeded(&[&[0], &[], &[]]);
+        let estimates = Estimates::new(&history, &ci(), 3);
+        assert_eq!(estimates.header(1, "build"), None);
+    }
+
+    #[test]
+    fn test_changed_commands_drop_samples() {
+        let history = seeded(&[&[60, 70], &[], &[]]);
+        let changed = plan(&[("ci/1", "$ make -j8"), ("ci/3", "$ test")]);
+        let estimates = Estimates::new(&history, &changed, 3);
+        assert_eq!(estimates.header(1, "build"), None);
+
+        let mut history = history;
+        estimates.record(&mut history, &[record(1, None, "build", 30)]);
+        assert_eq!(history.steps["ci/1"].samples_ms, [30_000]);
+    }
+
+    #[test]
+    fn test_samples_are_bounded() {
+        let secs: Vec<u64> = (1..=15).collect();
+        let history = seeded(&[&secs, &[], &[]]);
+        let samples = &history.steps["ci/1"].samples_ms;
+        assert_eq!(samples.len(), MAX_SAMPLES);
+        assert_eq!(samples.first(), Some(&6_000));
+    }
+
+    #[test]
+    fn test_slow_steps() {
+        let history = seeded(&[&[60, 62, 64], &[10], &[]]);
+        let estimates = Estimates::new(&history, &ci(), 3);
+        let records = [
+            record(1, None, "build", 130),
+            record(2, Some(1), "lint", 15),
+            record(2, Some(2), "task fmt", 25),
+            record(3, None, "$ test", 999),
+        ];
+        assert_eq!(
+            estimates.slow_steps(&records),
+            [
+                "step 1 (build) took 2m 10s; usually 1m 2s",
+                "step 2 (2 parallel steps) took 25s; usually 10s"
+            ]
+        );
+
+        // A parallel block is recorded as its slowest branch
+        let mut history = History::default();
+        estimates.record(&mut history, &records);
+        assert_eq!(history.steps["ci/2"].samples_ms, [25_000]);
+        let failed = StepRecord {
+            status: Status::Failed,
+            ..record(3, None, "$ test", 1)
+        };
+        assert!(step_durations(&[failed]).is_empty());
+    }
+
+    #[test]
+    fn test_human() {
+        let human = |ms| human(Duration::from_millis(ms));
+        assert_eq!(human(400), "0.4s");
+        assert_eq!(human(42_000), "42s");
+        assert_eq!(human(252_000), "4m 12s");
+        assert_eq!(human(3_900_000), "1h 5m");
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(load(dir.path()).unwrap(), History::default());
+        let history = seeded(&[&[3], &[], &[]]);
+        save(dir.path(), &history).unwrap();
+        assert_eq!(load(dir.path()).unwrap(), history);
+    }
+}
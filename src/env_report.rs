@@ -0,0 +1,127 @@
+//! The environment rnr adds for a task, for `--verbose` and `rnr env`
+//!
+//! Only variables rnr sets are shown, never the inherited environment. Each
+//! appears once, under the source whose value the command sees.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+use crate::args::TaskArgs;
+use crate::config;
+use crate::secrets::HiddenVars;
+
+/// Values longer than this are cut short
+const MAX_VALUE_LEN: usize = 120;
+
+/// Variables rnr sets for a task, grouped by where they come from
+#[derive(Debug, Default)]
+pub struct EnvReport {
+    groups: Vec<(&'static str, BTreeMap<String, String>)>,
+}
+
+impl EnvReport {
+    /// The task's `env`, then the `env` of the step or task that delegated
+    /// to it, then the runner's overrides, then `RNR_ARG_*` variables, each
+    /// winning over the ones before
+    pub fn new(
+        task_env: Option<&HashMap<String, String>>,
+        caller_env: Option<&HashMap<String, String>>,
+        overrides: &HashMap<String, String>,
+        args: &TaskArgs,
+    ) -> Self {
+        let sources = [
+            ("task env", task_env.cloned().unwrap_or_default()),
+            ("caller env", caller_env.cloned().unwrap_or_default()),
+            ("overrides", overrides.clone()),
+            ("arguments", args.env_vars()),
+        ];
+        let mut groups: Vec<(&'static str, BTreeMap<String, String>)> = Vec::new();
+        for (label, vars) in sources {
+            for (_, earlier) in &mut groups {
+                earlier.retain(|name, _| !vars.contains_key(name));
+            }
+            groups.push((label, vars.into_iter().collect()));
+        }
+        groups.retain(|(_, vars)| !vars.is_empty());
+        Self { groups }
+    }
+
+    /// Indented lines per source, with hidden values shown as `***`
+    pub fn render(&self, hidden: &HiddenVars) -> String {
+        if self.groups.is_empty() {
+            return "  (no variables set by rnr)\n".to_string();
+        }
+        let mut out = String::new();
+        for (label, vars) in &self.groups {
+            let _ = writeln!(out, "  {}:", label);
+            for (name, value) in vars {
+                let value = if hidden.hides(name) && !config::is_computed_marker(value) {
+                    "***".to_string()
+                } else {
+                    truncate(value)
+                };
+                let _ = writeln!(out, "    {}={}", name, value);
+            }
+        }
+        out
+    }
+}
+
+/// A value cut to `MAX_VALUE_LEN` characters, marked when shortened
+fn truncate(value: &str) -> String {
+    match value.char_indices().nth(MAX_VALUE_LEN) {
+        Some((end, _)) => format!("{}... ({} chars)", &value[..end], value.chars().count()),
+        None => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn vars(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_groups_by_winning_source() {
+        let task_env = vars(&[("MODE", "debug"), ("API_TOKEN", "abc123"), ("B", "1")]);
+        let caller_env = vars(&[("B", "2"), ("TARGET", "staging")]);
+        let overrides = vars(&[("MODE", "release")]);
+        let args = TaskArgs {
+            values: vars(&[("tag", "v1")]),
+            extra: Vec::new(),
+            foreach: HashMap::new(),
+        };
+        let report = EnvReport::new(Some(&task_env), Some(&caller_env), &overrides, &args);
+        let hidden = HiddenVars::for_config(&serde_yaml::from_str::<Config>("{}").unwrap());
+        assert_eq!(
+            report.render(&hidden),
+            "  task env:\n    API_TOKEN=***\n  caller env:\n    B=2\n    TARGET=staging\n  overrides:\n    MODE=release\n  arguments:\n    RNR_ARG_TAG=v1\n"
+        );
+    }
+
+    #[test]
+    fn test_truncates_long_values() {
+        let long = "x".repeat(150);
+        let report = EnvReport::new(
+            Some(&vars(&[("LONG", &long)])),
+            None,
+            &HashMap::new(),
+            &TaskArgs::default(),
+        );
+        let hidden = HiddenVars::for_config(&serde_yaml::from_str::<Config>("{}").unwrap());
+        assert_eq!(
+            report.render(&hidden),
+            format!("  task env:\n    LONG={}... (150 chars)\n", "x".repeat(120))
+        );
+        assert_eq!(
+            EnvReport::default().render(&hidden),
+            "  (no variables set by rnr)\n"
+        );
+    }
+}
@@ -0,0 +1,122 @@
+//! Errors returned by the library API
+
+use std::io;
+use std::path::PathBuf;
+
+use crate::config::{UnknownField, CONFIG_FILE};
+
+/// Result type for the library API
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Why a config couldn't be loaded or a task couldn't be started
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    /// The current directory couldn't be determined
+    #[error("Failed to get current directory")]
+    CurrentDir(#[source] io::Error),
+
+    /// No config file in the directory or any parent
+    #[error(
+        "No {} found in current directory or any parent directory",
+        CONFIG_FILE
+    )]
+    ConfigNotFound,
+
+    /// No config file in the directory given with `--project-dir`
+    #[error("No {} in {} (the --project-dir)", CONFIG_FILE, .0.display())]
+    ConfigNotInProjectDir(PathBuf),
+
+    /// The config file couldn't be read
+    #[error("Failed to read config file: {}", path.display())]
+    ReadConfig {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    /// The config file isn't valid rnr YAML
+    #[error("Failed to parse config file: {}", path.display())]
+    ParseConfig {
+        path: PathBuf,
+        #[source]
+        source: serde_yaml::Error,
+    },
+
+    /// A value in the config file needs quotes to mean what it looks like,
+    /// usually a command with `: ` in it
+    #[error("Failed to parse config file: {}:{line}: {hint}", path.display())]
+    NeedsQuotes {
+        path: PathBuf,
+        line: usize,
+        hint: String,
+    },
+
+    /// A task is named after one of the file's top-level settings
+    #[error(
+        "Failed to parse config file: {}:{line}: `{name}` is a reserved setting; rename the task",
+        path.display()
+    )]
+    ReservedName {
+        path: PathBuf,
+        line: usize,
+        name: String,
+    },
+
+    /// The config file parsed, but isn't a mapping of task names to tasks
+    #[error(
+        "{} must be a mapping of task names to tasks (like 'build: cargo build'), but it's a {found}",
+        path.display()
+    )]
+    ConfigNotMapping { path: PathBuf, found: &'static str },
+
+    /// The config file is over one of the limits that keep loading it fast
+    #[error("{} is too large or complex to load: {reason}", path.display())]
+    ConfigTooLarge { path: PathBuf, reason: String },
+
+    /// The config file needs a newer rnr (`min_version`)
+    #[error("this project requires rnr >= {required}, you have {current} — run ./rnr upgrade")]
+    RnrTooOld { required: String, current: String },
+
+    /// The config file's `min_version` isn't a version
+    #[error("min_version in {} must be a version like \"0.5\", not {value}", path.display())]
+    InvalidMinVersion { path: PathBuf, value: String },
+
+    /// Strict mode found task fields rnr doesn't know
+    #[error("{}", unknown_fields_message(path, fields))]
+    UnknownFields {
+        path: PathBuf,
+        fields: Vec<UnknownField>,
+    },
+
+    /// No task with this name is defined
+    #[error("Task '{0}' not found")]
+    TaskNotFound(String),
+
+    /// The arguments don't match the task's declared args
+    #[error("{0}")]
+    InvalidArgs(String),
+
+    /// The task couldn't be planned (e.g. an unresolved placeholder)
+    #[error("{0}")]
+    Plan(String),
+}
+
+impl Error {
+    /// Wrap an internal error, keeping its full context chain in the message
+    pub(crate) fn flatten(error: anyhow::Error, variant: fn(String) -> Self) -> Self {
+        variant(format!("{:#}", error))
+    }
+}
+
+/// One line per unknown field, under a heading naming the file
+fn unknown_fields_message(path: &std::path::Path, fields: &[UnknownField]) -> String {
+    let mut message = format!("Unknown fields in {} (strict mode):", path.display());
+    for field in fields {
+        match field.line {
+            Some(line) => message.push_str(&format!("\n  line {}: {}", line, field)),
+            None => message.push_str(&format!("\n  {}", field)),
+        }
+    }
+    message
+}
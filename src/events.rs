@@ -0,0 +1,340 @@
+//! Machine-readable events for `--events-json` and `--progress-json`
+//!
+//! Editors and other tools follow a run live by reading one JSON object per
+//! line: `run_started`, `step_started`, `step_finished`, `run_cancelled`
+//! (when rnr is asked to terminate), and `run_finished`.
+//! `rnr init` and `rnr upgrade` report their progress the same way, with
+//! `platform_download_started`, `platform_download_progress`,
+//! `platform_download_finished`, `config_written`, `wrapper_written`, and
+//! `completed`. Every event carries the schema version in `"v"` and its
+//! kind in `"event"`. Fields may be added within a version; renaming or
+//! removing one bumps [`SCHEMA_VERSION`].
+
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::sync::Mutex;
+
+use crate::history::Status;
+use crate::nesting::Nesting;
+
+/// Version of the event schema, sent as `"v"` in every event
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Where a step sits in its task: `index` in `steps`, and `branch` within a parallel block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StepPosition {
+    pub index: usize,
+    pub branch: Option<usize>,
+}
+
+/// Writes run events as newline-delimited JSON
+pub struct EventSink {
+    out: Mutex<Box<dyn Write + Send>>,
+}
+
+impl fmt::Debug for EventSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventSink").finish_non_exhaustive()
+    }
+}
+
+impl EventSink {
+    /// Events on stderr, next to rnr's own warnings
+    pub fn stderr() -> Self {
+        Self::new(io::stderr())
+    }
+
+    /// Events on an inherited file descriptor, e.g. `--events-fd 3`
+    pub fn fd(fd: u32) -> Result<Self> {
+        if !cfg!(unix) {
+            anyhow::bail!("--events-fd is only supported on Unix; use --events-json");
+        }
+        let path = format!("/dev/fd/{}", fd);
+        let file = OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("Failed to open file descriptor {} for events", fd))?;
+        Ok(Self::new(file))
+    }
+
+    /// Events written to `out`
+    pub fn new(out: impl Write + Send + 'static) -> Self {
+        Self {
+            out: Mutex::new(Box::new(out)),
+        }
+    }
+
+    /// A task named on the command line is starting, in a run `nesting`
+    /// places among the runs that started it
+    pub fn run_started(
+        &self,
+        task: &str,
+        description: Option<&str>,
+        args: &[String],
+        nesting: &Nesting,
+    ) {
+        self.emit(
+            "run_started",
+            json!({
+                "task": task,
+                "description": description,
+                "args": args,
+                "run_id": nesting.run_id,
+                "depth": nesting.depth,
+                "parent_task": nesting.parent_task,
+            }),
+        );
+    }
+
+    /// A top-level step (or a branch of a parallel block) is starting
+    pub fn step_started(&self, position: StepPosition, name: &str, cmd: Option<&str>, dir: &str) {
+        self.emit(
+            "step_started",
+            json!({
+                "index": position.index,
+                "branch": position.branch,
+                "name": name,
+                "cmd": cmd,
+                "dir": dir,
+            }),
+        );
+    }
+
+    /// A step finished, or was skipped or never reached
+    pub fn step_finished(&self, position: StepPosition, status: Status, duration_ms: u64) {
+        self.emit(
+            "step_finished",
+            json!({
+                "index": position.index,
+                "branch": position.branch,
+                "status": status,
+                "duration_ms": duration_ms,
+            }),
+        );
+    }
+
+    /// The task named on the command line finished
+    pub fn run_finished(
+        &self,
+        task: &str,
+        run_id: &str,
+        status: Status,
+        duration_ms: u64,
+        error: Option<&str>,
+    ) {
+        self.emit(
+            "run_finished",
+            json!({
+                "task": task,
+                "run_id": run_id,
+                "status": status,
+                "duration_ms": duration_ms,
+                "error": error,
+            }),
+        );
+    }
+
+    /// The task named on the command line was cut short because rnr was
+    /// asked to terminate; `run_finished` follows
+    pub fn run_cancelled(&self, task: &str, run_id: &str) {
+        self.emit(
+            "run_cancelled",
+            json!({
+                "task": task,
+                "run_id": run_id,
+                "signal": crate::termination::signal_name(),
+            }),
+        );
+    }
+
+    /// A platform's binary is about to be downloaded, or its placeholder
+    /// written; `bytes_total` is the expected size
+    pub fn platform_download_started(&self, platform: &str, bytes_total: u64, placeholder: bool) {
+        self.emit(
+            "platform_download_started",
+            json!({
+                "platform": platform,
+                "bytes_total": bytes_total,
+                "placeholder": placeholder,
+            }),
+        );
+    }
+
+    /// Part of a platform's binary arrived; `bytes_total` is the size the
+    /// server reported, if it did
+    pub fn platform_download_progress(
+        &self,
+        platform: &str,
+        bytes_done: u64,
+        bytes_total: Option<u64>,
+    ) {
+        self.emit(
+            "platform_download_progress",
+            json!({
+                "platform": platform,
+                "bytes_done": bytes_done,
+                "bytes_total": bytes_total,
+            }),
+        );
+    }
+
+    /// A platform's binary (or placeholder) is in place at `path`
+    pub fn platform_download_finished(&self, platform: &str, path: &str) {
+        self.emit(
+            "platform_download_finished",
+            json!({ "platform": platform, "path": path }),
+        );
+    }
+
+    /// `.rnr/config.yaml` or `rnr.yaml` was written
+    pub fn config_written(&self, path: &str) {
+        self.emit("config_written", json!({ "path": path }));
+    }
+
+    /// A wrapper script was written
+    pub fn wrapper_written(&self, path: &str) {
+        self.emit("wrapper_written", json!({ "path": path }));
+    }
+
+    /// The command finished, leaving the project on `version`
+    pub fn completed(&self, version: &str) {
+        self.emit("completed", json!({ "version": version }));
+    }
+
+    /// Write one event line; a reader that went away doesn't stop the run
+    fn emit(&self, event: &str, fields: Value) {
+        let mut line = json!({ "v": SCHEMA_VERSION, "event": event });
+        if let (Value::Object(line), Value::Object(fields)) = (&mut line, fields) {
+            line.extend(fields);
+        }
+        let mut out = self.out.lock().unwrap();
+        let _ = writeln!(out, "{}", line).and_then(|()| out.flush());
+    }
+}
+
+/// Fetch `platform`'s binary to `path` with `fetch`, reporting its start,
+/// the progress `fetch` passes on, and its end to `events`, if any
+pub fn track_download(
+    events: Option<&EventSink>,
+    platform: &str,
+    path: &str,
+    expected: u64,
+    placeholder: bool,
+    fetch: impl FnOnce(&mut dyn FnMut(u64, Option<u64>)) -> Result<()>,
+) -> Result<()> {
+    let Some(events) = events else {
+        return fetch(&mut |_, _| {});
+    };
+    events.platform_download_started(platform, expected, placeholder);
+    fetch(&mut |done, total| events.platform_download_progress(platform, done, total))?;
+    events.platform_download_finished(platform, path);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    /// A writer tests can read back
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_events_are_versioned_json_lines() {
+        let buffer = Buffer::default();
+        let sink = EventSink::new(buffer.clone());
+        let position = StepPosition {
+            index: 2,
+            branch: Some(1),
+        };
+        let nesting = Nesting {
+            depth: 1,
+            parent_task: Some("release".to_string()),
+            run_id: "run-1".to_string(),
+            ..Nesting::default()
+        };
+        sink.run_started("ci", Some("Every check"), &["--fast".to_string()], &nesting);
+        sink.step_started(position, "cargo test", Some("cargo test"), ".");
+        sink.step_finished(position, Status::Failed, 12);
+        sink.run_finished("ci", "run-1", Status::Failed, 15, Some("boom"));
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let events: Vec<Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                json!({"v": 1, "event": "run_started", "task": "ci",
+                       "description": "Every check", "args": ["--fast"], "run_id": "run-1",
+                       "depth": 1, "parent_task": "release"}),
+                json!({"v": 1, "event": "step_started", "index": 2, "branch": 1,
+                       "name": "cargo test", "cmd": "cargo test", "dir": "."}),
+                json!({"v": 1, "event": "step_finished", "index": 2, "branch": 1,
+                       "status": "failed", "duration_ms": 12}),
+                json!({"v": 1, "event": "run_finished", "task": "ci", "run_id": "run-1",
+                       "status": "failed", "duration_ms": 15, "error": "boom"}),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_download_events() {
+        let buffer = Buffer::default();
+        let sink = EventSink::new(buffer.clone());
+        let path = ".rnr/bin/rnr-linux-amd64";
+        track_download(Some(&sink), "linux-amd64", path, 100, false, |progress| {
+            progress(60, Some(120));
+            progress(120, Some(120));
+            Ok(())
+        })
+        .unwrap();
+        let failed = track_download(Some(&sink), "macos-arm64", path, 100, false, |_| {
+            anyhow::bail!("offline")
+        });
+        assert!(failed.is_err());
+        sink.config_written(".rnr/config.yaml");
+        sink.wrapper_written("rnr");
+        sink.completed("0.1.0");
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let events: Vec<Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                json!({"v": 1, "event": "platform_download_started", "platform": "linux-amd64",
+                       "bytes_total": 100, "placeholder": false}),
+                json!({"v": 1, "event": "platform_download_progress", "platform": "linux-amd64",
+                       "bytes_done": 60, "bytes_total": 120}),
+                json!({"v": 1, "event": "platform_download_progress", "platform": "linux-amd64",
+                       "bytes_done": 120, "bytes_total": 120}),
+                json!({"v": 1, "event": "platform_download_finished", "platform": "linux-amd64",
+                       "path": path}),
+                // A failed download starts but never finishes
+                json!({"v": 1, "event": "platform_download_started", "platform": "macos-arm64",
+                       "bytes_total": 100, "placeholder": false}),
+                json!({"v": 1, "event": "config_written", "path": ".rnr/config.yaml"}),
+                json!({"v": 1, "event": "wrapper_written", "path": "rnr"}),
+                json!({"v": 1, "event": "completed", "version": "0.1.0"}),
+            ]
+        );
+    }
+}
@@ -0,0 +1,150 @@
+//! What a failure hook is told about the failure
+//!
+//! While a task with an `on_failure` hook runs, its commands' output is
+//! copied to a scratch file, rotated so it stays small. It's kept in a
+//! randomly named directory under the temp directory that only its owner
+//! can open, since output can hold secrets. When the task fails, the last lines of that output are written to
+//! a file of their own, which the hook finds in `RNR_FAILURE_LOG` alongside
+//! `RNR_FAILED_TASK`, `RNR_FAILED_STEP` and `RNR_EXIT_CODE`.
+
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tempfile::TempDir;
+
+use crate::output_buffer::{self, Tail};
+use crate::task_log::TaskLog;
+use crate::ui::Ui;
+
+/// Lines of output handed to the hook
+pub const TAIL_LINES: usize = 100;
+
+/// Size the scratch copy rotates at, keeping one rotated copy
+const MAX_SIZE: u64 = 256 * 1024;
+
+/// A task's output, kept in case its hook needs it. Its directory, with
+/// the tail written for the hook, is removed when it's dropped.
+pub struct Capture {
+    log: Option<Arc<TaskLog>>,
+    /// The rotated copy and the current file, oldest first
+    files: Vec<PathBuf>,
+    /// Where the tail is written
+    tail: PathBuf,
+    /// Holds the files; declared last, so it's removed after they're closed
+    _dir: TempDir,
+}
+
+impl Capture {
+    /// Start keeping output in a new private directory in the temp directory
+    pub fn start(ui: &Ui) -> Result<Self> {
+        let mut builder = tempfile::Builder::new();
+        builder.prefix("rnr-failure-");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            builder.permissions(fs::Permissions::from_mode(0o700));
+        }
+        let dir = builder
+            .tempdir()
+            .context("Failed to create a directory for the task's output")?;
+        let log = TaskLog::scratch(dir.path().join("output.log"), MAX_SIZE, ui)?;
+        let files = vec![log.rotated(1), log.path().to_path_buf()];
+        Ok(Capture {
+            log: Some(Arc::new(log)),
+            files,
+            tail: dir.path().join("failure.log"),
+            _dir: dir,
+        })
+    }
+
+    /// The log the task's output is copied to
+    pub fn log(&self) -> Option<Arc<TaskLog>> {
+        self.log.clone()
+    }
+
+    /// Write the last [`TAIL_LINES`] lines of output to a file of their
+    /// own, returning where
+    pub fn write_tail(&self) -> Result<&Path> {
+        let mut lines = Tail::new(output_buffer::DEFAULT_LIMIT).max_lines(TAIL_LINES);
+        for file in &self.files {
+            // The rotated copy only exists once the output outgrew one file
+            if let Ok(file) = File::open(file) {
+                output_buffer::for_each_line(file, |line| lines.push(line));
+            }
+        }
+        let tail: String = lines.iter().map(|line| format!("{}\n", line)).collect();
+        fs::write(&self.tail, tail)
+            .with_context(|| format!("Failed to write {}", self.tail.display()))?;
+        Ok(&self.tail)
+    }
+}
+
+/// The variables a hook runs with
+pub fn env(
+    task: &str,
+    step: Option<&str>,
+    exit_code: i32,
+    log: Option<&Path>,
+) -> HashMap<String, String> {
+    let mut env = HashMap::from([
+        ("RNR_FAILED_TASK".to_string(), task.to_string()),
+        (
+            "RNR_FAILED_STEP".to_string(),
+            step.unwrap_or_default().to_string(),
+        ),
+        ("RNR_EXIT_CODE".to_string(), exit_code.to_string()),
+    ]);
+    if let Some(log) = log {
+        env.insert("RNR_FAILURE_LOG".to_string(), log.display().to_string());
+    }
+    env
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tail_keeps_the_last_lines() {
+        let (ui, _) = Ui::capture(false);
+        let capture = Capture::start(&ui).unwrap();
+        let log = capture.log().unwrap();
+        for n in 0..TAIL_LINES + 20 {
+            log.write(&format!("line {}", n));
+        }
+        let tail = capture.write_tail().unwrap().to_path_buf();
+        let text = fs::read_to_string(&tail).unwrap();
+        assert_eq!(text.lines().count(), TAIL_LINES);
+        assert_eq!(text.lines().next(), Some("line 20"));
+        assert!(text.ends_with(&format!("line {}\n", TAIL_LINES + 19)));
+
+        let dir = tail.parent().unwrap().to_path_buf();
+        assert!(dir
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .starts_with("rnr-failure-"));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&dir).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o700);
+        }
+        assert_ne!(Capture::start(&ui).unwrap().tail, tail);
+        drop(log);
+        drop(capture);
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn test_env() {
+        let vars = env("deploy", Some("2.1"), 3, Some(Path::new("/tmp/f.log")));
+        assert_eq!(vars["RNR_FAILED_TASK"], "deploy");
+        assert_eq!(vars["RNR_FAILED_STEP"], "2.1");
+        assert_eq!(vars["RNR_EXIT_CODE"], "3");
+        assert_eq!(vars["RNR_FAILURE_LOG"], "/tmp/f.log");
+        assert_eq!(env("ci", None, 1, None)["RNR_FAILED_STEP"], "");
+    }
+}
@@ -0,0 +1,755 @@
+//! `rnr fmt`: rnr.yaml in one canonical layout
+//!
+//! The file is parsed to a YAML value and written back out by hand: block
+//! style with two-space indentation, tasks in the order they're written, and
+//! each task's (and step's) fields in a fixed order. serde_yaml keeps no
+//! comments, so a pass over the text records which key or item each comment
+//! sits on and the writer puts it back beside that key. A comment that can't
+//! be placed stops formatting rather than being dropped.
+
+use anyhow::{Context, Result};
+use serde_yaml::{Mapping, Value};
+use std::collections::HashMap;
+
+use crate::config::{Config, SETTINGS, TASK_FIELDS};
+use crate::source::split_mapping_key;
+
+/// Canonical order of a step's fields; a parallel block's options come
+/// before its branches, like a task's `dir` and `env` before its `cmd`
+const STEP_FIELDS: &[&str] = &[
+    "description",
+    "dir",
+    "env",
+    "env_file",
+    "cmd",
+    "task",
+    "args",
+    "copy",
+    "remove",
+    "mkdir",
+    "download",
+    "render",
+    "output_filter",
+    "echo",
+    "expect_exit",
+    "export_exit",
+    "tags",
+    "priority",
+    "weight",
+    "allow_shared_dir",
+    "parallel",
+];
+
+/// Sequences of scalars up to this wide are written on one line, `[a, b]`
+const FLOW_WIDTH: usize = 80;
+
+/// `content` (an rnr.yaml that loads) in canonical layout
+pub fn format(content: &str) -> Result<String> {
+    let value: Value = serde_yaml::from_str(content).context("rnr.yaml isn't valid YAML")?;
+    let mut comments = Comments::scan(content)?;
+    let mut writer = Writer {
+        out: String::new(),
+        comments: &mut comments.nodes,
+    };
+    for line in &comments.header {
+        writer.line(0, line);
+    }
+    if !comments.header.is_empty() {
+        writer.out.push('\n');
+    }
+    match &value {
+        Value::Mapping(root) if root.is_empty() => writer.out.push_str("{}\n"),
+        Value::Mapping(root) => writer.root(root)?,
+        Value::Null => {}
+        _ => anyhow::bail!("rnr.yaml must be a mapping of task names to tasks"),
+    }
+    if !comments.footer.is_empty() && !writer.out.is_empty() {
+        writer.out.push('\n');
+    }
+    for line in &comments.footer {
+        writer.line(0, line);
+    }
+    if let Some((path, _)) = writer.comments.iter().next() {
+        anyhow::bail!(
+            "rnr fmt can't keep the comment at {}; move it next to a key",
+            display_path(path)
+        );
+    }
+    let formatted = writer.out;
+    check_equivalent(&value, &formatted)?;
+    Ok(formatted)
+}
+
+/// Refuse output that doesn't load back to the same config
+fn check_equivalent(original: &Value, formatted: &str) -> Result<()> {
+    let reparsed: Value = serde_yaml::from_str(formatted).unwrap_or(Value::Null);
+    let document = if reparsed.is_null() { "{}" } else { formatted };
+    let loads = serde_yaml::from_str::<Config>(document).is_ok();
+    let keys = |value: &Value| match value {
+        Value::Mapping(map) => map.keys().cloned().collect(),
+        _ => Vec::new(),
+    };
+    if !loads || reparsed != *original || keys(&reparsed) != keys(original) {
+        anyhow::bail!(
+            "rnr fmt's output doesn't load back to the same tasks, so rnr.yaml was left \
+             as it is. Please report this with the file attached."
+        );
+    }
+    Ok(())
+}
+
+/// One step on the way to a node: a mapping key or a sequence item
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Segment {
+    Key(String),
+    Item(usize),
+}
+
+/// Comments that sit on one key or item
+#[derive(Debug, Default)]
+struct NodeComments {
+    /// Whole-line comments just above it
+    before: Vec<String>,
+    /// The comment at the end of its line
+    trailing: Option<String>,
+}
+
+/// The comments of a file, by the node they belong to
+#[derive(Debug, Default)]
+struct Comments {
+    /// Comments opening the file, set off from the first key by a blank line
+    header: Vec<String>,
+    /// Comments after the last key
+    footer: Vec<String>,
+    nodes: HashMap<Vec<Segment>, NodeComments>,
+}
+
+/// A key or item being read, and how many items it has had so far
+struct Frame {
+    indent: usize,
+    segment: Segment,
+    items: usize,
+}
+
+impl Comments {
+    /// Find each comment's node by following indentation. Only block-style
+    /// collections have lines of their own; flow collections and multi-line
+    /// scalars are passed over as part of the node they start on.
+    fn scan(content: &str) -> Result<Self> {
+        let mut comments = Comments::default();
+        let mut stack: Vec<Frame> = Vec::new();
+        let mut root_items = 0;
+        // Whole-line comments waiting for the next node, with their lines
+        let mut pending: Vec<(usize, String)> = Vec::new();
+        let mut seen_node = false;
+        // Lines indented past this belong to a `|` or `>` scalar
+        let mut block_scalar: Option<usize> = None;
+
+        for (i, raw) in content.lines().enumerate() {
+            let text = raw.trim_start();
+            let indent = raw.len() - text.len();
+            if let Some(scalar) = block_scalar {
+                if text.is_empty() || indent > scalar {
+                    continue;
+                }
+                block_scalar = None;
+            }
+            if text.is_empty() {
+                if !seen_node {
+                    comments
+                        .header
+                        .extend(pending.drain(..).map(|(_, text)| text));
+                }
+                continue;
+            }
+            if let Some(comment) = text.strip_prefix('#') {
+                pending.push((i + 1, format!("#{}", comment)));
+                continue;
+            }
+            if text.starts_with("---") || text.starts_with("...") || text.starts_with('%') {
+                continue;
+            }
+
+            let (mut text, trailing) = split_comment(text);
+            let mut indent = indent;
+            let mut path: Option<Vec<Segment>> = None;
+            let mut line_indent = indent;
+            let mut is_node = false;
+            loop {
+                if text == "-" || text.starts_with("- ") {
+                    while stack.last().is_some_and(|frame| {
+                        frame.indent > indent
+                            || (frame.indent == indent && matches!(frame.segment, Segment::Item(_)))
+                    }) {
+                        stack.pop();
+                    }
+                    let counter = match stack.last_mut() {
+                        Some(frame) => &mut frame.items,
+                        None => &mut root_items,
+                    };
+                    let index = *counter;
+                    *counter += 1;
+                    stack.push(Frame {
+                        indent,
+                        segment: Segment::Item(index),
+                        items: 0,
+                    });
+                    line_indent = indent;
+                    is_node = true;
+                    attach_before(&mut comments.nodes, &stack, &mut pending);
+                    let rest = &text[1..];
+                    let skipped = rest.len() - rest.trim_start().len();
+                    indent += 1 + skipped;
+                    text = rest.trim_start();
+                    path = Some(segments(&stack));
+                    if text.is_empty() {
+                        break;
+                    }
+                    continue;
+                }
+                if let Some((key, value)) = split_mapping_key(text) {
+                    while stack.last().is_some_and(|frame| frame.indent >= indent) {
+                        stack.pop();
+                    }
+                    if key == "<<" {
+                        anyhow::bail!("line {}: rnr fmt can't keep YAML merge keys ('<<')", i + 1);
+                    }
+                    stack.push(Frame {
+                        indent,
+                        segment: Segment::Key(key),
+                        items: 0,
+                    });
+                    is_node = true;
+                    attach_before(&mut comments.nodes, &stack, &mut pending);
+                    path = Some(segments(&stack));
+                    text = value.trim();
+                    line_indent = stack.last().map_or(indent, |frame| frame.indent);
+                }
+                break;
+            }
+
+            if text.starts_with(['&', '*']) {
+                anyhow::bail!(
+                    "line {}: rnr fmt can't keep YAML anchors and aliases ('{}')",
+                    i + 1,
+                    text.split_whitespace().next().unwrap_or(text)
+                );
+            }
+            if text.starts_with(['|', '>']) {
+                block_scalar = Some(line_indent);
+            }
+            if !is_node {
+                // The rest of a multi-line scalar or flow collection
+                let inside = pending.first().map(|(line, _)| *line);
+                if let Some(line) = inside.or(trailing.map(|_| i + 1)) {
+                    anyhow::bail!(
+                        "rnr fmt can't keep the comment on line {}; move it next to a key",
+                        line
+                    );
+                }
+                continue;
+            }
+            seen_node = true;
+            if let (Some(path), Some(trailing)) = (path, trailing) {
+                comments.nodes.entry(path).or_default().trailing = Some(trailing.to_string());
+            }
+        }
+        comments.footer = pending.into_iter().map(|(_, text)| text).collect();
+        Ok(comments)
+    }
+}
+
+/// Give the comments waiting above a line to the node it starts
+fn attach_before(
+    nodes: &mut HashMap<Vec<Segment>, NodeComments>,
+    stack: &[Frame],
+    pending: &mut Vec<(usize, String)>,
+) {
+    if !pending.is_empty() {
+        let before = &mut nodes.entry(segments(stack)).or_default().before;
+        before.extend(pending.drain(..).map(|(_, text)| text));
+    }
+}
+
+fn segments(stack: &[Frame]) -> Vec<Segment> {
+    stack.iter().map(|frame| frame.segment.clone()).collect()
+}
+
+/// `line` without its trailing comment, and the comment (with its `#`). A
+/// `#` starts a comment at the start of the text or after whitespace,
+/// outside quoted scalars.
+fn split_comment(line: &str) -> (&str, Option<&str>) {
+    let mut quote = None;
+    let mut previous = ' ';
+    let mut chars = line.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match quote {
+            // `''` inside single quotes and `\x` inside double quotes are escapes
+            Some('\'') if c == '\'' && chars.peek().is_some_and(|&(_, next)| next == '\'') => {
+                chars.next();
+            }
+            Some('"') if c == '\\' => {
+                chars.next();
+            }
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if (c == '\'' || c == '"') && " \t[{,:-".contains(previous) => quote = Some(c),
+            None if c == '#' && previous.is_whitespace() => {
+                return (line[..i].trim_end(), Some(&line[i..]));
+            }
+            None => {}
+        }
+        previous = c;
+    }
+    (line.trim_end(), None)
+}
+
+/// `a.b[2]` for messages
+fn display_path(path: &[Segment]) -> String {
+    let mut text = String::new();
+    for segment in path {
+        match segment {
+            Segment::Key(key) if text.is_empty() => text.push_str(key),
+            Segment::Key(key) => text.push_str(&format!(".{}", key)),
+            Segment::Item(index) => text.push_str(&format!("[{}]", index)),
+        }
+    }
+    text
+}
+
+/// What a collection holds, for the order its keys are written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    /// A task's fields
+    Task,
+    /// Steps: a step's fields, or a list of steps
+    Step,
+    /// A parallel block's named lanes
+    Lanes,
+    /// Anything else, written in file order
+    Other,
+}
+
+impl Kind {
+    /// What the value of `key` in a mapping of this kind holds
+    fn child(self, key: &str) -> Kind {
+        match (self, key) {
+            (Kind::Task, "steps") | (Kind::Step, "parallel") | (Kind::Lanes, _) => Kind::Step,
+            _ => Kind::Other,
+        }
+    }
+}
+
+/// Writes values in block style, putting comments back as it goes
+struct Writer<'a> {
+    out: String,
+    comments: &'a mut HashMap<Vec<Segment>, NodeComments>,
+}
+
+impl Writer<'_> {
+    fn line(&mut self, indent: usize, text: &str) {
+        self.out.push_str(&" ".repeat(indent));
+        self.out.push_str(text);
+        self.out.push('\n');
+    }
+
+    /// The comments of the node at `path`, taken so each is written once
+    fn take_comments(&mut self, path: &[Segment]) -> NodeComments {
+        self.comments.remove(path).unwrap_or_default()
+    }
+
+    /// Top-level settings and tasks, in file order. Multi-line entries are
+    /// set off by blank lines, as is the first task after the settings.
+    fn root(&mut self, root: &Mapping) -> Result<()> {
+        let mut previous: Option<(bool, bool)> = None;
+        for (key, value) in root {
+            let name = key_text(key)?;
+            let setting = SETTINGS.contains(&name.as_str());
+            let kind = if setting { Kind::Other } else { Kind::Task };
+            let start = self.out.len();
+            self.entry(key, value, &mut vec![Segment::Key(name)], 0, None, kind)?;
+            let lines = self.out[start..]
+                .lines()
+                .filter(|line| !line.trim_start().starts_with('#'))
+                .count();
+            let multi_line = lines > 1;
+            if let Some((was_setting, was_multi_line)) = previous {
+                if multi_line || was_multi_line || (was_setting && !setting) {
+                    self.out.insert(start, '\n');
+                }
+            }
+            previous = Some((setting, multi_line));
+        }
+        Ok(())
+    }
+
+    /// One `key: value` entry at `indent`, whose value holds `kind`;
+    /// `prefix` (a sequence item's `- ` at that indentation) starts its line
+    fn entry(
+        &mut self,
+        key: &Value,
+        value: &Value,
+        path: &mut Vec<Segment>,
+        indent: usize,
+        prefix: Option<usize>,
+        kind: Kind,
+    ) -> Result<()> {
+        let comments = self.take_comments(path);
+        for comment in &comments.before {
+            self.line(prefix.unwrap_or(indent), comment);
+        }
+        let lead = match prefix {
+            Some(dash) => format!("{}- ", " ".repeat(dash)),
+            None => " ".repeat(indent),
+        };
+        let head = format!("{}{}:", lead, scalar(key, indent)?);
+        let trailing = trailing(comments.trailing);
+        let flow_text = match value {
+            // A command's argv reads best on one line, like the command it is
+            Value::Sequence(items) if key.as_str() == Some("cmd") || words(items) => {
+                flow(items, head.len() + 1)
+            }
+            _ => None,
+        };
+        match value {
+            Value::Mapping(map) if !map.is_empty() => {
+                self.out.push_str(&format!("{}{}\n", head, trailing));
+                let kind = if kind == Kind::Step {
+                    Kind::Lanes
+                } else {
+                    kind
+                };
+                self.mapping(map, path, indent + 2, None, kind)
+            }
+            Value::Sequence(items) if !items.is_empty() && flow_text.is_none() => {
+                self.out.push_str(&format!("{}{}\n", head, trailing));
+                self.sequence(items, path, indent + 2, kind)
+            }
+            _ => {
+                let text = match flow_text {
+                    Some(text) => text,
+                    None => scalar(value, indent)?,
+                };
+                self.out
+                    .push_str(&format!("{} {}{}\n", head, text, trailing));
+                Ok(())
+            }
+        }
+    }
+
+    /// A mapping's entries at `indent`, in the canonical order for `kind`
+    fn mapping(
+        &mut self,
+        map: &Mapping,
+        path: &mut Vec<Segment>,
+        indent: usize,
+        mut prefix: Option<usize>,
+        kind: Kind,
+    ) -> Result<()> {
+        let order = match kind {
+            Kind::Task => TASK_FIELDS,
+            Kind::Step => STEP_FIELDS,
+            Kind::Lanes | Kind::Other => &[],
+        };
+        let mut entries: Vec<(&Value, &Value)> = map.iter().collect();
+        // Known fields in order, then the rest as written
+        entries.sort_by_key(|(key, _)| {
+            let name = key.as_str().unwrap_or_default();
+            order
+                .iter()
+                .position(|field| *field == name)
+                .unwrap_or(order.len())
+        });
+        for (key, value) in entries {
+            let name = key_text(key)?;
+            let child = kind.child(&name);
+            path.push(Segment::Key(name));
+            self.entry(key, value, path, indent, prefix.take(), child)?;
+            path.pop();
+        }
+        Ok(())
+    }
+
+    /// A sequence's items at `indent`, each starting with `- `
+    fn sequence(
+        &mut self,
+        items: &[Value],
+        path: &mut Vec<Segment>,
+        indent: usize,
+        kind: Kind,
+    ) -> Result<()> {
+        for (index, item) in items.iter().enumerate() {
+            path.push(Segment::Item(index));
+            let comments = self.take_comments(path);
+            for comment in &comments.before {
+                self.line(indent, comment);
+            }
+            let flow_text = match item {
+                Value::Sequence(inner) if words(inner) => flow(inner, indent + 2),
+                _ => None,
+            };
+            match item {
+                Value::Mapping(map) if !map.is_empty() => {
+                    // Its own line's comment moves above it
+                    if let Some(comment) = &comments.trailing {
+                        self.line(indent, comment);
+                    }
+                    self.mapping(map, path, indent + 2, Some(indent), kind)?
+                }
+                Value::Sequence(inner) if !inner.is_empty() && flow_text.is_none() => {
+                    self.line(indent, &format!("-{}", trailing(comments.trailing)));
+                    self.sequence(inner, path, indent + 2, Kind::Other)?;
+                }
+                _ => {
+                    let text = match flow_text {
+                        Some(text) => text,
+                        None => scalar(item, indent)?,
+                    };
+                    let text = format!("- {}{}", text, trailing(comments.trailing));
+                    self.line(indent, &text);
+                }
+            }
+            path.pop();
+        }
+        Ok(())
+    }
+}
+
+/// A comment for the end of a line, two spaces after its text
+fn trailing(comment: Option<String>) -> String {
+    comment
+        .map(|comment| format!("  {}", comment))
+        .unwrap_or_default()
+}
+
+/// A mapping key as the comment pass sees it
+fn key_text(key: &Value) -> Result<String> {
+    Ok(match key {
+        Value::String(text) => text.clone(),
+        other => scalar(other, 0)?,
+    })
+}
+
+/// A scalar (or empty collection) as YAML, quoted only where it must be.
+/// Multi-line strings become `|` blocks indented past `indent`.
+fn scalar(value: &Value, indent: usize) -> Result<String> {
+    match value {
+        Value::Mapping(map) if map.is_empty() => return Ok("{}".to_string()),
+        Value::Sequence(items) if items.is_empty() => return Ok("[]".to_string()),
+        Value::Tagged(tagged) => anyhow::bail!("rnr fmt can't keep YAML tags ('{}')", tagged.tag),
+        _ => {}
+    }
+    let text = serde_yaml::to_string(value)?;
+    let mut lines = text.trim_end_matches('\n').lines();
+    let mut out = lines.next().unwrap_or_default().to_string();
+    for line in lines {
+        out.push('\n');
+        if !line.is_empty() {
+            out.push_str(&" ".repeat(indent));
+            out.push_str(line);
+        }
+    }
+    Ok(out)
+}
+
+/// Whether every item is a single word, like tags or variable names
+fn words(items: &[Value]) -> bool {
+    items.iter().all(|item| match item {
+        Value::String(text) => !text.is_empty() && !text.contains(char::is_whitespace),
+        Value::Mapping(_) | Value::Sequence(_) | Value::Tagged(_) => false,
+        _ => true,
+    })
+}
+
+/// `[a, b]` for a sequence of single-line scalars, if it fits on a line
+/// after `column`
+fn flow(items: &[Value], column: usize) -> Option<String> {
+    let mut parts = Vec::new();
+    for item in items {
+        if matches!(
+            item,
+            Value::Mapping(_) | Value::Sequence(_) | Value::Tagged(_)
+        ) {
+            return None;
+        }
+        let text = scalar(item, 0).ok()?;
+        if text.contains('\n') {
+            return None;
+        }
+        let quoted = text.starts_with(['\'', '"']);
+        match text.contains([',', '[', ']', '{', '}']) && !quoted {
+            true => parts.push(format!("'{}'", text.replace('\'', "''"))),
+            false => parts.push(text),
+        }
+    }
+    let text = format!("[{}]", parts.join(", "));
+    (column + text.len() <= FLOW_WIDTH).then_some(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn test_canonical_layout() {
+        let messy = "\
+build:
+    cmd: cargo build
+    description: Build it
+    dir: app
+lint: cargo clippy
+ci:
+  steps:
+  - task: lint
+  - cmd:   make
+    env: {MODE: ci}
+    dir: web
+  - parallel:
+      - {cmd: npm test, tags: [slow, web]}
+      - mkdir: out
+    allow_shared_dir: true
+strict: true
+";
+        assert_eq!(
+            format(messy).unwrap(),
+            "\
+build:
+  description: Build it
+  dir: app
+  cmd: cargo build
+
+lint: cargo clippy
+
+ci:
+  steps:
+    - task: lint
+    - dir: web
+      env:
+        MODE: ci
+      cmd: make
+    - allow_shared_dir: true
+      parallel:
+        - cmd: npm test
+          tags: [slow, web]
+        - mkdir: out
+
+strict: true
+"
+        );
+    }
+
+    #[test]
+    fn test_formatting_is_stable() {
+        let config = "\
+# Project tasks
+
+strict: true
+echo_format: '[{dir}] $ {cmd}'
+
+# Quick checks
+lint: cargo clippy  # fast
+fmt: cargo fmt --check
+
+release:
+  description: 'Build: release'
+  long_description: |
+    Builds everything.
+    # not a comment
+  env:
+    TOKEN: ''
+    LEVEL: '3'
+  steps:
+    # prepare first
+    - cmd: [printf, '%s|', 'a, b']
+    - parallel:
+        api:
+          - cmd: cargo build  # the api
+        web:
+          - cmd: npm run build
+
+# end
+";
+        assert_eq!(format(config).unwrap(), config);
+    }
+
+    #[test]
+    fn test_comments_follow_their_keys() {
+        let config = "\
+build:
+  cmd: cargo build  # compile
+  # where
+  dir: app
+";
+        assert_eq!(
+            format(config).unwrap(),
+            "\
+build:
+  # where
+  dir: app
+  cmd: cargo build  # compile
+"
+        );
+    }
+
+    #[test]
+    fn test_split_comment() {
+        assert_eq!(
+            split_comment("cmd: make # all"),
+            ("cmd: make", Some("# all"))
+        );
+        assert_eq!(split_comment("cmd: echo#1"), ("cmd: echo#1", None));
+        assert_eq!(
+            split_comment("cmd: 'it''s # quoted' # real"),
+            ("cmd: 'it''s # quoted'", Some("# real"))
+        );
+        assert_eq!(
+            split_comment(r#"cmd: "a \" # b" # c"#),
+            (r#"cmd: "a \" # b""#, Some("# c"))
+        );
+        assert_eq!(
+            split_comment("cmd: echo it's # c"),
+            ("cmd: echo it's", Some("# c"))
+        );
+    }
+
+    #[test]
+    fn test_refuses_what_it_cannot_keep() {
+        let anchors = "base: &base\n  dir: app\nbuild:\n  <<: *base\n  cmd: make\n";
+        let err = format(anchors).unwrap_err().to_string();
+        assert!(err.contains("anchors and aliases"), "{}", err);
+
+        let flow_comment = "build:\n  steps: [\n    # inside\n    {cmd: a}]\n";
+        let err = format(flow_comment).unwrap_err().to_string();
+        assert!(err.contains("can't keep the comment"), "{}", err);
+    }
+
+    #[test]
+    fn test_empty_files() {
+        assert_eq!(format("").unwrap(), "");
+        assert_eq!(format("# nothing yet\n").unwrap(), "# nothing yet\n");
+        assert_eq!(format("{}").unwrap(), "{}\n");
+    }
+
+    #[test]
+    fn test_fixtures_format_once() {
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+        for entry in std::fs::read_dir(fixtures).unwrap() {
+            let path = entry.unwrap().path().join("rnr.yaml");
+            let Ok(content) = std::fs::read_to_string(&path) else {
+                continue;
+            };
+            let formatted =
+                format(&content).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+            assert_eq!(format(&formatted).unwrap(), formatted, "{}", path.display());
+        }
+    }
+
+    #[test]
+    fn test_check_equivalent() {
+        let original: Value = serde_yaml::from_str("a: x\nb: y\n").unwrap();
+        check_equivalent(&original, "a: x\nb: y\n").unwrap();
+        assert!(check_equivalent(&original, "b: y\na: x\n").is_err());
+        assert!(check_equivalent(&original, "a: x\nb: z\n").is_err());
+    }
+}
@@ -0,0 +1,146 @@
+//! Golden-output tests for `rnr --list`, `rnr help <task>`, and command echoes
+//!
+//! Every `tests/fixtures/<name>/rnr.yaml` is rendered through the list and
+//! help code paths and compared with `tests/golden/<name>/list*.txt` (one
+//! of them with `--sources`) and
+//! `tests/golden/<name>/help/<task>.txt`. The tasks of the [`ECHO_FIXTURES`]
+//! are also dry-run, with what they echo compared with
+//! `tests/golden/<name>/dry-run/<task>.txt`. After an intentional change to
+//! the output, regenerate the expected files with:
+//!
+//! ```text
+//! UPDATE_GOLDEN=1 cargo test golden
+//! ```
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::commands::{help, list};
+use crate::config::Config;
+use crate::plan::{self, DiffLine};
+use crate::runner::{self, RunOptions};
+use crate::ui::Ui;
+
+/// Fixtures whose tasks are dry-run to check how their commands are echoed
+const ECHO_FIXTURES: &[&str] = &["computed", "echo", "echo-format"];
+
+fn tests_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests")
+}
+
+/// Fixture names with an rnr.yaml, sorted
+fn fixtures() -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(tests_dir().join("fixtures"))
+        .unwrap()
+        .flatten()
+        .filter(|entry| entry.path().join("rnr.yaml").is_file())
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    names
+}
+
+/// Compare `actual` with a golden file (or rewrite it), returning a report on mismatch
+fn check(golden: &Path, actual: &str) -> Option<String> {
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::create_dir_all(golden.parent().unwrap()).unwrap();
+        fs::write(golden, actual).unwrap();
+        return None;
+    }
+
+    let expected = fs::read_to_string(golden)
+        .unwrap_or_default()
+        .replace("\r\n", "\n");
+    if expected == actual {
+        return None;
+    }
+
+    let lines = |text: &str| text.lines().map(str::to_string).collect::<Vec<_>>();
+    let mut report = format!("--- {}\n+++ actual\n", golden.display());
+    for line in plan::diff_lines(&lines(&expected), &lines(actual)) {
+        match line {
+            DiffLine::Same(line) => report.push_str(&format!(" {}\n", line)),
+            DiffLine::Removed(line) => report.push_str(&format!("-{}\n", line)),
+            DiffLine::Added(line) => report.push_str(&format!("+{}\n", line)),
+        }
+    }
+    Some(report)
+}
+
+#[test]
+fn golden_outputs() {
+    let mut failures = Vec::new();
+
+    for fixture in fixtures() {
+        let config =
+            Config::load_from(&tests_dir().join("fixtures").join(&fixture).join("rnr.yaml"))
+                .unwrap();
+        let golden_dir = tests_dir().join("golden").join(&fixture);
+
+        // The default width when output isn't a terminal, a narrow one, and --wide
+        for (file, width) in [
+            ("list.txt", Some(80)),
+            ("list-narrow.txt", Some(40)),
+            ("list-wide.txt", None),
+        ] {
+            let mut out = Vec::new();
+            list::render(&config, width, None, &mut out).unwrap();
+            let listing = String::from_utf8(out).unwrap();
+            failures.extend(check(&golden_dir.join(file), &listing));
+        }
+
+        // --sources, with paths relative to the fixture
+        let mut out = Vec::new();
+        let root = tests_dir().join("fixtures").join(&fixture);
+        list::render(&config, Some(80), Some(&root), &mut out).unwrap();
+        let listing = String::from_utf8(out).unwrap();
+        failures.extend(check(&golden_dir.join("list-sources.txt"), &listing));
+
+        for name in config.task_names() {
+            let task = config.get_task(name).unwrap();
+            let golden = golden_dir.join("help").join(format!("{}.txt", name));
+            failures.extend(check(&golden, &help::render(name, task)));
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "output differs from golden files (rerun with UPDATE_GOLDEN=1 to accept):\n\n{}",
+        failures.join("\n")
+    );
+}
+
+#[test]
+fn golden_echoes() {
+    let mut failures = Vec::new();
+
+    for fixture in ECHO_FIXTURES {
+        // A dry run saves its plan under .rnr, so run from a copy
+        let temp = tempfile::tempdir().unwrap();
+        let source = tests_dir().join("fixtures").join(fixture).join("rnr.yaml");
+        fs::copy(&source, temp.path().join("rnr.yaml")).unwrap();
+        let config = Config::load_from(&source).unwrap();
+        let golden_dir = tests_dir().join("golden").join(fixture).join("dry-run");
+
+        for name in config.task_names() {
+            let (ui, capture) = Ui::capture(false);
+            let opts = RunOptions {
+                dry_run: true,
+                ui,
+                project_root: Some(temp.path().to_path_buf()),
+                // One branch at a time keeps parallel echoes in order
+                jobs: Some(1),
+                ..Default::default()
+            };
+            runner::run_task(name, &[], &opts).unwrap();
+            let golden = golden_dir.join(format!("{}.txt", name));
+            failures.extend(check(&golden, &capture.stdout()));
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "echoes differ from golden files (rerun with UPDATE_GOLDEN=1 to accept):\n\n{}",
+        failures.join("\n")
+    );
+}
@@ -0,0 +1,672 @@
+//! Limits on when and for how long a run goes
+//!
+//! A task's `not_between` window keeps it from starting at times of day
+//! such as a change freeze, checked with every task a run reaches before any
+//! of them runs. The top-level `budget` stops a run from starting more
+//! top-level steps once it has gone that long; the step running then
+//! finishes, and the rest are skipped. `--ignore-guards` runs past both,
+//! with a warning.
+//!
+//! Release tasks can also ask for a state of the git repository: no
+//! uncommitted changes (`require_clean_git`, waived by `--allow-dirty`), a
+//! branch checked out (`require_branch`), and the branch level with its
+//! upstream as of the last fetch (`require_upstream_synced`). The last two
+//! are waived by `--ignore-guards`.
+
+use anyhow::{Context, Result};
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::checkout;
+use crate::config::{GitRequirements, TimeOfDay, TimeSpan, TimeZone, Window};
+use crate::ui::Ui;
+
+/// Most uncommitted paths named when a task needs a clean git state
+const MAX_DIRTY_SHOWN: usize = 10;
+
+/// Where guards get the time: the system clock, or a stand-in for tests
+#[derive(Clone, Default)]
+pub struct Clock(Option<Arc<dyn Fn() -> SystemTime + Send + Sync>>);
+
+impl Clock {
+    /// A clock reading `now` instead of the system time
+    pub fn new(now: impl Fn() -> SystemTime + Send + Sync + 'static) -> Self {
+        Clock(Some(Arc::new(now)))
+    }
+
+    /// The current time
+    pub fn now(&self) -> SystemTime {
+        match &self.0 {
+            Some(now) => now(),
+            None => SystemTime::now(),
+        }
+    }
+}
+
+impl fmt::Debug for Clock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self.0 {
+            Some(_) => "Clock(injected)",
+            None => "Clock(system)",
+        })
+    }
+}
+
+/// The time of day at `now` in `tz`
+pub fn time_of_day(now: SystemTime, tz: TimeZone) -> TimeOfDay {
+    let seconds = now.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let offset = match tz {
+        TimeZone::Offset(offset) => i64::from(offset),
+        TimeZone::Local => sys::local_offset_minutes(seconds).unwrap_or(0),
+    };
+    let minutes = (seconds / 60) as i64 + offset;
+    TimeOfDay::from_minutes(minutes.rem_euclid(24 * 60) as u32)
+}
+
+/// Fail if `task`, kept out of `window` in `tz`, may not start at `now`.
+/// With `ignore`, warn instead.
+pub fn check_window(
+    task: &str,
+    window: &Window,
+    tz: TimeZone,
+    now: SystemTime,
+    ignore: bool,
+    ui: &Ui,
+) -> Result<()> {
+    let time = time_of_day(now, tz);
+    if !window.contains(time) {
+        return Ok(());
+    }
+    let message = format!(
+        "Task '{}' doesn't run between {} and {} ({}; it's {} now)",
+        task, window.from, window.to, tz, time
+    );
+    if ignore {
+        ui.warn(format!("{}; running it anyway (--ignore-guards)", message));
+        return Ok(());
+    }
+    anyhow::bail!("{}. Pass --ignore-guards to run it anyway", message)
+}
+
+/// A run's `budget`, counted from when the run started
+#[derive(Debug)]
+pub struct Budget {
+    limit: TimeSpan,
+    started: SystemTime,
+    /// Whether `--ignore-guards` has been announced as running past it
+    warned: bool,
+}
+
+impl Budget {
+    pub fn new(limit: TimeSpan, started: SystemTime) -> Self {
+        Budget {
+            limit,
+            started,
+            warned: false,
+        }
+    }
+
+    /// How long the run has gone at `now`
+    pub fn elapsed(&self, now: SystemTime) -> Duration {
+        now.duration_since(self.started).unwrap_or_default()
+    }
+
+    /// Whether the run is out of budget at `now`, so no more steps start.
+    /// With `ignore`, it never is, but says so the first time it would be.
+    pub fn exhausted(&mut self, now: SystemTime, ignore: bool, ui: &Ui) -> bool {
+        let elapsed = self.elapsed(now);
+        if elapsed < self.limit.0 {
+            return false;
+        }
+        if !ignore {
+            return true;
+        }
+        if !self.warned {
+            self.warned = true;
+            ui.warn(format!(
+                "The {} budget is used up after {}; starting more steps anyway (--ignore-guards)",
+                self.limit,
+                rounded(elapsed)
+            ));
+        }
+        false
+    }
+
+    /// The error for a run that left `skipped` steps unstarted at `now`
+    pub fn overrun(&self, now: SystemTime, skipped: usize) -> OverBudget {
+        OverBudget {
+            limit: self.limit,
+            elapsed: rounded(self.elapsed(now)),
+            skipped,
+        }
+    }
+}
+
+/// `elapsed` to the second, for messages
+fn rounded(elapsed: Duration) -> TimeSpan {
+    TimeSpan(Duration::from_secs(elapsed.as_secs()))
+}
+
+/// A run that stopped starting steps because it went past its `budget`
+#[derive(Debug, thiserror::Error)]
+#[error(
+    "Over the {limit} budget after {elapsed}, so {skipped} step(s) weren't started. \
+     Pass --ignore-guards to run past it"
+)]
+pub struct OverBudget {
+    pub limit: TimeSpan,
+    pub elapsed: TimeSpan,
+    pub skipped: usize,
+}
+
+/// What git requirements are checked against, each read at most once a run
+#[derive(Debug, Default)]
+pub struct GitState {
+    /// Tracked files with uncommitted changes
+    dirty: Option<Vec<String>>,
+    /// The branch checked out, `None` for a detached HEAD
+    branch: Option<Option<String>>,
+    /// The branch's upstream and how far apart they are, if it has one
+    upstream: Option<Option<Upstream>>,
+}
+
+/// A branch's upstream, as of the last fetch
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Upstream {
+    name: String,
+    ahead: usize,
+    behind: usize,
+}
+
+impl GitState {
+    /// Read what `requirements` need from the repository at `root` that
+    /// hasn't been read yet
+    pub fn read(&mut self, root: &Path, requirements: &GitRequirements) -> Result<()> {
+        if self.dirty.is_none() && self.branch.is_none() {
+            checkout::git(root, &["rev-parse", "--show-toplevel"])
+                .context("require_clean_git, require_branch and require_upstream_synced need the project to be in a git repository")?;
+        }
+        if requirements.clean && self.dirty.is_none() {
+            self.dirty = Some(checkout::dirty_files(root)?);
+        }
+        let needs_branch = requirements.branch.is_some() || requirements.upstream_synced;
+        if needs_branch && self.branch.is_none() {
+            let branch = checkout::git(root, &["rev-parse", "--abbrev-ref", "HEAD"])?;
+            self.branch = Some((branch != "HEAD").then_some(branch));
+        }
+        if requirements.upstream_synced && self.upstream.is_none() {
+            self.upstream = Some(upstream(root)?);
+        }
+        Ok(())
+    }
+
+    /// What isn't as `task` requires, each with the flag that waives it,
+    /// from what's been [read](Self::read)
+    pub fn problems(&self, task: &str, requirements: &GitRequirements) -> Vec<GitProblem> {
+        let mut problems = Vec::new();
+        let dirty = self.dirty.as_deref().unwrap_or_default();
+        if requirements.clean && !dirty.is_empty() {
+            let mut shown = dirty[..dirty.len().min(MAX_DIRTY_SHOWN)].join(", ");
+            if dirty.len() > MAX_DIRTY_SHOWN {
+                shown.push_str(&format!(" and {} more", dirty.len() - MAX_DIRTY_SHOWN));
+            }
+            problems.push(GitProblem {
+                message: format!(
+                    "Task '{}' needs a clean git state, but there are uncommitted changes to {}",
+                    task, shown
+                ),
+                flag: "--allow-dirty",
+            });
+        }
+        let branch = self.branch.as_ref().and_then(Option::as_deref);
+        if let Some(required) = &requirements.branch {
+            if branch != Some(required.as_str()) {
+                let current = match branch {
+                    Some(branch) => format!("'{}' is checked out", branch),
+                    None => "HEAD is detached".to_string(),
+                };
+                problems.push(GitProblem {
+                    message: format!(
+                        "Task '{}' runs from branch '{}', but {}",
+                        task, required, current
+                    ),
+                    flag: "--ignore-guards",
+                });
+            }
+        }
+        if requirements.upstream_synced {
+            let shown = branch.map_or("HEAD".to_string(), |branch| format!("'{}'", branch));
+            let message = match self.upstream.as_ref().and_then(Option::as_ref) {
+                None => Some(format!(
+                    "Task '{}' needs {} in sync with its upstream, but it has none",
+                    task, shown
+                )),
+                Some(upstream) if upstream.ahead > 0 || upstream.behind > 0 => {
+                    let mut apart = Vec::new();
+                    if upstream.ahead > 0 {
+                        apart.push(format!("{} commit(s) ahead", upstream.ahead));
+                    }
+                    if upstream.behind > 0 {
+                        apart.push(format!("{} behind", upstream.behind));
+                    }
+                    Some(format!(
+                        "Task '{}' needs {} in sync with {}, but it's {} (as of the last fetch)",
+                        task,
+                        shown,
+                        upstream.name,
+                        apart.join(" and ")
+                    ))
+                }
+                Some(_) => None,
+            };
+            problems.extend(message.map(|message| GitProblem {
+                message,
+                flag: "--ignore-guards",
+            }));
+        }
+        problems
+    }
+}
+
+/// Something about the git repository that a task doesn't allow
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitProblem {
+    pub message: String,
+    /// The flag that runs the task anyway
+    pub flag: &'static str,
+}
+
+/// The upstream of the branch checked out in `root` and how far apart they
+/// are, if it has one
+fn upstream(root: &Path) -> Result<Option<Upstream>> {
+    let Ok(name) = checkout::git(
+        root,
+        &[
+            "rev-parse",
+            "--abbrev-ref",
+            "--symbolic-full-name",
+            "@{upstream}",
+        ],
+    ) else {
+        return Ok(None);
+    };
+    let counts = checkout::git(
+        root,
+        &["rev-list", "--left-right", "--count", "HEAD...@{upstream}"],
+    )?;
+    let mut parsed = counts.split_whitespace().map(str::parse::<usize>);
+    match (parsed.next(), parsed.next()) {
+        (Some(Ok(ahead)), Some(Ok(behind))) => Ok(Some(Upstream {
+            name,
+            ahead,
+            behind,
+        })),
+        _ => anyhow::bail!("Unexpected output from git rev-list: {}", counts),
+    }
+}
+
+/// Fail on the first problem `state` finds with `task`'s git requirements,
+/// or warn about each if the flag that waives it is set (`allow_dirty` for
+/// `--allow-dirty`, `ignore` for `--ignore-guards`)
+pub fn check_git(
+    task: &str,
+    requirements: &GitRequirements,
+    state: &GitState,
+    allow_dirty: bool,
+    ignore: bool,
+    ui: &Ui,
+) -> Result<()> {
+    for problem in state.problems(task, requirements) {
+        let waived = match problem.flag {
+            "--allow-dirty" => allow_dirty,
+            _ => ignore,
+        };
+        if !waived {
+            anyhow::bail!(
+                "{}. Pass {} to run it anyway",
+                problem.message,
+                problem.flag
+            );
+        }
+        ui.warn(format!(
+            "{}; running it anyway ({})",
+            problem.message, problem.flag
+        ));
+    }
+    Ok(())
+}
+
+/// What `requirements` ask for, in words, e.g. "a clean git state, on
+/// branch 'main'"
+pub fn describe_git(requirements: &GitRequirements) -> String {
+    let mut parts = Vec::new();
+    if requirements.clean {
+        parts.push("a clean git state".to_string());
+    }
+    if let Some(branch) = &requirements.branch {
+        parts.push(format!("on branch '{}'", branch));
+    }
+    if requirements.upstream_synced {
+        parts.push("in sync with its upstream".to_string());
+    }
+    parts.join(", ")
+}
+
+#[cfg(unix)]
+mod sys {
+    use std::ffi::c_long;
+
+    /// Enough room for any platform's `struct tm`, whose first fields are
+    /// the seconds, minutes, and hours
+    #[repr(C)]
+    struct Tm {
+        fields: [i32; 9],
+        rest: [i64; 4],
+    }
+
+    extern "C" {
+        fn localtime_r(time: *const c_long, result: *mut Tm) -> *mut Tm;
+    }
+
+    /// Minutes local time is ahead of UTC at `seconds` after the epoch
+    pub fn local_offset_minutes(seconds: u64) -> Option<i64> {
+        let time = seconds as c_long;
+        let mut tm = Tm {
+            fields: [0; 9],
+            rest: [0; 4],
+        };
+        // SAFETY: localtime_r only writes the `struct tm` it's given, which
+        // `Tm` has room for
+        let result = unsafe { localtime_r(&time, &mut tm) };
+        if result.is_null() {
+            return None;
+        }
+        let local = i64::from(tm.fields[2]) * 60 + i64::from(tm.fields[1]);
+        let utc = (seconds as i64 / 60).rem_euclid(24 * 60);
+        // Local time is at most a day from UTC either way
+        let offset = (local - utc).rem_euclid(24 * 60);
+        Some(if offset > 12 * 60 {
+            offset - 24 * 60
+        } else {
+            offset
+        })
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    /// `SYSTEMTIME`: year, month, day of week, day, hour, minute, second, ms
+    type SystemTime = [u16; 8];
+
+    extern "system" {
+        fn GetLocalTime(time: *mut SystemTime);
+        fn GetSystemTime(time: *mut SystemTime);
+    }
+
+    /// Minutes local time is ahead of UTC now (Windows has no cheap way to
+    /// ask about another moment, and offsets rarely change mid-run)
+    pub fn local_offset_minutes(_seconds: u64) -> Option<i64> {
+        let (mut local, mut utc) = ([0; 8], [0; 8]);
+        // SAFETY: each call fills in the SYSTEMTIME it's given
+        unsafe {
+            GetLocalTime(&mut local);
+            GetSystemTime(&mut utc);
+        }
+        let minutes = |time: &SystemTime| i64::from(time[4]) * 60 + i64::from(time[5]);
+        let offset = (minutes(&local) - minutes(&utc)).rem_euclid(24 * 60);
+        Some(if offset > 12 * 60 {
+            offset - 24 * 60
+        } else {
+            offset
+        })
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod sys {
+    pub fn local_offset_minutes(_seconds: u64) -> Option<i64> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2024-03-01 at `hours:minutes` UTC
+    fn at(hours: u64, minutes: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(1_709_251_200 + hours * 3600 + minutes * 60)
+    }
+
+    fn window(yaml: &str) -> Window {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    fn tz(yaml: &str) -> TimeZone {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn windows_wrap_past_midnight() {
+        let freeze = window("['22:00', '06:00']");
+        let utc = TimeZone::Offset(0);
+        assert!(freeze.contains(time_of_day(at(23, 30), utc)));
+        assert!(freeze.contains(time_of_day(at(5, 59), utc)));
+        assert!(!freeze.contains(time_of_day(at(6, 0), utc)));
+        assert!(!freeze.contains(time_of_day(at(21, 59), utc)));
+
+        let lunch = window("['12:00', '13:30']");
+        assert!(lunch.contains(time_of_day(at(12, 0), utc)));
+        assert!(!lunch.contains(time_of_day(at(13, 30), utc)));
+    }
+
+    #[test]
+    fn offsets_shift_the_time_of_day() {
+        assert_eq!(time_of_day(at(23, 30), tz("'+02:00'")).to_string(), "01:30");
+        assert_eq!(time_of_day(at(1, 0), tz("'-0530'")).to_string(), "19:30");
+        assert_eq!(time_of_day(at(1, 0), tz("UTC")).to_string(), "01:00");
+        assert_eq!(tz("'+05:30'").to_string(), "UTC+05:30");
+        assert_eq!(tz("local"), TimeZone::Local);
+        assert!(serde_yaml::from_str::<TimeZone>("Europe/Berlin").is_err());
+        assert!(serde_yaml::from_str::<Window>("['25:00', '06:00']").is_err());
+        assert!(serde_yaml::from_str::<Window>("['22:0', '06:00']").is_err());
+        assert!(serde_yaml::from_str::<Window>("['06:00', '06:00']").is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn local_time_has_an_offset() {
+        let offset = sys::local_offset_minutes(1_709_251_200).unwrap();
+        assert!(offset.abs() <= 14 * 60);
+    }
+
+    #[test]
+    fn check_window_fails_or_warns_inside_it() {
+        let (ui, output) = Ui::capture(false);
+        let freeze = window("['22:00', '06:00']");
+        let utc = TimeZone::Offset(0);
+        check_window("deploy", &freeze, utc, at(12, 0), false, &ui).unwrap();
+        let error = check_window("deploy", &freeze, utc, at(23, 15), false, &ui).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Task 'deploy' doesn't run between 22:00 and 06:00 (UTC; it's 23:15 now). \
+             Pass --ignore-guards to run it anyway"
+        );
+        check_window("deploy", &freeze, utc, at(23, 15), true, &ui).unwrap();
+        assert!(output
+            .stderr()
+            .contains("running it anyway (--ignore-guards)"));
+    }
+
+    #[test]
+    fn budgets_run_out() {
+        let (ui, output) = Ui::capture(false);
+        let limit: TimeSpan = serde_yaml::from_str("30m").unwrap();
+        let mut budget = Budget::new(limit, at(9, 0));
+        assert!(!budget.exhausted(at(9, 29), false, &ui));
+        assert!(budget.exhausted(at(9, 30), false, &ui));
+        assert_eq!(
+            budget.overrun(at(9, 31), 2).to_string(),
+            "Over the 30m budget after 31m, so 2 step(s) weren't started. \
+             Pass --ignore-guards to run past it"
+        );
+
+        assert!(!budget.exhausted(at(9, 45), true, &ui));
+        assert!(!budget.exhausted(at(9, 50), true, &ui));
+        assert_eq!(output.stderr().matches("--ignore-guards").count(), 1);
+    }
+
+    /// Run git in `root`
+    fn git(root: &Path, args: &[&str]) {
+        checkout::git(root, args).unwrap();
+    }
+
+    /// A repository with one commit on `main`
+    fn repo() -> tempfile::TempDir {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        git(root, &["init", "--quiet"]);
+        git(root, &["config", "user.email", "dev@example.com"]);
+        git(root, &["config", "user.name", "dev"]);
+        std::fs::write(root.join("version.txt"), "1\n").unwrap();
+        git(root, &["add", "."]);
+        git(root, &["commit", "--quiet", "-m", "first"]);
+        git(root, &["checkout", "--quiet", "-B", "main"]);
+        temp
+    }
+
+    fn requirements(clean: bool, branch: Option<&str>, upstream_synced: bool) -> GitRequirements {
+        GitRequirements {
+            clean,
+            branch: branch.map(str::to_string),
+            upstream_synced,
+        }
+    }
+
+    fn problems(root: &Path, requirements: &GitRequirements) -> Vec<String> {
+        let mut state = GitState::default();
+        state.read(root, requirements).unwrap();
+        state
+            .problems("release", requirements)
+            .into_iter()
+            .map(|problem| problem.message)
+            .collect::<Vec<_>>()
+    }
+
+    #[test]
+    fn clean_git_lists_up_to_ten_dirty_paths() {
+        let temp = repo();
+        let root = temp.path();
+        let clean = requirements(true, None, false);
+        assert!(problems(root, &clean).is_empty());
+
+        std::fs::write(root.join("untracked.txt"), "").unwrap();
+        assert!(problems(root, &clean).is_empty());
+        for i in 0..12 {
+            std::fs::write(root.join(format!("f{:02}.txt", i)), "").unwrap();
+        }
+        git(root, &["add", "."]);
+        assert_eq!(
+            problems(root, &clean),
+            [
+                "Task 'release' needs a clean git state, but there are uncommitted changes to \
+              f00.txt, f01.txt, f02.txt, f03.txt, f04.txt, f05.txt, f06.txt, f07.txt, f08.txt, \
+              f09.txt and 3 more"
+            ]
+        );
+
+        let (ui, output) = Ui::capture(false);
+        let mut state = GitState::default();
+        state.read(root, &clean).unwrap();
+        let error = check_git("release", &clean, &state, false, false, &ui).unwrap_err();
+        assert!(error
+            .to_string()
+            .ends_with("and 3 more. Pass --allow-dirty to run it anyway"));
+        check_git("release", &clean, &state, true, false, &ui).unwrap();
+        assert!(output
+            .stderr()
+            .contains("and 3 more; running it anyway (--allow-dirty)"));
+        // --ignore-guards doesn't cover uncommitted changes
+        assert!(check_git("release", &clean, &state, false, true, &ui).is_err());
+    }
+
+    #[test]
+    fn require_branch_names_what_is_checked_out() {
+        let temp = repo();
+        let root = temp.path();
+        let main = requirements(false, Some("main"), false);
+        assert!(problems(root, &main).is_empty());
+
+        git(root, &["checkout", "--quiet", "-b", "feature/x"]);
+        assert_eq!(
+            problems(root, &main),
+            ["Task 'release' runs from branch 'main', but 'feature/x' is checked out"]
+        );
+        git(root, &["checkout", "--quiet", "--detach"]);
+        assert_eq!(
+            problems(root, &main),
+            ["Task 'release' runs from branch 'main', but HEAD is detached"]
+        );
+    }
+
+    #[test]
+    fn upstream_sync_counts_commits_apart() {
+        let temp = repo();
+        let root = temp.path();
+        let synced = requirements(false, None, true);
+        assert_eq!(
+            problems(root, &synced),
+            ["Task 'release' needs 'main' in sync with its upstream, but it has none"]
+        );
+
+        let remote = tempfile::tempdir().unwrap();
+        git(remote.path(), &["init", "--quiet", "--bare"]);
+        let url = remote.path().to_str().unwrap();
+        git(root, &["remote", "add", "origin", url]);
+        git(root, &["push", "--quiet", "-u", "origin", "main"]);
+        assert!(problems(root, &synced).is_empty());
+
+        std::fs::write(root.join("version.txt"), "2\n").unwrap();
+        git(root, &["commit", "--quiet", "-am", "second"]);
+        assert_eq!(
+            problems(root, &synced),
+            ["Task 'release' needs 'main' in sync with origin/main, but it's 1 commit(s) ahead (as of the last fetch)"]
+        );
+        git(root, &["push", "--quiet"]);
+        git(root, &["reset", "--quiet", "--hard", "HEAD~1"]);
+        assert_eq!(
+            problems(root, &synced),
+            ["Task 'release' needs 'main' in sync with origin/main, but it's 1 behind (as of the last fetch)"]
+        );
+    }
+
+    #[test]
+    fn git_requirements_need_a_repository() {
+        let dir = tempfile::tempdir().unwrap();
+        let error = GitState::default()
+            .read(dir.path(), &requirements(true, None, false))
+            .unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("need the project to be in a git repository"));
+        assert_eq!(
+            describe_git(&requirements(true, Some("main"), true)),
+            "a clean git state, on branch 'main', in sync with its upstream"
+        );
+    }
+
+    #[test]
+    fn time_spans_parse_with_units() {
+        let span = |yaml| serde_yaml::from_str::<TimeSpan>(yaml).map(|span| span.0.as_secs());
+        assert_eq!(span("90").unwrap(), 90);
+        assert_eq!(span("90s").unwrap(), 90);
+        assert_eq!(span("30m").unwrap(), 1800);
+        assert_eq!(span("1h30m").unwrap(), 5400);
+        assert!(span("30x").is_err());
+        assert!(span("m").is_err());
+        assert!(span("0s").is_err());
+        assert_eq!(TimeSpan(Duration::from_secs(5430)).to_string(), "1h30m30s");
+    }
+}
@@ -0,0 +1,247 @@
+//! Results of the last run, for `rnr last` and `rnr rerun-failed`
+//!
+//! The outcome of every task and top-level step is written to
+//! `.rnr/cache/last-run.json` when a run finishes.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::checksum;
+use crate::config;
+
+/// File (inside the cache) holding the last run's results
+const LAST_RUN_FILE: &str = "last-run.json";
+
+/// Outcome of a task or step
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Passed,
+    Failed,
+    /// Not reached because something before it failed
+    NotRun,
+    /// Deliberately not run (e.g. already passed before a rerun)
+    Skipped,
+    /// Running when rnr was asked to terminate (SIGTERM)
+    Cancelled,
+    /// Not started because the run had used up its `budget`
+    SkippedOverBudget,
+}
+
+impl Status {
+    /// Whether `rnr rerun-failed` should run this again
+    pub fn needs_rerun(self) -> bool {
+        matches!(
+            self,
+            Status::Failed | Status::NotRun | Status::Cancelled | Status::SkippedOverBudget
+        )
+    }
+
+    /// Label shown by `rnr last`
+    pub fn label(self) -> &'static str {
+        match self {
+            Status::Passed => "passed",
+            Status::Failed => "FAILED",
+            Status::NotRun => "not run",
+            Status::Skipped => "skipped",
+            Status::Cancelled => "cancelled",
+            Status::SkippedOverBudget => "skipped (over budget)",
+        }
+    }
+}
+
+/// Results of a whole invocation
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct RunRecord {
+    /// SHA-256 of rnr.yaml when the run happened
+    pub config_hash: String,
+    /// When the run finished (seconds since the Unix epoch)
+    pub finished_at: u64,
+    /// Tasks in the order they were run
+    pub tasks: Vec<TaskRecord>,
+}
+
+/// Result of one task
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TaskRecord {
+    /// Task name
+    pub name: String,
+    /// Arguments the task was invoked with
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Overall status
+    pub status: Status,
+    /// Wall-clock duration in milliseconds
+    pub duration_ms: u64,
+    /// Error message when the task failed
+    pub error: Option<String>,
+    /// Top-level steps (empty for tasks without steps)
+    #[serde(default)]
+    pub steps: Vec<StepRecord>,
+}
+
+/// Result of one top-level step, or one branch of a parallel block
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StepRecord {
+    /// 1-based index in the task's `steps`
+    pub index: usize,
+    /// 1-based branch within a parallel block
+    pub branch: Option<usize>,
+    /// Short description of the step
+    pub label: String,
+    /// Outcome
+    pub status: Status,
+    /// Wall-clock duration in milliseconds (0 if it didn't run)
+    #[serde(default)]
+    pub duration_ms: u64,
+    /// The command that made the step fail, if one exited non-zero
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failure: Option<CommandFailure>,
+}
+
+/// A command that exited non-zero
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommandFailure {
+    /// The command line as echoed
+    pub command: String,
+    /// Working directory relative to the project root
+    pub dir: String,
+    /// Exit code (1 when killed by a signal)
+    pub exit_code: i32,
+}
+
+impl StepRecord {
+    /// Position of the step, e.g. `2` or `2.1` for a parallel branch
+    pub fn position(&self) -> String {
+        match self.branch {
+            Some(branch) => format!("{}.{}", self.index, branch),
+            None => self.index.to_string(),
+        }
+    }
+}
+
+impl TaskRecord {
+    /// Whether the given step should run when rerunning this task
+    pub fn should_rerun(&self, index: usize, branch: Option<usize>) -> bool {
+        self.steps
+            .iter()
+            .find(|step| step.index == index && step.branch == branch)
+            .is_none_or(|step| step.status.needs_rerun())
+    }
+}
+
+impl RunRecord {
+    /// Create a record for tasks that just finished
+    pub fn new(config_hash: String, tasks: Vec<TaskRecord>) -> Self {
+        let finished_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        Self {
+            config_hash,
+            finished_at,
+            tasks,
+        }
+    }
+}
+
+/// Path of the last-run file
+pub fn last_run_path(project_root: &Path) -> PathBuf {
+    project_root.join(config::CACHE_DIR).join(LAST_RUN_FILE)
+}
+
+/// Hash of rnr.yaml, used to detect that recorded step indices are stale
+pub fn config_hash(project_root: &Path) -> Result<String> {
+    checksum::sha256_file(&project_root.join(config::CONFIG_FILE))
+}
+
+/// Load the last run, if one was recorded
+pub fn load(project_root: &Path) -> Result<Option<RunRecord>> {
+    let path = last_run_path(project_root);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content =
+        fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let record = serde_json::from_str(&content)
+        .with_context(|| format!("Failed to parse {}", path.display()))?;
+    Ok(Some(record))
+}
+
+/// Record a run as the latest
+pub fn save(project_root: &Path, record: &RunRecord) -> Result<()> {
+    let path = last_run_path(project_root);
+    config::create_cache_dir(project_root)?;
+    let content = serde_json::to_string_pretty(record)?;
+    fs::write(&path, content).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step(index: usize, branch: Option<usize>, status: Status) -> StepRecord {
+        StepRecord {
+            index,
+            branch,
+            label: format!("step {}", index),
+            status,
+            duration_ms: 0,
+            failure: None,
+        }
+    }
+
+    fn task_record() -> TaskRecord {
+        TaskRecord {
+            name: "ci".to_string(),
+            args: vec!["--fast".to_string()],
+            status: Status::Failed,
+            duration_ms: 1200,
+            error: Some("Command failed with exit code 1".to_string()),
+            steps: vec![
+                step(1, None, Status::Passed),
+                step(2, Some(1), Status::Passed),
+                step(2, Some(2), Status::Failed),
+                step(3, None, Status::NotRun),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_should_rerun() {
+        let record = task_record();
+        assert!(!record.should_rerun(1, None));
+        assert!(!record.should_rerun(2, Some(1)));
+        assert!(record.should_rerun(2, Some(2)));
+        assert!(record.should_rerun(3, None));
+        // Unknown steps are rerun to be safe
+        assert!(record.should_rerun(4, None));
+    }
+
+    #[test]
+    fn test_step_position() {
+        assert_eq!(step(3, None, Status::Passed).position(), "3");
+        assert_eq!(step(2, Some(1), Status::Passed).position(), "2.1");
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let temp = tempfile::tempdir().unwrap();
+        assert!(load(temp.path()).unwrap().is_none());
+
+        let record = RunRecord::new("abc".to_string(), vec![task_record()]);
+        save(temp.path(), &record).unwrap();
+        assert_eq!(load(temp.path()).unwrap(), Some(record));
+    }
+
+    #[test]
+    fn test_status_serialization() {
+        assert_eq!(
+            serde_json::to_string(&Status::NotRun).unwrap(),
+            "\"not_run\""
+        );
+    }
+}
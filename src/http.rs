@@ -0,0 +1,211 @@
+//! Shared HTTP client for network features
+//!
+//! Every request has a connect timeout and an overall timeout, so a
+//! black-holed network fails instead of hanging, and binary downloads are
+//! capped in size so a misbehaving server can't fill the disk or memory.
+
+use anyhow::{Context, Result};
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use std::io::Read;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use crate::platform::format_size;
+
+/// User agent sent with every request
+pub const USER_AGENT: &str = "rnr-cli";
+
+/// Time allowed to establish a connection
+pub const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Time allowed for a whole download
+pub const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Time allowed for release-metadata API calls
+pub const METADATA_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Largest rnr binary we'll accept (real ones are well under 10 MB)
+pub const MAX_BINARY_SIZE: u64 = 100 * 1024 * 1024;
+
+/// Overall timeout set with `--timeout`
+static TIMEOUT_OVERRIDE: OnceLock<Duration> = OnceLock::new();
+
+/// Override the overall timeout for every request in this process
+pub fn set_timeout(timeout: Duration) {
+    let _ = TIMEOUT_OVERRIDE.set(timeout);
+}
+
+/// Build an HTTP client with rnr's defaults, for downloads.
+/// Proxy settings are taken from the standard `HTTPS_PROXY`/`HTTP_PROXY` variables.
+pub fn client() -> Result<Client> {
+    build(CONNECT_TIMEOUT, timeout_or(DOWNLOAD_TIMEOUT))
+}
+
+/// Client for small API calls, with a shorter overall timeout
+pub fn metadata_client() -> Result<Client> {
+    build(CONNECT_TIMEOUT, timeout_or(METADATA_TIMEOUT))
+}
+
+fn timeout_or(default: Duration) -> Duration {
+    TIMEOUT_OVERRIDE.get().copied().unwrap_or(default)
+}
+
+fn build(connect_timeout: Duration, timeout: Duration) -> Result<Client> {
+    Client::builder()
+        .user_agent(USER_AGENT)
+        .connect_timeout(connect_timeout)
+        .timeout(timeout)
+        .build()
+        .context("Failed to create HTTP client")
+}
+
+/// Send a request, turning timeouts and connection failures into clear errors
+pub fn send(request: RequestBuilder, url: &str) -> Result<Response> {
+    request.send().map_err(|e| request_error(e, url))
+}
+
+/// Explain a failed request in terms of what the user can do about it
+pub fn request_error(err: reqwest::Error, url: &str) -> anyhow::Error {
+    if err.is_timeout() {
+        anyhow::anyhow!(
+            "Request to {} timed out. Check your network connection, or pass --timeout <SECS> to allow more time.",
+            url
+        )
+    } else if err.is_connect() {
+        anyhow::Error::new(err).context(format!("Could not connect to {}", url))
+    } else {
+        anyhow::Error::new(err).context(format!("Request to {} failed", url))
+    }
+}
+
+/// Read a response body, failing once it exceeds `max_size`.
+/// `progress` is called with the bytes read so far and the expected total, if known.
+pub fn read_limited(
+    mut response: Response,
+    url: &str,
+    max_size: u64,
+    progress: &mut dyn FnMut(u64, Option<u64>),
+) -> Result<Vec<u8>> {
+    let too_large = || {
+        anyhow::anyhow!(
+            "Download of {} is larger than the {} limit; the server may be misbehaving",
+            url,
+            format_size(max_size)
+        )
+    };
+
+    if response.content_length().is_some_and(|len| len > max_size) {
+        return Err(too_large());
+    }
+
+    let total = response.content_length();
+    let mut bytes = Vec::new();
+    let mut buf = [0u8; 64 * 1024];
+    loop {
+        let n = response.read(&mut buf).map_err(|e| read_error(e, url))?;
+        if n == 0 {
+            break;
+        }
+        bytes.extend_from_slice(&buf[..n]);
+        if bytes.len() as u64 > max_size {
+            return Err(too_large());
+        }
+        progress(bytes.len() as u64, total);
+    }
+    Ok(bytes)
+}
+
+/// Explain a failure while reading a response body
+fn read_error(err: std::io::Error, url: &str) -> anyhow::Error {
+    let timed_out = err
+        .get_ref()
+        .and_then(|e| e.downcast_ref::<reqwest::Error>())
+        .is_some_and(|e| e.is_timeout());
+    if timed_out {
+        anyhow::anyhow!(
+            "Download of {} timed out. Pass --timeout <SECS> to allow more time.",
+            url
+        )
+    } else {
+        anyhow::Error::new(err).context(format!("Failed to read response from {}", url))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{respond, TestServer};
+    use anyhow::bail;
+    use std::io::Write;
+    use std::net::TcpListener;
+
+    fn get_bytes(client: &Client, url: &str, max_size: u64) -> Result<Vec<u8>> {
+        let response = send(client.get(url), url)?;
+        if !response.status().is_success() {
+            bail!("HTTP {}", response.status().as_u16());
+        }
+        read_limited(response, url, max_size, &mut |_, _| {})
+    }
+
+    fn test_client(timeout: Duration) -> Client {
+        build(Duration::from_secs(2), timeout).unwrap()
+    }
+
+    #[test]
+    fn test_get_bytes() {
+        let server = TestServer::start(|_, stream| respond(stream, "200 OK", &[], b"abc"));
+        let bytes = get_bytes(&test_client(Duration::from_secs(5)), &server.url, 10).unwrap();
+        assert_eq!(bytes, b"abc");
+    }
+
+    #[test]
+    fn test_http_error_status() {
+        let server = TestServer::start(|_, stream| respond(stream, "404 Not Found", &[], b""));
+        let err = get_bytes(&test_client(Duration::from_secs(5)), &server.url, 10).unwrap_err();
+        assert!(err.to_string().contains("HTTP 404"), "{}", err);
+    }
+
+    #[test]
+    fn test_size_cap_from_content_length() {
+        let server = TestServer::start(|_, mut stream| {
+            let _ = stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2000\r\n\r\n");
+        });
+        let err = get_bytes(&test_client(Duration::from_secs(5)), &server.url, 1000).unwrap_err();
+        assert!(err.to_string().contains("larger than"), "{}", err);
+    }
+
+    #[test]
+    fn test_size_cap_without_content_length() {
+        let server = TestServer::start(|_, mut stream| {
+            let _ = stream.write_all(b"HTTP/1.1 200 OK\r\nConnection: close\r\n\r\n");
+            let _ = stream.write_all(&[b'x'; 2000]);
+        });
+        let err = get_bytes(&test_client(Duration::from_secs(5)), &server.url, 1000).unwrap_err();
+        assert!(err.to_string().contains("larger than"), "{}", err);
+    }
+
+    #[test]
+    fn test_timeout_error() {
+        let server = TestServer::start(|_, stream| {
+            // Never answer
+            std::thread::sleep(Duration::from_secs(2));
+            drop(stream);
+        });
+        let err = get_bytes(&test_client(Duration::from_millis(200)), &server.url, 10).unwrap_err();
+        assert!(err.to_string().contains("timed out"), "{}", err);
+        assert!(err.to_string().contains("--timeout"));
+    }
+
+    #[test]
+    fn test_connect_error() {
+        // Bind and drop to find a port nothing listens on
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let url = format!("http://127.0.0.1:{}", port);
+        let err = get_bytes(&test_client(Duration::from_secs(5)), &url, 10).unwrap_err();
+        assert!(err.to_string().contains("Could not connect"), "{}", err);
+    }
+}
@@ -0,0 +1,402 @@
+//! `${...}` interpolation for commands and templates
+//!
+//! Placeholders take the form `${namespace.key}`. Only known namespaces are
+//! interpolated, so shell syntax such as `${HOME}` or `${1:-default}` passes
+//! through untouched. Write `$${` to produce a literal `${`.
+//!
+//! A scope given a time with [`Scope::with_time`] also renders
+//! `${date(FORMAT)}`, e.g. `${date(%Y-%m-%d)}`, in UTC.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Namespaces understood by the interpolation engine
+const NAMESPACES: &[&str] = &["args", "bins", "env", "foreach", "git"];
+
+/// Error listing placeholders that could not be resolved
+#[derive(Debug, PartialEq, Eq)]
+pub struct Unresolved(pub Vec<String>);
+
+impl std::fmt::Display for Unresolved {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let names: Vec<String> = self.0.iter().map(|k| format!("${{{}}}", k)).collect();
+        write!(f, "Unresolved placeholder(s): {}", names.join(", "))
+    }
+}
+
+impl std::error::Error for Unresolved {}
+
+/// Values available to interpolation
+pub struct Scope {
+    /// Task-level environment, layered over the process environment
+    env: HashMap<String, String>,
+    /// Values of the task's declared arguments
+    args: HashMap<String, String>,
+    /// Absolute paths of the project's bins
+    bins: HashMap<String, String>,
+    /// Value of the `foreach` iteration running, by its `as` name
+    foreach: HashMap<String, String>,
+    /// Directory used for `git.*` lookups
+    root: PathBuf,
+    /// Time `${date(...)}` renders, if dates are allowed
+    now: Option<SystemTime>,
+}
+
+impl Scope {
+    /// Create a scope for the given project root and task environment
+    pub fn new(root: &Path, env: &HashMap<String, String>) -> Self {
+        Self {
+            env: env.clone(),
+            args: HashMap::new(),
+            bins: HashMap::new(),
+            foreach: HashMap::new(),
+            root: root.to_path_buf(),
+            now: None,
+        }
+    }
+
+    /// Add the task's argument values, exposed as `${args.<name>}`
+    pub fn with_args(mut self, args: &HashMap<String, String>) -> Self {
+        self.args = args.clone();
+        self
+    }
+
+    /// Add the project's bins, exposed as `${bins.<name>}`
+    pub fn with_bins(mut self, bins: HashMap<String, String>) -> Self {
+        self.bins = bins;
+        self
+    }
+
+    /// Add the value of the `foreach` iteration running, exposed as
+    /// `${foreach.<as>}`
+    pub fn with_foreach(mut self, foreach: &HashMap<String, String>) -> Self {
+        self.foreach = foreach.clone();
+        self
+    }
+
+    /// Look up a fully-qualified key such as `env.HOME` or `git.sha`
+    pub fn lookup(&self, key: &str) -> Option<String> {
+        let (namespace, name) = key.split_once('.')?;
+        match namespace {
+            "args" => self.args.get(name).cloned(),
+            "bins" => self.bins.get(name).cloned(),
+            "foreach" => self.foreach.get(name).cloned(),
+            "env" => self
+                .env
+                .get(name)
+                .cloned()
+                .or_else(|| std::env::var(name).ok()),
+            "git" => git_value(&self.root, name),
+            _ => None,
+        }
+    }
+
+    /// Render `${date(FORMAT)}` as `now`
+    pub fn with_time(mut self, now: SystemTime) -> Self {
+        self.now = Some(now);
+        self
+    }
+
+    /// Interpolate a string against this scope
+    pub fn render(&self, input: &str) -> Result<String, Unresolved> {
+        match self.now {
+            Some(now) => render(&render_dates(input, now), |key| self.lookup(key)),
+            None => render(input, |key| self.lookup(key)),
+        }
+    }
+}
+
+/// Replace each `${date(FORMAT)}` in `input` with `now` in that format,
+/// leaving escaped `$${date(...)}` for [`render`] to unescape
+fn render_dates(input: &str, now: SystemTime) -> String {
+    const START: &str = "${date(";
+    let mut output = String::with_capacity(input.len());
+    let mut rest = input;
+    while let Some(pos) = rest.find(START) {
+        let after = &rest[pos + START.len()..];
+        let escaped = rest[..pos].ends_with('$');
+        match after.find(")}") {
+            Some(end) if !escaped => {
+                output.push_str(&rest[..pos]);
+                output.push_str(&format_date(&after[..end], now));
+                rest = &after[end + 2..];
+            }
+            _ => {
+                output.push_str(&rest[..pos + START.len()]);
+                rest = after;
+            }
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+/// `now` in UTC, formatted with `%Y`, `%m`, `%d`, `%H`, `%M`, `%S`, and `%%`;
+/// anything else is kept as written
+pub fn format_date(format: &str, now: SystemTime) -> String {
+    let seconds = now.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (year, month, day) = civil_date(seconds / 86_400);
+    let time = seconds % 86_400;
+    let mut output = String::with_capacity(format.len() + 8);
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            output.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => output.push_str(&format!("{:04}", year)),
+            Some('m') => output.push_str(&format!("{:02}", month)),
+            Some('d') => output.push_str(&format!("{:02}", day)),
+            Some('H') => output.push_str(&format!("{:02}", time / 3600)),
+            Some('M') => output.push_str(&format!("{:02}", time / 60 % 60)),
+            Some('S') => output.push_str(&format!("{:02}", time % 60)),
+            Some('%') => output.push('%'),
+            Some(other) => {
+                output.push('%');
+                output.push(other);
+            }
+            None => output.push('%'),
+        }
+    }
+    output
+}
+
+/// Year, month, and day of the day `days` after 1970-01-01
+fn civil_date(days: u64) -> (u64, u64, u64) {
+    // Howard Hinnant's days-to-civil, counting from 0000-03-01
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = era * 400 + year_of_era + u64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Interpolate `${namespace.key}` placeholders using `lookup`
+pub fn render(input: &str, lookup: impl Fn(&str) -> Option<String>) -> Result<String, Unresolved> {
+    let mut output = String::with_capacity(input.len());
+    let mut unresolved = Vec::new();
+    let mut rest = input;
+
+    while let Some(pos) = rest.find("${") {
+        // `$${` escapes a literal `${`
+        if rest[..pos].ends_with('$') {
+            output.push_str(&rest[..pos - 1]);
+            output.push_str("${");
+            rest = &rest[pos + 2..];
+            continue;
+        }
+
+        output.push_str(&rest[..pos]);
+        let after = &rest[pos + 2..];
+        let Some(end) = after.find('}') else {
+            output.push_str(&rest[pos..]);
+            rest = "";
+            break;
+        };
+
+        let key = after[..end].trim();
+        if is_placeholder(key) {
+            match lookup(key) {
+                Some(value) => output.push_str(&value),
+                None => {
+                    if !unresolved.iter().any(|k| k == key) {
+                        unresolved.push(key.to_string());
+                    }
+                }
+            }
+        } else {
+            output.push_str(&rest[pos..pos + 2 + end + 1]);
+        }
+        rest = &after[end + 1..];
+    }
+    output.push_str(rest);
+
+    if unresolved.is_empty() {
+        Ok(output)
+    } else {
+        Err(Unresolved(unresolved))
+    }
+}
+
+/// Check whether a key belongs to a known namespace
+fn is_placeholder(key: &str) -> bool {
+    match key.split_once('.') {
+        Some((namespace, name)) => !name.is_empty() && NAMESPACES.contains(&namespace),
+        None => false,
+    }
+}
+
+/// Resolve a `git.*` builtin by running git in `root`
+fn git_value(root: &Path, name: &str) -> Option<String> {
+    let args: &[&str] = match name {
+        "sha" => &["rev-parse", "HEAD"],
+        "short_sha" => &["rev-parse", "--short", "HEAD"],
+        "branch" => &["rev-parse", "--abbrev-ref", "HEAD"],
+        "tag" => &["describe", "--tags", "--abbrev=0"],
+        _ => return None,
+    };
+
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(root)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let value = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (!value.is_empty()).then_some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(key: &str) -> Option<String> {
+        match key {
+            "env.NAME" => Some("world".to_string()),
+            "env.EMPTY" => Some(String::new()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_render_replaces_known_placeholders() {
+        assert_eq!(
+            render("hello ${env.NAME}!", lookup).unwrap(),
+            "hello world!"
+        );
+        assert_eq!(render("[${ env.EMPTY }]", lookup).unwrap(), "[]");
+    }
+
+    #[test]
+    fn test_render_leaves_shell_syntax_alone() {
+        let input = "echo ${HOME} ${1:-default} $PATH";
+        assert_eq!(render(input, lookup).unwrap(), input);
+    }
+
+    #[test]
+    fn test_render_leaves_unknown_namespaces_alone() {
+        let input = "echo ${foo.bar}";
+        assert_eq!(render(input, lookup).unwrap(), input);
+    }
+
+    #[test]
+    fn test_render_escape() {
+        assert_eq!(
+            render("literal $${env.NAME}", lookup).unwrap(),
+            "literal ${env.NAME}"
+        );
+    }
+
+    #[test]
+    fn test_render_unterminated() {
+        assert_eq!(
+            render("oops ${env.NAME", lookup).unwrap(),
+            "oops ${env.NAME"
+        );
+    }
+
+    #[test]
+    fn test_render_reports_all_unresolved() {
+        let err = render("${env.A} ${env.NAME} ${git.nope} ${env.A}", lookup).unwrap_err();
+        assert_eq!(err.0, vec!["env.A".to_string(), "git.nope".to_string()]);
+        assert_eq!(
+            err.to_string(),
+            "Unresolved placeholder(s): ${env.A}, ${git.nope}"
+        );
+    }
+
+    #[test]
+    fn test_scope_args() {
+        let mut args = HashMap::new();
+        args.insert("version".to_string(), "1.2.3".to_string());
+        let scope = Scope::new(Path::new("."), &HashMap::new()).with_args(&args);
+        assert_eq!(scope.render("v${args.version}").unwrap(), "v1.2.3");
+        assert!(scope.render("${args.missing}").is_err());
+    }
+
+    #[test]
+    fn test_scope_foreach() {
+        let foreach = HashMap::from([("DB".to_string(), "orders".to_string())]);
+        let scope = Scope::new(Path::new("."), &HashMap::new()).with_foreach(&foreach);
+        assert_eq!(
+            scope.render("migrate ${foreach.DB}").unwrap(),
+            "migrate orders"
+        );
+        assert!(scope.render("${foreach.TABLE}").is_err());
+    }
+
+    #[test]
+    fn test_scope_bins() {
+        let bins = HashMap::from([("dprint".to_string(), "/p/.tools/dprint".to_string())]);
+        let scope = Scope::new(Path::new("."), &HashMap::new()).with_bins(bins);
+        assert_eq!(
+            scope.render("${bins.dprint} fmt").unwrap(),
+            "/p/.tools/dprint fmt"
+        );
+        assert!(scope.render("${bins.cog}").is_err());
+    }
+
+    #[test]
+    fn test_format_date() {
+        use std::time::Duration;
+
+        let at = |seconds| UNIX_EPOCH + Duration::from_secs(seconds);
+        assert_eq!(
+            format_date("%Y-%m-%d %H:%M:%S", at(0)),
+            "1970-01-01 00:00:00"
+        );
+        // 2024-02-29 23:59:59, a leap day
+        assert_eq!(
+            format_date("%Y%m%d-%H%M%S", at(1_709_251_199)),
+            "20240229-235959"
+        );
+        assert_eq!(format_date("%Y-%m-%d", at(1_709_251_200)), "2024-03-01");
+        assert_eq!(format_date("100%% %q %", at(0)), "100% %q %");
+    }
+
+    #[test]
+    fn test_scope_dates() {
+        use std::time::Duration;
+
+        let now = UNIX_EPOCH + Duration::from_secs(1_760_486_400);
+        let mut env = HashMap::new();
+        env.insert("NAME".to_string(), "build".to_string());
+        let scope = Scope::new(Path::new("."), &env).with_time(now);
+        assert_eq!(
+            scope
+                .render("logs/${env.NAME}-${date(%Y-%m-%d)}.log")
+                .unwrap(),
+            "logs/build-2025-10-15.log"
+        );
+        assert_eq!(
+            scope.render("$${date(%Y)} ${date(%Y").unwrap(),
+            "${date(%Y)} ${date(%Y"
+        );
+        // Without a time, dates aren't placeholders at all
+        let scope = Scope::new(Path::new("."), &env);
+        assert_eq!(scope.render("${date(%Y)}").unwrap(), "${date(%Y)}");
+    }
+
+    #[test]
+    fn test_scope_task_env_overrides_process_env() {
+        let mut env = HashMap::new();
+        env.insert("PATH".to_string(), "overridden".to_string());
+        let scope = Scope::new(Path::new("."), &env);
+        assert_eq!(scope.lookup("env.PATH"), Some("overridden".to_string()));
+    }
+}
@@ -0,0 +1,107 @@
+//! rnr as a library
+//!
+//! The `rnr` binary is a thin layer over this crate. Other programs can load a
+//! project's task definitions and run tasks without shelling out:
+//!
+//! ```
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let dir = tempfile::tempdir()?;
+//! std::fs::write(
+//!     dir.path().join("rnr.yaml"),
+//!     "build:\n  description: Build it\n  steps:\n    - cmd: echo compiling\n    - cmd: echo linking\n",
+//! )?;
+//!
+//! let config = rnr::Config::load_from(&dir.path().join("rnr.yaml"))?;
+//! assert_eq!(config.task_names(), ["build"]);
+//!
+//! let report = rnr::Runner::new(dir.path())
+//!     .output(std::io::sink())
+//!     .run("build")?;
+//! assert!(report.success());
+//! assert_eq!(report.steps.len(), 2);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Only the items re-exported here, and the [`config`] module, are a stable
+//! API. The hidden modules exist for the binary and may change at any time.
+
+mod api;
+#[cfg(feature = "network")]
+mod archive;
+mod args;
+mod auto_deps;
+mod bins;
+#[doc(hidden)]
+pub mod build_info;
+mod builtins;
+#[doc(hidden)]
+pub mod chaos;
+mod checkout;
+mod checksum;
+mod choose;
+#[doc(hidden)]
+pub mod cli;
+#[doc(hidden)]
+pub mod commands;
+pub mod config;
+mod context;
+#[cfg(not(feature = "network"))]
+mod delegate;
+mod durations;
+mod env_report;
+mod error;
+#[doc(hidden)]
+pub mod events;
+mod failure_hook;
+mod formatter;
+#[cfg(test)]
+mod golden;
+mod guards;
+mod history;
+#[cfg(feature = "network")]
+#[doc(hidden)]
+pub mod http;
+mod interpolate;
+mod limits;
+#[doc(hidden)]
+pub mod nesting;
+#[cfg(feature = "network")]
+mod net;
+mod output_buffer;
+mod output_filter;
+mod permissions;
+#[doc(hidden)]
+pub mod pin;
+mod plan;
+mod platform;
+#[doc(hidden)]
+pub mod project;
+mod rnr_config;
+#[doc(hidden)]
+pub mod runner;
+mod script_hints;
+mod secrets;
+#[doc(hidden)]
+pub mod self_replace;
+#[doc(hidden)]
+pub mod settings;
+mod shared_dir;
+mod slots;
+mod source;
+pub mod step_filter;
+mod suggest;
+mod task_log;
+#[doc(hidden)]
+pub mod termination;
+#[cfg(all(test, feature = "network"))]
+mod test_server;
+#[doc(hidden)]
+pub mod trace;
+#[doc(hidden)]
+pub mod ui;
+mod version;
+
+pub use api::{RunReport, Runner, Status, StepResult};
+pub use config::{Config, TaskDef};
+pub use error::{Error, Result};
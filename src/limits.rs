@@ -0,0 +1,245 @@
+//! Limits that keep a hostile or broken rnr.yaml from hanging rnr before any
+//! task runs, e.g. an anchor "billion laughs" in a repository just cloned
+
+use serde::de::{self, DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
+use std::cell::Cell;
+use std::fmt;
+use std::path::Path;
+
+use crate::config::{Config, TaskDef};
+
+/// Environment variable overriding the largest rnr.yaml loaded, in bytes
+pub const MAX_SIZE_ENV: &str = "RNR_MAX_CONFIG_SIZE";
+
+/// Largest rnr.yaml loaded unless `RNR_MAX_CONFIG_SIZE` says otherwise
+pub const DEFAULT_MAX_SIZE: u64 = 1024 * 1024;
+
+/// Most YAML nodes a document may have once aliases are expanded
+pub const MAX_NODES: usize = 1_000_000;
+
+/// Most tasks a config may define
+pub const MAX_TASKS: usize = 10_000;
+
+/// Most steps a config may define, counting each parallel branch
+pub const MAX_STEPS: usize = 100_000;
+
+/// The largest rnr.yaml to load, in bytes
+fn max_size() -> u64 {
+    std::env::var(MAX_SIZE_ENV)
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or(DEFAULT_MAX_SIZE)
+}
+
+/// Refuse a file over the size limit before reading it
+pub fn check_size(path: &Path) -> std::io::Result<Result<(), String>> {
+    let size = path.metadata()?.len();
+    let max = max_size();
+    Ok(if size > max {
+        Err(format!(
+            "it is {} bytes, over the limit of {} bytes (set {} to raise it)",
+            size, max, MAX_SIZE_ENV
+        ))
+    } else {
+        Ok(())
+    })
+}
+
+/// Refuse a document whose aliases expand to more than `max` nodes. The
+/// count stops at the limit, so an anchor bomb fails fast instead of being
+/// built in memory. Syntax errors are left for the real parse to report.
+pub fn check_nodes(content: &str, max: usize) -> Result<(), String> {
+    let counter = NodeCounter {
+        remaining: Cell::new(max),
+    };
+    let result = counter.deserialize(serde_yaml::Deserializer::from_str(content));
+    match result {
+        Err(_) if counter.remaining.get() == 0 => Err(format!(
+            "its anchors and aliases expand to more than {} nodes",
+            max
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Refuse a config with more tasks or steps than rnr should ever need
+pub fn check_counts(config: &Config) -> Result<(), String> {
+    let tasks = config.tasks.len();
+    if tasks > MAX_TASKS {
+        return Err(format!(
+            "it defines {} tasks, over the limit of {}",
+            tasks, MAX_TASKS
+        ));
+    }
+    let steps: usize = config
+        .tasks
+        .values()
+        .filter_map(|task| match task {
+            TaskDef::Full(task) => task.steps.as_ref(),
+            TaskDef::Shorthand(_) => None,
+        })
+        .flatten()
+        .map(|step| step.step_defs().len())
+        .sum();
+    if steps > MAX_STEPS {
+        return Err(format!(
+            "it defines {} steps, over the limit of {}",
+            steps, MAX_STEPS
+        ));
+    }
+    Ok(())
+}
+
+/// Walks a document without building it, counting nodes against a budget
+struct NodeCounter {
+    remaining: Cell<usize>,
+}
+
+impl NodeCounter {
+    /// Spend one node of the budget
+    fn count<E: de::Error>(&self) -> Result<(), E> {
+        match self.remaining.get() {
+            0 => Err(E::custom("node limit exceeded")),
+            n => {
+                self.remaining.set(n - 1);
+                Ok(())
+            }
+        }
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for &NodeCounter {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for &NodeCounter {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("any YAML value")
+    }
+
+    fn visit_bool<E: de::Error>(self, _: bool) -> Result<(), E> {
+        self.count()
+    }
+
+    fn visit_i64<E: de::Error>(self, _: i64) -> Result<(), E> {
+        self.count()
+    }
+
+    fn visit_u64<E: de::Error>(self, _: u64) -> Result<(), E> {
+        self.count()
+    }
+
+    fn visit_f64<E: de::Error>(self, _: f64) -> Result<(), E> {
+        self.count()
+    }
+
+    fn visit_str<E: de::Error>(self, _: &str) -> Result<(), E> {
+        self.count()
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<(), E> {
+        self.count()
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<(), E> {
+        self.count()
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        self.deserialize(deserializer)
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        self.deserialize(deserializer)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        self.count()?;
+        while seq.next_element_seed(self)?.is_some() {}
+        Ok(())
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        self.count()?;
+        while map.next_entry_seed(self, self)?.is_some() {}
+        Ok(())
+    }
+
+    fn visit_enum<A: de::EnumAccess<'de>>(self, data: A) -> Result<(), A::Error> {
+        // A tagged value (`!tag value`): count the value under the tag
+        use de::VariantAccess;
+        let ((), variant) = data.variant_seed(self)?;
+        variant.newtype_variant_seed(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    /// Nested anchors, each level aliasing the previous one `fanout` times
+    fn anchor_bomb(levels: usize, fanout: usize, base: usize) -> String {
+        let mut yaml = format!("a0: &a0 [{}]\n", vec!["lol"; base].join(", "));
+        for level in 1..=levels {
+            let alias = format!("*a{}", level - 1);
+            yaml.push_str(&format!(
+                "a{}: &a{} [{}]\n",
+                level,
+                level,
+                vec![alias; fanout].join(", ")
+            ));
+        }
+        yaml.push_str("build: echo hi\n");
+        yaml
+    }
+
+    #[test]
+    fn test_anchor_bomb_fails_fast() {
+        let started = Instant::now();
+        let error = check_nodes(&anchor_bomb(5, 9, 5000), MAX_NODES).unwrap_err();
+        assert_eq!(
+            error,
+            "its anchors and aliases expand to more than 1000000 nodes"
+        );
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_check_nodes() {
+        let yaml =
+            "build:\n  cmd: echo hi\n  env: &env\n    A: '1'\nci:\n  env: *env\n  cmd: echo ci\n";
+        // 17 nodes once `*env` is expanded
+        assert!(check_nodes(yaml, 17).is_ok());
+        assert!(check_nodes(yaml, 16).is_err());
+        // Syntax errors are for the real parse to report
+        assert!(check_nodes("build: [unclosed", 100).is_ok());
+    }
+
+    #[test]
+    fn test_check_counts() {
+        let mut yaml = String::new();
+        for i in 0..=MAX_TASKS {
+            yaml.push_str(&format!("t{}: echo {}\n", i, i));
+        }
+        let config: Config = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(
+            check_counts(&config).unwrap_err(),
+            "it defines 10001 tasks, over the limit of 10000"
+        );
+
+        let steps = "    - cmd: echo hi\n".repeat(MAX_STEPS / 2 + 1);
+        let yaml = format!("a:\n  steps:\n{}b:\n  steps:\n{}", steps, steps);
+        let config: Config = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(
+            check_counts(&config).unwrap_err(),
+            "it defines 100002 steps, over the limit of 100000"
+        );
+    }
+}
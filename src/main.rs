@@ -1,31 +1,220 @@
-mod cli;
-mod commands;
-mod config;
-mod platform;
-mod rnr_config;
-mod runner;
-
 use anyhow::Result;
 use clap::Parser;
-use cli::{Cli, Command};
+use rnr::chaos::Chaos;
+use rnr::cli::{self, Cli, Command};
+use rnr::config::{self, ConfigCache, CONFIG_FILE};
+use rnr::events::EventSink;
+#[cfg(feature = "network")]
+use rnr::http;
+use rnr::nesting::Nesting;
+use rnr::project::Project;
+use rnr::settings::{ColorChoice, Layers, Settings};
+use rnr::step_filter::StepFilter;
+use rnr::ui::Ui;
+use rnr::{build_info, commands, pin, runner, self_replace, termination, trace};
 
-fn main() -> Result<()> {
+fn main() {
     let cli = Cli::parse();
+    let settings = match project_dir(&cli).and_then(|()| Layers::load(&cli)?.resolve(&cli)) {
+        Ok(settings) => settings,
+        Err(e) => {
+            Ui::new(console::colors_enabled(), cli.quiet).error(format!("{:?}", e));
+            std::process::exit(1);
+        }
+    };
+    let ui = ui_for(&settings, cli.quiet);
+    for warning in &settings.warnings {
+        ui.warn(warning);
+    }
+    let grace = cli.term_grace.map(std::time::Duration::from_secs);
+    termination::install(grace.unwrap_or(termination::DEFAULT_GRACE));
+    if let Err(e) = run(cli, &settings, &ui) {
+        // What a cancelled run's commands died of is beside the point
+        if termination::requested() {
+            ui.error(termination::Cancelled.to_string());
+            std::process::exit(termination::EXIT_CODE);
+        }
+        ui.error(format!("{:?}", e));
+        std::process::exit(1);
+    }
+}
+
+/// Pin the project to `--project-dir`, if given, before anything looks for it
+fn project_dir(cli: &Cli) -> Result<()> {
+    if let Some(dir) = &cli.project_dir {
+        if !dir.is_dir() {
+            anyhow::bail!("--project-dir {} is not a directory", dir.display());
+        }
+        config::set_project_dir(std::path::absolute(dir)?);
+    }
+    Ok(())
+}
+
+/// Where output goes and how it looks, given the settings
+fn ui_for(settings: &Settings, quiet: bool) -> Ui {
+    match settings.color.value {
+        ColorChoice::Auto => {}
+        choice => {
+            console::set_colors_enabled(choice == ColorChoice::Always);
+            console::set_colors_enabled_stderr(choice == ColorChoice::Always);
+        }
+    }
+    let mut ui = Ui::new(console::colors_enabled(), quiet);
+    if settings.output.value.is_plain() {
+        // Prompts and anything else styled by `console` go colorless too
+        console::set_colors_enabled(false);
+        console::set_colors_enabled_stderr(false);
+        ui = ui.plain();
+    }
+    if settings.timestamps.value {
+        ui = ui.with_timestamps();
+    }
+    ui
+}
 
-    match cli.command {
-        Some(Command::Init(args)) => commands::init::run(&args)?,
-        Some(Command::Upgrade) => commands::upgrade::run()?,
+/// Dispatch the parsed command line
+fn run(mut cli: Cli, settings: &Settings, ui: &Ui) -> Result<()> {
+    self_replace::cleanup_old_binaries();
+    #[cfg(feature = "network")]
+    if let Some(secs) = cli.timeout {
+        http::set_timeout(std::time::Duration::from_secs(secs));
+    }
+
+    // A global rnr hands over to the version the project pins, except for
+    // the commands that manage or inspect the project's binaries
+    let manages_binaries = matches!(
+        cli.command,
+        Some(Command::Init(_) | Command::Upgrade(_) | Command::Version(_) | Command::Verify)
+    );
+    if !manages_binaries {
+        pin::run_pinned(ui);
+    }
+
+    if cli.version {
+        match cli.verbose {
+            true => ui.print(build_info::verbose_version()),
+            false => ui.line(format!("rnr {}", build_info::VERSION)),
+        }
+        return Ok(());
+    }
+
+    // Nothing reads rnr.yaml until a command asks for it
+    let project = Project::new(cli.strict, ui);
+
+    match cli.command.take() {
+        Some(Command::Init(args)) => commands::init::run(&args, ui)?,
+        Some(Command::Upgrade(args)) => commands::upgrade::run(&args, ui)?,
+        Some(Command::Verify) => commands::verify::run(ui)?,
+        Some(Command::Version(args)) => commands::version::run(&args, ui)?,
+        Some(Command::Settings) => commands::settings::run(settings, ui),
+        Some(Command::Env(args)) => commands::env::run(&args, &project, ui)?,
+        Some(Command::Explain(args)) => commands::explain::run(&args, &project, ui)?,
+        Some(Command::Affected(args)) => {
+            if cli.dir.is_some() {
+                anyhow::bail!(
+                    "--dir applies to one invoked task, so it can't be used with 'rnr affected'"
+                );
+            }
+            let mut opts = run_options(&cli, settings, &project, ui)?;
+            opts.dry_run |= args.dry_run;
+            commands::affected::run(&args, &project, &opts)?;
+        }
+        Some(Command::Help(args)) => commands::help::run(&args, &project, ui)?,
+        Some(Command::Last) => commands::last::run(&project, ui)?,
+        Some(Command::Validate) => commands::validate::run(&project, cli.strict, ui)?,
+        Some(Command::Fmt(args)) => commands::fmt::run(&args, &project, ui)?,
+        Some(Command::Add(args)) => commands::add::run(&args, &project, ui)?,
+        Some(Command::Import(args)) => commands::import::run(&args, &project, ui)?,
+        Some(Command::Export(args)) => commands::export::run(&args, &project, ui)?,
+        Some(Command::Docs(args)) => commands::docs::run(&args, ui)?,
+        Some(Command::Run(args)) => {
+            runner::run_task(
+                &args.task,
+                &args.args,
+                &run_options(&cli, settings, &project, ui)?,
+            )?;
+        }
+        Some(Command::RerunFailed) => {
+            runner::rerun_failed(&run_options(&cli, settings, &project, ui)?)?
+        }
         None => {
             if cli.list {
-                commands::list::run()?;
-            } else if let Some(task_name) = cli.task {
-                runner::run_task(&task_name)?;
+                commands::list::run(&project, cli.recursive, cli.wide, cli.sources, ui)?;
+            } else if let Some(task_name) = &cli.task {
+                runner::run_task(
+                    task_name,
+                    &cli.args,
+                    &run_options(&cli, settings, &project, ui)?,
+                )?;
+            } else if let Some((task_name, args)) = cli::env_task()? {
+                runner::run_task(
+                    &task_name,
+                    &args,
+                    &run_options(&cli, settings, &project, ui)?,
+                )?;
             } else {
                 // No task specified, show help or list
-                commands::list::run()?;
+                commands::list::run(&project, false, false, false, ui)?;
             }
         }
     }
 
     Ok(())
 }
+
+/// Runner options from the command-line flags and settings, starting from
+/// the project's already-loaded rnr.yaml
+fn run_options(
+    cli: &Cli,
+    settings: &Settings,
+    project: &Project,
+    ui: &Ui,
+) -> Result<runner::RunOptions> {
+    let root = project.root()?;
+    let configs = ConfigCache::default();
+    configs.insert(&root.join(CONFIG_FILE), project.validated_config()?);
+
+    let events = match cli.events_fd {
+        Some(fd) => Some(EventSink::fd(fd)?),
+        None => cli.events_json.then(EventSink::stderr),
+    };
+    let chaos = Chaos::parse(&cli.chaos)?;
+    chaos.check_ci(cli.chaos_allow_ci)?;
+    chaos.announce(ui);
+    let dir = match &cli.dir {
+        Some(dir) => {
+            let path = root.join(dir);
+            if !path.is_dir() {
+                anyhow::bail!(
+                    "--dir '{}' isn't a directory in the project ({})",
+                    dir.display(),
+                    root.display()
+                );
+            }
+            Some(path)
+        }
+        None => None,
+    };
+    Ok(runner::RunOptions {
+        dry_run: cli.dry_run,
+        diff: cli.diff,
+        fuzzy: cli.fuzzy,
+        trace: trace::Tracer::for_run(cli.trace.clone()),
+        ui: ui.clone(),
+        project_root: Some(root.to_path_buf()),
+        jobs: settings.jobs.value.map(|jobs| jobs as usize),
+        configs,
+        strict: cli.strict,
+        events,
+        verbose: cli.verbose,
+        allow_dirty: cli.allow_dirty,
+        ignore_guards: cli.ignore_guards,
+        no_eta: !settings.eta.value,
+        no_auto_deps: cli.no_auto_deps,
+        steps: StepFilter::new(&cli.only, &cli.skip, cli.steps.as_deref())?,
+        nesting: Nesting::from_env(),
+        chaos,
+        dir,
+        ..Default::default()
+    })
+}
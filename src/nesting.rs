@@ -0,0 +1,269 @@
+//! Telling a run apart from one a task started
+//!
+//! Every command rnr runs gets `RNR_DEPTH` (one more than rnr's own),
+//! `RNR_PARENT_TASK` (the task running it), `RNR_TASK_CHAIN` (the tasks of
+//! every run above it) and `RNR_RUN_ID` (shared by the whole tree of runs).
+//! An rnr started by a task reads them back, so it can leave the failure
+//! summary and global hooks to the run above it, tag its events with the
+//! same run id, and stop a task that keeps invoking itself.
+
+use anyhow::Result;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::SystemTime;
+
+/// How many runs deep this one is (unset or 0 for a run from a shell)
+pub const DEPTH_ENV: &str = "RNR_DEPTH";
+
+/// The task whose command started this run
+pub const PARENT_TASK_ENV: &str = "RNR_PARENT_TASK";
+
+/// The tasks whose commands started the runs above this one, outermost
+/// first, one per line
+pub const TASK_CHAIN_ENV: &str = "RNR_TASK_CHAIN";
+
+/// Identifies the top-level run and every run nested in it
+pub const RUN_ID_ENV: &str = "RNR_RUN_ID";
+
+/// Overrides [`DEFAULT_MAX_DEPTH`]
+pub const MAX_DEPTH_ENV: &str = "RNR_MAX_DEPTH";
+
+/// How deep runs may nest before rnr assumes a task is invoking itself
+pub const DEFAULT_MAX_DEPTH: u32 = 16;
+
+/// Tasks kept from each end of a chain too long to show in full
+const CHAIN_ENDS: usize = 4;
+
+/// Where this run sits in a tree of nested runs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Nesting {
+    /// 0 for a run from a shell, 1 for one a task started, and so on
+    pub depth: u32,
+    /// The task that started this run, if a task did
+    pub parent_task: Option<String>,
+    /// The tasks that started each run above this one, outermost first
+    pub chain: Vec<String>,
+    /// Shared with the run that started this one, or new for a top-level run
+    pub run_id: String,
+    /// Deepest a run may be
+    pub max_depth: u32,
+}
+
+impl Default for Nesting {
+    /// A top-level run with a fresh run id
+    fn default() -> Self {
+        Self {
+            depth: 0,
+            parent_task: None,
+            chain: Vec::new(),
+            run_id: new_run_id(),
+            max_depth: DEFAULT_MAX_DEPTH,
+        }
+    }
+}
+
+impl Nesting {
+    /// This process's place, from the variables the run above it set
+    pub fn from_env() -> Self {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    /// This run's place, reading variables with `var`
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Self {
+        let number = |name| var(name).and_then(|value| value.trim().parse().ok());
+        let top = Self::default();
+        let parent_task = var(PARENT_TASK_ENV).filter(|task| !task.is_empty());
+        let mut chain: Vec<String> = var(TASK_CHAIN_ENV)
+            .unwrap_or_default()
+            .lines()
+            .filter(|task| !task.is_empty())
+            .map(str::to_string)
+            .collect();
+        // Started by an rnr that doesn't pass the chain down
+        if chain.is_empty() {
+            chain.extend(parent_task.clone());
+        }
+        Self {
+            depth: number(DEPTH_ENV).unwrap_or(0),
+            parent_task,
+            chain,
+            run_id: var(RUN_ID_ENV)
+                .filter(|id| !id.is_empty())
+                .unwrap_or(top.run_id),
+            max_depth: number(MAX_DEPTH_ENV).unwrap_or(top.max_depth),
+        }
+    }
+
+    /// Whether a task's command started this run
+    pub fn is_nested(&self) -> bool {
+        self.depth > 0
+    }
+
+    /// Fail if running `task` would nest runs deeper than the limit
+    pub fn check_depth(&self, task: &str) -> Result<()> {
+        if self.depth < self.max_depth {
+            return Ok(());
+        }
+        let chain: Vec<&str> = self
+            .chain
+            .iter()
+            .map(String::as_str)
+            .chain([task])
+            .collect();
+        anyhow::bail!(
+            "rnr is nested {} runs deep ({}), which looks like a task invoking itself; \
+             stopping. Set {} to allow deeper nesting",
+            self.depth,
+            describe_chain(&chain),
+            MAX_DEPTH_ENV
+        )
+    }
+
+    /// Variables for a command that `task` runs
+    pub fn child_env(&self, task: &str) -> [(&'static str, String); 4] {
+        let mut chain = self.chain.join("\n");
+        if !chain.is_empty() {
+            chain.push('\n');
+        }
+        chain.push_str(task);
+        [
+            (DEPTH_ENV, (self.depth + 1).to_string()),
+            (PARENT_TASK_ENV, task.to_string()),
+            (TASK_CHAIN_ENV, chain),
+            (RUN_ID_ENV, self.run_id.clone()),
+        ]
+    }
+}
+
+/// `'a' -> 'b' -> 'c'`, with the middle of a long chain left out
+fn describe_chain(tasks: &[&str]) -> String {
+    let quoted = |tasks: &[&str]| {
+        tasks
+            .iter()
+            .map(|task| format!("'{}'", task))
+            .collect::<Vec<_>>()
+            .join(" -> ")
+    };
+    if tasks.len() <= 2 * CHAIN_ENDS + 1 {
+        return quoted(tasks);
+    }
+    format!(
+        "{} -> ... {} more ... -> {}",
+        quoted(&tasks[..CHAIN_ENDS]),
+        tasks.len() - 2 * CHAIN_ENDS,
+        quoted(&tasks[tasks.len() - CHAIN_ENDS..])
+    )
+}
+
+/// A random version 4 UUID, from the randomly keyed hasher the standard
+/// library seeds per process
+fn new_run_id() -> String {
+    let mut halves = [0u64; 2];
+    for (i, half) in halves.iter_mut().enumerate() {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_usize(i);
+        hasher.write_u32(std::process::id());
+        if let Ok(now) = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+            hasher.write_u128(now.as_nanos());
+        }
+        *half = hasher.finish();
+    }
+    let bytes = ((halves[0] as u128) << 64 | halves[1] as u128) & !(0xf000 << 64 | 0xc << 60)
+        | (0x4000 << 64)
+        | (0x8 << 60);
+    let hex = format!("{:032x}", bytes);
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn nesting(vars: &[(&str, &str)]) -> Nesting {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        Nesting::from_vars(|name| vars.get(name).cloned())
+    }
+
+    #[test]
+    fn test_run_ids_are_uuids() {
+        let id = new_run_id();
+        assert_eq!(id.len(), 36);
+        assert_eq!(&id[14..15], "4");
+        assert!("89ab".contains(&id[19..20]), "{}", id);
+        assert_ne!(id, new_run_id());
+    }
+
+    #[test]
+    fn test_top_level_run() {
+        let top = nesting(&[]);
+        assert!(!top.is_nested());
+        assert_eq!(top.parent_task, None);
+        assert_eq!(top.max_depth, DEFAULT_MAX_DEPTH);
+        top.check_depth("build").unwrap();
+
+        let [depth, parent, chain, run_id] = top.child_env("ci");
+        assert_eq!(depth, (DEPTH_ENV, "1".to_string()));
+        assert_eq!(parent, (PARENT_TASK_ENV, "ci".to_string()));
+        assert_eq!(chain, (TASK_CHAIN_ENV, "ci".to_string()));
+        assert_eq!(run_id, (RUN_ID_ENV, top.run_id.clone()));
+    }
+
+    #[test]
+    fn test_nested_run_keeps_the_run_id() {
+        let nested = nesting(&[
+            (DEPTH_ENV, "2"),
+            (PARENT_TASK_ENV, "ci"),
+            (RUN_ID_ENV, "run-1"),
+        ]);
+        assert!(nested.is_nested());
+        assert_eq!(nested.parent_task.as_deref(), Some("ci"));
+        assert_eq!(nested.child_env("build")[0].1, "3");
+        assert_eq!(nested.child_env("build")[3].1, "run-1");
+        // Without a chain from above, it starts at the parent
+        assert_eq!(nested.chain, ["ci"]);
+        assert_eq!(nested.child_env("build")[2].1, "ci\nbuild");
+
+        // Garbage is a top-level run rather than an error
+        assert_eq!(nesting(&[(DEPTH_ENV, "deep")]).depth, 0);
+    }
+
+    #[test]
+    fn test_depth_limit() {
+        let looping = nesting(&[
+            (DEPTH_ENV, "3"),
+            (MAX_DEPTH_ENV, "3"),
+            (PARENT_TASK_ENV, "test"),
+            (TASK_CHAIN_ENV, "ci\nbuild\ntest"),
+        ]);
+        assert_eq!(
+            looping.check_depth("ci").unwrap_err().to_string(),
+            "rnr is nested 3 runs deep ('ci' -> 'build' -> 'test' -> 'ci'), which looks like \
+             a task invoking itself; stopping. Set RNR_MAX_DEPTH to allow deeper nesting"
+        );
+
+        // A long chain keeps its ends
+        let chain: Vec<String> = (1..=16).map(|n| format!("t{}", n)).collect();
+        let deep = nesting(&[
+            (DEPTH_ENV, "16"),
+            (PARENT_TASK_ENV, "t16"),
+            (TASK_CHAIN_ENV, &chain.join("\n")),
+        ]);
+        assert!(deep.check_depth("t1").unwrap_err().to_string().contains(
+            "('t1' -> 't2' -> 't3' -> 't4' -> ... 9 more ... -> 't14' -> 't15' -> 't16' -> 't1')"
+        ));
+        nesting(&[(DEPTH_ENV, "16"), (MAX_DEPTH_ENV, "32")])
+            .check_depth("ci")
+            .unwrap();
+    }
+}
@@ -0,0 +1,650 @@
+//! Where rnr releases come from
+//!
+//! `ReleaseSource` lists releases and downloads release assets, either from
+//! GitHub or from any server laid out like a GitHub release page (a mirror,
+//! or a local server in tests). Requests that fail with a connection error,
+//! a timeout, or a server error are retried a few times before giving up.
+
+use anyhow::{bail, Context, Result};
+use reqwest::blocking::{Client, Response};
+use serde::Deserialize;
+use std::cmp::Ordering;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::archive;
+use crate::checksum::{self, Sha256Hasher};
+use crate::http;
+use crate::platform::Platform;
+use crate::rnr_config::Channel;
+use crate::self_replace;
+use crate::version::compare_versions;
+
+/// GitHub repository for releases
+pub const GITHUB_REPO: &str = "CodingWithCalvin/rnr.cli";
+
+/// Overrides where releases are listed and downloaded from (mirrors, tests)
+pub const RELEASES_URL_ENV: &str = "RNR_RELEASES_URL";
+
+/// Releases requested per page from the GitHub API
+const RELEASES_PER_PAGE: usize = 100;
+
+/// Upper bound on pages fetched when looking for the newest release
+const MAX_RELEASE_PAGES: usize = 10;
+
+/// Attempts made for each request before giving up
+const MAX_ATTEMPTS: u32 = 3;
+
+/// A release as returned by the GitHub releases API
+#[derive(Debug, Deserialize)]
+pub struct Release {
+    pub tag_name: String,
+    #[serde(default)]
+    pub prerelease: bool,
+    #[serde(default)]
+    pub draft: bool,
+    #[serde(default)]
+    pub assets: Vec<Asset>,
+}
+
+/// A file attached to a release
+#[derive(Debug, Deserialize)]
+pub struct Asset {
+    pub name: String,
+    #[serde(default)]
+    pub size: u64,
+}
+
+impl Release {
+    /// Version without the leading `v`
+    pub fn version(&self) -> &str {
+        self.tag_name.strip_prefix('v').unwrap_or(&self.tag_name)
+    }
+
+    /// Size of the asset downloaded for `platform`: the first of
+    /// [`Platform::release_assets`] the release has
+    pub fn binary_size(&self, platform: Platform) -> Option<u64> {
+        platform.release_assets().iter().find_map(|name| {
+            self.assets
+                .iter()
+                .find(|asset| &asset.name == name)
+                .map(|asset| asset.size)
+        })
+    }
+}
+
+/// Where to list releases and download assets from
+#[derive(Debug, Clone)]
+pub struct ReleaseSource {
+    /// Repository or mirror URL, for messages
+    name: String,
+    /// URL of the release list
+    api_url: String,
+    /// URL that `latest/download/<asset>` and `download/v<version>/<asset>` hang off
+    download_base: String,
+    /// Pause before the first retry; doubles after each attempt
+    retry_delay: Duration,
+}
+
+impl ReleaseSource {
+    /// Releases of a GitHub repository, e.g. `CodingWithCalvin/rnr.cli`
+    pub fn github(repo: &str) -> Self {
+        Self {
+            name: repo.to_string(),
+            api_url: format!("https://api.github.com/repos/{}/releases", repo),
+            download_base: format!("https://github.com/{}/releases", repo),
+            retry_delay: Duration::from_secs(1),
+        }
+    }
+
+    /// Releases served from `base` with GitHub's layout
+    /// (`<base>/releases` for the list, `<base>/download/v<version>/<asset>` for assets)
+    pub fn from_base_url(base: &str) -> Self {
+        let base = base.trim_end_matches('/');
+        Self {
+            name: base.to_string(),
+            api_url: format!("{}/releases", base),
+            download_base: base.to_string(),
+            retry_delay: Duration::from_secs(1),
+        }
+    }
+
+    /// rnr's own releases, or the mirror named by `RNR_RELEASES_URL`
+    pub fn from_env() -> Self {
+        match std::env::var(RELEASES_URL_ENV) {
+            Ok(base) if !base.is_empty() => Self::from_base_url(&base),
+            _ => Self::github(GITHUB_REPO),
+        }
+    }
+
+    /// Change the pause between retries
+    #[cfg(test)]
+    pub fn with_retry_delay(mut self, delay: Duration) -> Self {
+        self.retry_delay = delay;
+        self
+    }
+
+    /// All published releases, following pagination
+    pub fn releases(&self) -> Result<Vec<Release>> {
+        let releases = self.fetch_releases()?;
+        if releases.is_empty() {
+            bail!("{}", self.no_releases_message());
+        }
+        Ok(releases)
+    }
+
+    /// Published releases, possibly none
+    fn fetch_releases(&self) -> Result<Vec<Release>> {
+        let client = http::metadata_client()?;
+
+        let mut releases = Vec::new();
+        for page in 1..=MAX_RELEASE_PAGES {
+            let url = format!(
+                "{}?per_page={}&page={}",
+                self.api_url, RELEASES_PER_PAGE, page
+            );
+            let response = self
+                .get(&client, &url)
+                .context("Failed to fetch release info")?;
+
+            if !response.status().is_success() {
+                bail!(
+                    "Failed to fetch release info: HTTP {}",
+                    response.status().as_u16()
+                );
+            }
+
+            let batch: Vec<Release> = response
+                .json()
+                .context("Failed to parse release info as JSON")?;
+            let done = batch.len() < RELEASES_PER_PAGE;
+            releases.extend(batch);
+            if done {
+                break;
+            }
+        }
+        Ok(releases)
+    }
+
+    /// Explain that nothing is published yet and how to set up a project anyway
+    fn no_releases_message(&self) -> String {
+        format!(
+            "No releases published yet for {}.\n\
+             Run 'rnr init --placeholders' to set up the project now, then \
+             'rnr upgrade' once the first release is out.",
+            self.name
+        )
+    }
+
+    /// Newest version published on a channel
+    pub fn latest_version(&self, channel: Channel) -> Result<String> {
+        latest_release(&self.releases()?, channel)
+            .with_context(|| format!("No releases found on the {} channel.", channel))
+    }
+
+    /// Newest release published on a channel, with its assets
+    pub fn latest(&self, channel: Channel) -> Result<Release> {
+        let releases = self.releases()?;
+        let version = latest_release(&releases, channel)
+            .with_context(|| format!("No releases found on the {} channel.", channel))?;
+        Ok(releases
+            .into_iter()
+            .find(|release| release.version() == version)
+            .expect("latest_release picks one of the releases"))
+    }
+
+    /// URL of a release asset; `None` means the latest release
+    fn asset_url(&self, asset: &str, version: Option<&str>) -> String {
+        match version {
+            Some(version) => format!("{}/download/v{}/{}", self.download_base, version, asset),
+            None => format!("{}/latest/download/{}", self.download_base, asset),
+        }
+    }
+
+    /// Download a platform's binary into `dest`, verifying it against the
+    /// release's `.sha256` file when one is published. The first of
+    /// [`Platform::release_assets`] the release has is used, so the slim build
+    /// is preferred over the full one, and an archive is unpacked. `version`
+    /// of `None` means the latest release. `progress` gets bytes read and the
+    /// total, if known.
+    pub fn download_asset(
+        &self,
+        platform: Platform,
+        version: Option<&str>,
+        dest: &Path,
+        progress: &mut dyn FnMut(u64, Option<u64>),
+    ) -> Result<()> {
+        let client = http::client()?;
+        let mut found = None;
+        for name in platform.release_assets() {
+            let url = self.asset_url(&name, version);
+            let response = self
+                .get(&client, &url)
+                .with_context(|| format!("Failed to download {}", name))?;
+            if response.status().as_u16() != 404 {
+                found = Some((name, url, response));
+                break;
+            }
+        }
+        let Some((name, url, response)) = found else {
+            let name = platform.binary_name();
+            let url = self.asset_url(name, version);
+            match version {
+                Some(version) => bail!("{} is not published for v{} ({})", name, version, url),
+                None if matches!(self.fetch_releases(), Ok(releases) if releases.is_empty()) => {
+                    bail!("{}", self.no_releases_message())
+                }
+                None => bail!("{} is not published in the latest release ({})", name, url),
+            }
+        };
+        if !response.status().is_success() {
+            bail!(
+                "Failed to download {}: HTTP {}",
+                name,
+                response.status().as_u16()
+            );
+        }
+        let bytes = http::read_limited(response, &url, http::MAX_BINARY_SIZE, progress)
+            .with_context(|| format!("Failed to download {}", name))?;
+
+        if let Some(expected) = self.published_checksum(&client, &url)? {
+            let mut hasher = Sha256Hasher::default();
+            hasher.update(&bytes);
+            let actual = hasher.finish();
+            if !checksum::matches(&expected, &actual) {
+                bail!(
+                    "Checksum mismatch for {}\n  expected: {}\n  actual:   {}",
+                    name,
+                    expected,
+                    actual
+                );
+            }
+        }
+        let binary = archive::unpack(&name, bytes, http::MAX_BINARY_SIZE)?;
+
+        // Swap in by renaming, since `dest` may be the binary that's running
+        self_replace::install(dest, &binary)
+    }
+
+    /// Digest from the `<asset>.sha256` file next to an asset, if the release has one
+    fn published_checksum(&self, client: &Client, asset_url: &str) -> Result<Option<String>> {
+        let url = format!("{}.sha256", asset_url);
+        let response = self.get(client, &url)?;
+        if response.status().as_u16() == 404 {
+            return Ok(None);
+        }
+        if !response.status().is_success() {
+            bail!(
+                "Failed to fetch checksum {}: HTTP {}",
+                url,
+                response.status().as_u16()
+            );
+        }
+        let text = http::read_limited(response, &url, 4096, &mut |_, _| {})?;
+        // `sha256sum` output is "<digest>  <file name>"
+        let digest = String::from_utf8_lossy(&text)
+            .split_whitespace()
+            .next()
+            .map(str::to_string);
+        Ok(digest)
+    }
+
+    /// GET with retries on connection errors, timeouts, and 5xx/429 responses
+    fn get(&self, client: &Client, url: &str) -> Result<Response> {
+        let mut delay = self.retry_delay;
+        for attempt in 1..=MAX_ATTEMPTS {
+            let last = attempt == MAX_ATTEMPTS;
+            match client.get(url).send() {
+                Ok(response) if !last && is_transient(response.status()) => {}
+                Ok(response) => return Ok(response),
+                Err(e) if !last && (e.is_connect() || e.is_timeout()) => {}
+                Err(e) => return Err(http::request_error(e, url)),
+            }
+            std::thread::sleep(delay);
+            delay *= 2;
+        }
+        unreachable!("the last attempt always returns")
+    }
+}
+
+/// Whether a response status is worth retrying
+fn is_transient(status: reqwest::StatusCode) -> bool {
+    status.is_server_error() || status.as_u16() == 429
+}
+
+/// Newest release version available on a channel
+pub fn latest_release(releases: &[Release], channel: Channel) -> Option<String> {
+    releases
+        .iter()
+        .filter(|release| !release.draft)
+        .filter(|release| channel == Channel::Prerelease || !release.prerelease)
+        .map(|release| release.version().to_string())
+        .max_by(|a, b| compare_versions(a, b))
+}
+
+/// Compare semantic versions, returns true if latest is newer than current
+pub fn is_newer_version(current: &str, latest: &str) -> bool {
+    compare_versions(latest, current) == Ordering::Greater
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{respond, TestServer};
+    use crate::version::is_prerelease;
+    use std::fs;
+    use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+    use std::sync::Arc;
+
+    const ABC_SHA256: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+
+    fn source(server: &TestServer) -> ReleaseSource {
+        ReleaseSource::from_base_url(&server.url).with_retry_delay(Duration::ZERO)
+    }
+
+    fn asset_path(version: &str) -> String {
+        format!(
+            "/download/v{}/{}",
+            version,
+            Platform::LinuxAmd64.binary_name()
+        )
+    }
+
+    #[test]
+    fn test_version_comparison() {
+        assert!(is_newer_version("0.1.0", "0.2.0"));
+        assert!(is_newer_version("0.1.0", "1.0.0"));
+        assert!(is_newer_version("0.1.0", "0.1.1"));
+        assert!(!is_newer_version("0.2.0", "0.1.0"));
+        assert!(!is_newer_version("1.0.0", "0.9.0"));
+        assert!(!is_newer_version("0.1.0", "0.1.0"));
+    }
+
+    #[test]
+    fn test_prerelease_comparison() {
+        assert!(is_newer_version("0.5.0-rc.1", "0.5.0-rc.2"));
+        assert!(is_newer_version("0.5.0-rc.2", "0.5.0"));
+        assert!(is_newer_version("0.4.1", "0.5.0-rc.1"));
+        assert!(!is_newer_version("0.5.0", "0.5.0-rc.2"));
+        assert!(is_newer_version("0.5.0-alpha", "0.5.0-alpha.1"));
+        assert!(is_newer_version("0.5.0-alpha.9", "0.5.0-beta"));
+        assert!(is_newer_version("0.5.0-rc.2", "0.5.0-rc.10"));
+        assert_eq!(compare_versions("v1.0.0+build.1", "1.0.0"), Ordering::Equal);
+        assert!(is_prerelease("0.5.0-rc.1"));
+        assert!(!is_prerelease("0.5.0"));
+    }
+
+    #[test]
+    fn test_latest_release_by_channel() {
+        let releases: Vec<Release> = serde_json::from_str(
+            r#"[
+                {"tag_name": "v0.6.0-rc.1", "prerelease": true, "draft": true},
+                {"tag_name": "v0.5.0-rc.2", "prerelease": true},
+                {"tag_name": "v0.5.0-rc.1", "prerelease": true},
+                {"tag_name": "v0.4.1", "prerelease": false},
+                {"tag_name": "v0.4.0", "prerelease": false}
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            latest_release(&releases, Channel::Stable).as_deref(),
+            Some("0.4.1")
+        );
+        assert_eq!(
+            latest_release(&releases, Channel::Prerelease).as_deref(),
+            Some("0.5.0-rc.2")
+        );
+        assert_eq!(latest_release(&[], Channel::Stable), None);
+    }
+
+    #[test]
+    fn test_latest_release_asset_sizes() {
+        let server = TestServer::start(|_, stream| {
+            let body = format!(
+                r#"[
+                    {{"tag_name": "v0.5.0-rc.1", "prerelease": true, "assets": []}},
+                    {{"tag_name": "v0.4.1", "assets": [
+                        {{"name": "{}", "size": 900}},
+                        {{"name": "{}", "size": 500}},
+                        {{"name": "{}", "size": 1200}}
+                    ]}}
+                ]"#,
+                Platform::LinuxAmd64.binary_name(),
+                Platform::LinuxAmd64.slim_asset_name(),
+                Platform::MacosArm64.binary_name()
+            );
+            respond(stream, "200 OK", &[], body.as_bytes());
+        });
+
+        let release = source(&server).latest(Channel::Stable).unwrap();
+        assert_eq!(release.version(), "0.4.1");
+        assert_eq!(release.binary_size(Platform::LinuxAmd64), Some(500));
+        assert_eq!(release.binary_size(Platform::MacosArm64), Some(1200));
+        assert_eq!(release.binary_size(Platform::WindowsArm64), None);
+    }
+
+    #[test]
+    fn test_latest_version_follows_pagination() {
+        let server = TestServer::start(|path, stream| {
+            let body = if path.ends_with("&page=1") {
+                // A full page means there may be more
+                let page: Vec<String> = (0..RELEASES_PER_PAGE)
+                    .map(|i| format!(r#"{{"tag_name": "v0.1.{}"}}"#, i))
+                    .collect();
+                format!("[{}]", page.join(","))
+            } else if path.ends_with("&page=2") {
+                r#"[{"tag_name": "v0.0.9"}, {"tag_name": "v1.0.0-rc.1", "prerelease": true}]"#
+                    .to_string()
+            } else {
+                "[]".to_string()
+            };
+            respond(stream, "200 OK", &[], body.as_bytes());
+        });
+
+        let source = source(&server);
+        assert_eq!(source.latest_version(Channel::Stable).unwrap(), "0.1.99");
+        assert_eq!(
+            source.latest_version(Channel::Prerelease).unwrap(),
+            "1.0.0-rc.1"
+        );
+        assert!(server.requests().iter().all(|p| !p.ends_with("&page=3")));
+    }
+
+    #[test]
+    fn test_download_asset_verifies_checksum() {
+        let server = TestServer::start(|path, stream| {
+            if path == asset_path("1.0.0") {
+                respond(stream, "200 OK", &[], b"abc");
+            } else if path == format!("{}.sha256", asset_path("1.0.0")) {
+                let body = format!("{}  rnr-linux-amd64\n", ABC_SHA256);
+                respond(stream, "200 OK", &[], body.as_bytes());
+            } else {
+                respond(stream, "404 Not Found", &[], b"");
+            }
+        });
+
+        let temp = tempfile::tempdir().unwrap();
+        let dest = temp.path().join("rnr-linux-amd64");
+        let mut seen = 0;
+        source(&server)
+            .download_asset(
+                Platform::LinuxAmd64,
+                Some("1.0.0"),
+                &dest,
+                &mut |read, _| seen = read,
+            )
+            .unwrap();
+        assert_eq!(fs::read(&dest).unwrap(), b"abc");
+        assert_eq!(seen, 3);
+    }
+
+    #[test]
+    fn test_download_asset_checksum_mismatch() {
+        let server = TestServer::start(|path, stream| {
+            if path.ends_with(".sha256") {
+                respond(stream, "200 OK", &[], ABC_SHA256.as_bytes());
+            } else {
+                respond(stream, "200 OK", &[], b"tampered");
+            }
+        });
+
+        let temp = tempfile::tempdir().unwrap();
+        let dest = temp.path().join("rnr-linux-amd64");
+        fs::write(&dest, "original").unwrap();
+        let err = source(&server)
+            .download_asset(Platform::LinuxAmd64, Some("1.0.0"), &dest, &mut |_, _| {})
+            .unwrap_err();
+        assert!(err.to_string().contains("Checksum mismatch"), "{}", err);
+        // The existing binary is left alone
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "original");
+    }
+
+    #[test]
+    fn test_download_asset_not_found() {
+        let server = TestServer::start(|_, stream| respond(stream, "404 Not Found", &[], b""));
+        let temp = tempfile::tempdir().unwrap();
+        let err = source(&server)
+            .download_asset(
+                Platform::LinuxAmd64,
+                Some("9.9.9"),
+                &temp.path().join("rnr"),
+                &mut |_, _| {},
+            )
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("not published for v9.9.9"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_download_asset_prefers_slim_build() {
+        let server = TestServer::start(|path, stream| match path {
+            "/download/v1.0.0/rnr-slim-linux-amd64" => respond(stream, "200 OK", &[], b"abc"),
+            "/download/v1.0.0/rnr-slim-linux-amd64.sha256" => {
+                respond(stream, "200 OK", &[], ABC_SHA256.as_bytes())
+            }
+            _ => respond(stream, "404 Not Found", &[], b""),
+        });
+
+        let temp = tempfile::tempdir().unwrap();
+        let dest = temp.path().join("rnr-linux-amd64");
+        source(&server)
+            .download_asset(Platform::LinuxAmd64, Some("1.0.0"), &dest, &mut |_, _| {})
+            .unwrap();
+        assert_eq!(fs::read(&dest).unwrap(), b"abc");
+        assert!(!server.requests().contains(&asset_path("1.0.0")));
+    }
+
+    #[test]
+    fn test_download_asset_unpacks_archives() {
+        let server = TestServer::start(|path, stream| match path {
+            "/download/v1.0.0/rnr-linux-amd64.tar.gz" => respond(
+                stream,
+                "200 OK",
+                &[],
+                include_bytes!("../tests/fixtures/archives/rnr-linux-amd64.tar.gz"),
+            ),
+            _ => respond(stream, "404 Not Found", &[], b""),
+        });
+
+        let temp = tempfile::tempdir().unwrap();
+        let dest = temp.path().join("rnr-linux-amd64");
+        source(&server)
+            .download_asset(Platform::LinuxAmd64, Some("1.0.0"), &dest, &mut |_, _| {})
+            .unwrap();
+        let binary = fs::read_to_string(&dest).unwrap();
+        assert!(binary.starts_with("#!/bin/sh\necho 'rnr from an archive'\n"));
+    }
+
+    #[test]
+    fn test_download_asset_before_first_release() {
+        let server = TestServer::start(|path, stream| {
+            if path.starts_with("/releases") {
+                respond(stream, "200 OK", &[], b"[]");
+            } else {
+                respond(stream, "404 Not Found", &[], b"");
+            }
+        });
+        let temp = tempfile::tempdir().unwrap();
+        let err = source(&server)
+            .download_asset(
+                Platform::LinuxAmd64,
+                None,
+                &temp.path().join("rnr"),
+                &mut |_, _| {},
+            )
+            .unwrap_err();
+        let message = err.to_string();
+        assert!(
+            message.starts_with(&format!("No releases published yet for {}", server.url)),
+            "{}",
+            message
+        );
+        assert!(message.contains("rnr init --placeholders"), "{}", message);
+        assert!(source(&server).latest_version(Channel::Stable).is_err());
+    }
+
+    #[test]
+    fn test_download_asset_retries_server_errors() {
+        let failures = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&failures);
+        let server = TestServer::start(move |path, stream| {
+            if path.ends_with(".sha256") {
+                respond(stream, "404 Not Found", &[], b"");
+            } else if counter.fetch_add(1, AtomicOrdering::SeqCst) < 2 {
+                respond(stream, "503 Service Unavailable", &[], b"");
+            } else {
+                respond(stream, "200 OK", &[], b"abc");
+            }
+        });
+
+        let temp = tempfile::tempdir().unwrap();
+        let dest = temp.path().join("rnr-linux-amd64");
+        source(&server)
+            .download_asset(Platform::LinuxAmd64, Some("1.0.0"), &dest, &mut |_, _| {})
+            .unwrap();
+        assert_eq!(fs::read(&dest).unwrap(), b"abc");
+        assert_eq!(failures.load(AtomicOrdering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_download_asset_gives_up_after_retries() {
+        let server = TestServer::start(|_, stream| {
+            respond(stream, "502 Bad Gateway", &[], b"");
+        });
+        let temp = tempfile::tempdir().unwrap();
+        let err = source(&server)
+            .download_asset(
+                Platform::LinuxAmd64,
+                None,
+                &temp.path().join("rnr"),
+                &mut |_, _| {},
+            )
+            .unwrap_err();
+        assert!(err.to_string().contains("HTTP 502"), "{}", err);
+        assert_eq!(server.requests().len(), MAX_ATTEMPTS as usize);
+    }
+
+    #[test]
+    fn test_download_asset_follows_redirects() {
+        let server = TestServer::start(|path, stream| {
+            if path == "/latest/download/rnr-linux-amd64" {
+                respond(stream, "302 Found", &[("Location", "/storage/blob")], b"");
+            } else if path == "/storage/blob" {
+                respond(stream, "200 OK", &[], b"abc");
+            } else {
+                respond(stream, "404 Not Found", &[], b"");
+            }
+        });
+
+        let temp = tempfile::tempdir().unwrap();
+        let dest = temp.path().join("rnr-linux-amd64");
+        source(&server)
+            .download_asset(Platform::LinuxAmd64, None, &dest, &mut |_, _| {})
+            .unwrap();
+        assert_eq!(fs::read(&dest).unwrap(), b"abc");
+        assert!(server.requests().contains(&"/storage/blob".to_string()));
+    }
+}
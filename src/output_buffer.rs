@@ -0,0 +1,339 @@
+//! Holding on to a command's output without holding all of it
+//!
+//! Output is read line by line, and a line is never longer than
+//! [`MAX_LINE`]: one that goes on longer is passed on in pieces. A [`Tail`]
+//! keeps the last lines it's given, up to a number of bytes (`output_buffer`,
+//! 1MB by default), and a [`Spill`] keeps everything in a file in the temp
+//! directory for when the tail isn't enough. Output can hold secrets, so
+//! that file has a random name and only its owner can read it.
+
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+#[cfg(test)]
+use std::path::Path;
+use std::path::PathBuf;
+use tempfile::NamedTempFile;
+
+use crate::config::Config;
+
+/// Output held in memory per step when `output_buffer` isn't set
+pub const DEFAULT_LIMIT: u64 = 1024 * 1024;
+
+/// Longest piece of a line read at once; longer lines are split
+pub const MAX_LINE: usize = 64 * 1024;
+
+/// The bytes of output a step may hold in memory under `config`
+pub fn limit(config: &Config) -> u64 {
+    config.output_buffer.map_or(DEFAULT_LIMIT, |size| size.0)
+}
+
+/// Call `f` with each line of `output`, without its line ending. A line
+/// longer than [`MAX_LINE`] bytes comes in pieces of at most that size, cut
+/// between characters.
+pub fn for_each_line(output: impl Read, mut f: impl FnMut(&str)) {
+    let mut reader = BufReader::new(output);
+    // What's been read of the line, starting with what was left over when
+    // the last piece was cut
+    let mut line = Vec::new();
+    loop {
+        // Room for a `\r\n` after a full piece, so a line of exactly
+        // MAX_LINE bytes comes whole
+        let room = (MAX_LINE + 2 - line.len()) as u64;
+        let read = (&mut reader).take(room).read_until(b'\n', &mut line);
+        let ended = line.last() == Some(&b'\n');
+        if ended {
+            line.pop();
+            if line.last() == Some(&b'\r') {
+                line.pop();
+            }
+        }
+        let more = read.is_ok() && !ended && line.len() == MAX_LINE + 2;
+        if !ended && !more && line.is_empty() {
+            break;
+        }
+        while line.len() > MAX_LINE {
+            let piece: Vec<u8> = line.drain(..piece_end(&line)).collect();
+            f(&String::from_utf8_lossy(&piece));
+        }
+        if !more {
+            f(&String::from_utf8_lossy(&line));
+            line.clear();
+        }
+    }
+}
+
+/// Where a piece of `line` ends: after [`MAX_LINE`] bytes, or before the
+/// character those bytes would split
+fn piece_end(line: &[u8]) -> usize {
+    let is_continuation = |byte: u8| byte & 0b1100_0000 == 0b1000_0000;
+    // A character is at most 4 bytes; past that it isn't UTF-8 anyway
+    (MAX_LINE - 3..=MAX_LINE)
+        .rev()
+        .find(|&end| !is_continuation(line[end]))
+        .unwrap_or(MAX_LINE)
+}
+
+/// The last lines of some output, up to a number of bytes and optionally of
+/// lines. The newest line is always kept, however long it is.
+#[derive(Debug)]
+pub struct Tail {
+    lines: VecDeque<String>,
+    /// Bytes held, counting a line break after each line
+    bytes: u64,
+    max_bytes: u64,
+    max_lines: usize,
+    /// Lines given but no longer held
+    dropped: usize,
+}
+
+impl Tail {
+    /// A tail holding up to `max_bytes` of output
+    pub fn new(max_bytes: u64) -> Self {
+        Tail {
+            lines: VecDeque::new(),
+            bytes: 0,
+            max_bytes,
+            max_lines: usize::MAX,
+            dropped: 0,
+        }
+    }
+
+    /// This tail, also holding no more than `max_lines` lines
+    pub fn max_lines(self, max_lines: usize) -> Self {
+        Tail { max_lines, ..self }
+    }
+
+    /// Whether `line` can be added without dropping one
+    pub fn fits(&self, line: &str) -> bool {
+        self.lines.len() < self.max_lines && self.bytes + size(line) <= self.max_bytes
+    }
+
+    /// Add `line`, dropping the oldest lines to make room for it
+    pub fn push(&mut self, line: &str) {
+        while !self.lines.is_empty() && !self.fits(line) {
+            if let Some(oldest) = self.lines.pop_front() {
+                self.bytes -= size(&oldest);
+                self.dropped += 1;
+            }
+        }
+        if self.max_lines == 0 {
+            self.dropped += 1;
+            return;
+        }
+        self.bytes += size(line);
+        self.lines.push_back(line.to_string());
+    }
+
+    /// Lines given that are no longer held
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    /// The lines held, oldest first
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.lines.iter().map(String::as_str)
+    }
+
+    /// Take the lines held, oldest first, leaving the tail empty
+    pub fn take(&mut self) -> Vec<String> {
+        self.bytes = 0;
+        self.lines.drain(..).collect()
+    }
+}
+
+/// The bytes a line takes up, with its line break
+fn size(line: &str) -> u64 {
+    line.len() as u64 + 1
+}
+
+/// All of some output, written to a file in the temp directory. The file
+/// is removed when this is dropped, unless it's kept.
+pub struct Spill {
+    file: BufWriter<NamedTempFile>,
+}
+
+impl Spill {
+    /// Start a spill file named for what it holds, created new with a
+    /// random name and readable only by its owner
+    pub fn create(kind: &str) -> io::Result<Self> {
+        let file = tempfile::Builder::new()
+            .prefix(&format!("rnr-{}-", kind))
+            .suffix(".log")
+            .tempfile()?;
+        Ok(Spill {
+            file: BufWriter::new(file),
+        })
+    }
+
+    /// Add a line. Write errors are ignored; the file is a convenience.
+    pub fn line(&mut self, line: &str) {
+        let _ = writeln!(self.file, "{}", line);
+    }
+
+    /// Finish the file and leave it in place, returning where it is
+    pub fn keep(self) -> io::Result<PathBuf> {
+        let file = self.file.into_inner().map_err(|e| e.into_error())?;
+        let (_, path) = file.keep().map_err(|e| e.error)?;
+        Ok(path)
+    }
+
+    /// Where the file is
+    #[cfg(test)]
+    pub fn path(&self) -> &Path {
+        self.file.get_ref().path()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn lines(output: &[u8]) -> Vec<String> {
+        let mut lines = Vec::new();
+        for_each_line(output, |line| lines.push(line.to_string()));
+        lines
+    }
+
+    #[test]
+    fn test_for_each_line() {
+        assert_eq!(lines(b"a\r\nb\n\nc"), ["a", "b", "", "c"]);
+        assert!(lines(b"").is_empty());
+    }
+
+    #[test]
+    fn test_long_lines_come_in_pieces() {
+        let mut output = vec![b'x'; MAX_LINE * 2 + 10];
+        output.extend(b"\nend\n");
+        let lines = lines(&output);
+        let sizes: Vec<usize> = lines.iter().map(String::len).collect();
+        assert_eq!(sizes, [MAX_LINE, MAX_LINE, 10, 3]);
+    }
+
+    #[test]
+    fn test_full_lines_come_whole() {
+        let mut output = vec![b'x'; MAX_LINE];
+        output.extend(b"\n");
+        output.extend(vec![b'y'; MAX_LINE]);
+        output.extend(b"\r\nend");
+        let sizes: Vec<usize> = lines(&output).iter().map(String::len).collect();
+        assert_eq!(sizes, [MAX_LINE, MAX_LINE, 3]);
+
+        let sizes: Vec<usize> = lines(&vec![b'x'; MAX_LINE + 1])
+            .iter()
+            .map(String::len)
+            .collect();
+        assert_eq!(sizes, [MAX_LINE, 1]);
+    }
+
+    #[test]
+    fn test_pieces_keep_characters_whole() {
+        let text = format!("{}é€{}", "x".repeat(MAX_LINE - 1), "z".repeat(MAX_LINE));
+        let pieces = lines(format!("{}\nend\n", text).as_bytes());
+        assert_eq!(
+            pieces.len(),
+            4,
+            "{:?}",
+            pieces.iter().map(String::len).collect::<Vec<_>>()
+        );
+        assert_eq!(pieces[0].len(), MAX_LINE - 1);
+        assert!(pieces[1].starts_with("é€z"));
+        assert_eq!(pieces[..3].concat(), text);
+        assert_eq!(pieces[3], "end");
+        assert!(!pieces.concat().contains('\u{FFFD}'));
+    }
+
+    #[test]
+    fn test_tail_keeps_the_last_bytes() {
+        let mut tail = Tail::new(10);
+        for line in ["one", "two", "three", "four"] {
+            tail.push(line);
+        }
+        // "three" and "four" take 11 bytes with their line breaks
+        assert_eq!(tail.iter().collect::<Vec<_>>(), ["four"]);
+        assert_eq!(tail.dropped(), 3);
+
+        let mut tail = Tail::new(12);
+        for line in ["one", "two", "three"] {
+            tail.push(line);
+        }
+        assert_eq!(tail.iter().collect::<Vec<_>>(), ["two", "three"]);
+        assert_eq!(tail.take(), ["two", "three"]);
+        assert_eq!(tail.iter().count(), 0);
+        tail.push("four");
+        assert_eq!(tail.iter().collect::<Vec<_>>(), ["four"]);
+    }
+
+    #[test]
+    fn test_tail_keeps_the_newest_line_whatever_its_size() {
+        let mut tail = Tail::new(4);
+        tail.push("a");
+        tail.push("longer than the limit");
+        assert_eq!(tail.iter().collect::<Vec<_>>(), ["longer than the limit"]);
+        assert_eq!(tail.dropped(), 1);
+        assert!(!tail.fits("b"));
+    }
+
+    #[test]
+    fn test_tail_max_lines() {
+        let mut tail = Tail::new(DEFAULT_LIMIT).max_lines(2);
+        for n in 0..5 {
+            tail.push(&n.to_string());
+        }
+        assert_eq!(tail.iter().collect::<Vec<_>>(), ["3", "4"]);
+        assert_eq!(tail.dropped(), 3);
+
+        let mut none = Tail::new(DEFAULT_LIMIT).max_lines(0);
+        none.push("a");
+        assert_eq!((none.iter().count(), none.dropped()), (0, 1));
+    }
+
+    #[test]
+    fn test_tail_stays_bounded() {
+        let mut tail = Tail::new(64 * 1024);
+        let line = "y".repeat(99);
+        for _ in 0..100_000 {
+            tail.push(&line);
+        }
+        assert_eq!(tail.iter().count(), 64 * 1024 / 100);
+        assert!(tail.bytes <= 64 * 1024);
+        assert_eq!(tail.dropped() + tail.iter().count(), 100_000);
+    }
+
+    #[test]
+    fn test_spill_files_are_private_and_unique() {
+        let (a, b) = (
+            Spill::create("test").unwrap(),
+            Spill::create("test").unwrap(),
+        );
+        assert_ne!(a.path(), b.path());
+        let name = a.path().file_name().unwrap().to_string_lossy().into_owned();
+        assert!(
+            name.starts_with("rnr-test-") && name.ends_with(".log"),
+            "{}",
+            name
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(a.path()).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+
+    #[test]
+    fn test_spill_is_removed_unless_kept() {
+        let mut spill = Spill::create("test").unwrap();
+        spill.line("a");
+        let path = spill.path().to_path_buf();
+        drop(spill);
+        assert!(!path.exists());
+
+        let mut spill = Spill::create("test").unwrap();
+        spill.line("a");
+        spill.line("b");
+        let path = spill.keep().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a\nb\n");
+        fs::remove_file(path).unwrap();
+    }
+}
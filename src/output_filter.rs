@@ -0,0 +1,267 @@
+//! Built-in filters for a command's stdout, selected with `output_filter`
+
+use serde_json::Value;
+
+use crate::config::OutputFilter;
+use crate::output_buffer::{Spill, Tail};
+
+/// Rewrites a command's stdout as it's read, line by line
+pub trait Filter: Send {
+    /// Lines to show for one line of output
+    fn line(&mut self, line: &str) -> Vec<String>;
+
+    /// Lines to show once the command has exited
+    fn finish(&mut self, success: bool) -> Vec<String>;
+}
+
+/// A fresh instance of `filter`, holding back at most `limit` bytes of output
+pub fn build(filter: OutputFilter, limit: u64) -> Box<dyn Filter> {
+    match filter {
+        OutputFilter::CargoJson => Box::<CargoJson>::default(),
+        OutputFilter::QuietSuccess => Box::new(QuietSuccess::new(limit)),
+    }
+}
+
+/// Turns cargo's JSON messages and test results into PASS/FAIL lines,
+/// followed by the counts
+#[derive(Debug, Default)]
+struct CargoJson {
+    passed: usize,
+    failed: usize,
+    ignored: usize,
+}
+
+impl CargoJson {
+    /// Count a test result and describe it
+    fn test(&mut self, name: &str, event: &str) -> Option<String> {
+        let (count, label) = match event {
+            "ok" => (&mut self.passed, "PASS"),
+            "failed" => (&mut self.failed, "FAIL"),
+            "ignored" => (&mut self.ignored, "SKIP"),
+            _ => return None,
+        };
+        *count += 1;
+        Some(format!("{} {}", label, name))
+    }
+
+    /// A message from cargo itself (`reason`) or the JSON test harness (`type`)
+    fn message(&mut self, message: &Value) -> Vec<String> {
+        let text = |key: &str| message.get(key).and_then(Value::as_str).unwrap_or_default();
+        match (text("reason"), text("type")) {
+            ("compiler-message", _) => message
+                .pointer("/message/rendered")
+                .and_then(Value::as_str)
+                .map(|rendered| rendered.trim_end().lines().map(str::to_string).collect())
+                .unwrap_or_default(),
+            ("build-finished", _) if message.get("success") == Some(&Value::Bool(false)) => {
+                vec!["build failed".to_string()]
+            }
+            (_, "test") => {
+                let mut lines: Vec<String> =
+                    self.test(text("name"), text("event")).into_iter().collect();
+                if text("event") == "failed" {
+                    lines.extend(
+                        text("stdout")
+                            .trim_end()
+                            .lines()
+                            .map(|l| format!("    {}", l)),
+                    );
+                }
+                lines
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl Filter for CargoJson {
+    fn line(&mut self, line: &str) -> Vec<String> {
+        if let Ok(message @ Value::Object(_)) = serde_json::from_str::<Value>(line) {
+            return self.message(&message);
+        }
+
+        // Without the unstable JSON test format, results are plain text
+        if let Some(rest) = line.strip_prefix("test ") {
+            if let Some((name, result)) = rest.rsplit_once(" ... ") {
+                let event = match result {
+                    "ok" => "ok",
+                    "FAILED" => "failed",
+                    _ if result.starts_with("ignored") => "ignored",
+                    _ => "",
+                };
+                if let Some(line) = self.test(name, event) {
+                    return vec![line];
+                }
+            }
+        }
+        if line.is_empty() || line.starts_with("running ") || line.starts_with("test result: ") {
+            return Vec::new();
+        }
+        vec![line.to_string()]
+    }
+
+    fn finish(&mut self, _success: bool) -> Vec<String> {
+        if self.passed + self.failed + self.ignored == 0 {
+            return Vec::new();
+        }
+        vec![format!(
+            "{} passed, {} failed, {} ignored",
+            self.passed, self.failed, self.ignored
+        )]
+    }
+}
+
+/// Holds stdout back, showing it only if the command fails. Past the
+/// buffer's limit, only the last of it is held, and all of it goes to a
+/// spill file that's kept when the command fails.
+struct QuietSuccess {
+    held: Tail,
+    spill: Option<Spill>,
+}
+
+impl QuietSuccess {
+    fn new(limit: u64) -> Self {
+        QuietSuccess {
+            held: Tail::new(limit),
+            spill: None,
+        }
+    }
+}
+
+impl Filter for QuietSuccess {
+    fn line(&mut self, line: &str) -> Vec<String> {
+        if self.spill.is_none() && !self.held.fits(line) {
+            self.spill = Spill::create("output").ok().map(|mut spill| {
+                self.held.iter().for_each(|held| spill.line(held));
+                spill
+            });
+        }
+        if let Some(spill) = &mut self.spill {
+            spill.line(line);
+        }
+        self.held.push(line);
+        Vec::new()
+    }
+
+    fn finish(&mut self, success: bool) -> Vec<String> {
+        if success {
+            return Vec::new();
+        }
+        let mut lines = Vec::new();
+        let dropped = self.held.dropped();
+        if dropped > 0 {
+            lines.push(
+                match self.spill.take().and_then(|spill| spill.keep().ok()) {
+                    Some(path) => format!(
+                        "... {} earlier lines not shown; all of the output is in {}",
+                        dropped,
+                        path.display()
+                    ),
+                    None => format!("... {} earlier lines not shown", dropped),
+                },
+            );
+        }
+        lines.extend(self.held.take());
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output_buffer::DEFAULT_LIMIT;
+    use std::fs;
+
+    /// Feed `output` through a new `filter`, returning what it shows
+    fn render(filter: OutputFilter, output: &str, success: bool) -> String {
+        let mut filter = build(filter, DEFAULT_LIMIT);
+        let mut lines: Vec<String> = output.lines().flat_map(|l| filter.line(l)).collect();
+        lines.extend(filter.finish(success));
+        lines.join("\n")
+    }
+
+    #[test]
+    fn test_cargo_json_messages() {
+        let output = r#"{"reason":"compiler-artifact","target":{"name":"rnr"},"fresh":true}
+{"reason":"compiler-message","message":{"rendered":"warning: unused variable: `x`\n --> src/lib.rs:2:9\n"}}
+{"reason":"build-finished","success":true}
+{"type":"suite","event":"started","test_count":3}
+{"type":"test","event":"started","name":"tests::adds"}
+{"type":"test","name":"tests::adds","event":"ok"}
+{"type":"test","name":"tests::subtracts","event":"failed","stdout":"assertion failed: left == right\n"}
+{"type":"test","name":"tests::slow","event":"ignored"}
+{"type":"suite","event":"failed","passed":1,"failed":1,"ignored":1}"#;
+        assert_eq!(
+            render(OutputFilter::CargoJson, output, false),
+            "warning: unused variable: `x`\n \
+             --> src/lib.rs:2:9\n\
+             PASS tests::adds\n\
+             FAIL tests::subtracts\n    \
+             assertion failed: left == right\n\
+             SKIP tests::slow\n\
+             1 passed, 1 failed, 1 ignored"
+        );
+    }
+
+    #[test]
+    fn test_cargo_json_plain_test_output() {
+        let output = r#"{"reason":"compiler-artifact","target":{"name":"rnr"},"fresh":true}
+{"reason":"build-finished","success":true}
+
+running 3 tests
+test tests::adds ... ok
+test tests::slow ... ignored, needs a server
+test tests::subtracts ... FAILED
+
+failures:
+    tests::subtracts
+
+test result: FAILED. 1 passed; 1 failed; 1 ignored; 0 measured; 0 filtered out"#;
+        assert_eq!(
+            render(OutputFilter::CargoJson, output, false),
+            "PASS tests::adds\n\
+             SKIP tests::slow\n\
+             FAIL tests::subtracts\n\
+             failures:\n    \
+             tests::subtracts\n\
+             1 passed, 1 failed, 1 ignored"
+        );
+    }
+
+    #[test]
+    fn test_cargo_json_build_failure() {
+        let output = r#"{"reason":"compiler-message","message":{"rendered":"error[E0425]: cannot find value `y`\n"}}
+{"reason":"build-finished","success":false}"#;
+        assert_eq!(
+            render(OutputFilter::CargoJson, output, false),
+            "error[E0425]: cannot find value `y`\nbuild failed"
+        );
+    }
+
+    #[test]
+    fn test_quiet_success() {
+        let output = "compiling\nlinking";
+        assert_eq!(render(OutputFilter::QuietSuccess, output, true), "");
+        assert_eq!(
+            render(OutputFilter::QuietSuccess, output, false),
+            "compiling\nlinking"
+        );
+    }
+
+    #[test]
+    fn test_quiet_success_past_its_limit() {
+        let mut filter = build(OutputFilter::QuietSuccess, 16);
+        for n in 0..10 {
+            assert!(filter.line(&format!("line {}", n)).is_empty());
+        }
+        let shown = filter.finish(false);
+        assert_eq!(shown[1..], ["line 8", "line 9"]);
+        let path = shown[0]
+            .strip_prefix("... 8 earlier lines not shown; all of the output is in ")
+            .unwrap();
+        let all = fs::read_to_string(path).unwrap();
+        assert_eq!(all.lines().count(), 10);
+        assert!(all.starts_with("line 0\n"));
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,117 @@
+//! Permissions of the files rnr writes
+//!
+//! Init and upgrade give every file they write an explicit mode, so a
+//! permissive umask doesn't leave them writable by others, and `umask` in
+//! rnr.yaml narrows the mode of everything created during a run. Windows has
+//! no mode bits, so there modes and `umask` are accepted and ignored.
+
+use anyhow::{Context, Result};
+use std::path::Path;
+
+use crate::config::FileMode;
+
+/// Wrapper scripts and binaries
+pub const EXECUTABLE: FileMode = FileMode(0o755);
+
+/// Config files and everything else init and upgrade write
+pub const READABLE: FileMode = FileMode(0o644);
+
+/// Give `path` exactly `mode` (Unix only)
+pub fn set(path: &Path, mode: FileMode) -> Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode.0))
+            .with_context(|| format!("Failed to set permissions on {}", path.display()))?;
+    }
+    #[cfg(not(unix))]
+    let _ = (path, mode);
+    Ok(())
+}
+
+/// A narrower umask for this process, put back when dropped. Files rnr
+/// creates get it, and so do the commands it starts, which inherit it.
+#[derive(Debug)]
+pub struct Umask {
+    previous: Option<u32>,
+}
+
+impl Umask {
+    /// Add `mask`'s bits to the process umask; does nothing for `None`
+    pub fn restrict(mask: Option<FileMode>) -> Self {
+        Umask {
+            previous: mask.and_then(|mask| sys::add(mask.0)),
+        }
+    }
+}
+
+impl Drop for Umask {
+    fn drop(&mut self) {
+        if let Some(previous) = self.previous {
+            sys::replace(previous);
+        }
+    }
+}
+
+#[cfg(unix)]
+mod sys {
+    // `mode_t` is 16 bits on some platforms and 32 on others; masks fit in
+    // either, and only the permission bits of what comes back are kept
+    extern "C" {
+        fn umask(mask: u32) -> u32;
+    }
+
+    /// Set the umask, returning the one it replaces
+    pub fn replace(mask: u32) -> u32 {
+        // SAFETY: umask(2) can't fail; it only swaps the process's mask
+        unsafe { umask(mask) & 0o777 }
+    }
+
+    /// Add `mask`'s bits to the umask, returning the one before
+    pub fn add(mask: u32) -> Option<u32> {
+        let previous = replace(mask);
+        replace(previous | mask);
+        Some(previous)
+    }
+}
+
+#[cfg(not(unix))]
+mod sys {
+    pub fn replace(_mask: u32) -> u32 {
+        0
+    }
+
+    pub fn add(_mask: u32) -> Option<u32> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(unix)]
+    fn test_set_gives_exact_modes() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("rnr");
+        std::fs::write(&path, "#!/bin/sh\n").unwrap();
+        let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o7777;
+
+        set(&path, EXECUTABLE).unwrap();
+        assert_eq!(mode(&path), 0o755);
+        set(&path, FileMode(0o600)).unwrap();
+        assert_eq!(mode(&path), 0o600);
+
+        let missing = temp.path().join("missing");
+        assert!(set(&missing, READABLE).is_err());
+    }
+
+    #[test]
+    fn test_no_umask_leaves_the_process_alone() {
+        let umask = Umask::restrict(None);
+        assert!(umask.previous.is_none());
+    }
+}
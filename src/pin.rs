@@ -0,0 +1,199 @@
+//! Run the rnr version a project pins
+//!
+//! An rnr installed once for the user (Homebrew, `cargo install`) that runs in
+//! a project whose `.rnr/config.yaml` names another version hands the command
+//! line to that version, the way gradlew or an asdf shim does. Pinned versions
+//! are kept in the user cache (`~/.cache/rnr/<version>/` on Linux) and
+//! downloaded the first time a project needs them.
+
+use anyhow::{Context, Result};
+use std::env;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::build_info::VERSION;
+use crate::platform::Platform;
+use crate::rnr_config::{self, RnrConfig, BIN_DIR};
+use crate::ui::Ui;
+use crate::version;
+
+/// Set to `1` to always run the rnr that was invoked
+pub const NO_REEXEC_ENV: &str = "RNR_NO_REEXEC";
+
+/// Set on the pinned rnr to the version that handed over, so it never hands over again
+pub const REEXEC_FROM_ENV: &str = "RNR_REEXEC_FROM";
+
+/// Overrides where pinned versions are kept
+pub const CACHE_DIR_ENV: &str = "RNR_CACHE_DIR";
+
+/// Hand this process over to the project's pinned rnr when it pins a
+/// version other than this one. Returns when this rnr should carry on: no
+/// project, the same version, re-exec turned off, or the pinned version
+/// can't be had (with a warning).
+pub fn run_pinned(ui: &Ui) {
+    if is_set(env::var_os(NO_REEXEC_ENV).as_deref()) || env::var_os(REEXEC_FROM_ENV).is_some() {
+        return;
+    }
+    let Ok(rnr_dir) = rnr_config::find_rnr_dir() else {
+        return;
+    };
+    // A broken config is reported by whatever reads it next
+    let Ok(config) = RnrConfig::load_from(&rnr_dir.join(rnr_config::CONFIG_FILE)) else {
+        return;
+    };
+    // The project's own binaries are what it pinned, whatever they report
+    if config.version == VERSION || running_from(&rnr_dir.join(BIN_DIR)) {
+        return;
+    }
+    // It names a directory in the cache and a release to download
+    if !version::is_semver(&config.version) {
+        ui.warn(format!(
+            "this project pins rnr '{}', which isn't a version like 1.2.3; running rnr {} instead",
+            config.version, VERSION
+        ));
+        return;
+    }
+    let Some(platform) = Platform::current() else {
+        return;
+    };
+
+    let binary = match cache_dir() {
+        Some(cache) => binary_path(&cache, &config.version),
+        None => {
+            ui.warn(format!(
+                "this project pins rnr {}, but there's no cache directory to keep it in; \
+                 running rnr {} instead (set {})",
+                config.version, VERSION, CACHE_DIR_ENV
+            ));
+            return;
+        }
+    };
+    if !binary.is_file() {
+        if let Err(e) = download(&config.version, platform, &binary, ui) {
+            ui.warn(format!(
+                "this project pins rnr {}, which isn't in {} and couldn't be downloaded \
+                 ({:#}); running rnr {} instead",
+                config.version,
+                binary.parent().unwrap_or(&binary).display(),
+                e,
+                VERSION
+            ));
+            return;
+        }
+    }
+    if let Err(e) = exec(&binary) {
+        ui.warn(format!("{:#}; running rnr {} instead", e, VERSION));
+    }
+}
+
+/// Whether an on/off environment variable is on
+fn is_set(value: Option<&OsStr>) -> bool {
+    value.is_some_and(|value| !value.is_empty() && value != "0" && value != "false")
+}
+
+/// Whether the running binary is in `dir`
+fn running_from(dir: &Path) -> bool {
+    let (Ok(exe), Ok(dir)) = (env::current_exe(), dir.canonicalize()) else {
+        return false;
+    };
+    exe.canonicalize().is_ok_and(|exe| exe.starts_with(dir))
+}
+
+/// Where pinned versions are kept
+fn cache_dir() -> Option<PathBuf> {
+    match env::var_os(CACHE_DIR_ENV).filter(|dir| !dir.is_empty()) {
+        Some(dir) => Some(PathBuf::from(dir)),
+        None => dirs::cache_dir().map(|dir| dir.join("rnr")),
+    }
+}
+
+/// The cached binary of `version` under `cache`
+fn binary_path(cache: &Path, version: &str) -> PathBuf {
+    cache
+        .join(version)
+        .join(format!("rnr{}", env::consts::EXE_SUFFIX))
+}
+
+/// Fetch `version` for `platform` into the cache
+#[cfg(feature = "network")]
+fn download(version: &str, platform: Platform, dest: &Path, ui: &Ui) -> Result<()> {
+    ui.notice(format!(
+        "Downloading rnr {} (pinned by this project) to {}",
+        version,
+        dest.display()
+    ));
+    let dir = dest.parent().unwrap_or(dest);
+    std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    crate::net::ReleaseSource::from_env().download_asset(
+        platform,
+        Some(version),
+        dest,
+        &mut |_, _| {},
+    )
+}
+
+/// Without network support, only versions already cached can be run
+#[cfg(not(feature = "network"))]
+fn download(_version: &str, _platform: Platform, _dest: &Path, _ui: &Ui) -> Result<()> {
+    anyhow::bail!("this rnr build has no network support")
+}
+
+/// Replace this process with `binary`, given the same arguments
+#[cfg(unix)]
+fn exec(binary: &Path) -> Result<()> {
+    use std::os::unix::process::CommandExt;
+
+    let error = pinned_command(binary).exec();
+    Err(error).with_context(|| format!("Failed to run {}", binary.display()))
+}
+
+/// Run `binary` with the same arguments and exit with its status
+#[cfg(not(unix))]
+fn exec(binary: &Path) -> Result<()> {
+    let status = pinned_command(binary)
+        .status()
+        .with_context(|| format!("Failed to run {}", binary.display()))?;
+    std::process::exit(status.code().unwrap_or(1));
+}
+
+/// The command line this process was given, for `binary`
+fn pinned_command(binary: &Path) -> Command {
+    let mut command = Command::new(binary);
+    command
+        .args(env::args_os().skip(1))
+        .env(REEXEC_FROM_ENV, VERSION);
+    command
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_set() {
+        assert!(is_set(Some(OsStr::new("1"))));
+        assert!(is_set(Some(OsStr::new("yes"))));
+        for off in [None, Some(""), Some("0"), Some("false")] {
+            assert!(!is_set(off.map(OsStr::new)), "{:?}", off);
+        }
+    }
+
+    #[test]
+    fn test_binary_path() {
+        let path = binary_path(Path::new("/cache/rnr"), "0.3.1");
+        assert_eq!(
+            path,
+            Path::new("/cache/rnr/0.3.1").join(format!("rnr{}", env::consts::EXE_SUFFIX))
+        );
+    }
+
+    #[test]
+    fn test_running_from() {
+        let exe = env::current_exe().unwrap();
+        assert!(running_from(exe.parent().unwrap()));
+        assert!(!running_from(
+            &tempfile::tempdir().unwrap().path().join("bin")
+        ));
+    }
+}
@@ -0,0 +1,1240 @@
+//! Execution plans for `--dry-run --diff`
+//!
+//! A plan lists every action a task performs along with its working
+//! directory and environment. The last plan for each task is recorded under
+//! `.rnr/cache/plans/<task>.json` so a later dry run can show what changed.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::args::TaskArgs;
+use crate::bins;
+use crate::choose;
+use crate::config::{
+    self, Cmd, Config, ConfigCache, Contexts, ExpectExit, GitRequirements, Invocation,
+    OutputFilter, Priority, Step, StepDef, Task, TaskDef, TaskTarget, TimeZone, Window,
+};
+use crate::context;
+use crate::guards::{self, GitState};
+use crate::interpolate::Scope;
+use crate::secrets;
+use crate::step_filter::StepFilter;
+use crate::ui::Ui;
+
+/// Directory (inside the cache) holding recorded plans
+const PLANS_DIR: &str = "plans";
+
+/// Maximum depth of task delegation followed while planning
+const MAX_DEPTH: usize = 32;
+
+/// Unchanged lines shown around each change
+const CONTEXT_LINES: usize = 2;
+
+/// Everything a task run would do
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Plan {
+    /// Task the plan was built for
+    pub task: String,
+    /// Actions in execution order
+    pub entries: Vec<PlanEntry>,
+    /// Tasks in the plan limited by `contexts:` (not recorded)
+    #[serde(skip)]
+    pub restricted: Vec<Restricted>,
+    /// Tasks in the plan kept out of `not_between` windows (not recorded)
+    #[serde(skip)]
+    pub windows: Vec<Windowed>,
+    /// Tasks in the plan with `require_*` git checks, once each (not recorded)
+    #[serde(skip)]
+    pub git: Vec<GitGuarded>,
+}
+
+/// A planned task that only runs in some contexts
+#[derive(Debug, Clone, PartialEq)]
+pub struct Restricted {
+    /// The task's name
+    pub task: String,
+    /// Its `contexts`
+    pub contexts: Contexts,
+    /// The contexts its rnr.yaml knows
+    pub known: Vec<String>,
+}
+
+/// A planned task that doesn't start at some times of day
+#[derive(Debug, Clone, PartialEq)]
+pub struct Windowed {
+    /// The task's name
+    pub task: String,
+    /// Its `not_between`
+    pub window: Window,
+    /// The `tz` of its rnr.yaml
+    pub tz: TimeZone,
+}
+
+/// A planned task that needs the git repository in some state
+#[derive(Debug, Clone, PartialEq)]
+pub struct GitGuarded {
+    /// The task's name
+    pub task: String,
+    /// Its `require_clean_git`, `require_branch` and `require_upstream_synced`
+    pub requirements: GitRequirements,
+}
+
+impl Plan {
+    /// Fail if a task in the plan may not run in `context`, before any of
+    /// it runs
+    pub fn check_contexts(&self, context: &str) -> Result<()> {
+        for restricted in &self.restricted {
+            let known: Vec<&str> = restricted.known.iter().map(String::as_str).collect();
+            context::check(&restricted.task, &restricted.contexts, context, &known)?;
+        }
+        Ok(())
+    }
+
+    /// Fail if a task in the plan may not start at `now`, before any of it
+    /// runs; with `ignore`, warn instead
+    pub fn check_windows(&self, now: SystemTime, ignore: bool, ui: &Ui) -> Result<()> {
+        for windowed in &self.windows {
+            guards::check_window(
+                &windowed.task,
+                &windowed.window,
+                windowed.tz,
+                now,
+                ignore,
+                ui,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Fail if the git repository at `root` isn't as a task in the plan
+    /// needs, before any of it runs
+    pub fn check_git(
+        &self,
+        root: &Path,
+        allow_dirty: bool,
+        ignore: bool,
+        dry_run: bool,
+        ui: &Ui,
+    ) -> Result<()> {
+        check_git(&self.git, root, allow_dirty, ignore, dry_run, ui)
+    }
+}
+
+/// Fail if the git repository at `root` isn't as one of the `guarded` tasks
+/// needs, reading its state once for them all. Flags waive problems with a
+/// warning, and a dry run only says what it would check.
+pub fn check_git(
+    guarded: &[GitGuarded],
+    root: &Path,
+    allow_dirty: bool,
+    ignore: bool,
+    dry_run: bool,
+    ui: &Ui,
+) -> Result<()> {
+    let mut state = GitState::default();
+    for guarded in guarded {
+        if dry_run {
+            ui.status(format!(
+                "Dry run: not checking that '{}' runs with {}",
+                guarded.task,
+                guards::describe_git(&guarded.requirements)
+            ));
+            continue;
+        }
+        state.read(root, &guarded.requirements)?;
+        guards::check_git(
+            &guarded.task,
+            &guarded.requirements,
+            &state,
+            allow_dirty,
+            ignore,
+            ui,
+        )?;
+    }
+    Ok(())
+}
+
+/// A single action in a plan
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PlanEntry {
+    /// Where the action comes from, e.g. `ci/2/lint`
+    pub step: String,
+    /// Command line or builtin description
+    pub action: String,
+    /// Working directory relative to the project root
+    pub dir: String,
+    /// Environment set by the task (secret values masked)
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Whether the runner would perform the action and why, for `rnr explain`:
+    /// a `run:`, `skip:` or `fail:` line, then details (not recorded)
+    #[serde(skip)]
+    pub why: Vec<String>,
+}
+
+/// Entries are equal if they'd be recorded the same, whatever the reasons
+impl PartialEq for PlanEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.step == other.step
+            && self.action == other.action
+            && self.dir == other.dir
+            && self.env == other.env
+    }
+}
+
+/// Build the plan for a task without executing anything, in `dir` instead
+/// of its own `dir` if given, leaving out the steps `filter` does
+#[allow(clippy::too_many_arguments)]
+pub fn build(
+    task_name: &str,
+    task: &TaskDef,
+    project_root: &Path,
+    dir: Option<&Path>,
+    config: &Config,
+    configs: &ConfigCache,
+    args: &TaskArgs,
+    filter: &StepFilter,
+) -> Result<Plan> {
+    let mut planner = Planner {
+        root: project_root,
+        configs,
+        filter,
+        dir,
+        entries: Vec::new(),
+        once: HashMap::new(),
+        restricted: Vec::new(),
+        windows: Vec::new(),
+        git: Vec::new(),
+        salt: None,
+    };
+    planner.restrict(task_name, task, config);
+    planner.task_def(task, task_name, project_root, config, args, None, 0)?;
+    Ok(Plan {
+        task: task_name.to_string(),
+        entries: planner.entries,
+        restricted: planner.restricted,
+        windows: planner.windows,
+        git: planner.git,
+    })
+}
+
+/// Path of the recorded plan for a task
+pub fn plan_path(project_root: &Path, task_name: &str) -> PathBuf {
+    let stem: String = task_name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    project_root
+        .join(config::CACHE_DIR)
+        .join(PLANS_DIR)
+        .join(format!("{}.json", stem))
+}
+
+/// Load the recorded plan for a task, if there is one
+pub fn load(project_root: &Path, task_name: &str) -> Result<Option<Plan>> {
+    let path = plan_path(project_root, task_name);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read plan: {}", path.display()))?;
+    let plan = serde_json::from_str(&content)
+        .with_context(|| format!("Failed to parse plan: {}", path.display()))?;
+    Ok(Some(plan))
+}
+
+/// Record a plan as the latest for its task
+pub fn save(project_root: &Path, plan: &Plan) -> Result<()> {
+    let path = plan_path(project_root, &plan.task);
+    config::create_cache_dir(project_root)?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    let content = serde_json::to_string_pretty(plan)?;
+    fs::write(&path, content).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Short description of a step: its `description`, or what it does, e.g.
+/// `$ cargo test` or `mkdir dist`, with its scheduling hints:
+/// `$ cargo build (priority: low, weight: 4)`
+pub fn describe(step_def: &StepDef) -> String {
+    let action = if let Some(description) = &step_def.description {
+        description.clone()
+    } else if let Some(task_name) = &step_def.task {
+        format!("task {}", task_name)
+    } else if let Some(cmd) = &step_def.cmd {
+        format!("$ {}", cmd)
+    } else {
+        describe_builtin(step_def).unwrap_or_else(|| "(empty step)".to_string())
+    };
+    match hints(step_def.priority.unwrap_or_default(), step_def.weight) {
+        Some(hints) => format!("{} ({})", action, hints),
+        None => action,
+    }
+}
+
+/// A step's scheduling hints, e.g. `priority: low, weight: 4`, if it has
+/// any besides the defaults
+pub fn hints(priority: Priority, weight: Option<NonZeroU32>) -> Option<String> {
+    let mut hints = Vec::new();
+    if priority != Priority::Normal {
+        hints.push(format!("priority: {}", priority));
+    }
+    if let Some(weight) = weight {
+        hints.push(format!("weight: {}", weight));
+    }
+    (!hints.is_empty()).then(|| hints.join(", "))
+}
+
+/// Description of a builtin step, if the step is one
+fn describe_builtin(step_def: &StepDef) -> Option<String> {
+    if let Some(spec) = &step_def.copy {
+        Some(format!("copy {} -> {}", spec.from, spec.to))
+    } else if let Some(spec) = &step_def.remove {
+        Some(format!("remove {}", spec.path()))
+    } else if let Some(dir) = &step_def.mkdir {
+        Some(format!("mkdir {}", dir))
+    } else if let Some(spec) = &step_def.download {
+        Some(format!("download {} -> {}", spec.url, spec.to))
+    } else if let Some(spec) = &step_def.render {
+        let source = spec.template.as_deref().unwrap_or("inline content");
+        Some(format!("render {} -> {}", source, spec.to))
+    } else if step_def.sets_env() {
+        Some(describe_env_step(step_def))
+    } else {
+        None
+    }
+}
+
+/// Description of an env step, naming the file and the variables it sets
+fn describe_env_step(step_def: &StepDef) -> String {
+    let mut parts = Vec::new();
+    if let Some(path) = &step_def.env_file {
+        parts.push(format!("env_file {}", path));
+    }
+    if let Some(env) = &step_def.env {
+        let mut names: Vec<&str> = env.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        parts.push(format!("env {}", names.join(", ")));
+    }
+    parts.join(", ")
+}
+
+/// A line of a plan diff
+#[derive(Debug, PartialEq, Eq)]
+pub enum DiffLine {
+    Same(String),
+    Removed(String),
+    Added(String),
+}
+
+/// Line-based diff between two plans
+pub fn diff(previous: &Plan, current: &Plan) -> Vec<DiffLine> {
+    diff_lines(&render_lines(previous), &render_lines(current))
+}
+
+/// Line-based diff, with removals before additions
+pub fn diff_lines(old: &[String], new: &[String]) -> Vec<DiffLine> {
+    // Longest common subsequence table, filled from the end
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push(DiffLine::Same(old[i].clone()));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            lines.push(DiffLine::Removed(old[i].clone()));
+            i += 1;
+        } else {
+            lines.push(DiffLine::Added(new[j].clone()));
+            j += 1;
+        }
+    }
+    lines
+}
+
+/// Print a colored, unified-style diff between two plans
+pub fn print_diff(ui: &Ui, previous: &Plan, current: &Plan) {
+    let lines = diff(previous, current);
+    if lines.iter().all(|line| matches!(line, DiffLine::Same(_))) {
+        ui.line("No changes since the last recorded plan\n");
+        return;
+    }
+
+    print_line_diff(
+        ui,
+        &format!("{} (last run)", previous.task),
+        &format!("{} (current)", current.task),
+        &lines,
+    );
+}
+
+/// Print `lines` as a colored, unified-style diff from `old` to `new`, with
+/// a little context around each change
+pub fn print_line_diff(ui: &Ui, old: &str, new: &str, lines: &[DiffLine]) {
+    ui.line(ui.style(format!("--- {}", old)).red());
+    ui.line(ui.style(format!("+++ {}", new)).green());
+
+    let changed: Vec<bool> = lines
+        .iter()
+        .map(|line| !matches!(line, DiffLine::Same(_)))
+        .collect();
+    let near_change = |index: usize| {
+        let start = index.saturating_sub(CONTEXT_LINES);
+        let end = (index + CONTEXT_LINES + 1).min(lines.len());
+        changed[start..end].iter().any(|c| *c)
+    };
+
+    let mut skipped = false;
+    for (index, line) in lines.iter().enumerate() {
+        if !near_change(index) {
+            skipped = true;
+            continue;
+        }
+        if skipped {
+            ui.line(ui.style("@@ ... @@").cyan());
+            skipped = false;
+        }
+        match line {
+            DiffLine::Same(text) => ui.line(format!("  {}", text)),
+            DiffLine::Removed(text) => ui.line(ui.style(format!("- {}", text)).red()),
+            DiffLine::Added(text) => ui.line(ui.style(format!("+ {}", text)).green()),
+        }
+    }
+    ui.line("");
+}
+
+/// Render a plan as diffable lines
+fn render_lines(plan: &Plan) -> Vec<String> {
+    let mut lines = Vec::new();
+    for entry in &plan.entries {
+        lines.push(format!("[{}] {}", entry.step, entry.action));
+        lines.push(format!("[{}]   dir: {}", entry.step, entry.dir));
+        for (name, value) in &entry.env {
+            lines.push(format!("[{}]   env: {}={}", entry.step, name, value));
+        }
+    }
+    lines
+}
+
+/// `dir` relative to the project root, `.` for the root itself
+pub fn relative_dir(dir: &Path, root: &Path) -> String {
+    match dir.strip_prefix(root) {
+        Ok(relative) if relative.as_os_str().is_empty() => ".".to_string(),
+        Ok(relative) => relative.display().to_string(),
+        Err(_) => dir.display().to_string(),
+    }
+}
+
+/// Walks a task the same way the runner does, recording actions
+struct Planner<'a> {
+    root: &'a Path,
+    configs: &'a ConfigCache,
+    /// Steps of the planned task (not the tasks it delegates to) to leave out
+    filter: &'a StepFilter,
+    /// Where the planned task (not the tasks it delegates to) runs instead
+    /// of its own `dir`
+    dir: Option<&'a Path>,
+    entries: Vec<PlanEntry>,
+    /// Where each `once` task (by config file and name) was first planned
+    once: HashMap<(PathBuf, String), String>,
+    /// Planned tasks limited by `contexts:`
+    restricted: Vec<Restricted>,
+    /// Planned tasks with `not_between:`
+    windows: Vec<Windowed>,
+    /// Planned tasks with `require_*` git checks
+    git: Vec<GitGuarded>,
+    /// Key for the fingerprints of secret values, read once one is needed
+    salt: Option<secrets::Salt>,
+}
+
+impl Planner<'_> {
+    #[allow(clippy::too_many_arguments)]
+    fn task_def(
+        &mut self,
+        task_def: &TaskDef,
+        path: &str,
+        project_root: &Path,
+        config: &Config,
+        args: &TaskArgs,
+        caller_env: Option<&HashMap<String, String>>,
+        depth: usize,
+    ) -> Result<()> {
+        if depth > MAX_DEPTH {
+            anyhow::bail!(
+                "Task nesting is too deep at '{}' (circular task references?)",
+                path
+            );
+        }
+        match task_def {
+            TaskDef::Shorthand(cmd) => {
+                let cmd = Cmd::Shell(cmd.clone());
+                let env = caller_env.cloned().unwrap_or_default();
+                let work_dir = self.dir.filter(|_| depth == 0).unwrap_or(project_root);
+                self.command(path, &cmd, work_dir, &env, args, config, None);
+                Ok(())
+            }
+            TaskDef::Full(task) => {
+                self.task(task, path, project_root, config, args, caller_env, depth)
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn task(
+        &mut self,
+        task: &Task,
+        path: &str,
+        project_root: &Path,
+        config: &Config,
+        args: &TaskArgs,
+        caller_env: Option<&HashMap<String, String>>,
+        depth: usize,
+    ) -> Result<()> {
+        let dir = self.dir.filter(|_| depth == 0);
+        let work_dir = match (dir, &task.dir) {
+            (Some(dir), _) => dir.to_path_buf(),
+            (None, Some(dir)) => config::join_dir(project_root, dir),
+            (None, None) => project_root.to_path_buf(),
+        };
+        let mut env = task.described_env().unwrap_or_default();
+        env.extend(caller_env.cloned().unwrap_or_default());
+
+        if let Some(steps) = &task.steps {
+            let filter = (depth == 0).then_some(self.filter);
+            for (index, step) in steps.iter().enumerate() {
+                let step_path = format!("{}/{}", path, index + 1);
+                let allowed = |step_def: &StepDef| {
+                    filter.is_none_or(|filter| filter.allows(index + 1, step_def))
+                };
+                match step {
+                    Step::Simple(step_def) if step_def.sets_env() => {
+                        self.env_step(step_def, &step_path, &work_dir, &mut env, args)
+                    }
+                    Step::Simple(step_def) if !allowed(step_def) => {}
+                    Step::Simple(step_def) => {
+                        self.step_def(step_def, &step_path, &work_dir, &env, config, args, depth)?
+                    }
+                    Step::Parallel { parallel, .. } => {
+                        for (branch, step_def) in parallel.iter().enumerate() {
+                            if !allowed(step_def) {
+                                continue;
+                            }
+                            let branch_path = format!("{}.{}", step_path, branch + 1);
+                            self.step_def(
+                                step_def,
+                                &branch_path,
+                                &work_dir,
+                                &env,
+                                config,
+                                args,
+                                depth,
+                            )?;
+                        }
+                    }
+                    Step::Lanes { lanes, .. } => {
+                        let lanes = lanes.iter().filter(|lane| {
+                            filter.is_none_or(|filter| filter.allows_lane(index + 1, &lane.steps))
+                        });
+                        for lane in lanes {
+                            for (position, step_def) in lane.steps.iter().enumerate() {
+                                let lane_path =
+                                    format!("{}.{}/{}", step_path, lane.name, position + 1);
+                                self.step_def(
+                                    step_def, &lane_path, &work_dir, &env, config, args, depth,
+                                )?;
+                            }
+                        }
+                    }
+                }
+            }
+            return Ok(());
+        }
+
+        // A menu plans the option its arguments pick; one that asks plans nothing
+        if let Some(choose) = &task.choose {
+            let picked = choose::given(&args.extra)
+                .ok()
+                .and_then(|(choice, rest)| Some((choose.option(&choice?)?, rest)));
+            let Some((option, rest)) = picked else {
+                return Ok(());
+            };
+            let nested_dir = (task.dir.is_some() || dir.is_some()).then_some(work_dir.as_path());
+            let invocation = Invocation {
+                env: Some(&env),
+                args: &rest,
+            };
+            return self.delegate(
+                &option.task,
+                invocation,
+                path,
+                nested_dir,
+                project_root,
+                config,
+                depth,
+            );
+        }
+
+        if let Some(task_name) = &task.task {
+            let nested_dir = (task.dir.is_some() || dir.is_some()).then_some(work_dir.as_path());
+            let invocation = Invocation {
+                env: Some(&env),
+                args: task.task_args.as_deref().unwrap_or_default(),
+            };
+            return self.delegate(
+                task_name,
+                invocation,
+                path,
+                nested_dir,
+                project_root,
+                config,
+                depth,
+            );
+        }
+
+        if let Some(cmd) = &task.cmd {
+            self.command(path, cmd, &work_dir, &env, args, config, task.output_filter);
+            self.note_exit(task.expect_exit.as_ref(), None);
+        }
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn step_def(
+        &mut self,
+        step_def: &StepDef,
+        path: &str,
+        default_dir: &Path,
+        env: &HashMap<String, String>,
+        config: &Config,
+        args: &TaskArgs,
+        depth: usize,
+    ) -> Result<()> {
+        let work_dir = match &step_def.dir {
+            Some(dir) => config::join_dir(self.root, dir),
+            None => default_dir.to_path_buf(),
+        };
+
+        if let Some(task_name) = &step_def.task {
+            let nested_dir = step_def.dir.is_some().then_some(work_dir.as_path());
+            return self.delegate(
+                task_name,
+                step_def.invocation(),
+                path,
+                nested_dir,
+                self.root,
+                config,
+                depth,
+            );
+        }
+
+        let mut env = env.clone();
+        env.extend(step_def.env.clone().unwrap_or_default());
+        if let Some(cmd) = &step_def.cmd {
+            self.command(
+                path,
+                cmd,
+                &work_dir,
+                &env,
+                args,
+                config,
+                step_def.output_filter,
+            );
+            let hints = hints(step_def.priority.unwrap_or_default(), step_def.weight);
+            if let (Some(hints), Some(entry)) = (hints, self.entries.last_mut()) {
+                entry.why.push(format!("scheduling: {}", hints));
+            }
+            self.note_exit(
+                step_def.expect_exit.as_ref(),
+                step_def.export_exit.as_deref(),
+            );
+            return Ok(());
+        }
+        let Some(action) = describe_builtin(step_def) else {
+            return Ok(());
+        };
+        let why = vec!["run: builtin step, handled by rnr itself".to_string()];
+        self.push(path, action, &work_dir, &env, why);
+        Ok(())
+    }
+
+    /// An env step: its values join `env`, so they show on every later entry
+    fn env_step(
+        &mut self,
+        step_def: &StepDef,
+        path: &str,
+        default_dir: &Path,
+        env: &mut HashMap<String, String>,
+        args: &TaskArgs,
+    ) {
+        let work_dir = match &step_def.dir {
+            Some(dir) => config::join_dir(self.root, dir),
+            None => default_dir.to_path_buf(),
+        };
+        let mut why = vec!["run: env step, sets variables for the steps after it".to_string()];
+        if step_def.has_action() {
+            why[0] = "fail: 'env_file' needs a step of its own".to_string();
+        }
+        if let Some(file) = &step_def.env_file {
+            let file_path = work_dir.join(file);
+            if !file_path.exists() {
+                why.push(format!(
+                    "env_file: {} doesn't exist yet; it's read when the step runs",
+                    file
+                ));
+            } else {
+                match crate::builtins::read_env_file(&file_path) {
+                    Ok(vars) => env.extend(vars),
+                    Err(e) => why[0] = format!("fail: {:#}", e),
+                }
+            }
+        }
+        // Interpolation errors are reported by the runner; plan the raw values
+        let scope = Scope::new(&work_dir, env).with_args(&args.values);
+        for (name, value) in step_def.env.iter().flatten() {
+            let value = scope.render(value).unwrap_or_else(|_| value.clone());
+            env.insert(name.clone(), value);
+        }
+        self.push(path, describe_env_step(step_def), &work_dir, env, why);
+    }
+
+    /// Follow a `task:` reference into `config` or the nested rnr.yaml it names
+    #[allow(clippy::too_many_arguments)]
+    fn delegate(
+        &mut self,
+        reference: &str,
+        invocation: Invocation,
+        path: &str,
+        nested_dir: Option<&Path>,
+        project_root: &Path,
+        config: &Config,
+        depth: usize,
+    ) -> Result<()> {
+        let path = format!("{}/{}", path, reference);
+        let configs = self.configs;
+        let target = config::resolve_task_ref(reference, nested_dir, self.root, config, |p| {
+            Ok(configs.load(p)?)
+        })?;
+        let (task_name, config, dir) = match &target {
+            TaskTarget::Current(name) => (*name, config, project_root),
+            TaskTarget::Nested { name, config, dir } => (*name, config.as_ref(), dir.as_path()),
+        };
+
+        // A name in another case plans the one task it matches, as it runs
+        let task_name = config
+            .match_case(task_name, config.strict)?
+            .unwrap_or(task_name);
+        match config.get_task(task_name) {
+            Some(task) => {
+                // Like the runner, plan a `once` task only the first time
+                let first = self.entries.len();
+                if task.is_once() {
+                    let key = (
+                        config.path().unwrap_or(Path::new("")).to_path_buf(),
+                        task_name.to_string(),
+                    );
+                    if let Some(earlier) = self.once.get(&key) {
+                        let why = vec![format!("skip: once: true, already ran at {}", earlier)];
+                        let action = format!("task {} (already ran)", task_name);
+                        self.push(&path, action, dir, &HashMap::new(), why);
+                        return Ok(());
+                    }
+                    self.once.insert(key, path.clone());
+                }
+
+                // Binding errors are reported by the runner; plan without the args
+                let quiet = Ui::sink(std::io::sink());
+                let args =
+                    crate::args::bind(task_name, task, invocation.args, &quiet).unwrap_or_default();
+                let depth = depth + 1;
+                self.restrict(task_name, task, config);
+                self.task_def(task, &path, dir, config, &args, invocation.env, depth)?;
+                if task.is_once() {
+                    if let Some(entry) = self.entries.get_mut(first) {
+                        let note = format!("once: true, first use of '{}' in this run", task_name);
+                        entry.why.insert(1.min(entry.why.len()), note);
+                    }
+                }
+                Ok(())
+            }
+            None => {
+                self.missing(&path, task_name, dir);
+                Ok(())
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn command(
+        &mut self,
+        path: &str,
+        cmd: &Cmd,
+        work_dir: &Path,
+        env: &HashMap<String, String>,
+        args: &TaskArgs,
+        config: &Config,
+        filter: Option<OutputFilter>,
+    ) {
+        // Interpolation errors are reported by the runner; plan the raw command
+        let (line, mut why) = match crate::runner::command_line(cmd, work_dir, env, args, config) {
+            Ok(line) => {
+                let how = if cmd.is_direct() {
+                    "run: command, run directly without a shell"
+                } else {
+                    "run: command, run by the shell"
+                };
+                (line, vec![how.to_string()])
+            }
+            Err(e) => (cmd.to_string(), vec![format!("fail: {:#}", e)]),
+        };
+        let paths = bins::paths(config);
+        for name in bins::used_by_cmd(cmd) {
+            if let Some(path) = paths.get(&name) {
+                why.push(format!("bins: {} is {}", name, path));
+            }
+        }
+        if let Some(filter) = filter {
+            why.push(format!(
+                "output_filter: stdout passes through {} (off with --verbose)",
+                filter
+            ));
+        }
+        let action = format!("$ {}", secrets::redact(&line, env));
+        self.push(path, action, work_dir, env, why);
+    }
+
+    /// Note on the last command which exit codes count as success, and the
+    /// variable that gets its code
+    fn note_exit(&mut self, expect: Option<&ExpectExit>, export: Option<&str>) {
+        let Some(entry) = self.entries.last_mut() else {
+            return;
+        };
+        if let Some(expect) = expect {
+            entry
+                .why
+                .push(format!("expect_exit: succeeds with {}", expect));
+        }
+        if let Some(name) = export {
+            entry.why.push(format!(
+                "export_exit: sets {} to the exit code for the following steps",
+                name
+            ));
+        }
+    }
+
+    /// Note task `name` if it has a `contexts` restriction, a
+    /// `not_between` window, or git requirements (once however often it's
+    /// reached)
+    fn restrict(&mut self, name: &str, task: &TaskDef, config: &Config) {
+        if let Some(requirements) = task.git_requirements() {
+            if !self.git.iter().any(|guarded| guarded.task == name) {
+                self.git.push(GitGuarded {
+                    task: name.to_string(),
+                    requirements,
+                });
+            }
+        }
+        if let Some(window) = task.not_between() {
+            self.windows.push(Windowed {
+                task: name.to_string(),
+                window: *window,
+                tz: config.tz,
+            });
+        }
+        if let Some(contexts) = task.contexts() {
+            self.restricted.push(Restricted {
+                task: name.to_string(),
+                contexts: contexts.clone(),
+                known: config
+                    .known_contexts()
+                    .into_iter()
+                    .map(String::from)
+                    .collect(),
+            });
+        }
+    }
+
+    fn missing(&mut self, path: &str, task_name: &str, work_dir: &Path) {
+        let action = format!("task {} (not found)", task_name);
+        let why = vec![format!("fail: no task named '{}'", task_name)];
+        self.push(path, action, work_dir, &HashMap::new(), why);
+    }
+
+    fn push(
+        &mut self,
+        path: &str,
+        action: String,
+        work_dir: &Path,
+        env: &HashMap<String, String>,
+        why: Vec<String>,
+    ) {
+        let dir = relative_dir(work_dir, self.root);
+        let root = self.root;
+        let env = env
+            .iter()
+            .map(|(name, value)| {
+                let value = if secrets::is_secret_name(name) && !config::is_computed_marker(value) {
+                    let salt = self
+                        .salt
+                        .get_or_insert_with(|| secrets::Salt::for_project(root));
+                    secrets::mask(value, salt)
+                } else {
+                    value.clone()
+                };
+                (name.clone(), value)
+            })
+            .collect();
+
+        self.entries.push(PlanEntry {
+            step: path.to_string(),
+            action,
+            dir,
+            env,
+            why,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plan_for(yaml: &str, task: &str) -> Plan {
+        // Masking a secret keeps the project's salt under its root
+        plan_in(tempfile::tempdir().unwrap().path(), yaml, task)
+    }
+
+    fn plan_in(root: &Path, yaml: &str, task: &str) -> Plan {
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        build(
+            task,
+            config.get_task(task).unwrap(),
+            root,
+            None,
+            &config,
+            &ConfigCache::default(),
+            &TaskArgs::default(),
+            &StepFilter::default(),
+        )
+        .unwrap()
+    }
+
+    const CONFIG: &str = r#"
+lint: cargo clippy
+ci:
+  env:
+    RUST_LOG: info
+    DEPLOY_TOKEN: abc123
+  steps:
+    - task: lint
+    - parallel:
+        - cmd: cargo test
+        - mkdir: dist
+    - cmd: cargo build
+      dir: app
+"#;
+
+    #[test]
+    fn test_build_plan() {
+        let plan = plan_for(CONFIG, "ci");
+        let steps: Vec<(&str, &str, &str)> = plan
+            .entries
+            .iter()
+            .map(|e| (e.step.as_str(), e.action.as_str(), e.dir.as_str()))
+            .collect();
+        assert_eq!(
+            steps,
+            vec![
+                ("ci/1/lint", "$ cargo clippy", "."),
+                ("ci/2.1", "$ cargo test", "."),
+                ("ci/2.2", "mkdir dist", "."),
+                ("ci/3", "$ cargo build", "app"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_scheduling_hints() {
+        let yaml = "ci:\n  steps:\n    - parallel:\n        - cmd: cargo build\n          weight: 4\n          priority: low\n        - task: lint\n          priority: high\n        - cmd: ./lint.sh\n          priority: normal\nlint: cargo clippy\n";
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        let Some(TaskDef::Full(ci)) = config.get_task("ci") else {
+            panic!("Expected full task");
+        };
+        let labels: Vec<String> = ci.steps.as_ref().unwrap()[0]
+            .step_defs()
+            .into_iter()
+            .map(describe)
+            .collect();
+        assert_eq!(
+            labels,
+            [
+                "$ cargo build (priority: low, weight: 4)",
+                "task lint (priority: high)",
+                "$ ./lint.sh",
+            ]
+        );
+
+        let plan = plan_for(yaml, "ci");
+        assert_eq!(
+            plan.entries[0].why.last().unwrap(),
+            "scheduling: priority: low, weight: 4"
+        );
+        assert!(!plan.entries[2]
+            .why
+            .iter()
+            .any(|why| why.starts_with("scheduling")));
+    }
+
+    #[test]
+    fn test_expected_exit_codes_noted() {
+        let yaml = "ci:\n  steps:\n    - cmd: diff a b\n      expect_exit: [0, 1]\n      export_exit: DIFF_STATUS\ncheck:\n  cmd: ./check.sh\n  expect_exit: any\n";
+        let plan = plan_for(yaml, "ci");
+        assert_eq!(
+            plan.entries[0].why[1..],
+            [
+                "expect_exit: succeeds with exit codes 0 or 1",
+                "export_exit: sets DIFF_STATUS to the exit code for the following steps",
+            ]
+        );
+        let plan = plan_for(yaml, "check");
+        assert_eq!(
+            plan.entries[0].why.last().unwrap(),
+            "expect_exit: succeeds with any exit code"
+        );
+    }
+
+    #[test]
+    fn test_descriptions_name_steps() {
+        let yaml = "ci:\n  steps:\n    - description: Integration tests\n      cmd: docker run --rm -v .:/src ci-image make test\n      weight: 2\n    - task: lint\nlint: cargo clippy\n";
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        let Some(TaskDef::Full(ci)) = config.get_task("ci") else {
+            panic!("Expected full task");
+        };
+        let labels: Vec<String> = ci
+            .steps
+            .as_ref()
+            .unwrap()
+            .iter()
+            .flat_map(Step::step_defs)
+            .map(describe)
+            .collect();
+        assert_eq!(labels, ["Integration tests (weight: 2)", "task lint"]);
+    }
+
+    #[test]
+    fn test_bins_show_their_paths() {
+        let plan = plan_for(
+            "bins:\n  dprint: .tools/dprint\nfmt:\n  cmd: [\"${bins.dprint}\", fmt]\n",
+            "fmt",
+        );
+        let dprint = Path::new(".tools").join("dprint").display().to_string();
+        let entry = &plan.entries[0];
+        assert!(entry.action.ends_with(&format!("{} fmt", dprint)));
+        assert!(entry.why.contains(&format!("bins: dprint is {}", dprint)));
+    }
+
+    #[test]
+    fn test_build_plan_masks_secrets() {
+        let plan = plan_for(CONFIG, "ci");
+        let env = &plan.entries[1].env;
+        assert_eq!(env["RUST_LOG"], "info");
+        assert_ne!(env["DEPLOY_TOKEN"], "abc123");
+        assert!(env["DEPLOY_TOKEN"].starts_with("****"));
+    }
+
+    #[test]
+    fn test_build_plan_missing_task() {
+        let plan = plan_for("ci:\n  steps:\n    - task: nope\n", "ci");
+        assert_eq!(plan.entries[0].action, "task nope (not found)");
+    }
+
+    #[test]
+    fn test_build_plan_delegates_with_env_and_args() {
+        let plan = plan_for(
+            "ci:\n  steps:\n    - task: deploy\n      env:\n        TARGET: staging\n      args: [--dry-run]\n    - task: deploy\ndeploy:\n  env:\n    TARGET: prod\n  cmd: ./deploy.sh ${env.TARGET}\n",
+            "ci",
+        );
+        let actions: Vec<(&str, &str)> = plan
+            .entries
+            .iter()
+            .map(|e| (e.action.as_str(), e.env["TARGET"].as_str()))
+            .collect();
+        assert_eq!(
+            actions,
+            vec![
+                ("$ ./deploy.sh staging --dry-run", "staging"),
+                ("$ ./deploy.sh prod", "prod"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_build_plan_env_steps() {
+        let plan = plan_for(
+            "ci:\n  env:\n    STAGE: base\n  steps:\n    - cmd: make codegen\n    - env:\n        STAGE: ${env.STAGE}-next\n        COMPOSE_PROJECT_NAME: demo\n    - env_file: .env.local\n    - cmd: docker compose up\n",
+            "ci",
+        );
+        let entries: Vec<(&str, &str, Option<&str>)> = plan
+            .entries
+            .iter()
+            .map(|e| {
+                let compose = e.env.get("COMPOSE_PROJECT_NAME").map(String::as_str);
+                (e.action.as_str(), e.env["STAGE"].as_str(), compose)
+            })
+            .collect();
+        assert_eq!(
+            entries,
+            vec![
+                ("$ make codegen", "base", None),
+                ("env COMPOSE_PROJECT_NAME, STAGE", "base-next", Some("demo")),
+                ("env_file .env.local", "base-next", Some("demo")),
+                ("$ docker compose up", "base-next", Some("demo")),
+            ]
+        );
+        assert_eq!(
+            plan.entries[2].why,
+            [
+                "run: env step, sets variables for the steps after it",
+                "env_file: .env.local doesn't exist yet; it's read when the step runs",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_build_plan_lanes() {
+        let plan = plan_for(
+            "ci:\n  steps:\n    - parallel:\n        api:\n          - cmd: cargo build\n          - cmd: cargo test\n        web:\n          - cmd: npm run build\n",
+            "ci",
+        );
+        let steps: Vec<(&str, &str)> = plan
+            .entries
+            .iter()
+            .map(|e| (e.step.as_str(), e.action.as_str()))
+            .collect();
+        assert_eq!(
+            steps,
+            vec![
+                ("ci/1.api/1", "$ cargo build"),
+                ("ci/1.api/2", "$ cargo test"),
+                ("ci/1.web/1", "$ npm run build"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_build_plan_leaves_out_filtered_steps() {
+        let config: Config = serde_yaml::from_str(
+            "ci:\n  steps:\n    - task: lint\n    - env:\n        MODE: ci\n    - parallel:\n        - cmd: cargo test\n          tags: [slow]\n        - cmd: cargo doc\n    - parallel:\n        api:\n          - cmd: cargo bench\n            tags: [slow]\n        web:\n          - cmd: npm test\nlint:\n  steps:\n    - cmd: cargo clippy\n      tags: [slow]\n",
+        )
+        .unwrap();
+        let skip = StepFilter::new(&[], &["slow".to_string()], None).unwrap();
+        let plan = build(
+            "ci",
+            config.get_task("ci").unwrap(),
+            Path::new("/project"),
+            None,
+            &config,
+            &ConfigCache::default(),
+            &TaskArgs::default(),
+            &skip,
+        )
+        .unwrap();
+        let steps: Vec<(&str, &str)> = plan
+            .entries
+            .iter()
+            .map(|e| (e.step.as_str(), e.action.as_str()))
+            .collect();
+        assert_eq!(
+            steps,
+            vec![
+                ("ci/1/lint/1", "$ cargo clippy"),
+                ("ci/2", "env MODE"),
+                ("ci/3.2", "$ cargo doc"),
+                ("ci/4.web/1", "$ npm test"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_build_plan_circular_reference() {
+        let config: Config = serde_yaml::from_str("a:\n  task: b\nb:\n  task: a\n").unwrap();
+        let result = build(
+            "a",
+            config.get_task("a").unwrap(),
+            Path::new("/project"),
+            None,
+            &config,
+            &ConfigCache::default(),
+            &TaskArgs::default(),
+            &StepFilter::default(),
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_diff_detects_changes() {
+        let root = tempfile::tempdir().unwrap();
+        let previous = plan_in(root.path(), CONFIG, "ci");
+        let current = plan_in(
+            root.path(),
+            &CONFIG.replace("cargo test", "cargo nextest run"),
+            "ci",
+        );
+        let changes: Vec<DiffLine> = diff(&previous, &current)
+            .into_iter()
+            .filter(|line| !matches!(line, DiffLine::Same(_)))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                DiffLine::Removed("[ci/2.1] $ cargo test".to_string()),
+                DiffLine::Added("[ci/2.1] $ cargo nextest run".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_diff_unchanged() {
+        let root = tempfile::tempdir().unwrap();
+        let previous = plan_in(root.path(), CONFIG, "ci");
+        let current = plan_in(root.path(), CONFIG, "ci");
+        assert!(diff(&previous, &current)
+            .iter()
+            .all(|line| matches!(line, DiffLine::Same(_))));
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let temp = tempfile::tempdir().unwrap();
+        assert!(load(temp.path(), "api:build").unwrap().is_none());
+
+        let plan = plan_for(CONFIG, "ci");
+        save(temp.path(), &plan).unwrap();
+        assert_eq!(load(temp.path(), "ci").unwrap(), Some(plan));
+        assert!(plan_path(temp.path(), "api:build").ends_with("api_build.json"));
+    }
+}
@@ -2,6 +2,9 @@
 
 use std::fmt;
 
+#[cfg(feature = "network")]
+use crate::archive::Packaging;
+
 /// All supported platforms
 pub const ALL_PLATFORMS: &[Platform] = &[
     Platform::LinuxAmd64,
@@ -33,6 +36,16 @@ impl Platform {
         }
     }
 
+    /// Operating system half of the identifier ("linux", "macos", "windows")
+    pub fn os(&self) -> &'static str {
+        self.id().split('-').next().unwrap_or_default()
+    }
+
+    /// Architecture half of the identifier ("amd64", "arm64")
+    pub fn arch(&self) -> &'static str {
+        self.id().rsplit('-').next().unwrap_or_default()
+    }
+
     /// Get the binary filename for this platform
     pub fn binary_name(&self) -> &'static str {
         match self {
@@ -44,14 +57,39 @@ impl Platform {
         }
     }
 
-    /// Get the approximate binary size in bytes
+    /// Release asset name of the slim build (no network stack) that projects commit
+    #[cfg(feature = "network")]
+    pub fn slim_asset_name(&self) -> String {
+        format!("rnr-slim-{}", &self.binary_name()["rnr-".len()..])
+    }
+
+    /// Release assets that can hold this platform's binary, in the order
+    /// they're tried: the slim build before the full one, each published
+    /// bare or in an archive (`.zip` for Windows, `.tar.gz` otherwise)
+    #[cfg(feature = "network")]
+    pub fn release_assets(&self) -> Vec<String> {
+        let archive = match self.os() {
+            "windows" => Packaging::Zip,
+            _ => Packaging::TarGz,
+        };
+        let stem = |name: &str| name.strip_suffix(".exe").unwrap_or(name).to_string();
+        let slim = self.slim_asset_name();
+        vec![
+            slim.clone(),
+            self.binary_name().to_string(),
+            format!("{}{}", stem(&slim), archive.extension()),
+            format!("{}{}", stem(self.binary_name()), archive.extension()),
+        ]
+    }
+
+    /// Get the approximate size in bytes of the slim release binary
     pub fn size_bytes(&self) -> u64 {
         match self {
-            Platform::LinuxAmd64 => 760 * 1024,
-            Platform::MacosAmd64 => 662 * 1024,
-            Platform::MacosArm64 => 608 * 1024,
-            Platform::WindowsAmd64 => 584 * 1024,
-            Platform::WindowsArm64 => 528 * 1024,
+            Platform::LinuxAmd64 => 1180 * 1024,
+            Platform::MacosAmd64 => 1028 * 1024,
+            Platform::MacosArm64 => 944 * 1024,
+            Platform::WindowsAmd64 => 907 * 1024,
+            Platform::WindowsArm64 => 820 * 1024,
         }
     }
 
@@ -61,6 +99,16 @@ impl Platform {
         format!("{} KB", kb)
     }
 
+    /// Platform whose binary can run here under emulation when this one isn't
+    /// available (x64 binaries via Rosetta on macOS or x64 emulation on Windows)
+    pub fn emulation_fallback(&self) -> Option<Platform> {
+        match self {
+            Platform::MacosArm64 => Some(Platform::MacosAmd64),
+            Platform::WindowsArm64 => Some(Platform::WindowsAmd64),
+            _ => None,
+        }
+    }
+
     /// Parse a platform from its identifier string
     pub fn from_id(id: &str) -> Option<Platform> {
         match id {
@@ -93,6 +141,37 @@ impl Platform {
         #[allow(unreachable_code)]
         None
     }
+
+    /// The platform of the machine itself, which the wrappers prefer. It
+    /// differs from [`Platform::current`] when an amd64 rnr runs under
+    /// emulation on ARM64 Windows.
+    pub fn native() -> Option<Platform> {
+        match Self::current()? {
+            Platform::WindowsAmd64 | Platform::WindowsArm64 => {
+                Some(Self::windows_machine(|name| std::env::var(name).ok()))
+            }
+            current => Some(current),
+        }
+    }
+
+    /// The Windows platform the variables `var` reads describe, the way
+    /// rnr.cmd decides: under x64 or x86 emulation on ARM64,
+    /// `PROCESSOR_ARCHITECTURE` names the emulated architecture, but
+    /// `PROCESSOR_ARCHITEW6432` or the CPU's name (`PROCESSOR_IDENTIFIER`)
+    /// still says ARM
+    pub fn windows_machine(var: impl Fn(&str) -> Option<String>) -> Platform {
+        let is = |name: &str, arm: fn(&str) -> bool| {
+            var(name).is_some_and(|value| arm(&value.to_ascii_uppercase()))
+        };
+        if is("PROCESSOR_ARCHITECTURE", |v| v == "ARM64")
+            || is("PROCESSOR_ARCHITEW6432", |v| v == "ARM64")
+            || is("PROCESSOR_IDENTIFIER", |v| v.starts_with("ARM"))
+        {
+            Platform::WindowsArm64
+        } else {
+            Platform::WindowsAmd64
+        }
+    }
 }
 
 impl fmt::Display for Platform {
@@ -101,6 +180,63 @@ impl fmt::Display for Platform {
     }
 }
 
+/// The OS and architecture an executable was built for, read from its header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Target {
+    pub os: &'static str,
+    /// `None` for universal binaries and architectures rnr doesn't ship
+    pub arch: Option<&'static str>,
+}
+
+impl Target {
+    /// Identify an ELF, Mach-O, or PE executable from its first bytes
+    pub fn from_header(header: &[u8]) -> Option<Target> {
+        let u16_at = |at: usize| Some(u16::from_le_bytes(header.get(at..at + 2)?.try_into().ok()?));
+        let u32_at = |at: usize| Some(u32::from_le_bytes(header.get(at..at + 4)?.try_into().ok()?));
+        let target = |os, arch| Some(Target { os, arch });
+
+        match header.get(..4)? {
+            [0x7f, b'E', b'L', b'F'] => match u16_at(18) {
+                Some(0x3e) => target("linux", Some("amd64")),
+                Some(0xb7) => target("linux", Some("arm64")),
+                _ => target("linux", None),
+            },
+            [0xcf, 0xfa, 0xed, 0xfe] => match u32_at(4) {
+                Some(0x0100_0007) => target("macos", Some("amd64")),
+                Some(0x0100_000c) => target("macos", Some("arm64")),
+                _ => target("macos", None),
+            },
+            [0xca, 0xfe, 0xba, 0xbe] => target("macos", None),
+            [b'M', b'Z', ..] => {
+                let pe = u32_at(0x3c)? as usize;
+                if header.get(pe..pe + 4) != Some(b"PE\0\0") {
+                    return target("windows", None);
+                }
+                match u16_at(pe + 4) {
+                    Some(0x8664) => target("windows", Some("amd64")),
+                    Some(0xaa64) => target("windows", Some("arm64")),
+                    _ => target("windows", None),
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether a binary built for this target can be `platform`'s binary
+    pub fn matches(&self, platform: Platform) -> bool {
+        self.os == platform.os() && self.arch.is_none_or(|arch| arch == platform.arch())
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.arch {
+            Some(arch) => write!(f, "{}-{}", self.os, arch),
+            None => f.write_str(self.os),
+        }
+    }
+}
+
 /// Calculate total size for a set of platforms
 pub fn total_size(platforms: &[Platform]) -> u64 {
     platforms.iter().map(|p| p.size_bytes()).sum()
@@ -110,8 +246,10 @@ pub fn total_size(platforms: &[Platform]) -> u64 {
 pub fn format_size(bytes: u64) -> String {
     if bytes >= 1024 * 1024 {
         format!("{:.2} MB", bytes as f64 / (1024.0 * 1024.0))
-    } else {
+    } else if bytes >= 1024 {
         format!("{} KB", bytes / 1024)
+    } else {
+        format!("{} bytes", bytes)
     }
 }
 
@@ -137,6 +275,102 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_windows_machine() {
+        let machine = |vars: &[(&str, &str)]| {
+            Platform::windows_machine(|name| {
+                vars.iter()
+                    .find(|(key, _)| *key == name)
+                    .map(|(_, value)| value.to_string())
+            })
+        };
+        let intel = "Intel64 Family 6 Model 154 Stepping 3, GenuineIntel";
+        let arm = "ARMv8 (64-bit) Family 8 Model 1 Revision 201, Qualcomm Technologies Inc";
+
+        assert_eq!(
+            machine(&[
+                ("PROCESSOR_ARCHITECTURE", "AMD64"),
+                ("PROCESSOR_IDENTIFIER", intel)
+            ]),
+            Platform::WindowsAmd64
+        );
+        assert_eq!(
+            machine(&[("PROCESSOR_ARCHITECTURE", "ARM64")]),
+            Platform::WindowsArm64
+        );
+        // An x86 shell on ARM64 Windows
+        assert_eq!(
+            machine(&[
+                ("PROCESSOR_ARCHITECTURE", "x86"),
+                ("PROCESSOR_ARCHITEW6432", "ARM64")
+            ]),
+            Platform::WindowsArm64
+        );
+        // An x64 shell on ARM64 Windows, where only the CPU's name tells
+        assert_eq!(
+            machine(&[
+                ("PROCESSOR_ARCHITECTURE", "AMD64"),
+                ("PROCESSOR_IDENTIFIER", arm)
+            ]),
+            Platform::WindowsArm64
+        );
+        assert_eq!(machine(&[]), Platform::WindowsAmd64);
+    }
+
+    #[test]
+    fn test_emulation_fallback() {
+        assert_eq!(
+            Platform::MacosArm64.emulation_fallback(),
+            Some(Platform::MacosAmd64)
+        );
+        assert_eq!(
+            Platform::WindowsArm64.emulation_fallback(),
+            Some(Platform::WindowsAmd64)
+        );
+        assert_eq!(Platform::LinuxAmd64.emulation_fallback(), None);
+    }
+
+    #[test]
+    fn test_target_from_header() {
+        let mut elf = vec![0x7f, b'E', b'L', b'F'];
+        elf.resize(20, 0);
+        elf[18] = 0x3e;
+        let linux = Target::from_header(&elf).unwrap();
+        assert_eq!(linux.to_string(), "linux-amd64");
+        assert!(linux.matches(Platform::LinuxAmd64));
+        assert!(!linux.matches(Platform::MacosAmd64));
+
+        let mut macho = vec![0xcf, 0xfa, 0xed, 0xfe];
+        macho.extend(0x0100_000cu32.to_le_bytes());
+        let macos = Target::from_header(&macho).unwrap();
+        assert_eq!(macos.to_string(), "macos-arm64");
+        assert!(!macos.matches(Platform::MacosAmd64));
+
+        let universal = Target::from_header(&[0xca, 0xfe, 0xba, 0xbe]).unwrap();
+        assert!(universal.matches(Platform::MacosAmd64));
+        assert!(universal.matches(Platform::MacosArm64));
+
+        let mut pe = vec![0; 0x48];
+        pe[..2].copy_from_slice(b"MZ");
+        pe[0x3c] = 0x40;
+        pe[0x40..0x44].copy_from_slice(b"PE\0\0");
+        pe[0x44..0x46].copy_from_slice(&0xaa64u16.to_le_bytes());
+        assert_eq!(
+            Target::from_header(&pe).unwrap().to_string(),
+            "windows-arm64"
+        );
+
+        assert_eq!(Target::from_header(b"#!/bin/sh\n"), None);
+        assert_eq!(Target::from_header(b""), None);
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(500), "500 bytes");
+        assert_eq!(format_size(760 * 1024), "760 KB");
+        assert_eq!(format_size(2 * 1024 * 1024), "2.00 MB");
+    }
+
     #[test]
     fn test_binary_names() {
         assert_eq!(Platform::LinuxAmd64.binary_name(), "rnr-linux-amd64");
@@ -145,4 +379,40 @@ mod tests {
             "rnr-windows-amd64.exe"
         );
     }
+
+    #[test]
+    #[cfg(feature = "network")]
+    fn test_slim_asset_names() {
+        assert_eq!(
+            Platform::LinuxAmd64.slim_asset_name(),
+            "rnr-slim-linux-amd64"
+        );
+        assert_eq!(
+            Platform::WindowsArm64.slim_asset_name(),
+            "rnr-slim-windows-arm64.exe"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "network")]
+    fn test_release_assets() {
+        assert_eq!(
+            Platform::LinuxAmd64.release_assets(),
+            [
+                "rnr-slim-linux-amd64",
+                "rnr-linux-amd64",
+                "rnr-slim-linux-amd64.tar.gz",
+                "rnr-linux-amd64.tar.gz",
+            ]
+        );
+        assert_eq!(
+            Platform::WindowsArm64.release_assets(),
+            [
+                "rnr-slim-windows-arm64.exe",
+                "rnr-windows-arm64.exe",
+                "rnr-slim-windows-arm64.zip",
+                "rnr-windows-arm64.zip",
+            ]
+        );
+    }
 }
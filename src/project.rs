@@ -0,0 +1,160 @@
+//! The project's rnr.yaml, found and parsed the first time a command asks
+//!
+//! Commands that don't need tasks (`--version`, `--help`, `init`, usage
+//! errors) never search for or read rnr.yaml. Set `RNR_PROFILE_STARTUP=1`
+//! to see how long discovery, parsing, and validation take on stderr.
+
+use anyhow::{Context, Result};
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use std::time::Instant;
+
+use crate::config::{self, Config};
+use crate::ui::Ui;
+
+/// Set to `1` to time each startup phase
+pub const PROFILE_ENV: &str = "RNR_PROFILE_STARTUP";
+
+/// Lazily discovered and loaded project configuration
+#[derive(Debug)]
+pub struct Project {
+    ui: Ui,
+    strict: bool,
+    profile: bool,
+    path: OnceLock<PathBuf>,
+    config: OnceLock<Arc<Config>>,
+    validated: OnceLock<()>,
+}
+
+impl Project {
+    /// The project around the current directory; nothing is read yet
+    pub fn new(strict: bool, ui: &Ui) -> Self {
+        Self {
+            ui: ui.clone(),
+            strict,
+            profile: std::env::var_os(PROFILE_ENV).is_some_and(|v| v == "1"),
+            path: OnceLock::new(),
+            config: OnceLock::new(),
+            validated: OnceLock::new(),
+        }
+    }
+
+    /// The project whose rnr.yaml is at `path`, skipping discovery
+    pub fn at(path: &Path, strict: bool, ui: &Ui) -> Self {
+        let project = Self::new(strict, ui);
+        let _ = project.path.set(path.to_path_buf());
+        project
+    }
+
+    /// Path of the project's rnr.yaml, searched for on first use
+    pub fn config_path(&self) -> Result<&Path> {
+        if let Some(path) = self.path.get() {
+            return Ok(path);
+        }
+        let path = self.timed("discovery", config::find_config_file)?;
+        self.note(format_args!("found {}", path.display()));
+        Ok(self.path.get_or_init(|| path))
+    }
+
+    /// The directory containing the project's rnr.yaml
+    pub fn root(&self) -> Result<&Path> {
+        self.config_path()?
+            .parent()
+            .context("Config file has no parent directory")
+    }
+
+    /// The parsed rnr.yaml, read on first use
+    pub fn config(&self) -> Result<Arc<Config>> {
+        if let Some(config) = self.config.get() {
+            return Ok(Arc::clone(config));
+        }
+        let path = self.config_path()?;
+        let config = Arc::new(self.timed("parse", || Config::load_from(path))?);
+        Ok(Arc::clone(self.config.get_or_init(|| config)))
+    }
+
+    /// The parsed rnr.yaml, refusing unknown task fields in strict mode
+    pub fn validated_config(&self) -> Result<Arc<Config>> {
+        let config = self.config()?;
+        if self.validated.get().is_none() {
+            if self.strict || config.strict {
+                let path = self.config_path()?;
+                self.timed("validation", || config.check_unknown_fields(path))?;
+            }
+            let _ = self.validated.set(());
+        }
+        Ok(config)
+    }
+
+    /// Run one startup phase, reporting its duration when profiling
+    fn timed<T, E>(&self, phase: &str, f: impl FnOnce() -> Result<T, E>) -> Result<T, E> {
+        let started = Instant::now();
+        let result = f();
+        let elapsed = started.elapsed().as_secs_f64() * 1000.0;
+        self.note(format_args!("{} {:.2}ms", phase, elapsed));
+        result
+    }
+
+    /// A profiling line on stderr
+    fn note(&self, text: impl Display) {
+        if self.profile {
+            self.ui.print_err(format!("startup: {}\n", text));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_reads_config_once() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join(config::CONFIG_FILE);
+        fs::write(&path, "build: cargo build\n").unwrap();
+        let (ui, _) = Ui::capture(false);
+        let project = Project::at(&path, false, &ui);
+        assert_eq!(project.root().unwrap(), temp.path());
+
+        let first = project.config().unwrap();
+        fs::remove_file(&path).unwrap();
+        let second = project.config().unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+    }
+
+    #[test]
+    fn test_validation_follows_strict_mode() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join(config::CONFIG_FILE);
+        fs::write(&path, "build:\n  comand: make\n").unwrap();
+        let (ui, _) = Ui::capture(false);
+        assert!(Project::at(&path, false, &ui).validated_config().is_ok());
+        let err = Project::at(&path, true, &ui)
+            .validated_config()
+            .unwrap_err();
+        assert!(err.to_string().contains("'comand'"), "{}", err);
+    }
+
+    #[test]
+    fn test_profile_reports_each_phase() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join(config::CONFIG_FILE);
+        fs::write(&path, "strict: true\nbuild: cargo build\n").unwrap();
+        let (ui, capture) = Ui::capture(false);
+        let project = Project {
+            profile: true,
+            ..Project::at(&path, false, &ui)
+        };
+        project.validated_config().unwrap();
+        project.validated_config().unwrap();
+
+        let stderr = capture.stderr();
+        let phases: Vec<&str> = stderr
+            .lines()
+            .map(|line| line.split_whitespace().nth(1).unwrap())
+            .collect();
+        assert_eq!(phases, ["parse", "validation"]);
+    }
+}
@@ -1,11 +1,19 @@
 //! RNR configuration file management (.rnr/config.yaml)
 
 use anyhow::{Context, Result};
+use clap::ValueEnum;
 use serde::{Deserialize, Serialize};
-use std::fs;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File, TryLockError};
+use std::io::{self, Write};
 use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
+use crate::checksum;
+use crate::config;
+use crate::permissions;
 use crate::platform::Platform;
+use crate::self_replace;
 
 /// The rnr configuration directory name
 pub const RNR_DIR: &str = ".rnr";
@@ -13,37 +21,160 @@ pub const RNR_DIR: &str = ".rnr";
 pub const CONFIG_FILE: &str = "config.yaml";
 /// The binary directory name
 pub const BIN_DIR: &str = "bin";
+/// The file locked while .rnr/config.yaml is being read and rewritten
+pub const LOCK_FILE: &str = ".lock";
+/// How long to wait for another rnr process to finish with the project
+pub const LOCK_WAIT: Duration = Duration::from_secs(60);
+/// The ignore file rnr keeps in .rnr
+pub const IGNORE_FILE: &str = ".gitignore";
+/// What .rnr/.gitignore leaves out of commits: the cache (which holds the
+/// secret salt), clean checkouts and the lock file
+pub const IGNORED: &str =
+    "# Written by rnr: local state that isn't committed\n/cache/\n/tmp/\n/.lock\n";
 
 /// RNR configuration stored in .rnr/config.yaml
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(from = "RawRnrConfig")]
 pub struct RnrConfig {
-    /// Version of rnr that created this config
+    /// Version of rnr the project is on
     pub version: String,
-    /// List of configured platform identifiers
-    pub platforms: Vec<String>,
+    /// Release channel `rnr upgrade` follows
+    #[serde(default)]
+    pub channel: Channel,
+    /// Whether the wrappers run committed binaries or the rnr on PATH
+    #[serde(default, skip_serializing_if = "Mode::is_binaries")]
+    pub mode: Mode,
+    /// Installed binaries keyed by platform identifier
+    pub binaries: BTreeMap<String, BinaryEntry>,
+}
+
+/// What the project's wrapper scripts run
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    /// The binaries committed to .rnr/bin
+    #[default]
+    Binaries,
+    /// The rnr installed on PATH (set up by a build that can't download)
+    System,
+}
+
+impl Mode {
+    fn is_binaries(&self) -> bool {
+        *self == Mode::Binaries
+    }
+}
+
+/// Which releases `rnr upgrade` considers
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Channel {
+    /// Only full releases
+    #[default]
+    Stable,
+    /// Full releases and prereleases, whichever is newest
+    Prerelease,
+}
+
+impl std::fmt::Display for Channel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Channel::Stable => write!(f, "stable"),
+            Channel::Prerelease => write!(f, "prerelease"),
+        }
+    }
+}
+
+/// What is known about one binary in .rnr/bin
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BinaryEntry {
+    /// rnr version of the binary
+    pub version: String,
+    /// SHA-256 of the binary when it was installed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// Size in bytes when it was installed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// .rnr/bin holds a placeholder for this binary (no release was available)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub placeholder: bool,
+    /// Names of the files in .rnr/bin kept for this platform, so ones a later
+    /// version stops using can be removed (empty in older configs)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<String>,
+}
+
+/// On-disk shape of config.yaml, accepting the older flat `platforms` list
+#[derive(Deserialize)]
+struct RawRnrConfig {
+    version: String,
+    #[serde(default)]
+    channel: Channel,
+    #[serde(default)]
+    mode: Mode,
+    #[serde(default)]
+    binaries: BTreeMap<String, BinaryEntry>,
+    /// Pre-checksum configs only listed platform identifiers
+    #[serde(default)]
+    platforms: Vec<String>,
+}
+
+impl From<RawRnrConfig> for RnrConfig {
+    fn from(raw: RawRnrConfig) -> Self {
+        let mut binaries = raw.binaries;
+        for id in raw.platforms {
+            binaries.entry(id).or_insert_with(|| BinaryEntry {
+                version: raw.version.clone(),
+                sha256: None,
+                size: None,
+                placeholder: false,
+                files: Vec::new(),
+            });
+        }
+        Self {
+            version: raw.version,
+            channel: raw.channel,
+            mode: raw.mode,
+            binaries,
+        }
+    }
 }
 
 impl RnrConfig {
     /// Create a new config with the given platforms
     pub fn new(version: &str, platforms: &[Platform]) -> Self {
-        Self {
+        let mut config = Self {
             version: version.to_string(),
-            platforms: platforms.iter().map(|p| p.id().to_string()).collect(),
+            channel: Channel::Stable,
+            mode: Mode::Binaries,
+            binaries: BTreeMap::new(),
+        };
+        for platform in platforms {
+            config.add_platform(*platform);
         }
+        config
     }
 
-    /// Load config from the default location
-    pub fn load() -> Result<Self> {
-        let path = config_path()?;
-        Self::load_from(&path)
+    /// Create a config for a project whose wrappers run the rnr on PATH
+    pub fn system(version: &str) -> Self {
+        Self {
+            mode: Mode::System,
+            ..Self::new(version, &[])
+        }
     }
 
     /// Load config from a specific path
     pub fn load_from(path: &Path) -> Result<Self> {
         let content = fs::read_to_string(path)
             .with_context(|| format!("Failed to read config: {}", path.display()))?;
-        let config: Self = serde_yaml::from_str(&content)
-            .with_context(|| format!("Failed to parse config: {}", path.display()))?;
+        let config: Self = serde_yaml::from_str(&content).with_context(|| {
+            format!(
+                "Failed to parse config: {}\n\
+                 If it was cut short by an interrupted rnr, delete it and run 'rnr init' again.",
+                path.display()
+            )
+        })?;
         Ok(config)
     }
 
@@ -61,45 +192,205 @@ impl RnrConfig {
                 .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
         }
 
+        // Written beside it and renamed into place, so a crash never leaves
+        // a half-written config behind
         let content = serde_yaml::to_string(self).context("Failed to serialize config")?;
-        fs::write(path, content)
-            .with_context(|| format!("Failed to write config: {}", path.display()))?;
-        Ok(())
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        let temp = path.with_file_name(format!(".{}.{}.tmp", file_name, std::process::id()));
+        let written = fs::write(&temp, content)
+            .with_context(|| format!("Failed to write config: {}", path.display()))
+            .and_then(|()| permissions::set(&temp, permissions::READABLE))
+            .and_then(|()| {
+                fs::rename(&temp, path)
+                    .with_context(|| format!("Failed to write config: {}", path.display()))
+            });
+        if written.is_err() {
+            let _ = fs::remove_file(&temp);
+        }
+        written
     }
 
     /// Get the configured platforms
     pub fn get_platforms(&self) -> Vec<Platform> {
-        self.platforms
-            .iter()
+        self.binaries
+            .keys()
             .filter_map(|id| Platform::from_id(id))
             .collect()
     }
 
     /// Add a platform to the config
     pub fn add_platform(&mut self, platform: Platform) {
-        let id = platform.id().to_string();
-        if !self.platforms.contains(&id) {
-            self.platforms.push(id);
-            self.platforms.sort();
-        }
+        let version = self.version.clone();
+        self.binaries
+            .entry(platform.id().to_string())
+            .or_insert(BinaryEntry {
+                version,
+                sha256: None,
+                size: None,
+                placeholder: false,
+                files: Vec::new(),
+            });
     }
 
     /// Remove a platform from the config
     pub fn remove_platform(&mut self, platform: Platform) {
-        let id = platform.id();
-        self.platforms.retain(|p| p != id);
+        self.binaries.remove(platform.id());
     }
 
     /// Check if a platform is configured
     pub fn has_platform(&self, platform: Platform) -> bool {
-        self.platforms.contains(&platform.id().to_string())
+        self.binaries.contains_key(platform.id())
+    }
+
+    /// Recorded details of a platform's binary
+    pub fn binary(&self, platform: Platform) -> Option<&BinaryEntry> {
+        self.binaries.get(platform.id())
     }
+
+    /// Record the version, checksum, and size of an installed binary,
+    /// removing the placeholder it replaces, if any
+    pub fn record_binary(&mut self, platform: Platform, version: &str, path: &Path) -> Result<()> {
+        let size = fs::metadata(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?
+            .len();
+        let sha256 = checksum::sha256_file(path)?;
+        self.binaries.insert(
+            platform.id().to_string(),
+            BinaryEntry {
+                version: version.to_string(),
+                sha256: Some(sha256),
+                size: Some(size),
+                placeholder: false,
+                files: file_name(path).into_iter().collect(),
+            },
+        );
+
+        let placeholder = placeholder_path(path);
+        if placeholder.exists() {
+            fs::remove_file(&placeholder)
+                .with_context(|| format!("Failed to remove {}", placeholder.display()))?;
+        }
+        Ok(())
+    }
+
+    /// Record that a platform only has a placeholder in .rnr/bin
+    pub fn record_placeholder(&mut self, platform: Platform, version: &str) {
+        self.binaries.insert(
+            platform.id().to_string(),
+            BinaryEntry {
+                version: version.to_string(),
+                sha256: None,
+                size: None,
+                placeholder: true,
+                files: vec![format!("{}{}", platform.binary_name(), PLACEHOLDER_SUFFIX)],
+            },
+        );
+    }
+
+    /// Names of the files in .rnr/bin this config accounts for: the ones
+    /// each platform recorded, or for entries that recorded none, the
+    /// platform's usual binary or placeholder name
+    pub fn managed_files(&self) -> BTreeSet<String> {
+        let mut files = BTreeSet::new();
+        for (id, entry) in &self.binaries {
+            if !entry.files.is_empty() {
+                files.extend(entry.files.iter().cloned());
+            } else if let Some(platform) = Platform::from_id(id) {
+                let name = platform.binary_name();
+                files.insert(match entry.placeholder {
+                    true => format!("{}{}", name, PLACEHOLDER_SUFFIX),
+                    false => name.to_string(),
+                });
+            }
+        }
+        files
+    }
+
+    /// Files in `bin_dir` this config doesn't account for, with their sizes.
+    /// Hidden files and the `.old` binaries an upgrade leaves behind (and
+    /// removes itself) aren't counted.
+    pub fn unmanaged_files(&self, bin_dir: &Path) -> Vec<(String, u64)> {
+        let Ok(entries) = fs::read_dir(bin_dir) else {
+            return Vec::new();
+        };
+        let managed = self.managed_files();
+        let mut files: Vec<(String, u64)> = entries
+            .flatten()
+            .filter_map(|entry| {
+                let name = entry.file_name().into_string().ok()?;
+                let metadata = entry.metadata().ok().filter(|m| m.is_file())?;
+                let ignored = name.starts_with('.')
+                    || self_replace::is_replaced(&name)
+                    || managed.contains(&name);
+                (!ignored).then_some((name, metadata.len()))
+            })
+            .collect();
+        files.sort();
+        files
+    }
+
+    /// Delete the files in `bin_dir` that were managed when `previous` was
+    /// taken but no longer are, returning the names of those removed
+    #[cfg(feature = "network")]
+    pub fn remove_stale_files(
+        &self,
+        bin_dir: &Path,
+        previous: &BTreeSet<String>,
+    ) -> Result<Vec<String>> {
+        let managed = self.managed_files();
+        let mut removed = Vec::new();
+        for name in previous.difference(&managed) {
+            // Only plain names; config.yaml is hand-editable
+            if Path::new(name).file_name() != Some(name.as_ref()) {
+                continue;
+            }
+            let path = bin_dir.join(name);
+            if path.is_file() {
+                fs::remove_file(&path)
+                    .with_context(|| format!("Failed to remove {}", path.display()))?;
+                removed.push(name.clone());
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Configured platforms whose binary version differs from the project version
+    pub fn version_drift(&self) -> Vec<(Platform, &BinaryEntry)> {
+        self.get_platforms()
+            .into_iter()
+            .filter_map(|p| self.binary(p).map(|entry| (p, entry)))
+            .filter(|(_, entry)| entry.version != self.version)
+            .collect()
+    }
+}
+
+/// Suffix of a placeholder file standing in for a binary in .rnr/bin
+pub const PLACEHOLDER_SUFFIX: &str = ".placeholder";
+
+/// Name of the file at `path`, if it has a valid UTF-8 one
+fn file_name(path: &Path) -> Option<String> {
+    path.file_name()?.to_str().map(str::to_string)
+}
+
+/// Path of the placeholder standing in for the binary at `binary`
+pub fn placeholder_path(binary: &Path) -> PathBuf {
+    let mut path = binary.as_os_str().to_owned();
+    path.push(PLACEHOLDER_SUFFIX);
+    PathBuf::from(path)
 }
 
 /// Get the path to .rnr directory
 pub fn rnr_dir() -> Result<PathBuf> {
-    let current = std::env::current_dir().context("Failed to get current directory")?;
-    Ok(current.join(RNR_DIR))
+    Ok(project_dir()?.join(RNR_DIR))
+}
+
+/// The directory `rnr init` sets up: the `--project-dir`, else the wrapper's
+/// directory when run through one, otherwise the current directory
+pub fn project_dir() -> Result<PathBuf> {
+    match config::project_dir_override().or_else(config::wrapper_dir) {
+        Some(dir) => Ok(dir),
+        None => std::env::current_dir().context("Failed to get current directory"),
+    }
 }
 
 /// Get the path to .rnr/config.yaml
@@ -112,10 +403,109 @@ pub fn bin_dir() -> Result<PathBuf> {
     Ok(rnr_dir()?.join(BIN_DIR))
 }
 
-/// Check if rnr is already initialized in the current directory
+/// Find the root of the initialized project a command works on: the
+/// `--project-dir`, else the wrapper's directory, else the nearest one at or
+/// above the current directory
+pub fn locate_project() -> Result<PathBuf> {
+    if let Some(dir) = config::project_dir_override() {
+        if !is_initialized_at(&dir) {
+            anyhow::bail!(
+                "rnr is not initialized in {} (the --project-dir). Run 'rnr init' there first.",
+                dir.display()
+            );
+        }
+        return Ok(dir);
+    }
+    if let Some(dir) = config::wrapper_dir().filter(|dir| is_initialized_at(dir)) {
+        return Ok(dir);
+    }
+    let current_dir = std::env::current_dir().context("Failed to get current directory")?;
+    find_initialized_root(&current_dir)
+        .context("rnr is not initialized here or in any parent directory. Run 'rnr init' first.")
+}
+
+/// Find the project's .rnr directory (see [`locate_project`])
+pub fn find_rnr_dir() -> Result<PathBuf> {
+    Ok(locate_project()?.join(RNR_DIR))
+}
+
+/// Write `rnr_dir`'s .gitignore if it has none, so the state rnr keeps
+/// there isn't committed along with the rest of the directory. Returns
+/// whether it was written; one that's already there is left as it is.
+pub fn ensure_ignored(rnr_dir: &Path) -> io::Result<bool> {
+    let path = rnr_dir.join(IGNORE_FILE);
+    if path.exists() {
+        return Ok(false);
+    }
+    fs::create_dir_all(rnr_dir)?;
+    match File::options().write(true).create_new(true).open(&path) {
+        Ok(mut file) => file.write_all(IGNORED.as_bytes()).map(|_| true),
+        // Another rnr got there first
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Check if rnr is already initialized in the current directory (or the wrapper's)
 pub fn is_initialized() -> Result<bool> {
-    let path = config_path()?;
-    Ok(path.exists())
+    Ok(is_initialized_at(&project_dir()?))
+}
+
+/// An exclusive lock on a project's .rnr directory, held while its config is
+/// read, changed and written back, and released when dropped
+#[derive(Debug)]
+pub struct ProjectLock {
+    _file: File,
+}
+
+impl ProjectLock {
+    /// Lock `rnr_dir`, waiting up to [`LOCK_WAIT`] for another rnr to finish
+    pub fn acquire(rnr_dir: &Path) -> Result<Self> {
+        Self::acquire_within(rnr_dir, LOCK_WAIT)
+    }
+
+    /// Lock `rnr_dir`, waiting up to `wait` for another rnr to finish
+    pub fn acquire_within(rnr_dir: &Path, wait: Duration) -> Result<Self> {
+        let path = rnr_dir.join(LOCK_FILE);
+        let file = File::options()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        let deadline = Instant::now() + wait;
+        loop {
+            match file.try_lock() {
+                Ok(()) => return Ok(Self { _file: file }),
+                Err(TryLockError::WouldBlock) if Instant::now() < deadline => {
+                    std::thread::sleep(Duration::from_millis(50));
+                }
+                Err(TryLockError::WouldBlock) => anyhow::bail!(
+                    "another rnr process is modifying this project (waited {}s for {}). \
+                     Try again once it finishes.",
+                    wait.as_secs(),
+                    path.display()
+                ),
+                Err(TryLockError::Error(e)) => {
+                    return Err(e).with_context(|| format!("Failed to lock {}", path.display()))
+                }
+            }
+        }
+    }
+}
+
+/// Check if rnr is initialized in `dir`
+pub fn is_initialized_at(dir: &Path) -> bool {
+    dir.join(RNR_DIR).join(CONFIG_FILE).is_file()
+}
+
+/// Find the nearest directory at or above `start` where rnr is initialized
+pub fn find_initialized_root(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .take(crate::config::MAX_SEARCH_LEVELS)
+        .find(|dir| is_initialized_at(dir))
+        .map(Path::to_path_buf)
 }
 
 #[cfg(test)]
@@ -131,7 +521,256 @@ mod tests {
         let parsed: RnrConfig = serde_yaml::from_str(&yaml).unwrap();
 
         assert_eq!(parsed.version, "0.1.0");
-        assert_eq!(parsed.platforms.len(), 2);
+        assert_eq!(parsed.get_platforms().len(), 2);
+    }
+
+    #[test]
+    fn test_migrate_platform_list() {
+        let yaml = "version: 0.3.0\nplatforms:\n- linux-amd64\n- macos-arm64\n";
+        let config: RnrConfig = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(
+            config.get_platforms(),
+            vec![Platform::LinuxAmd64, Platform::MacosArm64]
+        );
+        let entry = config.binary(Platform::MacosArm64).unwrap();
+        assert_eq!(entry.version, "0.3.0");
+        assert_eq!(entry.sha256, None);
+
+        // Saved in the new shape
+        assert_eq!(config.channel, Channel::Stable);
+        let saved = serde_yaml::to_string(&config).unwrap();
+        assert!(saved.contains("binaries:"));
+        assert!(!saved.contains("platforms:"));
+    }
+
+    #[test]
+    fn test_channel_roundtrip() {
+        let mut config = RnrConfig::new("0.5.0-rc.1", &[Platform::LinuxAmd64]);
+        config.channel = Channel::Prerelease;
+        let yaml = serde_yaml::to_string(&config).unwrap();
+        assert!(yaml.contains("channel: prerelease"));
+        let parsed: RnrConfig = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(parsed.channel, Channel::Prerelease);
+    }
+
+    #[test]
+    fn test_mode_roundtrip() {
+        let binaries = serde_yaml::to_string(&RnrConfig::new("0.1.0", &[])).unwrap();
+        assert!(!binaries.contains("mode:"), "{}", binaries);
+
+        let yaml = serde_yaml::to_string(&RnrConfig::system("0.1.0")).unwrap();
+        assert!(yaml.contains("mode: system"), "{}", yaml);
+        let parsed: RnrConfig = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(parsed.mode, Mode::System);
+        assert!(parsed.get_platforms().is_empty());
+    }
+
+    #[test]
+    fn test_record_binary_and_drift() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("rnr-linux-amd64");
+        fs::write(&path, "abc").unwrap();
+
+        let mut config = RnrConfig::new("0.2.0", &[Platform::LinuxAmd64, Platform::MacosArm64]);
+        config
+            .record_binary(Platform::LinuxAmd64, "0.1.0", &path)
+            .unwrap();
+
+        let entry = config.binary(Platform::LinuxAmd64).unwrap();
+        assert_eq!(entry.size, Some(3));
+        assert_eq!(
+            entry.sha256.as_deref(),
+            Some("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad")
+        );
+
+        let drift: Vec<Platform> = config.version_drift().into_iter().map(|(p, _)| p).collect();
+        assert_eq!(drift, vec![Platform::LinuxAmd64]);
+    }
+
+    #[test]
+    fn test_ensure_ignored() {
+        let temp = tempfile::tempdir().unwrap();
+        let rnr_dir = temp.path().join(RNR_DIR);
+        assert!(ensure_ignored(&rnr_dir).unwrap());
+        let ignore = fs::read_to_string(rnr_dir.join(IGNORE_FILE)).unwrap();
+        for path in ["/cache/", "/tmp/", "/.lock"] {
+            assert!(ignore.lines().any(|line| line == path), "{}", path);
+        }
+        assert!(!ensure_ignored(&rnr_dir).unwrap());
+
+        // One the user wrote is theirs
+        fs::write(rnr_dir.join(IGNORE_FILE), "/cache/\n").unwrap();
+        assert!(!ensure_ignored(&rnr_dir).unwrap());
+        assert_eq!(
+            fs::read_to_string(rnr_dir.join(IGNORE_FILE)).unwrap(),
+            "/cache/\n"
+        );
+    }
+
+    #[test]
+    fn test_find_initialized_root() {
+        let temp = tempfile::tempdir().unwrap();
+        let nested = temp.path().join("services").join("api");
+        fs::create_dir_all(&nested).unwrap();
+        assert_eq!(find_initialized_root(&nested), None);
+
+        // A bare .rnr directory isn't an initialized project
+        fs::create_dir_all(nested.join(RNR_DIR)).unwrap();
+        assert_eq!(find_initialized_root(&nested), None);
+
+        RnrConfig::new("0.1.0", &[])
+            .save_to(&temp.path().join(RNR_DIR).join(CONFIG_FILE))
+            .unwrap();
+        assert_eq!(find_initialized_root(&nested).as_deref(), Some(temp.path()));
+        assert!(is_initialized_at(temp.path()));
+        assert!(!is_initialized_at(&nested));
+    }
+
+    #[test]
+    fn test_record_binary_replaces_placeholder() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("rnr-linux-amd64");
+        fs::write(placeholder_path(&path), "placeholder").unwrap();
+
+        let mut config = RnrConfig::new("0.1.0", &[]);
+        config.record_placeholder(Platform::LinuxAmd64, "0.1.0");
+        let yaml = serde_yaml::to_string(&config).unwrap();
+        assert!(yaml.contains("placeholder: true"), "{}", yaml);
+
+        fs::write(&path, "abc").unwrap();
+        config
+            .record_binary(Platform::LinuxAmd64, "0.1.0", &path)
+            .unwrap();
+        assert!(!config.binary(Platform::LinuxAmd64).unwrap().placeholder);
+        assert!(!placeholder_path(&path).exists());
+        assert!(!serde_yaml::to_string(&config)
+            .unwrap()
+            .contains("placeholder"));
+    }
+
+    #[test]
+    #[cfg(feature = "network")]
+    fn test_managed_files_and_stale_ones() {
+        let temp = tempfile::tempdir().unwrap();
+        let bin = &temp.path().join(BIN_DIR);
+        fs::create_dir(bin).unwrap();
+        let mut config = RnrConfig::new("0.1.0", &[Platform::LinuxAmd64]);
+        config.record_placeholder(Platform::MacosArm64, "0.1.0");
+        // Entries that recorded no files manage the usual names
+        assert_eq!(
+            config.managed_files().into_iter().collect::<Vec<_>>(),
+            ["rnr-linux-amd64", "rnr-macos-arm64.placeholder"]
+        );
+
+        let previous = config.managed_files();
+        fs::write(bin.join("rnr-macos-arm64.placeholder"), "placeholder").unwrap();
+        fs::write(bin.join("rnr-macos-arm64"), "abc").unwrap();
+        config
+            .record_binary(Platform::MacosArm64, "0.1.0", &bin.join("rnr-macos-arm64"))
+            .unwrap();
+        assert_eq!(
+            config.binary(Platform::MacosArm64).unwrap().files,
+            ["rnr-macos-arm64"]
+        );
+        let yaml = serde_yaml::to_string(&config).unwrap();
+        assert!(yaml.contains("files:\n    - rnr-macos-arm64\n"), "{}", yaml);
+
+        // Recorded files the config no longer manages are removed, but never
+        // anything outside the directory
+        fs::write(bin.join("rnr-linux-amd64.sig"), "signature").unwrap();
+        fs::write(temp.path().join("outside"), "keep").unwrap();
+        let mut previous = previous;
+        previous.insert("rnr-linux-amd64.sig".to_string());
+        previous.insert("../outside".to_string());
+        let removed = config.remove_stale_files(bin, &previous).unwrap();
+        assert_eq!(removed, ["rnr-linux-amd64.sig"]);
+        assert!(!bin.join("rnr-linux-amd64.sig").exists());
+        assert!(bin.join("rnr-macos-arm64").exists());
+        assert!(temp.path().join("outside").exists());
+    }
+
+    #[test]
+    fn test_save_replaces_the_file_whole() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join(RNR_DIR).join(CONFIG_FILE);
+        RnrConfig::new("0.1.0", &[Platform::LinuxAmd64])
+            .save_to(&path)
+            .unwrap();
+        RnrConfig::new("0.2.0", &[Platform::MacosArm64])
+            .save_to(&path)
+            .unwrap();
+
+        let config = RnrConfig::load_from(&path).unwrap();
+        assert_eq!(config.version, "0.2.0");
+        assert_eq!(config.get_platforms(), vec![Platform::MacosArm64]);
+        let files: Vec<_> = fs::read_dir(temp.path().join(RNR_DIR))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(files, [CONFIG_FILE]);
+    }
+
+    #[test]
+    fn test_truncated_config_suggests_init() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join(CONFIG_FILE);
+        RnrConfig::new("0.1.0", &[Platform::LinuxAmd64])
+            .save_to(&path)
+            .unwrap();
+        let content = fs::read_to_string(&path).unwrap();
+        fs::write(&path, &content[..content.len() - 12]).unwrap();
+
+        let err = format!("{:#}", RnrConfig::load_from(&path).unwrap_err());
+        assert!(
+            err.contains("delete it and run 'rnr init' again"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_project_lock_serializes_changes() {
+        let temp = tempfile::tempdir().unwrap();
+        let rnr_dir = temp.path().join(RNR_DIR);
+        let path = rnr_dir.join(CONFIG_FILE);
+        RnrConfig::new("0.1.0", &[Platform::LinuxAmd64])
+            .save_to(&path)
+            .unwrap();
+
+        let platforms = [
+            Platform::MacosAmd64,
+            Platform::MacosArm64,
+            Platform::WindowsAmd64,
+            Platform::WindowsArm64,
+        ];
+        std::thread::scope(|scope| {
+            for platform in platforms {
+                let (rnr_dir, path) = (&rnr_dir, &path);
+                scope.spawn(move || {
+                    let _lock = ProjectLock::acquire(rnr_dir).unwrap();
+                    let mut config = RnrConfig::load_from(path).unwrap();
+                    std::thread::sleep(Duration::from_millis(20));
+                    config.add_platform(platform);
+                    config.save_to(path).unwrap();
+                });
+            }
+        });
+        // No change was lost to another thread's read-modify-write
+        let config = RnrConfig::load_from(&path).unwrap();
+        assert_eq!(config.get_platforms().len(), 5);
+    }
+
+    #[test]
+    fn test_project_lock_times_out() {
+        let temp = tempfile::tempdir().unwrap();
+        let _held = ProjectLock::acquire(temp.path()).unwrap();
+        let err = ProjectLock::acquire_within(temp.path(), Duration::ZERO).unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("another rnr process is modifying this project"),
+            "{}",
+            err
+        );
     }
 
     #[test]
@@ -1,202 +1,4968 @@
 use anyhow::{Context, Result};
-use std::collections::HashMap;
-use std::path::Path;
-use std::process::Command;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeSet, HashMap};
+use std::io;
+use std::num::{NonZeroU32, NonZeroUsize};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex, OnceLock, PoisonError, RwLock};
+use std::time::{Duration, Instant, SystemTime};
 
-use crate::config::{Config, Step, StepDef, Task, TaskDef};
+use crate::args::TaskArgs;
+use crate::auto_deps;
+use crate::bins;
+use crate::builtins;
+use crate::chaos::Chaos;
+use crate::checkout::{self, CleanCheckout};
+use crate::choose;
+use crate::commands::list;
+use crate::config::{
+    self, Choose, Cmd, Config, ConfigCache, EnvValue, ExpectExit, Foreach, FromCmd, Hook,
+    Invocation, Lane, OutputFilter, Priority, Step, StepDef, Task, TaskDef, TaskTarget,
+};
+use crate::context;
+use crate::durations::{self, Estimates, History};
+use crate::env_report::EnvReport;
+use crate::events::{EventSink, StepPosition};
+use crate::failure_hook::{self, Capture};
+use crate::guards::{self, Budget, Clock, OverBudget};
+use crate::history::{self, CommandFailure, RunRecord, Status, StepRecord, TaskRecord};
+use crate::interpolate::Scope;
+use crate::nesting::Nesting;
+use crate::output_buffer::{self, for_each_line};
+use crate::output_filter::{self, Filter};
+use crate::permissions::Umask;
+use crate::plan::{self, GitGuarded, Plan};
+use crate::script_hints;
+use crate::secrets::{self, HiddenVars};
+use crate::shared_dir;
+use crate::slots::{self, Slots};
+use crate::step_filter::StepFilter;
+use crate::suggest::{self, Abbreviation};
+use crate::task_log::{self, TaskLog};
+use crate::termination;
+use crate::trace::{self, Tracer};
+use crate::ui::Ui;
+
+/// Whether a `once` task has succeeded, locked while it runs
+type OnceSlot = Arc<Mutex<bool>>;
+
+/// Options controlling how tasks are executed
+#[derive(Debug, Default)]
+pub struct RunOptions {
+    /// Print what would be done without executing anything
+    pub dry_run: bool,
+    /// Show the plan diff against the last recorded run (with `dry_run`)
+    pub diff: bool,
+    /// Accept an unambiguous abbreviation of the task name
+    pub fuzzy: bool,
+    /// Records a span per task and step when tracing is enabled
+    pub trace: Option<Tracer>,
+    /// Where progress and command echoes go
+    pub ui: Ui,
+    /// Project to run in (default: the nearest directory with an rnr.yaml)
+    pub project_root: Option<PathBuf>,
+    /// Environment variables set for every command, overriding task `env`
+    pub env: HashMap<String, String>,
+    /// Run at most this many branches of a parallel block at once
+    pub jobs: Option<usize>,
+    /// Configs loaded so far, so nested rnr.yaml files are read once per invocation
+    pub configs: ConfigCache,
+    /// Refuse to run tasks from a config with unknown task fields
+    pub strict: bool,
+    /// Receives machine-readable progress events (`--events-json`)
+    pub events: Option<EventSink>,
+    /// Show the environment rnr sets for each task before running it
+    pub verbose: bool,
+    /// Run `checkout:` tasks even with uncommitted changes in the working tree
+    pub allow_dirty: bool,
+    /// Project root of the clean checkout the running task uses, if any
+    pub checkout_root: RwLock<Option<PathBuf>>,
+    /// Output of the `from_cmd` env commands run so far, by directory and command
+    pub env_commands: Mutex<HashMap<(PathBuf, String), String>>,
+    /// `once` tasks reached so far, by config and name, and whether each succeeded
+    pub once: Mutex<HashMap<(PathBuf, String), OnceSlot>>,
+    /// Which of the task's own steps run (`--only`, `--skip`, `--steps`)
+    pub steps: StepFilter,
+    /// Where this run sits among runs started by other runs' tasks
+    pub nesting: Nesting,
+    /// Context `contexts:` limits are checked against (default: detected)
+    pub context: Option<String>,
+    /// Failures to inject instead of running steps and tasks (`--chaos`)
+    pub chaos: Chaos,
+    /// Directory the invoked task runs in instead of its own `dir` (`--dir`)
+    pub dir: Option<PathBuf>,
+    /// Run despite `not_between` windows and the `budget`, with a warning
+    /// (`--ignore-guards`)
+    pub ignore_guards: bool,
+    /// Don't show how long steps took before (`--no-eta`)
+    pub no_eta: bool,
+    /// Don't run the tasks making a task's `sources` first (`--no-auto-deps`)
+    pub no_auto_deps: bool,
+    /// Where `not_between` and the `budget` get the time
+    pub clock: Clock,
+    /// When the run started, for the `budget`
+    pub started: OnceLock<SystemTime>,
+}
+
+impl RunOptions {
+    /// The project root to run in
+    fn project_root(&self) -> Result<PathBuf> {
+        if let Some(root) = self.checkout_root.read().unwrap().as_ref() {
+            return Ok(root.clone());
+        }
+        match &self.project_root {
+            Some(root) => Ok(root.clone()),
+            None => config::project_root(),
+        }
+    }
+
+    /// Load a config (once per invocation), enforcing strict mode
+    fn load_config(&self, path: &Path) -> Result<Arc<Config>> {
+        let config = self.configs.load(path)?;
+        if self.strict || config.strict {
+            config.check_unknown_fields(path)?;
+        }
+        Ok(config)
+    }
+
+    /// The slots a parallel block's branches share: `--jobs` of them, or
+    /// one per branch without it
+    fn parallel_slots(&self, branches: usize) -> usize {
+        self.jobs.unwrap_or(branches).max(1)
+    }
+
+    /// Slots a branch of `weight` takes; weights only count with `--jobs`,
+    /// since without it every branch runs at once anyway
+    fn branch_slots(&self, weight: Option<NonZeroU32>) -> usize {
+        match (self.jobs, weight) {
+            (Some(jobs), Some(weight)) => slots::share(weight.get() as usize, jobs),
+            _ => 1,
+        }
+    }
+
+    /// The context tasks run in: the one set, or else the detected one
+    fn context(&self) -> String {
+        self.context.clone().unwrap_or_else(context::current)
+    }
+
+    /// When the run started: the first time this is asked
+    fn started(&self) -> SystemTime {
+        *self.started.get_or_init(|| self.clock.now())
+    }
+
+    /// Fail if task `name` from `config` may not start now, unless guards
+    /// are ignored
+    fn check_window(&self, name: &str, task: &TaskDef, config: &Config) -> Result<()> {
+        match task.not_between() {
+            Some(window) => guards::check_window(
+                name,
+                window,
+                config.tz,
+                self.clock.now(),
+                self.ignore_guards,
+                &self.ui,
+            ),
+            None => Ok(()),
+        }
+    }
+
+    /// Fail if the git repository at `root` isn't as task `name` needs
+    fn check_git(&self, name: &str, task: &TaskDef, root: &Path) -> Result<()> {
+        let Some(requirements) = task.git_requirements() else {
+            return Ok(());
+        };
+        let guarded = GitGuarded {
+            task: name.to_string(),
+            requirements,
+        };
+        plan::check_git(
+            &[guarded],
+            root,
+            self.allow_dirty,
+            self.ignore_guards,
+            self.dry_run,
+            &self.ui,
+        )
+    }
+
+    /// Fail if task `name` from `config` may not run in this context
+    fn check_context(&self, name: &str, task: &TaskDef, config: &Config) -> Result<()> {
+        match task.contexts() {
+            Some(contexts) => {
+                context::check(name, contexts, &self.context(), &config.known_contexts())
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// The failure hook for `task`: its own `on_failure`, and the top-level
+    /// one only in a run from a shell, so a nested run leaves it to the run
+    /// that started it
+    fn failure_hook<'a>(&self, config: &'a Config, task: &'a TaskDef) -> Option<&'a Hook> {
+        match self.nesting.is_nested() {
+            true => task.on_failure(),
+            false => config.failure_hook(task),
+        }
+    }
+
+    /// A task's environment, with `from_cmd` values computed in `work_dir`,
+    /// then the `env` of whatever delegated to it and the overrides applied
+    fn task_env(
+        &self,
+        env: Option<&HashMap<String, EnvValue>>,
+        work_dir: &Path,
+        caller: Option<&HashMap<String, String>>,
+    ) -> Result<HashMap<String, String>> {
+        let declared = env.cloned().unwrap_or_default();
+        let mut env: HashMap<String, String> = declared
+            .iter()
+            .filter_map(|(name, value)| match value {
+                EnvValue::Text(text) => Some((name.clone(), text.clone())),
+                EnvValue::Command(_) => None,
+            })
+            .collect();
+        let mut computed: Vec<(&String, &FromCmd)> = declared
+            .iter()
+            .filter_map(|(name, value)| match value {
+                EnvValue::Command(command) => Some((name, command)),
+                EnvValue::Text(_) => None,
+            })
+            .collect();
+        computed.sort_by_key(|(name, _)| *name);
+        for (name, command) in computed {
+            let value = if self.dry_run {
+                EnvValue::Command(command.clone()).to_string()
+            } else {
+                let mut base = env.clone();
+                base.extend(self.env.clone());
+                self.env_command(name, &command.from_cmd, work_dir, &base)?
+            };
+            env.insert(name.clone(), value);
+        }
+        env.extend(caller.cloned().unwrap_or_default());
+        env.extend(self.env.clone());
+        Ok(env)
+    }
+
+    /// Whether the `once` task `name` from `config` has succeeded, shared by
+    /// every delegation to it
+    fn once_slot(&self, config: &Config, name: &str) -> OnceSlot {
+        self.once
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(task_key(config, name))
+            .or_default()
+            .clone()
+    }
+
+    /// The trimmed output of a `from_cmd` command, run once per invocation
+    /// for each command and directory
+    fn env_command(
+        &self,
+        name: &str,
+        cmd: &str,
+        work_dir: &Path,
+        base: &HashMap<String, String>,
+    ) -> Result<String> {
+        let mut outputs = self.env_commands.lock().unwrap();
+        let key = (work_dir.to_path_buf(), cmd.to_string());
+        if let Some(value) = outputs.get(&key) {
+            return Ok(value.clone());
+        }
+        let mut command = shell_command(cmd, work_dir);
+        command.envs(base).stdin(Stdio::null());
+        let output = command
+            .output()
+            .with_context(|| format!("Failed to run '{}' for env {}", cmd, name))?;
+        exit_result(output.status, cmd.to_string(), &command, None).with_context(|| {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let mut message = format!("Failed to compute env {} from '{}'", name, cmd);
+            if !stderr.trim().is_empty() {
+                message.push_str(&format!(":\n{}", stderr.trim_end()));
+            }
+            message
+        })?;
+        let value = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        outputs.insert(key, value.clone());
+        Ok(value)
+    }
+}
 
 /// Run a task by name
-pub fn run_task(task_name: &str) -> Result<()> {
-    let config = Config::load()?;
-    let project_root = crate::config::project_root()?;
+pub fn run_task(task_name: &str, args: &[String], opts: &RunOptions) -> Result<()> {
+    let project_root = opts.project_root()?;
+    let config = opts.load_config(&project_root.join(config::CONFIG_FILE))?;
 
+    let task_name = resolve_task_name(&config, task_name, opts)?;
+    opts.nesting.check_depth(&task_name)?;
     let task = config
-        .get_task(task_name)
+        .get_task(&task_name)
         .with_context(|| format!("Task '{}' not found", task_name))?;
 
-    execute_task_def(task, &project_root, &config)
+    let bound = crate::args::bind(&task_name, task, args, &opts.ui)?;
+
+    if !opts.no_auto_deps {
+        run_producers(&config, &project_root, &task_name, opts)?;
+    }
+    let (record, result) = run_bound_task(&config, &project_root, &task_name, args, &bound, opts)?;
+    match &result {
+        Err(e) => report_failures(&record, e, &config, &project_root, opts),
+        Ok(()) => record_outputs(&project_root, &task_name, task, opts),
+    }
+    result
 }
 
-/// Execute a task definition
-fn execute_task_def(task_def: &TaskDef, project_root: &Path, config: &Config) -> Result<()> {
-    match task_def {
-        TaskDef::Shorthand(cmd) => execute_command(cmd, project_root, &HashMap::new()),
-        TaskDef::Full(task) => execute_full_task(task, project_root, config),
+/// Run the tasks whose `outputs` `task_name` reads in its `sources` (and
+/// those they read in turn), skipping any whose outputs are up to date
+fn run_producers(
+    config: &Config,
+    project_root: &Path,
+    task_name: &str,
+    opts: &RunOptions,
+) -> Result<()> {
+    let (edges, order) = auto_deps::resolve(config, task_name)?;
+    for name in order {
+        let task = config
+            .get_task(&name)
+            .with_context(|| format!("Task '{}' not found", name))?;
+        let needed_by: Vec<&str> = edges
+            .iter()
+            .filter(|edge| edge.producer == name)
+            .map(|edge| edge.task.as_str())
+            .collect();
+        let needed_by = format!("'{}'", needed_by.join("', '"));
+        let Some(reason) = auto_deps::stale(project_root, &name, task)? else {
+            opts.ui.status(format!(
+                "Skipping '{}' (needed by {}): its outputs are up to date",
+                name, needed_by
+            ));
+            continue;
+        };
+        opts.ui.status(format!(
+            "Running '{}' first (needed by {}): {}",
+            name, needed_by, reason
+        ));
+        let bound = crate::args::bind(&name, task, &[], &opts.ui)
+            .with_context(|| format!("'{}' needs '{}' to run first", task_name, name))?;
+        let (record, result) = run_bound_task(config, project_root, &name, &[], &bound, opts)?;
+        if let Err(e) = result {
+            report_failures(&record, &e, config, project_root, opts);
+            return Err(e.context(format!(
+                "'{}' needed '{}' to run first, and it failed",
+                task_name, name
+            )));
+        }
+        record_outputs(project_root, &name, task, opts);
     }
+    Ok(())
 }
 
-/// Execute a full task definition
-fn execute_full_task(task: &Task, project_root: &Path, config: &Config) -> Result<()> {
-    let work_dir = match &task.dir {
-        Some(dir) => project_root.join(dir),
-        None => project_root.to_path_buf(),
-    };
+/// Remember what a task that succeeded made its `outputs` from; failures
+/// only warn
+fn record_outputs(project_root: &Path, task_name: &str, task: &TaskDef, opts: &RunOptions) {
+    if opts.dry_run {
+        return;
+    }
+    if let Err(e) = auto_deps::record(project_root, task_name, task) {
+        opts.ui.warn(format!(
+            "failed to record the sources of '{}': {:#}",
+            task_name, e
+        ));
+    }
+}
 
-    let env = task.env.clone().unwrap_or_default();
+/// Run a task whose name and arguments have been resolved.
+///
+/// Fails early only if the task can't be planned; otherwise returns its
+/// record alongside the outcome of running it.
+pub(crate) fn run_bound_task(
+    config: &Config,
+    project_root: &Path,
+    task_name: &str,
+    args: &[String],
+    bound: &TaskArgs,
+    opts: &RunOptions,
+) -> Result<(TaskRecord, Result<()>)> {
+    let task = config
+        .get_task(task_name)
+        .with_context(|| format!("Task '{}' not found", task_name))?;
 
-    // If task has steps, execute them
-    if let Some(steps) = &task.steps {
-        for step in steps {
-            execute_step(step, &work_dir, &env, config)?;
+    if opts.dry_run {
+        opts.ui.status("Dry run: nothing will be executed\n");
+    }
+    if let Some(dir) = &opts.dir {
+        if task.checkout().is_some() {
+            anyhow::bail!(
+                "--dir can't be used with '{}', which runs in a clean checkout \
+                 (checkout:) rather than a directory of the working tree",
+                task_name
+            );
         }
-        return Ok(());
+        let shown = plan::relative_dir(dir, project_root);
+        opts.ui
+            .status(format!("Running '{}' in {} (--dir)", task_name, shown));
     }
 
-    // If task delegates to another task
-    if let Some(task_name) = &task.task {
-        // If dir is specified, look for rnr.yaml in that directory
-        if task.dir.is_some() {
-            let nested_config_path = work_dir.join(crate::config::CONFIG_FILE);
-            if nested_config_path.exists() {
-                let nested_config = Config::load_from(&nested_config_path)?;
-                let nested_task = nested_config.get_task(task_name).with_context(|| {
-                    format!(
-                        "Task '{}' not found in {}",
-                        task_name,
-                        nested_config_path.display()
-                    )
-                })?;
-                return execute_task_def(nested_task, &work_dir, &nested_config);
-            }
+    // Narrowed for the rest of the run, recording included
+    let _umask = Umask::restrict(config.umask);
+    let plan = plan::build(
+        task_name,
+        task,
+        project_root,
+        opts.dir.as_deref(),
+        config,
+        &opts.configs,
+        bound,
+        &opts.steps,
+    )?;
+    // Every task the run reaches is checked before any of them runs
+    plan.check_contexts(&opts.context())?;
+    plan.check_windows(opts.clock.now(), opts.ignore_guards, &opts.ui)?;
+    plan.check_git(
+        project_root,
+        opts.allow_dirty,
+        opts.ignore_guards,
+        opts.dry_run,
+        &opts.ui,
+    )?;
+    if opts.diff {
+        match plan::load(project_root, task_name) {
+            Ok(Some(previous)) => plan::print_diff(&opts.ui, &previous, &plan),
+            Ok(None) => opts
+                .ui
+                .status(format!("No previous plan recorded for '{}'\n", task_name)),
+            Err(e) => opts.ui.warn(format!("{:#}", e)),
         }
+    }
+    // A filtered run isn't the task's whole plan, so it isn't recorded
+    if !opts.steps.is_active() {
+        if let Err(e) = plan::save(project_root, &plan) {
+            opts.ui.warn(format!("failed to record plan: {:#}", e));
+        }
+    }
+
+    // Like `rnr last`, step durations come from runs started from the shell
+    let estimates = (!opts.dry_run && !opts.nesting.is_nested())
+        .then(|| step_estimates(project_root, &plan, task, &opts.ui));
 
-        // Otherwise, look in current config
-        let target_task = config
-            .get_task(task_name)
-            .with_context(|| format!("Task '{}' not found", task_name))?;
-        return execute_task_def(target_task, project_root, config);
+    report_env(task_name, task, None, config, bound, opts);
+    let hook = opts.failure_hook(config, task);
+    let capture = start_capture(hook, opts);
+    let started = Instant::now();
+    opts.started();
+    if let Some(events) = &opts.events {
+        events.run_started(task_name, task.description(), args, &opts.nesting);
     }
+    let mut steps = Vec::new();
+    let result = in_checkout(task_name, task, project_root, opts, |root| {
+        trace::span(opts.trace.as_ref(), task_name, "task", || {
+            let (records, result) = with_task_log(task_name, task, root, bound, opts, || {
+                with_capture(capture.as_ref(), || {
+                    with_task(config, task_name, || {
+                        if let Err(e) = opts.chaos.at_task(task_name) {
+                            return (Vec::new(), Err(e));
+                        }
+                        with_priority(task.priority(), || {
+                            let shown = estimates.as_ref().filter(|_| !opts.no_eta);
+                            execute_root_task(task, root, config, bound, opts, None, shown)
+                        })
+                    })
+                })
+            })?;
+            steps = records;
+            result
+        })
+    })
+    .and_then(|result| result);
+    if let Some(tracer) = &opts.trace {
+        tracer.finish(&opts.ui);
+    }
+    let record = task_record(task_name, args, steps, &result, started);
+    finish_run_events(&record, opts);
+    if let Some(estimates) = &estimates {
+        if !opts.no_eta {
+            durations::report_slow(estimates, &record.steps, &opts.ui);
+        }
+        if result.is_ok() {
+            save_durations(project_root, estimates, &record.steps, &opts.ui);
+        }
+    }
+    // `rnr last` and `rerun-failed` go by the run started from the shell
+    if !opts.dry_run && !opts.nesting.is_nested() {
+        save_run(project_root, vec![record.clone()], &opts.ui);
+    }
+    on_failure(hook, &record, &result, capture, config, project_root, opts);
+    Ok((record, result))
+}
 
-    // Execute command if present
-    if let Some(cmd) = &task.cmd {
-        return execute_command(cmd, &work_dir, &env);
+/// Re-run the tasks and steps that failed (or never ran) in the last run
+pub fn rerun_failed(opts: &RunOptions) -> Result<()> {
+    let project_root = opts.project_root()?;
+    let config = opts.load_config(&project_root.join(config::CONFIG_FILE))?;
+
+    let last =
+        history::load(&project_root)?.context("No previous run recorded. Run a task first")?;
+    let pending: Vec<&TaskRecord> = last
+        .tasks
+        .iter()
+        .filter(|task| task.status.needs_rerun())
+        .collect();
+    if pending.is_empty() {
+        opts.ui.status("Nothing to rerun: the last run succeeded");
+        return Ok(());
     }
 
-    anyhow::bail!("Task has no cmd, task, or steps defined")
+    // Step indices are only meaningful if rnr.yaml is unchanged
+    let stale = history::config_hash(&project_root)? != last.config_hash;
+    if stale {
+        opts.ui.warn(format!(
+            "{} changed since the last run; rerunning failed tasks in full",
+            config::CONFIG_FILE
+        ));
+    }
+
+    if opts.dry_run {
+        opts.ui.status("Dry run: nothing will be executed\n");
+    }
+
+    let _umask = Umask::restrict(config.umask);
+    opts.started();
+    let mut records = Vec::new();
+    let mut failure = None;
+    for previous in pending {
+        if failure.is_some() {
+            records.push(TaskRecord {
+                name: previous.name.clone(),
+                args: previous.args.clone(),
+                status: Status::NotRun,
+                duration_ms: 0,
+                error: None,
+                steps: Vec::new(),
+            });
+            continue;
+        }
+
+        opts.ui.status(format!("Rerunning '{}'", previous.name));
+        let started = Instant::now();
+        let task = config
+            .get_task(&previous.name)
+            .with_context(|| format!("Task '{}' not found", previous.name))?;
+        if let Some(events) = &opts.events {
+            events.run_started(
+                &previous.name,
+                task.description(),
+                &previous.args,
+                &opts.nesting,
+            );
+        }
+        let bound = crate::args::bind(&previous.name, task, &previous.args, &opts.ui)?;
+        opts.check_context(&previous.name, task, &config)?;
+        opts.check_window(&previous.name, task, &config)?;
+        opts.check_git(&previous.name, task, &project_root)?;
+        report_env(&previous.name, task, None, &config, &bound, opts);
+        let hook = opts.failure_hook(&config, task);
+        let capture = start_capture(hook, opts);
+        let selection = (!stale).then_some(previous);
+        let mut steps = Vec::new();
+        let result = in_checkout(&previous.name, task, &project_root, opts, |root| {
+            trace::span(opts.trace.as_ref(), &previous.name, "task", || {
+                let (records, result) =
+                    with_task_log(&previous.name, task, root, &bound, opts, || {
+                        with_capture(capture.as_ref(), || {
+                            with_task(&config, &previous.name, || {
+                                with_priority(task.priority(), || {
+                                    execute_root_task(
+                                        task, root, &config, &bound, opts, selection, None,
+                                    )
+                                })
+                            })
+                        })
+                    })?;
+                steps = records;
+                result
+            })
+        })
+        .and_then(|result| result);
+        let record = task_record(&previous.name, &previous.args, steps, &result, started);
+        finish_run_events(&record, opts);
+        on_failure(
+            hook,
+            &record,
+            &result,
+            capture,
+            &config,
+            &project_root,
+            opts,
+        );
+        if let Err(e) = &result {
+            report_failures(&record, e, &config, &project_root, opts);
+        }
+        records.push(record);
+        failure = result.err();
+    }
+
+    if let Some(tracer) = &opts.trace {
+        tracer.finish(&opts.ui);
+    }
+    if !opts.dry_run {
+        save_run(&project_root, records, &opts.ui);
+    }
+    failure.map_or(Ok(()), Err)
 }
 
-/// Execute a single step
-fn execute_step(
-    step: &Step,
-    default_dir: &Path,
-    default_env: &HashMap<String, String>,
-    config: &Config,
-) -> Result<()> {
-    match step {
-        Step::Simple(step_def) => execute_step_def(step_def, default_dir, default_env, config),
-        Step::Parallel { parallel } => execute_parallel(parallel, default_dir, default_env, config),
+/// Run `run` in the project root a task runs in: a temporary clean checkout
+/// if it has `checkout:` (removed afterwards, even on failure), otherwise
+/// the project itself. A dry run only describes the checkout.
+fn in_checkout<T>(
+    task_name: &str,
+    task: &TaskDef,
+    project_root: &Path,
+    opts: &RunOptions,
+    run: impl FnOnce(&Path) -> T,
+) -> Result<T> {
+    let Some(spec) = task.checkout() else {
+        return Ok(run(project_root));
+    };
+    let commit = checkout::resolve_commit(project_root, spec.reference.as_deref())?;
+    let short = &commit[..commit.len().min(12)];
+    let dir = checkout::checkout_dir(project_root, task_name);
+    let shown = plan::relative_dir(&dir, project_root);
+    let dirty = checkout::dirty_files(project_root)?;
+    if !dirty.is_empty() && !opts.allow_dirty {
+        let message = format!(
+            "Uncommitted changes to {} won't be in the clean checkout of {}. Commit them, or pass --allow-dirty to run '{}' anyway",
+            dirty.join(", "),
+            short,
+            task_name
+        );
+        if !opts.dry_run {
+            anyhow::bail!(message);
+        }
+        opts.ui.warn(message);
     }
+    if opts.dry_run {
+        opts.ui
+            .status(format!("Would check out {} into {}\n", short, shown));
+        return Ok(run(project_root));
+    }
+
+    let clean = CleanCheckout::create(project_root, &commit, dir)?;
+    opts.ui
+        .status(format!("Checked out {} into {}\n", short, shown));
+    *opts.checkout_root.write().unwrap() = Some(clean.root().to_path_buf());
+    let result = run(clean.root());
+    *opts.checkout_root.write().unwrap() = None;
+    Ok(result)
 }
 
-/// Execute steps in parallel using scoped threads
-fn execute_parallel(
-    steps: &[StepDef],
-    default_dir: &Path,
-    default_env: &HashMap<String, String>,
+/// Execute the task named on the command line, recording each top-level step.
+///
+/// With a `selection` from a previous run, steps that passed are skipped.
+/// With `estimates`, steps that took a while before are announced with how
+/// long they took.
+fn execute_root_task(
+    task_def: &TaskDef,
+    project_root: &Path,
     config: &Config,
-) -> Result<()> {
-    use std::sync::Mutex;
-    use std::thread;
+    args: &TaskArgs,
+    opts: &RunOptions,
+    selection: Option<&TaskRecord>,
+    estimates: Option<&Estimates>,
+) -> (Vec<StepRecord>, Result<()>) {
+    let with_steps = match task_def {
+        // A `foreach` task runs all its steps per value, so they aren't recorded one by one
+        TaskDef::Full(task) if task.foreach.is_none() => {
+            task.steps.as_ref().map(|steps| (task, steps))
+        }
+        _ => None,
+    };
+    let Some((task, steps)) = with_steps else {
+        if opts.steps.is_active() {
+            let error = anyhow::anyhow!("{} only applies to a task with steps", opts.steps);
+            return (Vec::new(), Err(error));
+        }
+        return (
+            Vec::new(),
+            execute_task_def(
+                task_def,
+                project_root,
+                opts.dir.as_deref(),
+                config,
+                args,
+                None,
+                opts,
+            ),
+        );
+    };
+
+    if let Err(e) = check_cmd_fields(task).and_then(|()| check_bins(task_def, config)) {
+        return (Vec::new(), Err(e));
+    }
+    let work_dir = opts
+        .dir
+        .clone()
+        .unwrap_or_else(|| task_work_dir(task, project_root));
+    let mut env = match opts.task_env(task.env.as_ref(), &work_dir, None) {
+        Ok(env) => env,
+        Err(e) => return (Vec::new(), Err(e)),
+    };
+    let selected = |index: usize, branch: Option<usize>| {
+        selection.is_none_or(|previous| previous.should_rerun(index, branch))
+    };
 
-    let errors: Mutex<Vec<anyhow::Error>> = Mutex::new(Vec::new());
+    // Counted from the start of the run, not of this task
+    let mut budget = config
+        .budget
+        .map(|limit| Budget::new(limit, opts.started()));
 
-    thread::scope(|s| {
-        for step_def in steps {
-            s.spawn(|| {
-                if let Err(e) = execute_step_def(step_def, default_dir, default_env, config) {
-                    errors.lock().unwrap().push(e);
-                }
+    let mut records = Vec::new();
+    let mut failure: Option<anyhow::Error> = None;
+    for (position, step) in steps.iter().enumerate() {
+        let index = position + 1;
+        // The step that was running when the budget ran out finishes, but
+        // nothing starts after it
+        let out_of_budget = failure.is_none()
+            && budget.as_mut().is_some_and(|budget| {
+                budget.exhausted(opts.clock.now(), opts.ignore_guards, &opts.ui)
             });
+        // Branches of a parallel block to run; none once something has
+        // failed or the budget is used up
+        let selected_branches = |labels: Vec<String>, allowed: &dyn Fn(usize) -> bool| {
+            if failure.is_some() || out_of_budget {
+                return Vec::new();
+            }
+            (0..labels.len())
+                .filter(|&branch| selected(index, Some(branch + 1)))
+                .filter(|&branch| {
+                    let allowed = allowed(branch);
+                    if !allowed {
+                        let step = format!("{}.{}", index, branch + 1);
+                        filtered_out(&step, &labels[branch], opts);
+                    }
+                    allowed
+                })
+                .collect()
+        };
+        match step {
+            Step::Simple(step_def) => {
+                let started = Instant::now();
+                let position = StepPosition {
+                    index,
+                    branch: None,
+                };
+                let mut command_failed = None;
+                let status = if failure.is_some() {
+                    Status::NotRun
+                } else if out_of_budget {
+                    Status::SkippedOverBudget
+                } else if !selected(index, None) {
+                    Status::Skipped
+                } else if !opts.steps.allows(index, step_def) {
+                    filtered_out(&index.to_string(), &plan::describe(step_def), opts);
+                    Status::Skipped
+                } else {
+                    announce(estimates, index, &plan::describe(step_def), opts);
+                    start_step_event(position, step_def, &work_dir, project_root, opts);
+                    let result = with_step(index, || {
+                        if step_def.sets_env() {
+                            execute_env_step(step_def, &work_dir, &mut env, config, args, opts)
+                        } else if step_def.export_exit.is_some() {
+                            execute_exporting_step(
+                                step_def, &work_dir, &mut env, config, args, opts,
+                            )
+                        } else {
+                            execute_step_def(step_def, &work_dir, &env, config, args, opts)
+                        }
+                    });
+                    match result {
+                        Ok(()) => Status::Passed,
+                        Err(e) => {
+                            command_failed = command_failure(&e, project_root);
+                            failure = Some(e);
+                            failed_status()
+                        }
+                    }
+                };
+                let record = StepRecord {
+                    index,
+                    branch: None,
+                    label: plan::describe(step_def),
+                    status,
+                    duration_ms: started.elapsed().as_millis() as u64,
+                    failure: command_failed,
+                };
+                finish_step_event(&record, opts);
+                records.push(record);
+            }
+            Step::Parallel { parallel, .. } => {
+                let labels = parallel.iter().map(plan::describe).collect();
+                let branches = selected_branches(labels, &|branch| {
+                    opts.steps.allows(index, &parallel[branch])
+                });
+                let checked = with_step(index, || {
+                    check_shared_dirs(step, &branches, &work_dir, config, opts)
+                });
+                let branches = match checked {
+                    Ok(()) => branches,
+                    Err(e) => {
+                        failure = Some(e);
+                        Vec::new()
+                    }
+                };
+                if !branches.is_empty() {
+                    let label = format!("{} parallel steps", parallel.len());
+                    announce(estimates, index, &label, opts);
+                }
+                for &branch in &branches {
+                    let position = StepPosition {
+                        index,
+                        branch: Some(branch + 1),
+                    };
+                    start_step_event(position, &parallel[branch], &work_dir, project_root, opts);
+                }
+                let results = with_step(index, || {
+                    run_parallel_branches(parallel, &branches, &work_dir, &env, config, args, opts)
+                });
+                let labels = parallel.iter().map(plan::describe).collect();
+                let (branch_records, result) = record_branches(
+                    index,
+                    labels,
+                    &branches,
+                    results,
+                    unreached(failure.is_some(), out_of_budget),
+                    project_root,
+                    opts,
+                );
+                records.extend(branch_records);
+                if let Err(e) = result {
+                    failure = Some(e);
+                }
+            }
+            Step::Lanes { lanes, .. } => {
+                let labels = lanes.iter().map(lane_label).collect();
+                let branches = selected_branches(labels, &|branch| {
+                    opts.steps.allows_lane(index, &lanes[branch].steps)
+                });
+                let checked = with_step(index, || {
+                    check_shared_dirs(step, &branches, &work_dir, config, opts)
+                });
+                let branches = match checked {
+                    Ok(()) => branches,
+                    Err(e) => {
+                        failure = Some(e);
+                        Vec::new()
+                    }
+                };
+                if !branches.is_empty() {
+                    let label = format!("{} parallel steps", lanes.len());
+                    announce(estimates, index, &label, opts);
+                }
+                for &branch in &branches {
+                    let position = StepPosition {
+                        index,
+                        branch: Some(branch + 1),
+                    };
+                    start_lane_event(position, &lanes[branch], &work_dir, project_root, opts);
+                }
+                let results = with_step(index, || {
+                    run_lanes(lanes, &branches, &work_dir, &env, config, args, opts)
+                });
+                let labels = lanes.iter().map(lane_label).collect();
+                let (branch_records, result) = record_branches(
+                    index,
+                    labels,
+                    &branches,
+                    results,
+                    unreached(failure.is_some(), out_of_budget),
+                    project_root,
+                    opts,
+                );
+                records.extend(branch_records);
+                if let Err(e) = result {
+                    failure = Some(e);
+                }
+            }
         }
-    });
+    }
 
-    let errors = errors.into_inner().unwrap();
-    if errors.is_empty() {
-        Ok(())
-    } else {
-        // Combine all errors into one message
-        let error_messages: Vec<String> = errors.iter().map(|e| format!("  - {}", e)).collect();
-        anyhow::bail!(
-            "Parallel execution failed with {} error(s):\n{}",
-            error_messages.len(),
-            error_messages.join("\n")
-        )
+    let skipped: BTreeSet<usize> = records
+        .iter()
+        .filter(|record| record.status == Status::SkippedOverBudget)
+        .map(|record| record.index)
+        .collect();
+    if let (None, Some(budget)) = (&failure, &budget) {
+        if !skipped.is_empty() {
+            failure = Some(budget.overrun(opts.clock.now(), skipped.len()).into());
+        }
     }
+    (records, failure.map_or(Ok(()), Err))
 }
 
-/// Execute a step definition
-fn execute_step_def(
+/// How a step that doesn't start is recorded: not run when something before
+/// it failed, or skipped when the run is out of budget
+fn unreached(failed: bool, out_of_budget: bool) -> Option<Status> {
+    match (failed, out_of_budget) {
+        (true, _) => Some(Status::NotRun),
+        (false, true) => Some(Status::SkippedOverBudget),
+        (false, false) => None,
+    }
+}
+
+/// Show how long top-level step `index` took before, if it's worth saying
+fn announce(estimates: Option<&Estimates>, index: usize, label: &str, opts: &RunOptions) {
+    if let Some(header) = estimates.and_then(|estimates| estimates.header(index, label)) {
+        opts.ui.status(header);
+    }
+}
+
+/// Say that step `step` was left out by `--only`, `--skip` or `--steps`
+fn filtered_out(step: &str, label: &str, opts: &RunOptions) {
+    let before = format!("Skipping step {}: ", step);
+    let after = format!(" (filtered out by {})", opts.steps);
+    let used = before.chars().count() + after.chars().count();
+    opts.ui.status(format!(
+        "{}{}{}",
+        before,
+        fit_label(label, used, opts),
+        after
+    ));
+}
+
+/// A step's label cut to what's left of the terminal's width after `used`
+/// columns, or whole with `--verbose`
+fn fit_label(label: &str, used: usize, opts: &RunOptions) -> String {
+    if opts.verbose {
+        return label.to_string();
+    }
+    let width = list::terminal_width().saturating_sub(used);
+    list::truncate(label, width.max(list::MIN_LABEL_WIDTH))
+}
+
+/// Report a top-level step (or parallel branch) starting, if events are on
+fn start_step_event(
+    position: StepPosition,
     step_def: &StepDef,
     default_dir: &Path,
-    default_env: &HashMap<String, String>,
-    config: &Config,
-) -> Result<()> {
-    let work_dir = match &step_def.dir {
-        Some(dir) => {
-            let project_root = crate::config::project_root()?;
-            project_root.join(dir)
-        }
-        None => default_dir.to_path_buf(),
+    project_root: &Path,
+    opts: &RunOptions,
+) {
+    let Some(events) = &opts.events else {
+        return;
     };
+    let dir = step_work_dir(step_def, default_dir, opts).unwrap_or_else(|_| default_dir.into());
+    events.step_started(
+        position,
+        &plan::describe(step_def),
+        step_def.cmd.as_ref().map(Cmd::to_string).as_deref(),
+        &plan::relative_dir(&dir, project_root),
+    );
+}
 
-    // If step delegates to a task
-    if let Some(task_name) = &step_def.task {
-        // Check for nested rnr.yaml if dir is specified
-        if step_def.dir.is_some() {
-            let nested_config_path = work_dir.join(crate::config::CONFIG_FILE);
-            if nested_config_path.exists() {
-                let nested_config = Config::load_from(&nested_config_path)?;
-                let nested_task = nested_config.get_task(task_name).with_context(|| {
-                    format!(
-                        "Task '{}' not found in {}",
-                        task_name,
-                        nested_config_path.display()
-                    )
-                })?;
-                return execute_task_def(nested_task, &work_dir, &nested_config);
+/// Report a lane of a top-level parallel block starting, if events are on
+fn start_lane_event(
+    position: StepPosition,
+    lane: &Lane,
+    default_dir: &Path,
+    project_root: &Path,
+    opts: &RunOptions,
+) {
+    if let Some(events) = &opts.events {
+        let dir = plan::relative_dir(default_dir, project_root);
+        events.step_started(position, &lane_label(lane), None, &dir);
+    }
+}
+
+/// Record the branches of a top-level parallel block, returning their
+/// records and the block's combined result. `unreached` is the status of
+/// every branch of a block that didn't start; branches not in `branches`
+/// were skipped.
+fn record_branches(
+    index: usize,
+    labels: Vec<String>,
+    branches: &[usize],
+    results: Vec<BranchResult>,
+    unreached: Option<Status>,
+    project_root: &Path,
+    opts: &RunOptions,
+) -> (Vec<StepRecord>, Result<()>) {
+    let mut results = results.into_iter();
+    let mut records = Vec::new();
+    let mut errors = Vec::new();
+    for (branch, label) in labels.into_iter().enumerate() {
+        let mut duration = Duration::ZERO;
+        let mut command_failed = None;
+        let status = if let Some(status) = unreached {
+            status
+        } else if !branches.contains(&branch) {
+            Status::Skipped
+        } else {
+            match results.next() {
+                Some((Err(e), elapsed)) => {
+                    duration = elapsed;
+                    command_failed = command_failure(&e, project_root);
+                    errors.push(e);
+                    failed_status()
+                }
+                Some((Ok(()), elapsed)) => {
+                    duration = elapsed;
+                    Status::Passed
+                }
+                None => Status::Passed,
             }
+        };
+        let record = StepRecord {
+            index,
+            branch: Some(branch + 1),
+            label,
+            status,
+            duration_ms: duration.as_millis() as u64,
+            failure: command_failed,
+        };
+        finish_step_event(&record, opts);
+        records.push(record);
+    }
+    (records, parallel_result(errors))
+}
+
+/// Report a top-level step's outcome, if events are on
+fn finish_step_event(record: &StepRecord, opts: &RunOptions) {
+    if let Some(events) = &opts.events {
+        let position = StepPosition {
+            index: record.index,
+            branch: record.branch,
+        };
+        events.step_finished(position, record.status, record.duration_ms);
+    }
+}
+
+/// Report a task named on the command line finishing, if events are on
+fn finish_run_events(record: &TaskRecord, opts: &RunOptions) {
+    if let Some(events) = &opts.events {
+        if record.status == Status::Cancelled {
+            events.run_cancelled(&record.name, &opts.nesting.run_id);
         }
+        events.run_finished(
+            &record.name,
+            &opts.nesting.run_id,
+            record.status,
+            record.duration_ms,
+            record.error.as_deref(),
+        );
+    }
+}
 
-        let target_task = config
-            .get_task(task_name)
-            .with_context(|| format!("Task '{}' not found", task_name))?;
-        let project_root = crate::config::project_root()?;
-        return execute_task_def(target_task, &project_root, config);
+/// How a step or task that failed is recorded: cancelled if rnr was asked
+/// to terminate while it ran
+fn failed_status() -> Status {
+    match termination::requested() {
+        true => Status::Cancelled,
+        false => Status::Failed,
     }
+}
 
-    // Execute command
-    if let Some(cmd) = &step_def.cmd {
-        return execute_command(cmd, &work_dir, default_env);
+/// Build the record of a finished task
+fn task_record(
+    name: &str,
+    args: &[String],
+    steps: Vec<StepRecord>,
+    result: &Result<()>,
+    started: Instant,
+) -> TaskRecord {
+    TaskRecord {
+        name: name.to_string(),
+        args: args.to_vec(),
+        status: if result.is_ok() {
+            Status::Passed
+        } else {
+            failed_status()
+        },
+        duration_ms: started.elapsed().as_millis() as u64,
+        error: result.as_ref().err().map(|e| format!("{:#}", e)),
+        steps,
+    }
+}
+
+/// After a failed run, repeat each failed step (or the task itself, if it
+/// has no steps) with the command that failed, so the cause isn't lost in
+/// the scrollback above
+fn report_failures(
+    record: &TaskRecord,
+    error: &anyhow::Error,
+    config: &Config,
+    project_root: &Path,
+    opts: &RunOptions,
+) {
+    // The run whose task started this one prints the summary
+    if opts.nesting.is_nested() {
+        return;
+    }
+    let at = |step| {
+        config
+            .location(&record.name, step, project_root)
+            .map(|location| format!(" ({})", location))
+            .unwrap_or_default()
+    };
+    let task = format!("{}{}", record.name, at(None));
+    let failed: Vec<&StepRecord> = record
+        .steps
+        .iter()
+        .filter(|step| matches!(step.status, Status::Failed | Status::Cancelled))
+        .collect();
+    let over_budget = error.downcast_ref::<OverBudget>();
+    if failed.is_empty() && over_budget.is_none() {
+        let failure = command_failure(error, project_root);
+        opts.ui.print_err(failure_block(
+            record.status,
+            &task,
+            record.duration_ms,
+            failure.as_ref(),
+            &opts.ui,
+        ));
+    }
+    for step in failed {
+        let heading = format!(
+            "{} step {}{}: ",
+            task,
+            step.position(),
+            at(Some(step.index))
+        );
+        // Past the `FAILED ` or `CANCELLED ` before the heading
+        let word = match step.status {
+            Status::Cancelled => "CANCELLED ",
+            _ => "FAILED ",
+        };
+        let used = word.len() + heading.chars().count();
+        let heading = heading + &fit_label(&step.label, used, opts);
+        opts.ui.print_err(failure_block(
+            step.status,
+            &heading,
+            step.duration_ms,
+            step.failure.as_ref(),
+            &opts.ui,
+        ));
     }
+    if let Some(over_budget) = over_budget {
+        let skipped: BTreeSet<usize> = record
+            .steps
+            .iter()
+            .filter(|step| step.status == Status::SkippedOverBudget)
+            .map(|step| step.index)
+            .collect();
+        let skipped: Vec<String> = skipped.iter().map(usize::to_string).collect();
+        let word = opts.ui.style("OVER BUDGET").yellow().bold();
+        opts.ui.print_err(format!(
+            "\n{} {}\n  budget:      {}\n  elapsed:     {}\n  not started: step {}\n",
+            word,
+            task,
+            over_budget.limit,
+            over_budget.elapsed,
+            skipped.join(", ")
+        ));
+    }
+}
 
-    anyhow::bail!("Step has no cmd or task defined")
+/// One `FAILED` (or, in a run rnr was asked to terminate, `CANCELLED`)
+/// block of the failure summary
+fn failure_block(
+    status: Status,
+    heading: &str,
+    duration_ms: u64,
+    failure: Option<&CommandFailure>,
+    ui: &Ui,
+) -> String {
+    let word = match status {
+        Status::Cancelled => ui.style("CANCELLED").yellow().bold(),
+        _ => ui.style("FAILED").red().bold(),
+    };
+    let mut block = format!("\n{} {}\n", word, heading);
+    if let Some(failure) = failure {
+        block.push_str(&format!("  command:   {}\n", failure.command));
+        block.push_str(&format!("  dir:       {}\n", failure.dir));
+    }
+    block.push_str(&format!(
+        "  duration:  {:.1}s\n",
+        duration_ms as f64 / 1000.0
+    ));
+    if let Some(failure) = failure {
+        block.push_str(&format!("  exit code: {}\n", failure.exit_code));
+    }
+    block
 }
 
-/// Execute a shell command
-fn execute_command(cmd: &str, work_dir: &Path, env: &HashMap<String, String>) -> Result<()> {
-    println!("$ {}", cmd);
+/// The command behind an error, if it came from one exiting non-zero
+fn command_failure(error: &anyhow::Error, project_root: &Path) -> Option<CommandFailure> {
+    error
+        .downcast_ref::<CommandFailed>()
+        .map(|failed| CommandFailure {
+            command: failed.line.clone(),
+            dir: plan::relative_dir(&failed.dir, project_root),
+            exit_code: failed.code,
+        })
+}
 
-    let mut command = if cfg!(target_os = "windows") {
-        let mut c = Command::new("cmd");
-        c.args(["/C", cmd]);
-        c
-    } else {
-        let mut c = Command::new("sh");
-        c.args(["-c", cmd]);
-        c
+/// What earlier runs say about the top-level steps of `task`; a history
+/// that can't be read only warns, and is started over
+fn step_estimates(project_root: &Path, plan: &Plan, task: &TaskDef, ui: &Ui) -> Estimates {
+    let history = durations::load(project_root).unwrap_or_else(|e| {
+        ui.warn(format!("{:#}", e));
+        History::default()
+    });
+    let steps = match task {
+        TaskDef::Full(task) if task.foreach.is_none() => task.steps.as_ref().map_or(0, Vec::len),
+        _ => 0,
     };
+    Estimates::new(&history, plan, steps)
+}
 
-    command.current_dir(work_dir);
-    command.envs(env);
+/// Add a successful run's step durations to the history; failures only warn
+fn save_durations(project_root: &Path, estimates: &Estimates, steps: &[StepRecord], ui: &Ui) {
+    let mut history = durations::load(project_root).unwrap_or_default();
+    estimates.record(&mut history, steps);
+    if let Err(e) = durations::save(project_root, &history) {
+        ui.warn(format!("failed to record step durations: {:#}", e));
+    }
+}
 
-    let status = command
-        .status()
-        .with_context(|| format!("Failed to execute command: {}", cmd))?;
+/// Persist run results; failures only warn so they never mask the task's outcome
+fn save_run(project_root: &Path, tasks: Vec<TaskRecord>, ui: &Ui) {
+    let saved = history::config_hash(project_root)
+        .and_then(|hash| history::save(project_root, &RunRecord::new(hash, tasks)));
+    if let Err(e) = saved {
+        ui.warn(format!("failed to record run results: {:#}", e));
+    }
+}
 
-    if !status.success() {
-        let code = status.code().unwrap_or(1);
-        anyhow::bail!("Command failed with exit code {}", code);
+/// Resolve the task name given on the command line.
+///
+/// Exact names always win, then `renamed` names, then the one task named
+/// the same but for case. With abbreviations enabled, a unique prefix or
+/// segment match is accepted. Unknown names get "did you mean" suggestions.
+fn resolve_task_name(config: &Config, task_name: &str, opts: &RunOptions) -> Result<String> {
+    let strict = opts.strict || config.strict;
+    if let Some(task) = config.get_task(task_name) {
+        if let Some(deprecated) = task.deprecated() {
+            let mut message = format!("Task '{}' is deprecated", task_name);
+            if let Some(replacement) = deprecated.replaced_by() {
+                message.push_str(&format!("; use '{}' instead", replacement));
+            }
+            if let Some(extra) = deprecated.message() {
+                message.push_str(&format!(" ({})", extra));
+            }
+            if strict {
+                anyhow::bail!(message);
+            }
+            opts.ui.warn(message);
+        }
+        return Ok(task_name.to_string());
+    }
+    if let Some(new_name) = config.renamed_to(task_name) {
+        if config.get_task(new_name).is_none() {
+            anyhow::bail!(
+                "Task '{}' was renamed to '{}', which isn't a task",
+                task_name,
+                new_name
+            );
+        }
+        if strict {
+            anyhow::bail!(
+                "Task '{}' was renamed to '{}'; run 'rnr {}' instead",
+                task_name,
+                new_name,
+                new_name
+            );
+        }
+        opts.ui.warn(format!(
+            "Task '{}' was renamed to '{}'; running '{}'",
+            task_name, new_name, new_name
+        ));
+        return resolve_task_name(config, new_name, opts);
+    }
+    if let Some(name) = config.match_case(task_name, strict)? {
+        opts.ui
+            .notice(format!("Running '{}' (matched '{}')", name, task_name));
+        return resolve_task_name(config, name, opts);
     }
 
-    Ok(())
+    let names = config.task_names();
+    if opts.fuzzy || config.allow_abbreviations {
+        match suggest::abbreviation(task_name, &names) {
+            Abbreviation::Unique(name) => {
+                opts.ui
+                    .notice(format!("Running '{}' (matched '{}')", name, task_name));
+                return Ok(name.to_string());
+            }
+            Abbreviation::Ambiguous(candidates) => anyhow::bail!(
+                "Task '{}' is ambiguous. Candidates: {}",
+                task_name,
+                candidates.join(", ")
+            ),
+            Abbreviation::None => {}
+        }
+    }
+
+    let subcommands = crate::cli::subcommand_names();
+    let subcommands: Vec<&str> = subcommands.iter().map(String::as_str).collect();
+    let task = suggest::did_you_mean(task_name, &names).first().copied();
+    let subcommand = suggest::did_you_mean(task_name, &subcommands)
+        .first()
+        .copied();
+    match (task, subcommand) {
+        (Some(task), Some(subcommand)) => anyhow::bail!(
+            "Task '{}' not found. Did you mean '{}', or the '{}' subcommand?",
+            task_name,
+            task,
+            subcommand
+        ),
+        (Some(task), None) => {
+            anyhow::bail!("Task '{}' not found. Did you mean '{}'?", task_name, task)
+        }
+        (None, Some(subcommand)) => anyhow::bail!(
+            "Task '{}' not found. Did you mean the '{}' subcommand?",
+            task_name,
+            subcommand
+        ),
+        (None, None) if names.is_empty() => anyhow::bail!(
+            "Task '{}' not found: {} doesn't define any tasks yet",
+            task_name,
+            config
+                .path()
+                .unwrap_or(Path::new(config::CONFIG_FILE))
+                .display()
+        ),
+        (None, None) => anyhow::bail!(
+            "Task '{}' not found. Run 'rnr --list' to see available tasks",
+            task_name
+        ),
+    }
+}
+
+/// Execute a task definition, with `caller_env` layered over its own `env`,
+/// in `dir` instead of its own `dir` if given
+fn execute_task_def(
+    task_def: &TaskDef,
+    project_root: &Path,
+    dir: Option<&Path>,
+    config: &Config,
+    args: &TaskArgs,
+    caller_env: Option<&HashMap<String, String>>,
+    opts: &RunOptions,
+) -> Result<()> {
+    check_bins(task_def, config)?;
+    with_priority(task_def.priority(), || match task_def {
+        TaskDef::Shorthand(cmd) => {
+            let cmd = Cmd::Shell(cmd.clone());
+            let work_dir = dir.unwrap_or(project_root);
+            let env = opts.task_env(None, work_dir, caller_env)?;
+            let echo = Echo::new(config, None);
+            execute_command(&cmd, work_dir, &env, args, config, None, None, echo, opts).map(|_| ())
+        }
+        TaskDef::Full(task) => {
+            execute_full_task(task, project_root, dir, config, args, caller_env, opts)
+        }
+    })
+}
+
+/// Fail before the running task starts if a bin its commands use is
+/// undeclared or missing
+fn check_bins(task_def: &TaskDef, config: &Config) -> Result<()> {
+    match bins::problems(&running().task, task_def, config)
+        .into_iter()
+        .next()
+    {
+        Some(problem) => Err(anyhow::anyhow!(problem)),
+        None => Ok(()),
+    }
+}
+
+/// Execute a full task definition
+fn execute_full_task(
+    task: &Task,
+    project_root: &Path,
+    dir: Option<&Path>,
+    config: &Config,
+    args: &TaskArgs,
+    caller_env: Option<&HashMap<String, String>>,
+    opts: &RunOptions,
+) -> Result<()> {
+    let work_dir = dir
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| task_work_dir(task, project_root));
+    let env = opts.task_env(task.env.as_ref(), &work_dir, caller_env)?;
+    if task.task_args.is_some() && task.task.is_none() {
+        anyhow::bail!("'task_args' only applies to a task that delegates with 'task'");
+    }
+    let runs_more = task.cmd.is_some() || task.task.is_some() || task.steps.is_some();
+    if task.choose.is_some() && runs_more {
+        anyhow::bail!(
+            "'choose' runs the task of the option picked, so it can't have 'cmd', \
+             'task' or 'steps'"
+        );
+    }
+    check_cmd_fields(task)?;
+    match &task.foreach {
+        Some(foreach) => execute_foreach(
+            foreach,
+            task,
+            project_root,
+            dir,
+            &work_dir,
+            &env,
+            config,
+            args,
+            opts,
+        ),
+        None => execute_task_body(task, project_root, dir, &work_dir, env, config, args, opts),
+    }
+}
+
+/// Execute a full task's `steps`, `task` or `cmd` in `work_dir`
+#[allow(clippy::too_many_arguments)]
+fn execute_task_body(
+    task: &Task,
+    project_root: &Path,
+    dir: Option<&Path>,
+    work_dir: &Path,
+    mut env: HashMap<String, String>,
+    config: &Config,
+    args: &TaskArgs,
+    opts: &RunOptions,
+) -> Result<()> {
+    // If task has steps, execute them
+    if let Some(steps) = &task.steps {
+        for (position, step) in steps.iter().enumerate() {
+            with_step(position + 1, || match step {
+                Step::Simple(step_def) if step_def.sets_env() => {
+                    execute_env_step(step_def, work_dir, &mut env, config, args, opts)
+                }
+                Step::Simple(step_def) if step_def.export_exit.is_some() => {
+                    execute_exporting_step(step_def, work_dir, &mut env, config, args, opts)
+                }
+                _ => execute_step(step, work_dir, &env, config, args, opts),
+            })?;
+        }
+        return Ok(());
+    }
+
+    if let Some(choose) = &task.choose {
+        let nested_dir = (task.dir.is_some() || dir.is_some()).then_some(work_dir);
+        return run_choice(choose, nested_dir, project_root, &env, config, args, opts);
+    }
+
+    // If task delegates to another task
+    if let Some(task_name) = &task.task {
+        let label = format!("task {}", task_name);
+        return trace::span(opts.trace.as_ref(), &label, "task", || {
+            let nested_dir = (task.dir.is_some() || dir.is_some()).then_some(work_dir);
+            let invocation = Invocation {
+                env: Some(&env),
+                args: task.task_args.as_deref().unwrap_or_default(),
+            };
+            run_task_ref(
+                task_name,
+                invocation,
+                nested_dir,
+                project_root,
+                config,
+                opts,
+            )
+        });
+    }
+
+    // Execute command if present
+    if let Some(cmd) = &task.cmd {
+        let echo = Echo::new(config, task.echo);
+        return execute_command(
+            cmd,
+            work_dir,
+            &env,
+            args,
+            config,
+            task.output_filter,
+            task.expect_exit.as_ref(),
+            echo,
+            opts,
+        )
+        .map(|_| ());
+    }
+
+    anyhow::bail!("Task has no cmd, task, or steps defined")
+}
+
+/// Execute a full task's body once per value of its `foreach`, set as
+/// `${foreach.<as>}` and env var `<as>`, with the output of each iteration
+/// prefixed by its value. `jobs` of them (capped by `--jobs`) run at once.
+/// Unless `continue_on_error` is set, no iteration starts after one fails.
+/// The failed iterations are listed together at the end.
+#[allow(clippy::too_many_arguments)]
+fn execute_foreach(
+    foreach: &Foreach,
+    task: &Task,
+    project_root: &Path,
+    dir: Option<&Path>,
+    work_dir: &Path,
+    env: &HashMap<String, String>,
+    config: &Config,
+    args: &TaskArgs,
+    opts: &RunOptions,
+) -> Result<()> {
+    foreach.check()?;
+    let values = foreach_values(foreach, work_dir, env, opts)?;
+    if values.is_empty() {
+        opts.ui.notice(format!(
+            "foreach {}: no values, nothing to run",
+            foreach.name
+        ));
+        return Ok(());
+    }
+
+    let started: Vec<AtomicBool> = values.iter().map(|_| AtomicBool::new(false)).collect();
+    let stopped = AtomicBool::new(false);
+    let iteration = |index: usize| {
+        if stopped.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        started[index].store(true, Ordering::SeqCst);
+        let value = &values[index];
+        let mut env = env.clone();
+        env.insert(foreach.name.clone(), value.clone());
+        let mut args = args.clone();
+        args.foreach.insert(foreach.name.clone(), value.clone());
+        let lane = match current_lane() {
+            Some(outer) => format!("{}/{}", outer, value),
+            None => value.clone(),
+        };
+        let result = with_lane(Some(lane), || {
+            execute_task_body(task, project_root, dir, work_dir, env, config, &args, opts)
+        });
+        if result.is_err() && !foreach.continue_on_error {
+            stopped.store(true, Ordering::SeqCst);
+        }
+        result
+    };
+    let jobs = foreach
+        .jobs
+        .map_or(1, NonZeroUsize::get)
+        .min(opts.jobs.unwrap_or(usize::MAX));
+    let indexes: Vec<usize> = (0..values.len()).collect();
+    let results = run_on_threads(&indexes, jobs, |_| 1, iteration);
+
+    let not_run = started
+        .iter()
+        .filter(|started| !started.load(Ordering::SeqCst))
+        .count();
+    let failures: Vec<String> = values
+        .iter()
+        .zip(results)
+        .filter_map(|(value, (result, _))| {
+            result
+                .err()
+                .map(|error| format!("  - {}: {:#}", value, error))
+        })
+        .collect();
+    if failures.is_empty() {
+        return Ok(());
+    }
+    let mut summary = format!(
+        "foreach {} failed for {} of {} value(s)",
+        foreach.name,
+        failures.len(),
+        values.len()
+    );
+    if not_run > 0 {
+        summary.push_str(&format!(", {} not run", not_run));
+    }
+    anyhow::bail!("{}:\n{}", summary, failures.join("\n"))
+}
+
+/// The values a `foreach` runs for: its `values`, or each non-blank line
+/// printed by its `from_cmd`, run in `work_dir` before any iteration starts.
+/// A dry run doesn't run the command, and previews one iteration with
+/// `<as>` for the value.
+fn foreach_values(
+    foreach: &Foreach,
+    work_dir: &Path,
+    env: &HashMap<String, String>,
+    opts: &RunOptions,
+) -> Result<Vec<String>> {
+    let Some(cmd) = &foreach.from_cmd else {
+        return Ok(foreach.values.clone().unwrap_or_default());
+    };
+    if opts.dry_run {
+        opts.ui.status(format!(
+            "foreach {}: would run once per line printed by '{}'",
+            foreach.name, cmd
+        ));
+        return Ok(vec![format!("<{}>", foreach.name)]);
+    }
+    let mut command = shell_command(cmd, work_dir);
+    command.envs(env).stdin(Stdio::null());
+    let output = command
+        .output()
+        .with_context(|| format!("Failed to run '{}' for foreach {}", cmd, foreach.name))?;
+    exit_result(output.status, cmd.to_string(), &command, None).with_context(|| {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let mut message = format!(
+            "Failed to list foreach {} values from '{}'",
+            foreach.name, cmd
+        );
+        if !stderr.trim().is_empty() {
+            message.push_str(&format!(":\n{}", stderr.trim_end()));
+        }
+        message
+    })?;
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+/// A task's `output_filter`, `echo` and `expect_exit` apply to its `cmd`;
+/// steps set their own
+fn check_cmd_fields(task: &Task) -> Result<()> {
+    let cmd_only = task.cmd.is_none() || task.steps.is_some();
+    for (field, set) in [
+        ("output_filter", task.output_filter.is_some()),
+        ("echo", task.echo.is_some()),
+        ("expect_exit", task.expect_exit.is_some()),
+    ] {
+        if set && cmd_only {
+            anyhow::bail!(
+                "'{}' only applies to a task's 'cmd'; set it on each step instead",
+                field
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Run the task a `task:` reference points at, from `config` (whose tasks
+/// run in `config_dir`) or the nested rnr.yaml the reference names.
+///
+/// Each reference runs anew: the same task with different env or args is
+/// simply run again with those.
+fn run_task_ref(
+    reference: &str,
+    invocation: Invocation,
+    nested_dir: Option<&Path>,
+    config_dir: &Path,
+    config: &Config,
+    opts: &RunOptions,
+) -> Result<()> {
+    let root = opts.project_root()?;
+    let target = config::resolve_task_ref(reference, nested_dir, &root, config, |path| {
+        opts.load_config(path)
+    })?;
+    let (name, config, dir) = match &target {
+        TaskTarget::Current(name) => (*name, config, config_dir),
+        TaskTarget::Nested { name, config, dir } => (*name, config.as_ref(), dir.as_path()),
+    };
+    let name = match config.match_case(name, opts.strict || config.strict)? {
+        Some(matched) if matched != name => {
+            opts.ui
+                .notice(format!("Running '{}' (matched '{}')", matched, name));
+            matched
+        }
+        Some(matched) => matched,
+        None => return Err(config::task_not_found(name, config, &root, &opts.configs)),
+    };
+    let task = config.get_task(name).unwrap();
+    opts.check_context(name, task, config)?;
+    let args = crate::args::bind(name, task, invocation.args, &opts.ui)?;
+    if !task.is_once() {
+        report_env(name, task, invocation.env, config, &args, opts);
+        return with_task_log(name, task, dir, &args, opts, || {
+            with_task(config, name, || {
+                opts.chaos.at_task(name)?;
+                execute_task_def(task, dir, None, config, &args, invocation.env, opts)
+            })
+        })?;
+    }
+
+    // Delegations waiting on a `once` task that's running see how it ended;
+    // a failure isn't remembered, so a later delegation tries again. One
+    // that reaches back to a task it's part of would wait on itself.
+    check_cycle(config, name)?;
+    let slot = opts.once_slot(config, name);
+    let mut succeeded = slot.lock().unwrap_or_else(PoisonError::into_inner);
+    if *succeeded {
+        opts.ui.status(format!("{} (already ran)", name));
+        return Ok(());
+    }
+    report_env(name, task, invocation.env, config, &args, opts);
+    with_task_log(name, task, dir, &args, opts, || {
+        with_task(config, name, || {
+            opts.chaos.at_task(name)?;
+            execute_task_def(task, dir, None, config, &args, invocation.env, opts)
+        })
+    })??;
+    *succeeded = true;
+    Ok(())
+}
+
+/// Run the task of the `choose` option named by the running task's first
+/// argument or `--choice`, or else picked from the menu in a terminal. A dry
+/// run shows the menu rather than asking.
+fn run_choice(
+    choose: &Choose,
+    nested_dir: Option<&Path>,
+    config_dir: &Path,
+    env: &HashMap<String, String>,
+    config: &Config,
+    args: &TaskArgs,
+    opts: &RunOptions,
+) -> Result<()> {
+    let task = running().task;
+    let (choice, rest) = choose::given(&args.extra)?;
+    if opts.dry_run {
+        choose::menu(choose)
+            .iter()
+            .for_each(|line| opts.ui.status(line));
+    }
+    let option = match choice {
+        Some(choice) => choose::named(&task, choose, &choice)?,
+        None if opts.dry_run => {
+            opts.ui.status("(would ask which one to run)");
+            return Ok(());
+        }
+        None => choose::ask(&task, choose)?,
+    };
+    opts.ui.notice(format!(
+        "Running '{}' (chose '{}')",
+        option.task, option.name
+    ));
+    let label = format!("task {}", option.task);
+    trace::span(opts.trace.as_ref(), &label, "task", || {
+        let invocation = Invocation {
+            env: Some(env),
+            args: &rest,
+        };
+        run_task_ref(
+            &option.task,
+            invocation,
+            nested_dir,
+            config_dir,
+            config,
+            opts,
+        )
+    })
+}
+
+/// Start keeping a task's output for its failure `hook`, if it has one
+fn start_capture(hook: Option<&Hook>, opts: &RunOptions) -> Option<Capture> {
+    if hook.is_none() || opts.dry_run {
+        return None;
+    }
+    match Capture::start(&opts.ui) {
+        Ok(capture) => Some(capture),
+        Err(e) => {
+            opts.ui
+                .warn(format!("on_failure won't get the output: {:#}", e));
+            None
+        }
+    }
+}
+
+/// Run `f` with the output of the commands it runs kept in `capture`
+fn with_capture<T>(capture: Option<&Capture>, f: impl FnOnce() -> T) -> T {
+    let Some(log) = capture.and_then(Capture::log) else {
+        return f();
+    };
+    let mut logs = task_log::current();
+    logs.push(log);
+    task_log::with_logs(logs, f)
+}
+
+/// Run the failure `hook` of the task `record` describes if `result` is a
+/// failure; a dry run only says what it would run
+fn on_failure(
+    hook: Option<&Hook>,
+    record: &TaskRecord,
+    result: &Result<()>,
+    capture: Option<Capture>,
+    config: &Config,
+    project_root: &Path,
+    opts: &RunOptions,
+) {
+    let Some(hook) = hook else {
+        return;
+    };
+    if opts.dry_run {
+        opts.ui.status(format!(
+            "on_failure: would run {} if '{}' fails",
+            hook, record.name
+        ));
+        return;
+    }
+    let Err(error) = result else {
+        return;
+    };
+    // Nothing new starts in a run that's being cancelled
+    if termination::requested() {
+        return;
+    }
+
+    let failed = record
+        .steps
+        .iter()
+        .find(|step| step.status == Status::Failed);
+    let exit_code = failed
+        .and_then(|step| step.failure.as_ref())
+        .map(|failure| failure.exit_code)
+        .or_else(|| command_failure(error, project_root).map(|failure| failure.exit_code))
+        .unwrap_or(1);
+    let log = capture
+        .as_ref()
+        .and_then(|capture| match capture.write_tail() {
+            Ok(path) => Some(path),
+            Err(e) => {
+                opts.ui.warn(format!("{:#}", e));
+                None
+            }
+        });
+    let step = failed.map(StepRecord::position);
+    let env = failure_hook::env(&record.name, step.as_deref(), exit_code, log);
+
+    // The hook runs no hook of its own, so a failing hook can't set off
+    // another, and its failure is reported without replacing the task's
+    opts.ui.status(format!(
+        "'{}' failed; on_failure runs {}",
+        record.name, hook
+    ));
+    let ran = match hook {
+        Hook::Task(name) => {
+            let invocation = Invocation {
+                env: Some(&env),
+                args: &[],
+            };
+            run_task_ref(name, invocation, None, project_root, config, opts)
+        }
+        Hook::Cmd { cmd } => opts
+            .task_env(None, project_root, Some(&env))
+            .and_then(|env| {
+                let echo = Echo::new(config, None);
+                let args = TaskArgs::default();
+                execute_command(
+                    cmd,
+                    project_root,
+                    &env,
+                    &args,
+                    config,
+                    None,
+                    None,
+                    echo,
+                    opts,
+                )
+                .map(|_| ())
+            }),
+    };
+    if let Err(e) = ran {
+        opts.ui
+            .warn(format!("on_failure ({}) failed too: {:#}", hook, e));
+    }
+}
+
+/// Run `f` with the `log` of task `name` open, if it has one, so the output
+/// of the commands `f` runs is copied there. Paths are relative to `dir`, the
+/// directory of the task's rnr.yaml. Nothing is opened in a dry run.
+fn with_task_log<T>(
+    name: &str,
+    task_def: &TaskDef,
+    dir: &Path,
+    args: &TaskArgs,
+    opts: &RunOptions,
+    f: impl FnOnce() -> T,
+) -> Result<T> {
+    let TaskDef::Full(task) = task_def else {
+        return Ok(f());
+    };
+    if opts.dry_run {
+        return Ok(f());
+    }
+    let scope = Scope::new(dir, &opts.env)
+        .with_args(&args.values)
+        .with_foreach(&args.foreach);
+    let Some(log) = TaskLog::open(name, task, dir, scope, &opts.ui)? else {
+        return Ok(f());
+    };
+    if opts.verbose {
+        opts.ui
+            .notice(format!("Logging '{}' to {}", name, log.path().display()));
+    }
+    let mut logs = task_log::current();
+    logs.push(Arc::new(log));
+    Ok(task_log::with_logs(logs, f))
+}
+
+/// With `--verbose`, show the variables rnr sets for a task on stderr
+fn report_env(
+    name: &str,
+    task: &TaskDef,
+    caller_env: Option<&HashMap<String, String>>,
+    config: &Config,
+    args: &TaskArgs,
+    opts: &RunOptions,
+) {
+    if opts.verbose {
+        let report = EnvReport::new(task.env().as_ref(), caller_env, &opts.env, args);
+        let hidden = HiddenVars::for_config(config);
+        opts.ui.notice(format!(
+            "env for {}:\n{}",
+            name,
+            report.render(&hidden).trim_end()
+        ));
+    }
+}
+
+/// Working directory of a task
+fn task_work_dir(task: &Task, project_root: &Path) -> PathBuf {
+    match &task.dir {
+        Some(dir) => config::join_dir(project_root, dir),
+        None => project_root.to_path_buf(),
+    }
+}
+
+/// Execute a single step
+fn execute_step(
+    step: &Step,
+    default_dir: &Path,
+    default_env: &HashMap<String, String>,
+    config: &Config,
+    args: &TaskArgs,
+    opts: &RunOptions,
+) -> Result<()> {
+    match step {
+        Step::Simple(step_def) => {
+            execute_step_def(step_def, default_dir, default_env, config, args, opts)
+        }
+        Step::Parallel { parallel, .. } => {
+            let branches: Vec<usize> = (0..parallel.len()).collect();
+            check_shared_dirs(step, &branches, default_dir, config, opts)?;
+            parallel_summary(&execute_parallel(
+                parallel,
+                default_dir,
+                default_env,
+                config,
+                args,
+                opts,
+            ))
+        }
+        Step::Lanes { lanes, .. } => {
+            let branches: Vec<usize> = (0..lanes.len()).collect();
+            check_shared_dirs(step, &branches, default_dir, config, opts)?;
+            let errors = run_lanes(
+                lanes,
+                &branches,
+                default_dir,
+                default_env,
+                config,
+                args,
+                opts,
+            )
+            .into_iter()
+            .filter_map(|(result, _)| result.err())
+            .collect();
+            parallel_result(errors)
+        }
+    }
+}
+
+/// Warn (or fail, in strict mode) when `branches` of a parallel block work in
+/// the same directory, unless the block sets `allow_shared_dir: true`
+fn check_shared_dirs(
+    step: &Step,
+    branches: &[usize],
+    default_dir: &Path,
+    config: &Config,
+    opts: &RunOptions,
+) -> Result<()> {
+    let Running {
+        task, step: number, ..
+    } = running();
+    let number = number.unwrap_or_default();
+    let footprint = |step_def| step_footprint(step_def, default_dir, config, opts);
+    let branches: Vec<shared_dir::Branch> = match step {
+        Step::Parallel {
+            allow_shared_dir: false,
+            parallel,
+        } => branches
+            .iter()
+            .map(|&branch| shared_dir::Branch {
+                name: format!(
+                    "{}.{} ({})",
+                    number,
+                    branch + 1,
+                    plan::describe(&parallel[branch])
+                ),
+                paths: footprint(&parallel[branch]).into_iter().collect(),
+            })
+            .collect(),
+        Step::Lanes {
+            allow_shared_dir: false,
+            lanes,
+        } => branches
+            .iter()
+            .map(|&branch| shared_dir::Branch {
+                name: lane_label(&lanes[branch]),
+                paths: lanes[branch].steps.iter().filter_map(footprint).collect(),
+            })
+            .collect(),
+        _ => return Ok(()),
+    };
+
+    let root = opts.project_root()?;
+    for shared in shared_dir::find(&branches) {
+        let message = format!(
+            "Parallel branches of task '{}' step {} work in the same directory ({}): {}. \
+             They may interfere; set 'allow_shared_dir: true' on the block if that's intended",
+            task,
+            number,
+            plan::relative_dir(&shared.path, &root),
+            shared.branches.join(", ")
+        );
+        if opts.strict || config.strict {
+            anyhow::bail!(message);
+        }
+        opts.ui.warn(message);
+    }
+    Ok(())
+}
+
+/// Where a parallel branch works: the directory its command, or the task it
+/// delegates to, runs in, or the target of its builtin file step
+fn step_footprint(
+    step_def: &StepDef,
+    default_dir: &Path,
+    config: &Config,
+    opts: &RunOptions,
+) -> Option<PathBuf> {
+    let work_dir = step_work_dir(step_def, default_dir, opts).ok()?;
+    if step_def.cmd.is_some() {
+        return Some(work_dir);
+    }
+    if let Some(reference) = &step_def.task {
+        // Unresolvable references are reported when the branch runs
+        let root = opts.project_root().ok()?;
+        let nested_dir = step_def.dir.is_some().then_some(work_dir.as_path());
+        let target = config::resolve_task_ref(reference, nested_dir, &root, config, |path| {
+            opts.load_config(path)
+        })
+        .ok()?;
+        let (task, dir) = match &target {
+            TaskTarget::Current(name) => (config.get_task(name)?, root.as_path()),
+            TaskTarget::Nested { name, config, dir } => (config.get_task(name)?, dir.as_path()),
+        };
+        return Some(match task {
+            TaskDef::Full(task) => task_work_dir(task, dir),
+            TaskDef::Shorthand(_) => dir.to_path_buf(),
+        });
+    }
+    let target = match step_def {
+        StepDef {
+            copy: Some(copy), ..
+        } => &copy.to,
+        StepDef {
+            remove: Some(remove),
+            ..
+        } => remove.path(),
+        StepDef {
+            mkdir: Some(dir), ..
+        } => dir,
+        StepDef {
+            download: Some(download),
+            ..
+        } => &download.to,
+        StepDef {
+            render: Some(render),
+            ..
+        } => &render.to,
+        _ => return None,
+    };
+    Some(work_dir.join(target))
+}
+
+/// How one branch of a parallel block went
+#[derive(Debug)]
+struct StepOutcome {
+    /// 1-based branch within the block
+    branch: usize,
+    /// Short description of the step, e.g. `$ cargo test` or `task lint`
+    label: String,
+    /// Passed or failed
+    status: Status,
+    /// Wall-clock duration
+    duration: Duration,
+    /// Why it failed
+    error: Option<anyhow::Error>,
+}
+
+/// Execute the steps of a parallel block at once, returning each one's
+/// outcome in declaration order
+fn execute_parallel(
+    steps: &[StepDef],
+    default_dir: &Path,
+    default_env: &HashMap<String, String>,
+    config: &Config,
+    args: &TaskArgs,
+    opts: &RunOptions,
+) -> Vec<StepOutcome> {
+    let branches: Vec<usize> = (0..steps.len()).collect();
+    let results = run_parallel_branches(
+        steps,
+        &branches,
+        default_dir,
+        default_env,
+        config,
+        args,
+        opts,
+    );
+    steps
+        .iter()
+        .zip(results)
+        .enumerate()
+        .map(|(branch, (step_def, (result, duration)))| StepOutcome {
+            branch: branch + 1,
+            label: plan::describe(step_def),
+            status: if result.is_ok() {
+                Status::Passed
+            } else {
+                Status::Failed
+            },
+            duration,
+            error: result.err(),
+        })
+        .collect()
+}
+
+/// Combine the outcomes of a parallel block into one result, naming the
+/// branch each error came from
+fn parallel_summary(outcomes: &[StepOutcome]) -> Result<()> {
+    let failures: Vec<String> = outcomes
+        .iter()
+        .filter(|outcome| outcome.status == Status::Failed)
+        .map(|outcome| {
+            let error = match &outcome.error {
+                Some(error) => format!("{:#}", error),
+                None => "failed".to_string(),
+            };
+            format!(
+                "  - branch {} ({}) after {:.1}s: {}",
+                outcome.branch,
+                outcome.label,
+                outcome.duration.as_secs_f64(),
+                error
+            )
+        })
+        .collect();
+    if failures.is_empty() {
+        return Ok(());
+    }
+    anyhow::bail!(
+        "Parallel execution failed with {} error(s):\n{}",
+        failures.len(),
+        failures.join("\n")
+    )
+}
+
+/// Outcome and duration of one parallel branch
+type BranchResult = (Result<()>, Duration);
+
+/// Run the selected branches of a parallel block, returning their results and durations in order
+fn run_parallel_branches(
+    steps: &[StepDef],
+    branches: &[usize],
+    default_dir: &Path,
+    default_env: &HashMap<String, String>,
+    config: &Config,
+    args: &TaskArgs,
+    opts: &RunOptions,
+) -> Vec<BranchResult> {
+    // Spans, lanes, and task logs are tracked per thread, so traced runs,
+    // blocks inside a lane, logged tasks, and line-buffered output (whose
+    // branches are labeled lanes when plain) keep to threads
+    #[cfg(feature = "tokio")]
+    if opts.trace.is_none()
+        && current_lane().is_none()
+        && task_log::current().is_empty()
+        && !opts.ui.line_buffered()
+    {
+        return async_exec::run_parallel_branches(
+            steps,
+            branches,
+            default_dir,
+            default_env,
+            config,
+            args,
+            opts,
+        );
+    }
+    run_threaded_branches(
+        steps,
+        branches,
+        default_dir,
+        default_env,
+        config,
+        args,
+        opts,
+    )
+}
+
+/// Run parallel branches on scoped threads, at most `--jobs` at a time
+fn run_threaded_branches(
+    steps: &[StepDef],
+    branches: &[usize],
+    default_dir: &Path,
+    default_env: &HashMap<String, String>,
+    config: &Config,
+    args: &TaskArgs,
+    opts: &RunOptions,
+) -> Vec<BranchResult> {
+    let weight = |branch: usize| opts.branch_slots(steps[branch].weight);
+    let jobs = opts.parallel_slots(branches.len());
+    run_on_threads(branches, jobs, weight, |branch| {
+        with_step(branch + 1, || {
+            with_lane(branch_lane(opts), || {
+                execute_step_def(&steps[branch], default_dir, default_env, config, args, opts)
+            })
+        })
+    })
+}
+
+/// The lane a parallel branch's output is labeled with: the lane it's in,
+/// or with plain output, its task and step, e.g. `ci:2.1`
+fn branch_lane(opts: &RunOptions) -> Option<String> {
+    current_lane().or_else(|| {
+        let running = running();
+        let step = running.step?;
+        opts.ui
+            .is_plain()
+            .then(|| format!("{}:{}", running.task, step))
+    })
+}
+
+/// Run the selected lanes of a parallel block at once, each on its own
+/// thread, returning their results and durations in order
+fn run_lanes(
+    lanes: &[Lane],
+    branches: &[usize],
+    default_dir: &Path,
+    default_env: &HashMap<String, String>,
+    config: &Config,
+    args: &TaskArgs,
+    opts: &RunOptions,
+) -> Vec<BranchResult> {
+    // A lane holds its slots for all its steps, so it takes its heaviest's
+    let weight = |branch: usize| {
+        let heaviest = lanes[branch]
+            .steps
+            .iter()
+            .filter_map(|step| step.weight)
+            .max();
+        opts.branch_slots(heaviest)
+    };
+    let jobs = opts.parallel_slots(branches.len());
+    run_on_threads(branches, jobs, weight, |branch| {
+        with_step(branch + 1, || {
+            run_lane(&lanes[branch], default_dir, default_env, config, args, opts)
+        })
+    })
+}
+
+/// Run a lane's steps in order, stopping at the first failure
+fn run_lane(
+    lane: &Lane,
+    default_dir: &Path,
+    default_env: &HashMap<String, String>,
+    config: &Config,
+    args: &TaskArgs,
+    opts: &RunOptions,
+) -> Result<()> {
+    let name = match current_lane() {
+        Some(outer) => format!("{}/{}", outer, lane.name),
+        None => lane.name.clone(),
+    };
+    with_lane(Some(name), || {
+        for (position, step_def) in lane.steps.iter().enumerate() {
+            with_step(position + 1, || {
+                execute_step_def(step_def, default_dir, default_env, config, args, opts)
+            })
+            .with_context(|| {
+                format!(
+                    "lane '{}' step {} ({})",
+                    lane.name,
+                    position + 1,
+                    plan::describe(step_def)
+                )
+            })?;
+        }
+        Ok(())
+    })
+}
+
+/// How a lane is described in run records
+fn lane_label(lane: &Lane) -> String {
+    format!("lane {}", lane.name)
+}
+
+/// Run `branch` for each of `branches` on scoped threads sharing `jobs`
+/// slots, returning the results and durations in order. Branches start in
+/// order, each once its `weight` in slots is free.
+fn run_on_threads(
+    branches: &[usize],
+    jobs: usize,
+    weight: impl Fn(usize) -> usize + Sync,
+    branch: impl Fn(usize) -> Result<()> + Sync,
+) -> Vec<BranchResult> {
+    use std::thread;
+
+    let parent = trace::current_span();
+    let lane = current_lane();
+    let running = running();
+    let priority = current_priority();
+    let logs = task_log::current();
+    let slots = Slots::new(jobs);
+    let next = Mutex::new(0);
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|s| {
+        for _ in 0..jobs.clamp(1, branches.len().max(1)) {
+            let sender = sender.clone();
+            let (next, slots, lane, running, logs) = (&next, &slots, &lane, &running, &logs);
+            let (weight, branch) = (&weight, &branch);
+            s.spawn(move || loop {
+                // Slots are taken in branch order, so a heavy branch isn't
+                // passed over by lighter ones after it
+                let (slot, index, held) = {
+                    let mut next = next.lock().unwrap_or_else(PoisonError::into_inner);
+                    let slot = *next;
+                    let Some(&index) = branches.get(slot) else {
+                        break;
+                    };
+                    *next += 1;
+                    (slot, index, slots.take(weight(index)))
+                };
+                let started = Instant::now();
+                let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                    trace::with_parent(parent, || {
+                        with_lane(lane.clone(), || {
+                            with_running(running.clone(), || {
+                                with_priority(Some(priority), || {
+                                    task_log::with_logs(logs.clone(), || branch(index))
+                                })
+                            })
+                        })
+                    })
+                }))
+                .unwrap_or_else(|_| Err(anyhow::anyhow!("Parallel step panicked")));
+                drop(held);
+                let _ = sender.send((slot, (result, started.elapsed())));
+            });
+        }
+    });
+    drop(sender);
+
+    // Each result lands in its branch's slot, whatever order they finished in
+    let mut results: Vec<Option<BranchResult>> = branches.iter().map(|_| None).collect();
+    for (slot, result) in receiver {
+        results[slot] = Some(result);
+    }
+    results
+        .into_iter()
+        .map(|result| {
+            result.unwrap_or_else(|| {
+                (
+                    Err(anyhow::anyhow!("Parallel step panicked")),
+                    Duration::ZERO,
+                )
+            })
+        })
+        .collect()
+}
+
+thread_local! {
+    /// The parallel lane running on this thread, named before its output
+    static LANE: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Name of the lane the current thread is running, if any
+fn current_lane() -> Option<String> {
+    LANE.with(|lane| lane.borrow().clone())
+}
+
+/// Run `f` as part of `lane`, restoring the previous lane afterwards
+fn with_lane<T>(lane: Option<String>, f: impl FnOnce() -> T) -> T {
+    let previous = LANE.with(|current| current.replace(lane));
+    let result = f();
+    LANE.with(|current| *current.borrow_mut() = previous);
+    result
+}
+
+thread_local! {
+    /// Scheduling priority of the commands this thread starts
+    static PRIORITY: Cell<Priority> = const { Cell::new(Priority::Normal) };
+}
+
+/// Priority of the commands the current thread starts
+fn current_priority() -> Priority {
+    PRIORITY.with(Cell::get)
+}
+
+/// Run `f` at `priority` (or the current one, if `None`), restoring the
+/// previous priority afterwards
+fn with_priority<T>(priority: Option<Priority>, f: impl FnOnce() -> T) -> T {
+    let Some(priority) = priority else {
+        return f();
+    };
+    let previous = PRIORITY.with(|current| current.replace(priority));
+    let result = f();
+    PRIORITY.with(|current| current.set(previous));
+    result
+}
+
+thread_local! {
+    /// The task and step running on this thread, for `echo_format`, and
+    /// the tasks that led to it
+    static RUNNING: RefCell<Running> = RefCell::new(Running::default());
+}
+
+/// The task and step a thread is running
+#[derive(Debug, Clone, Default)]
+struct Running {
+    /// Name of the task
+    task: String,
+    /// Position of the step in the task: `2`, or `2.1` for the first
+    /// branch of a parallel block
+    step: Option<String>,
+    /// The tasks running, outermost first and ending with this one, by
+    /// config and name
+    tasks: Vec<TaskKey>,
+}
+
+/// A task by the config it's from and its name
+type TaskKey = (PathBuf, String);
+
+/// The key of task `name` from `config`
+fn task_key(config: &Config, name: &str) -> TaskKey {
+    let path = config.path().unwrap_or(Path::new("")).to_path_buf();
+    (path, name.to_string())
+}
+
+/// The task and step the current thread is running
+fn running() -> Running {
+    RUNNING.with(|running| running.borrow().clone())
+}
+
+/// Run `f` as `running`, restoring the previous task and step afterwards
+fn with_running<T>(running: Running, f: impl FnOnce() -> T) -> T {
+    let previous = RUNNING.with(|current| current.replace(running));
+    let result = f();
+    RUNNING.with(|current| *current.borrow_mut() = previous);
+    result
+}
+
+/// Run `f` as task `name` from `config`, before any of its steps
+fn with_task<T>(config: &Config, name: &str, f: impl FnOnce() -> T) -> T {
+    let mut tasks = running().tasks;
+    tasks.push(task_key(config, name));
+    let task = Running {
+        task: name.to_string(),
+        step: None,
+        tasks,
+    };
+    with_running(task, f)
+}
+
+/// Fail if task `name` from `config` is already running on this thread,
+/// naming the tasks that led back to it
+fn check_cycle(config: &Config, name: &str) -> Result<()> {
+    let key = task_key(config, name);
+    let tasks = running().tasks;
+    let Some(start) = tasks.iter().position(|task| *task == key) else {
+        return Ok(());
+    };
+    let mut cycle: Vec<&str> = tasks[start..]
+        .iter()
+        .map(|(_, name)| name.as_str())
+        .collect();
+    cycle.push(name);
+    anyhow::bail!("Task cycle {}", cycle.join(" -> "))
+}
+
+/// Run `f` as step `number` of the running task (a branch, within a step)
+fn with_step<T>(number: usize, f: impl FnOnce() -> T) -> T {
+    let Running { task, step, tasks } = running();
+    let step = match step {
+        Some(outer) => format!("{}.{}", outer, number),
+        None => number.to_string(),
+    };
+    with_running(
+        Running {
+            task,
+            step: Some(step),
+            tasks,
+        },
+        f,
+    )
+}
+
+/// Combine the errors of a parallel block into one
+fn parallel_result(errors: Vec<anyhow::Error>) -> Result<()> {
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        // Combine all errors into one message
+        let error_messages: Vec<String> = errors.iter().map(|e| format!("  - {:#}", e)).collect();
+        anyhow::bail!(
+            "Parallel execution failed with {} error(s):\n{}",
+            error_messages.len(),
+            error_messages.join("\n")
+        )
+    }
+}
+
+/// Execute a step definition
+fn execute_step_def(
+    step_def: &StepDef,
+    default_dir: &Path,
+    default_env: &HashMap<String, String>,
+    config: &Config,
+    args: &TaskArgs,
+    opts: &RunOptions,
+) -> Result<()> {
+    trace::span(
+        opts.trace.as_ref(),
+        &plan::describe(step_def),
+        "step",
+        || run_step_def(step_def, default_dir, default_env, config, args, opts),
+    )
+    .map(|_| ())
+}
+
+/// Run a step with `export_exit`, then set the variable it names to its
+/// command's exit code in `env`, for the steps after it. Overrides still win.
+fn execute_exporting_step(
+    step_def: &StepDef,
+    default_dir: &Path,
+    env: &mut HashMap<String, String>,
+    config: &Config,
+    args: &TaskArgs,
+    opts: &RunOptions,
+) -> Result<()> {
+    let name = step_def.export_exit.as_deref().unwrap_or_default();
+    if step_def.cmd.is_none() || step_def.task.is_some() {
+        anyhow::bail!("Step 'export_exit' only applies to a step with a 'cmd'");
+    }
+    let code = trace::span(
+        opts.trace.as_ref(),
+        &plan::describe(step_def),
+        "step",
+        || run_step_def(step_def, default_dir, env, config, args, opts),
+    )?;
+    if opts.env.contains_key(name) {
+        return Ok(());
+    }
+    let value = match code {
+        Some(code) => code.to_string(),
+        // Stands in for the code, so later steps still interpolate
+        None => {
+            opts.ui
+                .status(format!("  would set {} to its exit code", name));
+            "<exit code>".to_string()
+        }
+    };
+    env.insert(name.to_string(), value);
+    Ok(())
+}
+
+/// Run a step definition's action, returning its command's exit code if it
+/// ran one
+fn run_step_def(
+    step_def: &StepDef,
+    default_dir: &Path,
+    default_env: &HashMap<String, String>,
+    config: &Config,
+    args: &TaskArgs,
+    opts: &RunOptions,
+) -> Result<Option<i32>> {
+    inject_chaos(opts)?;
+    let work_dir = step_work_dir(step_def, default_dir, opts)?;
+    for (field, set) in [
+        ("output_filter", step_def.output_filter.is_some()),
+        ("echo", step_def.echo.is_some()),
+        ("expect_exit", step_def.expect_exit.is_some()),
+        ("export_exit", step_def.export_exit.is_some()),
+    ] {
+        if set && step_def.cmd.is_none() {
+            anyhow::bail!("Step '{}' only applies to a step with a 'cmd'", field);
+        }
+    }
+
+    // If step delegates to a task
+    if let Some(task_name) = &step_def.task {
+        let nested_dir = step_def.dir.is_some().then_some(work_dir.as_path());
+        let root = opts.project_root()?;
+        return run_task_ref(
+            task_name,
+            step_def.invocation(),
+            nested_dir,
+            &root,
+            config,
+            opts,
+        )
+        .map(|()| None);
+    }
+    if step_def.args.is_some() {
+        anyhow::bail!("Step 'args' only apply to a step that runs a 'task'");
+    }
+    let env = step_env(step_def, default_env, opts);
+
+    // Execute command
+    if let Some(cmd) = &step_def.cmd {
+        let echo = Echo::new(config, step_def.echo).weight(step_def.weight);
+        return with_priority(step_def.priority, || {
+            execute_command(
+                cmd,
+                &work_dir,
+                &env,
+                args,
+                config,
+                step_def.output_filter,
+                step_def.expect_exit.as_ref(),
+                echo,
+                opts,
+            )
+        });
+    }
+
+    // Builtin file operations
+    if let Some(spec) = &step_def.copy {
+        opts.ui
+            .command_echo(format!("copy {} -> {}", spec.from, spec.to));
+        let count = builtins::copy(spec, &work_dir, opts.dry_run)?;
+        let action = if opts.dry_run { "would copy" } else { "copied" };
+        opts.ui.status(format!("  {} {} file(s)", action, count));
+        return Ok(None);
+    }
+
+    if let Some(spec) = &step_def.remove {
+        opts.ui.command_echo(format!("remove {}", spec.path()));
+        let project_root = opts.project_root()?;
+        let count = builtins::remove(spec, &work_dir, &project_root, opts.dry_run)?;
+        let action = if opts.dry_run {
+            "would remove"
+        } else {
+            "removed"
+        };
+        opts.ui.status(format!("  {} {} file(s)", action, count));
+        return Ok(None);
+    }
+
+    if let Some(path) = &step_def.mkdir {
+        opts.ui.command_echo(format!("mkdir {}", path));
+        if builtins::mkdir(path, &work_dir, opts.dry_run)? {
+            let action = if opts.dry_run {
+                "would create"
+            } else {
+                "created"
+            };
+            opts.ui.status(format!("  {} directory", action));
+        } else {
+            opts.ui.status("  directory already exists");
+        }
+        return Ok(None);
+    }
+
+    if let Some(spec) = &step_def.download {
+        opts.ui
+            .command_echo(format!("download {} -> {}", spec.url, spec.to));
+        match builtins::download(spec, &work_dir, opts.dry_run)? {
+            builtins::DownloadOutcome::Downloaded(bytes) => {
+                opts.ui.status(format!("  downloaded {} bytes", bytes))
+            }
+            builtins::DownloadOutcome::UpToDate => {
+                opts.ui.status("  already up to date (checksum matches)")
+            }
+            builtins::DownloadOutcome::WouldDownload => opts.ui.status("  would download"),
+        }
+        return Ok(None);
+    }
+
+    if let Some(spec) = &step_def.render {
+        let source = spec.template.as_deref().unwrap_or("inline content");
+        opts.ui
+            .command_echo(format!("render {} -> {}", source, spec.to));
+        let scope = Scope::new(&work_dir, &env)
+            .with_args(&args.values)
+            .with_foreach(&args.foreach);
+        builtins::render(spec, &work_dir, &scope, opts.dry_run)?;
+        let action = if opts.dry_run {
+            "would render"
+        } else {
+            "rendered"
+        };
+        opts.ui.status(format!("  {} {}", action, spec.to));
+        return Ok(None);
+    }
+
+    anyhow::bail!(
+        "Step has no cmd, task, or builtin (copy, remove, mkdir, download, render) defined"
+    )
+}
+
+/// Fail or hang instead of running the current step, if `--chaos` names it
+fn inject_chaos(opts: &RunOptions) -> Result<()> {
+    let Running { task, step, .. } = running();
+    match step {
+        Some(step) => opts.chaos.at_step(&task, &step, &opts.ui),
+        None => Ok(()),
+    }
+}
+
+/// Run an env step: add the variables from its `env_file`, then its `env`
+/// (interpolated now), to `env` for the steps after it. Overrides still win.
+fn execute_env_step(
+    step_def: &StepDef,
+    default_dir: &Path,
+    env: &mut HashMap<String, String>,
+    config: &Config,
+    args: &TaskArgs,
+    opts: &RunOptions,
+) -> Result<()> {
+    if step_def.has_action() {
+        anyhow::bail!(
+            "Step 'env_file' can't be combined with cmd, task or a builtin; give it a step of its own"
+        );
+    }
+    inject_chaos(opts)?;
+    let work_dir = step_work_dir(step_def, default_dir, opts)?;
+    opts.ui.command_echo(plan::describe(step_def));
+
+    let mut vars = Vec::new();
+    if let Some(file) = &step_def.env_file {
+        let path = work_dir.join(file);
+        if opts.dry_run && !path.exists() {
+            opts.ui
+                .status(format!("  would load {} (not there yet)", file));
+        } else {
+            vars.extend(builtins::read_env_file(&path)?);
+        }
+    }
+    if let Some(values) = &step_def.env {
+        let mut values: Vec<(&String, &String)> = values.iter().collect();
+        values.sort();
+        let scope = Scope::new(&work_dir, env)
+            .with_args(&args.values)
+            .with_foreach(&args.foreach);
+        for (name, value) in values {
+            let value = match scope.render(value) {
+                Ok(value) => value,
+                // What it needs may come from a step the dry run skipped
+                Err(_) if opts.dry_run => value.clone(),
+                Err(e) => {
+                    return Err(e)
+                        .with_context(|| format!("Failed to interpolate env {}: {}", name, value))
+                }
+            };
+            vars.push((name.clone(), value));
+        }
+    }
+
+    let hidden = HiddenVars::for_config(config);
+    for (name, value) in vars {
+        if opts.env.contains_key(&name) {
+            continue;
+        }
+        let shown = if hidden.hides(&name) { "***" } else { &value };
+        opts.ui.status(format!("  {}={}", name, shown));
+        env.insert(name, value);
+    }
+    Ok(())
+}
+
+/// Environment of a step: the task's, with the step's `env` and then the
+/// overrides applied
+fn step_env(
+    step_def: &StepDef,
+    default_env: &HashMap<String, String>,
+    opts: &RunOptions,
+) -> HashMap<String, String> {
+    let mut env = default_env.clone();
+    if let Some(step_env) = &step_def.env {
+        env.extend(step_env.clone());
+        env.extend(opts.env.clone());
+    }
+    env
+}
+
+/// Working directory of a step: its `dir` relative to the project root, or the task's
+fn step_work_dir(step_def: &StepDef, default_dir: &Path, opts: &RunOptions) -> Result<PathBuf> {
+    Ok(match &step_def.dir {
+        Some(dir) => config::join_dir(&opts.project_root()?, dir),
+        None => default_dir.to_path_buf(),
+    })
+}
+
+/// Execute a command, passing its stdout through `filter` unless `--verbose`
+/// asks for the raw output, and failing unless it exits with an `expect`ed
+/// code (0 by default). Returns the exit code; none in a dry run.
+#[allow(clippy::too_many_arguments)]
+fn execute_command(
+    cmd: &Cmd,
+    work_dir: &Path,
+    env: &HashMap<String, String>,
+    args: &TaskArgs,
+    config: &Config,
+    filter: Option<OutputFilter>,
+    expect: Option<&ExpectExit>,
+    echo: Echo,
+    opts: &RunOptions,
+) -> Result<Option<i32>> {
+    let (line, mut command) = prepare_command(cmd, work_dir, env, args, config)?;
+    command.envs(opts.nesting.child_env(&running().task));
+    let lane = current_lane();
+    let echo = echo.render(&line, work_dir, env, opts);
+
+    match (&echo, &lane) {
+        (Some(echo), Some(lane)) => opts.ui.lane_echo(lane, echo),
+        (Some(echo), None) => opts.ui.echo(echo),
+        (None, _) => {}
+    }
+
+    if opts.dry_run {
+        return Ok(None);
+    }
+
+    let tee = task_log::Tee::current(env);
+    if let Some(echo) = &echo {
+        tee.line(lane.as_deref(), echo);
+    }
+    let limit = output_buffer::limit(config);
+    let filter = filter
+        .filter(|_| !opts.verbose)
+        .map(|filter| output_filter::build(filter, limit));
+    let piped = lane.is_some() || filter.is_some() || tee.is_active() || opts.ui.line_buffered();
+    termination::check()?;
+    let status = if piped {
+        run_piped(&mut command, lane.as_deref(), filter, &tee, &opts.ui)
+    } else {
+        command.spawn().and_then(|mut child| {
+            let _tracked = termination::track(child.id());
+            child.wait()
+        })
+    }
+    .map_err(|e| {
+        let hints = match e.kind() {
+            io::ErrorKind::NotFound | io::ErrorKind::PermissionDenied => {
+                project_script_hints(&line, &command, opts)
+            }
+            _ => Vec::new(),
+        };
+        start_error(e, &command, cmd.is_direct(), &line, &hints)
+    })?;
+    exit_result(status, line, &command, expect)
+        .map_err(|e| with_script_hints(e, &command, opts))
+        .map(Some)
+}
+
+/// `error` from a command the shell couldn't run (126) or find (127), with
+/// hints about the script it starts with
+fn with_script_hints(
+    mut error: anyhow::Error,
+    command: &Command,
+    opts: &RunOptions,
+) -> anyhow::Error {
+    if let Some(failed) = error.downcast_mut::<CommandFailed>() {
+        if script_hints::is_spawn_code(failed.code) {
+            failed.hints = project_script_hints(&failed.line, command, opts);
+        }
+    }
+    error
+}
+
+/// What might be wrong with the project script `line` starts with, going
+/// by the directory and PATH `command` runs with
+fn project_script_hints(line: &str, command: &Command, opts: &RunOptions) -> Vec<String> {
+    let (Ok(root), Some(dir)) = (opts.project_root(), command.get_current_dir()) else {
+        return Vec::new();
+    };
+    let path = command
+        .get_envs()
+        .find(|&(name, _)| name == "PATH")
+        .and_then(|(_, value)| value);
+    script_hints::hints(line, dir, &root, path)
+}
+
+/// How a command is echoed before it runs: by the config's `echo_format`,
+/// or not at all when its task or step sets `echo: false`
+#[derive(Debug, Clone, Copy)]
+struct Echo<'a> {
+    format: Option<&'a str>,
+    /// The step's `weight`, noted in a dry run's echo
+    weight: Option<NonZeroU32>,
+}
+
+impl<'a> Echo<'a> {
+    /// The echo for a command in `config` whose task or step sets `echo`
+    fn new(config: &'a Config, echo: Option<bool>) -> Self {
+        let format = config
+            .echo_format
+            .as_deref()
+            .unwrap_or(config::DEFAULT_ECHO_FORMAT);
+        Echo {
+            format: echo.unwrap_or(true).then_some(format),
+            weight: None,
+        }
+    }
+
+    /// The echo of a step with `weight`
+    fn weight(self, weight: Option<NonZeroU32>) -> Self {
+        Echo { weight, ..self }
+    }
+
+    /// The echo of the interpolated `line` run in `work_dir` by the task and
+    /// step running on this thread, with secret values masked. A dry run
+    /// notes the priority and weight the command would run with.
+    fn render(
+        self,
+        line: &str,
+        work_dir: &Path,
+        env: &HashMap<String, String>,
+        opts: &RunOptions,
+    ) -> Option<String> {
+        let format = self.format?;
+        let dir = match opts.project_root() {
+            Ok(root) => plan::relative_dir(work_dir, &root),
+            Err(_) => work_dir.display().to_string(),
+        };
+        let running = running();
+        let echo = config::render_echo(
+            format,
+            &[
+                ("cmd", line),
+                ("dir", &dir),
+                ("task", &running.task),
+                ("step", running.step.as_deref().unwrap_or_default()),
+            ],
+        );
+        let mut echo = secrets::redact(&echo, env);
+        if opts.dry_run {
+            if let Some(hints) = plan::hints(current_priority(), self.weight) {
+                echo = format!("{} ({})", echo, hints);
+            }
+        }
+        Some(echo)
+    }
+}
+
+/// Run a command with its output read by rnr: each line prefixed by the
+/// lane's name in a parallel block, stdout passed through `filter`, and
+/// everything copied to the task logs `tee` writes to. With `--quiet`, a
+/// logged command's output goes only to the logs. Only whole lines are
+/// passed on; a last line without a line break goes once the command ends.
+fn run_piped(
+    command: &mut Command,
+    lane: Option<&str>,
+    filter: Option<Box<dyn Filter>>,
+    tee: &task_log::Tee,
+    ui: &Ui,
+) -> io::Result<ExitStatus> {
+    command.stdout(Stdio::piped());
+    if lane.is_some() || tee.is_active() || ui.line_buffered() {
+        command.stderr(Stdio::piped());
+    }
+    let mut child = command.spawn()?;
+    let _tracked = termination::track(child.id());
+    let stdout = child.stdout.take();
+    let stderr = child.stderr.take();
+    let console = !(tee.is_active() && ui.quiet());
+    let emit = |line: &str, stderr: bool| {
+        tee.line(lane, line);
+        if !console {
+            return;
+        }
+        match lane {
+            Some(lane) => ui.lane_output(lane, line, stderr),
+            None if stderr => ui.print_err(format!("{}\n", line)),
+            None => ui.line(line),
+        }
+    };
+    let emit = &emit;
+    std::thread::scope(|s| {
+        let mut filter = filter;
+        let stdout = stdout.map(|stdout| {
+            s.spawn(move || {
+                for_each_line(stdout, |line| match &mut filter {
+                    Some(filter) => filter.line(line).iter().for_each(|l| emit(l, false)),
+                    None => emit(line, false),
+                });
+                filter
+            })
+        });
+        if let Some(stderr) = stderr {
+            s.spawn(|| for_each_line(stderr, |line| emit(line, true)));
+        }
+        let status = child.wait()?;
+        let filter = stdout.and_then(|reader| reader.join().ok().flatten());
+        for line in filter
+            .map(|mut f| f.finish(status.success()))
+            .unwrap_or_default()
+        {
+            emit(&line, false);
+        }
+        Ok(status)
+    })
+}
+
+/// Interpolate a command and build the process that runs it, returning the
+/// line to echo alongside it
+fn prepare_command(
+    cmd: &Cmd,
+    work_dir: &Path,
+    env: &HashMap<String, String>,
+    args: &TaskArgs,
+    config: &Config,
+) -> Result<(String, Command)> {
+    let (line, mut command) = match cmd {
+        Cmd::Shell(cmd) => {
+            let line = shell_line(cmd, work_dir, env, args, config)?;
+            let command = shell_command(&line, work_dir);
+            (line, command)
+        }
+        Cmd::Argv(argv) => {
+            let argv = command_argv(argv, work_dir, env, args, config)?;
+            let mut command = Command::new(&argv[0]);
+            command.args(&argv[1..]).current_dir(work_dir);
+            (Cmd::Argv(argv).to_string(), command)
+        }
+    };
+
+    // Tasks that run rnr themselves shouldn't inherit this wrapper's project
+    command.env_remove(config::WRAPPER_DIR_ENV);
+    command.envs(env);
+    command.envs(args.env_vars());
+    let mut command = with_os_priority(command, current_priority());
+    termination::isolate(&mut command);
+    Ok((line, command))
+}
+
+/// `command` set to run at `priority`: niced with `nice` on Unix, or in the
+/// matching priority class on Windows. Raising priority on Unix needs root,
+/// so `high` leaves the command as it is there.
+fn with_os_priority(command: Command, priority: Priority) -> Command {
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+
+        let mut command = command;
+        if let Some(class) = priority_class(priority) {
+            command.creation_flags(class);
+        }
+        command
+    }
+    #[cfg(not(windows))]
+    {
+        match nice_level(priority) {
+            Some(level) => niced(&command, level),
+            None => command,
+        }
+    }
+}
+
+/// The `nice` increment for a command at `priority`, if it needs one
+#[cfg_attr(windows, allow(dead_code))]
+fn nice_level(priority: Priority) -> Option<i32> {
+    match priority {
+        Priority::Low => Some(10),
+        Priority::Normal | Priority::High => None,
+    }
+}
+
+/// The Windows priority class flag for a command at `priority`, if it needs one
+#[cfg_attr(not(windows), allow(dead_code))]
+fn priority_class(priority: Priority) -> Option<u32> {
+    const BELOW_NORMAL_PRIORITY_CLASS: u32 = 0x0000_4000;
+    const ABOVE_NORMAL_PRIORITY_CLASS: u32 = 0x0000_8000;
+    match priority {
+        Priority::Low => Some(BELOW_NORMAL_PRIORITY_CLASS),
+        Priority::Normal => None,
+        Priority::High => Some(ABOVE_NORMAL_PRIORITY_CLASS),
+    }
+}
+
+/// `command` run through `nice -n level`, in the same directory and with the
+/// same environment
+#[cfg_attr(windows, allow(dead_code))]
+fn niced(command: &Command, level: i32) -> Command {
+    let mut niced = Command::new("nice");
+    niced
+        .arg("-n")
+        .arg(level.to_string())
+        .arg(command.get_program())
+        .args(command.get_args());
+    if let Some(dir) = command.get_current_dir() {
+        niced.current_dir(dir);
+    }
+    for (name, value) in command.get_envs() {
+        match value {
+            Some(value) => niced.env(name, value),
+            None => niced.env_remove(name),
+        };
+    }
+    niced
+}
+
+/// The platform shell running `cmd`
+fn shell_command(cmd: &str, work_dir: &Path) -> Command {
+    let mut command = if cfg!(target_os = "windows") {
+        let mut c = Command::new("cmd");
+        c.args(["/C", cmd]);
+        c
+    } else {
+        let mut c = Command::new("sh");
+        c.args(["-c", cmd]);
+        c
+    };
+    command.current_dir(work_dir);
+    command
+}
+
+/// Explain why a command couldn't start, naming the program when rnr ran it
+/// directly, followed by `hints` about the script it starts with
+fn start_error(
+    error: io::Error,
+    command: &Command,
+    direct: bool,
+    line: &str,
+    hints: &[String],
+) -> anyhow::Error {
+    let hints = script_hints::render(hints);
+    if direct && error.kind() == io::ErrorKind::NotFound {
+        let program = command.get_program().to_string_lossy();
+        let place = if program.contains(['/', '\\']) {
+            "at that path"
+        } else {
+            "on PATH"
+        };
+        return anyhow::anyhow!("Program '{}' not found {}{}", program, place, hints);
+    }
+    anyhow::Error::new(error).context(format!("Failed to execute command: {}{}", line, hints))
+}
+
+/// Turn a command's exit status into its exit code, or an error unless it's
+/// one of the `expect`ed codes (just 0 by default). A command killed by a
+/// signal always fails.
+fn exit_result(
+    status: ExitStatus,
+    line: String,
+    command: &Command,
+    expect: Option<&ExpectExit>,
+) -> Result<i32> {
+    let allowed = match (status.code(), expect) {
+        (Some(code), Some(expect)) => expect.allows(code),
+        _ => status.success(),
+    };
+    if !allowed {
+        return Err(CommandFailed {
+            line,
+            dir: command.get_current_dir().unwrap_or(Path::new(".")).into(),
+            code: status.code().unwrap_or(1),
+            expected: expect.cloned(),
+            hints: Vec::new(),
+        }
+        .into());
+    }
+    Ok(status.code().unwrap_or_default())
+}
+
+/// A command that ran but exited with a code it wasn't expected to
+#[derive(Debug, thiserror::Error)]
+#[error(
+    "Command failed with exit code {code}{}{}",
+    expected_note(.expected.as_ref()),
+    script_hints::render(.hints)
+)]
+struct CommandFailed {
+    /// The command line as echoed
+    line: String,
+    /// Where it ran
+    dir: PathBuf,
+    /// Exit code (1 when killed by a signal)
+    code: i32,
+    /// The step's or task's `expect_exit`, if it set one
+    expected: Option<ExpectExit>,
+    /// What might be wrong with the script it ran, when it couldn't be run
+    hints: Vec<String>,
+}
+
+/// ` (expected exit codes 0 or 1)` for a command with `expect_exit`
+fn expected_note(expected: Option<&ExpectExit>) -> String {
+    expected
+        .map(|expected| format!(" (expected {})", expected))
+        .unwrap_or_default()
+}
+
+/// The command as it will be echoed, interpolated and with the task's extra arguments
+pub(crate) fn command_line(
+    cmd: &Cmd,
+    work_dir: &Path,
+    env: &HashMap<String, String>,
+    args: &TaskArgs,
+    config: &Config,
+) -> Result<String> {
+    match cmd {
+        Cmd::Shell(cmd) => shell_line(cmd, work_dir, env, args, config),
+        Cmd::Argv(argv) => {
+            Ok(Cmd::Argv(command_argv(argv, work_dir, env, args, config)?).to_string())
+        }
+    }
+}
+
+/// Interpolate a shell command and append the task's extra arguments, quoted
+fn shell_line(
+    cmd: &str,
+    work_dir: &Path,
+    env: &HashMap<String, String>,
+    args: &TaskArgs,
+    config: &Config,
+) -> Result<String> {
+    let mut line = Scope::new(work_dir, env)
+        .with_args(&args.values)
+        .with_foreach(&args.foreach)
+        .with_bins(bins::paths(config))
+        .render(cmd)
+        .with_context(|| format!("Failed to interpolate command: {}", cmd))?;
+    for arg in &args.extra {
+        line.push(' ');
+        line.push_str(&quote_arg(arg));
+    }
+    Ok(line)
+}
+
+/// Interpolate each element of an argument array and append the task's extra arguments
+fn command_argv(
+    argv: &[String],
+    work_dir: &Path,
+    env: &HashMap<String, String>,
+    args: &TaskArgs,
+    config: &Config,
+) -> Result<Vec<String>> {
+    if argv.is_empty() {
+        anyhow::bail!("Command array is empty; it needs at least the program to run");
+    }
+    let scope = Scope::new(work_dir, env)
+        .with_args(&args.values)
+        .with_foreach(&args.foreach)
+        .with_bins(bins::paths(config));
+    let mut rendered = argv
+        .iter()
+        .map(|arg| {
+            scope
+                .render(arg)
+                .with_context(|| format!("Failed to interpolate command argument: {}", arg))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    rendered.extend(args.extra.iter().cloned());
+    Ok(rendered)
+}
+
+/// Quote an argument for the platform shell when it contains special characters
+pub(crate) fn quote_arg(arg: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c);
+    if !arg.is_empty() && arg.chars().all(safe) {
+        return arg.to_string();
+    }
+
+    if cfg!(target_os = "windows") {
+        format!("\"{}\"", arg.replace('"', "\\\""))
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+#[cfg(feature = "tokio")]
+mod async_exec {
+    //! Parallel blocks on a tokio runtime
+    //!
+    //! Command branches run as `tokio::process` children driven from the
+    //! calling thread, with a semaphore enforcing `--jobs`, instead of one
+    //! blocked OS thread each. Branches that delegate to a task or use a
+    //! builtin still get a scoped thread. Ctrl-C kills the running children
+    //! and fails the block.
+
+    use std::thread;
+    use tokio::sync::Semaphore;
+    use tokio::task::JoinSet;
+
+    use super::*;
+
+    /// Error for branches cut short by Ctrl-C
+    const INTERRUPTED: &str = "Interrupted";
+
+    pub(super) fn run_parallel_branches(
+        steps: &[StepDef],
+        branches: &[usize],
+        default_dir: &Path,
+        default_env: &HashMap<String, String>,
+        config: &Config,
+        args: &TaskArgs,
+        opts: &RunOptions,
+    ) -> Vec<BranchResult> {
+        let runtime = match tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+        {
+            Ok(runtime) => runtime,
+            Err(e) => {
+                return branches
+                    .iter()
+                    .map(|_| {
+                        let error = anyhow::anyhow!("Failed to start async runtime: {}", e);
+                        (Err(error), Duration::ZERO)
+                    })
+                    .collect()
+            }
+        };
+        let limit = Arc::new(Semaphore::new(opts.parallel_slots(branches.len())));
+        let mut results: Vec<Option<BranchResult>> = branches.iter().map(|_| None).collect();
+        let running = running();
+        let priority = current_priority();
+
+        thread::scope(|s| {
+            let mut commands = JoinSet::new();
+            let mut threads = Vec::new();
+            for (slot, &branch) in branches.iter().enumerate() {
+                let step_def = &steps[branch];
+                let permits = opts.branch_slots(step_def.weight) as u32;
+                if step_def.task.is_some() || step_def.cmd.is_none() {
+                    let limit = Arc::clone(&limit);
+                    let handle = runtime.handle().clone();
+                    let running = running.clone();
+                    threads.push((
+                        slot,
+                        s.spawn(move || {
+                            let _permit = handle.block_on(limit.acquire_many(permits));
+                            let started = Instant::now();
+                            let result = with_running(running, || {
+                                with_priority(Some(priority), || {
+                                    with_step(branch + 1, || {
+                                        execute_step_def(
+                                            step_def,
+                                            default_dir,
+                                            default_env,
+                                            config,
+                                            args,
+                                            opts,
+                                        )
+                                    })
+                                })
+                            });
+                            (result, started.elapsed())
+                        }),
+                    ));
+                    continue;
+                }
+
+                let prepared = with_step(branch + 1, || {
+                    prepare(step_def, default_dir, default_env, config, args, opts)
+                });
+                match prepared {
+                    Ok((echo, line, command)) => {
+                        let limit = Arc::clone(&limit);
+                        let ui = opts.ui.clone();
+                        let dry_run = opts.dry_run;
+                        let direct = step_def.cmd.as_ref().is_some_and(Cmd::is_direct);
+                        let expect = step_def.expect_exit.clone();
+                        commands.spawn_on(
+                            async move {
+                                let _permit = limit.acquire_many_owned(permits).await;
+                                let started = Instant::now();
+                                let result =
+                                    run(echo, line, command, direct, expect, &ui, dry_run).await;
+                                (slot, (result, started.elapsed()))
+                            },
+                            runtime.handle(),
+                        );
+                    }
+                    Err(e) => results[slot] = Some((Err(e), Duration::ZERO)),
+                }
+            }
+
+            runtime.block_on(collect(&mut commands, &mut results));
+
+            for (slot, handle) in threads {
+                results[slot] = handle.join().ok();
+            }
+        });
+
+        results
+            .into_iter()
+            .map(|result| {
+                result.unwrap_or_else(|| {
+                    (
+                        Err(anyhow::anyhow!("Parallel step panicked")),
+                        Duration::ZERO,
+                    )
+                })
+            })
+            .collect()
+    }
+
+    /// Interpolate a command branch and build its child process, returning
+    /// its echo (if it has one) and line alongside it
+    fn prepare(
+        step_def: &StepDef,
+        default_dir: &Path,
+        default_env: &HashMap<String, String>,
+        config: &Config,
+        args: &TaskArgs,
+        opts: &RunOptions,
+    ) -> Result<(Option<String>, String, tokio::process::Command)> {
+        let work_dir = step_work_dir(step_def, default_dir, opts)?;
+        let cmd = step_def.cmd.as_ref().context("Step has no cmd")?;
+        if step_def.args.is_some() {
+            anyhow::bail!("Step 'args' only apply to a step that runs a 'task'");
+        }
+        let env = step_env(step_def, default_env, opts);
+        let (echo, line, command) = with_priority(step_def.priority, || {
+            let (line, command) = prepare_command(cmd, &work_dir, &env, args, config)?;
+            let echo = Echo::new(config, step_def.echo)
+                .weight(step_def.weight)
+                .render(&line, &work_dir, &env, opts);
+            anyhow::Ok((echo, line, command))
+        })?;
+        let mut command = tokio::process::Command::from(command);
+        command.envs(opts.nesting.child_env(&running().task));
+        command.kill_on_drop(true);
+        Ok((echo, line, command))
+    }
+
+    /// Echo and run one command branch
+    async fn run(
+        echo: Option<String>,
+        line: String,
+        mut command: tokio::process::Command,
+        direct: bool,
+        expect: Option<ExpectExit>,
+        ui: &Ui,
+        dry_run: bool,
+    ) -> Result<()> {
+        if let Some(echo) = echo {
+            ui.echo(echo);
+        }
+        if dry_run {
+            return Ok(());
+        }
+        termination::check()?;
+        let status = match command.spawn() {
+            Ok(mut child) => {
+                let _tracked = child.id().map(termination::track);
+                child.wait().await
+            }
+            Err(e) => Err(e),
+        }
+        .map_err(|e| start_error(e, command.as_std(), direct, &line, &[]))?;
+        exit_result(status, line, command.as_std(), expect.as_ref()).map(|_| ())
+    }
+
+    /// Wait for every command branch, or kill them all on Ctrl-C
+    async fn collect(
+        commands: &mut JoinSet<(usize, BranchResult)>,
+        results: &mut [Option<BranchResult>],
+    ) {
+        loop {
+            tokio::select! {
+                next = commands.join_next() => match next {
+                    Some(Ok((slot, result))) => results[slot] = Some(result),
+                    Some(Err(_)) => {}
+                    None => return,
+                },
+                _ = tokio::signal::ctrl_c() => {
+                    // Dropping the tasks drops their children, which kills them
+                    commands.abort_all();
+                    while commands.join_next().await.is_some() {}
+                    for result in results.iter_mut().filter(|r| r.is_none()) {
+                        *result = Some((Err(anyhow::anyhow!(INTERRUPTED)), Duration::ZERO));
+                    }
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(yaml: &str) -> Config {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn test_resolve_exact_name() {
+        let config = config("build: cargo build\nbuild-web: npm run build\n");
+        let opts = RunOptions {
+            fuzzy: true,
+            ..Default::default()
+        };
+        assert_eq!(resolve_task_name(&config, "build", &opts).unwrap(), "build");
+    }
+
+    #[test]
+    fn test_resolve_abbreviation_requires_opt_in() {
+        let config = config("build: cargo build\ntest: cargo test\n");
+        let err = resolve_task_name(&config, "bu", &RunOptions::default()).unwrap_err();
+        assert!(err.to_string().contains("not found"));
+
+        let opts = RunOptions {
+            fuzzy: true,
+            ..Default::default()
+        };
+        assert_eq!(resolve_task_name(&config, "bu", &opts).unwrap(), "build");
+    }
+
+    #[test]
+    fn test_resolve_abbreviation_from_config() {
+        let config = config("allow_abbreviations: true\nbuild: cargo build\n");
+        assert_eq!(
+            resolve_task_name(&config, "b", &RunOptions::default()).unwrap(),
+            "build"
+        );
+    }
+
+    #[test]
+    fn test_resolve_ambiguous_abbreviation() {
+        let config = config("allow_abbreviations: true\nbuild: a\nbuild-web: b\n");
+        let err = resolve_task_name(&config, "bu", &RunOptions::default()).unwrap_err();
+        assert!(err.to_string().contains("Candidates: build, build-web"));
+    }
+
+    #[test]
+    fn test_resolve_did_you_mean() {
+        let config = config("build: cargo build\n");
+        let err = resolve_task_name(&config, "biuld", &RunOptions::default()).unwrap_err();
+        assert!(err.to_string().contains("Did you mean 'build'?"));
+    }
+
+    #[test]
+    fn test_resolve_suggests_subcommands() {
+        let config = config("int: cargo test\nbuild: cargo build\n");
+        let err = resolve_task_name(&config, "intt", &RunOptions::default()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Task 'intt' not found. Did you mean 'int', or the 'init' subcommand?"
+        );
+        let err = resolve_task_name(&config, "upgrde", &RunOptions::default()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Task 'upgrde' not found. Did you mean the 'upgrade' subcommand?"
+        );
+    }
+
+    /// Branches of the first (parallel) step of task `ci`
+    fn parallel_block(config: &Config) -> &[StepDef] {
+        let Some(TaskDef::Full(task)) = config.get_task("ci") else {
+            panic!("ci is not a full task");
+        };
+        match &task.steps.as_ref().unwrap()[0] {
+            Step::Parallel { parallel, .. } => parallel,
+            _ => panic!("first step of ci is not a flat parallel block"),
+        }
+    }
+
+    /// Run every branch of `ci`'s parallel block with `run`
+    fn run_block(
+        yaml: &str,
+        jobs: Option<usize>,
+        run: impl Fn(
+            &[StepDef],
+            &[usize],
+            &Path,
+            &HashMap<String, String>,
+            &Config,
+            &TaskArgs,
+            &RunOptions,
+        ) -> Vec<BranchResult>,
+    ) -> Vec<BranchResult> {
+        let temp = tempfile::tempdir().unwrap();
+        let config = config(yaml);
+        let steps = parallel_block(&config);
+        let branches: Vec<usize> = (0..steps.len()).collect();
+        let opts = RunOptions {
+            project_root: Some(temp.path().to_path_buf()),
+            ui: Ui::capture(false).0,
+            jobs,
+            ..Default::default()
+        };
+        run(
+            steps,
+            &branches,
+            temp.path(),
+            &HashMap::new(),
+            &config,
+            &TaskArgs::default(),
+            &opts,
+        )
+    }
+
+    /// Parallel blocks whose outcome shouldn't depend on how they're run
+    const PARALLEL_FIXTURES: &[&str] = &[
+        "ci:\n  steps:\n    - parallel:\n        - cmd: echo a\n        - cmd: echo b\n",
+        // The slow failure is still reported first
+        "ci:\n  steps:\n    - parallel:\n        - cmd: sleep 0.2; exit 1\n        - cmd: exit 2\n        - cmd: echo ok\n",
+        // Task delegation and builtins alongside commands
+        "helper: exit 4\nci:\n  steps:\n    - parallel:\n        - task: helper\n        - mkdir: out\n        - cmd: exit 5\n        - dir: sub\n",
+        // Interpolation errors fail only their branch
+        "ci:\n  steps:\n    - parallel:\n        - cmd: echo ${args.missing}\n        - cmd: echo fine\n",
+        // Argument arrays, including a program that isn't installed
+        "ci:\n  steps:\n    - parallel:\n        - cmd: [sh, -c, exit 6]\n        - cmd: [rnr-no-such-program]\n",
+    ];
+
+    fn outcomes(results: Vec<BranchResult>) -> Vec<Result<(), String>> {
+        results
+            .into_iter()
+            .map(|(result, _)| result.map_err(|e| format!("{:#}", e)))
+            .collect()
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_threaded_branches_keep_order() {
+        let results = outcomes(run_block(PARALLEL_FIXTURES[1], None, run_threaded_branches));
+        assert_eq!(
+            results,
+            [
+                Err("Command failed with exit code 1".to_string()),
+                Err("Command failed with exit code 2".to_string()),
+                Ok(())
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_threaded_jobs_limit() {
+        let yaml =
+            "ci:\n  steps:\n    - parallel:\n        - cmd: sleep 0.3\n        - cmd: sleep 0.3\n";
+        let started = Instant::now();
+        run_block(yaml, Some(1), run_threaded_branches);
+        assert!(started.elapsed() >= Duration::from_millis(600));
+    }
+
+    #[test]
+    #[cfg(all(feature = "tokio", unix))]
+    fn test_async_matches_threaded() {
+        for yaml in PARALLEL_FIXTURES {
+            let threaded = outcomes(run_block(yaml, None, run_threaded_branches));
+            let limited = outcomes(run_block(yaml, Some(1), run_threaded_branches));
+            let concurrent = outcomes(run_block(yaml, None, async_exec::run_parallel_branches));
+            assert_eq!(threaded, concurrent, "{}", yaml);
+            assert_eq!(limited, concurrent, "{}", yaml);
+
+            let aggregate = |results: &[Result<(), String>]| {
+                let errors = results
+                    .iter()
+                    .filter_map(|r| r.clone().err())
+                    .map(|e| anyhow::anyhow!(e))
+                    .collect();
+                parallel_result(errors).map_err(|e| e.to_string())
+            };
+            assert_eq!(aggregate(&threaded), aggregate(&concurrent));
+        }
+    }
+
+    #[test]
+    #[cfg(all(feature = "tokio", unix))]
+    fn test_async_jobs_limit() {
+        let yaml =
+            "ci:\n  steps:\n    - parallel:\n        - cmd: sleep 0.3\n        - cmd: sleep 0.3\n";
+        let started = Instant::now();
+        let results = run_block(yaml, Some(1), async_exec::run_parallel_branches);
+        assert!(started.elapsed() >= Duration::from_millis(600));
+        assert!(results.iter().all(|(result, _)| result.is_ok()));
+    }
+
+    #[test]
+    fn test_weights_share_jobs_slots() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let weights = [4, 1, 1, 2, 3, 1];
+        let run = |opts: &RunOptions| {
+            let (in_use, peak) = (AtomicUsize::new(0), AtomicUsize::new(0));
+            let alone = AtomicUsize::new(0);
+            let branches: Vec<usize> = (0..weights.len()).collect();
+            let weight = |branch: usize| opts.branch_slots(NonZeroU32::new(weights[branch] as u32));
+            let jobs = opts.parallel_slots(branches.len());
+            let results = run_on_threads(&branches, jobs, weight, |branch| {
+                let slots = weight(branch);
+                let now = in_use.fetch_add(slots, Ordering::SeqCst) + slots;
+                peak.fetch_max(now, Ordering::SeqCst);
+                if weights[branch] == 4 && now == 4 {
+                    alone.fetch_add(1, Ordering::SeqCst);
+                }
+                std::thread::sleep(Duration::from_millis(20));
+                in_use.fetch_sub(slots, Ordering::SeqCst);
+                Ok(())
+            });
+            assert!(results.iter().all(|(result, _)| result.is_ok()));
+            (peak.into_inner(), alone.into_inner())
+        };
+
+        let jobs = RunOptions {
+            jobs: Some(4),
+            ..Default::default()
+        };
+        let (peak, alone) = run(&jobs);
+        assert!(peak <= 4, "{} slots in use at once", peak);
+        assert_eq!(alone, 1);
+
+        // A weight larger than --jobs runs alone rather than never
+        let two = RunOptions {
+            jobs: Some(2),
+            ..Default::default()
+        };
+        assert!(run(&two).0 <= 2);
+
+        // Without --jobs every branch starts at once, whatever its weight
+        assert_eq!(RunOptions::default().branch_slots(NonZeroU32::new(4)), 1);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_threaded_weight_limit() {
+        let yaml = "ci:\n  steps:\n    - parallel:\n        - cmd: sleep 0.3\n          weight: 3\n        - cmd: sleep 0.3\n        - cmd: sleep 0.3\n";
+        let started = Instant::now();
+        run_block(yaml, Some(3), run_threaded_branches);
+        assert!(started.elapsed() >= Duration::from_millis(600));
+    }
+
+    #[test]
+    #[cfg(all(feature = "tokio", unix))]
+    fn test_async_weight_limit() {
+        let yaml = "ci:\n  steps:\n    - parallel:\n        - cmd: sleep 0.3\n          weight: 3\n        - cmd: sleep 0.3\n        - task: nap\nnap: sleep 0.3\n";
+        let started = Instant::now();
+        let results = run_block(yaml, Some(3), async_exec::run_parallel_branches);
+        assert!(started.elapsed() >= Duration::from_millis(600));
+        assert!(results.iter().all(|(result, _)| result.is_ok()));
+    }
+
+    #[test]
+    fn test_priority_mapping() {
+        let dir = std::env::temp_dir();
+        let mut env = HashMap::new();
+        env.insert("GREETING".to_string(), "hi".to_string());
+        let prepare = |priority| {
+            with_priority(priority, || {
+                let cmd = Cmd::Argv(vec!["echo".into(), "hi".into()]);
+                prepare_command(&cmd, &dir, &env, &TaskArgs::default(), &config("{}"))
+                    .unwrap()
+                    .1
+            })
+        };
+        let argv = |command: &Command| {
+            std::iter::once(command.get_program())
+                .chain(command.get_args())
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+        };
+
+        let normal = prepare(None);
+        assert_eq!(argv(&normal), ["echo", "hi"]);
+        #[cfg(unix)]
+        {
+            let low = prepare(Some(Priority::Low));
+            assert_eq!(argv(&low), ["nice", "-n", "10", "echo", "hi"]);
+            assert_eq!(low.get_current_dir(), Some(dir.as_path()));
+            assert_eq!(
+                low.get_envs().collect::<Vec<_>>(),
+                normal.get_envs().collect::<Vec<_>>()
+            );
+            assert_eq!(argv(&prepare(Some(Priority::High))), ["echo", "hi"]);
+            // Steps and delegated tasks keep the priority of what runs them
+            let inherited = with_priority(Some(Priority::Low), || prepare(None));
+            assert_eq!(inherited.get_program(), "nice");
+            let reset = with_priority(Some(Priority::Low), || prepare(Some(Priority::Normal)));
+            assert_eq!(reset.get_program(), "echo");
+        }
+
+        assert_eq!(priority_class(Priority::Low), Some(0x4000));
+        assert_eq!(priority_class(Priority::Normal), None);
+        assert_eq!(priority_class(Priority::High), Some(0x8000));
+        assert_eq!(nice_level(Priority::Low), Some(10));
+        assert_eq!(nice_level(Priority::High), None);
+    }
+
+    #[test]
+    fn test_dry_run_shows_hints() {
+        let temp = tempfile::tempdir().unwrap();
+        let yaml = "build:\n  priority: low\n  steps:\n    - parallel:\n        - cmd: make\n          weight: 4\n        - cmd: make docs\n          priority: normal\n";
+        std::fs::write(temp.path().join(config::CONFIG_FILE), yaml).unwrap();
+        let (ui, capture) = Ui::capture(false);
+        let opts = RunOptions {
+            dry_run: true,
+            jobs: Some(1),
+            ui,
+            project_root: Some(temp.path().to_path_buf()),
+            ..Default::default()
+        };
+        run_task("build", &[], &opts).unwrap();
+        let stdout = capture.stdout();
+        assert!(
+            stdout.contains("$ make (priority: low, weight: 4)\n"),
+            "{}",
+            stdout
+        );
+        assert!(stdout.contains("$ make docs\n"), "{}", stdout);
+    }
+
+    #[test]
+    fn test_nested_configs_read_once() {
+        let temp = tempfile::tempdir().unwrap();
+        let sub = temp.path().join("sub");
+        std::fs::create_dir(&sub).unwrap();
+        std::fs::write(sub.join(config::CONFIG_FILE), "build: echo sub\n").unwrap();
+        let steps = "    - task: build\n      dir: sub\n".repeat(30);
+        let yaml = format!(
+            "all:\n  steps:\n{}    - parallel:\n{}    - task: nested\nnested:\n  dir: sub\n  task: build\n",
+            steps,
+            "        - task: build\n          dir: sub\n".repeat(4)
+        );
+        std::fs::write(temp.path().join(config::CONFIG_FILE), yaml).unwrap();
+
+        let (ui, capture) = Ui::capture(false);
+        let opts = RunOptions {
+            dry_run: true,
+            ui,
+            project_root: Some(temp.path().to_path_buf()),
+            ..Default::default()
+        };
+        run_task("all", &[], &opts).unwrap();
+        assert_eq!(capture.stdout().matches("$ echo sub").count(), 35);
+        assert_eq!(opts.configs.reads(), 2);
+    }
+
+    fn run_cmd(yaml: &str, args: &[String]) -> (Result<()>, String) {
+        let temp = tempfile::tempdir().unwrap();
+        std::fs::write(temp.path().join(config::CONFIG_FILE), yaml).unwrap();
+        let (ui, capture) = Ui::capture(false);
+        let opts = RunOptions {
+            ui,
+            project_root: Some(temp.path().to_path_buf()),
+            ..Default::default()
+        };
+        let result = run_task("greet", args, &opts);
+        (result, capture.stdout())
+    }
+
+    #[test]
+    fn test_echo_can_be_turned_off() {
+        let yaml = "greet:\n  cmd: echo hi\n  echo: false\n";
+        let (result, stdout) = run_cmd(yaml, &[]);
+        result.unwrap();
+        assert_eq!(stdout, "");
+
+        let yaml = "echo_format: '{task}#{step} $ {cmd}'\ngreet:\n  steps:\n    - cmd: echo one\n    - cmd: echo two\n      echo: false\n";
+        let (result, stdout) = run_cmd(yaml, &[]);
+        result.unwrap();
+        assert_eq!(stdout, "greet#1 $ echo one\n");
+
+        let yaml = "greet:\n  echo: false\n  steps:\n    - cmd: echo hi\n";
+        let (result, _) = run_cmd(yaml, &[]);
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "'echo' only applies to a task's 'cmd'; set it on each step instead"
+        );
+
+        let yaml = "greet:\n  steps:\n    - mkdir: out\n      echo: false\n";
+        let (result, _) = run_cmd(yaml, &[]);
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Step 'echo' only applies to a step with a 'cmd'"
+        );
+    }
+
+    #[test]
+    fn test_deprecated_and_renamed_tasks() {
+        let yaml = "build: echo build\nold-build:\n  cmd: echo old\n  deprecated:\n    replaced_by: build\n    message: going away in 0.6\nlint:\n  cmd: echo lint\n  deprecated: true\nrenamed:\n  compile: build\n";
+        let run = |name: &str, strict: bool| {
+            let temp = tempfile::tempdir().unwrap();
+            std::fs::write(temp.path().join(config::CONFIG_FILE), yaml).unwrap();
+            let (ui, capture) = Ui::capture(false);
+            let opts = RunOptions {
+                ui,
+                project_root: Some(temp.path().to_path_buf()),
+                strict,
+                dry_run: true,
+                ..Default::default()
+            };
+            let result = run_task(name, &[], &opts);
+            (result, capture.stderr())
+        };
+
+        let (result, stderr) = run("compile", false);
+        result.unwrap();
+        assert_eq!(
+            stderr,
+            "Warning: Task 'compile' was renamed to 'build'; running 'build'\n"
+        );
+        let (result, _) = run("compile", true);
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Task 'compile' was renamed to 'build'; run 'rnr build' instead"
+        );
+
+        let (result, stderr) = run("old-build", false);
+        result.unwrap();
+        assert_eq!(
+            stderr,
+            "Warning: Task 'old-build' is deprecated; use 'build' instead (going away in 0.6)\n"
+        );
+        let (result, _) = run("old-build", true);
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Task 'old-build' is deprecated; use 'build' instead (going away in 0.6)"
+        );
+
+        let (result, stderr) = run("lint", false);
+        result.unwrap();
+        assert_eq!(stderr, "Warning: Task 'lint' is deprecated\n");
+        let (result, stderr) = run("build", true);
+        result.unwrap();
+        assert_eq!(stderr, "");
+    }
+
+    #[test]
+    fn test_task_names_in_another_case() {
+        let yaml = "build: echo built >> out.txt\nlint: echo linted >> out.txt\nci:\n  steps:\n    - task: Build\nrenamed:\n  LINT: build\nDeploy: echo a\nDEPLOY: echo b\nrelease:\n  task: deploy\n";
+        let run = |name: &str, strict: bool| {
+            let temp = tempfile::tempdir().unwrap();
+            std::fs::write(temp.path().join(config::CONFIG_FILE), yaml).unwrap();
+            let (ui, capture) = Ui::capture(false);
+            let opts = RunOptions {
+                ui,
+                project_root: Some(temp.path().to_path_buf()),
+                strict,
+                ..Default::default()
+            };
+            let result = run_task(name, &[], &opts);
+            let out = std::fs::read_to_string(temp.path().join("out.txt")).unwrap_or_default();
+            (result, capture.stderr(), out)
+        };
+
+        let (result, stderr, out) = run("BUILD", false);
+        result.unwrap();
+        assert_eq!(stderr, "Running 'build' (matched 'BUILD')\n");
+        assert_eq!(out, "built\n");
+        let (result, stderr, out) = run("ci", false);
+        result.unwrap();
+        assert_eq!(stderr, "Running 'build' (matched 'Build')\n");
+        assert_eq!(out, "built\n");
+
+        // `renamed` names come before other cases
+        let (result, _, out) = run("LINT", false);
+        result.unwrap();
+        assert_eq!(out, "built\n");
+        run("Lint", false).0.unwrap();
+
+        let strict =
+            "Task 'Build' not found; task names are case-sensitive in strict mode, so use 'build'";
+        assert_eq!(run("Build", true).0.unwrap_err().to_string(), strict);
+        let (result, _, out) = run("ci", true);
+        assert_eq!(format!("{:#}", result.unwrap_err()), strict);
+        assert_eq!(out, "");
+
+        // Names that differ only in case never pick one
+        let ambiguous = "Task 'deploy' matches several tasks that differ only in case: DEPLOY, Deploy; use the exact name";
+        assert_eq!(run("deploy", false).0.unwrap_err().to_string(), ambiguous);
+        let error = run("release", false).0.unwrap_err();
+        assert!(format!("{:#}", error).contains(ambiguous), "{:#}", error);
+        run("Deploy", true).0.unwrap();
+    }
+
+    #[test]
+    fn test_contexts_are_checked_before_anything_runs() {
+        let yaml = "deploy:\n  cmd: echo deploying\n  contexts:\n    allow: [ci]\nrelease:\n  steps:\n    - mkdir: out\n    - task: deploy\n";
+        let run = |context: &str| {
+            let temp = tempfile::tempdir().unwrap();
+            std::fs::write(temp.path().join(config::CONFIG_FILE), yaml).unwrap();
+            let (ui, _) = Ui::capture(false);
+            let opts = RunOptions {
+                ui,
+                project_root: Some(temp.path().to_path_buf()),
+                context: Some(context.to_string()),
+                ..Default::default()
+            };
+            let result = run_task("release", &[], &opts);
+            (result, temp.path().join("out").exists())
+        };
+
+        let (result, ran) = run("local");
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Task 'deploy' can't run in the 'local' context (allowed: ci). \
+             Set RNR_CONTEXT to run it in another context"
+        );
+        assert!(!ran);
+
+        let (result, ran) = run("ci");
+        result.unwrap();
+        assert!(ran);
+    }
+
+    #[test]
+    fn test_not_between_windows_are_checked_before_anything_runs() {
+        let yaml = "tz: '+01:00'\ndeploy:\n  cmd: echo deploying\n  not_between: ['22:00', '06:00']\nrelease:\n  steps:\n    - mkdir: out\n    - task: deploy\n";
+        let run = |utc_hour: u64, ignore_guards: bool| {
+            let temp = tempfile::tempdir().unwrap();
+            std::fs::write(temp.path().join(config::CONFIG_FILE), yaml).unwrap();
+            let (ui, capture) = Ui::capture(false);
+            let now = SystemTime::UNIX_EPOCH + Duration::from_secs(utc_hour * 3600);
+            let opts = RunOptions {
+                ui,
+                project_root: Some(temp.path().to_path_buf()),
+                clock: Clock::new(move || now),
+                ignore_guards,
+                ..Default::default()
+            };
+            let result = run_task("release", &[], &opts);
+            (result, temp.path().join("out").exists(), capture.stderr())
+        };
+
+        let (result, ran, _) = run(21, false);
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Task 'deploy' doesn't run between 22:00 and 06:00 (UTC+01:00; it's 22:00 now). \
+             Pass --ignore-guards to run it anyway"
+        );
+        assert!(!ran);
+
+        let (result, ran, _) = run(5, false);
+        result.unwrap();
+        assert!(ran);
+
+        let (result, ran, stderr) = run(21, true);
+        result.unwrap();
+        assert!(ran);
+        assert!(stderr.contains("running it anyway (--ignore-guards)"));
+    }
+
+    #[test]
+    fn test_budget_stops_starting_steps() {
+        let yaml = "budget: 15m\nci:\n  steps:\n    - mkdir: a\n    - parallel:\n        - mkdir: b\n    - mkdir: c\n    - mkdir: d\n";
+        let run = |ignore_guards: bool| {
+            let temp = tempfile::tempdir().unwrap();
+            std::fs::write(temp.path().join(config::CONFIG_FILE), yaml).unwrap();
+            let (ui, capture) = Ui::capture(false);
+            // Each step takes ten minutes, going by the directories made so far
+            let root = temp.path().to_path_buf();
+            let clock = Clock::new(move || {
+                let done = ["a", "b", "c", "d"]
+                    .iter()
+                    .filter(|dir| root.join(dir).exists())
+                    .count();
+                SystemTime::UNIX_EPOCH + Duration::from_secs(600 * done as u64)
+            });
+            let opts = RunOptions {
+                ui,
+                project_root: Some(temp.path().to_path_buf()),
+                clock,
+                ignore_guards,
+                ..Default::default()
+            };
+            let result = run_task("ci", &[], &opts);
+            let made: Vec<&str> = ["a", "b", "c", "d"]
+                .into_iter()
+                .filter(|dir| temp.path().join(dir).exists())
+                .collect();
+            let last = history::load(temp.path()).unwrap().unwrap();
+            let statuses: Vec<Status> = last.tasks[0].steps.iter().map(|s| s.status).collect();
+            (result, made, statuses, capture.stderr())
+        };
+
+        let (result, made, statuses, stderr) = run(false);
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Over the 15m budget after 20m, so 2 step(s) weren't started. \
+             Pass --ignore-guards to run past it"
+        );
+        assert_eq!(made, ["a", "b"]);
+        assert_eq!(
+            statuses,
+            [
+                Status::Passed,
+                Status::Passed,
+                Status::SkippedOverBudget,
+                Status::SkippedOverBudget
+            ]
+        );
+        assert!(stderr.contains("OVER BUDGET ci"));
+        assert!(stderr.contains("not started: step 3, 4"));
+
+        let (result, made, _, stderr) = run(true);
+        result.unwrap();
+        assert_eq!(made, ["a", "b", "c", "d"]);
+        assert_eq!(stderr.matches("--ignore-guards").count(), 1);
+    }
+
+    #[test]
+    fn test_shared_dir_warning() {
+        let run = |yaml: &str, strict: bool| {
+            let temp = tempfile::tempdir().unwrap();
+            std::fs::write(temp.path().join(config::CONFIG_FILE), yaml).unwrap();
+            std::fs::create_dir(temp.path().join("web")).unwrap();
+            let (ui, capture) = Ui::capture(false);
+            let opts = RunOptions {
+                ui,
+                project_root: Some(temp.path().to_path_buf()),
+                strict,
+                dry_run: true,
+                ..Default::default()
+            };
+            let result = run_task("greet", &[], &opts);
+            (result, capture.stderr())
+        };
+
+        let yaml = "install:\n  dir: web\n  cmd: npm ci\ngreet:\n  steps:\n    - cmd: echo start\n    - parallel:\n        - cmd: npm install\n          dir: web\n        - cmd: cargo build\n        - task: install\n";
+        let (result, stderr) = run(yaml, false);
+        result.unwrap();
+        assert_eq!(
+            stderr,
+            "Warning: Parallel branches of task 'greet' step 2 work in the same directory (web): \
+             2.1 ($ npm install), 2.3 (task install). They may interfere; set \
+             'allow_shared_dir: true' on the block if that's intended\n"
+        );
+        let (result, _) = run(yaml, true);
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .starts_with("Parallel branches of task 'greet' step 2 work in the same directory"));
+
+        let allowed = yaml.replace(
+            "    - parallel:",
+            "    - allow_shared_dir: true\n      parallel:",
+        );
+        let (result, stderr) = run(&allowed, true);
+        result.unwrap();
+        assert_eq!(stderr, "");
+
+        let lanes = "greet:\n  steps:\n    - parallel:\n        api:\n          - mkdir: out\n        web:\n          - cmd: npm run build\n            dir: web\n          - mkdir: out\n";
+        let (result, stderr) = run(lanes, false);
+        result.unwrap();
+        assert!(
+            stderr.contains("step 1 work in the same directory (out): lane api, lane web."),
+            "{}",
+            stderr
+        );
+    }
+
+    #[test]
+    fn test_step_filter() {
+        let yaml = "greet:\n  steps:\n    - cmd: echo lint\n      tags: [lint]\n    - cmd: echo build\n    - parallel:\n        - cmd: echo unit\n          tags: [slow]\n        - cmd: echo docs\n          tags: [slow]\n    - cmd: echo fmt\n      tags: [lint, fast]\n";
+        let run = |only: &[&str], skip: &[&str], steps: Option<&str>| {
+            let temp = tempfile::tempdir().unwrap();
+            std::fs::write(temp.path().join(config::CONFIG_FILE), yaml).unwrap();
+            let tags =
+                |tags: &[&str]| -> Vec<String> { tags.iter().map(|t| t.to_string()).collect() };
+            let (ui, capture) = Ui::capture(false);
+            let opts = RunOptions {
+                ui,
+                project_root: Some(temp.path().to_path_buf()),
+                jobs: Some(1),
+                steps: StepFilter::new(&tags(only), &tags(skip), steps).unwrap(),
+                ..Default::default()
+            };
+            run_task("greet", &[], &opts).unwrap();
+            capture.stdout()
+        };
+
+        assert_eq!(
+            run(&[], &["slow"], None),
+            "$ echo lint\n$ echo build\n\
+             Skipping step 3.1: $ echo unit (filtered out by --skip slow)\n\
+             Skipping step 3.2: $ echo docs (filtered out by --skip slow)\n\
+             $ echo fmt\n"
+        );
+        let stdout = run(&["lint"], &["fast"], None);
+        assert!(
+            stdout.starts_with("$ echo lint\nSkipping step 2: $ echo build"),
+            "{}",
+            stdout
+        );
+        assert!(stdout
+            .ends_with("Skipping step 4: $ echo fmt (filtered out by --only lint --skip fast)\n"));
+        let stdout = run(&[], &[], Some("2-3"));
+        assert!(
+            stdout.starts_with(
+                "Skipping step 1: $ echo lint (filtered out by --steps 2-3)\n$ echo build\n"
+            ),
+            "{}",
+            stdout
+        );
+        assert!(stdout.contains("$ echo unit\n"), "{}", stdout);
+        assert!(!stdout.contains("$ echo fmt\n"), "{}", stdout);
+
+        let yaml = "greet:\n  cmd: echo hi\n";
+        let temp = tempfile::tempdir().unwrap();
+        std::fs::write(temp.path().join(config::CONFIG_FILE), yaml).unwrap();
+        let opts = RunOptions {
+            ui: Ui::capture(false).0,
+            project_root: Some(temp.path().to_path_buf()),
+            steps: StepFilter::new(&[], &[], Some("1")).unwrap(),
+            ..Default::default()
+        };
+        assert_eq!(
+            run_task("greet", &[], &opts).unwrap_err().to_string(),
+            "--steps 1 only applies to a task with steps"
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_argv_runs_without_a_shell() {
+        let yaml = "greet:\n  env:\n    NAME: world\n  cmd: [printf, '%s|', 'hello ${env.NAME}', '$HOME', \"it's\"]\n";
+        let (result, stdout) = run_cmd(yaml, &["--x y".to_string()]);
+        result.unwrap();
+        assert_eq!(
+            stdout,
+            "$ printf '%s|' 'hello world' '$HOME' 'it'\\''s' '--x y'\n"
+        );
+    }
+
+    #[test]
+    fn test_argv_names_missing_program() {
+        let yaml = "greet:\n  cmd: [rnr-no-such-program, --version]\n";
+        let (result, _) = run_cmd(yaml, &[]);
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Program 'rnr-no-such-program' not found on PATH"
+        );
+
+        let yaml = "greet:\n  cmd: [./missing.sh]\n";
+        let (result, _) = run_cmd(yaml, &[]);
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Program './missing.sh' not found at that path"
+        );
+    }
+
+    #[test]
+    fn test_delegation_with_env_and_args() {
+        let yaml = "greet:\n  steps:\n    - task: deploy\n      env:\n        MODE: fast\n      args: [staging]\n    - task: deploy\n      args: [--target, prod]\n    - task: release\n    - cmd: echo ${env.MODE}\n      env:\n        MODE: step\nrelease:\n  env:\n    MODE: careful\n  task: deploy\n  task_args: [qa]\ndeploy:\n  args:\n    - name: target\n      required: true\n  env:\n    MODE: safe\n  cmd: echo ${args.target} ${env.MODE}\n";
+        let (result, stdout) = run_cmd(yaml, &[]);
+        result.unwrap();
+        assert_eq!(
+            stdout,
+            "$ echo staging fast\n$ echo prod safe\n$ echo qa careful\n$ echo step\n"
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_parallel_outcomes_per_step() {
+        let yaml = "ci:\n  steps:\n    - parallel:\n        - cmd: sleep 0.2; exit 3\n        - cmd: echo ok\n        - task: check\ncheck: exit 4\n";
+        let temp = tempfile::tempdir().unwrap();
+        let config = config(yaml);
+        let opts = RunOptions {
+            project_root: Some(temp.path().to_path_buf()),
+            ui: Ui::capture(false).0,
+            ..Default::default()
+        };
+        let outcomes = execute_parallel(
+            parallel_block(&config),
+            temp.path(),
+            &HashMap::new(),
+            &config,
+            &TaskArgs::default(),
+            &opts,
+        );
+
+        let summary: Vec<(usize, &str, Status, Option<String>)> = outcomes
+            .iter()
+            .map(|o| {
+                let error = o.error.as_ref().map(|e| format!("{:#}", e));
+                (o.branch, o.label.as_str(), o.status, error)
+            })
+            .collect();
+        let failed = |code| Some(format!("Command failed with exit code {}", code));
+        assert_eq!(
+            summary,
+            [
+                (1, "$ sleep 0.2; exit 3", Status::Failed, failed(3)),
+                (2, "$ echo ok", Status::Passed, None),
+                (3, "task check", Status::Failed, failed(4)),
+            ]
+        );
+        assert!(outcomes[0].duration >= Duration::from_millis(200));
+
+        let message = parallel_summary(&outcomes).unwrap_err().to_string();
+        let lines: Vec<&str> = message.lines().collect();
+        assert_eq!(lines[0], "Parallel execution failed with 2 error(s):");
+        assert!(lines[1].starts_with("  - branch 1 ($ sleep 0.2; exit 3) after "));
+        assert!(lines[1].ends_with("Command failed with exit code 3"));
+        assert!(lines[2].starts_with("  - branch 3 (task check) after "));
+        assert!(lines[2].ends_with("Command failed with exit code 4"));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_env_from_cmd() {
+        let temp = tempfile::tempdir().unwrap();
+        std::fs::write(
+            temp.path().join(config::CONFIG_FILE),
+            "greet:\n  steps:\n    - task: tag\n    - task: tag\ntag:\n  env:\n    PREFIX: v\n    TAG: { from_cmd: 'echo run >> runs.txt; echo \"  ${PREFIX}1.2 \"' }\n  cmd: echo ${env.TAG}\nbroken:\n  env:\n    TAG: { from_cmd: 'echo no tags >&2; exit 3' }\n  cmd: echo ${env.TAG}\n",
+        )
+        .unwrap();
+        let (ui, capture) = Ui::capture(false);
+        let mut opts = RunOptions {
+            ui,
+            project_root: Some(temp.path().to_path_buf()),
+            ..Default::default()
+        };
+
+        run_task("greet", &[], &opts).unwrap();
+        assert_eq!(capture.stdout(), "$ echo v1.2\n$ echo v1.2\n");
+        let runs = std::fs::read_to_string(temp.path().join("runs.txt")).unwrap();
+        assert_eq!(runs, "run\n");
+
+        let err = run_task("broken", &[], &opts).unwrap_err();
+        assert_eq!(
+            format!("{:#}", err),
+            "Failed to compute env TAG from 'echo no tags >&2; exit 3':\nno tags: Command failed with exit code 3"
+        );
+
+        opts.dry_run = true;
+        run_task("broken", &[], &opts).unwrap();
+        assert!(capture
+            .stdout()
+            .ends_with("$ echo <computed from: echo no tags >&2; exit 3>\n"));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_foreach_runs_the_task_per_value() {
+        let temp = tempfile::tempdir().unwrap();
+        std::fs::write(
+            temp.path().join(config::CONFIG_FILE),
+            "migrate:\n  foreach: { values: [users, orders, billing], as: DB }\n  cmd: echo ${foreach.DB} $DB\nlisted:\n  foreach: { from_cmd: 'printf \"a\\n\\n b \\n\"', as: X }\n  task: show\nshow: echo $X\nnone:\n  foreach: { values: [], as: X }\n  cmd: echo never\nbroken:\n  foreach: { from_cmd: 'echo nope >&2; exit 2', as: X }\n  cmd: echo never\nfails:\n  foreach: { values: [a, b, c], as: X }\n  cmd: test $X != b\nkeeps_going:\n  foreach: { values: [a, b, c], as: X, continue_on_error: true }\n  cmd: test $X = a\n",
+        )
+        .unwrap();
+        let (ui, capture) = Ui::capture(false);
+        let opts = RunOptions {
+            ui,
+            project_root: Some(temp.path().to_path_buf()),
+            ..Default::default()
+        };
+
+        run_task("migrate", &[], &opts).unwrap();
+        run_task("listed", &[], &opts).unwrap();
+        assert_eq!(
+            capture.stdout(),
+            "[users] $ echo users $DB\n[users] users users\n\
+             [orders] $ echo orders $DB\n[orders] orders orders\n\
+             [billing] $ echo billing $DB\n[billing] billing billing\n\
+             [a] $ echo $X\n[a] a\n[b] $ echo $X\n[b] b\n"
+        );
+        run_task("none", &[], &opts).unwrap();
+        assert!(capture
+            .stderr()
+            .contains("foreach X: no values, nothing to run"));
+
+        let err = run_task("broken", &[], &opts).unwrap_err();
+        assert_eq!(
+            format!("{:#}", err),
+            "Failed to list foreach X values from 'echo nope >&2; exit 2':\nnope: Command failed with exit code 2"
+        );
+        assert!(!capture.stdout().contains("never"));
+
+        let err = run_task("fails", &[], &opts).unwrap_err();
+        assert_eq!(
+            format!("{:#}", err),
+            "foreach X failed for 1 of 3 value(s), 1 not run:\n  - b: Command failed with exit code 1"
+        );
+        let err = run_task("keeps_going", &[], &opts).unwrap_err();
+        assert_eq!(
+            format!("{:#}", err),
+            "foreach X failed for 2 of 3 value(s):\n  - b: Command failed with exit code 1\n  - c: Command failed with exit code 1"
+        );
+    }
+
+    #[test]
+    fn test_foreach_jobs_and_dry_run() {
+        let yaml = "greet:\n  foreach: { values: [a, b, c, d], as: X, jobs: 2 }\n  cmd: echo ${foreach.X}\nlisted:\n  foreach: { from_cmd: exit 1, as: X }\n  cmd: echo ${foreach.X}\nbad:\n  foreach: { values: [a], from_cmd: echo a, as: X }\n  cmd: echo\n";
+        let temp = tempfile::tempdir().unwrap();
+        std::fs::write(temp.path().join(config::CONFIG_FILE), yaml).unwrap();
+        let (ui, capture) = Ui::capture(false);
+        let mut opts = RunOptions {
+            ui,
+            project_root: Some(temp.path().to_path_buf()),
+            ..Default::default()
+        };
+
+        run_task("greet", &[], &opts).unwrap();
+        let mut lines: Vec<String> = capture.stdout().lines().map(str::to_string).collect();
+        lines.sort();
+        assert_eq!(lines.len(), 8);
+        assert_eq!(lines[0], "[a] $ echo a");
+        let err = run_task("bad", &[], &opts).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "'foreach' takes 'values' or 'from_cmd', not both"
+        );
+
+        opts.dry_run = true;
+        run_task("listed", &[], &opts).unwrap();
+        assert!(capture.stdout().ends_with("[<X>] $ echo <X>\n"));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_env_steps_set_env_for_later_steps() {
+        let temp = tempfile::tempdir().unwrap();
+        std::fs::write(
+            temp.path().join(config::CONFIG_FILE),
+            "greet:\n  args:\n    - name: who\n  steps:\n    - cmd: echo \"[$STAGE]\" >> out.txt\n    - env:\n        STAGE: ${args.who}-1\n        API_TOKEN: hunter22\n    - cmd: printf 'GENERATED=\"from file\"\\nSTAGE=file\\n' > gen.env\n    - env_file: gen.env\n    - cmd: echo \"[$STAGE] [$GENERATED] [${env.API_TOKEN}]\" >> out.txt\n    - task: child\nchild: echo \"child [$STAGE]\" >> out.txt\n",
+        )
+        .unwrap();
+        let (ui, capture) = Ui::capture(false);
+        let opts = RunOptions {
+            ui,
+            project_root: Some(temp.path().to_path_buf()),
+            ..Default::default()
+        };
+        run_task("greet", &["dev".to_string()], &opts).unwrap();
+        let out = std::fs::read_to_string(temp.path().join("out.txt")).unwrap();
+        // Like the task's own env, it isn't passed to delegated tasks
+        assert_eq!(out, "[]\n[file] [from file] [hunter22]\nchild []\n");
+        let stdout = capture.stdout();
+        assert!(stdout.contains("$ env API_TOKEN, STAGE\n  API_TOKEN=***\n  STAGE=dev-1\n"));
+        assert!(stdout.contains("$ env_file gen.env\n  GENERATED=from file\n  STAGE=file\n"));
+
+        let yaml = "greet:\n  steps:\n    - env_file: missing.env\n    - cmd: echo never\n";
+        let (result, stdout) = run_cmd(yaml, &[]);
+        assert!(format!("{:#}", result.unwrap_err()).contains("Failed to read env file"));
+        assert!(!stdout.contains("never"));
+
+        let yaml = "greet:\n  steps:\n    - env_file: a.env\n      cmd: echo hi\n";
+        let (result, _) = run_cmd(yaml, &[]);
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Step 'env_file' can't be combined with cmd, task or a builtin; give it a step of its own"
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_expected_exit_codes() {
+        let temp = tempfile::tempdir().unwrap();
+        std::fs::write(temp.path().join("words.txt"), "apple\n").unwrap();
+        std::fs::write(
+            temp.path().join(config::CONFIG_FILE),
+            "greet:\n  steps:\n    - cmd: grep -q pear words.txt\n      expect_exit: [0, 1]\n      export_exit: FOUND\n    - cmd: echo \"pear [$FOUND] [${env.FOUND}]\" >> out.txt\n    - cmd: grep -q apple words.txt\n      expect_exit: [0, 1]\n      export_exit: FOUND\n    - cmd: echo \"apple [$FOUND]\" >> out.txt\n    - cmd: exit 3\n      expect_exit: any\n    - task: nested\nnested:\n  steps:\n    - cmd: exit 5\n      expect_exit: 5\n      export_exit: CODE\n    - cmd: echo \"nested [$CODE]\" >> out.txt\n",
+        )
+        .unwrap();
+        let (ui, _capture) = Ui::capture(false);
+        let opts = RunOptions {
+            ui,
+            project_root: Some(temp.path().to_path_buf()),
+            ..Default::default()
+        };
+        run_task("greet", &[], &opts).unwrap();
+        let out = std::fs::read_to_string(temp.path().join("out.txt")).unwrap();
+        assert_eq!(out, "pear [1] [1]\napple [0]\nnested [5]\n");
+
+        // Codes outside the set still fail, naming the ones expected
+        let yaml = "greet:\n  steps:\n    - cmd: grep -q pear missing.txt\n      expect_exit: [0, 1]\n    - cmd: echo never\n";
+        let (result, stdout) = run_cmd(yaml, &[]);
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Command failed with exit code 2 (expected exit codes 0 or 1)"
+        );
+        assert!(!stdout.contains("never"));
+
+        let yaml = "greet:\n  cmd: exit 1\n  expect_exit: [1]\n";
+        run_cmd(yaml, &[]).0.unwrap();
+        let yaml = "greet:\n  cmd: exit 0\n  expect_exit: [1]\n";
+        assert_eq!(
+            run_cmd(yaml, &[]).0.unwrap_err().to_string(),
+            "Command failed with exit code 0 (expected exit code 1)"
+        );
+
+        let yaml = "greet:\n  steps:\n    - task: other\n      export_exit: CODE\nother: echo hi\n";
+        assert_eq!(
+            run_cmd(yaml, &[]).0.unwrap_err().to_string(),
+            "Step 'export_exit' only applies to a step with a 'cmd'"
+        );
+        let yaml = "greet:\n  steps:\n    - mkdir: out\n      expect_exit: any\n";
+        assert_eq!(
+            run_cmd(yaml, &[]).0.unwrap_err().to_string(),
+            "Step 'expect_exit' only applies to a step with a 'cmd'"
+        );
+        let yaml = "greet:\n  expect_exit: any\n  steps:\n    - cmd: echo hi\n";
+        assert_eq!(
+            run_cmd(yaml, &[]).0.unwrap_err().to_string(),
+            "'expect_exit' only applies to a task's 'cmd'; set it on each step instead"
+        );
+    }
+
+    #[test]
+    fn test_chaos_fails_steps_and_tasks_instead_of_running_them() {
+        let temp = tempfile::tempdir().unwrap();
+        std::fs::write(
+            temp.path().join(config::CONFIG_FILE),
+            "greet:\n  steps:\n    - cmd: echo one\n    - cmd: echo two\n    - cmd: echo three\nci:\n  steps:\n    - parallel:\n        - cmd: echo lint\n        - task: build\nbuild:\n  steps:\n    - cmd: echo building\n",
+        )
+        .unwrap();
+        let run = |task: &str, specs: &[&str]| {
+            let (ui, capture) = Ui::capture(false);
+            let specs: Vec<String> = specs.iter().map(|spec| spec.to_string()).collect();
+            let opts = RunOptions {
+                ui,
+                project_root: Some(temp.path().to_path_buf()),
+                chaos: Chaos::parse(&specs).unwrap(),
+                ..Default::default()
+            };
+            let result = run_task(task, &[], &opts);
+            (result, capture.stdout())
+        };
+
+        let (result, stdout) = run("greet", &["fail-step=2"]);
+        let error = result.unwrap_err();
+        assert!(error.downcast_ref::<crate::chaos::Injected>().is_some());
+        assert_eq!(
+            error.to_string(),
+            "Injected failure from --chaos fail-step=2 (not a real failure)"
+        );
+        assert!(stdout.contains("one\n"), "{}", stdout);
+        assert!(!stdout.contains("two"), "{}", stdout);
+        assert!(!stdout.contains("three"), "{}", stdout);
+
+        // A delegated task fails where it's reached; steps match by task
+        let (result, stdout) = run("ci", &["fail-task=build"]);
+        assert!(format!("{:#}", result.unwrap_err()).contains("--chaos fail-task=build"));
+        assert!(stdout.contains("lint\n"), "{}", stdout);
+        assert!(!stdout.contains("building"), "{}", stdout);
+        let (result, _) = run("ci", &["fail-step=build:1"]);
+        assert!(format!("{:#}", result.unwrap_err()).contains("fail-step=build:1"));
+        run("ci", &["fail-step=greet:1"]).0.unwrap();
+        run("greet", &["fail-step=4"]).0.unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_dir_overrides_the_invoked_task_only() {
+        let temp = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(temp.path().join("api")).unwrap();
+        std::fs::create_dir_all(temp.path().join("web")).unwrap();
+        std::fs::write(
+            temp.path().join(config::CONFIG_FILE),
+            "build:\n  dir: web\n  steps:\n    - cmd: pwd > here.txt\n    - cmd: pwd > there.txt\n      dir: web\n    - task: root\nroot: pwd > root.txt\nrelease:\n  checkout: clean\n  cmd: make\n",
+        )
+        .unwrap();
+        let (ui, capture) = Ui::capture(false);
+        let opts = RunOptions {
+            ui,
+            project_root: Some(temp.path().to_path_buf()),
+            dir: Some(temp.path().join("api")),
+            ..Default::default()
+        };
+
+        run_task("build", &[], &opts).unwrap();
+        let api = temp.path().join("api");
+        assert!(api.join("here.txt").exists());
+        assert!(!temp.path().join("web").join("here.txt").exists());
+        // Steps and delegations with their own dir keep it
+        assert!(temp.path().join("web").join("there.txt").exists());
+        assert!(temp.path().join("root.txt").exists());
+        assert!(capture
+            .stdout()
+            .contains("Running 'build' in api (--dir)\n"));
+
+        let error = run_task("release", &[], &opts).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("--dir can't be used with 'release'"));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_once_tasks_run_once() {
+        let temp = tempfile::tempdir().unwrap();
+        std::fs::write(
+            temp.path().join(config::CONFIG_FILE),
+            "ci:\n  steps:\n    - parallel:\n        - task: lint\n        - task: test\n        - task: setup\nlint:\n  steps:\n    - task: setup\n    - cmd: echo lint\ntest:\n  task: setup\nsetup:\n  once: true\n  cmd: echo run >> runs.txt\nflaky:\n  once: true\n  cmd: test -f ok || { touch ok; exit 1; }\nretry:\n  task: flaky\n",
+        )
+        .unwrap();
+        let (ui, capture) = Ui::capture(false);
+        let opts = RunOptions {
+            ui,
+            project_root: Some(temp.path().to_path_buf()),
+            ..Default::default()
+        };
+
+        run_task("ci", &[], &opts).unwrap();
+        let runs = std::fs::read_to_string(temp.path().join("runs.txt")).unwrap();
+        assert_eq!(runs, "run\n");
+        assert_eq!(capture.stdout().matches("setup (already ran)\n").count(), 2);
+
+        // Failures aren't remembered
+        assert!(run_task("retry", &[], &opts).is_err());
+        run_task("retry", &[], &opts).unwrap();
+        run_task("retry", &[], &opts).unwrap();
+        assert_eq!(capture.stdout().matches("flaky (already ran)\n").count(), 1);
+    }
+
+    #[test]
+    fn test_once_task_cycles_fail() {
+        let temp = tempfile::tempdir().unwrap();
+        std::fs::write(
+            temp.path().join(config::CONFIG_FILE),
+            "a:\n  once: true\n  steps:\n    - task: b\nb:\n  steps:\n    - parallel:\n        - task: a\nc:\n  task: a\n",
+        )
+        .unwrap();
+        let opts = RunOptions {
+            ui: Ui::capture(false).0,
+            project_root: Some(temp.path().to_path_buf()),
+            ..Default::default()
+        };
+
+        let err = run_task("a", &[], &opts).unwrap_err();
+        assert!(
+            format!("{:#}", err).contains("Task cycle a -> b -> a"),
+            "{:#}",
+            err
+        );
+        let err = run_task("c", &[], &opts).unwrap_err();
+        assert!(
+            format!("{:#}", err).contains("Task cycle a -> b -> a"),
+            "{:#}",
+            err
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_output_filters() {
+        let temp = tempfile::tempdir().unwrap();
+        std::fs::write(
+            temp.path().join(config::CONFIG_FILE),
+            "test:\n  cmd: printf 'test a ... ok\\ntest b ... FAILED\\n'\n  output_filter: cargo-json\nbuild:\n  steps:\n    - cmd: echo compiled\n      output_filter: quiet-success\n    - cmd: echo broken; exit 2\n      output_filter: quiet-success\nlanes:\n  steps:\n    - parallel:\n        web:\n          - cmd: echo hidden\n            output_filter: quiet-success\nmisplaced:\n  output_filter: quiet-success\n  steps:\n    - cmd: echo hi\n",
+        )
+        .unwrap();
+        let (ui, capture) = Ui::capture(false);
+        let mut opts = RunOptions {
+            ui,
+            project_root: Some(temp.path().to_path_buf()),
+            ..Default::default()
+        };
+
+        run_task("test", &[], &opts).unwrap();
+        assert!(capture
+            .stdout()
+            .ends_with("PASS a\nFAIL b\n1 passed, 1 failed, 0 ignored\n"));
+
+        assert!(run_task("build", &[], &opts).is_err());
+        assert!(!capture.stdout().contains("\ncompiled\n"));
+        assert!(capture
+            .stdout()
+            .ends_with("$ echo broken; exit 2\nbroken\n"));
+
+        run_task("lanes", &[], &opts).unwrap();
+        assert!(!capture.stdout().contains("[web] hidden"));
+
+        let err = run_task("misplaced", &[], &opts).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "'output_filter' only applies to a task's 'cmd'; set it on each step instead"
+        );
+
+        // --verbose leaves the output alone
+        opts.verbose = true;
+        run_task("lanes", &[], &opts).unwrap();
+        assert!(capture.stdout().contains("[web] hidden\n"));
+    }
+
+    #[test]
+    fn test_step_args_need_a_task() {
+        let yaml = "greet:\n  steps:\n    - cmd: echo hi\n      args: [x]\n";
+        let (result, _) = run_cmd(yaml, &[]);
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Step 'args' only apply to a step that runs a 'task'"
+        );
+
+        let yaml = "greet:\n  steps:\n    - task: deploy\ndeploy:\n  args:\n    - name: target\n      required: true\n  cmd: echo\n";
+        let (result, _) = run_cmd(yaml, &[]);
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("Missing required argument(s) for task 'deploy': target"));
+    }
+
+    /// A task's log file, without the line marking each run
+    fn log_lines(path: &Path) -> Vec<String> {
+        std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .filter(|line| !line.starts_with("--- rnr "))
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_task_log_tees_output() {
+        let temp = tempfile::tempdir().unwrap();
+        std::fs::write(
+            temp.path().join(config::CONFIG_FILE),
+            "build:\n  log: logs/build.log\n  env:\n    API_TOKEN: hunter2-secret\n  steps:\n    - cmd: echo out\n    - cmd: echo err >&2\n    - parallel:\n        - task: inner\n    - cmd: echo $API_TOKEN\ninner:\n  log: inner.log\n  cmd: echo nested\n",
+        )
+        .unwrap();
+        let run = |quiet| {
+            let (ui, capture) = Ui::capture(quiet);
+            let opts = RunOptions {
+                ui,
+                project_root: Some(temp.path().to_path_buf()),
+                ..Default::default()
+            };
+            run_task("build", &[], &opts).unwrap();
+            capture
+        };
+
+        let capture = run(false);
+        assert!(
+            capture.stdout().contains("$ echo out\nout\n"),
+            "{}",
+            capture.stdout()
+        );
+        assert!(capture.stdout().contains("nested\n"));
+        assert!(capture.stdout().contains("hunter2-secret\n"));
+        assert!(capture.stderr().contains("err\n"), "{}", capture.stderr());
+        let logged = [
+            "$ echo out",
+            "out",
+            "$ echo err >&2",
+            "err",
+            "$ echo nested",
+            "nested",
+            "$ echo $API_TOKEN",
+            "****",
+        ];
+        assert_eq!(log_lines(&temp.path().join("logs/build.log")), logged);
+        // The delegated task's own log gets only its output
+        assert_eq!(
+            log_lines(&temp.path().join("inner.log")),
+            ["$ echo nested", "nested"]
+        );
+
+        // Quiet keeps the output out of the console but still logs it
+        let capture = run(true);
+        assert!(!capture.stdout().contains("out"), "{}", capture.stdout());
+        assert!(!capture.stderr().contains("err"), "{}", capture.stderr());
+        let log = log_lines(&temp.path().join("logs/build.log"));
+        assert_eq!(log.len(), logged.len() * 2);
+        assert_eq!(log[logged.len()..], logged);
+    }
+
+    #[test]
+    fn test_task_log_not_opened_in_dry_run() {
+        let temp = tempfile::tempdir().unwrap();
+        std::fs::write(
+            temp.path().join(config::CONFIG_FILE),
+            "build:\n  log: logs/build.log\n  cmd: echo hi\n",
+        )
+        .unwrap();
+        let opts = RunOptions {
+            ui: Ui::capture(false).0,
+            project_root: Some(temp.path().to_path_buf()),
+            dry_run: true,
+            ..Default::default()
+        };
+        run_task("build", &[], &opts).unwrap();
+        assert!(!temp.path().join("logs").exists());
+    }
+
+    #[test]
+    fn test_failure_summary_per_failed_step() {
+        let temp = tempfile::tempdir().unwrap();
+        std::fs::write(
+            temp.path().join(config::CONFIG_FILE),
+            "ci:\n  steps:\n    - cmd: echo ok\n    - parallel:\n        - cmd: exit 3\n        - cmd: echo fine\n        - task: check\ncheck: exit 4\nbuild: exit 5\n",
+        )
+        .unwrap();
+        let (ui, capture) = Ui::capture(false);
+        let opts = RunOptions {
+            ui,
+            project_root: Some(temp.path().to_path_buf()),
+            ..Default::default()
+        };
+
+        assert!(run_task("ci", &[], &opts).is_err());
+        let stderr = capture.stderr();
+        assert!(
+            stderr
+                .contains("\nFAILED ci (rnr.yaml:1) step 2.1 (rnr.yaml:4): $ exit 3\n  command:   exit 3\n  dir:       .\n"),
+            "{}",
+            stderr
+        );
+        assert!(stderr.contains("  exit code: 3\n"), "{}", stderr);
+        assert!(
+            stderr.contains(
+                "\nFAILED ci (rnr.yaml:1) step 2.3 (rnr.yaml:4): task check\n  command:   exit 4\n"
+            ),
+            "{}",
+            stderr
+        );
+        assert!(stderr.contains("  exit code: 4\n"), "{}", stderr);
+        assert_eq!(stderr.matches("FAILED").count(), 2);
+
+        assert!(run_task("build", &[], &opts).is_err());
+        assert!(
+            capture
+                .stderr()
+                .contains("\nFAILED build (rnr.yaml:9)\n  command:   exit 5\n"),
+            "{}",
+            capture.stderr()
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_clean_checkout() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let seen = root.join("seen.txt");
+        std::fs::write(
+            root.join(config::CONFIG_FILE),
+            format!(
+                "release:\n  checkout: clean\n  steps:\n    - cmd: cp version.txt {}\n",
+                seen.display()
+            ),
+        )
+        .unwrap();
+        std::fs::write(root.join("version.txt"), "1\n").unwrap();
+        let git = |args: &[&str]| {
+            let status = Command::new("git")
+                .current_dir(root)
+                .args(["-c", "user.name=dev", "-c", "user.email=dev@example.com"])
+                .args(args)
+                .stdout(Stdio::null())
+                .status()
+                .unwrap();
+            assert!(status.success());
+        };
+        git(&["init", "--quiet"]);
+        git(&["add", "."]);
+        git(&["commit", "--quiet", "-m", "release 1"]);
+        std::fs::write(root.join("version.txt"), "2\n").unwrap();
+
+        let (ui, capture) = Ui::capture(false);
+        let mut opts = RunOptions {
+            ui,
+            project_root: Some(root.to_path_buf()),
+            ..Default::default()
+        };
+        let err = run_task("release", &[], &opts).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("Uncommitted changes to version.txt won't be in the clean checkout of "));
+        assert!(!seen.exists());
+
+        opts.allow_dirty = true;
+        run_task("release", &[], &opts).unwrap();
+        assert_eq!(std::fs::read_to_string(&seen).unwrap(), "1\n");
+        assert!(capture.stdout().contains(&format!(
+            "Checked out {} into .rnr/tmp/release-{}\n",
+            &checkout::resolve_commit(root, None).unwrap()[..12],
+            std::process::id()
+        )));
+        let tmp = root.join(checkout::TMP_DIR);
+        assert_eq!(std::fs::read_dir(&tmp).unwrap().count(), 0);
+        assert!(opts.checkout_root.read().unwrap().is_none());
+
+        opts.dry_run = true;
+        std::fs::remove_file(&seen).unwrap();
+        run_task("release", &[], &opts).unwrap();
+        assert!(capture.stdout().contains("Would check out "));
+        assert_eq!(std::fs::read_dir(&tmp).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_lanes_run_their_steps_in_order() {
+        let yaml = "greet:\n  steps:\n    - parallel:\n        api:\n          - cmd: echo build api\n          - cmd: echo test api\n        web:\n          - cmd: echo build web\n";
+        let (result, stdout) = run_cmd(yaml, &[]);
+        result.unwrap();
+        let api: Vec<&str> = stdout.lines().filter(|l| l.starts_with("[api]")).collect();
+        assert_eq!(
+            api,
+            [
+                "[api] $ echo build api",
+                "[api] build api",
+                "[api] $ echo test api",
+                "[api] test api"
+            ]
+        );
+        assert!(stdout.contains("[web] build web\n"), "{}", stdout);
+    }
+
+    #[test]
+    fn test_lane_failure_names_lane_and_step() {
+        let temp = tempfile::tempdir().unwrap();
+        std::fs::write(
+            temp.path().join(config::CONFIG_FILE),
+            "ci:\n  steps:\n    - parallel:\n        api:\n          - cmd: echo one\n          - cmd: exit 3\n          - cmd: echo never\n        web:\n          - cmd: echo web\n",
+        )
+        .unwrap();
+        let (ui, capture) = Ui::capture(false);
+        let opts = RunOptions {
+            ui,
+            project_root: Some(temp.path().to_path_buf()),
+            ..Default::default()
+        };
+
+        let err = run_task("ci", &[], &opts).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Parallel execution failed with 1 error(s):\n  - lane 'api' step 2 ($ exit 3): Command failed with exit code 3"
+        );
+        assert!(!capture.stdout().contains("never"));
+        assert!(capture.stdout().contains("[web] web\n"));
+
+        let last = history::load(temp.path()).unwrap().unwrap();
+        let steps: Vec<(String, &str, Status)> = last.tasks[0]
+            .steps
+            .iter()
+            .map(|step| (step.position(), step.label.as_str(), step.status))
+            .collect();
+        assert_eq!(
+            steps,
+            [
+                ("1.1".to_string(), "lane api", Status::Failed),
+                ("1.2".to_string(), "lane web", Status::Passed),
+            ]
+        );
+        assert!(capture.stderr().contains(
+            "FAILED ci (rnr.yaml:1) step 1.1 (rnr.yaml:3): lane api\n  command:   exit 3\n"
+        ));
+    }
+
+    #[test]
+    fn test_argv_must_name_a_program() {
+        let (result, _) = run_cmd("greet:\n  cmd: []\n", &[]);
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("Command array is empty"));
+    }
+
+    #[test]
+    #[cfg(not(target_os = "windows"))]
+    fn test_quote_arg() {
+        assert_eq!(quote_arg("--release"), "--release");
+        assert_eq!(quote_arg("hello world"), "'hello world'");
+        assert_eq!(quote_arg("it's"), "'it'\\''s'");
+        assert_eq!(quote_arg(""), "''");
+    }
 }
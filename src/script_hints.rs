@@ -0,0 +1,290 @@
+//! Why a script in the project couldn't be run
+//!
+//! A command that exits 126 or 127, or can't be started at all, usually
+//! gets no more than "Permission denied" or "not found" from the shell. When
+//! the command starts with a file in the project, this looks at the file for
+//! the usual culprits: no executable bit, Windows line endings, a shebang
+//! naming an interpreter that isn't installed, or a script that isn't run
+//! by its path.
+
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// How much of a script is read to look for line endings
+const SNIFF_BYTES: u64 = 4096;
+
+/// Exit codes a shell uses for a command it couldn't run (126) or find (127)
+pub(crate) fn is_spawn_code(code: i32) -> bool {
+    code == 126 || code == 127
+}
+
+/// What to tell someone whose command `line`, run in `work_dir`, couldn't be
+/// started, when it starts with a file inside `project_root`. `path_var` is
+/// the PATH the command ran with.
+pub(crate) fn hints(
+    line: &str,
+    work_dir: &Path,
+    project_root: &Path,
+    path_var: Option<&OsStr>,
+) -> Vec<String> {
+    let Some(program) = first_word(line) else {
+        return Vec::new();
+    };
+    let Some(script) = project_file(&program, work_dir, project_root) else {
+        return Vec::new();
+    };
+    let name = script.display().to_string();
+    if !program.contains(['/', '\\']) {
+        // The file here isn't what ran, so what's wrong with it is beside the point
+        if on_path(&program, path_var) {
+            return Vec::new();
+        }
+        return vec![format!(
+            "'{}' is a file here, but a bare name is looked up on PATH; run it as ./{}",
+            program, program
+        )];
+    }
+    let mut hints = Vec::new();
+    let path = work_dir.join(&script);
+    if !is_executable(&path) {
+        hints.push(format!(
+            "{} is not executable; run: chmod +x {}",
+            name, name
+        ));
+    }
+    let head = read_head(&path);
+    if let Some(interpreter) = shebang(&head) {
+        if let Some(hint) = interpreter_hint(&name, &interpreter, path_var) {
+            hints.push(hint);
+        }
+    }
+    if head.windows(2).any(|pair| pair == b"\r\n") {
+        hints.push(format!(
+            "{} has Windows (CRLF) line endings, which break its shebang and commands; convert it to LF (e.g. dos2unix {})",
+            name, name
+        ));
+    }
+    hints
+}
+
+/// `hints` as lines to append to an error message
+pub(crate) fn render(hints: &[String]) -> String {
+    hints
+        .iter()
+        .map(|hint| format!("\n  hint: {}", hint))
+        .collect()
+}
+
+/// The program `line` runs: its first word after any `NAME=value`
+/// assignments, without quotes
+fn first_word(line: &str) -> Option<String> {
+    line.split_whitespace()
+        .map(|word| word.trim_matches(['\'', '"']))
+        .find(|word| !is_assignment(word))
+        .filter(|word| !word.is_empty())
+        .map(str::to_string)
+}
+
+/// Whether `word` is a shell variable assignment like `RUST_LOG=debug`
+fn is_assignment(word: &str) -> bool {
+    word.split_once('=').is_some_and(|(name, _)| {
+        !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    })
+}
+
+/// `program` as a path relative to `work_dir`, if it names a file inside
+/// `project_root`
+fn project_file(program: &str, work_dir: &Path, project_root: &Path) -> Option<PathBuf> {
+    let path = work_dir.join(program);
+    if !path.is_file() {
+        return None;
+    }
+    let root = project_root.canonicalize().ok()?;
+    path.canonicalize()
+        .ok()
+        .filter(|path| path.starts_with(&root))
+        .map(|_| PathBuf::from(program))
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::metadata(path).is_ok_and(|metadata| metadata.permissions().mode() & 0o111 != 0)
+}
+
+/// Windows runs scripts by their extension, not an executable bit
+#[cfg(not(unix))]
+fn is_executable(_path: &Path) -> bool {
+    true
+}
+
+/// The start of the file at `path`
+fn read_head(path: &Path) -> Vec<u8> {
+    let mut head = Vec::new();
+    if let Ok(file) = fs::File::open(path) {
+        let _ = file.take(SNIFF_BYTES).read_to_end(&mut head);
+    }
+    head
+}
+
+/// The interpreter and its argument on a `#!` line, e.g. `/usr/bin/env
+/// python3`, with a stray `\r` left on
+fn shebang(head: &[u8]) -> Option<String> {
+    let line = head.strip_prefix(b"#!")?;
+    let end = line.iter().position(|&b| b == b'\n').unwrap_or(line.len());
+    let line = String::from_utf8_lossy(&line[..end]);
+    let line = line.trim_start_matches([' ', '\t']);
+    (!line.trim().is_empty()).then(|| line.to_string())
+}
+
+/// What's wrong with `interpreter` from the shebang of `name`, if it can't run
+fn interpreter_hint(name: &str, interpreter: &str, path_var: Option<&OsStr>) -> Option<String> {
+    let mut words = interpreter.split([' ', '\t']).filter(|w| !w.is_empty());
+    let program = words.next()?;
+    if program.ends_with('\r') {
+        // Reported along with the rest of the file's line endings
+        return None;
+    }
+    if !Path::new(program).is_file() {
+        return Some(format!(
+            "the shebang in {} points to {}, which is not installed",
+            name, program
+        ));
+    }
+    let is_env = Path::new(program).file_name() == Some(OsStr::new("env"));
+    let command = words.find(|word| !word.starts_with('-'))?;
+    if !is_env || command.ends_with('\r') || command.contains('=') {
+        return None;
+    }
+    (!on_path(command, path_var)).then(|| {
+        format!(
+            "the shebang in {} runs {} through env, but it isn't on PATH",
+            name, command
+        )
+    })
+}
+
+/// Whether `program` is a file in a directory of `path_var`, or of rnr's own
+/// PATH if the command's isn't known
+fn on_path(program: &str, path_var: Option<&OsStr>) -> bool {
+    let Some(path_var) = path_var
+        .map(OsStr::to_os_string)
+        .or_else(|| env::var_os("PATH"))
+    else {
+        return false;
+    };
+    env::split_paths(&path_var).any(|dir| dir.join(program).is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A project with `scripts/<name>` holding `contents`, made executable
+    /// unless `executable` is false
+    fn script(contents: &str, executable: bool) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("scripts")).unwrap();
+        let path = dir.path().join("scripts/deploy.sh");
+        fs::write(&path, contents).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = if executable { 0o755 } else { 0o644 };
+            fs::set_permissions(&path, fs::Permissions::from_mode(mode)).unwrap();
+        }
+        #[cfg(not(unix))]
+        let _ = executable;
+        dir
+    }
+
+    fn hints_for(dir: &Path, line: &str) -> Vec<String> {
+        hints(line, dir, dir, Some(OsStr::new("/nonexistent")))
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn notices_a_missing_executable_bit() {
+        let dir = script("#!/bin/sh\necho hi\n", false);
+        assert_eq!(
+            hints_for(dir.path(), "./scripts/deploy.sh --prod"),
+            ["./scripts/deploy.sh is not executable; run: chmod +x ./scripts/deploy.sh"]
+        );
+    }
+
+    #[test]
+    fn notices_crlf_line_endings() {
+        let dir = script("#!/bin/sh\r\necho hi\r\n", true);
+        let hints = hints_for(dir.path(), "./scripts/deploy.sh");
+        assert_eq!(hints.len(), 1, "{:?}", hints);
+        assert!(hints[0].contains("Windows (CRLF) line endings"));
+        assert!(hints[0].contains("dos2unix ./scripts/deploy.sh"));
+    }
+
+    #[test]
+    fn notices_a_missing_interpreter() {
+        let dir = script("#!/usr/bin/rnr-no-such-python\nprint('hi')\n", true);
+        assert_eq!(
+            hints_for(dir.path(), "./scripts/deploy.sh"),
+            ["the shebang in ./scripts/deploy.sh points to /usr/bin/rnr-no-such-python, which is not installed"]
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn notices_an_env_interpreter_missing_from_path() {
+        let dir = script("#!/usr/bin/env -S rnr-no-such-python -u\n", true);
+        assert_eq!(
+            hints_for(dir.path(), "./scripts/deploy.sh"),
+            ["the shebang in ./scripts/deploy.sh runs rnr-no-such-python through env, but it isn't on PATH"]
+        );
+        let dir = script("#!/usr/bin/env sh\n", true);
+        let path = env::var_os("PATH");
+        assert!(hints(
+            "./scripts/deploy.sh",
+            dir.path(),
+            dir.path(),
+            path.as_deref()
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn notices_a_script_run_by_its_bare_name() {
+        let dir = script("#!/bin/sh\n", true);
+        let scripts = dir.path().join("scripts");
+        let hints = hints(
+            "deploy.sh",
+            &scripts,
+            dir.path(),
+            Some(OsStr::new("/nonexistent")),
+        );
+        assert_eq!(
+            hints,
+            ["'deploy.sh' is a file here, but a bare name is looked up on PATH; run it as ./deploy.sh"]
+        );
+    }
+
+    #[test]
+    fn skips_assignments_and_quotes() {
+        let dir = script("#!/bin/sh\r\n", true);
+        assert_eq!(
+            hints_for(dir.path(), "RUST_LOG=debug './scripts/deploy.sh' x").len(),
+            1
+        );
+    }
+
+    #[test]
+    fn ignores_files_outside_the_project_and_other_commands() {
+        let dir = script("#!/bin/sh\r\n", false);
+        let app = dir.path().join("app");
+        fs::create_dir(&app).unwrap();
+        assert!(hints("../scripts/deploy.sh", &app, &app, None).is_empty());
+        assert!(hints_for(dir.path(), "cargo test").is_empty());
+        assert!(hints_for(dir.path(), "").is_empty());
+    }
+}
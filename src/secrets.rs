@@ -0,0 +1,242 @@
+//! Detection and masking of secret values
+
+use glob::{MatchOptions, Pattern};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::hash::{BuildHasher, RandomState};
+use std::io::Write;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::checksum;
+use crate::config::{self, Config};
+
+/// File in the cache holding a project's key for secret fingerprints
+const SALT_FILE: &str = "secret-salt";
+
+/// Name fragments that mark an environment variable as secret
+const SECRET_MARKERS: &[&str] = &[
+    "SECRET",
+    "TOKEN",
+    "PASSWORD",
+    "PASSWD",
+    "API_KEY",
+    "APIKEY",
+    "PRIVATE_KEY",
+    "CREDENTIAL",
+    "AUTH",
+];
+
+/// Name patterns whose values `--verbose` and `rnr env` never show
+const HIDDEN_PATTERNS: &[&str] = &["*TOKEN*", "*SECRET*", "*PASSWORD*", "*KEY*"];
+
+/// Values shorter than this are not redacted from text (too likely to collide)
+const MIN_REDACT_LEN: usize = 4;
+
+/// Check whether an environment variable name looks like it holds a secret
+pub fn is_secret_name(name: &str) -> bool {
+    let name = name.to_ascii_uppercase();
+    SECRET_MARKERS.iter().any(|marker| name.contains(marker))
+}
+
+/// Mask a secret value, keeping a short fingerprint so changes are visible.
+/// The fingerprint is keyed with the project's `salt`, so a cached plan
+/// can't be used to test guesses at the value or to link equal secrets.
+pub fn mask(value: &str, salt: &Salt) -> String {
+    let fingerprint = checksum::hmac_sha256(&salt.0, value.as_bytes());
+    format!("**** ({})", &fingerprint[..8])
+}
+
+/// A project's random key for secret fingerprints, kept in `.rnr/cache`
+/// and never shown
+pub struct Salt([u8; 32]);
+
+impl Salt {
+    /// The salt of the project at `root`, made the first time it's needed.
+    /// When it can't be kept, this run's fingerprints are its own.
+    pub fn for_project(root: &Path) -> Self {
+        let path = root.join(config::CACHE_DIR).join(SALT_FILE);
+        if let Ok(bytes) = fs::read(&path) {
+            if let Ok(salt) = <[u8; 32]>::try_from(bytes.as_slice()) {
+                return Salt(salt);
+            }
+        }
+        let salt = Salt::random();
+        if config::create_cache_dir(root).is_ok() {
+            let _ = private_file(&path).and_then(|mut file| file.write_all(&salt.0));
+        }
+        salt
+    }
+
+    /// A new salt, from the OS-seeded keys of the standard library's
+    /// hasher along with the time and process
+    pub fn random() -> Self {
+        let mut hasher = Sha256::new();
+        for n in 0..4u64 {
+            hasher.update(RandomState::new().hash_one(n).to_le_bytes());
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        hasher.update(now.as_nanos().to_le_bytes());
+        hasher.update(std::process::id().to_le_bytes());
+        Salt(hasher.finalize().into())
+    }
+}
+
+/// Create `path` readable only by its owner (on Unix)
+fn private_file(path: &Path) -> std::io::Result<fs::File> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)
+}
+
+/// Which variables to hide when showing an environment: the built-in
+/// patterns, plus a config's `secret_patterns` and `secrets` list
+#[derive(Debug, Clone)]
+pub struct HiddenVars {
+    patterns: Vec<Pattern>,
+    names: Vec<String>,
+}
+
+impl HiddenVars {
+    /// The rules for tasks defined in `config`
+    pub fn for_config(config: &Config) -> Self {
+        let patterns = HIDDEN_PATTERNS
+            .iter()
+            .copied()
+            .chain(config.secret_patterns.iter().map(String::as_str))
+            .filter_map(|pattern| Pattern::new(pattern).ok())
+            .collect();
+        Self {
+            patterns,
+            names: config.secrets.clone(),
+        }
+    }
+
+    /// Whether the value of variable `name` must not be shown
+    pub fn hides(&self, name: &str) -> bool {
+        let options = MatchOptions {
+            case_sensitive: false,
+            ..MatchOptions::new()
+        };
+        self.names.iter().any(|secret| secret == name)
+            || self.patterns.iter().any(|p| p.matches_with(name, options))
+    }
+}
+
+/// Replace secret values from `env` and the process environment in `text`
+pub fn redact(text: &str, env: &HashMap<String, String>) -> String {
+    redact_values(text, &secret_values(env))
+}
+
+/// Values of secret variables in `env` and the process environment that are
+/// long enough to redact
+pub fn secret_values(env: &HashMap<String, String>) -> Vec<String> {
+    let process_env = std::env::vars();
+    env.iter()
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .chain(process_env)
+        .filter(|(name, value)| {
+            value.len() >= MIN_REDACT_LEN
+                && is_secret_name(name)
+                && !config::is_computed_marker(value)
+        })
+        .map(|(_, value)| value)
+        .collect()
+}
+
+/// Replace each of `values` in `text`
+pub fn redact_values(text: &str, values: &[String]) -> String {
+    let mut text = text.to_string();
+    for value in values {
+        if text.contains(value.as_str()) {
+            text = text.replace(value.as_str(), "****");
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checksum::Sha256Hasher;
+
+    #[test]
+    fn test_is_secret_name() {
+        assert!(is_secret_name("GITHUB_TOKEN"));
+        assert!(is_secret_name("db_password"));
+        assert!(is_secret_name("AWS_SECRET_ACCESS_KEY"));
+        assert!(!is_secret_name("NODE_ENV"));
+        assert!(!is_secret_name("PATH"));
+    }
+
+    #[test]
+    fn test_mask_keeps_fingerprint() {
+        let salt = Salt::random();
+        let masked = mask("hunter22", &salt);
+        assert!(masked.starts_with("**** ("));
+        assert!(!masked.contains("hunter22"));
+        assert_eq!(masked, mask("hunter22", &salt));
+        assert_ne!(masked, mask("hunter23", &salt));
+
+        // Without the project's salt, the fingerprint says nothing
+        let mut plain = Sha256Hasher::default();
+        plain.update(b"hunter22");
+        assert!(!masked.contains(&plain.finish()[..8]));
+        assert_ne!(masked, mask("hunter22", &Salt::random()));
+    }
+
+    #[test]
+    fn test_salt_is_kept_per_project() {
+        let a = tempfile::tempdir().unwrap();
+        let b = tempfile::tempdir().unwrap();
+        let first = mask("hunter22", &Salt::for_project(a.path()));
+        assert_eq!(first, mask("hunter22", &Salt::for_project(a.path())));
+        assert_ne!(first, mask("hunter22", &Salt::for_project(b.path())));
+
+        let path = a.path().join(config::CACHE_DIR).join(SALT_FILE);
+        assert_eq!(fs::read(&path).unwrap().len(), 32);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+
+    #[test]
+    fn test_redact() {
+        let mut env = HashMap::new();
+        env.insert("API_TOKEN".to_string(), "s3cr3t-value".to_string());
+        env.insert("MODE".to_string(), "release".to_string());
+        assert_eq!(
+            redact("deploy --token s3cr3t-value --mode release", &env),
+            "deploy --token **** --mode release"
+        );
+
+        // A dry run's description of a computed value isn't the secret
+        let computed = "<computed from: vault read token>".to_string();
+        env.insert("API_TOKEN".to_string(), computed.clone());
+        assert_eq!(
+            redact(&format!("deploy --token {}", computed), &env),
+            format!("deploy --token {}", computed)
+        );
+    }
+
+    #[test]
+    fn test_hidden_vars() {
+        let config: Config =
+            serde_yaml::from_str("secrets: [DB_URL]\nsecret_patterns: ['*_CERT']\n").unwrap();
+        let hidden = HiddenVars::for_config(&config);
+        assert!(hidden.hides("GITHUB_TOKEN"));
+        assert!(hidden.hides("ssh_key_path"));
+        assert!(hidden.hides("DB_URL"));
+        assert!(hidden.hides("TLS_CERT"));
+        assert!(!hidden.hides("DB_URL_2"));
+        assert!(!hidden.hides("NODE_ENV"));
+    }
+}
@@ -0,0 +1,138 @@
+//! Replacing binaries that may be running
+//!
+//! `rnr upgrade` runs from `.rnr/bin/` and overwrites the very file it was
+//! started from. Writing over a running executable fails on Windows and can
+//! corrupt the running image elsewhere, so new binaries are written next to
+//! the old one and swapped in by renaming. The displaced binary is renamed to
+//! `<name>.old` and deleted straight away where the OS allows it, or on the
+//! next invocation otherwise.
+
+use anyhow::{Context, Result};
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::permissions;
+
+/// Suffix for a binary that has been replaced but not yet deleted
+const OLD_SUFFIX: &str = ".old";
+
+/// Suffix for a download that hasn't been swapped in yet
+const NEW_SUFFIX: &str = ".new";
+
+/// Install `contents` at `dest` without writing over the existing file
+pub fn install(dest: &Path, contents: &[u8]) -> Result<()> {
+    let staged = with_suffix(dest, NEW_SUFFIX);
+    fs::write(&staged, contents)
+        .with_context(|| format!("Failed to write {}", staged.display()))?;
+    make_executable(&staged)?;
+
+    let result = swap(&staged, dest);
+    if result.is_err() {
+        let _ = fs::remove_file(&staged);
+    }
+    result
+}
+
+/// Move `staged` into place at `dest`, keeping the original until that succeeds
+fn swap(staged: &Path, dest: &Path) -> Result<()> {
+    if !dest.exists() {
+        return fs::rename(staged, dest)
+            .with_context(|| format!("Failed to move {} into place", dest.display()));
+    }
+
+    let old = with_suffix(dest, OLD_SUFFIX);
+    // A leftover from an earlier upgrade would block the rename on Windows
+    let _ = fs::remove_file(&old);
+    fs::rename(dest, &old).with_context(|| format!("Failed to move {} aside", dest.display()))?;
+
+    if let Err(e) = fs::rename(staged, dest) {
+        // Put the original back so the project keeps a working binary
+        let _ = fs::rename(&old, dest);
+        return Err(e).with_context(|| format!("Failed to move {} into place", dest.display()));
+    }
+
+    // Fails on Windows while the old binary is still running; cleaned up next time
+    let _ = fs::remove_file(&old);
+    Ok(())
+}
+
+/// Whether `name` is a replaced binary waiting to be deleted
+pub fn is_replaced(name: &str) -> bool {
+    name.starts_with("rnr") && name.ends_with(OLD_SUFFIX)
+}
+
+/// Delete `.old` binaries left next to the running executable by an earlier upgrade
+pub fn cleanup_old_binaries() {
+    let Some(dir) = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf))
+    else {
+        return;
+    };
+    cleanup_dir(&dir);
+}
+
+/// Best-effort removal of replaced rnr binaries in `dir`
+fn cleanup_dir(dir: &Path) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if is_replaced(&name) {
+            let _ = fs::remove_file(entry.path());
+        }
+    }
+}
+
+/// `path` with `suffix` appended to its file name
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().map(OsString::from).unwrap_or_default();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// Mark a file as executable on Unix
+fn make_executable(path: &Path) -> Result<()> {
+    permissions::set(path, permissions::EXECUTABLE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_install_replaces_existing() {
+        let temp = tempfile::tempdir().unwrap();
+        let dest = temp.path().join("rnr-linux-amd64");
+        fs::write(&dest, "old").unwrap();
+
+        install(&dest, b"new").unwrap();
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "new");
+        assert!(!with_suffix(&dest, NEW_SUFFIX).exists());
+        assert!(!with_suffix(&dest, OLD_SUFFIX).exists());
+    }
+
+    #[test]
+    fn test_install_fresh() {
+        let temp = tempfile::tempdir().unwrap();
+        let dest = temp.path().join("rnr-linux-amd64");
+        install(&dest, b"new").unwrap();
+        assert_eq!(fs::read_to_string(&dest).unwrap(), "new");
+    }
+
+    #[test]
+    fn test_cleanup_dir() {
+        let temp = tempfile::tempdir().unwrap();
+        fs::write(temp.path().join("rnr-macos-arm64.old"), "").unwrap();
+        fs::write(temp.path().join("rnr-macos-arm64"), "").unwrap();
+        fs::write(temp.path().join("other.old"), "").unwrap();
+
+        cleanup_dir(temp.path());
+        assert!(!temp.path().join("rnr-macos-arm64.old").exists());
+        assert!(temp.path().join("rnr-macos-arm64").exists());
+        assert!(temp.path().join("other.old").exists());
+    }
+}
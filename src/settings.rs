@@ -0,0 +1,581 @@
+//! Defaults for rnr's own flags, from the environment and settings files
+//!
+//! Each setting comes from the first of these that sets it: the command-line
+//! flag, its `RNR_*` environment variable, `settings:` in the project's
+//! rnr.yaml, the user's `~/.config/rnr/settings.yaml`, then rnr's default.
+//! Keys rnr doesn't know are warned about rather than refused, so a file
+//! written for a newer rnr still works.
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde_yaml::{Mapping, Value};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::cli::{Cli, Command};
+use crate::config;
+use crate::limits;
+use crate::suggest;
+use crate::ui::OutputMode;
+
+/// The user's settings file, inside their config directory
+pub const USER_FILE: &str = "rnr/settings.yaml";
+
+/// A setting's name in the settings files, and the flag and environment
+/// variable that set it
+struct Key {
+    name: &'static str,
+    flag: &'static str,
+    env: &'static str,
+}
+
+const COLOR: Key = Key {
+    name: "color",
+    flag: "--color",
+    env: "RNR_COLOR",
+};
+const JOBS: Key = Key {
+    name: "jobs",
+    flag: "--jobs",
+    env: "RNR_JOBS",
+};
+const OUTPUT: Key = Key {
+    name: "output",
+    flag: "--output",
+    env: "RNR_OUTPUT",
+};
+const TIMESTAMPS: Key = Key {
+    name: "timestamps",
+    flag: "--timestamps",
+    env: "RNR_TIMESTAMPS",
+};
+const ETA: Key = Key {
+    name: "eta",
+    flag: "--no-eta",
+    env: "RNR_ETA",
+};
+
+/// Settings rnr knows, in the order `rnr settings` shows them
+const KEYS: &[&Key] = &[&COLOR, &JOBS, &OUTPUT, &TIMESTAMPS, &ETA];
+
+/// The common variable turning color off, honored when `RNR_COLOR` isn't set
+const NO_COLOR_ENV: &str = "NO_COLOR";
+
+/// When output is colored (`--color`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ColorChoice {
+    /// When the terminal supports it
+    #[default]
+    Auto,
+    /// Always, even into a pipe
+    Always,
+    /// Never
+    Never,
+}
+
+/// Where a setting's value came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    /// A command-line flag
+    Flag(&'static str),
+    /// An environment variable
+    Env(&'static str),
+    /// `settings:` in the project's rnr.yaml
+    Project(PathBuf),
+    /// The user's settings file
+    User(PathBuf),
+    /// rnr's default
+    Default,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Flag(flag) => write!(f, "{}", flag),
+            Source::Env(var) => write!(f, "{}", var),
+            Source::Project(path) => write!(f, "settings: in {}", path.display()),
+            Source::User(path) => write!(f, "{}", path.display()),
+            Source::Default => write!(f, "default"),
+        }
+    }
+}
+
+/// A setting's value and where it came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Setting<T> {
+    pub value: T,
+    pub source: Source,
+}
+
+/// The settings in effect for this invocation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Settings {
+    /// When output is colored
+    pub color: Setting<ColorChoice>,
+    /// Most parallel branches run at once; all of them when unset
+    pub jobs: Setting<Option<u32>>,
+    /// How output is laid out
+    pub output: Setting<OutputMode>,
+    /// Whether every line of output starts with a timestamp
+    pub timestamps: Setting<bool>,
+    /// Whether steps are announced with how long they took before
+    pub eta: Setting<bool>,
+    /// Unknown keys in the settings files, to warn about
+    pub warnings: Vec<String>,
+}
+
+impl Settings {
+    /// Each setting's name, value and source, for `rnr settings`
+    pub fn rows(&self) -> Vec<(&'static str, String, &Source)> {
+        let jobs = match self.jobs.value {
+            Some(jobs) => jobs.to_string(),
+            None => "unlimited".to_string(),
+        };
+        vec![
+            ("color", name_of(&self.color.value), &self.color.source),
+            ("jobs", jobs, &self.jobs.source),
+            ("output", name_of(&self.output.value), &self.output.source),
+            (
+                "timestamps",
+                self.timestamps.value.to_string(),
+                &self.timestamps.source,
+            ),
+            ("eta", self.eta.value.to_string(), &self.eta.source),
+        ]
+    }
+}
+
+/// Values of one settings file
+#[derive(Debug, Clone, PartialEq)]
+struct File {
+    path: PathBuf,
+    values: Mapping,
+}
+
+/// Everything below the command line that can set a setting
+#[derive(Debug, Default)]
+pub struct Layers {
+    /// The settings' environment variables that are set
+    env: BTreeMap<&'static str, String>,
+    /// `settings:` in the project's rnr.yaml
+    project: Option<File>,
+    /// The user's settings file
+    user: Option<File>,
+}
+
+impl Layers {
+    /// The layers of this process: its environment, the project's rnr.yaml
+    /// (once `--project-dir` is applied) if `cli` runs a command that reads
+    /// it, and the user's settings file
+    pub fn load(cli: &Cli) -> Result<Self> {
+        let vars = KEYS.iter().map(|key| key.env);
+        let env = vars
+            .chain([NO_COLOR_ENV])
+            .filter_map(|var| Some((var, std::env::var(var).ok().filter(|v| !v.is_empty())?)))
+            .collect();
+        let user = match user_path() {
+            Some(path) if path.exists() => Some(user_file(&path)?),
+            _ => None,
+        };
+        Ok(Self {
+            env,
+            project: reads_project(cli).then(project_file).flatten(),
+            user,
+        })
+    }
+
+    /// Resolve every setting, with the flags given on `cli` first
+    pub fn resolve(&self, cli: &Cli) -> Result<Settings> {
+        let color = match self.resolve_one(&COLOR, cli.color, ColorChoice::Auto, choice)? {
+            // `NO_COLOR` only counts where `RNR_COLOR` would have
+            Setting {
+                source: Source::Project(_) | Source::User(_) | Source::Default,
+                ..
+            } if self.env.contains_key(NO_COLOR_ENV) => Setting {
+                value: ColorChoice::Never,
+                source: Source::Env(NO_COLOR_ENV),
+            },
+            color => color,
+        };
+        let jobs = self.resolve_one(&JOBS, cli.jobs.map(Some), None, |value| {
+            match value.trim().parse::<u32>() {
+                Ok(jobs) if jobs > 0 => Ok(Some(jobs)),
+                _ => Err("expected a whole number of 1 or more".to_string()),
+            }
+        })?;
+        Ok(Settings {
+            color,
+            jobs,
+            output: self.resolve_one(&OUTPUT, cli.output, OutputMode::Auto, choice)?,
+            timestamps: self.resolve_one(
+                &TIMESTAMPS,
+                cli.timestamps.then_some(true),
+                false,
+                boolean,
+            )?,
+            eta: self.resolve_one(&ETA, cli.no_eta.then_some(false), true, boolean)?,
+            warnings: self.warnings(),
+        })
+    }
+
+    /// One setting: `flag` if it was given, or else the first layer that
+    /// sets it, read with `parse`, or else `default`
+    fn resolve_one<T>(
+        &self,
+        key: &Key,
+        flag: Option<T>,
+        default: T,
+        parse: impl Fn(&str) -> Result<T, String>,
+    ) -> Result<Setting<T>> {
+        if let Some(value) = flag {
+            return Ok(Setting {
+                value,
+                source: Source::Flag(key.flag),
+            });
+        }
+        let files = [
+            (&self.project, Source::Project as fn(PathBuf) -> Source),
+            (&self.user, Source::User),
+        ];
+        let from_env = (self.env.get(key.env)).map(|raw| (Some(raw.clone()), Source::Env(key.env)));
+        let from_files = files.into_iter().find_map(|(file, source)| {
+            let file = file.as_ref()?;
+            let value = file.values.get(key.name)?;
+            Some((scalar(value), source(file.path.clone())))
+        });
+        let Some((raw, source)) = from_env.or(from_files) else {
+            return Ok(Setting {
+                value: default,
+                source: Source::Default,
+            });
+        };
+        let raw = raw.with_context(|| {
+            format!(
+                "Invalid {} (from {}): expected a single value",
+                key.name, source
+            )
+        })?;
+        let value = parse(&raw).map_err(|reason| {
+            anyhow::anyhow!(
+                "Invalid {} '{}' (from {}): {}",
+                key.name,
+                raw,
+                source,
+                reason
+            )
+        })?;
+        Ok(Setting { value, source })
+    }
+
+    /// A warning for each key of the settings files rnr doesn't know
+    fn warnings(&self) -> Vec<String> {
+        let names: Vec<&str> = KEYS.iter().map(|key| key.name).collect();
+        let files = [&self.project, &self.user];
+        files
+            .into_iter()
+            .flatten()
+            .flat_map(|file| {
+                file.values.keys().filter_map(|key| {
+                    let key = scalar(key).unwrap_or_default();
+                    if names.contains(&key.as_str()) {
+                        return None;
+                    }
+                    let hint = match suggest::nearest(&key, &names) {
+                        Some(known) => format!("; did you mean '{}'?", known),
+                        None => format!(" (known: {})", names.join(", ")),
+                    };
+                    Some(format!(
+                        "Unknown setting '{}' in {} is ignored{}",
+                        key,
+                        file.path.display(),
+                        hint
+                    ))
+                })
+            })
+            .collect()
+    }
+}
+
+/// A YAML scalar as text, as an environment variable would give it
+fn scalar(value: &Value) -> Option<String> {
+    match value {
+        Value::String(value) => Some(value.clone()),
+        Value::Bool(value) => Some(value.to_string()),
+        Value::Number(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+/// One of the values of a `--flag` taking a choice
+fn choice<T: ValueEnum>(value: &str) -> Result<T, String> {
+    T::from_str(value.trim(), true).map_err(|_| {
+        let names: Vec<String> = T::value_variants().iter().map(|v| name_of(v)).collect();
+        format!("expected one of {}", names.join(", "))
+    })
+}
+
+/// A true or false value
+fn boolean(value: &str) -> Result<bool, String> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "true" | "1" => Ok(true),
+        "false" | "0" => Ok(false),
+        _ => Err("expected true or false".to_string()),
+    }
+}
+
+/// How a `--flag` choice is written
+fn name_of<T: ValueEnum>(value: &T) -> String {
+    value
+        .to_possible_value()
+        .map(|value| value.get_name().to_string())
+        .unwrap_or_default()
+}
+
+/// The user's settings file: under `$XDG_CONFIG_HOME`, or `~/.config`
+pub fn user_path() -> Option<PathBuf> {
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|home| home.join(".config")))?;
+    Some(config_home.join(USER_FILE))
+}
+
+/// Read the user's settings file, which must be a mapping
+fn user_file(path: &Path) -> Result<File> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let values = match serde_yaml::from_str(&content)
+        .with_context(|| format!("Failed to parse {}", path.display()))?
+    {
+        Value::Mapping(values) => values,
+        Value::Null => Mapping::new(),
+        _ => anyhow::bail!("{} should map setting names to values", path.display()),
+    };
+    Ok(File {
+        path: path.to_path_buf(),
+        values,
+    })
+}
+
+/// Whether `cli` runs a command that reads the project's rnr.yaml. The rest
+/// never open it, so a broken rnr.yaml can't stop `rnr --version`.
+fn reads_project(cli: &Cli) -> bool {
+    if cli.version {
+        return false;
+    }
+    match &cli.command {
+        Some(
+            Command::Init(_)
+            | Command::Upgrade(_)
+            | Command::Verify
+            | Command::Version(_)
+            | Command::Docs(_),
+        ) => false,
+        Some(Command::Help(args)) => args.task.is_some(),
+        _ => true,
+    }
+}
+
+/// `settings:` of the project's rnr.yaml. Anything wrong with the file is
+/// left for loading it to report, once a command needs it.
+fn project_file() -> Option<File> {
+    let path = config::find_config_file().ok()?;
+    limits::check_size(&path).ok()?.ok()?;
+    let content = fs::read_to_string(&path).ok()?;
+    limits::check_nodes(&content, limits::MAX_NODES).ok()?;
+    let document: Value = serde_yaml::from_str(&content).ok()?;
+    let values = document.get("settings")?.as_mapping()?.clone();
+    let path = match path.strip_prefix(std::env::current_dir().ok()?) {
+        Ok(relative) => relative.to_path_buf(),
+        Err(_) => path,
+    };
+    Some(File { path, values })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CONFIG_FILE;
+    use clap::Parser;
+
+    fn file(path: &str, yaml: &str) -> Option<File> {
+        Some(File {
+            path: PathBuf::from(path),
+            values: serde_yaml::from_str(yaml).unwrap(),
+        })
+    }
+
+    fn cli(args: &[&str]) -> Cli {
+        Cli::try_parse_from([&["rnr"], args].concat()).unwrap()
+    }
+
+    fn layers(env: &[(&'static str, &str)], project: &str, user: &str) -> Layers {
+        Layers {
+            env: env.iter().map(|(k, v)| (*k, v.to_string())).collect(),
+            project: file(CONFIG_FILE, project),
+            user: file("/home/dev/.config/rnr/settings.yaml", user),
+        }
+    }
+
+    #[test]
+    fn test_each_layer_in_turn() {
+        let user = "jobs: 1\noutput: plain\ntimestamps: true\neta: false\ncolor: never";
+        let project = "jobs: 2\noutput: normal\ntimestamps: false";
+        let env = [(JOBS.env, "3"), (OUTPUT.env, "auto")];
+        let settings = layers(&env, project, user)
+            .resolve(&cli(&["--jobs", "4", "build"]))
+            .unwrap();
+        let user_file = Source::User("/home/dev/.config/rnr/settings.yaml".into());
+        assert_eq!(
+            settings.jobs,
+            Setting {
+                value: Some(4),
+                source: Source::Flag("--jobs")
+            }
+        );
+        assert_eq!(settings.output.value, OutputMode::Auto);
+        assert_eq!(settings.output.source, Source::Env(OUTPUT.env));
+        assert!(!settings.timestamps.value);
+        assert_eq!(
+            settings.timestamps.source,
+            Source::Project(CONFIG_FILE.into())
+        );
+        assert!(!settings.eta.value);
+        assert_eq!(settings.eta.source, user_file);
+        assert_eq!(settings.color.value, ColorChoice::Never);
+        assert_eq!(settings.color.source, user_file);
+
+        // Without the flag, the environment; without that, the project
+        let settings = layers(&env, project, user).resolve(&cli(&[])).unwrap();
+        assert_eq!(settings.jobs.value, Some(3));
+        assert_eq!(settings.jobs.source, Source::Env(JOBS.env));
+        let settings = layers(&[], project, user).resolve(&cli(&[])).unwrap();
+        assert_eq!(settings.jobs.value, Some(2));
+        assert_eq!(settings.jobs.source, Source::Project(CONFIG_FILE.into()));
+    }
+
+    #[test]
+    fn test_defaults() {
+        let settings = Layers::default().resolve(&cli(&[])).unwrap();
+        let rows: Vec<String> = settings
+            .rows()
+            .into_iter()
+            .map(|(key, value, source)| format!("{} {} {}", key, value, source))
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                "color auto default",
+                "jobs unlimited default",
+                "output auto default",
+                "timestamps false default",
+                "eta true default"
+            ]
+        );
+        assert!(settings.warnings.is_empty());
+
+        let settings = Layers::default()
+            .resolve(&cli(&["--no-eta", "--timestamps", "--color=always"]))
+            .unwrap();
+        assert_eq!(settings.eta.source, Source::Flag("--no-eta"));
+        assert!(!settings.eta.value && settings.timestamps.value);
+        assert_eq!(settings.color.value, ColorChoice::Always);
+    }
+
+    #[test]
+    fn test_no_color() {
+        let env = [(NO_COLOR_ENV, "1")];
+        let settings = layers(&env, "color: always", "")
+            .resolve(&cli(&[]))
+            .unwrap();
+        assert_eq!(settings.color.value, ColorChoice::Never);
+        assert_eq!(settings.color.source, Source::Env(NO_COLOR_ENV));
+
+        let env = [(NO_COLOR_ENV, "1"), (COLOR.env, "always")];
+        let settings = layers(&env, "", "").resolve(&cli(&[])).unwrap();
+        assert_eq!(settings.color.value, ColorChoice::Always);
+        let settings = layers(&[(NO_COLOR_ENV, "1")], "", "")
+            .resolve(&cli(&["--color", "always"]))
+            .unwrap();
+        assert_eq!(settings.color.value, ColorChoice::Always);
+    }
+
+    #[test]
+    fn test_unknown_keys_warn() {
+        let settings = layers(&[], "jbos: 2\ncollapse: true", "{}")
+            .resolve(&cli(&[]))
+            .unwrap();
+        assert_eq!(
+            settings.warnings,
+            [
+                "Unknown setting 'jbos' in rnr.yaml is ignored; did you mean 'jobs'?",
+                "Unknown setting 'collapse' in rnr.yaml is ignored (known: color, jobs, output, timestamps, eta)"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_values() {
+        let err = |env: &[(&'static str, &str)], project: &str| {
+            layers(env, project, "")
+                .resolve(&cli(&[]))
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(
+            err(&[(JOBS.env, "0")], ""),
+            "Invalid jobs '0' (from RNR_JOBS): expected a whole number of 1 or more"
+        );
+        assert_eq!(
+            err(&[], "output: fancy"),
+            "Invalid output 'fancy' (from settings: in rnr.yaml): expected one of auto, normal, plain"
+        );
+        assert_eq!(
+            err(&[], "eta: [1]"),
+            "Invalid eta (from settings: in rnr.yaml): expected a single value"
+        );
+        assert_eq!(
+            err(&[(TIMESTAMPS.env, "sometimes")], ""),
+            "Invalid timestamps 'sometimes' (from RNR_TIMESTAMPS): expected true or false"
+        );
+    }
+
+    #[test]
+    fn test_reads_project() {
+        for args in [
+            &["build"][..],
+            &["--list"],
+            &["help", "build"],
+            &["settings"],
+            &[],
+        ] {
+            assert!(reads_project(&cli(args)), "{:?}", args);
+        }
+        for args in [
+            &["--version"][..],
+            &["help"],
+            &["version"],
+            &["verify"],
+            &["init"],
+        ] {
+            assert!(!reads_project(&cli(args)), "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn test_user_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("settings.yaml");
+        fs::write(&path, "# nothing yet\n").unwrap();
+        assert!(user_file(&path).unwrap().values.is_empty());
+        fs::write(&path, "- jobs\n").unwrap();
+        let err = user_file(&path).unwrap_err().to_string();
+        assert!(
+            err.ends_with("should map setting names to values"),
+            "{}",
+            err
+        );
+    }
+}
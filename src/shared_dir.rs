@@ -0,0 +1,94 @@
+//! Spotting parallel branches that work in the same directory
+//!
+//! Two branches running, say, `npm install` in one directory at once can
+//! corrupt what both write. The check is best-effort: it compares where each
+//! branch runs its commands (and the targets of builtin file steps), not
+//! what those commands actually touch.
+
+use std::path::PathBuf;
+
+/// One branch (or lane) of a parallel block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Branch {
+    /// How the branch is named in messages, e.g. `3.1 ($ npm install)`
+    pub name: String,
+    /// Directories its commands run in and paths its builtins write
+    pub paths: Vec<PathBuf>,
+}
+
+/// A path more than one branch works in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SharedDir {
+    pub path: PathBuf,
+    /// Names of the branches sharing it, in block order
+    pub branches: Vec<String>,
+}
+
+/// Paths shared by two or more branches, in the order they first appear
+pub fn find(branches: &[Branch]) -> Vec<SharedDir> {
+    let mut shared: Vec<(PathBuf, Vec<usize>)> = Vec::new();
+    for (index, branch) in branches.iter().enumerate() {
+        for path in &branch.paths {
+            match shared.iter_mut().find(|(seen, _)| seen == path) {
+                Some((_, users)) if users.contains(&index) => {}
+                Some((_, users)) => users.push(index),
+                None => shared.push((path.clone(), vec![index])),
+            }
+        }
+    }
+    shared
+        .into_iter()
+        .filter(|(_, users)| users.len() > 1)
+        .map(|(path, users)| SharedDir {
+            path,
+            branches: users.iter().map(|&i| branches[i].name.clone()).collect(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn branch(name: &str, paths: &[&str]) -> Branch {
+        Branch {
+            name: name.to_string(),
+            paths: paths.iter().map(PathBuf::from).collect(),
+        }
+    }
+
+    #[test]
+    fn test_finds_shared_dirs() {
+        let branches = [
+            branch("1 ($ npm install)", &["/p/web"]),
+            branch("2 ($ cargo build)", &["/p/api"]),
+            branch("3 ($ npm ci)", &["/p/web"]),
+            branch("lane docs", &["/p/docs", "/p/api", "/p/docs"]),
+        ];
+        assert_eq!(
+            find(&branches),
+            [
+                SharedDir {
+                    path: PathBuf::from("/p/web"),
+                    branches: vec!["1 ($ npm install)".into(), "3 ($ npm ci)".into()],
+                },
+                SharedDir {
+                    path: PathBuf::from("/p/api"),
+                    branches: vec!["2 ($ cargo build)".into(), "lane docs".into()],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_distinct_dirs_and_one_branch_are_fine() {
+        let branches = [
+            branch("1", &["/p/web"]),
+            branch("2", &["/p/web/dist"]),
+            branch("3", &[]),
+        ];
+        assert!(find(&branches).is_empty());
+        // A lane running several steps in one directory shares it with no one
+        assert!(find(&[branch("lane api", &["/p/api", "/p/api"])]).is_empty());
+    }
+}
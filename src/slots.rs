@@ -0,0 +1,120 @@
+//! The `--jobs` slots a parallel block's branches share
+//!
+//! A branch takes as many slots as its `weight` while it runs, so one heavy
+//! build can stand in for several small scripts. A weight larger than the
+//! whole pool is taken as the whole pool, which runs the branch alone.
+
+use std::sync::{Condvar, Mutex, PoisonError};
+
+/// A fixed number of slots, taken and given back by branches
+#[derive(Debug)]
+pub struct Slots {
+    capacity: usize,
+    free: Mutex<usize>,
+    freed: Condvar,
+}
+
+/// Slots held by a running branch, given back when dropped
+#[derive(Debug)]
+pub struct Held<'a> {
+    slots: &'a Slots,
+    count: usize,
+}
+
+impl Slots {
+    /// A pool of `capacity` slots (at least one)
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Slots {
+            capacity,
+            free: Mutex::new(capacity),
+            freed: Condvar::new(),
+        }
+    }
+
+    /// Wait until a branch of `weight` fits, then take its slots
+    pub fn take(&self, weight: usize) -> Held<'_> {
+        let count = share(weight, self.capacity);
+        let mut free = self.free.lock().unwrap_or_else(PoisonError::into_inner);
+        while *free < count {
+            free = self
+                .freed
+                .wait(free)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+        *free -= count;
+        Held { slots: self, count }
+    }
+}
+
+/// How many of `capacity` slots a branch of `weight` takes
+pub fn share(weight: usize, capacity: usize) -> usize {
+    weight.clamp(1, capacity.max(1))
+}
+
+impl Drop for Held<'_> {
+    fn drop(&mut self) {
+        let mut free = self
+            .slots
+            .free
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        *free += self.count;
+        self.slots.freed.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    /// Slots not taken right now
+    fn free(slots: &Slots) -> usize {
+        *slots.free.lock().unwrap()
+    }
+
+    #[test]
+    fn test_weights_take_their_share() {
+        assert_eq!(share(0, 4), 1);
+        assert_eq!(share(3, 4), 3);
+        // Heavier than the pool: the branch runs alone
+        assert_eq!(share(9, 4), 4);
+        assert_eq!(share(2, 0), 1);
+
+        let slots = Slots::new(4);
+
+        let build = slots.take(3);
+        assert_eq!(free(&slots), 1);
+        let script = slots.take(1);
+        assert_eq!(free(&slots), 0);
+        drop(build);
+        assert_eq!(free(&slots), 3);
+        drop(script);
+        assert_eq!(free(&slots), 4);
+    }
+
+    #[test]
+    fn test_mixed_weights_never_overbook() {
+        let slots = Slots::new(4);
+        let in_use = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+        thread::scope(|s| {
+            for weight in [4, 1, 1, 1, 2, 3, 1] {
+                let (slots, in_use, peak) = (&slots, &in_use, &peak);
+                s.spawn(move || {
+                    let held = slots.take(weight);
+                    let now = in_use.fetch_add(weight, Ordering::SeqCst) + weight;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    thread::sleep(Duration::from_millis(10));
+                    in_use.fetch_sub(weight, Ordering::SeqCst);
+                    drop(held);
+                });
+            }
+        });
+        assert!(peak.load(Ordering::SeqCst) <= 4);
+        assert_eq!(free(&slots), 4);
+    }
+}
@@ -0,0 +1,498 @@
+//! The raw pass over an rnr.yaml
+//!
+//! serde's typed parse keeps neither unknown keys nor where anything was
+//! written. Everything that needs them (unknown fields, strict mode, the file
+//! and line shown in messages, commands YAML misread for want of quotes)
+//! comes from this one extra pass over the text.
+
+use std::collections::HashMap;
+
+use crate::config::{self, QuotingProblem, UnknownField, SETTINGS, TASK_FIELDS};
+use crate::suggest;
+
+/// What the raw pass found in one rnr.yaml
+#[derive(Debug, Default)]
+pub(crate) struct RawConfig {
+    /// Keys of full task definitions that aren't task fields, in file order
+    pub unknown_fields: Vec<UnknownField>,
+    /// Where each task and its steps are written
+    pub locations: Locations,
+    /// Shorthand tasks YAML read as something other than a command, in
+    /// file order; the config can't be loaded with any
+    pub misread_tasks: Vec<QuotingProblem>,
+    /// Shorthand commands YAML changed from what's written, in file order
+    pub altered_commands: Vec<QuotingProblem>,
+}
+
+impl RawConfig {
+    /// Scan a document that has already parsed as a config, given its text
+    /// and the untyped value it parsed to
+    pub fn scan(content: &str, value: &serde_yaml::Value) -> Self {
+        let locations = Locations::scan(content);
+        let unknown_fields = find_unknown_fields(value, &locations);
+        let (misread_tasks, altered_commands) = find_misquoted(content, value, &locations);
+        RawConfig {
+            unknown_fields,
+            locations,
+            misread_tasks,
+            altered_commands,
+        }
+    }
+}
+
+/// 1-based lines of the tasks in one file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Locations {
+    tasks: HashMap<String, TaskLines>,
+}
+
+/// Where one task is written
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct TaskLines {
+    /// The task's key
+    line: usize,
+    /// Its fields, by name
+    fields: HashMap<String, usize>,
+    /// The `- ` of each entry in its `steps`
+    steps: Vec<usize>,
+}
+
+impl Locations {
+    /// The line the task `name` starts on
+    pub fn task(&self, name: &str) -> Option<usize> {
+        self.tasks.get(name).map(|task| task.line)
+    }
+
+    /// The line step `index` (1-based) of task `name` starts on
+    pub fn step(&self, name: &str, index: usize) -> Option<usize> {
+        let steps = &self.tasks.get(name)?.steps;
+        index.checked_sub(1).and_then(|i| steps.get(i)).copied()
+    }
+
+    /// The line of `field` in task `name`
+    pub fn field(&self, name: &str, field: &str) -> Option<usize> {
+        self.tasks.get(name)?.fields.get(field).copied()
+    }
+
+    /// Find task keys at the start of a line, their fields at the first
+    /// indentation below them, and the items of their block-style `steps`.
+    /// Flow-style (`steps: [a, b]`) steps have no lines of their own.
+    fn scan(content: &str) -> Self {
+        let mut tasks = HashMap::new();
+        let mut current: Option<(String, TaskLines)> = None;
+        let mut field_indent = None;
+        // Indentation of the `steps:` key being read, and of its items
+        let mut steps_indent: Option<usize> = None;
+        let mut item_indent = None;
+
+        for (i, raw) in content.lines().enumerate() {
+            let line = i + 1;
+            let text = raw.trim_start();
+            if text.is_empty() || text.starts_with('#') {
+                continue;
+            }
+            let indent = raw.len() - text.len();
+            if indent == 0 {
+                tasks.extend(current.take());
+                current = mapping_key(text)
+                    .filter(|key| !SETTINGS.contains(&key.as_str()))
+                    .map(|key| {
+                        (
+                            key,
+                            TaskLines {
+                                line,
+                                ..TaskLines::default()
+                            },
+                        )
+                    });
+                field_indent = None;
+                steps_indent = None;
+                continue;
+            }
+            let Some((_, task)) = current.as_mut() else {
+                continue;
+            };
+
+            if let Some(steps) = steps_indent {
+                if (text == "-" || text.starts_with("- ")) && indent >= steps {
+                    if *item_indent.get_or_insert(indent) == indent {
+                        task.steps.push(line);
+                    }
+                    continue;
+                }
+                if item_indent.is_some_and(|items| indent > items) || indent > steps {
+                    continue;
+                }
+                steps_indent = None;
+            }
+
+            if *field_indent.get_or_insert(indent) != indent {
+                continue;
+            }
+            if let Some(key) = mapping_key(text) {
+                if key == "steps" {
+                    steps_indent = Some(indent);
+                    item_indent = None;
+                }
+                task.fields.entry(key).or_insert(line);
+            }
+        }
+        tasks.extend(current);
+        Locations { tasks }
+    }
+}
+
+/// The key of a `key: value` line, unquoted
+fn mapping_key(text: &str) -> Option<String> {
+    split_mapping_key(text).map(|(key, _)| key)
+}
+
+/// The unquoted key of a `key: value` line and the text after its colon
+pub(crate) fn split_mapping_key(text: &str) -> Option<(String, &str)> {
+    if let Some(quote) = text.chars().next().filter(|c| *c == '"' || *c == '\'') {
+        let end = text[1..].find(quote)? + 1;
+        let rest = text[end + 1..].trim_start().strip_prefix(':')?;
+        return Some((text[1..end].to_string(), rest));
+    }
+    let colon = text
+        .char_indices()
+        .find(|&(i, c)| c == ':' && text[i + 1..].chars().next().is_none_or(char::is_whitespace))?
+        .0;
+    let key = text[..colon].trim_end();
+    (!key.is_empty() && !key.starts_with(['-', '{', '[', '?']))
+        .then(|| (key.to_string(), &text[colon + 1..]))
+}
+
+/// Why a value written without quotes doesn't read as the text it looks
+/// like, if it doesn't
+fn quoting_problem(value: &str) -> Option<String> {
+    let first = value.chars().next()?;
+    if "*&!@`%|>{[".contains(first)
+        || value == "-"
+        || value.starts_with(['-', '?']) && value[1..].starts_with(' ')
+    {
+        return Some(format!(
+            "a leading '{}' means something else to YAML",
+            first
+        ));
+    }
+    if value.contains(": ") || value.contains(":\t") {
+        return Some("a ': ' inside it starts a mapping in YAML".to_string());
+    }
+    value
+        .ends_with(':')
+        .then(|| "a ':' at its end starts a mapping in YAML".to_string())
+}
+
+/// What to tell someone whose rnr.yaml failed to parse at `line`, when that
+/// line's value needs quotes: why, and the line quoted
+pub(crate) fn quoting_hint(content: &str, line: usize) -> Option<String> {
+    let raw = content.lines().nth(line.checked_sub(1)?)?;
+    let text = raw.trim();
+    let item = text.strip_prefix("- ").map_or(text, str::trim_start);
+    let (key, rest) = split_mapping_key(item)?;
+    let value = written_value(rest)?;
+    let reason = quoting_problem(value)?;
+    Some(format!(
+        "the value of '{}' needs quotes, because {}; write the line as:\n    {}",
+        key,
+        reason,
+        quoted_line(text, rest, value)
+    ))
+}
+
+/// A value as written after its key, without a comment, unless it's
+/// quoted, a block scalar or on the lines below
+fn written_value(rest: &str) -> Option<&str> {
+    let comment = rest
+        .char_indices()
+        .find(|&(i, c)| c == '#' && (i == 0 || rest[..i].ends_with([' ', '\t'])))
+        .map_or(rest.len(), |(i, _)| i);
+    let value = rest[..comment].trim();
+    let quoted = value.starts_with(['"', '\'']);
+    let block =
+        value.starts_with(['|', '>']) && !value[1..].contains(|c: char| c.is_alphanumeric());
+    (!value.is_empty() && !quoted && !block).then_some(value)
+}
+
+/// `line`, whose value `value` starts `rest`, with the value single-quoted
+pub(crate) fn quoted_line(line: &str, rest: &str, value: &str) -> String {
+    format!(
+        "{} {}",
+        &line[..line.len() - rest.len()],
+        single_quoted(value)
+    )
+}
+
+/// `text` as a single-quoted YAML string
+pub(crate) fn single_quoted(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "''"))
+}
+
+/// Shorthand tasks YAML read as something other than a command (a mapping
+/// with one spaced-out key, a boolean or a number), and shorthand commands
+/// it changed from what's written, like `&name` dropped as an anchor
+fn find_misquoted(
+    content: &str,
+    value: &serde_yaml::Value,
+    locations: &Locations,
+) -> (Vec<QuotingProblem>, Vec<QuotingProblem>) {
+    let (mut misread, mut altered) = (Vec::new(), Vec::new());
+    let serde_yaml::Value::Mapping(root) = value else {
+        return (misread, altered);
+    };
+    let lines: Vec<&str> = content.lines().collect();
+    let line_text = |line: usize| lines.get(line - 1).map_or("", |text| text.trim());
+    for (name, def) in root {
+        let Some(name) = name.as_str().filter(|name| !SETTINGS.contains(name)) else {
+            continue;
+        };
+        let Some(line) = locations.task(name) else {
+            continue;
+        };
+        let text = line_text(line);
+        let Some((_, rest)) = split_mapping_key(text) else {
+            continue;
+        };
+        let problem = |line, message| QuotingProblem {
+            task: name.to_string(),
+            line,
+            message,
+        };
+        match def {
+            serde_yaml::Value::Bool(_) | serde_yaml::Value::Number(_) => {
+                let Some(written) = written_value(rest) else {
+                    continue;
+                };
+                let kind = if def.is_bool() {
+                    "a boolean"
+                } else {
+                    "a number"
+                };
+                misread.push(problem(
+                    line,
+                    format!(
+                        "task '{}' is {} in YAML, not a command; if it's a command, \
+                         write the line as:\n    {}",
+                        name,
+                        kind,
+                        quoted_line(text, rest, written)
+                    ),
+                ));
+            }
+            serde_yaml::Value::Mapping(task) if task.len() == 1 => {
+                let (key, field) = task.iter().next().unwrap();
+                let spaced = key
+                    .as_str()
+                    .filter(|key| key.contains(char::is_whitespace) && !TASK_FIELDS.contains(key));
+                let scalar = !matches!(
+                    field,
+                    serde_yaml::Value::Mapping(_) | serde_yaml::Value::Sequence(_)
+                );
+                let Some(key) = spaced.filter(|_| scalar) else {
+                    continue;
+                };
+                let key_line = locations.field(name, key).unwrap_or(line);
+                let command = match key_line == line {
+                    true => format!("{}: {}", key, yaml_scalar(field)),
+                    false => written_value(line_text(key_line))
+                        .unwrap_or(line_text(key_line))
+                        .to_string(),
+                };
+                misread.push(problem(
+                    key_line,
+                    format!(
+                        "task '{}' is a mapping in YAML, because the ': ' in '{}' starts one; \
+                         if it's a command, write it as:\n    {}: {}",
+                        name,
+                        command,
+                        &text[..text.len() - rest.len() - 1],
+                        single_quoted(&command)
+                    ),
+                ));
+            }
+            serde_yaml::Value::String(parsed) => {
+                let Some(written) = written_value(rest) else {
+                    continue;
+                };
+                // A plain value folded with the lines below it starts the same
+                if parsed.starts_with(written) {
+                    continue;
+                }
+                let reason = quoting_problem(written)
+                    .unwrap_or_else(|| "YAML read it differently".to_string());
+                altered.push(problem(
+                    line,
+                    format!(
+                        "task '{}' runs '{}' rather than '{}', because {}; \
+                         write the line as:\n    {}",
+                        name,
+                        parsed,
+                        written,
+                        reason,
+                        quoted_line(text, rest, written)
+                    ),
+                ));
+            }
+            _ => {}
+        }
+    }
+    (misread, altered)
+}
+
+/// A scalar YAML value as text
+fn yaml_scalar(value: &serde_yaml::Value) -> String {
+    match value {
+        serde_yaml::Value::String(text) => text.clone(),
+        serde_yaml::Value::Null => String::new(),
+        other => serde_yaml::to_string(other)
+            .unwrap_or_default()
+            .trim_end()
+            .to_string(),
+    }
+}
+
+/// The first top-level setting written as a task, and its line: a command
+/// or a mapping with task fields that the setting doesn't accept. A value
+/// the setting accepts is read as the setting.
+pub(crate) fn find_reserved(content: &str, value: &serde_yaml::Value) -> Option<(String, usize)> {
+    let serde_yaml::Value::Mapping(root) = value else {
+        return None;
+    };
+    let task_shaped = |def: &serde_yaml::Value| match def {
+        serde_yaml::Value::String(_) => true,
+        serde_yaml::Value::Mapping(fields) => fields
+            .keys()
+            .filter_map(|key| key.as_str())
+            .any(|field| TASK_FIELDS.contains(&field)),
+        _ => false,
+    };
+    // Free-form mappings take any keys, so only those that make a task run
+    // something count
+    let runs_something = |def: &serde_yaml::Value| {
+        ["cmd", "steps", "task"]
+            .iter()
+            .any(|field| def.get(field).is_some())
+    };
+    let (name, _) = root.iter().find_map(|(name, def)| {
+        let name = name.as_str().filter(|name| SETTINGS.contains(name))?;
+        let reserved = match name {
+            "bins" | "renamed" | "settings" => runs_something(def),
+            _ => !config::fits_setting(name, def) && task_shaped(def),
+        };
+        reserved.then_some((name, def))
+    })?;
+    let line = content
+        .lines()
+        .position(|line| mapping_key(line).as_deref() == Some(name))
+        .map_or(1, |index| index + 1);
+    Some((name.to_string(), line))
+}
+
+/// Keys of full task definitions that aren't task fields, in file order
+fn find_unknown_fields(value: &serde_yaml::Value, locations: &Locations) -> Vec<UnknownField> {
+    let serde_yaml::Value::Mapping(root) = value else {
+        return Vec::new();
+    };
+    let mut unknown = Vec::new();
+    for (name, def) in root {
+        let (Some(name), serde_yaml::Value::Mapping(task)) = (name.as_str(), def) else {
+            continue;
+        };
+        if SETTINGS.contains(&name) {
+            continue;
+        }
+        for field in task.keys().filter_map(|key| key.as_str()) {
+            if !TASK_FIELDS.contains(&field) {
+                unknown.push(UnknownField {
+                    task: name.to_string(),
+                    field: field.to_string(),
+                    suggestion: suggest::nearest(field, TASK_FIELDS),
+                    line: locations.field(name, field),
+                });
+            }
+        }
+    }
+    unknown
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = "\
+# Tasks
+strict: false
+lint: cargo clippy
+
+build:
+  description: Build
+  comand: cargo build
+\"deploy\":
+  dir: app
+  steps:
+    - cmd: make
+    - task: build
+
+    # comment between steps
+    - parallel:
+        - cmd: a
+        - cmd: b
+    -
+      cmd: |
+        echo done
+        - not a step
+  env:
+    MODE: release
+ci:
+  steps:
+  - task: lint
+  - task: deploy
+flow:
+  steps: [a, b]
+";
+
+    #[test]
+    fn test_locations() {
+        let locations = Locations::scan(CONFIG);
+        assert_eq!(locations.task("strict"), None);
+        assert_eq!(locations.task("lint"), Some(3));
+        assert_eq!(locations.task("build"), Some(5));
+        assert_eq!(locations.task("deploy"), Some(8));
+        assert_eq!(locations.task("missing"), None);
+
+        assert_eq!(locations.field("build", "comand"), Some(7));
+        assert_eq!(locations.field("deploy", "env"), Some(22));
+        let steps: Vec<_> = (1..=5).map(|i| locations.step("deploy", i)).collect();
+        assert_eq!(steps, [Some(11), Some(12), Some(15), Some(18), None]);
+        assert_eq!(locations.step("deploy", 0), None);
+
+        // Items may sit at the same indentation as `steps:`
+        assert_eq!(locations.step("ci", 1), Some(26));
+        assert_eq!(locations.step("ci", 2), Some(27));
+        assert_eq!(locations.step("flow", 1), None);
+    }
+
+    #[test]
+    fn test_mapping_key() {
+        assert_eq!(mapping_key("build: make").as_deref(), Some("build"));
+        assert_eq!(mapping_key("build:").as_deref(), Some("build"));
+        assert_eq!(mapping_key("api:build:").as_deref(), Some("api:build"));
+        assert_eq!(mapping_key("'a: b' : x").as_deref(), Some("a: b"));
+        assert_eq!(mapping_key("\"deploy\":").as_deref(), Some("deploy"));
+        assert_eq!(mapping_key("- cmd: make"), None);
+        assert_eq!(mapping_key("just text"), None);
+        assert_eq!(mapping_key("url: http://x").as_deref(), Some("url"));
+    }
+
+    #[test]
+    fn test_unknown_fields_have_lines() {
+        let raw = RawConfig::scan(CONFIG, &serde_yaml::from_str(CONFIG).unwrap());
+        let found: Vec<(&str, &str, Option<usize>)> = raw
+            .unknown_fields
+            .iter()
+            .map(|u| (u.task.as_str(), u.field.as_str(), u.line))
+            .collect();
+        assert_eq!(found, [("build", "comand", Some(7))]);
+    }
+}
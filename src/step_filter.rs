@@ -0,0 +1,176 @@
+//! Running part of a task: `--only`, `--skip`, and `--steps`
+//!
+//! Filters apply to the steps of the task named on the command line, not to
+//! the tasks it delegates to. Steps that only set env always run, since the
+//! steps after them may need what they set.
+
+use anyhow::Result;
+use std::fmt;
+use std::ops::RangeInclusive;
+
+use crate::config::StepDef;
+
+/// Which of a task's own steps run
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StepFilter {
+    /// Run only steps with one of these tags
+    only: Vec<String>,
+    /// Leave out steps with one of these tags
+    skip: Vec<String>,
+    /// Run only the top-level steps in these ranges (1-based)
+    steps: Vec<RangeInclusive<usize>>,
+    /// `--steps` as given, for messages
+    steps_arg: Option<String>,
+}
+
+impl StepFilter {
+    /// A filter from the command line's `--only` and `--skip` tags and
+    /// `--steps` ranges (`2-4`, `1,3`, `5-`)
+    pub fn new(only: &[String], skip: &[String], steps: Option<&str>) -> Result<Self> {
+        let ranges = match steps {
+            Some(steps) => parse_ranges(steps)?,
+            None => Vec::new(),
+        };
+        Ok(StepFilter {
+            only: only.to_vec(),
+            skip: skip.to_vec(),
+            steps: ranges,
+            steps_arg: steps.map(str::to_string),
+        })
+    }
+
+    /// Whether any steps could be filtered out
+    pub fn is_active(&self) -> bool {
+        !self.only.is_empty() || !self.skip.is_empty() || !self.steps.is_empty()
+    }
+
+    /// Whether `step_def`, in top-level step `index`, runs
+    pub fn allows(&self, index: usize, step_def: &StepDef) -> bool {
+        if step_def.sets_env() {
+            return true;
+        }
+        let tags = step_def.tags.as_deref().unwrap_or_default();
+        let tagged = |wanted: &[String]| tags.iter().any(|tag| wanted.contains(tag));
+        self.allows_index(index)
+            && (self.only.is_empty() || tagged(&self.only))
+            && !tagged(&self.skip)
+    }
+
+    /// Whether a lane in top-level step `index` runs: it does, whole, when
+    /// any of its steps is allowed
+    pub fn allows_lane(&self, index: usize, steps: &[StepDef]) -> bool {
+        steps.iter().any(|step_def| self.allows(index, step_def))
+    }
+
+    /// Whether top-level step `index` is in the `--steps` ranges
+    fn allows_index(&self, index: usize) -> bool {
+        self.steps.is_empty() || self.steps.iter().any(|range| range.contains(&index))
+    }
+}
+
+/// The options that filter, e.g. `--only lint --steps 2-4`
+impl fmt::Display for StepFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut options = Vec::new();
+        if !self.only.is_empty() {
+            options.push(format!("--only {}", self.only.join(",")));
+        }
+        if !self.skip.is_empty() {
+            options.push(format!("--skip {}", self.skip.join(",")));
+        }
+        if let Some(steps) = &self.steps_arg {
+            options.push(format!("--steps {}", steps));
+        }
+        f.write_str(&options.join(" "))
+    }
+}
+
+/// Parse comma-separated step numbers and ranges; `5-` runs to the end
+fn parse_ranges(text: &str) -> Result<Vec<RangeInclusive<usize>>> {
+    let invalid = || {
+        anyhow::anyhow!(
+            "Invalid --steps '{}': expected step numbers or ranges like '2-4', '1,3' or '5-'",
+            text
+        )
+    };
+    let number = |part: &str| match part.trim().parse::<usize>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(invalid()),
+    };
+    text.split(',')
+        .map(|part| match part.split_once('-') {
+            None => number(part).map(|n| n..=n),
+            Some((start, end)) if end.trim().is_empty() => number(start).map(|n| n..=usize::MAX),
+            Some((start, end)) => {
+                let (start, end) = (number(start)?, number(end)?);
+                if start > end {
+                    return Err(invalid());
+                }
+                Ok(start..=end)
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step(yaml: &str) -> StepDef {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    fn tags(list: &[&str]) -> Vec<String> {
+        list.iter().map(|tag| tag.to_string()).collect()
+    }
+
+    #[test]
+    fn test_tags() {
+        let lint = step("cmd: cargo clippy\ntags: [lint, fast]\n");
+        let slow = step("cmd: cargo test\ntags: [slow]\n");
+        let untagged = step("cmd: cargo build\n");
+        let env = step("env:\n  MODE: ci\n");
+
+        let only = StepFilter::new(&tags(&["lint"]), &[], None).unwrap();
+        assert!(only.allows(1, &lint));
+        assert!(!only.allows(1, &slow));
+        assert!(!only.allows(1, &untagged));
+        assert!(only.allows(1, &env));
+
+        let skip = StepFilter::new(&[], &tags(&["slow"]), None).unwrap();
+        assert!(skip.allows(1, &lint));
+        assert!(!skip.allows(1, &slow));
+        assert!(skip.allows(1, &untagged));
+
+        let both = StepFilter::new(&tags(&["lint", "slow"]), &tags(&["fast"]), None).unwrap();
+        assert!(!both.allows(1, &lint));
+        assert!(both.allows(1, &slow));
+        assert_eq!(both.to_string(), "--only lint,slow --skip fast");
+
+        let none = StepFilter::default();
+        assert!(!none.is_active());
+        assert!(none.allows(1, &untagged));
+    }
+
+    #[test]
+    fn test_step_ranges() {
+        let untagged = step("cmd: make\n");
+        let filter = StepFilter::new(&[], &[], Some("2-4,7,9-")).unwrap();
+        let allowed: Vec<usize> = (1..=10).filter(|&i| filter.allows(i, &untagged)).collect();
+        assert_eq!(allowed, [2, 3, 4, 7, 9, 10]);
+        assert_eq!(filter.to_string(), "--steps 2-4,7,9-");
+
+        for bad in ["", "0", "4-2", "a-b", "1,,2", "-3"] {
+            let err = StepFilter::new(&[], &[], Some(bad)).unwrap_err();
+            assert!(err.to_string().starts_with("Invalid --steps"), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_lanes_run_whole() {
+        let steps = vec![step("cmd: a\ntags: [lint]\n"), step("cmd: b\n")];
+        let only = StepFilter::new(&tags(&["lint"]), &[], None).unwrap();
+        assert!(only.allows_lane(1, &steps));
+        assert!(!only.allows_lane(1, &steps[1..]));
+    }
+}
@@ -0,0 +1,199 @@
+//! Task name matching: abbreviations, other cases and "did you mean"
+//! suggestions
+
+/// Maximum edit distance for a name to be suggested
+const MAX_SUGGESTION_DISTANCE: usize = 3;
+
+/// Result of resolving an abbreviated (or differently cased) task name
+#[derive(Debug, PartialEq, Eq)]
+pub enum Abbreviation<'a> {
+    /// Exactly one task matches
+    Unique(&'a str),
+    /// Several tasks match (sorted)
+    Ambiguous(Vec<&'a str>),
+    /// Nothing matches
+    None,
+}
+
+/// Resolve an abbreviated task name against the available names.
+///
+/// Prefix matches win (`bu` -> `build`). Without a prefix match, each
+/// `-`, `_`, or `:` separated segment is matched as a prefix of the
+/// corresponding segment in the name (`b-w` -> `build-web`).
+pub fn abbreviation<'a>(input: &str, names: &[&'a str]) -> Abbreviation<'a> {
+    if input.is_empty() {
+        return Abbreviation::None;
+    }
+
+    let mut matches: Vec<&str> = names
+        .iter()
+        .copied()
+        .filter(|name| name.starts_with(input))
+        .collect();
+    if matches.is_empty() {
+        matches = names
+            .iter()
+            .copied()
+            .filter(|name| segments_match(input, name))
+            .collect();
+    }
+    matches.sort_unstable();
+
+    match matches.len() {
+        0 => Abbreviation::None,
+        1 => Abbreviation::Unique(matches[0]),
+        _ => Abbreviation::Ambiguous(matches),
+    }
+}
+
+/// Resolve a task name typed in another case, e.g. `Build` -> `build`
+pub fn case_insensitive<'a>(input: &str, names: &[&'a str]) -> Abbreviation<'a> {
+    let lower = input.to_lowercase();
+    let mut matches: Vec<&str> = names
+        .iter()
+        .copied()
+        .filter(|name| name.to_lowercase() == lower)
+        .collect();
+    matches.sort_unstable();
+
+    match matches.len() {
+        0 => Abbreviation::None,
+        1 => Abbreviation::Unique(matches[0]),
+        _ => Abbreviation::Ambiguous(matches),
+    }
+}
+
+/// Names close to `input`, nearest first
+pub fn did_you_mean<'a>(input: &str, names: &[&'a str]) -> Vec<&'a str> {
+    let mut scored: Vec<(usize, &str)> = names
+        .iter()
+        .map(|name| (levenshtein(input, name), *name))
+        .filter(|(distance, name)| {
+            *distance <= MAX_SUGGESTION_DISTANCE && distance * 2 <= name.chars().count()
+        })
+        .collect();
+    scored.sort_unstable();
+    scored.into_iter().map(|(_, name)| name).collect()
+}
+
+/// The known name closest to a misspelled one, e.g. `comand` -> `cmd`
+///
+/// Unlike [`did_you_mean`], the distance is weighed against the longer of the
+/// two, so dropped letters in a short name still match.
+pub fn nearest<'a>(input: &str, names: &[&'a str]) -> Option<&'a str> {
+    names
+        .iter()
+        .map(|name| (levenshtein(input, name), *name))
+        .filter(|(distance, name)| {
+            let longer = input.chars().count().max(name.chars().count());
+            *distance <= MAX_SUGGESTION_DISTANCE && distance * 2 <= longer
+        })
+        .min()
+        .map(|(_, name)| name)
+}
+
+/// Check each separator-delimited segment of `input` is a prefix of the
+/// matching segment of `name`
+fn segments_match(input: &str, name: &str) -> bool {
+    let is_separator = |c: char| matches!(c, '-' | '_' | ':');
+    let input_segments: Vec<&str> = input.split(is_separator).collect();
+    let name_segments: Vec<&str> = name.split(is_separator).collect();
+    input_segments.len() > 1
+        && input_segments.len() <= name_segments.len()
+        && input_segments
+            .iter()
+            .zip(&name_segments)
+            .all(|(part, segment)| segment.starts_with(part))
+}
+
+/// Edit distance between two strings
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NAMES: &[&str] = &["build", "build-web", "deploy", "lint", "test", "api:test"];
+
+    #[test]
+    fn test_abbreviation_unique_prefix() {
+        assert_eq!(abbreviation("de", NAMES), Abbreviation::Unique("deploy"));
+        assert_eq!(abbreviation("l", NAMES), Abbreviation::Unique("lint"));
+    }
+
+    #[test]
+    fn test_abbreviation_ambiguous_prefix() {
+        assert_eq!(
+            abbreviation("bu", NAMES),
+            Abbreviation::Ambiguous(vec!["build", "build-web"])
+        );
+    }
+
+    #[test]
+    fn test_abbreviation_segments() {
+        assert_eq!(
+            abbreviation("b-w", NAMES),
+            Abbreviation::Unique("build-web")
+        );
+        assert_eq!(abbreviation("a:t", NAMES), Abbreviation::Unique("api:test"));
+        assert_eq!(abbreviation("x-y", NAMES), Abbreviation::None);
+    }
+
+    #[test]
+    fn test_abbreviation_no_match() {
+        assert_eq!(abbreviation("zz", NAMES), Abbreviation::None);
+        assert_eq!(abbreviation("", NAMES), Abbreviation::None);
+    }
+
+    #[test]
+    fn test_case_insensitive() {
+        assert_eq!(
+            case_insensitive("BUILD", NAMES),
+            Abbreviation::Unique("build")
+        );
+        assert_eq!(
+            case_insensitive("Api:Test", NAMES),
+            Abbreviation::Unique("api:test")
+        );
+        assert_eq!(case_insensitive("buil", NAMES), Abbreviation::None);
+        assert_eq!(
+            case_insensitive("build", &["Build", "BUILD", "lint"]),
+            Abbreviation::Ambiguous(vec!["BUILD", "Build"])
+        );
+    }
+
+    #[test]
+    fn test_did_you_mean() {
+        assert_eq!(did_you_mean("biuld", NAMES), vec!["build"]);
+        assert_eq!(did_you_mean("tset", NAMES), vec!["test"]);
+        assert!(did_you_mean("completely-different", NAMES).is_empty());
+    }
+
+    #[test]
+    fn test_nearest() {
+        let fields = &["cmd", "dir", "steps", "description"];
+        assert_eq!(nearest("comand", fields), Some("cmd"));
+        assert_eq!(nearest("step", fields), Some("steps"));
+        assert_eq!(nearest("descripton", fields), Some("description"));
+        assert_eq!(nearest("timeout", fields), None);
+    }
+
+    #[test]
+    fn test_levenshtein() {
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+        assert_eq!(levenshtein("", "abc"), 3);
+        assert_eq!(levenshtein("same", "same"), 0);
+    }
+}
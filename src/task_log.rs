@@ -0,0 +1,440 @@
+//! A task's `log` file: a copy of everything its commands print
+//!
+//! While a task with `log` runs, rnr reads its commands' output and writes
+//! each line to the console and to the file, with secrets redacted. Tasks it
+//! delegates to, and the branches of its parallel blocks, write there too.
+
+use anyhow::{Context, Result};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use crate::config::{FileMode, LogMode, Task};
+use crate::interpolate::{self, Scope};
+use crate::permissions;
+use crate::secrets;
+use crate::ui::Ui;
+
+/// Rotated copies kept when `log_keep` isn't set
+pub const DEFAULT_KEEP: usize = 5;
+
+thread_local! {
+    /// Logs of the tasks running on this thread, outermost first
+    static LOGS: RefCell<Vec<Arc<TaskLog>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Logs that output on this thread goes to
+pub fn current() -> Vec<Arc<TaskLog>> {
+    LOGS.with(|logs| logs.borrow().clone())
+}
+
+/// Run `f` with `logs` as this thread's logs, restoring the previous ones afterwards
+pub fn with_logs<T>(logs: Vec<Arc<TaskLog>>, f: impl FnOnce() -> T) -> T {
+    let previous = LOGS.with(|current| current.replace(logs));
+    let result = f();
+    LOGS.with(|current| *current.borrow_mut() = previous);
+    result
+}
+
+/// An open task log
+pub struct TaskLog {
+    /// The file, relative paths resolved
+    path: PathBuf,
+    /// Rotate before the file would grow past this many bytes
+    max_size: Option<u64>,
+    /// Rotated copies to keep
+    keep: usize,
+    /// Permissions each new file gets
+    mode: Option<FileMode>,
+    /// Where a failure to write is reported
+    ui: Ui,
+    state: Mutex<State>,
+}
+
+/// The open file and how big it is
+struct State {
+    file: Option<File>,
+    size: u64,
+    /// Set after the first failed write, which is the only one reported
+    failed: bool,
+}
+
+impl TaskLog {
+    /// Open the `log` of task `name`, whose path is rendered in `scope` and
+    /// relative to `dir`, starting with a line marking the run. `None` when
+    /// the task has no `log`.
+    pub fn open(
+        name: &str,
+        task: &Task,
+        dir: &Path,
+        scope: Scope,
+        ui: &Ui,
+    ) -> Result<Option<Self>> {
+        let Some(template) = &task.log else {
+            let stray = [
+                ("log_mode", task.log_mode.is_some()),
+                ("log_file_mode", task.log_file_mode.is_some()),
+                ("log_max_size", task.log_max_size.is_some()),
+                ("log_keep", task.log_keep.is_some()),
+            ];
+            if let Some((field, _)) = stray.iter().find(|(_, set)| *set) {
+                anyhow::bail!("'{}' only applies to a task with 'log'", field);
+            }
+            return Ok(None);
+        };
+        let now = SystemTime::now();
+        let rendered = scope
+            .with_time(now)
+            .render(template)
+            .with_context(|| format!("Failed to interpolate log path: {}", template))?;
+        let path = dir.join(rendered);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+
+        let mut options = OpenOptions::new();
+        match task.log_mode.unwrap_or_default() {
+            LogMode::Append => options.append(true),
+            LogMode::Truncate => options.write(true).truncate(true),
+        };
+        // Set at creation too, so the file is never briefly readable by others
+        #[cfg(unix)]
+        if let Some(FileMode(mode)) = task.log_file_mode {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(mode);
+        }
+        let file = options
+            .create(true)
+            .open(&path)
+            .with_context(|| format!("Failed to open log {}", path.display()))?;
+        if let Some(mode) = task.log_file_mode {
+            permissions::set(&path, mode)?;
+        }
+        let size = file.metadata().map_or(0, |metadata| metadata.len());
+        let log = TaskLog {
+            path,
+            max_size: task.log_max_size.map(|size| size.0),
+            keep: task.log_keep.unwrap_or(DEFAULT_KEEP),
+            mode: task.log_file_mode,
+            ui: ui.clone(),
+            state: Mutex::new(State {
+                file: Some(file),
+                size,
+                failed: false,
+            }),
+        };
+        log.write(&format!(
+            "--- rnr {} at {} UTC ---",
+            name,
+            interpolate::format_date("%Y-%m-%d %H:%M:%S", now)
+        ));
+        Ok(Some(log))
+    }
+
+    /// A new, empty file at `path` that rotates to one copy past `max_size`
+    /// bytes, for rnr's own use. It must not exist yet, and only its owner
+    /// can read it.
+    pub fn scratch(path: PathBuf, max_size: u64, ui: &Ui) -> Result<Self> {
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let file = options
+            .open(&path)
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+        Ok(TaskLog {
+            path,
+            max_size: Some(max_size),
+            keep: 1,
+            mode: None,
+            ui: ui.clone(),
+            state: Mutex::new(State {
+                file: Some(file),
+                size: 0,
+                failed: false,
+            }),
+        })
+    }
+
+    /// Where the log is
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Add a line, rotating first if it would take the file past its size.
+    /// Only the first failure is reported; the run carries on without the log.
+    pub fn write(&self, line: &str) {
+        let mut state = self.state.lock().unwrap();
+        if state.failed {
+            return;
+        }
+        if let Err(e) = self.write_line(&mut state, line) {
+            state.failed = true;
+            self.ui.warn(format!(
+                "failed to write to log {}: {}",
+                self.path.display(),
+                e
+            ));
+        }
+    }
+
+    fn write_line(&self, state: &mut State, line: &str) -> io::Result<()> {
+        let len = line.len() as u64 + 1;
+        if self
+            .max_size
+            .is_some_and(|max| state.size > 0 && state.size + len > max)
+        {
+            self.rotate(state)?;
+        }
+        let file = state.file.as_mut().ok_or(io::ErrorKind::NotFound)?;
+        writeln!(file, "{}", line)?;
+        state.size += len;
+        Ok(())
+    }
+
+    /// Move `build.log` to `build.log.1`, `build.log.1` to `build.log.2`, and
+    /// so on, dropping the copy past `keep`, and start a new file
+    fn rotate(&self, state: &mut State) -> io::Result<()> {
+        // Closed first, since an open file can't be renamed on Windows
+        state.file = None;
+        if self.keep > 0 {
+            remove_if_present(&self.rotated(self.keep))?;
+            for n in (1..self.keep).rev() {
+                let from = self.rotated(n);
+                if from.exists() {
+                    fs::rename(&from, self.rotated(n + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.rotated(1))?;
+        }
+        state.file = Some(File::create(&self.path)?);
+        if let Some(mode) = self.mode {
+            permissions::set(&self.path, mode).map_err(io::Error::other)?;
+        }
+        state.size = 0;
+        Ok(())
+    }
+
+    /// The `n`th rotated copy, e.g. `build.log.2`
+    pub fn rotated(&self, n: usize) -> PathBuf {
+        let mut path = OsString::from(self.path.as_os_str());
+        path.push(format!(".{}", n));
+        PathBuf::from(path)
+    }
+}
+
+/// Remove a file, if there is one
+fn remove_if_present(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Copies one command's output to the logs open on this thread
+pub struct Tee {
+    logs: Vec<Arc<TaskLog>>,
+    /// Values redacted from every line
+    secrets: Vec<String>,
+}
+
+impl Tee {
+    /// Tee to this thread's logs, redacting secrets from `env` and the
+    /// process environment
+    pub fn current(env: &HashMap<String, String>) -> Self {
+        let logs = current();
+        let secrets = if logs.is_empty() {
+            Vec::new()
+        } else {
+            secrets::secret_values(env)
+        };
+        Tee { logs, secrets }
+    }
+
+    /// Whether there's any log to write to
+    pub fn is_active(&self) -> bool {
+        !self.logs.is_empty()
+    }
+
+    /// Copy a line, prefixed with the lane it came from
+    pub fn line(&self, lane: Option<&str>, line: &str) {
+        if self.logs.is_empty() {
+            return;
+        }
+        let line = secrets::redact_values(line, &self.secrets);
+        let line = match lane {
+            Some(lane) => format!("[{}] {}", lane, line),
+            None => line,
+        };
+        for log in &self.logs {
+            log.write(&line);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn task(yaml: &str) -> Task {
+        let config: Config = serde_yaml::from_str(yaml).unwrap();
+        match config.tasks.into_values().next().unwrap() {
+            crate::config::TaskDef::Full(task) => *task,
+            other => panic!("expected a full task, got {:?}", other),
+        }
+    }
+
+    fn open(yaml: &str, dir: &Path) -> Result<Option<TaskLog>> {
+        let scope = Scope::new(dir, &HashMap::new());
+        TaskLog::open("build", &task(yaml), dir, scope, &Ui::capture(false).0)
+    }
+
+    /// The file's lines after the run marker
+    fn lines(path: &Path) -> Vec<String> {
+        fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .filter(|line| !line.starts_with("--- rnr"))
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn test_open_creates_dirs_and_appends() {
+        let temp = tempfile::tempdir().unwrap();
+        let yaml = "build:\n  log: logs/${date(%Y)}/build.log\n  cmd: make\n";
+        let log = open(yaml, temp.path()).unwrap().unwrap();
+        log.write("first");
+        let path = log.path().to_path_buf();
+        assert!(path.starts_with(temp.path().join("logs")));
+        assert_eq!(
+            path.parent().unwrap().file_name().unwrap().len(),
+            4,
+            "{}",
+            path.display()
+        );
+        drop(log);
+
+        open(yaml, temp.path()).unwrap().unwrap().write("second");
+        assert_eq!(lines(&path), ["first", "second"]);
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(text.starts_with("--- rnr build at "), "{}", text);
+        assert_eq!(text.matches("--- rnr build").count(), 2);
+
+        let truncate =
+            "build:\n  log: logs/${date(%Y)}/build.log\n  log_mode: truncate\n  cmd: make\n";
+        open(truncate, temp.path()).unwrap().unwrap().write("third");
+        assert_eq!(lines(&path), ["third"]);
+    }
+
+    #[test]
+    fn test_rotation() {
+        let temp = tempfile::tempdir().unwrap();
+        let yaml = "build:\n  log: build.log\n  log_max_size: 64\n  log_keep: 2\n  cmd: make\n";
+        let log = open(yaml, temp.path()).unwrap().unwrap();
+        let path = temp.path().join("build.log");
+        let rotated = |n: usize| temp.path().join(format!("build.log.{}", n));
+
+        // A line that brings the file to exactly 64 bytes still fits
+        let size = fs::metadata(&path).unwrap().len();
+        let fits = "x".repeat((64 - size - 1) as usize);
+        log.write(&fits);
+        assert_eq!(fs::metadata(&path).unwrap().len(), 64);
+        assert!(!rotated(1).exists());
+
+        // One more byte rotates
+        log.write("a");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a\n");
+        assert!(fs::read_to_string(rotated(1))
+            .unwrap()
+            .ends_with(&format!("{}\n", fits)));
+
+        let long = "y".repeat(63);
+        log.write(&long);
+        log.write("b");
+        log.write(&long);
+        assert_eq!(fs::read_to_string(&path).unwrap(), format!("{}\n", long));
+        assert_eq!(fs::read_to_string(rotated(1)).unwrap(), "b\n");
+        assert_eq!(
+            fs::read_to_string(rotated(2)).unwrap(),
+            format!("{}\n", long)
+        );
+        assert!(!rotated(3).exists(), "only log_keep copies are kept");
+    }
+
+    #[test]
+    fn test_rotation_without_copies() {
+        let temp = tempfile::tempdir().unwrap();
+        let yaml = "build:\n  log: build.log\n  log_max_size: 1K\n  log_keep: 0\n  log_mode: truncate\n  cmd: make\n";
+        let log = open(yaml, temp.path()).unwrap().unwrap();
+        let line = "z".repeat(600);
+        log.write(&line);
+        log.write(&line);
+        assert_eq!(lines(&temp.path().join("build.log")), [line]);
+        assert!(!temp.path().join("build.log.1").exists());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_log_file_mode() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = tempfile::tempdir().unwrap();
+        let mode = |name: &str| {
+            let metadata = fs::metadata(temp.path().join(name)).unwrap();
+            metadata.permissions().mode() & 0o777
+        };
+        let yaml = "build:\n  log: build.log\n  log_file_mode: \"0600\"\n  log_max_size: 64\n  cmd: make\n";
+        let log = open(yaml, temp.path()).unwrap().unwrap();
+        assert_eq!(mode("build.log"), 0o600);
+        log.write(&"x".repeat(63));
+        assert_eq!(mode("build.log.1"), 0o600);
+        assert_eq!(mode("build.log"), 0o600);
+    }
+
+    #[test]
+    fn test_log_settings_need_a_log() {
+        let temp = tempfile::tempdir().unwrap();
+        assert!(open("build:\n  cmd: make\n", temp.path())
+            .unwrap()
+            .is_none());
+        let err = open("build:\n  log_keep: 3\n  cmd: make\n", temp.path())
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "'log_keep' only applies to a task with 'log'"
+        );
+    }
+
+    #[test]
+    fn test_tee_redacts_and_prefixes_lanes() {
+        let temp = tempfile::tempdir().unwrap();
+        let log = open("build:\n  log: build.log\n  cmd: make\n", temp.path())
+            .unwrap()
+            .unwrap();
+        let mut env = HashMap::new();
+        env.insert("DEPLOY_TOKEN".to_string(), "tok-123456".to_string());
+        with_logs(vec![Arc::new(log)], || {
+            let tee = Tee::current(&env);
+            assert!(tee.is_active());
+            tee.line(None, "$ deploy --token tok-123456");
+            tee.line(Some("api"), "done");
+        });
+        assert!(!Tee::current(&env).is_active());
+        assert_eq!(
+            lines(&temp.path().join("build.log")),
+            ["$ deploy --token ****", "[api] done"]
+        );
+    }
+}
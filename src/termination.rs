@@ -0,0 +1,305 @@
+//! Winding a run down when rnr is asked to terminate
+//!
+//! CI runners and Kubernetes cancel a job with SIGTERM (on Windows, the
+//! console closing or the machine shutting down). The handler only notes
+//! that it happened; a watcher thread passes SIGTERM on to the commands rnr
+//! is running and kills whatever is still there after the grace period.
+//! Meanwhile no new command starts, the steps that were running are marked
+//! cancelled in the summary, and rnr exits with [`EXIT_CODE`].
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Exit code of a cancelled run: 128 + SIGTERM, as a shell reports it
+pub const EXIT_CODE: i32 = 143;
+
+/// How long commands get to exit after termination is passed on to them,
+/// unless `--term-grace` says otherwise
+pub const DEFAULT_GRACE: Duration = Duration::from_secs(10);
+
+/// How long the run gets to print its summary once the commands are gone
+const WIND_DOWN: Duration = Duration::from_secs(5);
+
+/// How often the watcher looks for a termination request
+const POLL: Duration = Duration::from_millis(50);
+
+/// Set by the signal handler
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// The grace period, in milliseconds
+static GRACE_MS: AtomicU64 = AtomicU64::new(DEFAULT_GRACE.as_millis() as u64);
+
+/// Process ids of the commands running now
+static CHILDREN: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+
+/// Error for a command rnr didn't start, or that was stopped, because the
+/// run is being cancelled
+#[derive(Debug)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Cancelled by {}", sys::NAME)
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// Catch termination requests for the rest of the process, giving running
+/// commands `grace` to exit once they're passed on
+pub fn install(grace: Duration) {
+    GRACE_MS.store(grace.as_millis() as u64, Ordering::SeqCst);
+    if !sys::install() {
+        return;
+    }
+    std::thread::spawn(|| {
+        while !requested() {
+            std::thread::sleep(POLL);
+        }
+        for pid in running() {
+            sys::terminate(pid);
+        }
+        let deadline = Instant::now() + self::grace();
+        while !running().is_empty() && Instant::now() < deadline {
+            std::thread::sleep(POLL);
+        }
+        for pid in running() {
+            sys::kill(pid);
+        }
+        // The run normally exits on its own by now; this is for one stuck
+        // in something other than a command
+        std::thread::sleep(WIND_DOWN);
+        std::process::exit(EXIT_CODE);
+    });
+}
+
+/// Whether termination has been asked for
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
+
+/// Fail with [`Cancelled`] if termination has been asked for, so nothing
+/// new starts
+pub fn check() -> anyhow::Result<()> {
+    match requested() {
+        true => Err(Cancelled.into()),
+        false => Ok(()),
+    }
+}
+
+/// How long commands get to exit once termination is passed on to them
+fn grace() -> Duration {
+    Duration::from_millis(GRACE_MS.load(Ordering::SeqCst))
+}
+
+/// What asked the run to terminate: `SIGTERM`, or on Windows the console closing
+pub fn signal_name() -> &'static str {
+    sys::NAME
+}
+
+/// Treat the run as asked to terminate, as the signal handler does
+pub fn request() {
+    REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// Set `command` up so termination reaches the processes it starts too: on
+/// Unix, outside a terminal, it leads a process group of its own
+pub fn isolate(command: &mut std::process::Command) {
+    sys::isolate(command);
+}
+
+/// A running command that termination is passed on to, until dropped
+pub struct Tracked(u32);
+
+/// Pass termination on to the command with process id `pid` while the
+/// returned guard lives
+pub fn track(pid: u32) -> Tracked {
+    CHILDREN.lock().unwrap_or_else(|e| e.into_inner()).push(pid);
+    // Started just as termination came in, after the watcher passed it on
+    if requested() {
+        sys::terminate(pid);
+    }
+    Tracked(pid)
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        let mut children = CHILDREN.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(index) = children.iter().position(|&pid| pid == self.0) {
+            children.swap_remove(index);
+        }
+    }
+}
+
+/// Process ids of the commands running now
+fn running() -> Vec<u32> {
+    CHILDREN.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+#[cfg(unix)]
+mod sys {
+    use std::io::IsTerminal;
+    use std::os::unix::process::CommandExt;
+    use std::process::Command;
+    use std::sync::OnceLock;
+
+    /// How a termination request is named in messages
+    pub const NAME: &str = "SIGTERM";
+
+    const SIGTERM: i32 = 15;
+    const SIGKILL: i32 = 9;
+    /// `signal(2)`'s error return
+    const SIG_ERR: usize = usize::MAX;
+
+    extern "C" {
+        fn signal(signum: i32, handler: usize) -> usize;
+        #[link_name = "kill"]
+        fn send_signal(pid: i32, sig: i32) -> i32;
+    }
+
+    extern "C" fn on_signal(_signum: i32) {
+        // Only an atomic store: anything more isn't safe in a handler
+        super::request();
+    }
+
+    /// Handle SIGTERM, returning whether it worked
+    pub fn install() -> bool {
+        let handler = on_signal as extern "C" fn(i32) as usize;
+        // SAFETY: the handler only stores to an atomic
+        unsafe { signal(SIGTERM, handler) != SIG_ERR }
+    }
+
+    /// Whether commands get a process group of their own, so the processes
+    /// they start can be signalled with them. Not when stdin is a terminal:
+    /// only the terminal's foreground group gets Ctrl-C and may read from
+    /// it, and there the group rnr is in already ends with rnr.
+    fn grouped() -> bool {
+        static GROUPED: OnceLock<bool> = OnceLock::new();
+        *GROUPED.get_or_init(|| !std::io::stdin().is_terminal())
+    }
+
+    /// Start `command` in a process group of its own, when commands get one
+    pub fn isolate(command: &mut Command) {
+        if grouped() {
+            command.process_group(0);
+        }
+    }
+
+    /// Ask `pid`, and the processes it started, to terminate
+    pub fn terminate(pid: u32) {
+        signal_group(pid, SIGTERM);
+    }
+
+    /// Kill `pid`, and the processes it started, outright
+    pub fn kill(pid: u32) {
+        signal_group(pid, SIGKILL);
+    }
+
+    /// Send `sig` to the process group `pid` leads, or to just `pid` when
+    /// commands share rnr's group
+    fn signal_group(pid: u32, sig: i32) {
+        let target = match grouped() {
+            true => -(pid as i32),
+            false => pid as i32,
+        };
+        // SAFETY: kill(2) only sends a signal; a group that's gone is an
+        // error there's nothing to do about
+        unsafe { send_signal(target, sig) };
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use std::process::{Command, Stdio};
+    use std::time::Duration;
+
+    /// How a termination request is named in messages
+    pub const NAME: &str = "the console closing";
+
+    const CTRL_CLOSE_EVENT: u32 = 2;
+    const CTRL_SHUTDOWN_EVENT: u32 = 6;
+
+    extern "system" {
+        fn SetConsoleCtrlHandler(handler: usize, add: i32) -> i32;
+    }
+
+    /// How long the handler holds off a console closing. Windows ends the
+    /// process about 5 seconds after the close event whatever the handler
+    /// does, so waiting longer would only lose the summary.
+    const CLOSE_LIMIT: Duration = Duration::from_secs(4);
+
+    extern "system" fn on_event(event: u32) -> i32 {
+        if event != CTRL_CLOSE_EVENT && event != CTRL_SHUTDOWN_EVENT {
+            return 0;
+        }
+        super::request();
+        // Windows ends the process when the handler returns, so give the
+        // run its grace period and summary first, as far as it allows
+        let mut wait = super::grace() + Duration::from_secs(2);
+        if event == CTRL_CLOSE_EVENT {
+            wait = wait.min(CLOSE_LIMIT);
+        }
+        std::thread::sleep(wait);
+        1
+    }
+
+    /// Handle console close and shutdown, returning whether it worked
+    pub fn install() -> bool {
+        let handler = on_event as extern "system" fn(u32) -> i32 as usize;
+        // SAFETY: registers a handler that only stores to an atomic and sleeps
+        unsafe { SetConsoleCtrlHandler(handler, 1) != 0 }
+    }
+
+    /// Commands share the console, and so its events, with rnr
+    pub fn isolate(_command: &mut Command) {}
+
+    /// Commands in the console get the close event themselves
+    pub fn terminate(_pid: u32) {}
+
+    /// Kill `pid` and what it started
+    pub fn kill(pid: u32) {
+        let _ = Command::new("taskkill")
+            .args(["/F", "/T", "/PID", &pid.to_string()])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod sys {
+    pub const NAME: &str = "a termination request";
+
+    pub fn install() -> bool {
+        false
+    }
+
+    pub fn isolate(_command: &mut std::process::Command) {}
+
+    pub fn terminate(_pid: u32) {}
+
+    pub fn kill(_pid: u32) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tracks_commands_while_they_run() {
+        let tracked = track(u32::MAX);
+        assert!(running().contains(&u32::MAX));
+        drop(tracked);
+        assert!(!running().contains(&u32::MAX));
+    }
+
+    #[test]
+    fn names_what_cancelled_the_run() {
+        assert_eq!(
+            Cancelled.to_string(),
+            format!("Cancelled by {}", signal_name())
+        );
+    }
+}
@@ -0,0 +1,380 @@
+//! Span recording for `--trace`
+//!
+//! Every task and step run becomes a span. Spans are written as Chrome
+//! trace-event JSON (loadable in chrome://tracing or Perfetto), with each
+//! thread on its own row so parallel branches show up as overlapping bars.
+//! With the `otel` feature, spans are also exported over OTLP/HTTP when
+//! `OTEL_EXPORTER_OTLP_ENDPOINT` is set.
+
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use std::cell::Cell;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime};
+
+use crate::ui::Ui;
+
+thread_local! {
+    /// Span currently running on this thread
+    static CURRENT: Cell<Option<u64>> = const { Cell::new(None) };
+    /// Row this thread is drawn on (0 = not assigned yet)
+    static LANE: Cell<u64> = const { Cell::new(0) };
+}
+
+/// A finished span
+#[derive(Debug, Clone)]
+pub struct Span {
+    pub id: u64,
+    pub parent: Option<u64>,
+    pub name: String,
+    pub category: &'static str,
+    pub lane: u64,
+    /// Microseconds since the tracer started
+    pub start_us: u64,
+    pub end_us: u64,
+    /// Error message if the span failed
+    pub error: Option<String>,
+}
+
+/// Collects spans for a run
+#[derive(Debug)]
+pub struct Tracer {
+    /// Chrome trace file to write, if any
+    output: Option<PathBuf>,
+    started: Instant,
+    #[cfg_attr(not(feature = "otel"), allow(dead_code))]
+    started_wall: SystemTime,
+    spans: Mutex<Vec<Span>>,
+    next_id: AtomicU64,
+    next_lane: AtomicU64,
+}
+
+impl Tracer {
+    /// Create a tracer writing to `output`
+    pub fn new(output: Option<PathBuf>) -> Self {
+        Self {
+            output,
+            started: Instant::now(),
+            started_wall: SystemTime::now(),
+            spans: Mutex::new(Vec::new()),
+            next_id: AtomicU64::new(1),
+            next_lane: AtomicU64::new(1),
+        }
+    }
+
+    /// Tracer for a run: enabled by `--trace`, or by an OTLP endpoint with the `otel` feature
+    pub fn for_run(output: Option<PathBuf>) -> Option<Self> {
+        if output.is_some() || otlp_endpoint().is_some() {
+            Some(Self::new(output))
+        } else {
+            None
+        }
+    }
+
+    /// Spans recorded so far, in completion order
+    pub fn spans(&self) -> Vec<Span> {
+        self.spans.lock().unwrap().clone()
+    }
+
+    /// Write the trace file and export spans, warning on failure
+    pub fn finish(&self, ui: &Ui) {
+        if let Some(path) = &self.output {
+            match self.write_chrome_trace(path) {
+                Ok(()) => ui.notice(format!("Trace written to {}", path.display())),
+                Err(e) => ui.warn(format!("{:#}", e)),
+            }
+        }
+        #[cfg(feature = "otel")]
+        if let Some(endpoint) = otlp_endpoint() {
+            if let Err(e) = self.export_otlp(&endpoint) {
+                ui.warn(format!("failed to export trace: {:#}", e));
+            }
+        }
+    }
+
+    fn lane(&self) -> u64 {
+        LANE.with(|lane| {
+            if lane.get() == 0 {
+                lane.set(self.next_lane.fetch_add(1, Ordering::Relaxed));
+            }
+            lane.get()
+        })
+    }
+
+    fn elapsed_us(&self) -> u64 {
+        self.started.elapsed().as_micros() as u64
+    }
+
+    /// Chrome trace-event JSON for the recorded spans
+    pub fn chrome_trace(&self) -> Value {
+        let events: Vec<Value> = self
+            .spans()
+            .iter()
+            .map(|span| {
+                let mut args = json!({
+                    "status": if span.error.is_some() { "failed" } else { "ok" },
+                });
+                if let Some(parent) = span.parent {
+                    args["parent"] = json!(parent);
+                }
+                if let Some(error) = &span.error {
+                    args["error"] = json!(error);
+                }
+                json!({
+                    "name": span.name,
+                    "cat": span.category,
+                    "ph": "X",
+                    "ts": span.start_us,
+                    "dur": span.end_us - span.start_us,
+                    "pid": 1,
+                    "tid": span.lane,
+                    "id": span.id,
+                    "args": args,
+                })
+            })
+            .collect();
+        json!({ "traceEvents": events, "displayTimeUnit": "ms" })
+    }
+
+    fn write_chrome_trace(&self, path: &std::path::Path) -> Result<()> {
+        let content = serde_json::to_string_pretty(&self.chrome_trace())?;
+        fs::write(path, content)
+            .with_context(|| format!("Failed to write trace: {}", path.display()))
+    }
+
+    /// Send spans to an OTLP/HTTP collector as JSON
+    #[cfg(feature = "otel")]
+    fn export_otlp(&self, endpoint: &str) -> Result<()> {
+        let body = otlp::payload(&self.spans(), self.started_wall);
+        let response = crate::http::client()?
+            .post(endpoint)
+            .json(&body)
+            .send()
+            .with_context(|| format!("Failed to send trace to {}", endpoint))?;
+        if !response.status().is_success() {
+            anyhow::bail!("{} returned HTTP {}", endpoint, response.status());
+        }
+        Ok(())
+    }
+}
+
+/// Run `f` inside a span; without a tracer it just runs `f`
+pub fn span<T>(
+    tracer: Option<&Tracer>,
+    name: &str,
+    category: &'static str,
+    f: impl FnOnce() -> Result<T>,
+) -> Result<T> {
+    let Some(tracer) = tracer else {
+        return f();
+    };
+
+    let id = tracer.next_id.fetch_add(1, Ordering::Relaxed);
+    let parent = CURRENT.with(|current| current.replace(Some(id)));
+    let lane = tracer.lane();
+    let start_us = tracer.elapsed_us();
+
+    let result = f();
+
+    CURRENT.with(|current| current.set(parent));
+    tracer.spans.lock().unwrap().push(Span {
+        id,
+        parent,
+        name: name.to_string(),
+        category,
+        lane,
+        start_us,
+        end_us: tracer.elapsed_us(),
+        error: result.as_ref().err().map(|e| format!("{:#}", e)),
+    });
+    result
+}
+
+/// Span running on the current thread, to hand to spawned threads
+pub fn current_span() -> Option<u64> {
+    CURRENT.with(|current| current.get())
+}
+
+/// Run `f` with `parent` as the current span (used in spawned threads)
+pub fn with_parent<T>(parent: Option<u64>, f: impl FnOnce() -> T) -> T {
+    let previous = CURRENT.with(|current| current.replace(parent));
+    let result = f();
+    CURRENT.with(|current| current.set(previous));
+    result
+}
+
+/// OTLP traces endpoint from the standard environment variables
+fn otlp_endpoint() -> Option<String> {
+    if !cfg!(feature = "otel") {
+        return None;
+    }
+    if let Ok(endpoint) = std::env::var("OTEL_EXPORTER_OTLP_TRACES_ENDPOINT") {
+        return Some(endpoint);
+    }
+    std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT")
+        .ok()
+        .filter(|endpoint| !endpoint.is_empty())
+        .map(|endpoint| format!("{}/v1/traces", endpoint.trim_end_matches('/')))
+}
+
+#[cfg(feature = "otel")]
+mod otlp {
+    //! OTLP/HTTP JSON encoding
+
+    use serde_json::{json, Value};
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use super::Span;
+    use crate::checksum::Sha256Hasher;
+
+    /// Build an `ExportTraceServiceRequest` body
+    pub fn payload(spans: &[Span], started: SystemTime) -> Value {
+        let base_ns = started
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or(Duration::ZERO)
+            .as_nanos() as u64;
+        let trace_id = trace_id(base_ns);
+
+        let spans: Vec<Value> = spans
+            .iter()
+            .map(|span| {
+                let mut value = json!({
+                    "traceId": trace_id,
+                    "spanId": format!("{:016x}", span.id),
+                    "name": span.name,
+                    "kind": 1,
+                    "startTimeUnixNano": (base_ns + span.start_us * 1000).to_string(),
+                    "endTimeUnixNano": (base_ns + span.end_us * 1000).to_string(),
+                    "attributes": [
+                        { "key": "rnr.category", "value": { "stringValue": span.category } },
+                    ],
+                    "status": match &span.error {
+                        Some(error) => json!({ "code": 2, "message": error }),
+                        None => json!({ "code": 1 }),
+                    },
+                });
+                if let Some(parent) = span.parent {
+                    value["parentSpanId"] = json!(format!("{:016x}", parent));
+                }
+                value
+            })
+            .collect();
+
+        json!({
+            "resourceSpans": [{
+                "resource": {
+                    "attributes": [
+                        { "key": "service.name", "value": { "stringValue": "rnr" } },
+                    ],
+                },
+                "scopeSpans": [{
+                    "scope": { "name": "rnr", "version": env!("CARGO_PKG_VERSION") },
+                    "spans": spans,
+                }],
+            }],
+        })
+    }
+
+    /// 128-bit trace id derived from the start time and process id
+    fn trace_id(base_ns: u64) -> String {
+        let mut hasher = Sha256Hasher::default();
+        hasher.update(&base_ns.to_le_bytes());
+        hasher.update(&std::process::id().to_le_bytes());
+        hasher.finish()[..32].to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_span_records_parent_and_status() {
+        let tracer = Tracer::new(None);
+        let result: Result<()> = span(Some(&tracer), "ci", "task", || {
+            span(Some(&tracer), "$ cargo test", "step", || Ok(()))?;
+            span(Some(&tracer), "$ false", "step", || {
+                anyhow::bail!("Command failed with exit code 1")
+            })
+        });
+        assert!(result.is_err());
+
+        let spans = tracer.spans();
+        assert_eq!(spans.len(), 3);
+        let root = spans.iter().find(|s| s.name == "ci").unwrap();
+        assert_eq!(root.parent, None);
+        assert!(root.error.is_some());
+        for child in spans.iter().filter(|s| s.category == "step") {
+            assert_eq!(child.parent, Some(root.id));
+            assert!(child.start_us >= root.start_us && child.end_us <= root.end_us);
+        }
+        assert_eq!(current_span(), None);
+    }
+
+    #[test]
+    fn test_threads_get_own_lanes() {
+        let tracer = Tracer::new(None);
+        span(Some(&tracer), "root", "task", || {
+            let parent = current_span();
+            std::thread::scope(|s| {
+                for name in ["a", "b"] {
+                    let tracer = &tracer;
+                    s.spawn(move || {
+                        with_parent(parent, || span(Some(tracer), name, "step", || Ok(())))
+                    });
+                }
+            });
+            Ok(())
+        })
+        .unwrap();
+
+        let spans = tracer.spans();
+        let root = spans.iter().find(|s| s.name == "root").unwrap();
+        let a = spans.iter().find(|s| s.name == "a").unwrap();
+        let b = spans.iter().find(|s| s.name == "b").unwrap();
+        assert_eq!(a.parent, Some(root.id));
+        assert_eq!(b.parent, Some(root.id));
+        assert_ne!(a.lane, b.lane);
+        assert_ne!(a.lane, root.lane);
+    }
+
+    #[test]
+    fn test_chrome_trace_format() {
+        let tracer = Tracer::new(None);
+        span(Some(&tracer), "build", "task", || Ok(())).unwrap();
+        let trace = tracer.chrome_trace();
+        let event = &trace["traceEvents"][0];
+        assert_eq!(event["name"], "build");
+        assert_eq!(event["ph"], "X");
+        assert_eq!(event["args"]["status"], "ok");
+    }
+
+    #[test]
+    #[cfg(feature = "otel")]
+    fn test_otlp_payload() {
+        let tracer = Tracer::new(None);
+        span(Some(&tracer), "ci", "task", || {
+            span(Some(&tracer), "$ false", "step", || -> Result<()> {
+                anyhow::bail!("boom")
+            })
+        })
+        .unwrap_err();
+
+        let payload = otlp::payload(&tracer.spans(), tracer.started_wall);
+        let spans = &payload["resourceSpans"][0]["scopeSpans"][0]["spans"];
+        let child = &spans[0];
+        let root = &spans[1];
+        assert_eq!(child["parentSpanId"], root["spanId"]);
+        assert_eq!(child["traceId"], root["traceId"]);
+        assert_eq!(child["status"]["code"], 2);
+        assert_eq!(root["traceId"].as_str().unwrap().len(), 32);
+    }
+
+    #[test]
+    fn test_span_without_tracer() {
+        assert_eq!(span(None, "noop", "task", || Ok(42)).unwrap(), 42);
+    }
+}
@@ -0,0 +1,424 @@
+//! Terminal output
+//!
+//! Commands and the runner write through a `Ui` instead of printing
+//! directly, so output can be captured in tests, colors decided in one place,
+//! and progress messages kept apart from warnings and errors.
+//!
+//! `--output=plain` is for log collectors: no colors, and commands' output
+//! read and written a whole line at a time, so parallel steps can't split
+//! each other's lines. `--timestamps` starts every line with the time.
+
+use clap::ValueEnum;
+use console::StyledObject;
+use std::fmt::{self, Display};
+use std::io::{self, IsTerminal, Write};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use crate::interpolate;
+
+/// How output is laid out (`--output`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputMode {
+    /// Plain when `CI=true` and stdout isn't a terminal, normal otherwise
+    #[default]
+    Auto,
+    /// Colors and commands writing straight to the terminal
+    Normal,
+    /// No colors, whole lines only, and labeled parallel steps
+    Plain,
+}
+
+impl OutputMode {
+    /// Whether output is plain, given this process's `CI` and stdout
+    pub fn is_plain(self) -> bool {
+        self.plain_for(
+            std::env::var("CI").ok().as_deref(),
+            io::stdout().is_terminal(),
+        )
+    }
+
+    /// Whether output is plain with `CI` set to `ci` and stdout a terminal or not
+    fn plain_for(self, ci: Option<&str>, terminal: bool) -> bool {
+        match self {
+            OutputMode::Auto => {
+                ci.is_some_and(|ci| ci.eq_ignore_ascii_case("true") || ci == "1") && !terminal
+            }
+            OutputMode::Normal => false,
+            OutputMode::Plain => true,
+        }
+    }
+}
+
+/// Where output goes and how it looks
+#[derive(Clone)]
+pub struct Ui {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    out: Mutex<Stream>,
+    err: Mutex<Stream>,
+    color: bool,
+    quiet: bool,
+    plain: bool,
+    timestamps: bool,
+}
+
+/// One of the streams, and whether the next write starts a line
+struct Stream {
+    writer: Box<dyn Write + Send>,
+    line_start: bool,
+}
+
+impl Ui {
+    /// Write to stdout and stderr
+    pub fn new(color: bool, quiet: bool) -> Self {
+        Self::with_writers(Box::new(io::stdout()), Box::new(io::stderr()), color, quiet)
+    }
+
+    /// Plain output, as `--output=plain` asks: no colors, and every line of
+    /// command output passed on whole. Set up before the Ui is cloned.
+    pub fn plain(mut self) -> Self {
+        if let Some(inner) = Arc::get_mut(&mut self.inner) {
+            inner.color = false;
+            inner.plain = true;
+        }
+        self
+    }
+
+    /// Start every line with the time, as `--timestamps` asks. Set up
+    /// before the Ui is cloned.
+    pub fn with_timestamps(mut self) -> Self {
+        if let Some(inner) = Arc::get_mut(&mut self.inner) {
+            inner.timestamps = true;
+        }
+        self
+    }
+
+    fn with_writers(
+        out: Box<dyn Write + Send>,
+        err: Box<dyn Write + Send>,
+        color: bool,
+        quiet: bool,
+    ) -> Self {
+        let stream = |writer| {
+            Mutex::new(Stream {
+                writer,
+                line_start: true,
+            })
+        };
+        Self {
+            inner: Arc::new(Inner {
+                out: stream(out),
+                err: stream(err),
+                color,
+                quiet,
+                plain: false,
+                timestamps: false,
+            }),
+        }
+    }
+
+    /// Send everything, from both streams, to one writer (no colors)
+    pub fn sink(writer: impl Write + Send + 'static) -> Self {
+        let shared = Arc::new(Mutex::new(writer));
+        Self::with_writers(
+            Box::new(Shared(Arc::clone(&shared))),
+            Box::new(Shared(shared)),
+            false,
+            false,
+        )
+    }
+
+    /// A Ui that records output instead of printing it (no colors)
+    #[cfg(test)]
+    pub fn capture(quiet: bool) -> (Self, Capture) {
+        let capture = Capture::default();
+        let ui = Self::with_writers(
+            Box::new(Shared(Arc::clone(&capture.out))),
+            Box::new(Shared(Arc::clone(&capture.err))),
+            false,
+            quiet,
+        );
+        (ui, capture)
+    }
+
+    /// Whether output is colored
+    pub fn color(&self) -> bool {
+        self.inner.color
+    }
+
+    /// Whether progress output is suppressed (`--quiet`)
+    pub fn quiet(&self) -> bool {
+        self.inner.quiet
+    }
+
+    /// Whether output is plain (`--output=plain`)
+    pub fn is_plain(&self) -> bool {
+        self.inner.plain
+    }
+
+    /// Whether commands' output has to pass through rnr a line at a time,
+    /// to keep lines whole or to timestamp them
+    pub fn line_buffered(&self) -> bool {
+        self.inner.plain || self.inner.timestamps
+    }
+
+    /// Style a value for stdout, honoring the color choice
+    pub fn style<D>(&self, value: D) -> StyledObject<D> {
+        console::style(value).force_styling(self.inner.color)
+    }
+
+    /// Write output exactly as given; never suppressed
+    pub fn print(&self, text: impl Display) {
+        self.write(&self.inner.out, format_args!("{}", text));
+    }
+
+    /// A line of command output on stdout; never suppressed
+    pub fn line(&self, text: impl Display) {
+        self.write(&self.inner.out, format_args!("{}\n", text));
+    }
+
+    /// A progress or status line on stdout
+    pub fn status(&self, text: impl Display) {
+        if !self.inner.quiet {
+            self.write(&self.inner.out, format_args!("{}\n", text));
+        }
+    }
+
+    /// Status text without a line break, to be finished by a later `status`
+    pub fn status_inline(&self, text: impl Display) {
+        if !self.inner.quiet {
+            self.write(&self.inner.out, format_args!("{}", text));
+            let _ = self.inner.out.lock().unwrap().writer.flush();
+        }
+    }
+
+    /// Echo a command before running it
+    pub fn command_echo(&self, command: impl Display) {
+        if !self.inner.quiet {
+            self.write(&self.inner.out, format_args!("$ {}\n", command));
+        }
+    }
+
+    /// Echo a command already formatted by `echo_format`
+    pub fn echo(&self, echo: impl Display) {
+        if !self.inner.quiet {
+            self.write(&self.inner.out, format_args!("{}\n", echo));
+        }
+    }
+
+    /// Echo a command run in a named lane of a parallel block, already
+    /// formatted by `echo_format`
+    pub fn lane_echo(&self, lane: &str, echo: impl Display) {
+        if !self.inner.quiet {
+            let lane = self.style(format!("[{}]", lane)).cyan();
+            self.write(&self.inner.out, format_args!("{} {}\n", lane, echo));
+        }
+    }
+
+    /// A line of output from a command in a named lane; never suppressed
+    pub fn lane_output(&self, lane: &str, line: &str, stderr: bool) {
+        let lane = self.style(format!("[{}]", lane)).cyan();
+        let writer = if stderr {
+            &self.inner.err
+        } else {
+            &self.inner.out
+        };
+        self.write(writer, format_args!("{} {}\n", lane, line));
+    }
+
+    /// An informational line on stderr, kept out of stdout
+    pub fn notice(&self, text: impl Display) {
+        if !self.inner.quiet {
+            self.write(&self.inner.err, format_args!("{}\n", text));
+        }
+    }
+
+    /// A warning on stderr; never suppressed
+    pub fn warn(&self, text: impl Display) {
+        self.write(&self.inner.err, format_args!("Warning: {}\n", text));
+    }
+
+    /// An error on stderr; never suppressed
+    pub fn error(&self, text: impl Display) {
+        self.write(&self.inner.err, format_args!("Error: {}\n", text));
+    }
+
+    /// Text on stderr exactly as given; never suppressed
+    pub fn print_err(&self, text: impl Display) {
+        self.write(&self.inner.err, format_args!("{}", text));
+    }
+
+    fn write(&self, stream: &Mutex<Stream>, args: fmt::Arguments) {
+        let mut stream = stream.lock().unwrap();
+        // Output errors (e.g. a closed pipe) aren't worth failing a task over
+        if !self.inner.timestamps {
+            let _ = stream.writer.write_fmt(args);
+            return;
+        }
+        let text = args.to_string();
+        for line in text.split_inclusive('\n') {
+            if stream.line_start {
+                let _ = write!(stream.writer, "{} ", timestamp(SystemTime::now()));
+            }
+            let _ = stream.writer.write_all(line.as_bytes());
+            stream.line_start = line.ends_with('\n');
+        }
+    }
+}
+
+/// `now` as an RFC 3339 timestamp in UTC
+fn timestamp(now: SystemTime) -> String {
+    interpolate::format_date("%Y-%m-%dT%H:%M:%SZ", now)
+}
+
+impl Default for Ui {
+    fn default() -> Self {
+        Self::new(console::colors_enabled(), false)
+    }
+}
+
+impl fmt::Debug for Ui {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Ui")
+            .field("color", &self.inner.color)
+            .field("quiet", &self.inner.quiet)
+            .field("plain", &self.inner.plain)
+            .field("timestamps", &self.inner.timestamps)
+            .finish()
+    }
+}
+
+/// Output recorded by `Ui::capture`
+#[cfg(test)]
+#[derive(Default)]
+pub struct Capture {
+    out: Arc<Mutex<Vec<u8>>>,
+    err: Arc<Mutex<Vec<u8>>>,
+}
+
+#[cfg(test)]
+impl Capture {
+    /// Everything written to stdout
+    pub fn stdout(&self) -> String {
+        String::from_utf8_lossy(&self.out.lock().unwrap()).into_owned()
+    }
+
+    /// Everything written to stderr
+    pub fn stderr(&self) -> String {
+        String::from_utf8_lossy(&self.err.lock().unwrap()).into_owned()
+    }
+}
+
+/// A writer shared between handles
+struct Shared<W>(Arc<Mutex<W>>);
+
+impl<W: Write> Write for Shared<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.lock().unwrap().flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_streams() {
+        let (ui, capture) = Ui::capture(false);
+        ui.status("Checking...");
+        ui.command_echo("cargo build");
+        ui.line("result");
+        ui.print("raw");
+        ui.notice("Running 'build' (matched 'b')");
+        ui.warn("careful");
+        ui.error("boom");
+        assert_eq!(capture.stdout(), "Checking...\n$ cargo build\nresult\nraw");
+        assert_eq!(
+            capture.stderr(),
+            "Running 'build' (matched 'b')\nWarning: careful\nError: boom\n"
+        );
+    }
+
+    #[test]
+    fn test_quiet_keeps_output_and_problems() {
+        let (ui, capture) = Ui::capture(true);
+        ui.status("Checking...");
+        ui.command_echo("cargo build");
+        ui.echo("[app] $ make");
+        ui.notice("note");
+        ui.print("result\n");
+        ui.warn("careful");
+        assert_eq!(capture.stdout(), "result\n");
+        assert_eq!(capture.stderr(), "Warning: careful\n");
+    }
+
+    #[test]
+    fn test_sink_interleaves_streams() {
+        let buf = Arc::new(Mutex::new(Vec::new()));
+        let ui = Ui::sink(Shared(Arc::clone(&buf)));
+        ui.command_echo("make");
+        ui.warn("slow");
+        ui.status("done");
+        assert_eq!(
+            String::from_utf8_lossy(&buf.lock().unwrap()),
+            "$ make\nWarning: slow\ndone\n"
+        );
+    }
+
+    #[test]
+    fn test_auto_output_is_plain_in_ci_logs() {
+        assert!(OutputMode::Auto.plain_for(Some("true"), false));
+        assert!(OutputMode::Auto.plain_for(Some("1"), false));
+        assert!(!OutputMode::Auto.plain_for(Some("true"), true));
+        assert!(!OutputMode::Auto.plain_for(Some("false"), false));
+        assert!(!OutputMode::Auto.plain_for(None, false));
+        assert!(OutputMode::Plain.plain_for(None, true));
+        assert!(!OutputMode::Normal.plain_for(Some("true"), false));
+    }
+
+    #[test]
+    fn test_timestamps_start_every_line() {
+        let (ui, capture) = Ui::capture(false);
+        let ui = ui.with_timestamps();
+        assert!(ui.line_buffered() && !ui.is_plain());
+        ui.status_inline("Checking... ");
+        ui.status("done");
+        ui.print("two\nlines\n");
+        ui.warn("careful");
+
+        let stamp = |line: &str| {
+            let (stamp, rest) = line.split_once(' ').unwrap();
+            assert_eq!(stamp.len(), "2025-10-15T00:00:00Z".len(), "{}", line);
+            assert!(
+                stamp.ends_with('Z') && stamp.as_bytes()[10] == b'T',
+                "{}",
+                line
+            );
+            rest.to_string()
+        };
+        let stdout: Vec<String> = capture.stdout().lines().map(stamp).collect();
+        assert_eq!(stdout, ["Checking... done", "two", "lines"]);
+        let stderr: Vec<String> = capture.stderr().lines().map(stamp).collect();
+        assert_eq!(stderr, ["Warning: careful"]);
+        let (ui, _) = Ui::capture(false);
+        let ui = ui.plain();
+        assert!(ui.is_plain() && ui.line_buffered() && !ui.color());
+        assert_eq!(
+            timestamp(std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_760_486_400)),
+            "2025-10-15T00:00:00Z"
+        );
+    }
+
+    #[test]
+    fn test_style_without_color() {
+        let (ui, _) = Ui::capture(false);
+        assert_eq!(ui.style("ok").green().to_string(), "ok");
+    }
+}
@@ -0,0 +1,142 @@
+//! rnr version numbers
+//!
+//! Release tags, pinned versions, and rnr.yaml's `min_version` are compared
+//! by semver precedence. Missing parts count as zero, so `0.5` is `0.5.0`.
+
+use std::cmp::Ordering;
+
+/// Whether `text` is a version: numbers separated by dots, optionally with
+/// a leading `v` and a prerelease or build suffix
+pub fn is_version(text: &str) -> bool {
+    let text = text.strip_prefix('v').unwrap_or(text);
+    let core = text.split(['-', '+']).next().unwrap_or_default();
+    let parts: Vec<&str> = core.split('.').collect();
+    parts.len() <= 3
+        && parts
+            .iter()
+            .all(|part| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// Whether `text` is a full semver version, `MAJOR.MINOR.PATCH` with an
+/// optional prerelease and build of letters, digits and hyphens, and no
+/// leading `v`. Safe to use as a file name or in a URL.
+pub fn is_semver(text: &str) -> bool {
+    let identifiers = |text: &str| {
+        text.split('.').all(|part| {
+            !part.is_empty() && part.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-')
+        })
+    };
+    let (text, build) = match text.split_once('+') {
+        Some((text, build)) => (text, Some(build)),
+        None => (text, None),
+    };
+    let (core, pre) = match text.split_once('-') {
+        Some((core, pre)) => (core, Some(pre)),
+        None => (text, None),
+    };
+    let parts: Vec<&str> = core.split('.').collect();
+    parts.len() == 3
+        && parts.iter().all(|part| {
+            !part.is_empty()
+                && part.bytes().all(|b| b.is_ascii_digit())
+                && (part.len() == 1 || !part.starts_with('0'))
+        })
+        && pre.is_none_or(identifiers)
+        && build.is_none_or(identifiers)
+}
+
+/// Whether a version has a prerelease suffix, e.g. `0.5.0-rc.1`
+#[cfg(feature = "network")]
+pub fn is_prerelease(version: &str) -> bool {
+    !split_version(version).1.is_empty()
+}
+
+/// Split a version into its numeric core and prerelease identifiers
+fn split_version(version: &str) -> ([u64; 3], Vec<&str>) {
+    let version = version.strip_prefix('v').unwrap_or(version);
+    // Build metadata doesn't affect precedence
+    let version = version.split('+').next().unwrap_or_default();
+    let (core, pre) = match version.split_once('-') {
+        Some((core, pre)) => (core, pre.split('.').collect()),
+        None => (version, Vec::new()),
+    };
+
+    let mut numbers = [0; 3];
+    for (slot, part) in numbers.iter_mut().zip(core.split('.')) {
+        *slot = part.parse().unwrap_or(0);
+    }
+    (numbers, pre)
+}
+
+/// Order versions by semver precedence, so `0.5.0-rc.1 < 0.5.0-rc.2 < 0.5.0`
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    let (a_core, a_pre) = split_version(a);
+    let (b_core, b_pre) = split_version(b);
+
+    a_core
+        .cmp(&b_core)
+        .then_with(|| match (a_pre.is_empty(), b_pre.is_empty()) {
+            (true, true) => Ordering::Equal,
+            // A release is newer than any of its prereleases
+            (true, false) => Ordering::Greater,
+            (false, true) => Ordering::Less,
+            (false, false) => {
+                for (x, y) in a_pre.iter().zip(&b_pre) {
+                    let ordering = match (x.parse::<u64>(), y.parse::<u64>()) {
+                        (Ok(x), Ok(y)) => x.cmp(&y),
+                        (Ok(_), Err(_)) => Ordering::Less,
+                        (Err(_), Ok(_)) => Ordering::Greater,
+                        (Err(_), Err(_)) => x.cmp(y),
+                    };
+                    if ordering != Ordering::Equal {
+                        return ordering;
+                    }
+                }
+                a_pre.len().cmp(&b_pre.len())
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_short_versions_fill_in_zeros() {
+        assert_eq!(compare_versions("0.5", "0.5.0"), Ordering::Equal);
+        assert_eq!(compare_versions("1", "0.9.9"), Ordering::Greater);
+        assert_eq!(compare_versions("0.5.0-rc.1", "0.5"), Ordering::Less);
+    }
+
+    #[test]
+    fn test_is_version() {
+        for version in ["0.5", "1", "v0.5.1", "0.6.0-rc.1", "1.0.0+build.2"] {
+            assert!(is_version(version), "{}", version);
+        }
+        for text in ["", "latest", "0..5", "0.5.x", "1.2.3.4", "0.5 "] {
+            assert!(!is_version(text), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn test_is_semver() {
+        for version in ["0.5.0", "10.2.31", "0.6.0-rc.1", "1.0.0-x-y+build.2"] {
+            assert!(is_semver(version), "{}", version);
+        }
+        for text in [
+            "",
+            "0.5",
+            "v0.5.0",
+            "01.2.3",
+            "0.5.0-",
+            "0.5.0-rc..1",
+            "../../x",
+            "1.0.0-../../x",
+            "1.0.0+a/b",
+            "1.0.0\\..",
+            "1.0.0 ",
+        ] {
+            assert!(!is_semver(text), "{:?}", text);
+        }
+    }
+}
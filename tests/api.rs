@@ -0,0 +1,81 @@
+//! Using rnr as a library, the way an embedding program would
+
+mod common;
+
+use common::Project;
+use rnr::{Config, Error, Runner, Status, TaskDef};
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+/// A writer that keeps what was written
+#[derive(Clone, Default)]
+struct Output(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Output {
+    fn text(&self) -> String {
+        String::from_utf8_lossy(&self.0.lock().unwrap()).into_owned()
+    }
+}
+
+#[test]
+fn loads_config_from_path() {
+    let project = Project::from_fixture("basic");
+    let config = Config::load_from(&project.path().join("rnr.yaml")).unwrap();
+    assert!(config.task_names().contains(&"hello"));
+    assert!(matches!(
+        config.get_task("hello"),
+        Some(TaskDef::Shorthand(_))
+    ));
+}
+
+#[test]
+fn runs_task_and_reports_steps() {
+    let project = Project::from_fixture("steps");
+    let output = Output::default();
+    let report = Runner::new(project.path())
+        .output(output.clone())
+        .run("sequential")
+        .unwrap();
+
+    assert!(report.success());
+    assert_eq!(report.task, "sequential");
+    assert_eq!(report.steps.len(), 3);
+    assert!(report
+        .steps
+        .iter()
+        .all(|step| step.status == Status::Passed));
+    assert!(output.text().contains("$ echo"));
+}
+
+#[test]
+fn failed_task_is_a_report_not_an_error() {
+    let project = Project::with_config("broken: exit 2\n");
+    let report = Runner::new(project.path())
+        .output(std::io::sink())
+        .run("broken")
+        .unwrap();
+    assert_eq!(report.status, Status::Failed);
+    assert!(report.error.unwrap().contains("exit code 2"));
+}
+
+#[test]
+fn reports_missing_tasks_and_configs() {
+    let project = Project::with_config("build: echo build\n");
+    let runner = Runner::new(project.path()).output(std::io::sink());
+    assert!(matches!(runner.run("deploy"), Err(Error::TaskNotFound(_))));
+
+    let err = Config::load_from(&project.path().join("missing.yaml")).unwrap_err();
+    assert!(matches!(err, Error::ReadConfig { .. }));
+    assert!(err.to_string().contains("missing.yaml"));
+}
@@ -0,0 +1,2558 @@
+//! End-to-end runs of the rnr binary against scratch projects
+
+mod common;
+
+use common::Project;
+use predicates::prelude::PredicateBooleanExt;
+use predicates::str::contains;
+
+#[test]
+fn runs_shorthand_task() {
+    Project::from_fixture("basic")
+        .rnr(["hello"])
+        .success()
+        .stdout(contains("Hello, World!"));
+}
+
+#[test]
+fn runs_steps_in_order() {
+    let output = Project::from_fixture("steps")
+        .rnr(["sequential"])
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let stdout = String::from_utf8(output).unwrap();
+    let positions: Vec<usize> = ["Step 1", "Step 2", "Step 3"]
+        .iter()
+        .map(|step| stdout.find(step).unwrap())
+        .collect();
+    assert!(positions.windows(2).all(|w| w[0] < w[1]), "{}", stdout);
+}
+
+#[test]
+fn runs_parallel_and_delegated_steps() {
+    Project::from_fixture("steps")
+        .rnr(["mixed"])
+        .success()
+        .stdout(contains("Parallel task 1"))
+        .stdout(contains("Parallel task 2"))
+        .stdout(contains("Done"));
+    Project::from_fixture("steps")
+        .rnr(["delegate"])
+        .success()
+        .stdout(contains("Running step C"));
+}
+
+#[test]
+#[cfg(unix)]
+fn plain_output_keeps_parallel_lines_whole() {
+    // Each branch writes its lines in pieces, with the other writing between
+    let project = Project::with_config(
+        "ci:\n  steps:\n    - parallel:\n        - cmd: printf a1; sleep 0.4; printf 'a2\\n'; printf a-tail\n        - cmd: sleep 0.2; printf b1; sleep 0.4; printf 'b2\\n'\n",
+    );
+    let plain_lines = |assert: assert_cmd::assert::Assert| {
+        let output = assert.success().get_output().clone();
+        let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+        let output: Vec<String> = stdout
+            .lines()
+            .filter(|line| !line.contains(" $ "))
+            .map(str::to_string)
+            .collect();
+        output
+    };
+
+    let mut lines = plain_lines(project.rnr(["ci", "--output=plain"]));
+    lines.sort();
+    assert_eq!(lines, ["[ci:1.1] a-tail", "[ci:1.1] a1a2", "[ci:1.2] b1b2"]);
+
+    // CI logs get plain output without asking
+    let mut lines = plain_lines(project.command().arg("ci").env("CI", "true").assert());
+    lines.sort();
+    assert_eq!(lines, ["[ci:1.1] a-tail", "[ci:1.1] a1a2", "[ci:1.2] b1b2"]);
+
+    let lines = plain_lines(project.rnr(["ci", "--output=plain", "--timestamps"]));
+    assert_eq!(lines.len(), 3);
+    for line in lines {
+        let (stamp, rest) = line.split_once(' ').unwrap();
+        assert!(stamp.ends_with('Z') && stamp.contains('T'), "{}", line);
+        assert!(["[ci:1.1] a-tail", "[ci:1.1] a1a2", "[ci:1.2] b1b2"].contains(&rest));
+    }
+}
+
+#[test]
+fn runs_named_parallel_lanes() {
+    Project::with_config(
+        "ci:\n  steps:\n    - parallel:\n        api:\n          - cmd: echo building api\n          - cmd: echo testing api\n        web:\n          - cmd: echo building web\n          - cmd: exit 2\n          - cmd: echo unreachable\n",
+    )
+    .rnr(["ci"])
+    .code(1)
+    .stdout(contains("[api] building api\n"))
+    .stdout(contains("[api] testing api\n"))
+    .stdout(contains("[web] building web\n"))
+    .stdout(contains("unreachable").not())
+    .stderr(contains("lane 'web' step 2 ($ exit 2)"));
+}
+
+#[test]
+fn failing_command_exits_non_zero() {
+    Project::with_config(
+        "broken: exit 3\nafter:\n  steps:\n    - task: broken\n    - cmd: echo unreachable\n",
+    )
+    .rnr(["after"])
+    .code(1)
+    .stderr(contains("exit code 3"))
+    .stdout(contains("unreachable").not());
+}
+
+#[test]
+fn summarizes_failed_steps_at_the_end() {
+    Project::with_config(
+        "ci:\n  steps:\n    - cmd: echo first\n    - cmd: exit 7\n      dir: app\n    - cmd: echo unreachable\n",
+    )
+    .file("app/.keep", "")
+    .rnr(["ci"])
+    .code(1)
+    .stderr(contains("FAILED ci (rnr.yaml:1) step 2 (rnr.yaml:4): $ exit 7"))
+    .stderr(contains("  command:   exit 7\n  dir:       app\n"))
+    .stderr(contains("  exit code: 7\n"));
+}
+
+#[test]
+fn step_descriptions_name_steps_in_summaries() {
+    let long = format!("echo {} && exit 5", "x".repeat(200));
+    let project = Project::with_config(&format!(
+        "ci:\n  steps:\n    - description: Integration tests\n      cmd: exit 4\n    - cmd: echo unreachable\nlong:\n  steps:\n    - cmd: {}\n",
+        long
+    ));
+    project.rnr(["ci"]).code(1).stderr(contains(
+        "FAILED ci (rnr.yaml:1) step 1 (rnr.yaml:3): Integration tests\n  command:   exit 4\n",
+    ));
+    project
+        .rnr(["last"])
+        .success()
+        .stdout(contains("1  FAILED   Integration tests"))
+        .stdout(contains("2  not run  $ echo unreachable"));
+
+    // Without a description, a long command is cut to the terminal's width,
+    // unless --verbose asks for all of it
+    project
+        .command()
+        .arg("long")
+        .env("COLUMNS", "80")
+        .assert()
+        .code(1)
+        .stderr(contains("(rnr.yaml:8): $ echo xxx"))
+        .stderr(contains("x…\n"))
+        .stderr(contains(format!("  command:   {}\n", long)));
+    project
+        .command()
+        .args(["long", "--verbose"])
+        .env("COLUMNS", "80")
+        .assert()
+        .code(1)
+        .stderr(contains(format!("(rnr.yaml:8): $ {}\n", long)));
+}
+
+#[test]
+#[cfg(unix)]
+fn large_output_is_held_within_the_buffer() {
+    // 100MB of lines and a 10MB line without breaks, held back by quiet-success
+    let project = Project::with_config(
+        "output_buffer: 64K\nflood:\n  output_filter: quiet-success\n  cmd: yes 0123456789abcdefghijklmnopqrstuvwxyz | head -c 100000000; head -c 10000000 /dev/zero | tr '\\0' x; echo; echo last; exit 1\n",
+    );
+    let tmp = project.path().join("tmp");
+    std::fs::create_dir(&tmp).unwrap();
+    let output = project
+        .command()
+        .arg("flood")
+        .env("TMPDIR", &tmp)
+        .assert()
+        .failure()
+        .get_output()
+        .clone();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.len() < 128 * 1024, "{} bytes shown", stdout.len());
+    assert!(stdout.ends_with("\nlast\n"));
+
+    let note = stdout
+        .lines()
+        .find(|line| line.starts_with("... "))
+        .unwrap();
+    assert!(note.contains(" earlier lines not shown; all of the output is in "));
+    let spilled = std::fs::read_dir(&tmp)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .find(|path| note.ends_with(&*path.to_string_lossy()))
+        .unwrap();
+    assert!(std::fs::metadata(spilled).unwrap().len() > 110_000_000);
+}
+
+#[test]
+#[cfg(unix)]
+fn runs_argument_arrays_without_a_shell() {
+    Project::with_config(
+        "greet:\n  env:\n    WHO: world\n  cmd: [printf, '%s|', 'hello ${env.WHO}', $HOME, \"it's\"]\n",
+    )
+    .rnr(["greet", "a b"])
+    .success()
+    .stdout(contains(
+        "$ printf '%s|' 'hello world' '$HOME' 'it'\\''s' 'a b'\n",
+    ))
+    .stdout(contains("hello world|$HOME|it's|a b|"));
+}
+
+#[test]
+fn argument_arrays_name_a_missing_program() {
+    Project::with_config("missing:\n  cmd: [rnr-no-such-program, --version]\n")
+        .rnr(["missing"])
+        .code(1)
+        .stderr(contains("Program 'rnr-no-such-program' not found on PATH"));
+}
+
+#[test]
+#[cfg(unix)]
+fn scripts_that_cannot_run_get_hints() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let project = Project::with_config(
+        "plain: ./scripts/plain.sh\ncrlf: ./scripts/crlf.sh\npython: ./scripts/tool.py\ndirect:\n  cmd: [./scripts/plain.sh]\nbare:\n  dir: scripts\n  cmd: plain.sh\n",
+    )
+    .file("scripts/plain.sh", "#!/bin/sh\necho hi\n")
+    .file("scripts/crlf.sh", "#!/bin/sh\r\necho hi\r\n")
+    .file("scripts/tool.py", "#!/usr/bin/rnr-no-such-python\nprint('hi')\n");
+    for script in ["crlf.sh", "tool.py"] {
+        let path = project.path().join("scripts").join(script);
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    project
+        .rnr(["plain"])
+        .code(1)
+        .stderr(contains("exit code 126"))
+        .stderr(contains(
+            "hint: ./scripts/plain.sh is not executable; run: chmod +x ./scripts/plain.sh",
+        ));
+    project.rnr(["crlf"]).code(1).stderr(contains(
+        "hint: ./scripts/crlf.sh has Windows (CRLF) line endings",
+    ));
+    project.rnr(["python"]).code(1).stderr(contains(
+        "hint: the shebang in ./scripts/tool.py points to /usr/bin/rnr-no-such-python, which is not installed",
+    ));
+    project
+        .rnr(["direct"])
+        .code(1)
+        .stderr(contains("Failed to execute command"))
+        .stderr(contains("hint: ./scripts/plain.sh is not executable"));
+    project
+        .rnr(["bare"])
+        .code(1)
+        .stderr(contains("run it as ./plain.sh"));
+}
+
+#[test]
+fn unknown_task_exits_non_zero() {
+    Project::from_fixture("basic")
+        .rnr(["helo"])
+        .failure()
+        .stderr(contains("Did you mean 'hello', or the 'help' subcommand?"));
+}
+
+#[test]
+fn runs_task_named_in_another_case() {
+    let project = Project::with_config(
+        "build: echo building\nci:\n  task: BUILD\nTest: echo a\nTEST: echo b\n",
+    );
+    project
+        .rnr(["Build"])
+        .success()
+        .stdout(contains("building"))
+        .stderr(contains("Running 'build' (matched 'Build')"));
+    project.rnr(["ci"]).success().stdout(contains("building"));
+    project
+        .rnr(["--strict", "Build"])
+        .failure()
+        .stderr(contains("case-sensitive in strict mode, so use 'build'"));
+    project
+        .rnr(["test"])
+        .failure()
+        .stderr(contains("differ only in case: TEST, Test"));
+}
+
+#[test]
+fn lists_tasks() {
+    Project::from_fixture("basic")
+        .rnr(["--list"])
+        .success()
+        .stdout(contains("hello"))
+        .stdout(contains("Build the project"));
+}
+
+#[test]
+fn lists_nested_tasks_recursively() {
+    let project = Project::from_fixture("nested");
+    project
+        .rnr(["--list"])
+        .success()
+        .stdout(contains("Tasks in subproject").not());
+    project
+        .rnr(["--list", "--recursive"])
+        .success()
+        .stdout(contains("build-subproject"))
+        .stdout(contains(
+            "Tasks in subproject:\n\n  build\n  clean\n  test\n",
+        ));
+}
+
+#[test]
+fn lists_where_tasks_are_defined() {
+    let project = Project::from_fixture("nested");
+    project
+        .rnr(["--list", "--sources", "--recursive"])
+        .success()
+        .stdout(contains("  build-subproject  rnr.yaml:"))
+        .stdout(contains(format!(
+            "  build  {}:",
+            std::path::Path::new("subproject/rnr.yaml").display()
+        )));
+    project
+        .rnr(["--sources"])
+        .failure()
+        .stderr(contains("--list"));
+}
+
+/// JSON event lines in `output`, skipping anything else
+fn events(output: &[u8]) -> Vec<serde_json::Value> {
+    String::from_utf8_lossy(output)
+        .lines()
+        .filter(|line| line.starts_with('{'))
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+
+#[test]
+fn streams_run_events_as_json() {
+    let output = Project::from_fixture("steps")
+        .rnr(["--events-json", "mixed"])
+        .success()
+        .stdout(contains("Parallel task 2"))
+        .stdout(contains("\"event\"").not())
+        .get_output()
+        .clone();
+    let events = events(&output.stderr);
+    assert!(events.iter().all(|event| event["v"] == 1));
+
+    let kinds: Vec<String> = events
+        .iter()
+        .map(|event| {
+            let position = match event["branch"].as_u64() {
+                Some(branch) => format!(" {}.{}", event["index"], branch),
+                None if event["index"].is_u64() => format!(" {}", event["index"]),
+                None => String::new(),
+            };
+            format!("{}{}", event["event"].as_str().unwrap(), position)
+        })
+        .collect();
+    assert_eq!(
+        kinds,
+        [
+            "run_started",
+            "step_started 1",
+            "step_finished 1",
+            "step_started 2.1",
+            "step_started 2.2",
+            "step_finished 2.1",
+            "step_finished 2.2",
+            "step_started 3",
+            "step_finished 3",
+            "run_finished",
+        ]
+    );
+    assert_eq!(events[1]["cmd"], "echo \"Starting...\"");
+    assert_eq!(events[1]["dir"], ".");
+    assert_eq!(events[2]["status"], "passed");
+    assert_eq!(events[9]["status"], "passed");
+    assert_eq!(events[9]["task"], "mixed");
+}
+
+#[test]
+fn run_events_report_failures() {
+    let output = Project::with_config("ci:\n  steps:\n    - cmd: exit 3\n    - cmd: echo never\n")
+        .rnr(["--events-json", "ci"])
+        .failure()
+        .get_output()
+        .clone();
+    let events = events(&output.stderr);
+    let statuses: Vec<&str> = events
+        .iter()
+        .filter_map(|event| event["status"].as_str())
+        .collect();
+    assert_eq!(statuses, ["failed", "not_run", "failed"]);
+    assert!(events.last().unwrap()["error"]
+        .as_str()
+        .unwrap()
+        .contains("exit code 3"));
+}
+
+#[test]
+#[cfg(unix)]
+fn run_events_on_a_file_descriptor() {
+    let output = Project::with_config("hello: echo hi")
+        .rnr(["--events-fd", "1", "hello"])
+        .success()
+        .get_output()
+        .clone();
+    let kinds: Vec<_> = events(&output.stdout)
+        .into_iter()
+        .map(|event| event["event"].clone())
+        .collect();
+    assert_eq!(kinds, ["run_started", "run_finished"]);
+}
+
+#[test]
+#[cfg(unix)]
+fn sigterm_cancels_the_run_and_its_commands() {
+    let project = Project::with_config(
+        "ci:\n  steps:\n    - cmd: echo started\n    - cmd: echo $$ > pid; sleep 30 & echo $! >> pid; wait\n    - cmd: echo never\n",
+    );
+    let child = project
+        .process()
+        .args(["--events-json", "ci"])
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    let pid_file = project.path().join("pid");
+    let started = std::time::Instant::now();
+    while std::fs::read_to_string(&pid_file).map_or(true, |pids| pids.lines().count() < 2) {
+        assert!(started.elapsed() < std::time::Duration::from_secs(10));
+        std::thread::sleep(std::time::Duration::from_millis(20));
+    }
+    let status = std::process::Command::new("kill")
+        .args(["-TERM", &child.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(output.status.code(), Some(143));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!stdout.contains("never\n"), "{}", stdout);
+    assert!(stderr.contains("CANCELLED ci"), "{}", stderr);
+    assert!(stderr.contains("Cancelled by SIGTERM"), "{}", stderr);
+    let statuses: Vec<_> = events(&output.stderr)
+        .into_iter()
+        .filter_map(|event| {
+            Some((
+                event["event"].as_str()?.to_string(),
+                event["status"].clone(),
+            ))
+        })
+        .filter(|(event, _)| event != "step_started")
+        .map(|(event, status)| format!("{} {}", event, status.as_str().unwrap_or("-")))
+        .collect();
+    assert_eq!(
+        statuses,
+        [
+            "run_started -",
+            "step_finished passed",
+            "step_finished cancelled",
+            "step_finished not_run",
+            "run_cancelled -",
+            "run_finished cancelled"
+        ]
+    );
+
+    // The shell's background command was stopped along with it (a zombie
+    // waiting to be reaped by whatever adopted it is as good as gone)
+    for pid in std::fs::read_to_string(&pid_file).unwrap().lines() {
+        let state = std::process::Command::new("ps")
+            .args(["-o", "stat=", "-p", pid])
+            .output()
+            .unwrap();
+        let state = String::from_utf8_lossy(&state.stdout);
+        assert!(
+            state.trim().is_empty() || state.starts_with('Z'),
+            "process {} outlived the run ({})",
+            pid,
+            state.trim()
+        );
+    }
+}
+
+#[test]
+fn delegates_into_nested_config() {
+    Project::from_fixture("nested")
+        .rnr(["build-subproject"])
+        .success()
+        .stdout(contains("Building subproject"));
+    Project::from_fixture("nested")
+        .rnr(["all"])
+        .success()
+        .stdout(contains("Testing subproject"));
+}
+
+#[test]
+fn dirs_accept_either_separator() {
+    let project = Project::from_fixture("separators");
+    project
+        .rnr(["api-build"])
+        .success()
+        .stdout(contains("Building API"));
+    project
+        .rnr(["all"])
+        .success()
+        .stdout(contains("Testing API"));
+    let api = project.path().join("services").join("api");
+    assert!(api.join("out-forward").is_dir());
+    assert!(api.join("out-backslash").is_dir());
+    project.rnr(["validate"]).success().stderr(contains(
+        "task 'all' has a backslash in dir 'services\\api'",
+    ));
+}
+
+#[test]
+fn delegates_with_env_and_args() {
+    Project::with_config(
+        "deploy:\n  args:\n    - name: target\n  env:\n    MODE: safe\n  cmd: echo deploying ${args.target} in ${env.MODE} mode\nboth:\n  steps:\n    - task: deploy\n      args: [staging]\n    - task: deploy\n      env:\n        MODE: fast\n      args: [--target, prod]\n",
+    )
+    .rnr(["both"])
+    .success()
+    .stdout(contains("deploying staging in safe mode"))
+    .stdout(contains("deploying prod in fast mode"));
+    Project::with_config(
+        "deploy:\n  args:\n    - name: target\n  cmd: echo deploying ${args.target} in ${env.MODE} mode\nhotfix:\n  env:\n    MODE: urgent\n  task: deploy\n  task_args: [prod]\n",
+    )
+    .rnr(["hotfix"])
+    .success()
+    .stdout(contains("deploying prod in urgent mode"));
+}
+
+#[test]
+fn delegates_with_path_references() {
+    let project = Project::with_config(
+        "ci:\n  steps:\n    - task: services/api:docker:build\n    - task: ./:docker:build\n\"docker:build\": echo root image\nlint:\n  task: check\n",
+    )
+    .file(
+        "services/api/rnr.yaml",
+        "\"docker:build\": echo api image\ncheck: echo checked\n",
+    );
+    project
+        .rnr(["ci"])
+        .success()
+        .stdout(contains("api image"))
+        .stdout(contains("root image"));
+    project
+        .rnr(["lint"])
+        .failure()
+        .stderr(contains("Task 'check' not found in rnr.yaml"))
+        .stderr(contains("reference it as 'services/api:check'"));
+}
+
+#[test]
+fn runs_project_bins() {
+    let project = Project::with_config(
+        "bins:\n  dprint: .tools/dprint\n  cog: .tools/cog\nformat: echo ${bins.dprint} fmt\nrelease:\n  steps:\n    - cmd: echo releasing\n    - cmd: ${bins.cog} bump\n",
+    )
+    .file(".tools/dprint", "");
+    project
+        .rnr(["format"])
+        .success()
+        .stdout(contains("dprint fmt"))
+        .stdout(contains("${bins").not());
+    project
+        .rnr(["release"])
+        .failure()
+        .stdout(contains("releasing").not())
+        .stderr(contains(
+            "task 'release' needs bin 'cog', but .tools/cog doesn't exist",
+        ));
+}
+
+#[test]
+#[cfg(unix)]
+fn umask_covers_commands_and_rnr_files() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let project = Project::with_config(
+        "umask: \"077\"\nsetup:\n  log: logs/setup.log\n  steps:\n    - cmd: touch token.txt\n    - render: { content: \"key=1\", to: conf/app.env }\n    - render: { content: \"ok\", to: public.txt, mode: \"0644\" }\n",
+    );
+    project.rnr(["setup"]).success();
+    let mode = |path: &str| {
+        let metadata = std::fs::metadata(project.path().join(path)).unwrap();
+        metadata.permissions().mode() & 0o777
+    };
+    assert_eq!(mode("token.txt"), 0o600);
+    assert_eq!(mode("conf/app.env"), 0o600);
+    assert_eq!(mode("logs/setup.log"), 0o600);
+    // An explicit mode wins
+    assert_eq!(mode("public.txt"), 0o644);
+}
+
+#[test]
+#[cfg(unix)]
+fn failure_hooks_get_the_failure() {
+    let project = Project::with_config(
+        "deploy:\n  on_failure: notify\n  steps:\n    - cmd: echo building\n    - cmd: echo boom && exit 3\nnotify:\n  on_failure: notify\n  cmd: 'echo \"hook: $RNR_FAILED_TASK step $RNR_FAILED_STEP code $RNR_EXIT_CODE\" && tail -n 1 \"$RNR_FAILURE_LOG\" && exit 9'\n",
+    );
+    let output = project
+        .rnr(["deploy"])
+        .failure()
+        .code(1)
+        .stdout(contains("hook: deploy step 2 code 3"))
+        .stderr(contains("on_failure (task notify) failed too"))
+        .stderr(contains("exit code 3"))
+        .get_output()
+        .clone();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    // The tail ends with the failing command's output, and the hook ran once
+    let mut tail = stdout.lines().skip_while(|line| !line.starts_with("hook:"));
+    assert_eq!(tail.nth(1), Some("boom"));
+    let runs = stdout.lines().filter(|line| line.starts_with("hook:"));
+    assert_eq!(runs.count(), 1);
+}
+
+#[test]
+fn global_failure_hook_and_dry_run() {
+    let project = Project::with_config(
+        "hooks:\n  on_failure: { cmd: echo global hook for $RNR_FAILED_TASK }\nbuild: exit 4\nok: echo fine\n",
+    );
+    project
+        .rnr(["build"])
+        .failure()
+        .stdout(contains("global hook for build"));
+    project
+        .rnr(["ok"])
+        .success()
+        .stdout(contains("global hook").not());
+    project
+        .rnr(["--dry-run", "build"])
+        .success()
+        .stdout(contains("on_failure: would run $ echo global hook"));
+}
+
+#[test]
+fn shows_the_environment_rnr_sets() {
+    let project = Project::with_config(
+        "helper:\n  env:\n    STAGE: two\n  cmd: echo helper\ndeploy:\n  args:\n    - name: target\n  env:\n    DEPLOY_TOKEN: s3cr3t\n    REGION: eu\n  steps:\n    - cmd: echo deploying\n    - task: helper\n",
+    );
+    project
+        .rnr(["--verbose", "deploy", "prod"])
+        .success()
+        .stderr(contains(
+            "env for deploy:\n  task env:\n    DEPLOY_TOKEN=***\n    REGION=eu\n  arguments:\n    RNR_ARG_TARGET=prod\n",
+        ))
+        .stderr(contains("env for helper:\n  task env:\n    STAGE=two\n"))
+        .stderr(contains("s3cr3t").not());
+    project
+        .rnr(["deploy", "prod"])
+        .success()
+        .stderr(contains("env for").not());
+    project
+        .rnr(["env", "deploy", "prod"])
+        .success()
+        .stdout(contains("Environment rnr sets for 'deploy':"))
+        .stdout(contains("DEPLOY_TOKEN=***"))
+        .stdout(contains("deploying").not());
+}
+
+/// An env step changes the environment of the steps after it, in a dry run too
+#[test]
+fn env_steps_apply_to_later_steps() {
+    let project = Project::with_config(
+        "dev:\n  steps:\n    - cmd: echo STAGE=generated > gen.env\n    - env_file: gen.env\n    - env:\n        NAME: app-${env.STAGE}\n    - cmd: echo name is ${env.NAME}\n",
+    );
+    project
+        .rnr(["dev", "--dry-run"])
+        .success()
+        .stdout(contains(
+            "$ env_file gen.env\n  would load gen.env (not there yet)\n",
+        ))
+        .stdout(contains("$ env NAME\n  NAME=app-${env.STAGE}\n"));
+    project
+        .rnr(["dev"])
+        .success()
+        .stdout(contains("  STAGE=generated\n"))
+        .stdout(contains("  NAME=app-generated\n"))
+        .stdout(contains("name is app-generated"));
+}
+
+/// `expect_exit` lets grep's "no match" pass, and `export_exit` hands its
+/// code to the steps after it
+#[test]
+#[cfg(unix)]
+fn expected_exit_codes_and_exported_code() {
+    let project = Project::with_config(
+        "todo:\n  steps:\n    - cmd: grep -q TODO notes.txt\n      expect_exit: [0, 1]\n      export_exit: FOUND\n    - cmd: echo found=${env.FOUND}\n",
+    )
+    .file("notes.txt", "all done\n");
+    project.rnr(["todo"]).success().stdout(contains("found=1"));
+    project
+        .rnr(["todo", "--dry-run"])
+        .success()
+        .stdout(contains("would set FOUND to its exit code"))
+        .stdout(contains("$ echo found=<exit code>"));
+
+    let project = project.file(
+        "rnr.yaml",
+        "todo:\n  cmd: grep -q TODO missing.txt\n  expect_exit: [0, 1]\n",
+    );
+    project
+        .rnr(["todo"])
+        .failure()
+        .stderr(contains("exit code 2 (expected exit codes 0 or 1)"));
+}
+
+/// `--chaos` fails or hangs the step it names instead of running it, and
+/// won't run in CI without `--chaos-allow-ci`
+#[test]
+fn chaos_injects_labeled_failures() {
+    let project = Project::with_config(
+        "ci:\n  steps:\n    - cmd: echo linted\n    - cmd: echo tested\n    - cmd: echo built\n",
+    );
+    project
+        .rnr(["--chaos", "fail-step=2", "ci"])
+        .failure()
+        .stdout(contains("linted"))
+        .stdout(contains("tested").not())
+        .stderr(contains("chaos mode: injecting fail-step=2"))
+        .stderr(contains(
+            "Injected failure from --chaos fail-step=2 (not a real failure)",
+        ));
+    project
+        .rnr(["--chaos", "explode=2", "ci"])
+        .failure()
+        .stderr(contains("Invalid --chaos 'explode=2'"));
+    project
+        .command()
+        .env("CI", "true")
+        .args(["--chaos", "fail-task=ci", "ci"])
+        .assert()
+        .failure()
+        .stderr(contains("add --chaos-allow-ci"))
+        .stdout(contains("linted").not());
+    project
+        .command()
+        .env("CI", "true")
+        .args(["--chaos", "fail-task=ci", "--chaos-allow-ci", "ci"])
+        .assert()
+        .failure()
+        .stderr(contains("--chaos fail-task=ci (not a real failure)"));
+
+    project
+        .command()
+        .args(["--chaos", "timeout-step=3", "ci"])
+        .timeout(std::time::Duration::from_secs(3))
+        .assert()
+        .interrupted()
+        .stdout(contains("tested"))
+        .stdout(contains("built").not())
+        .stderr(contains("hanging in step 3 of 'ci'"));
+}
+
+/// A task's `log` gets its output, and `--quiet` leaves it only there
+#[test]
+fn task_log_keeps_output_out_of_quiet_console() {
+    let project = Project::with_config(
+        "serve:\n  log: .rnr/logs/serve-${date(%Y)}.log\n  log_max_size: 10MB\n  cmd: echo listening\n",
+    );
+    project
+        .rnr(["--quiet", "serve"])
+        .success()
+        .stdout(contains("listening").not());
+    project
+        .rnr(["serve"])
+        .success()
+        .stdout(contains("$ echo listening\nlistening\n"));
+
+    let logs: Vec<_> = std::fs::read_dir(project.path().join(".rnr/logs"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    assert_eq!(logs.len(), 1, "{:?}", logs);
+    let log = std::fs::read_to_string(&logs[0]).unwrap();
+    assert_eq!(log.matches("--- rnr serve at ").count(), 2, "{}", log);
+    assert_eq!(log.matches("\nlistening\n").count(), 2, "{}", log);
+}
+
+/// `echo_format` shapes the echo, and `--quiet` still turns it off
+#[test]
+fn echo_format_and_quiet() {
+    let project = Project::with_config(
+        "echo_format: '[{dir}] $ {cmd}'\nbuild:\n  dir: app\n  cmd: echo built\n",
+    )
+    .file("app/.keep", "");
+    project
+        .rnr(["build"])
+        .success()
+        .stdout(contains("[app] $ echo built\nbuilt\n"));
+    project
+        .rnr(["--quiet", "build"])
+        .success()
+        .stdout("built\n");
+}
+
+/// `--dir` runs the invoked task in another directory of the project
+#[test]
+fn dir_flag_runs_task_elsewhere() {
+    let project = Project::with_config(
+        "echo_format: '[{dir}] $ {cmd}'\nbuild:\n  dir: app\n  cmd: pwd\nrelease:\n  checkout: clean\n  cmd: make\n",
+    )
+    .file("app/.keep", "")
+    .file("services/payments/.keep", "");
+    project
+        .rnr(["--dir", "services/payments", "build"])
+        .success()
+        .stdout(contains("Running 'build' in services/payments (--dir)"))
+        .stdout(contains("[services/payments] $ pwd\n"))
+        .stdout(contains("payments\n"));
+    project
+        .rnr(["--dir", "services/payments", "--dry-run", "build"])
+        .success()
+        .stdout(contains("services/payments"))
+        .stdout(contains("payments\n").not());
+    project
+        .rnr(["--dir", "services/billing", "build"])
+        .failure()
+        .stderr(contains("--dir 'services/billing' isn't a directory"));
+    project
+        .rnr(["--dir", "services/payments", "release"])
+        .failure()
+        .stderr(contains("--dir can't be used with 'release'"));
+}
+
+/// `--only`, `--skip` and `--steps` pick which of a task's steps run
+#[test]
+fn step_filters_select_steps() {
+    let project = Project::with_config(
+        "ci:\n  steps:\n    - cmd: echo fmt\n      tags: [lint, fast]\n    - cmd: echo clippy\n      tags: [lint]\n    - cmd: echo build\n    - cmd: echo test\n      tags: [slow]\n",
+    );
+    project
+        .rnr(["ci", "--only", "lint", "--skip", "fast"])
+        .success()
+        .stdout(contains("$ echo clippy\nclippy\n"))
+        .stdout(contains(
+            "Skipping step 1: $ echo fmt (filtered out by --only lint --skip fast)",
+        ))
+        .stdout(contains("build\n").not());
+    project
+        .rnr(["--skip", "slow", "--steps", "2-", "--dry-run", "ci"])
+        .success()
+        .stdout(contains("$ echo clippy\n$ echo build\nSkipping step 4"))
+        .stdout(contains("Skipping step 1"));
+    project
+        .rnr(["ci", "--steps", "3-1"])
+        .failure()
+        .stderr(contains("Invalid --steps '3-1'"));
+}
+
+/// `rnr fmt --check` fails on a messy rnr.yaml, and `rnr fmt` fixes it
+#[test]
+fn fmt_rewrites_and_checks() {
+    let messy = "# tasks\nbuild:\n    cmd: cargo build   # compile\n    description: Build\nlint: cargo clippy\n";
+    let project = Project::with_config(messy);
+    project.rnr(["fmt", "--check"]).failure().stderr(contains(
+        "rnr.yaml isn't formatted (first difference on line 3)",
+    ));
+    assert_eq!(
+        std::fs::read_to_string(project.path().join("rnr.yaml")).unwrap(),
+        messy
+    );
+
+    project
+        .rnr(["fmt"])
+        .success()
+        .stdout("Formatted rnr.yaml.\n");
+    assert_eq!(
+        std::fs::read_to_string(project.path().join("rnr.yaml")).unwrap(),
+        "# tasks\nbuild:\n  description: Build\n  cmd: cargo build  # compile\n\nlint: cargo clippy\n"
+    );
+    project
+        .rnr(["fmt", "--check"])
+        .success()
+        .stdout("rnr.yaml is already formatted.\n");
+}
+
+/// `rnr add` appends a task that runs straight away, and won't clobber one
+#[test]
+fn add_appends_a_runnable_task() {
+    let project = Project::with_config("# Tasks\nbuild: echo built\n").file("infra/.keep", "");
+    project
+        .rnr([
+            "add",
+            "deploy",
+            "--cmd",
+            "echo deployed",
+            "--description",
+            "Deploy to prod",
+            "--dir",
+            "infra",
+        ])
+        .success()
+        .stdout("Added task 'deploy' in rnr.yaml\n");
+    assert_eq!(
+        std::fs::read_to_string(project.path().join("rnr.yaml")).unwrap(),
+        "# Tasks\nbuild: echo built\n\ndeploy:\n  description: Deploy to prod\n  dir: infra\n  cmd: 'echo deployed'\n"
+    );
+    project
+        .rnr(["deploy"])
+        .success()
+        .stdout(contains("deployed"));
+    project
+        .rnr(["add", "build", "--cmd", "echo again"])
+        .failure()
+        .stderr(contains(
+            "Task 'build' already exists in rnr.yaml; pass --force to replace it",
+        ));
+}
+
+/// `rnr affected` runs only the tasks whose paths cover what changed
+#[test]
+fn affected_runs_tasks_matching_changed_files() {
+    let tasks = "api:\n  paths: ['api/**']\n  cmd: echo api > api.out\nweb:\n  paths: [web/]\n  cmd: echo web > web.out\nlint: echo lint > lint.out\n";
+    let project = Project::with_config(tasks)
+        .file("api/main.rs", "fn main() {}\n")
+        .file("web/index.ts", "export {}\n");
+    project
+        .git(&["init", "--quiet", "--initial-branch=main"])
+        .git(&["add", "."])
+        .git(&["commit", "--quiet", "-m", "initial"])
+        .git(&["checkout", "--quiet", "-b", "feature"]);
+
+    project
+        .rnr(["affected", "--since", "main"])
+        .success()
+        .stdout(contains("0 files changed since main"))
+        .stdout(contains("Nothing affected."));
+
+    std::fs::write(project.path().join("api/main.rs"), "fn main() { }\n").unwrap();
+    project.git(&["commit", "--quiet", "-am", "change api"]);
+    project
+        .rnr(["affected", "--since", "main", "--dry-run"])
+        .success()
+        .stdout(contains(
+            "Affected tasks:\n  api  api/main.rs matches 'api/**'\n",
+        ))
+        .stdout(contains("Not affected: lint, web"))
+        .stdout(contains("$ echo api > api.out"));
+    assert!(!project.path().join("api.out").exists());
+
+    let config = format!("affected_without_paths: run\n{}", tasks);
+    std::fs::write(project.path().join("rnr.yaml"), config).unwrap();
+    project
+        .rnr(["affected", "--since", "main"])
+        .success()
+        .stdout(contains("lint  no paths (affected_without_paths: run)"));
+    assert!(project.path().join("api.out").is_file());
+    assert!(project.path().join("lint.out").is_file());
+    assert!(!project.path().join("web.out").exists());
+
+    project
+        .rnr(["affected", "--since", "no-such-branch"])
+        .failure()
+        .stderr(contains(
+            "Can't find where 'no-such-branch' and HEAD diverged",
+        ));
+}
+
+/// `rnr explain` gives the reason for each step without running any of them
+#[test]
+fn explain_shows_why_without_running() {
+    let project = Project::with_config(
+        "setup:\n  once: true\n  cmd: mkdir made\nci:\n  steps:\n    - task: setup\n    - cmd: echo ran > ran.txt\n    - task: setup\n",
+    );
+    project
+        .rnr(["explain", "ci"])
+        .success()
+        .stdout(contains("once: true, first use of 'setup' in this run"))
+        .stdout(contains("skip: once: true, already ran at ci/1/setup"))
+        .stdout(contains("run: command, run by the shell"));
+    assert!(!project.path().join("made").exists());
+    assert!(!project.path().join("ran.txt").exists());
+    project
+        .rnr(["explain", "nope"])
+        .failure()
+        .stderr(contains("Task 'nope' not found"));
+}
+
+/// An rnr.yaml whose anchors expand to hundreds of millions of nodes is
+/// refused quickly, before anything runs
+#[test]
+fn list_refuses_anchor_bomb() {
+    let project = Project::new().file("rnr.yaml", include_str!("fixtures/anchor-bomb.yaml"));
+    let started = std::time::Instant::now();
+    project
+        .rnr(["--list"])
+        .failure()
+        .stderr(contains("is too large or complex to load"))
+        .stderr(contains("expand to more than 1000000 nodes"));
+    assert!(started.elapsed() < std::time::Duration::from_secs(10));
+}
+
+/// The size limit can be raised for a project that really needs it
+#[test]
+fn config_size_limit_is_overridable() {
+    let project = Project::with_config(&format!("# {}\nbuild: echo built\n", "x".repeat(2000)));
+    project
+        .command()
+        .arg("build")
+        .env("RNR_MAX_CONFIG_SIZE", "1000")
+        .assert()
+        .failure()
+        .stderr(contains(
+            "over the limit of 1000 bytes (set RNR_MAX_CONFIG_SIZE to raise it)",
+        ));
+    project
+        .command()
+        .arg("build")
+        .env("RNR_MAX_CONFIG_SIZE", "4000")
+        .assert()
+        .success()
+        .stdout(contains("built"));
+}
+
+#[test]
+fn rnr_task_picks_the_task_for_a_bare_rnr() {
+    let project = Project::with_config(
+        "build: echo built
+show:
+  cmd: echo \"[$RNR_ARG_WHO]\"
+  args:
+    - name: who
+",
+    );
+    let bare = |task: &str, args: Option<&str>| {
+        let mut cmd = project.command();
+        cmd.env("RNR_TASK", task);
+        if let Some(args) = args {
+            cmd.env("RNR_ARGS", args);
+        }
+        cmd
+    };
+
+    bare("show", Some("'two words'"))
+        .assert()
+        .success()
+        .stdout(contains("[two words]"));
+    // A task on the command line wins, and RNR_ARGS only goes with RNR_TASK
+    bare("show", Some("ignored"))
+        .arg("build")
+        .assert()
+        .success()
+        .stdout(contains("built").and(contains("ignored").not()));
+    // So does --list, and without RNR_TASK a bare rnr lists the tasks
+    bare("build", None)
+        .arg("--list")
+        .assert()
+        .success()
+        .stdout(contains("show").and(contains("built").not()));
+    bare("", None)
+        .assert()
+        .success()
+        .stdout(contains("show").and(contains("built").not()));
+
+    bare("show", Some("\"unclosed"))
+        .assert()
+        .failure()
+        .stderr(contains(
+            "Couldn't parse RNR_ARGS (\"unclosed): a double quote is never closed",
+        ));
+}
+
+#[test]
+fn min_version_stops_an_older_rnr() {
+    let project = Project::with_config(
+        "min_version: \"999.0\"\nmatrix: { os: [linux] }\nbuild: echo built\n",
+    );
+    for args in [&["build"][..], &["--list"], &["validate"]] {
+        project.rnr(args).failure().stderr(contains(format!(
+            "this project requires rnr >= 999.0, you have {} — run ./rnr upgrade",
+            env!("CARGO_PKG_VERSION")
+        )));
+    }
+
+    let project = Project::with_config("min_version: \"0.1\"\nbuild: echo built\n");
+    project.rnr(["build"]).success().stdout(contains("built"));
+    project
+        .rnr(["--list"])
+        .success()
+        .stdout(contains("min_version").not());
+}
+
+#[test]
+fn renamed_and_deprecated_tasks_warn_or_fail_in_strict_mode() {
+    let config = "build: echo built\nold-build:\n  cmd: echo old\n  deprecated:\n    replaced_by: build\nrenamed:\n  compile: build\n";
+    let project = Project::with_config(config);
+    project
+        .rnr(["compile"])
+        .success()
+        .stdout(contains("built"))
+        .stderr(contains(
+            "Task 'compile' was renamed to 'build'; running 'build'",
+        ));
+    project
+        .rnr(["old-build"])
+        .success()
+        .stdout(contains("old"))
+        .stderr(contains(
+            "Task 'old-build' is deprecated; use 'build' instead",
+        ));
+    project
+        .rnr(["--list"])
+        .success()
+        .stdout(contains("old-build  (deprecated, use 'build')").and(contains("compile").not()));
+
+    project
+        .rnr(["--strict", "compile"])
+        .failure()
+        .stdout(contains("built").not())
+        .stderr(contains(
+            "Task 'compile' was renamed to 'build'; run 'rnr build' instead",
+        ));
+    let project = Project::with_config(&format!("strict: true\n{}", config));
+    project
+        .rnr(["old-build"])
+        .failure()
+        .stdout(contains("old").not())
+        .stderr(contains(
+            "Task 'old-build' is deprecated; use 'build' instead",
+        ));
+}
+
+#[cfg(unix)]
+#[test]
+fn nested_runs_share_the_run_id_and_leave_the_summary_to_the_top() {
+    let rnr = env!("CARGO_BIN_EXE_rnr");
+    let project = Project::with_config(&format!(
+        "outer: \"'{}' inner\"\ninner: echo \"depth=$RNR_DEPTH parent=$RNR_PARENT_TASK id=$RNR_RUN_ID\"; exit 3\nloop: \"'{}' loop\"\n",
+        rnr, rnr
+    ));
+    project
+        .command()
+        .arg("outer")
+        .env("RNR_RUN_ID", "run-1")
+        .assert()
+        .failure()
+        .stdout(contains("depth=2 parent=inner id=run-1"))
+        .stderr(contains("FAILED outer").and(contains("FAILED inner").not()));
+
+    project
+        .command()
+        .arg("loop")
+        .env("RNR_MAX_DEPTH", "3")
+        .assert()
+        .failure()
+        .stderr(contains(
+            "rnr is nested 3 runs deep ('loop' -> 'loop' -> 'loop' -> 'loop'), \
+             which looks like a task invoking itself",
+        ));
+}
+
+#[test]
+fn contexts_limit_where_tasks_run() {
+    let project = Project::with_config(
+        "contexts: [prod]\ndeploy-prod:\n  cmd: echo deployed\n  contexts:\n    allow: [ci, prod]\nclean:\n  cmd: echo cleaned\n  contexts:\n    deny: [ci]\nship:\n  task: deploy-prod\n",
+    );
+    for task in ["deploy-prod", "ship"] {
+        project
+            .rnr([task])
+            .failure()
+            .stdout(contains("deployed").not())
+            .stderr(contains(
+                "Task 'deploy-prod' can't run in the 'local' context (allowed: ci, prod)",
+            ));
+        project
+            .command()
+            .arg(task)
+            .env("CI", "true")
+            .assert()
+            .success()
+            .stdout(contains("deployed"));
+    }
+    project
+        .command()
+        .arg("ship")
+        .env("RNR_CONTEXT", "prod")
+        .assert()
+        .success()
+        .stdout(contains("deployed"));
+
+    project.rnr(["clean"]).success().stdout(contains("cleaned"));
+    project
+        .command()
+        .arg("clean")
+        .env("CI", "1")
+        .assert()
+        .failure()
+        .stderr(contains(
+            "Task 'clean' can't run in the 'ci' context (allowed: local, prod)",
+        ));
+    project
+        .rnr(["--list"])
+        .success()
+        .stdout(contains("[ci, prod only]").and(contains("[not in ci]")));
+}
+
+#[test]
+fn validate_warns_about_unknown_fields() {
+    let project = Project::with_config("build:\n  comand: echo built\n");
+    project
+        .rnr(["validate"])
+        .success()
+        .stdout(contains("rnr.yaml is valid with 1 warning."))
+        .stderr(contains("unknown field 'comand' (did you mean 'cmd'?)"));
+    project
+        .rnr(["--strict", "build"])
+        .failure()
+        .stderr(contains("Unknown fields in").and(contains("'comand'")));
+}
+
+/// An rnr.yaml with nothing in it yet is a project without tasks, not a parse error
+#[test]
+fn empty_config_has_no_tasks() {
+    for content in ["", "# tasks go here\n", "null\n"] {
+        let project = Project::with_config(content);
+        project
+            .rnr(["--list"])
+            .success()
+            .stdout(contains("No tasks defined in rnr.yaml"));
+        project.rnr(["build"]).failure().stderr(
+            contains("Task 'build' not found: ")
+                .and(contains("rnr.yaml doesn't define any tasks yet")),
+        );
+        project
+            .rnr(["validate"])
+            .success()
+            .stdout(contains("rnr.yaml is valid."))
+            .stderr(contains("rnr.yaml doesn't define any tasks yet"));
+    }
+}
+
+#[test]
+fn config_must_be_a_mapping() {
+    for (content, found) in [("- build\n", "list"), ("cargo build\n", "string")] {
+        Project::with_config(content)
+            .rnr(["--list"])
+            .failure()
+            .stderr(contains(format!(
+                "rnr.yaml must be a mapping of task names to tasks (like 'build: cargo build'), but it's a {}",
+                found
+            )))
+            .stderr(contains("invalid type").not());
+    }
+}
+
+#[test]
+fn run_reaches_tasks_shadowed_by_subcommands() {
+    let project = Project::with_config("init: echo task init\nupgrade: echo task $1\n");
+    project
+        .rnr(["run", "init"])
+        .success()
+        .stdout(contains("task init"));
+    project
+        .rnr(["run", "upgrade", "deps"])
+        .success()
+        .stdout(contains("task deps"));
+    project
+        .rnr(["--list"])
+        .success()
+        .stderr(contains(
+            "task 'init' is shadowed by the 'init' subcommand; run it with 'rnr run init'",
+        ))
+        .stderr(contains("task 'upgrade' is shadowed"));
+    project
+        .rnr(["intt"])
+        .failure()
+        .stderr(contains("Did you mean 'init', or the 'init' subcommand?"));
+}
+
+#[test]
+fn missing_config_is_an_error() {
+    Project::new().rnr(["build"]).failure();
+}
+
+#[test]
+fn version_json_describes_binary_and_project() {
+    let project = Project::new().file(
+        ".rnr/config.yaml",
+        "version: 0.2.0\nplatforms:\n- linux-amd64\n- windows-amd64\n",
+    );
+    let assert = project.rnr(["version", "--json"]).success();
+    let report: serde_json::Value = serde_json::from_slice(&assert.get_output().stdout).unwrap();
+    assert_eq!(report["binary_version"], env!("CARGO_PKG_VERSION"));
+    assert_eq!(report["config_version"], "0.2.0");
+    assert_eq!(
+        report["platforms"],
+        serde_json::json!(["linux-amd64", "windows-amd64"])
+    );
+    for field in ["git_sha", "build_date", "target"] {
+        assert!(report[field].is_string(), "{}: {}", field, report);
+    }
+    assert!(report["features"].is_array());
+
+    let assert = Project::new().rnr(["version", "--json"]).success();
+    let report: serde_json::Value = serde_json::from_slice(&assert.get_output().stdout).unwrap();
+    assert!(report["config_version"].is_null());
+
+    project
+        .rnr(["--version", "--verbose"])
+        .success()
+        .stdout(contains("  commit:"))
+        .stdout(contains("  target:"));
+}
+
+/// The binary-managing commands find the project from any subdirectory
+#[test]
+#[cfg(feature = "network")]
+fn project_commands_work_from_a_subdirectory() {
+    let project = Project::from_fixture("nested")
+        .file(
+            ".rnr/config.yaml",
+            "version: 0.2.0\nplatforms:\n- linux-amd64\n- windows-amd64\n",
+        )
+        .file(".rnr/bin/rnr-linux-amd64", "");
+    let run = |args: &[&str]| {
+        project
+            .command()
+            .current_dir(project.path().join("subproject"))
+            .env("RNR_RELEASES_URL", "http://127.0.0.1:9")
+            .args(args)
+            .assert()
+    };
+
+    run(&["init", "--show-platforms"])
+        .success()
+        .stdout(contains("linux-amd64"));
+    run(&["init", "--add-platform", "macos-arm64", "--placeholders"]).success();
+    let bin = project.path().join(".rnr").join("bin");
+    assert!(bin.join("rnr-macos-arm64.placeholder").is_file());
+    let config = std::fs::read_to_string(project.path().join(".rnr/config.yaml")).unwrap();
+    assert!(config.contains("macos-arm64"), "{}", config);
+    run(&["init", "--remove-platform", "macos-arm64"]).success();
+    assert!(!bin.join("rnr-macos-arm64.placeholder").exists());
+    assert!(!project.path().join("subproject/.rnr").exists());
+
+    run(&["version"])
+        .success()
+        .stdout(contains("Project version: v0.2.0"));
+    run(&["verify"])
+        .failure()
+        .stdout(contains("project version v0.2.0"));
+    run(&["upgrade", "--check"])
+        .failure()
+        .stdout(contains("Current version: v0.2.0"));
+}
+
+/// verify flags files in .rnr/bin that config.yaml doesn't manage, and
+/// `init --prune` deletes them
+#[test]
+#[cfg(feature = "network")]
+fn prune_removes_unmanaged_files() {
+    let project = Project::new()
+        .file(
+            ".rnr/config.yaml",
+            "version: 0.2.0\nbinaries:\n  linux-amd64:\n    version: 0.2.0\n    files:\n    - rnr-linux-amd64\n",
+        )
+        .file(".rnr/bin/rnr-linux-amd64", "")
+        .file(".rnr/bin/rnr-linux-amd64.tar.gz", "left by an older version");
+    project
+        .rnr(["verify"])
+        .failure()
+        .stdout(contains(format!(
+            "rnr {}\n  commit:",
+            env!("CARGO_PKG_VERSION")
+        )))
+        .stdout(contains(
+            "rnr-linux-amd64.tar.gz (not managed by config.yaml)",
+        ))
+        .stdout(contains("Run 'rnr init --prune'"));
+
+    project
+        .rnr(["init", "--prune", "--yes"])
+        .success()
+        .stdout(contains("  Removed rnr-linux-amd64.tar.gz"));
+    let bin = project.path().join(".rnr/bin");
+    assert!(!bin.join("rnr-linux-amd64.tar.gz").exists());
+    assert!(bin.join("rnr-linux-amd64").exists());
+    project
+        .rnr(["verify"])
+        .failure()
+        .stdout(contains("not managed").not());
+}
+
+/// `--project-dir` pins the project, wherever rnr runs from
+#[test]
+fn project_dir_pins_the_project() {
+    let project = Project::from_fixture("nested").file(
+        ".rnr/config.yaml",
+        "version: 0.2.0\nplatforms:\n- linux-amd64\n",
+    );
+    let elsewhere = Project::new();
+    let root = project.path().to_str().unwrap();
+
+    elsewhere
+        .rnr(["--project-dir", root, "run-in-subdir"])
+        .success()
+        .stdout(contains("$ echo \"Running in subproject directory\""));
+    // Not the subproject's rnr.yaml, even from inside it
+    project
+        .command()
+        .current_dir(project.path().join("subproject"))
+        .args(["--project-dir", root, "--list"])
+        .assert()
+        .success()
+        .stdout(contains("build-subproject"));
+    elsewhere
+        .rnr(["version", "--project-dir", root])
+        .success()
+        .stdout(contains("Project version: v0.2.0"));
+
+    elsewhere
+        .rnr(["--project-dir", elsewhere.path().to_str().unwrap(), "build"])
+        .failure()
+        .stderr(contains("No rnr.yaml in"))
+        .stderr(contains("(the --project-dir)"));
+    elsewhere
+        .rnr(["--project-dir", "missing", "build"])
+        .failure()
+        .stderr(contains("--project-dir missing is not a directory"));
+}
+
+/// Commands that don't need tasks never look for rnr.yaml, even a broken one
+#[test]
+fn startup_reads_config_only_when_needed() {
+    let project = Project::with_config("build: [unterminated\n");
+    for args in [
+        &["--version"][..],
+        &["--help"],
+        &["help"],
+        &["--list", "--bogus"],
+    ] {
+        let assert = project
+            .command()
+            .env("RNR_PROFILE_STARTUP", "1")
+            .args(args)
+            .assert();
+        let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+        assert!(!stderr.contains("startup:"), "{:?}: {}", args, stderr);
+    }
+
+    // Nor is it opened: reading a FIFO would wait for a writer forever
+    #[cfg(unix)]
+    {
+        let fifo = Project::new();
+        let made = std::process::Command::new("mkfifo")
+            .arg(fifo.path().join("rnr.yaml"))
+            .status()
+            .unwrap();
+        assert!(made.success());
+        for args in [
+            &["--version"][..],
+            &["--version", "--verbose"],
+            &["--help"],
+            &["help"],
+        ] {
+            fifo.command()
+                .args(args)
+                .timeout(std::time::Duration::from_secs(10))
+                .assert()
+                .success();
+        }
+    }
+
+    project
+        .command()
+        .env("RNR_PROFILE_STARTUP", "1")
+        .arg("--list")
+        .assert()
+        .failure()
+        .stderr(contains("startup: discovery"))
+        .stderr(contains("startup: found"))
+        .stderr(contains("startup: parse"));
+}
+
+/// Run through a wrapper, rnr uses the wrapper's rnr.yaml wherever it's run from
+#[test]
+fn wrapper_dir_selects_the_project() {
+    let project = Project::with_config("where: echo wrapper project");
+    let elsewhere = Project::with_config("where: echo current directory");
+    project
+        .command()
+        .current_dir(elsewhere.path())
+        .env("RNR_WRAPPER_DIR", project.path())
+        .arg("where")
+        .assert()
+        .success()
+        .stdout(contains("wrapper project"));
+    elsewhere
+        .rnr(["where"])
+        .success()
+        .stdout(contains("current directory"));
+}
+
+/// Slim builds hand init to the full rnr named by RNR_FULL
+#[test]
+#[cfg(all(unix, not(feature = "network")))]
+fn slim_init_delegates_to_full_build() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let project = Project::new().file("full-rnr", "#!/bin/sh\necho \"full: $* $RNR_DELEGATED\"\n");
+    let full = project.path().join("full-rnr");
+    std::fs::set_permissions(&full, std::fs::Permissions::from_mode(0o755)).unwrap();
+    project
+        .command()
+        .args(["init", "--current-platform-only", "--force"])
+        .env("RNR_FULL", &full)
+        .assert()
+        .success()
+        .stdout(contains("full: init --current-platform-only --force 1"));
+    assert!(!project.path().join(".rnr").exists());
+}
+
+/// Without a full rnr to delegate to, a slim init sets the project up to run
+/// the rnr on PATH
+#[test]
+#[cfg(not(feature = "network"))]
+fn slim_init_without_full_build_uses_system_rnr() {
+    let project = Project::new();
+    project
+        .command()
+        .args(["init", "--force"])
+        .env("RNR_DELEGATED", "1")
+        .assert()
+        .success()
+        .stderr(contains("rnr init --download"));
+
+    let config = std::fs::read_to_string(project.path().join(".rnr/config.yaml")).unwrap();
+    assert!(config.contains("mode: system"), "{}", config);
+    assert!(!project.path().join(".rnr/bin").exists());
+    let wrapper = std::fs::read_to_string(project.path().join("rnr")).unwrap();
+    assert!(wrapper.contains("exec rnr \"$@\""));
+    assert!(project.path().join("rnr.yaml").exists());
+
+    project
+        .command()
+        .arg("verify")
+        .assert()
+        .success()
+        .stdout(contains("mode: system"));
+}
+
+/// Without a full rnr to delegate to, slim builds explain how to get one
+#[test]
+#[cfg(not(feature = "network"))]
+fn slim_upgrade_without_full_build() {
+    Project::new()
+        .command()
+        .arg("upgrade")
+        .env("RNR_DELEGATED", "1")
+        .assert()
+        .failure()
+        .stderr(contains("slim rnr build without network support"));
+}
+
+/// Before the first release, init can write labeled placeholders that verify flags
+#[test]
+fn init_with_placeholders() {
+    let Some(platform) = common::platform_id() else {
+        return;
+    };
+    let project = Project::new();
+    project
+        .command()
+        .args([
+            "init",
+            "--current-platform-only",
+            "--placeholders",
+            "--force",
+        ])
+        .assert()
+        .success()
+        .stderr(contains(format!(
+            "Warning: wrote placeholder .rnr/bin/rnr-{}",
+            platform
+        )));
+
+    let bin = project.path().join(".rnr").join("bin");
+    let binary = format!("rnr-{}{}", platform, std::env::consts::EXE_SUFFIX);
+    assert!(bin.join(format!("{}.placeholder", binary)).is_file());
+    assert!(!bin.join(&binary).exists());
+
+    project
+        .rnr(["verify"])
+        .failure()
+        .stdout(contains("placeholder file, not a real binary"));
+
+    // A wrapper checked out with CRLF gets flagged with the fix
+    let attributes = std::fs::read_to_string(project.path().join(".gitattributes")).unwrap();
+    assert!(attributes.contains("rnr text eol=lf"));
+    let ignore = std::fs::read_to_string(project.path().join(".rnr/.gitignore")).unwrap();
+    assert!(ignore.lines().any(|line| line == "/cache/"));
+    let wrapper = project.path().join("rnr");
+    let crlf = std::fs::read_to_string(&wrapper)
+        .unwrap()
+        .replace('\n', "\r\n");
+    std::fs::write(&wrapper, crlf).unwrap();
+    project
+        .rnr(["verify"])
+        .failure()
+        .stdout(contains("CRLF line endings"))
+        .stdout(contains("git add --renormalize rnr"));
+}
+
+/// The state rnr keeps in .rnr, like the cache holding the secret salt,
+/// stays out of commits once anything is written there
+#[test]
+fn runtime_state_is_not_committed() {
+    let project =
+        Project::with_config("build:\n  cmd: echo built\n  env:\n    API_TOKEN: hunter2\n");
+    project.git(&["init", "--quiet"]);
+    project.rnr(["build", "--dry-run"]).success();
+    project.rnr(["build"]).success();
+    assert!(project.path().join(".rnr/cache/secret-salt").is_file());
+
+    project.git(&["add", "--all"]);
+    let listed = std::process::Command::new("git")
+        .current_dir(project.path())
+        .args(["ls-files", ".rnr"])
+        .output()
+        .unwrap();
+    assert_eq!(String::from_utf8_lossy(&listed.stdout), ".rnr/.gitignore\n");
+}
+
+/// init reports its progress as JSON on stderr, placeholders included, for wrapper UIs
+#[test]
+fn init_streams_progress_as_json() {
+    let project = Project::new();
+    let assert = project
+        .command()
+        .args([
+            "init",
+            "--platforms",
+            "linux-amd64,macos-arm64",
+            "--placeholders",
+            "--force",
+            "--progress-json",
+        ])
+        .assert()
+        .success()
+        .stdout(contains("rnr initialized successfully!"));
+    let output = assert.get_output();
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("\"event\""));
+
+    let events = events(&output.stderr);
+    let kinds: Vec<&str> = events
+        .iter()
+        .map(|event| event["event"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        kinds,
+        [
+            "platform_download_started",
+            "platform_download_progress",
+            "platform_download_finished",
+            "platform_download_started",
+            "platform_download_progress",
+            "platform_download_finished",
+            "config_written",
+            "wrapper_written",
+            "wrapper_written",
+            "config_written",
+            "completed",
+        ]
+    );
+    assert!(events.iter().all(|event| event["v"] == 1));
+    assert_eq!(events[0]["platform"], "linux-amd64");
+    assert_eq!(events[0]["placeholder"], true);
+    let size = events[0]["bytes_total"].as_u64().unwrap();
+    assert_eq!(events[1]["bytes_done"], size);
+    assert_eq!(events[2]["path"], ".rnr/bin/rnr-linux-amd64.placeholder");
+    assert_eq!(events[3]["platform"], "macos-arm64");
+    assert_eq!(events[6]["path"], ".rnr/config.yaml");
+    assert_eq!(events[7]["path"], "rnr");
+    assert_eq!(events[8]["path"], "rnr.cmd");
+    assert_eq!(events[9]["path"], "rnr.yaml");
+    assert_eq!(events[10]["version"], env!("CARGO_PKG_VERSION"));
+}
+
+/// init copies the team's starter rnr.yaml from the flag, the environment, or ~/.config
+#[test]
+fn init_uses_a_starter_file() {
+    if common::platform_id().is_none() {
+        return;
+    }
+    let home = Project::new()
+        .file(".config/rnr/starter.yaml", "from-home: echo home\n")
+        .file("team.yaml", "lint: echo linting ${project_name}\n")
+        .file("broken.yaml", "lint: [unterminated\n");
+    let init = |extra: &[&str], env: Option<&str>| {
+        let project = Project::new();
+        let mut cmd = project.command();
+        cmd.env("HOME", home.path()).args([
+            "init",
+            "--current-platform-only",
+            "--placeholders",
+            "--force",
+        ]);
+        cmd.args(extra);
+        if let Some(starter) = env {
+            cmd.env("RNR_STARTER", home.path().join(starter));
+        }
+        let assert = cmd.assert();
+        (project, assert)
+    };
+    let config =
+        |project: &Project| std::fs::read_to_string(project.path().join("rnr.yaml")).unwrap();
+
+    let team = home.path().join("team.yaml");
+    let (project, assert) = init(&["--starter", team.to_str().unwrap()], Some("broken.yaml"));
+    assert.success();
+    let name = project.path().file_name().unwrap().to_string_lossy();
+    assert_eq!(config(&project), format!("lint: echo linting {}\n", name));
+
+    let (project, assert) = init(&[], Some("team.yaml"));
+    assert.success();
+    assert!(config(&project).starts_with("lint:"));
+
+    let (project, assert) = init(&[], Some("broken.yaml"));
+    assert
+        .failure()
+        .stderr(contains("Starter rnr.yaml from RNR_STARTER"))
+        .stderr(contains("is not a valid task file"));
+    assert!(!project.path().join(".rnr").exists());
+
+    #[cfg(unix)]
+    {
+        let (project, assert) = init(&[], None);
+        assert.success();
+        assert_eq!(config(&project), "from-home: echo home\n");
+    }
+}
+
+/// init inside an existing project refuses unless the sub-project is intended
+#[test]
+fn init_inside_project_needs_nested() {
+    if common::platform_id().is_none() {
+        return;
+    }
+    let project = Project::with_config("build: echo root").file("services/api/main.rs", "");
+    let api = project.path().join("services").join("api");
+    let init = |extra: &[&str]| {
+        let mut cmd = project.command();
+        cmd.current_dir(&api)
+            .args([
+                "init",
+                "--current-platform-only",
+                "--placeholders",
+                "--force",
+            ])
+            .args(extra);
+        cmd.assert()
+    };
+
+    init(&[])
+        .failure()
+        .stderr(contains("inside the rnr project at"))
+        .stderr(contains("--nested"));
+    assert!(!api.join(".rnr").exists());
+
+    init(&["--nested"]).success();
+    assert!(api.join(".rnr").join("config.yaml").is_file());
+}
+
+/// init --dry-run checks the platforms and shows what it would write, and
+/// writes nothing
+#[test]
+fn init_dry_run_changes_nothing() {
+    let base = common::serve(|path, stream| {
+        let body = if path.starts_with("/releases") {
+            r#"[{"tag_name": "v0.9.0", "assets": [
+                {"name": "rnr-linux-amd64", "size": 3145728},
+                {"name": "rnr-macos-arm64", "size": 4194304}
+            ]}]"#
+        } else {
+            ""
+        };
+        common::respond(stream, "200 OK", body.as_bytes());
+    });
+    let project = Project::new();
+    let before = project.snapshot();
+
+    let assert = project
+        .command()
+        .args([
+            "init",
+            "--platforms",
+            "linux-amd64,macos-arm64",
+            "--dry-run",
+            "--force",
+        ])
+        .env("RNR_RELEASES_URL", &base)
+        .assert()
+        .success()
+        .stdout(contains("Dry run: nothing will be created or downloaded."))
+        .stdout(contains("Platforms: linux-amd64, macos-arm64"))
+        .stdout(contains("create  .rnr/config.yaml"))
+        .stdout(contains("create  rnr.cmd"))
+        .stdout(contains("create  rnr.yaml"))
+        .stdout(contains("Repository size change: +"));
+    if cfg!(feature = "network") {
+        assert
+            .stdout(contains("sizes from the v0.9.0 release"))
+            .stdout(contains("3.00 MB"))
+            .stdout(contains("4.00 MB"));
+    } else {
+        assert.stdout(contains("sizes are estimates (~)"));
+    }
+    assert_eq!(project.snapshot(), before);
+
+    project
+        .rnr([
+            "init",
+            "--platforms",
+            "linux-amd64,linux-arm64",
+            "--dry-run",
+        ])
+        .failure()
+        .stderr(contains(
+            "Unknown platform: linux-arm64. Valid platforms: linux-amd64, macos-amd64, \
+             macos-arm64, windows-amd64, windows-arm64",
+        ));
+    assert_eq!(project.snapshot(), before);
+}
+
+/// In an initialized project, init --dry-run shows how the platforms would change
+#[test]
+fn init_dry_run_compares_with_configured_platforms() {
+    let project = Project::with_config("build: echo hi")
+        .file(
+            ".rnr/config.yaml",
+            "version: 0.1.0\nbinaries:\n  linux-amd64:\n    version: 0.1.0\n",
+        )
+        .file(".rnr/bin/rnr-linux-amd64", "linux binary");
+    let before = project.snapshot();
+
+    project
+        .command()
+        .args([
+            "init",
+            "--platforms",
+            "macos-arm64",
+            "--dry-run",
+            "--placeholders",
+        ])
+        .assert()
+        .success()
+        .stdout(contains("already initialized"))
+        .stdout(contains("+ macos-arm64"))
+        .stdout(contains("- linux-amd64      -12 bytes"))
+        .stdout(contains("rnr init --add-platform macos-arm64"))
+        .stdout(contains("rnr init --remove-platform linux-amd64"));
+    assert_eq!(project.snapshot(), before);
+}
+
+/// With the network feature, init downloads from a local release server
+#[test]
+#[cfg(feature = "network")]
+fn init_current_platform_only() {
+    let Some(platform) = common::platform_id() else {
+        return;
+    };
+    let base = common::serve(|path, stream| {
+        if path.starts_with("/latest/download/rnr-") && !path.ends_with(".sha256") {
+            common::respond(stream, "200 OK", b"placeholder binary");
+        } else {
+            common::respond(stream, "404 Not Found", b"");
+        }
+    });
+
+    let project = Project::new();
+    project
+        .command()
+        .args(["init", "--current-platform-only", "--force"])
+        .env("RNR_RELEASES_URL", &base)
+        .assert()
+        .success()
+        .stdout(contains("rnr initialized successfully!"));
+    assert_initialized(&project, platform);
+}
+
+/// init --download gives a project set up by a slim build committed binaries
+#[test]
+#[cfg(feature = "network")]
+fn init_download_replaces_system_mode() {
+    let Some(platform) = common::platform_id() else {
+        return;
+    };
+    let base = common::serve(|path, stream| {
+        if path.starts_with("/latest/download/rnr-") && !path.ends_with(".sha256") {
+            common::respond(stream, "200 OK", b"placeholder binary");
+        } else {
+            common::respond(stream, "404 Not Found", b"");
+        }
+    });
+
+    let project = Project::new()
+        .file(
+            ".rnr/config.yaml",
+            "version: 0.1.0\nmode: system\nbinaries: {}\n",
+        )
+        .file("rnr", "#!/bin/sh\nexec rnr \"$@\"\n");
+    project
+        .command()
+        .args(["init", "--download", "--current-platform-only"])
+        .env("RNR_RELEASES_URL", &base)
+        .assert()
+        .success()
+        .stdout(contains("now run the binaries in .rnr/bin"));
+
+    let config = std::fs::read_to_string(project.path().join(".rnr/config.yaml")).unwrap();
+    assert!(!config.contains("mode:"), "{}", config);
+    assert!(config.contains(platform), "{}", config);
+    let wrapper = std::fs::read_to_string(project.path().join("rnr")).unwrap();
+    assert!(wrapper.contains(".rnr/bin"));
+
+    // A project with binaries already has nothing to switch
+    project
+        .command()
+        .args(["init", "--download", "--current-platform-only"])
+        .env("RNR_RELEASES_URL", &base)
+        .assert()
+        .failure()
+        .stderr(contains("already has binaries"));
+}
+
+/// A global rnr runs the version a project pins, downloading it into the
+/// user cache once
+#[test]
+#[cfg(all(unix, feature = "network"))]
+fn global_rnr_runs_pinned_version() {
+    let downloads = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let counter = downloads.clone();
+    let base = common::serve(move |path, stream| {
+        if path.starts_with("/download/v9.9.9/rnr-") && !path.ends_with(".sha256") {
+            counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            let script = "#!/bin/sh\necho \"pinned rnr: $* (from $RNR_REEXEC_FROM)\"\n";
+            common::respond(stream, "200 OK", script.as_bytes());
+        } else {
+            common::respond(stream, "404 Not Found", b"");
+        }
+    });
+    let cache = tempfile::tempdir().unwrap();
+    let project = Project::with_config("build: echo built by this rnr\n")
+        .file(".rnr/config.yaml", "version: 9.9.9\nbinaries: {}\n");
+    let pinned = |project: &Project| {
+        let mut command = project.command();
+        command
+            .env_remove("RNR_NO_REEXEC")
+            .env("RNR_CACHE_DIR", cache.path())
+            .env("RNR_RELEASES_URL", &base);
+        command
+    };
+
+    for _ in 0..2 {
+        pinned(&project)
+            .arg("build")
+            .assert()
+            .success()
+            .stdout(contains(format!(
+                "pinned rnr: build (from {})",
+                env!("CARGO_PKG_VERSION")
+            )));
+    }
+    assert_eq!(downloads.load(std::sync::atomic::Ordering::SeqCst), 1);
+    assert!(cache.path().join("9.9.9/rnr").is_file());
+
+    // Turned off, or already handed over once: this rnr runs the task
+    for (name, value) in [("RNR_NO_REEXEC", "1"), ("RNR_REEXEC_FROM", "0.0.1")] {
+        pinned(&project)
+            .arg("build")
+            .env(name, value)
+            .assert()
+            .success()
+            .stdout(contains("$ echo built by this rnr"));
+    }
+    // Commands that manage the project's binaries never hand over
+    pinned(&project)
+        .arg("version")
+        .assert()
+        .success()
+        .stdout(contains(format!("rnr v{}", env!("CARGO_PKG_VERSION"))));
+
+    // A version that can't be had falls back to this rnr, with a warning
+    let project = Project::with_config("build: echo built by this rnr\n")
+        .file(".rnr/config.yaml", "version: 9.9.8\nbinaries: {}\n");
+    pinned(&project)
+        .arg("build")
+        .assert()
+        .success()
+        .stderr(contains("Warning: this project pins rnr 9.9.8"))
+        .stderr(contains("couldn't be downloaded"))
+        .stdout(contains("$ echo built by this rnr"));
+
+    // So does one that isn't a version, before it's used as a path or URL
+    let project = Project::with_config("build: echo built by this rnr\n")
+        .file(".rnr/config.yaml", "version: ../../9.9.9\nbinaries: {}\n");
+    pinned(&project)
+        .arg("build")
+        .assert()
+        .success()
+        .stderr(contains(
+            "Warning: this project pins rnr '../../9.9.9', which isn't a version like 1.2.3",
+        ))
+        .stdout(contains("$ echo built by this rnr"));
+    assert_eq!(downloads.load(std::sync::atomic::Ordering::SeqCst), 1);
+}
+
+#[cfg(feature = "network")]
+fn assert_initialized(project: &Project, platform: &str) {
+    let root = project.path();
+    assert!(root.join("rnr").is_file());
+    assert!(root.join("rnr.cmd").is_file());
+    assert!(root.join("rnr.yaml").is_file());
+
+    let config = std::fs::read_to_string(root.join(".rnr").join("config.yaml")).unwrap();
+    assert!(config.contains(platform), "{}", config);
+    let bin = std::fs::read_dir(root.join(".rnr").join("bin"))
+        .unwrap()
+        .flatten()
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .collect::<Vec<_>>();
+    assert_eq!(bin.len(), 1, "{:?}", bin);
+    assert!(bin[0].contains(platform));
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = |path: &str| {
+            let metadata = std::fs::metadata(root.join(path)).unwrap();
+            metadata.permissions().mode() & 0o777
+        };
+        assert_eq!(mode("rnr"), 0o755);
+        assert_eq!(mode("rnr.cmd"), 0o644);
+        assert_eq!(mode("rnr.yaml"), 0o644);
+        assert_eq!(mode(".rnr/config.yaml"), 0o644);
+    }
+
+    // The starter config is runnable
+    project.rnr(["--list"]).success();
+}
+
+/// `rnr import` previews a package.json's scripts as tasks, then merges them
+/// without touching tasks rnr.yaml already has
+#[test]
+fn import_merges_package_json_scripts() {
+    let project = Project::from_fixture("import");
+    let config = || std::fs::read_to_string(project.path().join("rnr.yaml")).unwrap();
+    project
+        .rnr(["import", "package.json", "--dry-run"])
+        .success()
+        .stdout(contains("+++ rnr.yaml (imported from package.json)"))
+        .stdout(contains("+ build: 'npm run build'"))
+        .stdout(contains(
+            "Would import 7 task(s) from package.json; nothing was written",
+        ));
+    assert_eq!(config(), "# Tasks\ntest: echo rnr test\n");
+
+    project
+        .rnr(["import", "package.json", "--inline"])
+        .success()
+        .stdout(contains(
+            "Imported 7 task(s) from package.json into rnr.yaml",
+        ))
+        .stderr(contains(
+            "Kept the tasks already in rnr.yaml: test; pass --force to replace them",
+        ))
+        .stderr(contains("npm hooks prebuild, postbuild became steps"));
+    assert!(config().starts_with(
+        "# Tasks\ntest: echo rnr test\n\nbuild:\n  steps:\n    - cmd: 'echo cleaning'\n"
+    ));
+    project.rnr(["build"]).success().stdout(
+        contains("cleaning")
+            .and(contains("building"))
+            .and(contains("built")),
+    );
+    project
+        .rnr(["greet"])
+        .success()
+        .stdout(contains("hello: npm"));
+    project.rnr(["test"]).success().stdout(contains("rnr test"));
+    project
+        .rnr(["import", "package.json"])
+        .success()
+        .stdout("Nothing to import into rnr.yaml\n");
+}
+
+/// Exporting tasks imported from package.json leaves their scripts alone,
+/// since a shim would have `npm run test` call itself through rnr
+#[test]
+fn import_then_export_keeps_npm_scripts() {
+    let project = Project::from_fixture("import");
+    let package = || std::fs::read_to_string(project.path().join("package.json")).unwrap();
+    let before = package();
+    project.rnr(["import", "package.json"]).success();
+    project
+        .rnr(["export", "--format", "npm"])
+        .success()
+        .stderr(contains(
+            "Left out tasks that only run their own npm script: build, build:docs, dev, format, greet, lint, start",
+        ));
+    let after = package();
+    assert!(after.contains(r#""build": "echo building""#), "{}", after);
+    assert!(
+        after.contains(r#""start": "node dist/index.js""#),
+        "{}",
+        after
+    );
+    // The one task rnr.yaml already had is rnr's own, so it gets the shim
+    assert_eq!(after, before.replace(r#""vitest run""#, r#""./rnr test""#));
+    project
+        .rnr(["export", "--format", "npm", "--check"])
+        .success();
+}
+
+/// Make targets become tasks that run make; variables and pattern rules don't
+#[test]
+#[cfg(unix)]
+fn import_makefile_targets_skips_variables() {
+    let project = Project::from_fixture("import");
+    project
+        .rnr(["import", "Makefile"])
+        .success()
+        .stdout(contains("+ all: 'make all'"))
+        .stderr(contains(
+            "Skipped Makefile variables CC, VERSION; make still sets them for the tasks that run it",
+        ));
+    let config = std::fs::read_to_string(project.path().join("rnr.yaml")).unwrap();
+    assert!(
+        !config.contains("%.o") && !config.contains("PHONY"),
+        "{}",
+        config
+    );
+    project
+        .rnr(["all"])
+        .success()
+        .stdout(contains("building 1.0").and(contains("checked")));
+}
+
+/// `rnr export` writes a Makefile that runs the tasks through ./rnr, and
+/// `--check` notices when rnr.yaml has moved on
+#[cfg(unix)]
+#[test]
+fn export_makefile_round_trips() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let project = Project::from_fixture("basic").file(
+        "rnr",
+        &format!("#!/bin/sh\nexec '{}' \"$@\"\n", env!("CARGO_BIN_EXE_rnr")),
+    );
+    let wrapper = project.path().join("rnr");
+    std::fs::set_permissions(&wrapper, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let makefile = || std::fs::read_to_string(project.path().join("Makefile")).unwrap();
+
+    project
+        .rnr(["export", "--format", "makefile"])
+        .success()
+        .stdout("Wrote Makefile from rnr.yaml.\n");
+    let written = makefile();
+    assert!(written.contains(".PHONY: build greet hello with-env\n"));
+    assert!(written.contains("\n# Build the project\nbuild:\n\t./rnr build\n"));
+    project
+        .rnr(["export", "--format", "makefile", "--check"])
+        .success()
+        .stdout("Makefile is in sync with rnr.yaml.\n");
+    project.rnr(["export", "--format", "makefile"]).success();
+    assert_eq!(makefile(), written);
+
+    let output = std::process::Command::new("make")
+        .args(["-s", "build"])
+        .current_dir(project.path())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Building project..."));
+
+    let yaml = project.path().join("rnr.yaml");
+    let config = std::fs::read_to_string(&yaml).unwrap();
+    std::fs::write(&yaml, format!("{}\nlint: echo linted\n", config)).unwrap();
+    project
+        .rnr(["export", "--format", "makefile", "--check"])
+        .failure()
+        .stdout(contains("+ lint:"))
+        .stderr(contains(
+            "Makefile isn't in sync with rnr.yaml; run 'rnr export --format makefile' to update it",
+        ));
+    assert_eq!(makefile(), written);
+
+    std::fs::write(project.path().join("Makefile"), "all:\n\tcc main.c\n").unwrap();
+    project
+        .rnr(["export", "--format", "makefile"])
+        .failure()
+        .stderr(contains(
+            "Makefile wasn't written by rnr export; pass --force to replace it",
+        ));
+}
+
+/// `rnr export --format npm` points the scripts of tasks at rnr and leaves
+/// the rest of package.json alone
+#[test]
+fn export_npm_scripts_touches_only_task_keys() {
+    let package = "{\n  \"name\": \"web\",\n  \"scripts\": {\n    \"build\": \"tsc\",\n    \"dev\": \"vite\"\n  },\n  \"dependencies\": {}\n}\n";
+    let project = Project::from_fixture("basic").file("package.json", package);
+    let read = || std::fs::read_to_string(project.path().join("package.json")).unwrap();
+
+    project
+        .rnr(["export", "--format", "npm", "--check"])
+        .failure()
+        .stdout(contains("+     \"build\": \"./rnr build\","))
+        .stderr(contains("package.json isn't in sync with rnr.yaml"));
+    assert_eq!(read(), package);
+
+    project
+        .rnr(["export", "--format", "npm"])
+        .success()
+        .stdout("Wrote package.json from rnr.yaml.\n");
+    assert_eq!(
+        read(),
+        "{\n  \"name\": \"web\",\n  \"scripts\": {\n    \"build\": \"./rnr build\",\n    \"dev\": \"vite\",\n    \"greet\": \"./rnr greet\",\n    \"hello\": \"./rnr hello\",\n    \"with-env\": \"./rnr with-env\"\n  },\n  \"dependencies\": {}\n}\n"
+    );
+    project
+        .rnr(["export", "--format", "npm", "--check"])
+        .success()
+        .stdout("package.json is in sync with rnr.yaml.\n");
+}
+
+/// `require_clean_git` and `require_branch` are checked once per task before
+/// anything runs, even for a task reached twice, and not in a dry run
+#[test]
+fn git_requirements_guard_release_tasks() {
+    let project = Project::with_config(
+        "release:\n  require_clean_git: true\n  require_branch: main\n  steps:\n    - task: publish\n    - task: publish\n\
+         publish:\n  require_clean_git: true\n  cmd: echo published\n",
+    )
+    .file("notes.txt", "v1\n");
+    project
+        .git(&["init", "--quiet"])
+        .git(&["add", "."])
+        .git(&["commit", "--quiet", "-m", "first"])
+        .git(&["checkout", "--quiet", "-B", "main"]);
+    project
+        .rnr(["release"])
+        .success()
+        .stdout(contains("published"));
+
+    std::fs::write(project.path().join("notes.txt"), "v2\n").unwrap();
+    project
+        .rnr(["release"])
+        .failure()
+        .stdout(contains("published").not())
+        .stderr(contains(
+            "Task 'release' needs a clean git state, but there are uncommitted changes to notes.txt. \
+             Pass --allow-dirty to run it anyway",
+        ));
+    let allowed = project.rnr(["release", "--allow-dirty"]).success();
+    let stderr = String::from_utf8_lossy(&allowed.get_output().stderr).into_owned();
+    assert_eq!(
+        stderr.matches("running it anyway (--allow-dirty)").count(),
+        2,
+        "{}",
+        stderr
+    );
+    project
+        .rnr(["release", "--dry-run"])
+        .success()
+        .stdout(contains(
+            "Dry run: not checking that 'release' runs with a clean git state, on branch 'main'",
+        ));
+    project
+        .rnr(["explain", "release"])
+        .success()
+        .stdout(contains(
+            "requires: 'publish' runs with a clean git state",
+        ))
+        .stdout(contains(
+            "fail: Task 'release' needs a clean git state, but there are uncommitted changes to notes.txt (pass --allow-dirty to run anyway)",
+        ));
+
+    project
+        .git(&["commit", "--quiet", "-am", "second"])
+        .git(&["checkout", "--quiet", "-b", "feature"]);
+    project.rnr(["release"]).failure().stderr(contains(
+        "Task 'release' runs from branch 'main', but 'feature' is checked out. \
+             Pass --ignore-guards to run it anyway",
+    ));
+    project
+        .rnr(["publish"])
+        .success()
+        .stdout(contains("published"));
+}
+
+#[test]
+fn step_history_estimates_durations() {
+    let project = Project::with_config(
+        "ci:\n  steps:\n    - cmd: echo building\n      description: build\n    - cmd: echo testing\n      description: integration tests\n",
+    );
+    project.rnr(["ci"]).success();
+
+    // Seed the recorded samples, keeping the hashes of the commands
+    let path = project.path().join(".rnr/cache/history.json");
+    let mut history: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    history["steps"]["ci/1"]["samples_ms"] = serde_json::json!([30000, 20000]);
+    history["steps"]["ci/2"]["samples_ms"] = serde_json::json!([250000, 252000]);
+    std::fs::write(&path, history.to_string()).unwrap();
+
+    project
+        .rnr(["ci"])
+        .success()
+        .stdout(contains("[1/2] build (last: 20s, about 4m 36s left)"))
+        .stdout(contains(
+            "[2/2] integration tests (last: 4m 12s, about 4m 11s left)",
+        ));
+    project
+        .rnr(["ci", "--no-eta"])
+        .success()
+        .stdout(contains("[1/2]").not());
+
+    // A changed command starts its step's history over
+    std::fs::write(
+        project.path().join("rnr.yaml"),
+        "ci:\n  steps:\n    - cmd: echo building\n      description: build\n    - cmd: echo testing again\n      description: integration tests\n",
+    )
+    .unwrap();
+    project
+        .rnr(["ci"])
+        .success()
+        .stdout(contains("[1/2] build (last:"))
+        .stdout(contains("[2/2]").not());
+}
+
+#[cfg(unix)]
+#[test]
+fn step_history_flags_slow_steps() {
+    let project = Project::with_config("ci:\n  steps:\n    - cmd: sleep 1\n");
+    project.rnr(["ci"]).success();
+    let path = project.path().join(".rnr/cache/history.json");
+    let mut history: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    history["steps"]["ci/1"]["samples_ms"] = serde_json::json!([300, 200, 400]);
+    std::fs::write(&path, history.to_string()).unwrap();
+
+    project
+        .rnr(["ci"])
+        .success()
+        .stdout(contains("Slower than usual in 'ci':"))
+        .stdout(contains("  step 1 ($ sleep 1) took 1."))
+        .stdout(contains("; usually 0.3s"));
+}
+
+#[test]
+fn settings_layers_and_provenance() {
+    let project = Project::with_config(
+        "settings:\n  jobs: 2\n  timestamps: false\n  colour: always\n\nbuild: echo hi\n",
+    )
+    .file(
+        ".config-home/rnr/settings.yaml",
+        "jobs: 1\noutput: plain\neta: false\n",
+    );
+    let shown = project
+        .rnr(["settings"])
+        .success()
+        .stderr(contains(
+            "Unknown setting 'colour' in rnr.yaml is ignored; did you mean 'color'?",
+        ))
+        .stdout(contains("color       never  (NO_COLOR)\n"))
+        .stdout(contains("jobs        2      (settings: in rnr.yaml)\n"))
+        .stdout(contains("timestamps  false  (settings: in rnr.yaml)\n"));
+    let stdout = String::from_utf8_lossy(&shown.get_output().stdout).into_owned();
+    let user_file = std::path::Path::new(".config-home")
+        .join("rnr")
+        .join("settings.yaml");
+    for line in ["output      plain  (", "eta         false  ("] {
+        let line = stdout.lines().find(|l| l.starts_with(line)).unwrap();
+        assert!(
+            line.ends_with(&format!("{})", user_file.display())),
+            "{}",
+            stdout
+        );
+    }
+
+    project
+        .command()
+        .env("RNR_JOBS", "3")
+        .arg("settings")
+        .assert()
+        .success()
+        .stdout(contains("jobs        3      (RNR_JOBS)\n"));
+    project
+        .command()
+        .env("RNR_JOBS", "3")
+        .args(["--jobs", "4", "settings"])
+        .assert()
+        .success()
+        .stdout(contains("jobs        4      (--jobs)\n"));
+    project
+        .command()
+        .env("RNR_JOBS", "none")
+        .arg("build")
+        .assert()
+        .failure()
+        .stderr(contains(
+            "Invalid jobs 'none' (from RNR_JOBS): expected a whole number of 1 or more",
+        ));
+
+    // The settings apply to runs, and `settings:` isn't a task
+    project.rnr(["build"]).success().stdout(contains("hi"));
+    project
+        .rnr(["--list"])
+        .success()
+        .stdout(contains("settings").not());
+}
+
+#[cfg(unix)]
+#[test]
+fn outputs_feed_sources_of_later_tasks() {
+    let project = Project::with_config(
+        "gen:\n  sources: [schema.txt]\n  outputs: ['gen/*.txt']\n  cmd: mkdir -p gen && cp schema.txt gen/out.txt && echo ran-gen\n\
+         build:\n  sources: ['gen/*.txt', main.txt]\n  outputs: [dist/]\n  cmd: mkdir -p dist && cat gen/out.txt main.txt > dist/app.txt && echo ran-build\n\
+         package:\n  sources: [dist/app.txt]\n  cmd: echo ran-package\n",
+    )
+    .file("schema.txt", "v1\n")
+    .file("main.txt", "main\n");
+    let ran = |args: &[&str]| {
+        let output = project.rnr(args).success().get_output().stdout.clone();
+        let stdout = String::from_utf8(output).unwrap();
+        let ran: Vec<&str> = ["ran-gen", "ran-build", "ran-package"]
+            .into_iter()
+            .filter(|task| stdout.lines().any(|line| line == *task))
+            .collect();
+        (ran, stdout)
+    };
+
+    let (tasks, stdout) = ran(&["package"]);
+    assert_eq!(tasks, ["ran-gen", "ran-build", "ran-package"]);
+    assert!(
+        stdout.contains("Running 'gen' first (needed by 'build'): it hasn't run yet"),
+        "{}",
+        stdout
+    );
+    let (tasks, stdout) = ran(&["package"]);
+    assert_eq!(tasks, ["ran-package"]);
+    assert!(
+        stdout.contains("Skipping 'build' (needed by 'package'): its outputs are up to date"),
+        "{}",
+        stdout
+    );
+
+    // A source of build changed: gen is still up to date
+    std::fs::write(project.path().join("main.txt"), "main 2\n").unwrap();
+    let (tasks, stdout) = ran(&["package"]);
+    assert_eq!(tasks, ["ran-build", "ran-package"]);
+    assert!(
+        stdout.contains("Running 'build' first (needed by 'package'): its sources changed"),
+        "{}",
+        stdout
+    );
+
+    // gen's source changed, and what it makes changes build's sources
+    std::fs::write(project.path().join("schema.txt"), "v2\n").unwrap();
+    assert_eq!(ran(&["package", "--no-auto-deps"]).0, ["ran-package"]);
+    assert_eq!(ran(&["package"]).0, ["ran-gen", "ran-build", "ran-package"]);
+    assert_eq!(
+        std::fs::read_to_string(project.path().join("dist/app.txt")).unwrap(),
+        "v2\nmain 2\n"
+    );
+
+    // Missing outputs count as stale
+    std::fs::remove_dir_all(project.path().join("dist")).unwrap();
+    assert_eq!(ran(&["build"]).0, ["ran-build"]);
+    std::fs::remove_dir_all(project.path().join("dist")).unwrap();
+    project
+        .rnr(["explain", "package"])
+        .success()
+        .stdout(contains(
+            "needs: 'build' first, as its outputs 'dist/' overlap the sources 'dist/app.txt' of 'package'",
+        ))
+        .stdout(contains(
+            "needs: 'gen' first, as its outputs 'gen/*.txt' overlap the sources 'gen/*.txt' of 'build'",
+        ))
+        .stdout(contains("skip: 'gen' is up to date"))
+        .stdout(contains("run: 'build' first: nothing matches its output 'dist/'"));
+}
+
+#[test]
+fn outputs_and_sources_in_a_cycle_fail() {
+    Project::with_config(
+        "a:\n  sources: [b.txt]\n  outputs: [a.txt]\n  cmd: echo a\n\
+         b:\n  sources: [a.txt]\n  outputs: [b.txt]\n  cmd: echo b\n",
+    )
+    .rnr(["a"])
+    .failure()
+    .stdout(contains("echo").not())
+    .stderr(contains(
+        "Tasks' outputs and sources form a cycle: a -> b -> a; pass --no-auto-deps to run without them",
+    ));
+}
+
+#[test]
+fn menu_tasks_run_the_option_named() {
+    let project = Project::with_config(
+        "setup:\n  choose:\n    prompt: What do you want to set up?\n    options:\n      frontend: setup-frontend\n      backend: setup-backend\n\
+         setup-frontend: echo frontend\nsetup-backend: echo backend\n",
+    );
+    project
+        .rnr(["setup", "frontend"])
+        .success()
+        .stdout(contains("$ echo frontend\n"))
+        .stdout(contains("backend").not())
+        .stderr(contains("Running 'setup-frontend' (chose 'frontend')"));
+
+    // The arguments after the choice go to the task it runs
+    project
+        .rnr(["setup", "--choice", "backend", "--fast"])
+        .success()
+        .stdout(contains("$ echo backend --fast\n"));
+
+    project
+        .rnr(["setup", "fronted"])
+        .failure()
+        .stdout(contains("$ echo").not())
+        .stderr(contains(
+            "'fronted' isn't an option of 'setup' (did you mean 'frontend'?); choose one of: frontend, backend",
+        ));
+
+    // Without a terminal to ask in, a missing choice fails rather than waiting
+    project
+        .rnr(["setup"])
+        .failure()
+        .stderr(contains(
+            "'setup' asks which task to run, but there's no terminal to ask in; pass one of: frontend, backend",
+        ));
+}
+
+#[test]
+fn menu_tasks_show_their_options_in_dry_runs_and_help() {
+    let project = Project::with_config(
+        "setup:\n  choose:\n    prompt: What do you want to set up?\n    options:\n      frontend: setup-frontend\n      everything: setup-all\n\
+         setup-frontend: echo frontend\nsetup-all: echo all\n",
+    );
+    let menu = "What do you want to set up?\n  frontend    -> setup-frontend\n  everything  -> setup-all\n";
+    project
+        .rnr(["--dry-run", "setup"])
+        .success()
+        .stdout(contains(menu))
+        .stdout(contains("(would ask which one to run)"))
+        .stdout(contains("$ echo").not());
+    project
+        .rnr(["--dry-run", "setup", "everything"])
+        .success()
+        .stdout(contains(menu))
+        .stdout(contains("$ echo all\n"));
+    project
+        .rnr(["help", "setup"])
+        .success()
+        .stdout(contains("Usage: rnr setup [option]\n"))
+        .stdout(contains(format!("Choices: {}", menu)));
+}
@@ -0,0 +1,211 @@
+//! Helpers for end-to-end tests
+//!
+//! `Project` scaffolds a throwaway project directory and runs the rnr binary
+//! in it, so a new scenario only needs its rnr.yaml and the assertions:
+//!
+//! ```ignore
+//! Project::with_config("hello: echo hi")
+//!     .rnr(["hello"])
+//!     .success()
+//!     .stdout(contains("hi"));
+//! ```
+
+#![allow(dead_code)]
+
+use assert_cmd::assert::Assert;
+use assert_cmd::Command;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+/// A temporary project directory
+pub struct Project {
+    dir: TempDir,
+}
+
+impl Project {
+    /// An empty project
+    pub fn new() -> Self {
+        Self {
+            dir: tempfile::tempdir().unwrap(),
+        }
+    }
+
+    /// A project whose rnr.yaml is `yaml`
+    pub fn with_config(yaml: &str) -> Self {
+        Self::new().file("rnr.yaml", yaml)
+    }
+
+    /// A copy of `tests/fixtures/<name>`
+    pub fn from_fixture(name: &str) -> Self {
+        let project = Self::new();
+        copy_dir(&fixtures_dir().join(name), project.path());
+        project
+    }
+
+    /// Add a file, creating parent directories as needed
+    pub fn file(self, path: &str, contents: &str) -> Self {
+        let path = self.path().join(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).unwrap();
+        }
+        fs::write(path, contents).unwrap();
+        self
+    }
+
+    /// Run git in the project, which `git(&["init"])` makes a repository
+    pub fn git(&self, args: &[&str]) -> &Self {
+        let output = std::process::Command::new("git")
+            .current_dir(self.path())
+            .args(["-c", "user.name=dev", "-c", "user.email=dev@example.com"])
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "git {:?} failed: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        self
+    }
+
+    /// Root of the project
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// A command running the rnr binary under test in the project
+    pub fn command(&self) -> Command {
+        Command::from_std(self.process())
+    }
+
+    /// [`Project::command`] as a plain process, for tests that need it
+    /// running while they act on it
+    pub fn process(&self) -> std::process::Command {
+        let mut cmd = std::process::Command::new(env!("CARGO_BIN_EXE_rnr"));
+        cmd.current_dir(self.path())
+            .env("NO_COLOR", "1")
+            .env_remove("RNR_RELEASES_URL")
+            .env_remove("RNR_PROFILE_STARTUP")
+            .env_remove("RNR_STARTER")
+            .env_remove("RNR_TASK")
+            .env_remove("CI")
+            .env_remove("RNR_ARGS")
+            .env_remove("RNR_REEXEC_FROM")
+            .env_remove("RNR_DEPTH")
+            .env_remove("RNR_PARENT_TASK")
+            .env_remove("RNR_TASK_CHAIN")
+            .env_remove("RNR_RUN_ID")
+            .env_remove("RNR_MAX_DEPTH")
+            .env_remove("RNR_CONTEXT")
+            .env_remove("RNR_COLOR")
+            .env_remove("RNR_JOBS")
+            .env_remove("RNR_OUTPUT")
+            .env_remove("RNR_TIMESTAMPS")
+            .env_remove("RNR_ETA")
+            // Settings of whoever runs the tests don't apply
+            .env("XDG_CONFIG_HOME", self.path().join(".config-home"))
+            .env("RNR_NO_REEXEC", "1")
+            .env_remove("OTEL_EXPORTER_OTLP_ENDPOINT")
+            .env_remove("OTEL_EXPORTER_OTLP_TRACES_ENDPOINT");
+        cmd
+    }
+
+    /// Every file and directory in the project, with each file's contents,
+    /// to check that a command changed nothing
+    pub fn snapshot(&self) -> Vec<(PathBuf, Option<Vec<u8>>)> {
+        let mut entries = Vec::new();
+        snapshot_dir(self.path(), self.path(), &mut entries);
+        entries.sort();
+        entries
+    }
+
+    /// Run rnr with `args` in the project
+    pub fn rnr<I, S>(&self, args: I) -> Assert
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<std::ffi::OsStr>,
+    {
+        self.command().args(args).assert()
+    }
+}
+
+fn fixtures_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+}
+
+fn copy_dir(from: &Path, to: &Path) {
+    fs::create_dir_all(to).unwrap();
+    for entry in fs::read_dir(from).unwrap().flatten() {
+        let target = to.join(entry.file_name());
+        if entry.file_type().unwrap().is_dir() {
+            copy_dir(&entry.path(), &target);
+        } else {
+            fs::copy(entry.path(), target).unwrap();
+        }
+    }
+}
+
+fn snapshot_dir(root: &Path, dir: &Path, entries: &mut Vec<(PathBuf, Option<Vec<u8>>)>) {
+    for entry in fs::read_dir(dir).unwrap().flatten() {
+        let path = entry.path();
+        let relative = path.strip_prefix(root).unwrap().to_path_buf();
+        if entry.file_type().unwrap().is_dir() {
+            entries.push((relative, None));
+            snapshot_dir(root, &path, entries);
+        } else {
+            entries.push((relative, Some(fs::read(&path).unwrap())));
+        }
+    }
+}
+
+/// Platform identifier of the machine running the tests
+pub fn platform_id() -> Option<&'static str> {
+    match (std::env::consts::OS, std::env::consts::ARCH) {
+        ("linux", "x86_64") => Some("linux-amd64"),
+        ("macos", "x86_64") => Some("macos-amd64"),
+        ("macos", "aarch64") => Some("macos-arm64"),
+        ("windows", "x86_64") => Some("windows-amd64"),
+        ("windows", "aarch64") => Some("windows-arm64"),
+        _ => None,
+    }
+}
+
+/// Serve requests on a local port with `handler`, which gets the request
+/// path and the connection; returns the base URL
+pub fn serve(handler: impl Fn(&str, TcpStream) + Send + 'static) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            let _ = reader.read_line(&mut request_line);
+            // Skip headers
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap_or(0) > 2 {
+                line.clear();
+            }
+            let path = request_line.split_whitespace().nth(1).unwrap_or("/");
+            handler(path, stream);
+        }
+    });
+    base
+}
+
+/// Write a complete response and close the connection
+pub fn respond(mut stream: TcpStream, status: &str, body: &[u8]) {
+    let _ = write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        body.len()
+    );
+    let _ = stream.write_all(body);
+}
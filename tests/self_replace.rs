@@ -0,0 +1,116 @@
+//! Upgrading a project whose running binary is the one being replaced
+
+#![cfg(feature = "network")]
+
+mod common;
+
+use common::{platform_id, respond, serve};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// Serve a release list and `binary` as every download; returns the base URL
+fn serve_release(version: &'static str, binary: Vec<u8>) -> String {
+    serve(move |path, stream| {
+        if path.starts_with("/releases") {
+            if path.ends_with("&page=1") {
+                let page = format!(r#"[{{"tag_name": "v{}", "prerelease": false}}]"#, version);
+                respond(stream, "200 OK", page.as_bytes());
+            } else {
+                respond(stream, "200 OK", b"[]");
+            }
+        } else if path.starts_with(&format!("/download/v{}/", version))
+            && !path.ends_with(".sha256")
+        {
+            respond(stream, "200 OK", &binary);
+        } else {
+            respond(stream, "404 Not Found", b"");
+        }
+    })
+}
+
+/// A small stand-in for the new release: a script that reports its version.
+/// The binary under test won't do, as a debug build with every feature is
+/// over upgrade's download limit.
+fn release_payload(version: &str) -> Vec<u8> {
+    format!("#!/bin/sh\necho \"rnr {}\"\n", version).into_bytes()
+}
+
+fn rnr(binary: &Path, project: &Path) -> Command {
+    let mut cmd = Command::new(binary);
+    cmd.current_dir(project)
+        .env_remove("HTTP_PROXY")
+        .env_remove("HTTPS_PROXY")
+        .env_remove("http_proxy")
+        .env_remove("https_proxy");
+    cmd
+}
+
+#[test]
+fn upgrade_replaces_running_binary() {
+    let Some(platform) = platform_id() else {
+        return;
+    };
+    let suffix = if cfg!(windows) { ".exe" } else { "" };
+    let binary_name = format!("rnr-{}{}", platform, suffix);
+
+    let temp = tempfile::tempdir().unwrap();
+    let project = temp.path();
+    let bin_dir = project.join(".rnr").join("bin");
+    fs::create_dir_all(&bin_dir).unwrap();
+
+    // The "old" binary is a copy of the one under test, and it upgrades itself
+    let release = release_payload("99.0.0");
+    let installed = bin_dir.join(&binary_name);
+    fs::copy(env!("CARGO_BIN_EXE_rnr"), &installed).unwrap();
+    // The old version also kept a file the new one doesn't use
+    let stale = format!("{}.sig", binary_name);
+    fs::write(bin_dir.join(&stale), "signature").unwrap();
+    fs::write(
+        project.join(".rnr").join("config.yaml"),
+        format!(
+            "version: 0.0.1\nbinaries:\n  {}:\n    version: 0.0.1\n    files:\n    - {}\n    - {}\n",
+            platform, binary_name, stale
+        ),
+    )
+    .unwrap();
+
+    let base = serve_release("99.0.0", release.clone());
+    let output = rnr(&installed, project)
+        .args(["upgrade", "--yes"])
+        .env("RNR_RELEASES_URL", &base)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "upgrade failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains(&format!("Removed {} (no longer used)", stale)),
+        "{}",
+        stdout
+    );
+
+    // The swapped-in file is the release, and it runs
+    assert_eq!(fs::read(&installed).unwrap(), release);
+    if cfg!(unix) {
+        let output = rnr(&installed, project).arg("--version").output().unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "rnr 99.0.0\n");
+    }
+
+    // Nothing else is left behind. Windows can't delete the old binary while
+    // it runs, so it waits as `.old` for the next real rnr to clear it.
+    let replaced = format!("{}.old", binary_name);
+    let leftovers: Vec<_> = fs::read_dir(&bin_dir)
+        .unwrap()
+        .flatten()
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .filter(|name| name != &binary_name && !(cfg!(windows) && name == &replaced))
+        .collect();
+    assert!(leftovers.is_empty(), "unexpected files: {:?}", leftovers);
+
+    let config = fs::read_to_string(project.join(".rnr").join("config.yaml")).unwrap();
+    assert!(config.contains("version: 99.0.0"));
+}
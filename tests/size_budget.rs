@@ -0,0 +1,38 @@
+//! Size budget for the slim binary that projects commit to .rnr/bin
+//!
+//! Ignored by default because it needs a slim release build. CI runs
+//! `cargo build-slim` and then `cargo test --test size_budget -- --ignored`.
+
+use std::path::PathBuf;
+
+/// Largest slim binary we're willing to commit to every project
+const BUDGET_BYTES: u64 = 1536 * 1024;
+
+/// The slim binary: `RNR_SLIM_BINARY`, or `cargo build-slim`'s output
+fn slim_binary() -> PathBuf {
+    if let Some(path) = std::env::var_os("RNR_SLIM_BINARY") {
+        return PathBuf::from(path);
+    }
+    let target = std::env::var_os("CARGO_TARGET_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("target"));
+    target
+        .join("release")
+        .join(format!("rnr{}", std::env::consts::EXE_SUFFIX))
+}
+
+#[test]
+#[ignore = "needs a slim release build (cargo build-slim)"]
+fn slim_binary_fits_budget() {
+    let path = slim_binary();
+    let size = std::fs::metadata(&path)
+        .unwrap_or_else(|e| panic!("{}: {} (run `cargo build-slim` first)", path.display(), e))
+        .len();
+    assert!(
+        size <= BUDGET_BYTES,
+        "{} is {} KB, over the {} KB budget (was it built with `cargo build-slim`?)",
+        path.display(),
+        size / 1024,
+        BUDGET_BYTES / 1024
+    );
+}